@@ -14,9 +14,13 @@
 use bevy::prelude::*;
 use bevy::render::camera::{Camera, PerspectiveProjection};
 use bevy_skybox_cubemap::{SkyboxBundle, SkyboxMaterial, SkyboxPlugin};
-use xsecurelock_saver::engine::XSecurelockSaverPlugins;
+use xsecurelock_saver::cli::Cli;
+use xsecurelock_saver::engine::{self, XSecurelockSaverPlugins};
 
 fn main() {
+    Cli::new(engine::config_help("saver_bevymin"))
+        .about("Minimal Bevy saver circling the camera around a cube.")
+        .get_matches();
     App::build()
         .insert_resource(ClearColor(Color::rgb(0.5, 0.5, 0.9)))
         .insert_resource(Msaa { samples: 4 })
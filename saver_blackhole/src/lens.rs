@@ -0,0 +1,129 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The black hole's growth, and the lensing the shader in [`crate::pass`] applies. Distances are
+//! measured from the middle of the screen, in screen heights.
+//!
+//! A ray passing a point mass `M` at distance `b` is bent by `4GM / (c² b)`, twice what Newtonian
+//! gravity alone would give. With the desktop a fixed distance behind the hole, the point at
+//! `image` on screen shows the point of the desktop at `image - θ² image / |image|²`, where `θ` is
+//! the Einstein radius; the desktop right behind the hole appears as a ring at that radius. Rays
+//! passing closer than about 2.6 Schwarzschild radii fall in, which makes the hole's shadow. A
+//! spinning hole also drags light around it, which the saver exaggerates into a swirl.
+
+use std::time::Duration;
+
+/// `G / c²`, in screen heights per unit of mass.
+const G_OVER_C2: f32 = 1.0e-3;
+
+/// Radius of the shadow relative to the Schwarzschild radius, `3√3 / 2`.
+const SHADOW_FACTOR: f32 = 2.598;
+
+/// Mass of the hole when it appears.
+const START_MASS: f32 = 0.25;
+
+/// Swirl angle in radians at a distance of one Schwarzschild radius.
+const SWIRL: f32 = 4.0;
+
+/// Lensing parameters for the shader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lens {
+    /// Square of the Einstein radius.
+    pub einstein_radius_sq: f32,
+    /// Radius of the shadow, inside which everything is black.
+    pub shadow_radius: f32,
+    /// Swirl angle in radians at a distance of one screen height. Falls off with distance.
+    pub swirl: f32,
+}
+
+impl Lens {
+    pub fn with_mass(mass: f32) -> Self {
+        let schwarzschild_radius = 2.0 * G_OVER_C2 * mass;
+        Self {
+            einstein_radius_sq: 4.0 * G_OVER_C2 * mass,
+            shadow_radius: SHADOW_FACTOR * schwarzschild_radius,
+            swirl: SWIRL * schwarzschild_radius,
+        }
+    }
+}
+
+/// How the hole grows. It swallows the screen, stays black for a while, then the desktop comes
+/// back and it starts again.
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    /// How long the hole takes to swallow the whole screen.
+    pub swallow: Duration,
+    /// How long the screen stays black afterwards.
+    pub hold: Duration,
+}
+
+impl Schedule {
+    /// Mass of the hole `elapsed` since the saver started, on a screen with the given width over
+    /// height. The hole grows exponentially, as it would accreting in proportion to its mass, and
+    /// its shadow just covers the corners of the screen once it's done.
+    pub fn mass_at(&self, elapsed: Duration, aspect: f32) -> f32 {
+        let cycle = self.swallow + self.hold;
+        let into_cycle = elapsed.as_secs_f32() % cycle.as_secs_f32().max(f32::EPSILON);
+        let progress = (into_cycle / self.swallow.as_secs_f32().max(f32::EPSILON)).min(1.0);
+        let half_diagonal = (aspect * aspect + 1.0).sqrt() / 2.0;
+        let final_mass = half_diagonal / (SHADOW_FACTOR * 2.0 * G_OVER_C2);
+        START_MASS * (final_mass / START_MASS).powf(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> Schedule {
+        Schedule {
+            swallow: Duration::from_secs(180),
+            hold: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn starts_small() {
+        let lens = Lens::with_mass(schedule().mass_at(Duration::from_secs(0), 16.0 / 9.0));
+        assert!(lens.shadow_radius < 0.002);
+        assert!(lens.einstein_radius_sq.sqrt() < 0.05);
+    }
+
+    #[test]
+    fn swallows_whole_screen() {
+        let aspect = 16.0 / 9.0;
+        let lens = Lens::with_mass(schedule().mass_at(Duration::from_secs(180), aspect));
+        let corner = (aspect * aspect + 1.0).sqrt() / 2.0;
+        assert!((lens.shadow_radius - corner).abs() < 1e-4);
+        // Stays black until the cycle restarts.
+        let held = schedule().mass_at(Duration::from_secs(185), aspect);
+        assert_eq!(Lens::with_mass(held), lens);
+        let restarted = schedule().mass_at(Duration::from_secs(190), aspect);
+        assert!((restarted - START_MASS).abs() < 1e-4);
+    }
+
+    #[test]
+    fn grows_steadily() {
+        let schedule = schedule();
+        let masses: Vec<f32> = (0..=18)
+            .map(|i| schedule.mass_at(Duration::from_secs(i * 10), 1.0))
+            .collect();
+        for pair in masses.windows(2) {
+            assert!(pair[1] > pair[0]);
+            // Exponential growth multiplies the mass by the same factor every step.
+            let ratio = pair[1] / pair[0];
+            assert!((ratio - masses[1] / masses[0]).abs() < 1e-3);
+        }
+    }
+}
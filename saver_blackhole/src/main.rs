@@ -0,0 +1,109 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A black hole appears in the middle of the desktop and slowly swallows it. The desktop is
+//! captured when the saver starts, then gravitationally lensed and swirled around a hole which
+//! grows until its shadow covers the whole screen. After a short while in the dark the desktop
+//! comes back and the hole starts over. If the screen can't be captured, a grid is swallowed
+//! instead.
+//!
+//! Configured with an environment variable:
+//!
+//! * `XSECURELOCK_SAVER_BLACKHOLE_MINUTES`: how long the hole takes to swallow the screen, from
+//!   0.1 to 60. Defaults to 3.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_wgpu_xsecurelock::custom_pass::CustomPasses;
+use bevy_wgpu_xsecurelock::fade_in::FadeIn;
+use bevy_wgpu_xsecurelock::screen_dissolve::Screenshot;
+use bevy_wgpu_xsecurelock::WgpuOptions;
+use xsecurelock_saver::cli::Cli;
+use xsecurelock_saver::config_help::Setting;
+use xsecurelock_saver::engine::screen_capture::capture_root_window;
+use xsecurelock_saver::engine::{self, XSecurelockSaverPlugins};
+use xsecurelock_saver::env::parse_var_or;
+
+use crate::lens::Schedule;
+use crate::pass::LensPass;
+
+mod lens;
+mod pass;
+
+const MINUTES_VAR: &str = "XSECURELOCK_SAVER_BLACKHOLE_MINUTES";
+
+/// How long the screen stays black before the desktop comes back.
+const HOLD: Duration = Duration::from_secs(10);
+
+fn main() {
+    Cli::new(engine::config_help("saver_blackhole").section(
+        "Black hole",
+        vec![Setting::new::<f32>(
+                MINUTES_VAR,
+                "How long the hole takes to swallow the screen, from 0.1 to 60.",
+            )
+            .with_default(3)],
+    ))
+    .about("A black hole which slowly swallows the desktop.")
+    .get_matches();
+    let minutes = parse_var_or(MINUTES_VAR, 3.0, |minutes: &f32| {
+        (0.1..=60.0).contains(minutes)
+    });
+    App::build()
+        .insert_resource(ClearColor(Color::BLACK))
+        // The desktop should be there when the saver starts, not fade in from black.
+        .insert_resource(WgpuOptions {
+            fade_in: Some(FadeIn {
+                duration: Duration::from_secs(0),
+            }),
+            ..Default::default()
+        })
+        .insert_resource(Schedule {
+            swallow: Duration::from_secs_f32(minutes * 60.0),
+            hold: HOLD,
+        })
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_startup_system(setup.system())
+        .run();
+}
+
+fn setup(schedule: Res<Schedule>, mut passes: ResMut<CustomPasses>) {
+    let desktop = capture_root_window().unwrap_or_else(|| {
+        warn!("Unable to capture the screen, swallowing a grid instead");
+        grid(1920, 1080)
+    });
+    passes.add(LensPass::new(*schedule, desktop));
+}
+
+/// A white grid on dark blue, which shows off the lensing when there's no desktop.
+fn grid(width: u32, height: u32) -> Screenshot {
+    const SPACING: u32 = 60;
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let line = x % SPACING < 2 || y % SPACING < 2;
+            pixels.extend_from_slice(if line {
+                &[255, 255, 255, 255]
+            } else {
+                &[16, 24, 64, 255]
+            });
+        }
+    }
+    Screenshot {
+        width,
+        height,
+        pixels,
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Custom pass drawing the captured desktop lensed by the black hole. It replaces the whole frame,
+//! so Bevy's own rendering is never seen.
+
+use bevy::prelude::*;
+use bevy::render::shader::{Shader, ShaderStage};
+use bevy_wgpu_xsecurelock::custom_pass::{CustomPass, CustomPassContext};
+use bevy_wgpu_xsecurelock::screen_dissolve::Screenshot;
+use xsecurelock_saver::engine::SaverTime;
+
+use crate::lens::{Lens, Schedule};
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) out vec2 v_Uv;
+
+void main() {
+    // One triangle covering the screen.
+    vec2 corner = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_Uv = vec2(corner.x, 1.0 - corner.y);
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec2 v_Uv;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 0, binding = 0) uniform texture2D desktop;
+layout(set = 0, binding = 1) uniform sampler desktop_sampler;
+layout(set = 0, binding = 2) uniform Lens {
+    // Square of the Einstein radius in x, the shadow radius in y, the swirl in z, and the width
+    // over the height in w.
+    vec4 lens;
+};
+
+void main() {
+    vec2 aspect = vec2(lens.w, 1.0);
+    // Position relative to the hole, in screen heights.
+    vec2 image = (v_Uv - 0.5) * aspect;
+    float dist = length(image);
+    if (dist <= lens.y) {
+        o_Target = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    // The point lens equation, then the swirl from the hole dragging light around.
+    vec2 source = image - image * lens.x / (dist * dist);
+    float angle = lens.z / dist;
+    source = mat2(cos(angle), sin(angle), -sin(angle), cos(angle)) * source;
+    // The sampler mirrors past the edges, so lensing from off screen doesn't show a seam.
+    vec3 color = texture(sampler2D(desktop, desktop_sampler), source / aspect + 0.5).rgb;
+    // Light grazing the shadow is mostly lost, which softens its edge.
+    color *= smoothstep(lens.y, lens.y * 1.15, dist);
+    o_Target = vec4(color, 1.0);
+}
+"#;
+
+/// GPU resources of the pass, created on its first frame.
+struct Resources {
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+    desktop: wgpu::TextureView,
+    _texture: wgpu::Texture,
+}
+
+impl Resources {
+    fn new(context: &CustomPassContext, desktop: &Screenshot) -> Self {
+        let device = context.device;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("blackhole"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(16),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("blackhole"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let vertex = shader_module(device, ShaderStage::Vertex, VERTEX_SHADER);
+        let fragment = shader_module(device, ShaderStage::Fragment, FRAGMENT_SHADER);
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("blackhole"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment,
+                entry_point: "main",
+                targets: &[context.format.into()],
+            }),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("blackhole"),
+            address_mode_u: wgpu::AddressMode::MirrorRepeat,
+            address_mode_v: wgpu::AddressMode::MirrorRepeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("blackhole"),
+            size: 16,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let size = wgpu::Extent3d {
+            width: desktop.width,
+            height: desktop.height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("blackhole_desktop"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        context.queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &desktop.pixels,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * desktop.width,
+                rows_per_image: desktop.height,
+            },
+            size,
+        );
+        Self {
+            layout,
+            pipeline,
+            sampler,
+            uniforms,
+            desktop: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            _texture: texture,
+        }
+    }
+}
+
+/// Pass drawing the lensed desktop.
+pub struct LensPass {
+    schedule: Schedule,
+    /// The desktop until it is uploaded.
+    desktop: Option<Screenshot>,
+    resources: Option<Resources>,
+}
+
+impl LensPass {
+    pub fn new(schedule: Schedule, desktop: Screenshot) -> Self {
+        Self {
+            schedule,
+            desktop: Some(desktop),
+            resources: None,
+        }
+    }
+}
+
+impl CustomPass for LensPass {
+    fn run(&mut self, world: &World, context: &mut CustomPassContext) {
+        if let Some(desktop) = self.desktop.take() {
+            self.resources = Some(Resources::new(context, &desktop));
+        }
+        let resources = self
+            .resources
+            .as_ref()
+            .expect("resources are created on the first frame");
+        let elapsed = world
+            .get_resource::<SaverTime>()
+            .map_or_else(Default::default, SaverTime::elapsed);
+        let aspect = context.size.0 as f32 / context.size.1.max(1) as f32;
+        let lens = Lens::with_mass(self.schedule.mass_at(elapsed, aspect));
+        let uniforms = [
+            lens.einstein_radius_sq,
+            lens.shadow_radius,
+            lens.swirl,
+            aspect,
+        ];
+        let bytes: Vec<u8> = uniforms
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        context.queue.write_buffer(&resources.uniforms, 0, &bytes);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("blackhole"),
+                layout: &resources.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&resources.desktop),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&resources.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: resources.uniforms.as_entire_binding(),
+                    },
+                ],
+            });
+        let mut pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blackhole"),
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: context.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+        pass.set_pipeline(&resources.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn shader_module(device: &wgpu::Device, stage: ShaderStage, source: &str) -> wgpu::ShaderModule {
+    let spirv = Shader::from_glsl(stage, source)
+        .get_spirv(None)
+        .expect("blackhole shaders should compile");
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("blackhole"),
+        source: wgpu::ShaderSource::SpirV(spirv.into()),
+        flags: Default::default(),
+    })
+}
@@ -13,50 +13,31 @@
 // limitations under the License.
 
 extern crate rand;
-extern crate sfml;
 extern crate xsecurelock_saver;
 
-use sfml::graphics::{Color, Image, RenderTarget, Sprite, Texture};
-use sfml::system::Vector2u;
+use rand::rngs::StdRng;
+use rand::RngCore;
 
-use xsecurelock_saver::simple::Screensaver;
+use xsecurelock_saver::cli::{self, Cli};
+use xsecurelock_saver::config_help::ConfigHelp;
+use xsecurelock_saver::simple::PixelSaver;
 
 struct StaticScreensaver {
-    img: Image,
+    rng: StdRng,
 }
 
-impl Screensaver for StaticScreensaver {
-    fn update(&mut self) {
-        for Vector2u { x, y } in row_major_iterator(self.img.size()) {
-            self.img.set_pixel(
-                x,
-                y,
-                Color::rgb(rand::random(), rand::random(), rand::random()),
-            );
+impl PixelSaver for StaticScreensaver {
+    fn fill(&mut self, buf: &mut [u8], _size: (u32, u32)) {
+        self.rng.fill_bytes(buf);
+        for pixel in buf.chunks_mut(4) {
+            pixel[3] = 255;
         }
     }
-
-    fn draw<T: RenderTarget>(&self, target: &mut T) {
-        let tex = Texture::from_image(&self.img).unwrap();
-        let sprite = Sprite::with_texture(&tex);
-
-        target.draw(&sprite);
-    }
 }
 
 fn main() {
-    xsecurelock_saver::simple::run_saver(|screen_size| StaticScreensaver {
-        img: Image::new(screen_size.x, screen_size.y),
-    });
-}
-
-fn row_major_iterator<V>(size: V) -> impl Iterator<Item = Vector2u>
-where
-    V: Into<Vector2u>,
-{
-    let Vector2u {
-        x: width,
-        y: height,
-    } = size.into();
-    (0..height).flat_map(move |y| (0..width).map(move |x| Vector2u { x: x, y: y }))
+    Cli::new(ConfigHelp::new("saver_colorstatic"))
+        .about("Full screen colored static.")
+        .get_matches();
+    xsecurelock_saver::simple::run_pixel_saver(|_| StaticScreensaver { rng: cli::rng() });
 }
@@ -0,0 +1,154 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stochastic L-systems describing the plants. A system is expanded into a string of turtle
+//! commands which [`crate::turtle`] interprets into a branching skeleton:
+//!
+//! * `F`: draw a branch segment forward.
+//! * `+`/`-`: turn left/right.
+//! * `&`/`^`: pitch down/up.
+//! * `\`/`/`: roll left/right.
+//! * `[`/`]`: start/end a side branch.
+//!
+//! Any other symbol is a growth point which doesn't draw anything. Growth points left over after
+//! the last expansion sprout leaves.
+
+use rand::Rng;
+
+/// One possible replacement for a symbol, picked with probability proportional to its weight.
+#[derive(Debug, Clone, Copy)]
+pub struct Production {
+    pub weight: f32,
+    pub replacement: &'static str,
+}
+
+/// A stochastic L-system and the parameters for drawing it.
+#[derive(Debug, Clone, Copy)]
+pub struct LSystem {
+    pub axiom: &'static str,
+    pub rules: &'static [(char, &'static [Production])],
+    pub iterations: u32,
+    /// Turning angle in degrees.
+    pub angle: f32,
+}
+
+const fn p(weight: f32, replacement: &'static str) -> Production {
+    Production {
+        weight,
+        replacement,
+    }
+}
+
+/// A broad tree with three-way branching.
+pub const TREE: LSystem = LSystem {
+    axiom: "FFFA",
+    rules: &[
+        (
+            'A',
+            &[
+                p(0.6, "F[&FA]/////[&FA]///////[&FA]"),
+                p(0.3, "F[&FA]////////[&FA]"),
+                p(0.1, "F/A"),
+            ],
+        ),
+        ('F', &[p(0.8, "F"), p(0.2, "FF")]),
+    ],
+    iterations: 6,
+    angle: 28.0,
+};
+
+/// A low, dense shrub.
+pub const SHRUB: LSystem = LSystem {
+    axiom: "A",
+    rules: &[(
+        'A',
+        &[
+            p(0.5, "[&FA]////[&FA]////[&FA]////[&FA]"),
+            p(0.5, "[&FFA]/////[&FA]/////[&FFA]"),
+        ],
+    )],
+    iterations: 5,
+    angle: 35.0,
+};
+
+/// A tall, weedy plant with alternating side shoots.
+pub const WEED: LSystem = LSystem {
+    axiom: "X",
+    rules: &[
+        (
+            'X',
+            &[
+                p(0.5, "F[+X]F[-X]/X"),
+                p(0.3, "F[&X]F[^X]\\X"),
+                p(0.2, "F[+X][-X]FX"),
+            ],
+        ),
+        ('F', &[p(0.7, "FF"), p(0.3, "F")]),
+    ],
+    iterations: 5,
+    angle: 24.0,
+};
+
+/// Every plant the saver picks from.
+pub const PLANTS: &[LSystem] = &[TREE, SHRUB, WEED];
+
+impl LSystem {
+    /// Expands the axiom `iterations` times, choosing between productions with `rng`.
+    pub fn expand(&self, rng: &mut impl Rng) -> String {
+        let mut current = self.axiom.to_string();
+        for _ in 0..self.iterations {
+            let mut next = String::with_capacity(current.len() * 4);
+            for symbol in current.chars() {
+                match self.rules.iter().find(|(lhs, _)| *lhs == symbol) {
+                    Some((_, productions)) => next.push_str(choose(productions, rng)),
+                    None => next.push(symbol),
+                }
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+/// Picks one production according to the weights.
+fn choose(productions: &[Production], rng: &mut impl Rng) -> &'static str {
+    let total: f32 = productions.iter().map(|prod| prod.weight).sum();
+    let mut pick = rng.gen_range(0.0..total);
+    for prod in productions {
+        if pick < prod.weight {
+            return prod.replacement;
+        }
+        pick -= prod.weight;
+    }
+    productions.last().unwrap().replacement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn expands_deterministic_rules() {
+        const SYSTEM: LSystem = LSystem {
+            axiom: "A",
+            rules: &[('A', &[p(1.0, "F[A]B")]), ('B', &[p(1.0, "+")])],
+            iterations: 2,
+            angle: 0.0,
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(SYSTEM.expand(&mut rng), "F[F[A]B]+");
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A garden of L-system plants which slowly grow over the course of the lock session. Each plant is
+//! a randomly expanded stochastic L-system whose branches and leaves are added to its mesh as the
+//! growth front reaches them, while the wind sways the finished parts. Once the whole garden has
+//! grown it rests for a while, then is replanted from new seeds.
+//!
+//! Configured with environment variables:
+//!
+//! * `XSECURELOCK_SAVER_FLORA_COUNT`: number of plants. Defaults to 5.
+//! * `XSECURELOCK_SAVER_FLORA_GROWTH_MINUTES`: minutes for each plant to fully grow. Defaults to 8.
+//! * `XSECURELOCK_SAVER_FLORA_SEASON`: palette to use, one of `spring`, `summer`, `autumn`, or
+//!   `winter`. Defaults to the season of the current date.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::render::camera::{Camera, PerspectiveProjection};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use xsecurelock_saver::cli::{self, Cli};
+use xsecurelock_saver::config_help::Setting;
+use xsecurelock_saver::engine::{self, SaverTime, XSecurelockSaverPlugins};
+use xsecurelock_saver::env::parse_var_or;
+
+use crate::lsystem::PLANTS;
+use crate::season::{Palette, Season};
+use crate::turtle::Skeleton;
+use crate::wind::WindPlugin;
+
+mod lsystem;
+mod mesh;
+mod season;
+mod turtle;
+mod wind;
+
+const COUNT_VAR: &str = "XSECURELOCK_SAVER_FLORA_COUNT";
+const GROWTH_MINUTES_VAR: &str = "XSECURELOCK_SAVER_FLORA_GROWTH_MINUTES";
+const SEASON_VAR: &str = "XSECURELOCK_SAVER_FLORA_SEASON";
+
+/// How often plant meshes are extended. Growth is slow, so there is no need to rebuild every frame.
+const GROW_INTERVAL: Duration = Duration::from_millis(250);
+/// Delay between plants starting to grow, as a fraction of the growth time.
+const STAGGER: f64 = 0.15;
+/// How long the fully grown garden is shown before replanting.
+const REST: f64 = 120.0;
+/// Width of the strip plants are planted along.
+const GARDEN_WIDTH: f32 = 14.0;
+/// Range of plant heights.
+const HEIGHTS: (f32, f32) = (3.0, 6.5);
+
+fn main() {
+    Cli::new(engine::config_help("saver_flora").section("Flora", FloraConfig::settings()))
+        .about("A garden of L-system plants growing over the lock session.")
+        .get_matches();
+    let config = FloraConfig::from_env();
+    let palette = config.season.palette();
+    App::build()
+        .insert_resource(ClearColor(palette.sky))
+        .insert_resource(Msaa { samples: 4 })
+        .insert_resource(config)
+        .insert_resource(palette)
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_plugin(WindPlugin)
+        .add_startup_system(setup.system())
+        .add_system(grow_plants.system())
+        .add_system(replant.system())
+        .add_system(drift_camera.system())
+        .run();
+}
+
+/// Saver settings loaded from the environment.
+struct FloraConfig {
+    count: usize,
+    growth_secs: f64,
+    season: Season,
+}
+
+impl FloraConfig {
+    fn from_env() -> Self {
+        let growth_minutes = parse_var_or(GROWTH_MINUTES_VAR, 8.0, |&minutes| {
+            (0.1..=240.0).contains(&minutes)
+        });
+        Self {
+            count: parse_var_or(COUNT_VAR, 5, |&count| (1..=16).contains(&count)),
+            growth_secs: growth_minutes * 60.0,
+            season: parse_var_or(SEASON_VAR, Season::current(), |_| true),
+        }
+    }
+
+    /// The settings read by [`FloraConfig::from_env`], for `--help-config`.
+    fn settings() -> Vec<Setting> {
+        vec![
+            Setting::new::<usize>(COUNT_VAR, "Number of plants, from 1 to 16.").with_default(5),
+            Setting::new::<f64>(
+                GROWTH_MINUTES_VAR,
+                "Minutes for each plant to fully grow, from 0.1 to 240.",
+            )
+            .with_default(8),
+            Setting::new::<Season>(
+                SEASON_VAR,
+                "Palette to use. Defaults to the season of the current date.",
+            )
+            .with_kind("spring | summer | autumn | winter"),
+        ]
+    }
+}
+
+/// State of the garden as a whole.
+struct Garden {
+    timer: Timer,
+    rng: StdRng,
+    /// When the garden will be replanted, once every plant has finished growing.
+    replant_at: Option<f64>,
+}
+
+/// A growing plant.
+struct Plant {
+    skeleton: Skeleton,
+    /// Number of segments and leaves added to the mesh so far.
+    segments: usize,
+    leaves: usize,
+    /// Seconds since startup when the plant started growing.
+    start: f64,
+    rng: StdRng,
+}
+
+impl Plant {
+    fn is_grown(&self) -> bool {
+        self.segments == self.skeleton.segments.len() && self.leaves == self.skeleton.leaves.len()
+    }
+
+    /// Appends everything the growth front has reached to the mesh. Returns whether anything was
+    /// added.
+    fn grow(&mut self, progress: f32, palette: &Palette, mesh: &mut Mesh) -> bool {
+        let (segments, leaves) = (self.segments, self.leaves);
+        while let Some(segment) = self.skeleton.segments.get(self.segments) {
+            if segment.end_reach > progress {
+                break;
+            }
+            mesh::push_segment(mesh, segment, palette.bark);
+            self.segments += 1;
+        }
+        while let Some(leaf) = self.skeleton.leaves.get(self.leaves) {
+            if leaf.reach > progress {
+                break;
+            }
+            // Bare seasons still step through the leaves so the plant finishes growing.
+            if let Some(&rgb) = palette.leaves.choose(&mut self.rng) {
+                let color = Color::rgb_u8((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+                mesh::push_leaf(mesh, leaf, color);
+            }
+            self.leaves += 1;
+        }
+        self.segments != segments || self.leaves != leaves
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    config: Res<FloraConfig>,
+    palette: Res<Palette>,
+    time: Res<SaverTime>,
+) {
+    let mut garden = Garden {
+        timer: Timer::new(GROW_INTERVAL, true),
+        rng: cli::rng(),
+        replant_at: None,
+    };
+    plant_garden(
+        &mut commands,
+        &mut garden,
+        &mut meshes,
+        &config,
+        &palette,
+        time.seconds_since_startup(),
+    );
+    commands.insert_resource(garden);
+
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Plane { size: 80.0 })),
+        material: materials.add(StandardMaterial {
+            base_color: palette.ground,
+            roughness: 1.0,
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    commands.spawn_bundle(LightBundle {
+        light: Light {
+            intensity: 6000.0,
+            range: 120.0,
+            ..Default::default()
+        },
+        transform: Transform::from_xyz(8.0, 30.0, 12.0),
+        ..Default::default()
+    });
+    commands.spawn_bundle(PerspectiveCameraBundle::default());
+}
+
+/// Spawns a new set of plants, starting one after another.
+fn plant_garden(
+    commands: &mut Commands,
+    garden: &mut Garden,
+    meshes: &mut Assets<Mesh>,
+    config: &FloraConfig,
+    palette: &Palette,
+    now: f64,
+) {
+    let spacing = GARDEN_WIDTH / config.count as f32;
+    for i in 0..config.count {
+        let rng = &mut garden.rng;
+        let system = PLANTS.choose(rng).unwrap();
+        let commands_string = system.expand(rng);
+        let height = rng.gen_range(HEIGHTS.0..=HEIGHTS.1);
+        let skeleton = turtle::interpret(&commands_string, system.angle, height, rng);
+        let x = -GARDEN_WIDTH * 0.5 + spacing * (i as f32 + rng.gen_range(0.25..=0.75));
+        let z = rng.gen_range(-3.0..=3.0);
+        let mut plant = Plant {
+            skeleton,
+            segments: 0,
+            leaves: 0,
+            start: now + i as f64 * config.growth_secs * STAGGER,
+            rng: StdRng::from_rng(&mut *rng).unwrap(),
+        };
+        // Meshes can't be empty, so plants start out with the base of their trunk.
+        let mut mesh = mesh::empty();
+        let first = plant.skeleton.segments.first().map_or(0.0, |s| s.end_reach);
+        plant.grow(first, palette, &mut mesh);
+        commands
+            .spawn_bundle(MeshBundle {
+                mesh: meshes.add(mesh),
+                render_pipelines: wind::render_pipelines(),
+                transform: Transform {
+                    translation: Vec3::new(x, 0.0, z),
+                    rotation: Quat::from_rotation_y(rng.gen_range(0.0..std::f32::consts::TAU)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert_bundle((plant, wind::material()));
+    }
+}
+
+/// Extends every plant's mesh as far as its growth front has reached.
+fn grow_plants(
+    time: Res<SaverTime>,
+    palette: Res<Palette>,
+    config: Res<FloraConfig>,
+    mut garden: ResMut<Garden>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut plants: Query<(&mut Plant, &Handle<Mesh>)>,
+) {
+    if !garden.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let now = time.seconds_since_startup();
+    let mut all_grown = true;
+    for (mut plant, mesh) in plants.iter_mut() {
+        if plant.is_grown() {
+            continue;
+        }
+        let progress = ((now - plant.start) / config.growth_secs).max(0.0) as f32;
+        // Check before borrowing the mesh mutably, which marks it for re-upload.
+        let next_segment = plant.skeleton.segments.get(plant.segments);
+        let next_leaf = plant.skeleton.leaves.get(plant.leaves);
+        let due = next_segment.map_or(false, |segment| segment.end_reach <= progress)
+            || next_leaf.map_or(false, |leaf| leaf.reach <= progress);
+        if due {
+            if let Some(mesh) = meshes.get_mut(mesh) {
+                plant.grow(progress, &palette, mesh);
+            }
+        }
+        all_grown &= plant.is_grown();
+    }
+    if all_grown && garden.replant_at.is_none() {
+        garden.replant_at = Some(now + REST);
+    }
+}
+
+/// Clears the garden and plants a new one once it has rested.
+fn replant(
+    mut commands: Commands,
+    time: Res<SaverTime>,
+    palette: Res<Palette>,
+    config: Res<FloraConfig>,
+    mut garden: ResMut<Garden>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    plants: Query<Entity, With<Plant>>,
+) {
+    let now = time.seconds_since_startup();
+    if garden.replant_at.map_or(true, |at| now < at) {
+        return;
+    }
+    for entity in plants.iter() {
+        commands.entity(entity).despawn();
+    }
+    garden.replant_at = None;
+    plant_garden(
+        &mut commands,
+        &mut garden,
+        &mut meshes,
+        &config,
+        &palette,
+        now,
+    );
+}
+
+/// Slowly sways the camera from side to side in front of the garden.
+fn drift_camera(
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, (With<Camera>, With<PerspectiveProjection>)>,
+) {
+    const SPEED: f32 = 0.02;
+    let t = time.seconds_since_startup() as f32 * SPEED;
+    for mut transform in cameras.iter_mut() {
+        let (sin, cos) = (t.sin() * 0.6).sin_cos();
+        *transform = Transform::from_xyz(sin * 16.0, 3.5 + (t * 1.3).sin(), cos * 16.0)
+            .looking_at(Vec3::new(0.0, 2.8, 0.0), Vec3::Y);
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Incrementally built plant meshes. Each plant has a single mesh which segments and leaves are
+//! appended to as they grow, rather than one entity per branch.
+//!
+//! Vertex colors carry how much each vertex sways in the wind in their alpha channel.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::pipeline::PrimitiveTopology;
+
+use crate::turtle::{Leaf, Segment};
+
+/// Sides around each branch segment.
+const SIDES: u32 = 6;
+/// How much thinner branches get towards their tips.
+const TIP_TAPER: f32 = 0.75;
+/// Leaf length, in world units.
+const LEAF_LENGTH: f32 = 0.22;
+/// Leaf width, as a fraction of the length.
+const LEAF_WIDTH: f32 = 0.5;
+
+/// Creates a mesh with no geometry yet.
+pub fn empty() -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(Vec::new())));
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new());
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, Vec::<[f32; 3]>::new());
+    mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, Vec::<[f32; 4]>::new());
+    mesh
+}
+
+/// Appends a tapered tube for the segment.
+pub fn push_segment(mesh: &mut Mesh, segment: &Segment, color: Color) {
+    let axis = segment.end - segment.start;
+    let rotation = Quat::from_rotation_arc(Vec3::Y, axis.normalize());
+    let mut vertices = Vec::with_capacity((SIDES as usize + 1) * 2);
+    for &(center, reach) in &[
+        (segment.start, segment.start_reach),
+        (segment.end, segment.end_reach),
+    ] {
+        let radius = segment.radius * (1.0 - TIP_TAPER * reach);
+        for i in 0..=SIDES {
+            let (sin, cos) = (i as f32 / SIDES as f32 * TAU).sin_cos();
+            let normal = rotation * Vec3::new(cos, 0.0, sin);
+            vertices.push((center + normal * radius, normal, with_sway(color, reach)));
+        }
+    }
+
+    let ring = SIDES + 1;
+    let mut indices = Vec::with_capacity(SIDES as usize * 6);
+    for i in 0..SIDES {
+        let (bottom, next_bottom) = (i, i + 1);
+        let (top, next_top) = (i + ring, i + ring + 1);
+        indices.extend_from_slice(&[bottom, top, next_bottom, next_bottom, top, next_top]);
+    }
+    append(mesh, &vertices, &indices);
+}
+
+/// Appends a diamond-shaped leaf. Leaves are drawn double sided.
+pub fn push_leaf(mesh: &mut Mesh, leaf: &Leaf, color: Color) {
+    let normal = leaf.heading.cross(leaf.side).normalize();
+    let tip = leaf.position + leaf.heading * LEAF_LENGTH;
+    let middle = leaf.position + leaf.heading * (LEAF_LENGTH * 0.4);
+    let half_width = leaf.side * (LEAF_LENGTH * LEAF_WIDTH * 0.5);
+    // Leaves flutter a little more than the twig they hang from.
+    let color = with_sway(color, leaf.reach + 0.1);
+    let vertices = [
+        (leaf.position, normal, color),
+        (middle + half_width, normal, color),
+        (tip, normal, color),
+        (middle - half_width, normal, color),
+    ];
+    append(mesh, &vertices, &[0, 1, 2, 0, 2, 3]);
+}
+
+fn with_sway(color: Color, sway: f32) -> [f32; 4] {
+    let [r, g, b, _] = color.as_linear_rgba_f32();
+    [r, g, b, sway]
+}
+
+/// Appends vertices and indices relative to the first new vertex.
+fn append(mesh: &mut Mesh, vertices: &[(Vec3, Vec3, [f32; 4])], indices: &[u32]) {
+    let base = mesh.count_vertices() as u32;
+    if let Some(VertexAttributeValues::Float3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        positions.extend(vertices.iter().map(|(position, _, _)| (*position).into()));
+    }
+    if let Some(VertexAttributeValues::Float3(normals)) = mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        normals.extend(vertices.iter().map(|(_, normal, _)| (*normal).into()));
+    }
+    if let Some(VertexAttributeValues::Float4(colors)) = mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR) {
+        colors.extend(vertices.iter().map(|(_, _, color)| *color));
+    }
+    if let Some(Indices::U32(all)) = mesh.indices_mut() {
+        all.extend(indices.iter().map(|index| base + index));
+    }
+}
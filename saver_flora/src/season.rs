@@ -0,0 +1,98 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Seasonal palettes. By default the season follows the calendar, so the garden changes over the
+//! year.
+
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl FromStr for Season {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "spring" => Ok(Season::Spring),
+            "summer" => Ok(Season::Summer),
+            "autumn" | "fall" => Ok(Season::Autumn),
+            "winter" => Ok(Season::Winter),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Colors for one season.
+pub struct Palette {
+    pub bark: Color,
+    /// Leaf colors, picked at random per leaf. Empty for bare branches.
+    pub leaves: &'static [u32],
+    pub ground: Color,
+    pub sky: Color,
+}
+
+impl Season {
+    /// Gets the (northern hemisphere) season for the current date. Months are approximated from
+    /// the day of the year, which is close enough for picking colors.
+    pub fn current() -> Self {
+        let days = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() / 86_400);
+        let month = ((days as f64 % 365.2425) / 30.44) as u32;
+        match month {
+            2..=4 => Season::Spring,
+            5..=7 => Season::Summer,
+            8..=10 => Season::Autumn,
+            _ => Season::Winter,
+        }
+    }
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Season::Spring => Palette {
+                bark: Color::rgb_u8(0x5a, 0x43, 0x32),
+                leaves: &[0xf7c6d9, 0xf29bbd, 0xfdf0f4, 0x9ad36a, 0xb7e48c],
+                ground: Color::rgb_u8(0x4f, 0x7a, 0x3a),
+                sky: Color::rgb_u8(0x9c, 0xc8, 0xe8),
+            },
+            Season::Summer => Palette {
+                bark: Color::rgb_u8(0x4a, 0x36, 0x28),
+                leaves: &[0x2f7d32, 0x3c9a3f, 0x1e5e24, 0x5cb85c],
+                ground: Color::rgb_u8(0x3d, 0x6b, 0x2a),
+                sky: Color::rgb_u8(0x6f, 0xb0, 0xe6),
+            },
+            Season::Autumn => Palette {
+                bark: Color::rgb_u8(0x4b, 0x35, 0x2a),
+                leaves: &[0xd9480f, 0xf08c00, 0xf5c211, 0xa61e1e, 0x8f5b2e],
+                ground: Color::rgb_u8(0x6b, 0x55, 0x33),
+                sky: Color::rgb_u8(0xc9, 0xa8, 0x84),
+            },
+            Season::Winter => Palette {
+                bark: Color::rgb_u8(0x3a, 0x33, 0x30),
+                leaves: &[],
+                ground: Color::rgb_u8(0xe8, 0xee, 0xf2),
+                sky: Color::rgb_u8(0x8d, 0x9b, 0xa8),
+            },
+        }
+    }
+}
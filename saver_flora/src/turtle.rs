@@ -0,0 +1,156 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interprets expanded L-system strings into a branching skeleton of segments and leaves, each
+//! tagged with when it grows so the plant can be built up gradually.
+
+use bevy::prelude::*;
+use rand::Rng;
+
+/// Radius of the trunk at the base, relative to the height of the plant.
+const TRUNK_RADIUS: f32 = 0.03;
+/// Factor by which side branches are thinner than their parent.
+const BRANCH_TAPER: f32 = 0.72;
+/// Random variation added to every turn, in degrees.
+const ANGLE_JITTER: f32 = 6.0;
+
+/// A straight piece of branch.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: f32,
+    /// Distance along the branches from the root to the start and end, as a fraction of the
+    /// longest path through the plant. Doubles as how far into growth each end appears and how
+    /// much it sways in the wind.
+    pub start_reach: f32,
+    pub end_reach: f32,
+}
+
+/// A leaf at the tip of a branch.
+#[derive(Debug, Clone)]
+pub struct Leaf {
+    pub position: Vec3,
+    /// Direction the leaf points in, away from the branch.
+    pub heading: Vec3,
+    /// Direction across the leaf.
+    pub side: Vec3,
+    pub reach: f32,
+}
+
+/// A fully interpreted plant, with segments and leaves ordered by when they grow.
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub segments: Vec<Segment>,
+    pub leaves: Vec<Leaf>,
+}
+
+#[derive(Clone, Copy)]
+struct Turtle {
+    position: Vec3,
+    rotation: Quat,
+    /// Distance traveled from the root, in segments.
+    reach: f32,
+    radius: f32,
+}
+
+/// Interprets `commands` with the given turning angle, scaling the result to be `height` tall.
+pub fn interpret(commands: &str, angle: f32, height: f32, rng: &mut impl Rng) -> Skeleton {
+    let mut turn =
+        |sign: f32| sign * (angle + rng.gen_range(-ANGLE_JITTER..=ANGLE_JITTER)).to_radians();
+    let mut skeleton = Skeleton::default();
+    let mut stack = Vec::new();
+    let mut turtle = Turtle {
+        position: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        reach: 0.0,
+        radius: 1.0,
+    };
+    let mut commands = commands.chars().peekable();
+    while let Some(symbol) = commands.next() {
+        match symbol {
+            'F' => {
+                let end = turtle.position + turtle.rotation * Vec3::Y;
+                skeleton.segments.push(Segment {
+                    start: turtle.position,
+                    end,
+                    radius: turtle.radius,
+                    start_reach: turtle.reach,
+                    end_reach: turtle.reach + 1.0,
+                });
+                turtle.position = end;
+                turtle.reach += 1.0;
+            }
+            '+' => turtle.rotation *= Quat::from_rotation_z(turn(1.0)),
+            '-' => turtle.rotation *= Quat::from_rotation_z(turn(-1.0)),
+            '&' => turtle.rotation *= Quat::from_rotation_x(turn(1.0)),
+            '^' => turtle.rotation *= Quat::from_rotation_x(turn(-1.0)),
+            '\\' => turtle.rotation *= Quat::from_rotation_y(turn(1.0)),
+            '/' => turtle.rotation *= Quat::from_rotation_y(turn(-1.0)),
+            '[' => {
+                stack.push(turtle);
+                turtle.radius *= BRANCH_TAPER;
+            }
+            ']' => {
+                if let Some(saved) = stack.pop() {
+                    turtle = saved;
+                }
+            }
+            _ => {
+                // Leaves only grow at the end of a branch, not on growth points followed by more
+                // drawing or at the bare root.
+                let at_tip = commands.peek().map_or(true, |&next| next == ']');
+                if at_tip && turtle.reach > 0.0 {
+                    skeleton.leaves.push(Leaf {
+                        position: turtle.position,
+                        heading: turtle.rotation * Vec3::Y,
+                        side: turtle.rotation * Vec3::X,
+                        reach: turtle.reach,
+                    });
+                }
+            }
+        }
+    }
+
+    // Normalize to the requested height and to reach fractions.
+    let top = skeleton
+        .segments
+        .iter()
+        .map(|segment| segment.end.y)
+        .fold(0.0, f32::max);
+    let scale = if top > 0.0 { height / top } else { 1.0 };
+    let max_reach = skeleton
+        .segments
+        .iter()
+        .map(|segment| segment.end_reach)
+        .fold(1.0, f32::max);
+    for segment in &mut skeleton.segments {
+        segment.start *= scale;
+        segment.end *= scale;
+        segment.radius *= height * TRUNK_RADIUS;
+        segment.start_reach /= max_reach;
+        segment.end_reach /= max_reach;
+    }
+    for leaf in &mut skeleton.leaves {
+        leaf.position *= scale;
+        leaf.reach /= max_reach;
+    }
+    skeleton
+        .segments
+        .sort_by(|a, b| a.end_reach.partial_cmp(&b.end_reach).unwrap());
+    skeleton
+        .leaves
+        .sort_by(|a, b| a.reach.partial_cmp(&b.reach).unwrap());
+    skeleton
+}
@@ -0,0 +1,155 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wind sway for the plants. Plant meshes are drawn with a custom pipeline whose vertex shader
+//! bends each vertex downwind in proportion to the square of its sway, stored in the vertex
+//! color's alpha, so trunks stay planted while twigs and leaves move the most.
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::{
+    pipeline::{CullMode, PipelineDescriptor, RenderPipeline},
+    render_graph::{base, AssetRenderResourcesNode, RenderGraph},
+    renderer::RenderResources,
+    shader::{ShaderStage, ShaderStages},
+};
+
+/// Render graph node binding the wind material.
+const MATERIAL_NODE: &str = "flora_wind_material";
+
+const PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 4402950718763391641);
+const MATERIAL_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(WindMaterial::TYPE_UUID, 11878311627091535022);
+
+/// Peak push of the wind at the tips of the plants, in world units.
+const STRENGTH: f32 = 0.35;
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec4 Vertex_Color;
+layout(location = 1) in vec3 Vertex_Normal;
+layout(location = 2) in vec3 Vertex_Position;
+
+layout(location = 0) out vec3 v_Color;
+layout(location = 1) out vec3 v_Normal;
+
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+layout(set = 2, binding = 0) uniform WindMaterial_wind {
+    vec4 wind;
+};
+
+void main() {
+    vec4 world = Model * vec4(Vertex_Position, 1.0);
+    float sway = Vertex_Color.a * Vertex_Color.a;
+    // Neighbouring branches move slightly out of step.
+    float phase = wind.w * 1.3 + dot(world.xz, vec2(0.7, 0.4));
+    float flutter = 0.75 + 0.25 * sin(phase) + 0.1 * sin(phase * 3.7);
+    world.xz += wind.xz * sway * flutter;
+    v_Color = Vertex_Color.rgb;
+    v_Normal = mat3(Model) * Vertex_Normal;
+    gl_Position = ViewProj * world;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec3 v_Color;
+layout(location = 1) in vec3 v_Normal;
+
+layout(location = 0) out vec4 o_Target;
+
+const vec3 SUN = normalize(vec3(0.4, 1.0, 0.6));
+
+void main() {
+    // Leaves are double sided, so light both faces the same.
+    float diffuse = abs(dot(normalize(v_Normal), SUN));
+    o_Target = vec4(v_Color * (0.35 + 0.65 * diffuse), 1.0);
+}
+"#;
+
+/// Uniforms for the wind shader, shared by every plant.
+#[derive(Debug, Default, RenderResources, TypeUuid)]
+#[uuid = "0c9f3a62-8a47-4d3e-b3c5-7d1e2f90a4b6"]
+pub struct WindMaterial {
+    /// Push of the wind at the tips in `xz`, seconds since startup in `w`.
+    pub wind: Vec4,
+}
+
+/// Sets up the wind pipeline and keeps the wind blowing.
+pub struct WindPlugin;
+
+impl Plugin for WindPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<WindMaterial>();
+        let world = app.world_mut();
+        world
+            .get_resource_mut::<Assets<WindMaterial>>()
+            .unwrap()
+            .set_untracked(MATERIAL_HANDLE, WindMaterial::default());
+        let pipeline = {
+            let mut shaders = world.get_resource_mut::<Assets<Shader>>().unwrap();
+            let mut pipeline = PipelineDescriptor::default_config(ShaderStages {
+                vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+                fragment: Some(
+                    shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER)),
+                ),
+            });
+            pipeline.primitive.cull_mode = CullMode::None;
+            pipeline
+        };
+        world
+            .get_resource_mut::<Assets<PipelineDescriptor>>()
+            .unwrap()
+            .set_untracked(PIPELINE_HANDLE, pipeline);
+        let mut render_graph = world.get_resource_mut::<RenderGraph>().unwrap();
+        render_graph.add_system_node(
+            MATERIAL_NODE,
+            AssetRenderResourcesNode::<WindMaterial>::new(true),
+        );
+        render_graph
+            .add_node_edge(MATERIAL_NODE, base::node::MAIN_PASS)
+            .unwrap();
+
+        app.add_system(blow_wind.system());
+    }
+}
+
+/// Render pipelines for drawing a mesh with the wind shader.
+pub fn render_pipelines() -> RenderPipelines {
+    RenderPipelines::from_pipelines(vec![RenderPipeline::new(PIPELINE_HANDLE.typed())])
+}
+
+/// The wind material, which every plant must have to be drawn.
+pub fn material() -> Handle<WindMaterial> {
+    MATERIAL_HANDLE.typed()
+}
+
+/// Slowly turns the wind and varies it in gusts.
+fn blow_wind(time: Res<Time>, mut materials: ResMut<Assets<WindMaterial>>) {
+    let t = time.seconds_since_startup() as f32;
+    let heading = 0.4 * (t * 0.013).sin() + 0.2 * (t * 0.031).sin();
+    let gust = 0.55 + 0.3 * (t * 0.21).sin() * (t * 0.07).sin() + 0.15 * (t * 0.9).sin();
+    let push = Vec2::new(heading.cos(), heading.sin()) * STRENGTH * gust;
+    if let Some(material) = materials.get_mut(&material()) {
+        material.wind = Vec4::new(push.x, 0.0, push.y, t);
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The emitters that stir the fluid. Each one follows its own Lissajous curve across the screen,
+//! pushing the fluid along its direction of travel and injecting dye whose hue slowly cycles.
+
+use bevy::prelude::*;
+
+/// Number of emitters. Must match `EMITTERS` in the shaders.
+pub const EMITTERS: usize = 3;
+
+/// Distance of the emitter paths from the center of the screen, in UV units.
+const PATH_RADIUS: f32 = 0.38;
+/// Peak force applied by an emitter, in cells per second squared.
+const FORCE: f32 = 2400.0;
+/// Hue rotations per second.
+const HUE_SPEED: f32 = 0.02;
+
+/// A point stirring the fluid at one moment.
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    /// Position in UV coordinates across the simulation grid.
+    pub position: Vec2,
+    /// Force applied around the emitter, in cells per second squared.
+    pub force: Vec2,
+    /// Linear RGB dye injected per second.
+    pub color: Vec3,
+}
+
+/// Gets the emitters at the given number of seconds since the saver started.
+pub fn emitters_at(time: f32) -> [Emitter; EMITTERS] {
+    let mut emitters = [Emitter {
+        position: Vec2::ZERO,
+        force: Vec2::ZERO,
+        color: Vec3::ZERO,
+    }; EMITTERS];
+    for (i, emitter) in emitters.iter_mut().enumerate() {
+        let i = i as f32;
+        let (fx, fy) = (0.11 + 0.05 * i, 0.07 + 0.04 * i);
+        let phase = 2.1 * i;
+        let (x, y) = (fx * time + phase, fy * time + 2.0 * phase);
+        emitter.position = Vec2::new(0.5, 0.5) + PATH_RADIUS * Vec2::new(x.sin(), y.sin());
+        let heading = Vec2::new(fx * x.cos(), fy * y.cos()).normalize_or_zero();
+        let strength = 0.6 + 0.4 * (0.5 * time + i).sin();
+        emitter.force = heading * FORCE * strength;
+        let hue = (HUE_SPEED * time + i / EMITTERS as f32).fract() * 360.0;
+        let [r, g, b, _] = Color::hsl(hue, 1.0, 0.5).as_linear_rgba_f32();
+        emitter.color = Vec3::new(r, g, b) * 3.0;
+    }
+    emitters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emitters_stay_on_screen() {
+        for step in 0..10_000 {
+            for emitter in emitters_at(step as f32 * 0.37).iter() {
+                assert!((0.0..=1.0).contains(&emitter.position.x));
+                assert!((0.0..=1.0).contains(&emitter.position.y));
+                assert!(emitter.force.length() <= FORCE * 1.001);
+            }
+        }
+    }
+}
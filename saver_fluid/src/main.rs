@@ -0,0 +1,137 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A 2D stable-fluids simulation run entirely in compute shaders. A handful of emitters wander
+//! across the screen stirring the fluid and injecting colored dye, which is carried along by the
+//! flow and slowly fades. Serves as a showcase and stress test of the renderer's compute path.
+//!
+//! Configured with environment variables:
+//!
+//! * `XSECURELOCK_SAVER_FLUID_RESOLUTION`: width of the simulation grid in cells; the height
+//!   follows the window's aspect ratio. Defaults to 256.
+//! * `XSECURELOCK_SAVER_FLUID_PRESSURE_ITERATIONS`: Jacobi iterations used to solve for pressure
+//!   each step. More iterations give a less compressible fluid. Defaults to 30.
+//!
+//! Each frame's step is split into the engine's `XSECURELOCK_SAVER_PHYSICS_SUBSTEPS`, which keep
+//! fast flows stable at the cost of running the solver that many times a frame.
+
+use bevy::prelude::*;
+use bevy::sprite::SpriteResizeMode;
+use bevy_wgpu_xsecurelock::compute::ComputeJobs;
+use xsecurelock_saver::cli::Cli;
+use xsecurelock_saver::config_help::Setting;
+use xsecurelock_saver::engine::{self, XSecurelockSaverPlugins};
+use xsecurelock_saver::env::parse_var_or;
+
+use crate::solver::{FluidJob, DISPLAY_TEXTURE_HANDLE};
+
+mod emitters;
+mod shaders;
+mod solver;
+
+const RESOLUTION_VAR: &str = "XSECURELOCK_SAVER_FLUID_RESOLUTION";
+const PRESSURE_ITERATIONS_VAR: &str = "XSECURELOCK_SAVER_FLUID_PRESSURE_ITERATIONS";
+
+fn main() {
+    Cli::new(engine::config_help("saver_fluid").section("Fluid", FluidConfig::settings()))
+        .about("A stable-fluids simulation stirred by wandering emitters.")
+        .get_matches();
+    let config = FluidConfig::from_env();
+    App::build()
+        .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(config)
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_startup_system(setup.system())
+        .add_system(fit_display.system())
+        .run();
+}
+
+/// Saver settings loaded from the environment.
+#[derive(Debug, Clone, Copy)]
+pub struct FluidConfig {
+    /// Width of the simulation grid in cells.
+    pub resolution: u32,
+    /// Jacobi iterations per pressure solve.
+    pub pressure_iterations: u32,
+}
+
+impl FluidConfig {
+    fn from_env() -> Self {
+        Self {
+            resolution: parse_var_or(RESOLUTION_VAR, 256, |&res| (32..=2048).contains(&res)),
+            pressure_iterations: parse_var_or(PRESSURE_ITERATIONS_VAR, 30, |&iters| {
+                (1..=200).contains(&iters)
+            }),
+        }
+    }
+
+    /// The settings read by [`FluidConfig::from_env`], for `--help-config`.
+    fn settings() -> Vec<Setting> {
+        vec![
+            Setting::new::<u32>(
+                RESOLUTION_VAR,
+                "Width of the simulation grid in cells, from 32 to 2048. The height follows the \
+                 window's aspect ratio.",
+            )
+            .with_default(256),
+            Setting::new::<u32>(
+                PRESSURE_ITERATIONS_VAR,
+                "Jacobi iterations used to solve for pressure each step, from 1 to 200.",
+            )
+            .with_default(30),
+        ]
+    }
+}
+
+/// Marks the sprite showing the simulation.
+struct FluidDisplay;
+
+fn setup(
+    mut commands: Commands,
+    config: Res<FluidConfig>,
+    mut jobs: ResMut<ComputeJobs>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    jobs.add(FluidJob::new(*config));
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands
+        .spawn_bundle(SpriteBundle {
+            // The texture is written by the solver each frame rather than loaded as an asset, so
+            // the sprite can't size itself from it.
+            sprite: Sprite {
+                size: Vec2::ZERO,
+                resize_mode: SpriteResizeMode::Manual,
+                ..Default::default()
+            },
+            material: materials.add(ColorMaterial::texture(
+                DISPLAY_TEXTURE_HANDLE.typed::<Texture>(),
+            )),
+            ..Default::default()
+        })
+        .insert(FluidDisplay);
+}
+
+/// Stretches the display sprite over the whole window.
+fn fit_display(windows: Res<Windows>, mut sprites: Query<&mut Sprite, With<FluidDisplay>>) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let size = Vec2::new(window.width(), window.height());
+    for mut sprite in sprites.iter_mut() {
+        if sprite.size != size {
+            sprite.size = size;
+        }
+    }
+}
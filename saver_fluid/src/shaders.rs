@@ -0,0 +1,190 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! GLSL sources for the solver's compute passes. Every pass shares one bind group layout: the
+//! frame parameters, a linear sampler, two input textures, and an output storage image. Passes
+//! ignore whichever inputs they don't need. With the `PUSH_CONSTANTS` define, the frame parameters
+//! are push constants instead of a uniform buffer.
+
+/// Declarations shared by every pass, up to the output image.
+pub const PRELUDE: &str = r#"#version 450
+layout(local_size_x = 8, local_size_y = 8) in;
+
+const int EMITTERS = 3;
+
+#ifdef PUSH_CONSTANTS
+#define PARAMS_LAYOUT push_constant
+#else
+#define PARAMS_LAYOUT set = 0, binding = 0
+#endif
+
+layout(PARAMS_LAYOUT) uniform Params {
+    vec2 texel;
+    float dt;
+    float aspect;
+    float velocity_dissipation;
+    float dye_dissipation;
+    float splat_radius;
+    float _padding;
+    vec4 emitter_motion[EMITTERS];
+    vec4 emitter_color[EMITTERS];
+};
+layout(set = 0, binding = 1) uniform sampler lin_sampler;
+layout(set = 0, binding = 2) uniform texture2D src_a;
+layout(set = 0, binding = 3) uniform texture2D src_b;
+"#;
+
+/// Output image for the simulation passes.
+pub const FIELD_OUTPUT: &str = r#"
+layout(set = 0, binding = 4, rgba16f) uniform writeonly image2D dst;
+"#;
+
+/// Output image for the display pass.
+pub const DISPLAY_OUTPUT: &str = r#"
+layout(set = 0, binding = 4, rgba8) uniform writeonly image2D dst;
+"#;
+
+/// Helpers shared by every pass, after the output image is declared.
+pub const HELPERS: &str = r#"
+ivec2 cell() {
+    return ivec2(gl_GlobalInvocationID.xy);
+}
+
+bool outside() {
+    return any(greaterThanEqual(cell(), imageSize(dst)));
+}
+
+vec2 uv_of(ivec2 c) {
+    return (vec2(c) + 0.5) * texel;
+}
+
+vec4 sample_a(vec2 uv) {
+    return texture(sampler2D(src_a, lin_sampler), uv);
+}
+
+vec4 sample_b(vec2 uv) {
+    return texture(sampler2D(src_b, lin_sampler), uv);
+}
+
+// Texel fetches clamp to the edge, which keeps the boundaries free-slip.
+vec4 fetch_a(ivec2 c) {
+    return texelFetch(sampler2D(src_a, lin_sampler), clamp(c, ivec2(0), imageSize(dst) - 1), 0);
+}
+
+vec4 fetch_b(ivec2 c) {
+    return texelFetch(sampler2D(src_b, lin_sampler), clamp(c, ivec2(0), imageSize(dst) - 1), 0);
+}
+"#;
+
+/// Semi-Lagrangian advection of `src_b` through the velocity field in `src_a`. Built with `DYE`
+/// defined to use the dye dissipation rather than the velocity dissipation.
+pub const ADVECT: &str = r#"
+void main() {
+    if (outside()) {
+        return;
+    }
+    vec2 uv = uv_of(cell());
+    vec2 back = uv - dt * sample_a(uv).xy * texel;
+#ifdef DYE
+    float dissipation = dye_dissipation;
+#else
+    float dissipation = velocity_dissipation;
+#endif
+    imageStore(dst, cell(), sample_b(back) * dissipation);
+}
+"#;
+
+/// Adds the emitters to the field in `src_a`: their force when splatting velocity, or their color
+/// when built with `DYE` defined.
+pub const SPLAT: &str = r#"
+void main() {
+    if (outside()) {
+        return;
+    }
+    vec2 uv = uv_of(cell());
+    vec4 value = fetch_a(cell());
+    for (int i = 0; i < EMITTERS; i++) {
+        vec2 offset = uv - emitter_motion[i].xy;
+        offset.x *= aspect;
+        float weight = exp(-dot(offset, offset) / splat_radius) * dt;
+#ifdef DYE
+        value.rgb += emitter_color[i].rgb * weight;
+#else
+        value.xy += emitter_motion[i].zw * weight;
+#endif
+    }
+    imageStore(dst, cell(), value);
+}
+"#;
+
+/// Divergence of the velocity field in `src_a`.
+pub const DIVERGENCE: &str = r#"
+void main() {
+    if (outside()) {
+        return;
+    }
+    ivec2 c = cell();
+    float left = fetch_a(c - ivec2(1, 0)).x;
+    float right = fetch_a(c + ivec2(1, 0)).x;
+    float down = fetch_a(c - ivec2(0, 1)).y;
+    float up = fetch_a(c + ivec2(0, 1)).y;
+    imageStore(dst, c, vec4(0.5 * (right - left + up - down), 0.0, 0.0, 1.0));
+}
+"#;
+
+/// One Jacobi iteration solving for pressure in `src_a` given the divergence in `src_b`.
+pub const JACOBI: &str = r#"
+void main() {
+    if (outside()) {
+        return;
+    }
+    ivec2 c = cell();
+    float left = fetch_a(c - ivec2(1, 0)).x;
+    float right = fetch_a(c + ivec2(1, 0)).x;
+    float down = fetch_a(c - ivec2(0, 1)).x;
+    float up = fetch_a(c + ivec2(0, 1)).x;
+    float divergence = fetch_b(c).x;
+    imageStore(dst, c, vec4(0.25 * (left + right + down + up - divergence), 0.0, 0.0, 1.0));
+}
+"#;
+
+/// Subtracts the gradient of the pressure in `src_a` from the velocity in `src_b`, leaving it
+/// divergence free.
+pub const GRADIENT: &str = r#"
+void main() {
+    if (outside()) {
+        return;
+    }
+    ivec2 c = cell();
+    float left = fetch_a(c - ivec2(1, 0)).x;
+    float right = fetch_a(c + ivec2(1, 0)).x;
+    float down = fetch_a(c - ivec2(0, 1)).x;
+    float up = fetch_a(c + ivec2(0, 1)).x;
+    vec4 velocity = fetch_b(c);
+    velocity.xy -= 0.5 * vec2(right - left, up - down);
+    imageStore(dst, c, velocity);
+}
+"#;
+
+/// Tone maps the dye in `src_a` into the displayed image.
+pub const DISPLAY: &str = r#"
+void main() {
+    if (outside()) {
+        return;
+    }
+    vec3 dye = max(fetch_a(cell()).rgb, vec3(0.0));
+    vec3 color = vec3(1.0) - exp(-dye);
+    imageStore(dst, cell(), vec4(color, 1.0));
+}
+"#;
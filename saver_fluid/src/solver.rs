@@ -0,0 +1,608 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The stable-fluids solver, run as a compute job. Each frame it advects velocity, adds the
+//! emitters' forces, projects the velocity to be divergence free with a Jacobi pressure solve,
+//! then advects and injects dye and tone maps it into a texture Bevy draws as a sprite. Every
+//! field is a pair of textures which passes ping-pong between.
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::renderer::{RenderResourceContext, RenderResourceId, TextureId};
+use bevy::render::shader::{Shader, ShaderStage};
+use bevy::render::texture::{
+    Extent3d, FilterMode, SamplerDescriptor, TextureDescriptor, TextureFormat, TextureUsage,
+    SAMPLER_ASSET_INDEX, TEXTURE_ASSET_INDEX,
+};
+use bevy_wgpu_xsecurelock::compute::{ComputeContext, ComputeJob};
+use bevy_wgpu_xsecurelock::push_constants::PushConstants;
+use xsecurelock_saver::engine::{PhysicsSubsteps, SaverTime};
+
+use crate::emitters::{emitters_at, EMITTERS};
+use crate::shaders;
+use crate::FluidConfig;
+
+/// Handle of the texture the solver draws the dye into.
+pub const DISPLAY_TEXTURE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Texture::TYPE_UUID, 0x6a3c_91f2_0d5e_47b8);
+
+/// Format of the simulation fields.
+const FIELD_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Format of the displayed texture.
+const DISPLAY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+/// Workgroup width and height. Must match `local_size` in the shaders.
+const WORKGROUP_SIZE: u32 = 8;
+/// Size of the `Params` block in bytes. Exactly fits the guaranteed push constant space.
+const PARAMS_SIZE: u64 = ((8 + 8 * EMITTERS) * 4) as u64;
+/// Fraction of velocity lost per second.
+const VELOCITY_DISSIPATION: f32 = 0.2;
+/// Fraction of dye lost per second.
+const DYE_DISSIPATION: f32 = 0.35;
+/// Squared radius of the emitters' influence, in UV units.
+const SPLAT_RADIUS: f32 = 0.0008;
+/// Longest time step the solver stays stable with. Shorter than the frames `SaverTime` allows.
+const MAX_DT: f32 = 1.0 / 30.0;
+
+/// Compute job running the simulation.
+pub struct FluidJob {
+    config: FluidConfig,
+    time: f32,
+    /// GPU state, created on the first frame once the window size is known.
+    state: Option<FluidState>,
+}
+
+impl FluidJob {
+    pub fn new(config: FluidConfig) -> Self {
+        Self {
+            config,
+            time: 0.0,
+            state: None,
+        }
+    }
+}
+
+impl ComputeJob for FluidJob {
+    fn run(&mut self, world: &World, context: &mut ComputeContext) {
+        if self.state.is_none() {
+            // The grid is sized once. XSecurelock doesn't resize the saver window.
+            let size = match grid_size(world, self.config.resolution) {
+                Some(size) => size,
+                None => return,
+            };
+            self.state = Some(FluidState::new(context, size));
+        }
+        let dt = world
+            .get_resource::<SaverTime>()
+            .map_or(0.0, |time| time.delta_seconds())
+            .min(MAX_DT);
+        let substeps = world
+            .get_resource::<PhysicsSubsteps>()
+            .copied()
+            .unwrap_or_default();
+        self.time += dt;
+        let state = self.state.as_mut().unwrap();
+        let params = state.params(dt / substeps.count() as f32, self.time);
+        if let Some(ref buffer) = state.params_buffer {
+            context.queue.write_buffer(buffer, 0, params.as_bytes());
+        }
+        state.record(
+            context,
+            &params,
+            self.config.pressure_iterations,
+            substeps.count(),
+        );
+    }
+}
+
+/// Gets the size of the simulation grid: `resolution` cells wide, with the window's aspect ratio.
+fn grid_size(world: &World, resolution: u32) -> Option<(u32, u32)> {
+    let window = world.get_resource::<Windows>()?.get_primary()?;
+    let (width, height) = (window.physical_width(), window.physical_height());
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let rows = (resolution as f32 * height as f32 / width as f32).round() as u32;
+    Some((resolution, rows.max(1)))
+}
+
+/// A texture and its view.
+struct Field {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Field {
+    /// Creates a zeroed field.
+    fn new(context: &ComputeContext, (width, height): (u32, u32), label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FIELD_FORMAT,
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::STORAGE
+                | wgpu::TextureUsage::COPY_DST,
+        });
+        // Texture contents start out undefined, and a single NaN would spread across the grid.
+        let bytes_per_row = width * 8;
+        context.queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &vec![0; (bytes_per_row * height) as usize],
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: height,
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            _texture: texture,
+            view,
+        }
+    }
+}
+
+/// A field with a second texture to write the next step into.
+struct PingPong {
+    fields: [Field; 2],
+    current: usize,
+}
+
+impl PingPong {
+    fn new(context: &ComputeContext, size: (u32, u32), label: &str) -> Self {
+        Self {
+            fields: [
+                Field::new(context, size, label),
+                Field::new(context, size, label),
+            ],
+            current: 0,
+        }
+    }
+
+    fn read(&self) -> &wgpu::TextureView {
+        &self.fields[self.current].view
+    }
+
+    fn write(&self) -> &wgpu::TextureView {
+        &self.fields[1 - self.current].view
+    }
+
+    fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+}
+
+/// Compute pipelines for each pass.
+struct Pipelines {
+    advect_velocity: wgpu::ComputePipeline,
+    advect_dye: wgpu::ComputePipeline,
+    splat_velocity: wgpu::ComputePipeline,
+    splat_dye: wgpu::ComputePipeline,
+    divergence: wgpu::ComputePipeline,
+    jacobi: wgpu::ComputePipeline,
+    gradient: wgpu::ComputePipeline,
+    display: wgpu::ComputePipeline,
+}
+
+/// GPU resources of the simulation.
+struct FluidState {
+    size: (u32, u32),
+    field_layout: wgpu::BindGroupLayout,
+    display_layout: wgpu::BindGroupLayout,
+    pipelines: Pipelines,
+    /// Uniform buffer for the `Params` block, if push constants aren't supported.
+    params_buffer: Option<wgpu::Buffer>,
+    sampler: wgpu::Sampler,
+    velocity: PingPong,
+    dye: PingPong,
+    pressure: PingPong,
+    divergence: Field,
+    /// Bevy's ID for the display texture, which is owned by the render resource context.
+    display: TextureId,
+}
+
+impl FluidState {
+    fn new(context: &ComputeContext, size: (u32, u32)) -> Self {
+        let device = context.device;
+        let push_constants = context.render_resource_context.supports_push_constants();
+        let field_layout = bind_group_layout(device, FIELD_FORMAT, push_constants);
+        let display_layout = bind_group_layout(device, DISPLAY_FORMAT, push_constants);
+        let field_pipeline_layout = pipeline_layout(device, &field_layout, push_constants);
+        let display_pipeline_layout = pipeline_layout(device, &display_layout, push_constants);
+        let field_pipeline = |body, defs: &[&str]| {
+            pipeline(
+                device,
+                &field_pipeline_layout,
+                shaders::FIELD_OUTPUT,
+                body,
+                defs,
+                push_constants,
+            )
+        };
+        let pipelines = Pipelines {
+            advect_velocity: field_pipeline(shaders::ADVECT, &[]),
+            advect_dye: field_pipeline(shaders::ADVECT, &["DYE"]),
+            splat_velocity: field_pipeline(shaders::SPLAT, &[]),
+            splat_dye: field_pipeline(shaders::SPLAT, &["DYE"]),
+            divergence: field_pipeline(shaders::DIVERGENCE, &[]),
+            jacobi: field_pipeline(shaders::JACOBI, &[]),
+            gradient: field_pipeline(shaders::GRADIENT, &[]),
+            display: pipeline(
+                device,
+                &display_pipeline_layout,
+                shaders::DISPLAY_OUTPUT,
+                shaders::DISPLAY,
+                &[],
+                push_constants,
+            ),
+        };
+        let params_buffer = if push_constants {
+            None
+        } else {
+            Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("fluid_params"),
+                size: PARAMS_SIZE,
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+                mapped_at_creation: false,
+            }))
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("fluid_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            size,
+            field_layout,
+            display_layout,
+            pipelines,
+            params_buffer,
+            sampler,
+            velocity: PingPong::new(context, size, "fluid_velocity"),
+            dye: PingPong::new(context, size, "fluid_dye"),
+            pressure: PingPong::new(context, size, "fluid_pressure"),
+            divergence: Field::new(context, size, "fluid_divergence"),
+            display: create_display_texture(context, size),
+        }
+    }
+
+    /// Gets the `Params` block for this frame.
+    fn params(&self, dt: f32, time: f32) -> PushConstants {
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        let mut params = PushConstants::new();
+        params
+            .vec2([1.0 / width, 1.0 / height])
+            .float(dt)
+            .float(width / height)
+            .float((-VELOCITY_DISSIPATION * dt).exp())
+            .float((-DYE_DISSIPATION * dt).exp())
+            .float(SPLAT_RADIUS)
+            .float(0.0);
+        let emitters = emitters_at(time);
+        for emitter in emitters.iter() {
+            let (position, force) = (emitter.position, emitter.force);
+            params.vec4([position.x, position.y, force.x, force.y]);
+        }
+        for emitter in emitters.iter() {
+            let color = emitter.color;
+            params.vec4([color.x, color.y, color.z, 0.0]);
+        }
+        debug_assert_eq!(params.as_bytes().len() as u64, PARAMS_SIZE);
+        params
+    }
+
+    /// Records `substeps` simulation steps, each covering the time step in `params`, and the
+    /// display pass.
+    fn record(
+        &mut self,
+        context: &mut ComputeContext,
+        params: &PushConstants,
+        pressure_iterations: u32,
+        substeps: u32,
+    ) {
+        let texture_views = context
+            .render_resource_context
+            .resources
+            .texture_views
+            .read();
+        let display = match texture_views.get(&self.display) {
+            Some(display) => display,
+            None => return,
+        };
+        let FluidState {
+            ref size,
+            ref field_layout,
+            ref display_layout,
+            ref pipelines,
+            ref params_buffer,
+            ref sampler,
+            ref mut velocity,
+            ref mut dye,
+            ref mut pressure,
+            ref divergence,
+            ..
+        } = *self;
+        let device = context.device;
+        let bind = |layout: &wgpu::BindGroupLayout,
+                    a: &wgpu::TextureView,
+                    b: &wgpu::TextureView,
+                    dst: &wgpu::TextureView| {
+            let mut entries = vec![
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(a),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(b),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(dst),
+                },
+            ];
+            if let Some(buffer) = params_buffer {
+                entries.push(wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                });
+            }
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &entries,
+            })
+        };
+
+        // Bind groups must outlive the pass, so every dispatch is set up before it begins. Unused
+        // inputs are bound to the divergence field, which is never written alongside them.
+        let mut dispatches = Vec::new();
+        for _ in 0..substeps {
+            dispatches.push((
+                &pipelines.advect_velocity,
+                bind(
+                    field_layout,
+                    velocity.read(),
+                    velocity.read(),
+                    velocity.write(),
+                ),
+            ));
+            velocity.swap();
+            dispatches.push((
+                &pipelines.splat_velocity,
+                bind(
+                    field_layout,
+                    velocity.read(),
+                    &divergence.view,
+                    velocity.write(),
+                ),
+            ));
+            velocity.swap();
+            dispatches.push((
+                &pipelines.divergence,
+                bind(
+                    field_layout,
+                    velocity.read(),
+                    pressure.read(),
+                    &divergence.view,
+                ),
+            ));
+            // Pressure carries over between frames as the starting guess for the solve.
+            for _ in 0..pressure_iterations {
+                dispatches.push((
+                    &pipelines.jacobi,
+                    bind(
+                        field_layout,
+                        pressure.read(),
+                        &divergence.view,
+                        pressure.write(),
+                    ),
+                ));
+                pressure.swap();
+            }
+            dispatches.push((
+                &pipelines.gradient,
+                bind(
+                    field_layout,
+                    pressure.read(),
+                    velocity.read(),
+                    velocity.write(),
+                ),
+            ));
+            velocity.swap();
+            dispatches.push((
+                &pipelines.advect_dye,
+                bind(field_layout, velocity.read(), dye.read(), dye.write()),
+            ));
+            dye.swap();
+            dispatches.push((
+                &pipelines.splat_dye,
+                bind(field_layout, dye.read(), &divergence.view, dye.write()),
+            ));
+            dye.swap();
+        }
+        dispatches.push((
+            &pipelines.display,
+            bind(display_layout, dye.read(), &divergence.view, display),
+        ));
+
+        let groups = (
+            (size.0 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+            (size.1 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+        );
+        let mut pass = context
+            .encoder
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("fluid"),
+            });
+        for (pipeline, bind_group) in dispatches.iter() {
+            pass.set_pipeline(pipeline);
+            if params_buffer.is_none() {
+                pass.set_push_constants(0, params.as_bytes());
+            }
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch(groups.0, groups.1, 1);
+        }
+    }
+}
+
+/// Creates the texture the dye is displayed in through Bevy, and registers it as the asset behind
+/// [`DISPLAY_TEXTURE_HANDLE`] so materials can sample it.
+fn create_display_texture(context: &ComputeContext, (width, height): (u32, u32)) -> TextureId {
+    let render_resource_context = context.render_resource_context;
+    let texture = render_resource_context.create_texture(TextureDescriptor {
+        size: Extent3d::new(width, height, 1),
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsage::SAMPLED | TextureUsage::STORAGE,
+        ..Default::default()
+    });
+    let sampler = render_resource_context.create_sampler(&SamplerDescriptor {
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..Default::default()
+    });
+    render_resource_context.set_asset_resource_untyped(
+        DISPLAY_TEXTURE_HANDLE,
+        RenderResourceId::Texture(texture),
+        TEXTURE_ASSET_INDEX,
+    );
+    render_resource_context.set_asset_resource_untyped(
+        DISPLAY_TEXTURE_HANDLE,
+        RenderResourceId::Sampler(sampler),
+        SAMPLER_ASSET_INDEX,
+    );
+    texture
+}
+
+/// Layout shared by every pass, writing to an image of the given format. The `Params` uniform
+/// buffer is left out when it is passed as push constants.
+fn bind_group_layout(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    push_constants: bool,
+) -> wgpu::BindGroupLayout {
+    let texture = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let mut entries = vec![
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::Sampler {
+                filtering: true,
+                comparison: false,
+            },
+            count: None,
+        },
+        texture(2),
+        texture(3),
+        wgpu::BindGroupLayoutEntry {
+            binding: 4,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        },
+    ];
+    if !push_constants {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(PARAMS_SIZE),
+            },
+            count: None,
+        });
+    }
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("fluid_pass"),
+        entries: &entries,
+    })
+}
+
+fn pipeline_layout(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    push_constants: bool,
+) -> wgpu::PipelineLayout {
+    let params = [wgpu::PushConstantRange {
+        stages: wgpu::ShaderStage::COMPUTE,
+        range: 0..PARAMS_SIZE as u32,
+    }];
+    let push_constant_ranges: &[_] = if push_constants { &params } else { &[] };
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("fluid_pass"),
+        bind_group_layouts: &[layout],
+        push_constant_ranges,
+    })
+}
+
+/// Compiles a pass from the shared prelude, its output declaration, and its body.
+fn pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    output: &str,
+    body: &str,
+    defs: &[&str],
+    push_constants: bool,
+) -> wgpu::ComputePipeline {
+    let source = [shaders::PRELUDE, output, shaders::HELPERS, body].concat();
+    let mut defs: Vec<String> = defs.iter().map(|def| def.to_string()).collect();
+    if push_constants {
+        defs.push("PUSH_CONSTANTS".to_string());
+    }
+    let spirv = Shader::from_glsl(ShaderStage::Compute, &source)
+        .get_spirv(Some(&defs))
+        .expect("fluid shaders should compile");
+    let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::SpirV(spirv.into()),
+        flags: Default::default(),
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        module: &module,
+        entry_point: "main",
+    })
+}
@@ -0,0 +1,265 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A decorative asteroid belt: small rocks on circular orbits around the planets' center of mass.
+//! Rocks are moved here rather than by rapier, so they cost nothing to simulate and never touch
+//! the planets or the score. Every rock goes into one mesh, so the whole belt is a single draw.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use xsecurelock_saver::cli;
+
+use crate::config::visualization::{AsteroidBeltConfig, VisualizationConfig};
+use crate::world::{GravityConstant, Planet};
+
+/// Corners of the tetrahedron each rock is drawn as, before scaling by the rock's size.
+const CORNERS: [[f32; 3]; 4] = [
+    [1.0, 1.0, 1.0],
+    [1.0, -1.0, -1.0],
+    [-1.0, 1.0, -1.0],
+    [-1.0, -1.0, 1.0],
+];
+
+/// Faces of the tetrahedron, as indices into [`CORNERS`].
+const FACES: [u32; 12] = [0, 1, 2, 0, 3, 1, 0, 2, 3, 1, 3, 2];
+
+/// Adds the asteroid belt.
+pub struct AsteroidsPlugin;
+
+impl Plugin for AsteroidsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BeltState>()
+            .add_system(rebuild_belt.system().label("rebuild-belt"))
+            .add_system(orbit_rocks.system().after("rebuild-belt"));
+    }
+}
+
+/// The rocks, and the entity drawing them, if the belt is on.
+#[derive(Default)]
+struct BeltState {
+    entity: Option<Entity>,
+    mesh: Handle<Mesh>,
+    rocks: Vec<Rock>,
+}
+
+/// A single rock, on a circular orbit in the horizontal plane.
+#[derive(Debug, Clone, Copy)]
+struct Rock {
+    /// Distance from the center of mass.
+    radius: f32,
+    /// Angle around the orbit, in radians.
+    angle: f32,
+    /// Height above the center of mass.
+    height: f32,
+    /// Scale of the rock's tetrahedron.
+    size: f32,
+}
+
+impl Rock {
+    /// A rock at a random point of the belt.
+    fn random(config: &AsteroidBeltConfig, rng: &mut impl Rng) -> Self {
+        let inner = config.inner_radius.min(config.outer_radius);
+        let outer = config.inner_radius.max(config.outer_radius);
+        let half_thickness = config.thickness / 2.0;
+        Self {
+            // Uniform over the area of the band, so the outside isn't sparser than the inside.
+            radius: rng.gen_range(inner * inner..=outer * outer).sqrt(),
+            angle: rng.gen_range(0.0..TAU),
+            height: rng.gen_range(-half_thickness..=half_thickness),
+            size: config.rock_size * rng.gen_range(0.5..=1.5),
+        }
+    }
+
+    /// Position relative to the center of mass.
+    fn offset(&self) -> Vec3 {
+        Vec3::new(
+            self.radius * self.angle.cos(),
+            self.height,
+            self.radius * self.angle.sin(),
+        )
+    }
+
+    /// Moves the rock `dt` seconds along a circular orbit around `mass`.
+    fn orbit(&mut self, g: f32, mass: f32, dt: f32) {
+        // Circular orbit: v^2 / r = g * m / r^2, so the angular speed is sqrt(g * m / r^3).
+        let angular_speed = (g * mass / self.radius.powi(3)).sqrt();
+        self.angle = (self.angle + angular_speed * dt) % TAU;
+    }
+}
+
+/// Replaces the belt whenever the config changes, so it can be turned on and restyled while
+/// running.
+fn rebuild_belt(
+    mut commands: Commands,
+    config: Res<VisualizationConfig>,
+    mut state: ResMut<BeltState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    if let Some(entity) = state.entity.take() {
+        commands.entity(entity).despawn();
+    }
+    let config = &config.asteroid_belt;
+    if config.count == 0 {
+        state.rocks.clear();
+        return;
+    }
+    let mut rng = cli::rng();
+    state.rocks = (0..config.count)
+        .map(|_| Rock::random(config, &mut rng))
+        .collect();
+    state.mesh = meshes.add(belt_mesh(Vec3::ZERO, &state.rocks));
+    let [r, g, b] = config.color;
+    let entity = commands
+        .spawn_bundle(PbrBundle {
+            mesh: state.mesh.clone(),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(r, g, b),
+                unlit: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .id();
+    state.entity = Some(entity);
+}
+
+/// Moves the rocks along by the physics timestep, around the planets' current center of mass,
+/// and redraws them.
+fn orbit_rocks(
+    mut state: ResMut<BeltState>,
+    g: Res<GravityConstant>,
+    integration: Res<IntegrationParameters>,
+    planets: Query<&RigidBodyMassProps, With<Planet>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if state.entity.is_none() {
+        return;
+    }
+    let (weighted, mass) = planets
+        .iter()
+        .fold((Vec3::ZERO, 0.0), |(weighted, total), props| {
+            let com = props.world_com;
+            (
+                weighted + Vec3::new(com.x, com.y, com.z) * props.mass(),
+                total + props.mass(),
+            )
+        });
+    let center = if mass > 0.0 {
+        weighted / mass
+    } else {
+        Vec3::ZERO
+    };
+    let state = &mut *state;
+    for rock in &mut state.rocks {
+        rock.orbit(g.0, mass, integration.dt);
+    }
+    if let Some(mesh) = meshes.get_mut(&state.mesh) {
+        *mesh = belt_mesh(center, &state.rocks);
+    }
+}
+
+/// Builds a mesh with a small tetrahedron for each rock, around `center`.
+fn belt_mesh(center: Vec3, rocks: &[Rock]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::with_capacity(rocks.len() * CORNERS.len());
+    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(rocks.len() * CORNERS.len());
+    let mut indices = Vec::with_capacity(rocks.len() * FACES.len());
+    for rock in rocks {
+        let start = positions.len() as u32;
+        let position = center + rock.offset();
+        for &corner in &CORNERS {
+            let corner = Vec3::from(corner);
+            positions.push((position + corner * rock.size).into());
+            normals.push(corner.normalize().into());
+        }
+        indices.extend(FACES.iter().map(|&index| start + index));
+    }
+    let uvs = vec![[0.0, 0.0]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rocks_stay_in_the_belt() {
+        let config = AsteroidBeltConfig {
+            inner_radius: 300.0,
+            outer_radius: 200.0,
+            thickness: 10.0,
+            ..Default::default()
+        };
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let rock = Rock::random(&config, &mut rng);
+            assert!((200.0..=300.0).contains(&rock.radius));
+            assert!(rock.height.abs() <= 5.0);
+            let offset = rock.offset();
+            let across = Vec3::new(offset.x, 0.0, offset.z).length();
+            assert!((across - rock.radius).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn rocks_orbit_at_circular_speed() {
+        let g: f32 = 500.0;
+        let mut rock = Rock {
+            radius: 1000.0,
+            angle: 0.0,
+            height: 0.0,
+            size: 1.0,
+        };
+        rock.orbit(g, 2000.0, 0.1);
+        let speed = rock.angle * rock.radius / 0.1;
+        assert!((speed - (g * 2000.0 / 1000.0).sqrt()).abs() < 1e-3);
+        // Nothing to orbit, nothing moves.
+        let before = rock.angle;
+        rock.orbit(g, 0.0, 0.1);
+        assert_eq!(rock.angle, before);
+    }
+
+    #[test]
+    fn draws_a_tetrahedron_per_rock() {
+        let rock = Rock {
+            radius: 10.0,
+            angle: 0.0,
+            height: 0.0,
+            size: 1.0,
+        };
+        let mesh = belt_mesh(Vec3::ONE, &[rock, rock]);
+        assert_eq!(mesh.count_vertices(), 8);
+        match mesh.indices() {
+            Some(Indices::U32(indices)) => {
+                assert_eq!(indices.len(), 24);
+                assert_eq!(indices[12..15], [4, 5, 6]);
+            }
+            other => panic!("unexpected indices {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Plays soft procedural chimes when planets collide. Only available with the `audio-out` feature.
+
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rodio::{OutputStream, Sink, Source};
+use xsecurelock_saver::priority;
+
+use crate::collisions::CollisionStarted;
+use crate::config::sound::SoundConfig;
+use crate::world::Planet;
+
+/// Sample rate of generated sounds.
+const SAMPLE_RATE: u32 = 44100;
+
+/// How long a chime rings for.
+const CHIME_DURATION: Duration = Duration::from_millis(900);
+
+/// Time for a chime to fade in, which avoids clicks.
+const ATTACK_SECONDS: f32 = 0.01;
+
+/// Minimum time between chimes, so a pile up doesn't turn into noise.
+const MIN_CHIME_INTERVAL_SECONDS: f64 = 0.05;
+
+/// Pitch of a collision with the reference mass, in Hz. Heavier collisions are lower.
+const REFERENCE_PITCH: f32 = 440.0;
+
+/// Adds collision sounds.
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        match AudioOutput::start() {
+            Some(output) => {
+                app.insert_resource(output)
+                    .add_system(play_collision_sounds.system());
+            }
+            None => warn!("No audio output available, collision sounds disabled"),
+        }
+    }
+}
+
+/// Sends chimes to the thread which owns the audio output stream. The stream can't be shared
+/// between threads, so it lives on its own thread for the life of the saver.
+struct AudioOutput {
+    sender: Sender<Chime>,
+}
+
+// This is safe because the sender is only used through &mut self, so sharing &self is safe though
+// not useful.
+unsafe impl Sync for AudioOutput {}
+
+impl AudioOutput {
+    /// Starts the audio thread, returning None if there is no audio device.
+    fn start() -> Option<AudioOutput> {
+        let (sender, recv) = mpsc::channel::<Chime>();
+        let (ready_sender, ready) = mpsc::channel();
+        priority::spawn_named("orbits-audio", move || {
+            let (_stream, handle) = match OutputStream::try_default() {
+                Ok(output) => {
+                    let _ = ready_sender.send(true);
+                    output
+                }
+                Err(err) => {
+                    error!("Unable to open audio output: {}", err);
+                    let _ = ready_sender.send(false);
+                    return;
+                }
+            };
+            // Exits when the saver shuts down and the sender is dropped.
+            for chime in recv {
+                match Sink::try_new(&handle) {
+                    Ok(sink) => {
+                        sink.append(chime);
+                        sink.detach();
+                    }
+                    Err(err) => warn!("Unable to play chime: {}", err),
+                }
+            }
+        });
+        if ready.recv().unwrap_or(false) {
+            Some(AudioOutput { sender })
+        } else {
+            None
+        }
+    }
+
+    /// Queues a chime to play.
+    fn play(&mut self, chime: Chime) {
+        // The audio thread only stops if the saver is shutting down, so there's no need to report
+        // a failure here.
+        let _ = self.sender.send(chime);
+    }
+}
+
+/// Plays a chime for each collision between planets.
+fn play_collision_sounds(
+    mut started: EventReader<CollisionStarted>,
+    mut output: ResMut<AudioOutput>,
+    mut last_chime: Local<f64>,
+    config: Res<SoundConfig>,
+    time: Res<Time>,
+    planets: Query<&RigidBodyMassProps, With<Planet>>,
+) {
+    let config = &config.collision_sounds;
+    for &CollisionStarted { first, second, .. } in started.iter() {
+        let now = time.seconds_since_startup();
+        if config.muted
+            || config.max_volume <= 0.0
+            || now - *last_chime < MIN_CHIME_INTERVAL_SECONDS
+        {
+            continue;
+        }
+        let mass = match (planets.get(first), planets.get(second)) {
+            (Ok(first), Ok(second)) => first.mass() + second.mass(),
+            _ => continue,
+        };
+        *last_chime = now;
+        let scale = mass / config.reference_mass;
+        output.play(Chime {
+            frequency: (REFERENCE_PITCH / scale.cbrt()).max(110.0).min(1760.0),
+            volume: config.max_volume * scale / (scale + 1.0),
+            sample: 0,
+        });
+    }
+}
+
+/// A sine wave which fades in quickly then decays exponentially.
+struct Chime {
+    /// Pitch, in Hz.
+    frequency: f32,
+    /// Peak amplitude, from 0 to 1.
+    volume: f32,
+    /// Index of the next sample.
+    sample: u32,
+}
+
+impl Iterator for Chime {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let total = (CHIME_DURATION.as_secs_f32() * SAMPLE_RATE as f32) as u32;
+        if self.sample >= total {
+            return None;
+        }
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        self.sample += 1;
+        let envelope =
+            (t / ATTACK_SECONDS).min(1.0) * (-6.0 * t / CHIME_DURATION.as_secs_f32()).exp();
+        Some(self.volume * envelope * (std::f32::consts::TAU * self.frequency * t).sin())
+    }
+}
+
+impl Source for Chime {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(CHIME_DURATION)
+    }
+}
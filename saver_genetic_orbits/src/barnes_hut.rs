@@ -0,0 +1,271 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Barnes-Hut approximation of gravity, which scales to thousands of planets. Planets are sorted
+//! into an octree, and each planet is pulled by distant cells of the tree as a whole, from the
+//! cell's center of mass, rather than by every planet in them. A cell counts as distant when its
+//! width is less than `theta` times its distance from the planet, so a `theta` of 0 gives the
+//! exact forces and larger values trade accuracy for speed.
+//!
+//! Unlike the pairwise computation, the forces on two planets aren't exactly equal and opposite,
+//! so momentum drifts slightly over time.
+
+use std::ops::Range;
+
+use bevy::prelude::*;
+
+/// Deepest the tree goes. Planets which are still together in a cell this small, such as planets
+/// on top of each other, share a leaf and pull each other directly.
+const MAX_DEPTH: u32 = 24;
+
+/// A cube of the octree.
+#[derive(Debug, Clone)]
+struct Cell {
+    /// Center of the cube.
+    center: Vec3,
+    /// Width of the cube.
+    size: f32,
+    /// Total mass of the planets in the cell.
+    mass: f32,
+    /// Center of mass of the planets in the cell.
+    com: Vec3,
+    /// Range of [`Octree::order`] holding the planets in the cell.
+    bodies: Range<usize>,
+    /// Range of [`Octree::cells`] holding the cell's children, empty for a leaf.
+    children: Range<usize>,
+}
+
+/// An octree over planets given as `(center of mass, mass)`.
+#[derive(Debug, Clone, Default)]
+struct Octree {
+    cells: Vec<Cell>,
+    /// Indices of the planets, ordered so that each cell's planets are contiguous.
+    order: Vec<usize>,
+    /// Position of each planet in `order`.
+    rank: Vec<usize>,
+}
+
+impl Octree {
+    fn build(bodies: &[(Vec3, f32)]) -> Self {
+        let mut tree = Self {
+            cells: vec![],
+            order: (0..bodies.len()).collect(),
+            rank: vec![0; bodies.len()],
+        };
+        if bodies.is_empty() {
+            return tree;
+        }
+        let (min, max) = bodies.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), &(position, _)| (min.min(position), max.max(position)),
+        );
+        tree.cells.push(Cell {
+            center: (min + max) * 0.5,
+            // Pad the cube slightly so planets on its faces fall inside it.
+            size: (max - min).max_element() * 1.001 + f32::EPSILON,
+            mass: 0.0,
+            com: Vec3::ZERO,
+            bodies: 0..bodies.len(),
+            children: 0..0,
+        });
+        tree.split(0, bodies, 0);
+        for (rank, &index) in tree.order.iter().enumerate() {
+            tree.rank[index] = rank;
+        }
+        tree
+    }
+
+    /// Fills in the mass of a cell whose planets are in place, splitting it into children if it
+    /// holds more than one planet.
+    fn split(&mut self, cell: usize, bodies: &[(Vec3, f32)], depth: u32) {
+        let Cell {
+            center,
+            size,
+            bodies: range,
+            ..
+        } = self.cells[cell].clone();
+        let (mass, weighted) =
+            self.order[range.clone()]
+                .iter()
+                .fold((0.0, Vec3::ZERO), |(mass, weighted), &index| {
+                    let (position, body_mass) = bodies[index];
+                    (mass + body_mass, weighted + position * body_mass)
+                });
+        self.cells[cell].mass = mass;
+        self.cells[cell].com = if mass > 0.0 { weighted / mass } else { center };
+        if range.len() <= 1 || depth >= MAX_DEPTH {
+            return;
+        }
+
+        // Sort the cell's planets by octant so each child's planets are contiguous, then add all
+        // the children before filling any of them in, so they are contiguous too.
+        let octant = |index: &usize| {
+            let offset = bodies[*index].0 - center;
+            (offset.x >= 0.0) as usize
+                | ((offset.y >= 0.0) as usize) << 1
+                | ((offset.z >= 0.0) as usize) << 2
+        };
+        self.order[range.clone()].sort_unstable_by_key(octant);
+        let first_child = self.cells.len();
+        let mut start = range.start;
+        while start < range.end {
+            let this_octant = octant(&self.order[start]);
+            let end = start
+                + self.order[start..range.end]
+                    .iter()
+                    .take_while(|index| octant(index) == this_octant)
+                    .count();
+            let sign = |bit: usize| if this_octant & bit != 0 { 1.0 } else { -1.0 };
+            self.cells.push(Cell {
+                center: center + Vec3::new(sign(1), sign(2), sign(4)) * (size * 0.25),
+                size: size * 0.5,
+                mass: 0.0,
+                com: Vec3::ZERO,
+                bodies: start..end,
+                children: 0..0,
+            });
+            start = end;
+        }
+        let children = first_child..self.cells.len();
+        self.cells[cell].children = children.clone();
+        for child in children {
+            self.split(child, bodies, depth + 1);
+        }
+    }
+}
+
+/// Gravitational force on each of the planets given as `(center of mass, mass)`, approximated with
+/// the given `theta`.
+pub fn forces(g: f32, theta: f32, bodies: &[(Vec3, f32)]) -> Vec<Vec3> {
+    let tree = Octree::build(bodies);
+    let mut stack = Vec::new();
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(index, &(position, mass))| {
+            let rank = tree.rank[index];
+            let mut force = Vec3::ZERO;
+            stack.clear();
+            if !tree.cells.is_empty() {
+                stack.push(0);
+            }
+            while let Some(cell) = stack.pop() {
+                let cell = &tree.cells[cell];
+                let contains_self = cell.bodies.contains(&rank);
+                if cell.children.is_empty() {
+                    for &other in &tree.order[cell.bodies.clone()] {
+                        if other != index {
+                            let (other_position, other_mass) = bodies[other];
+                            force += pull(g, position, mass, other_position, other_mass);
+                        }
+                    }
+                } else if !contains_self && cell.size < theta * position.distance(cell.com) {
+                    force += pull(g, position, mass, cell.com, cell.mass);
+                } else {
+                    stack.extend(cell.children.clone());
+                }
+            }
+            force
+        })
+        .collect()
+}
+
+/// Force on a planet at `position` from a mass at `other`, or zero if they are in the same place.
+fn pull(g: f32, position: Vec3, mass: f32, other: Vec3, other_mass: f32) -> Vec3 {
+    let diff = other - position;
+    let dist_sq = diff.length_squared();
+    let magnitude = g * mass * other_mass / dist_sq;
+    if magnitude.is_finite() {
+        diff * (magnitude / dist_sq.sqrt())
+    } else {
+        Vec3::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_bodies(count: usize) -> Vec<(Vec3, f32)> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|_| {
+                let position = Vec3::new(
+                    rng.gen_range(-1000.0..1000.0),
+                    rng.gen_range(-1000.0..1000.0),
+                    rng.gen_range(-1000.0..1000.0),
+                );
+                (position, rng.gen_range(1.0..100.0))
+            })
+            .collect()
+    }
+
+    fn exact(g: f32, bodies: &[(Vec3, f32)]) -> Vec<Vec3> {
+        bodies
+            .iter()
+            .enumerate()
+            .map(|(i, &(position, mass))| {
+                bodies.iter().enumerate().filter(|&(j, _)| j != i).fold(
+                    Vec3::ZERO,
+                    |force, (_, &(other, other_mass))| {
+                        force + pull(g, position, mass, other, other_mass)
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn theta_zero_is_exact() {
+        let bodies = random_bodies(50);
+        for (approx, exact) in forces(500.0, 0.0, &bodies)
+            .iter()
+            .zip(exact(500.0, &bodies))
+        {
+            assert!((*approx - exact).length() <= exact.length() * 1e-4);
+        }
+    }
+
+    #[test]
+    fn approximates_forces() {
+        let bodies = random_bodies(500);
+        let approx = forces(500.0, 0.5, &bodies);
+        let exact = exact(500.0, &bodies);
+        let error: f32 = approx
+            .iter()
+            .zip(&exact)
+            .map(|(approx, exact)| (*approx - *exact).length() / exact.length())
+            .sum::<f32>()
+            / bodies.len() as f32;
+        assert!(error < 0.01, "mean relative error {}", error);
+    }
+
+    #[test]
+    fn handles_planets_on_top_of_each_other() {
+        let bodies = vec![
+            (Vec3::ZERO, 10.0),
+            (Vec3::ZERO, 10.0),
+            (Vec3::new(100.0, 0.0, 0.0), 10.0),
+        ];
+        let forces = forces(500.0, 0.5, &bodies);
+        assert!(forces.iter().all(|force| force.is_finite()));
+        assert_eq!(forces[0], forces[1]);
+        assert!(forces[0].x > 0.0);
+        assert!(forces[2].x < 0.0);
+        assert_eq!(super::forces(500.0, 0.5, &[]), vec![]);
+    }
+}
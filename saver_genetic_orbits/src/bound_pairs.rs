@@ -0,0 +1,434 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Finds pairs of planets which stay gravitationally bound to each other, for the `bound_pairs`
+//! scoring variable, and optionally draws a faint ring around each of them.
+//!
+//! Two planets are bound when the energy of their orbit around each other is negative, so neither
+//! could escape the other without outside help. In a cluster every planet is bound to most of the
+//! others, so each planet is only paired with the partner it is most tightly bound to, and a pair
+//! only counts when that choice is mutual.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy_rapier3d::prelude::*;
+
+use crate::config::scoring::ScoringConfig;
+use crate::config::visualization::VisualizationConfig;
+use crate::model::RadiusLaw;
+use crate::statustracker::{self, ActiveWorld};
+use crate::world::{GravityConstant, PendingPlanets, Planet};
+use crate::SaverState;
+
+/// Number of line segments in each ring.
+const RING_SEGMENTS: u32 = 48;
+
+/// Adds tracking of bound pairs, and the rings showing them.
+pub struct BoundPairsPlugin;
+
+impl Plugin for BoundPairsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BoundPairs>()
+            .init_resource::<RingState>()
+            .add_system(rebuild_rings.system().label("rebuild-rings"))
+            .add_system_set(SystemSet::on_enter(SaverState::Run).with_system(reset.system()))
+            .add_system_set(
+                SystemSet::on_update(SaverState::Run)
+                    .with_system(track_bound_pairs.system().label("track-bound-pairs"))
+                    .with_system(
+                        draw_rings
+                            .system()
+                            .after("track-bound-pairs")
+                            .after("rebuild-rings"),
+                    ),
+            );
+    }
+}
+
+/// A planet as seen by the bound pair search.
+#[derive(Debug, Clone, Copy)]
+pub struct Body {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub mass: f32,
+}
+
+impl Body {
+    /// The planets with the given physics state which are in the scored area.
+    pub fn in_scored_area<'a>(
+        config: &ScoringConfig,
+        planets: impl IntoIterator<Item = (Entity, &'a RigidBodyMassProps, &'a RigidBodyVelocity)>,
+    ) -> Vec<Self> {
+        planets
+            .into_iter()
+            .filter(|(_, mass, _)| statustracker::in_scored_area(config, mass))
+            .map(|(entity, mass, velocity)| {
+                let com = mass.world_com;
+                let linvel = velocity.linvel;
+                Body {
+                    entity,
+                    position: Vec3::new(com.x, com.y, com.z),
+                    velocity: Vec3::new(linvel.x, linvel.y, linvel.z),
+                    mass: mass.mass(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A pair of planets which is currently bound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BoundPair {
+    /// Seconds of simulation the pair has been bound for without a break.
+    seconds: f32,
+    /// The pair's center of mass.
+    center: Vec3,
+    /// Normal of the plane the pair orbits in.
+    normal: Vec3,
+    /// Radius around the center of mass which contains both planets.
+    radius: f32,
+}
+
+impl BoundPair {
+    fn new(a: &Body, b: &Body, law: &RadiusLaw, seconds: f32) -> Self {
+        let center = (a.position * a.mass + b.position * b.mass) / (a.mass + b.mass);
+        let radius = [a, b].iter().fold(0.0f32, |radius, body| {
+            radius.max(body.position.distance(center) + law.radius(body.mass))
+        });
+        let normal = (b.position - a.position)
+            .cross(b.velocity - a.velocity)
+            .normalize_or_zero();
+        Self {
+            seconds,
+            center,
+            // Planets heading straight at or away from each other don't define a plane.
+            normal: if normal == Vec3::ZERO {
+                Vec3::Y
+            } else {
+                normal
+            },
+            radius,
+        }
+    }
+}
+
+/// Resource following which pairs of planets are bound, and for how long.
+#[derive(Debug, Default)]
+pub struct BoundPairs {
+    pairs: HashMap<(Entity, Entity), BoundPair>,
+}
+
+impl BoundPairs {
+    /// Forgets all pairs, so none count until they have been bound for the full time again.
+    pub fn reset(&mut self) {
+        self.pairs.clear();
+    }
+
+    /// Finds the bound pairs among `bodies` after `dt` more seconds of simulation. Pairs which
+    /// were bound on the last update keep counting up, and pairs which aren't bound any more are
+    /// dropped. Planets are sized by `law` to fit them in their pair's ring.
+    pub fn update(&mut self, g: f32, law: &RadiusLaw, bodies: &[Body], dt: f32) {
+        let mut pairs = HashMap::with_capacity(self.pairs.len());
+        for (i, j) in mutual_partners(g, bodies) {
+            let (a, b) = (&bodies[i], &bodies[j]);
+            // The query doesn't promise an order, so key by the entities in a fixed order.
+            let key = (a.entity.min(b.entity), a.entity.max(b.entity));
+            let seconds = self.pairs.get(&key).map_or(0.0, |pair| pair.seconds) + dt;
+            pairs.insert(key, BoundPair::new(a, b, law, seconds));
+        }
+        self.pairs = pairs;
+    }
+
+    /// Number of pairs which have been bound for at least `min_time`.
+    pub fn count(&self, min_time: Duration) -> usize {
+        self.long_lived(min_time).count()
+    }
+
+    fn long_lived(&self, min_time: Duration) -> impl Iterator<Item = &BoundPair> {
+        let min_seconds = min_time.as_secs_f32();
+        self.pairs
+            .values()
+            .filter(move |pair| pair.seconds >= min_seconds)
+    }
+}
+
+/// Energy of two bodies' orbit around each other per unit of reduced mass. Negative if they are
+/// bound.
+fn specific_energy(g: f32, a: &Body, b: &Body) -> f32 {
+    let speed_sq = (b.velocity - a.velocity).length_squared();
+    0.5 * speed_sq - g * (a.mass + b.mass) / a.position.distance(b.position)
+}
+
+/// Indices of pairs of bodies which are each other's most tightly bound partner, lower index
+/// first.
+fn mutual_partners(g: f32, bodies: &[Body]) -> Vec<(usize, usize)> {
+    let mut partners: Vec<Option<(usize, f32)>> = vec![None; bodies.len()];
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let energy = specific_energy(g, &bodies[i], &bodies[j]);
+            // Bodies on top of each other give an infinite energy, which can't be compared.
+            if !energy.is_finite() || energy >= 0.0 {
+                continue;
+            }
+            for &(from, to) in &[(i, j), (j, i)] {
+                if partners[from].map_or(true, |(_, best)| energy < best) {
+                    partners[from] = Some((to, energy));
+                }
+            }
+        }
+    }
+    partners
+        .iter()
+        .enumerate()
+        .filter_map(|(i, partner)| match *partner {
+            Some((j, _)) if j > i && matches!(partners[j], Some((k, _)) if k == i) => Some((i, j)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Starts the pairs over for the new scenario.
+fn reset(mut bound: ResMut<BoundPairs>) {
+    bound.reset();
+}
+
+/// Follows the bound pairs in the scored area, if the scoring function or the rings need them.
+fn track_bound_pairs(
+    scoring: Res<ScoringConfig>,
+    visualization: Res<VisualizationConfig>,
+    g: Res<GravityConstant>,
+    integration: Res<IntegrationParameters>,
+    pending: Res<PendingPlanets>,
+    world: Res<ActiveWorld>,
+    mut bound: ResMut<BoundPairs>,
+    planets: Query<(Entity, &RigidBodyMassProps, &RigidBodyVelocity), With<Planet>>,
+) {
+    if !scoring.score_per_second.uses_bound_pairs() && !visualization.bound_pairs.show {
+        bound.reset();
+        return;
+    }
+    // Pairs in a partly spawned world may only be bound until the rest of it arrives.
+    if pending.is_spawning() {
+        return;
+    }
+    let bodies = Body::in_scored_area(&scoring, planets.iter());
+    bound.update(g.0, &world.world.radius_law, &bodies, integration.dt);
+}
+
+/// The entity drawing the rings, if they are shown.
+#[derive(Default)]
+struct RingState {
+    entity: Option<Entity>,
+    mesh: Handle<Mesh>,
+}
+
+/// Adds or removes the rings whenever the config changes, so they can be turned on and recolored
+/// while running.
+fn rebuild_rings(
+    mut commands: Commands,
+    config: Res<VisualizationConfig>,
+    mut state: ResMut<RingState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    if let Some(entity) = state.entity.take() {
+        commands.entity(entity).despawn();
+    }
+    let config = &config.bound_pairs;
+    if !config.show {
+        return;
+    }
+    state.mesh = meshes.add(ring_mesh(std::iter::empty()));
+    let [r, g, b] = config.color;
+    let entity = commands
+        .spawn_bundle(PbrBundle {
+            mesh: state.mesh.clone(),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(r, g, b),
+                unlit: true,
+                ..Default::default()
+            }),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: false,
+            },
+            ..Default::default()
+        })
+        .id();
+    state.entity = Some(entity);
+}
+
+/// Redraws the rings around the pairs which have been bound long enough to count.
+fn draw_rings(
+    scoring: Res<ScoringConfig>,
+    bound: Res<BoundPairs>,
+    state: Res<RingState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut visible: Query<&mut Visible>,
+) {
+    let entity = match state.entity {
+        Some(entity) => entity,
+        None => return,
+    };
+    let mut pairs = bound.long_lived(scoring.bound_pair_time).peekable();
+    // The entity is spawned by commands, so may not exist until the next frame.
+    if let Ok(mut visible) = visible.get_mut(entity) {
+        visible.is_visible = pairs.peek().is_some();
+    }
+    if let Some(mesh) = meshes.get_mut(&state.mesh) {
+        *mesh = ring_mesh(pairs);
+    }
+}
+
+/// Builds a mesh of a ring around each pair, in the plane the pair orbits in.
+fn ring_mesh<'a>(pairs: impl IntoIterator<Item = &'a BoundPair>) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut indices = vec![];
+    for pair in pairs {
+        let (u, v) = pair.normal.any_orthonormal_pair();
+        let start = positions.len() as u32;
+        positions.extend((0..RING_SEGMENTS).map(|segment| {
+            let angle = segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+            (pair.center + (u * angle.cos() + v * angle.sin()) * pair.radius).into()
+        }));
+        for segment in 0..RING_SEGMENTS {
+            indices.extend_from_slice(&[start + segment, start + (segment + 1) % RING_SEGMENTS]);
+        }
+    }
+    let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+    let uvs = vec![[0.0, 0.0]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(id: u32, position: Vec3, velocity: Vec3, mass: f32) -> Body {
+        Body {
+            entity: Entity::new(id),
+            position,
+            velocity,
+            mass,
+        }
+    }
+
+    /// Two bodies of 1000 circling each other at distance 200, and a third passing by too fast
+    /// to be caught.
+    fn binary_and_passerby(g: f32) -> Vec<Body> {
+        // Circular orbit of each body around the center: v^2 / 100 = g * 1000 / 200^2.
+        let speed = (g * 1000.0 * 100.0 / 200.0f32.powi(2)).sqrt();
+        vec![
+            body(
+                0,
+                Vec3::new(-100.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -speed),
+                1000.0,
+            ),
+            body(
+                1,
+                Vec3::new(100.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, speed),
+                1000.0,
+            ),
+            body(
+                2,
+                Vec3::new(0.0, 0.0, 1000.0),
+                Vec3::new(500.0, 0.0, 0.0),
+                10.0,
+            ),
+        ]
+    }
+
+    #[test]
+    fn pairs_mutually_bound_bodies() {
+        let g: f32 = 500.0;
+        let bodies = binary_and_passerby(g);
+        assert_eq!(mutual_partners(g, &bodies), vec![(0, 1)]);
+        let pair = BoundPair::new(&bodies[0], &bodies[1], &RadiusLaw::default(), 0.0);
+        assert_eq!(pair.center, Vec3::ZERO);
+        assert_eq!(pair.normal, -Vec3::Y);
+        assert!(pair.radius > 100.0);
+
+        // A moon close to one of the pair is that body's tightest partner, splitting the pair.
+        let mut with_moon = bodies;
+        with_moon.push(body(
+            3,
+            Vec3::new(120.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 50.0),
+            1.0,
+        ));
+        assert_eq!(mutual_partners(g, &with_moon), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn counts_pairs_bound_long_enough() {
+        let g: f32 = 500.0;
+        let mut bodies = binary_and_passerby(g);
+        let mut bound = BoundPairs::default();
+        let law = RadiusLaw::default();
+        let min_time = Duration::from_secs(1);
+        for _ in 0..3 {
+            bound.update(g, &law, &bodies, 0.5);
+        }
+        assert_eq!(bound.count(min_time), 1);
+        // The order of bodies doesn't matter.
+        bodies.reverse();
+        bound.update(g, &law, &bodies, 0.5);
+        assert_eq!(bound.count(Duration::from_secs(2)), 1);
+
+        // Separating the pair breaks it, and it has to start over.
+        bodies[1].velocity.z += 1000.0;
+        bound.update(g, &law, &bodies, 0.5);
+        assert_eq!(bound.count(Duration::from_secs(0)), 0);
+        bodies[1].velocity.z -= 1000.0;
+        bound.update(g, &law, &bodies, 0.5);
+        assert_eq!(bound.count(Duration::from_secs(0)), 1);
+        assert_eq!(bound.count(min_time), 0);
+    }
+
+    #[test]
+    fn rings_close_around_each_pair() {
+        let pair = BoundPair {
+            seconds: 0.0,
+            center: Vec3::new(10.0, 0.0, 0.0),
+            normal: Vec3::Z,
+            radius: 5.0,
+        };
+        let mesh = ring_mesh(&[pair, pair]);
+        assert_eq!(mesh.count_vertices(), 2 * RING_SEGMENTS as usize);
+        match mesh.indices() {
+            Some(Indices::U32(indices)) => {
+                assert_eq!(indices.len(), 4 * RING_SEGMENTS as usize);
+                let last = RING_SEGMENTS - 1;
+                assert_eq!(indices[2 * last as usize..][..2], [last, 0]);
+            }
+            other => panic!("unexpected indices {:?}", other),
+        }
+    }
+}
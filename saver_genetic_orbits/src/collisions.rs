@@ -0,0 +1,554 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregates Rapier's contact and intersection events into counters, once per frame, so scoring,
+//! effects and diagnostics can all read the [`Collisions`] resource rather than each keeping its
+//! own event reader. Systems which need to follow individual pairs of planets can read the
+//! [`CollisionStarted`], [`CollisionPersisted`], [`CollisionMerged`] and [`CollisionEnded`] events
+//! instead, which are sent from the same tracked pair state. Events are kept for two frames, so
+//! every reader sees each of them once wherever it runs in the frame, with no ordering against the
+//! counting system. Events for touching planets carry the deepest point of
+//! their contact from Rapier's narrow phase, where it has one, so responders don't need to work out
+//! the geometry again.
+//!
+//! Planets don't actually merge while the simulation runs, but colliding planets with no bounce
+//! tend to stick together. A pair which stays in contact for [`MERGE_TIME`] is counted as a merge.
+//! A near miss is a pair of planets which came close enough for Rapier to start checking them for
+//! contact, roughly when their bounding boxes overlap, then moved apart without ever touching.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use xsecurelock_saver::engine::SaverTime;
+
+use crate::SaverState;
+
+/// How long a pair of planets has to stay in contact to count as merged.
+pub const MERGE_TIME: Duration = Duration::from_secs(1);
+
+/// Plugin which counts collisions between planets.
+pub struct CollisionsPlugin;
+
+impl Plugin for CollisionsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Collisions>()
+            .add_event::<CollisionStarted>()
+            .add_event::<CollisionPersisted>()
+            .add_event::<CollisionMerged>()
+            .add_event::<CollisionEnded>()
+            .add_system(count_collisions.system().label("count-collisions"))
+            .add_system_set(SystemSet::on_enter(SaverState::Run).with_system(reset.system()));
+    }
+}
+
+/// Numbers of each kind of collision event.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionCounts {
+    /// Pairs of planets which started touching.
+    pub collisions: u32,
+    /// Pairs of planets which stopped touching.
+    pub separations: u32,
+    /// Pairs of planets which have been touching for [`MERGE_TIME`].
+    pub merges: u32,
+    /// Pairs of planets which came close then moved apart without touching.
+    pub near_misses: u32,
+    /// Colliders which started intersecting a sensor.
+    pub intersections: u32,
+}
+
+impl AddAssign for CollisionCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.collisions += other.collisions;
+        self.separations += other.separations;
+        self.merges += other.merges;
+        self.near_misses += other.near_misses;
+        self.intersections += other.intersections;
+    }
+}
+
+/// The deepest point of contact between two touching planets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionManifold {
+    /// Where the planets touch, in world space.
+    pub point: Vec3,
+    /// How far the planets overlap at the point, or 0 if they are only just touching.
+    pub depth: f32,
+    /// Unit normal of the contact, pointing from the first planet towards the second.
+    pub normal: Vec3,
+}
+
+impl CollisionManifold {
+    /// The deepest of the contact points Rapier found between the pair's colliders, or None if it
+    /// found none this step. If `flip` is set, the pair's second collider is the first planet.
+    fn deepest(contact: &ContactPair, flip: bool) -> Option<Self> {
+        contact
+            .manifolds
+            .iter()
+            .flat_map(|manifold| {
+                manifold
+                    .data
+                    .solver_contacts
+                    .iter()
+                    .map(move |point| (manifold, point))
+            })
+            .min_by(|a, b| a.1.dist.partial_cmp(&b.1.dist).unwrap_or(Ordering::Equal))
+            .map(|(manifold, point)| {
+                let normal = manifold.data.normal;
+                let normal = Vec3::new(normal.x, normal.y, normal.z);
+                Self {
+                    point: Vec3::new(point.point.x, point.point.y, point.point.z),
+                    depth: (-point.dist).max(0.0),
+                    normal: if flip { -normal } else { normal },
+                }
+            })
+    }
+}
+
+/// Sent when two planets start touching.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionStarted {
+    pub first: Entity,
+    pub second: Entity,
+    /// Where the planets touch, if Rapier has found the contact point yet.
+    pub manifold: Option<CollisionManifold>,
+}
+
+/// Sent each frame for every pair of planets which is still touching after the frame it started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionPersisted {
+    pub first: Entity,
+    pub second: Entity,
+    /// How long the planets have been touching, including this frame.
+    pub duration: Duration,
+    /// Where the planets touch, if Rapier found a contact point this frame.
+    pub manifold: Option<CollisionManifold>,
+}
+
+/// Sent when two planets have been touching for [`MERGE_TIME`], and so count as merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionMerged {
+    pub first: Entity,
+    pub second: Entity,
+}
+
+/// Sent when two planets stop touching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionEnded {
+    pub first: Entity,
+    pub second: Entity,
+    /// How long the planets were touching for.
+    pub duration: Duration,
+}
+
+/// Collisions between planets, updated once per frame.
+#[derive(Default)]
+pub struct Collisions {
+    /// Counts for the last frame.
+    pub frame: CollisionCounts,
+    /// Counts since the current scenario started.
+    pub scenario: CollisionCounts,
+    /// Pairs being tracked for merges and near misses.
+    pairs: PairTracker,
+}
+
+/// A pair of entities, in a consistent order.
+type Pair = (Entity, Entity);
+
+fn pair(first: Entity, second: Entity) -> Pair {
+    if first <= second {
+        (first, second)
+    } else {
+        (second, first)
+    }
+}
+
+/// What's known about a pair of planets Rapier is checking for contact.
+#[derive(Debug, Default, Clone, Copy)]
+struct PairState {
+    /// Whether the planets are touching now.
+    touching: bool,
+    /// Whether the planets have touched since they came close.
+    touched: bool,
+    /// How long the planets have been touching.
+    touching_for: Duration,
+    /// Whether the pair has already been counted as a merge.
+    merged: bool,
+}
+
+/// Follows pairs of planets from when they come close until they move apart.
+#[derive(Debug, Default)]
+struct PairTracker {
+    pairs: HashMap<Pair, PairState>,
+    /// Pairs counted as merged in the last update.
+    merged: Vec<Pair>,
+}
+
+impl PairTracker {
+    /// Updates the tracked pairs with one frame's contact events, given every pair Rapier is
+    /// currently checking for contact. Returns the counts for the frame, apart from intersections,
+    /// and adds the frame's persisted and ended contacts to `persisted` and `ended`. Persisted
+    /// contacts are added without a manifold.
+    fn update(
+        &mut self,
+        started: &[Pair],
+        stopped: &[Pair],
+        nearby: &HashSet<Pair>,
+        delta: Duration,
+        persisted: &mut Vec<CollisionPersisted>,
+        ended: &mut Vec<CollisionEnded>,
+    ) -> CollisionCounts {
+        let mut counts = CollisionCounts {
+            collisions: started.len() as u32,
+            separations: stopped.len() as u32,
+            ..Default::default()
+        };
+        self.merged.clear();
+        for &(first, second) in stopped {
+            match self.pairs.get_mut(&(first, second)) {
+                Some(state) if state.touching => {
+                    state.touching = false;
+                    ended.push(CollisionEnded {
+                        first,
+                        second,
+                        duration: state.touching_for,
+                    });
+                }
+                _ => {}
+            }
+        }
+        for &pair in started {
+            let state = self.pairs.entry(pair).or_default();
+            state.touching = true;
+            state.touched = true;
+            state.touching_for = Duration::from_secs(0);
+        }
+        for &pair in nearby {
+            self.pairs.entry(pair).or_default();
+        }
+
+        let merged = &mut self.merged;
+        self.pairs.retain(|pair, state| {
+            // Rapier sometimes reports the end of a contact a frame after dropping the pair.
+            if !nearby.contains(pair) && !state.touching {
+                if !state.touched {
+                    counts.near_misses += 1;
+                }
+                return false;
+            }
+            if state.touching {
+                state.touching_for += delta;
+                if !state.merged && state.touching_for >= MERGE_TIME {
+                    state.merged = true;
+                    counts.merges += 1;
+                    merged.push(*pair);
+                }
+                if !started.contains(pair) {
+                    persisted.push(CollisionPersisted {
+                        first: pair.0,
+                        second: pair.1,
+                        duration: state.touching_for,
+                        manifold: None,
+                    });
+                }
+            }
+            true
+        });
+        counts
+    }
+}
+
+/// Reads this frame's contact and intersection events into the counters, and sends the events
+/// for each pair of touching planets.
+#[allow(clippy::too_many_arguments)]
+fn count_collisions(
+    time: Res<SaverTime>,
+    narrow_phase: Res<NarrowPhase>,
+    mut contacts: EventReader<ContactEvent>,
+    mut intersections: EventReader<IntersectionEvent>,
+    mut collisions: ResMut<Collisions>,
+    mut started_events: EventWriter<CollisionStarted>,
+    mut persisted_events: EventWriter<CollisionPersisted>,
+    mut merged_events: EventWriter<CollisionMerged>,
+    mut ended_events: EventWriter<CollisionEnded>,
+) {
+    let mut started = Vec::new();
+    let mut stopped = Vec::new();
+    for contact in contacts.iter() {
+        match contact {
+            ContactEvent::Started(first, second) => {
+                started.push(pair(first.entity(), second.entity()))
+            }
+            ContactEvent::Stopped(first, second) => {
+                stopped.push(pair(first.entity(), second.entity()))
+            }
+        }
+    }
+    let mut nearby = HashSet::new();
+    let mut manifolds = HashMap::new();
+    for contact in narrow_phase.contact_pairs() {
+        let first = contact.collider1.entity();
+        let key = pair(first, contact.collider2.entity());
+        nearby.insert(key);
+        if let Some(manifold) = CollisionManifold::deepest(contact, first != key.0) {
+            manifolds.insert(key, manifold);
+        }
+    }
+
+    let collisions = &mut *collisions;
+    let mut persisted = Vec::new();
+    let mut ended = Vec::new();
+    let mut counts = collisions.pairs.update(
+        &started,
+        &stopped,
+        &nearby,
+        time.delta(),
+        &mut persisted,
+        &mut ended,
+    );
+    counts.intersections = intersections
+        .iter()
+        .filter(|intersection| intersection.intersecting)
+        .count() as u32;
+    collisions.frame = counts;
+    collisions.scenario += counts;
+    started_events.send_batch(started.iter().map(|&(first, second)| CollisionStarted {
+        first,
+        second,
+        manifold: manifolds.get(&(first, second)).copied(),
+    }));
+    for event in &mut persisted {
+        event.manifold = manifolds.get(&(event.first, event.second)).copied();
+    }
+    persisted_events.send_batch(persisted.into_iter());
+    sort_by_impact(&mut collisions.pairs, &manifolds);
+    merged_events.send_batch(
+        collisions
+            .pairs
+            .merged
+            .iter()
+            .map(|&(first, second)| CollisionMerged { first, second }),
+    );
+    ended_events.send_batch(ended.into_iter());
+}
+
+/// Sorts the pairs merged in the last update into the order the planets hit each other, so the
+/// merges of a pileup are handled in a consistent order. Pairs which have been touching longest
+/// hit first. Among pairs which started touching in the same frame, the one overlapping most
+/// deeply hit earliest in the frame. Any remaining ties go by entity.
+fn sort_by_impact(tracker: &mut PairTracker, manifolds: &HashMap<Pair, CollisionManifold>) {
+    let pairs = &tracker.pairs;
+    let key = |pair: &Pair| {
+        let touching_for = pairs
+            .get(pair)
+            .map_or(Duration::from_secs(0), |state| state.touching_for);
+        let depth = manifolds.get(pair).map_or(0.0, |manifold| manifold.depth);
+        (touching_for, depth)
+    };
+    tracker.merged.sort_by(|a, b| {
+        let (a_time, a_depth) = key(a);
+        let (b_time, b_depth) = key(b);
+        b_time
+            .cmp(&a_time)
+            .then(b_depth.partial_cmp(&a_depth).unwrap_or(Ordering::Equal))
+            .then(a.cmp(b))
+    });
+}
+
+/// Clears the counters when a new scenario starts.
+fn reset(mut collisions: ResMut<Collisions>) {
+    *collisions = Collisions::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities() -> (Entity, Entity, Entity) {
+        (Entity::new(0), Entity::new(1), Entity::new(2))
+    }
+
+    /// Updates the tracker, dropping the persisted and ended contacts.
+    fn update(
+        tracker: &mut PairTracker,
+        started: &[Pair],
+        stopped: &[Pair],
+        nearby: &HashSet<Pair>,
+        delta: Duration,
+    ) -> CollisionCounts {
+        tracker.update(
+            started,
+            stopped,
+            nearby,
+            delta,
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+    }
+
+    #[test]
+    fn orders_pairs() {
+        let (a, b, _) = entities();
+        assert_eq!(pair(a, b), pair(b, a));
+    }
+
+    #[test]
+    fn counts_near_misses() {
+        let (a, b, c) = entities();
+        let frame = Duration::from_millis(100);
+        let mut tracker = PairTracker::default();
+        let nearby: HashSet<Pair> = vec![pair(a, b), pair(b, c)].into_iter().collect();
+        let counts = update(&mut tracker, &[pair(b, c)], &[], &nearby, frame);
+        assert_eq!(counts.collisions, 1);
+        assert_eq!(counts.near_misses, 0);
+
+        // Both pairs move apart, but only a and b never touched.
+        let counts = update(&mut tracker, &[], &[pair(b, c)], &HashSet::new(), frame);
+        assert_eq!(counts.separations, 1);
+        assert_eq!(counts.near_misses, 1);
+        assert!(tracker.pairs.is_empty());
+    }
+
+    #[test]
+    fn counts_lasting_contacts_as_merges_once() {
+        let (a, b, _) = entities();
+        let frame = Duration::from_millis(400);
+        let mut tracker = PairTracker::default();
+        let nearby: HashSet<Pair> = vec![pair(a, b)].into_iter().collect();
+        let mut merges = update(&mut tracker, &[pair(a, b)], &[], &nearby, frame).merges;
+        let mut merged = tracker.merged.clone();
+        for _ in 0..5 {
+            merges += update(&mut tracker, &[], &[], &nearby, frame).merges;
+            merged.extend_from_slice(&tracker.merged);
+        }
+        assert_eq!(merges, 1);
+        assert_eq!(merged, vec![pair(a, b)]);
+    }
+
+    #[test]
+    fn sorts_merges_by_impact() {
+        let (a, b, c) = entities();
+        let d = Entity::new(3);
+        let frame = Duration::from_millis(100);
+        let mut tracker = PairTracker::default();
+        let nearby: HashSet<Pair> = vec![pair(a, b), pair(b, c), pair(c, d), pair(a, d)]
+            .into_iter()
+            .collect();
+        update(&mut tracker, &[pair(c, d)], &[], &nearby, frame);
+        let others = [pair(a, b), pair(b, c), pair(a, d)];
+        update(&mut tracker, &others, &[], &nearby, frame);
+        // A long frame, so every pair merges at once.
+        update(&mut tracker, &[], &[], &nearby, MERGE_TIME);
+        assert_eq!(tracker.merged.len(), 4);
+
+        let manifold = |depth| CollisionManifold {
+            point: Vec3::ZERO,
+            depth,
+            normal: Vec3::X,
+        };
+        let mut manifolds = HashMap::new();
+        manifolds.insert(pair(b, c), manifold(0.5));
+        manifolds.insert(pair(a, b), manifold(0.1));
+        sort_by_impact(&mut tracker, &manifolds);
+        assert_eq!(
+            tracker.merged,
+            vec![pair(c, d), pair(b, c), pair(a, b), pair(a, d)]
+        );
+    }
+
+    #[test]
+    fn bounces_are_not_merges() {
+        let (a, b, _) = entities();
+        let frame = Duration::from_millis(400);
+        let mut tracker = PairTracker::default();
+        let nearby: HashSet<Pair> = vec![pair(a, b)].into_iter().collect();
+        let mut merges = 0;
+        for _ in 0..5 {
+            merges += update(&mut tracker, &[pair(a, b)], &[], &nearby, frame).merges;
+            merges += update(&mut tracker, &[], &[pair(a, b)], &nearby, frame).merges;
+        }
+        assert_eq!(merges, 0);
+    }
+
+    #[test]
+    fn reports_persisted_and_ended_contacts() {
+        let (a, b, _) = entities();
+        let frame = Duration::from_millis(100);
+        let mut tracker = PairTracker::default();
+        let nearby: HashSet<Pair> = vec![pair(a, b)].into_iter().collect();
+        let (mut persisted, mut ended) = (Vec::new(), Vec::new());
+        tracker.update(
+            &[pair(a, b)],
+            &[],
+            &nearby,
+            frame,
+            &mut persisted,
+            &mut ended,
+        );
+        assert!(persisted.is_empty());
+        for _ in 0..2 {
+            tracker.update(&[], &[], &nearby, frame, &mut persisted, &mut ended);
+        }
+        assert_eq!(
+            persisted,
+            vec![
+                CollisionPersisted {
+                    first: a,
+                    second: b,
+                    duration: frame * 2,
+                    manifold: None,
+                },
+                CollisionPersisted {
+                    first: a,
+                    second: b,
+                    duration: frame * 3,
+                    manifold: None,
+                },
+            ]
+        );
+        assert!(ended.is_empty());
+
+        persisted.clear();
+        tracker.update(
+            &[],
+            &[pair(a, b)],
+            &nearby,
+            frame,
+            &mut persisted,
+            &mut ended,
+        );
+        assert!(persisted.is_empty());
+        assert_eq!(
+            ended,
+            vec![CollisionEnded {
+                first: a,
+                second: b,
+                duration: frame * 3,
+            }]
+        );
+        // A late report of the same contact ending isn't sent again.
+        tracker.update(
+            &[],
+            &[pair(a, b)],
+            &nearby,
+            frame,
+            &mut persisted,
+            &mut ended,
+        );
+        assert_eq!(ended.len(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compensated summation, which keeps the rounding error of long sums from growing with the number
+//! of terms.
+
+/// A running sum of 3d vectors which keeps track of the rounding error lost from each addition,
+/// using Neumaier's variant of Kahan summation.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompensatedSum {
+    /// Naive running sum.
+    sum: [f64; 3],
+    /// Accumulated rounding error of the naive sum.
+    compensation: [f64; 3],
+}
+
+impl CompensatedSum {
+    /// Adds a vector to the sum.
+    pub fn add(&mut self, value: [f64; 3]) {
+        for axis in 0..3 {
+            let sum = self.sum[axis];
+            let x = value[axis];
+            let t = sum + x;
+            self.compensation[axis] += if sum.abs() >= x.abs() {
+                (sum - t) + x
+            } else {
+                (x - t) + sum
+            };
+            self.sum[axis] = t;
+        }
+    }
+
+    /// Gets the compensated total.
+    pub fn total(&self) -> [f64; 3] {
+        [
+            self.sum[0] + self.compensation[0],
+            self.sum[1] + self.compensation[1],
+            self.sum[2] + self.compensation[2],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_small_terms() {
+        let mut sum = CompensatedSum::default();
+        sum.add([1e100, 1.0, 0.1]);
+        sum.add([1.0, 1e100, 0.2]);
+        sum.add([-1e100, -1e100, 0.3]);
+        let [x, y, z] = sum.total();
+        assert_eq!(x, 1.0);
+        assert_eq!(y, 1.0);
+        assert_eq!(z, 0.6);
+    }
+}
@@ -13,6 +13,9 @@
 // limitations under the License.
 
 use serde::{Deserialize, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
+
+use super::util::{deserialize_non_negative, deserialize_positive, Vector};
 
 /// Configuration for the scenario camera.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -23,6 +26,18 @@ pub struct CameraConfig {
 
     /// How far from the origin the camera should be.
     pub view_dist: f32,
+
+    /// Time in seconds the camera takes to get most of the way to a new rotation speed, so the
+    /// camera starts smoothly instead of jumping to full speed. 0 changes speed immediately.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub speed_easing_seconds: f32,
+
+    /// Vertical oscillation of the camera while it orbits.
+    pub tilt: TiltConfig,
+
+    /// Keyframed paths for the camera to follow. If any are given, the camera plays them in order
+    /// and loops back to the first once the last one finishes, instead of orbiting.
+    pub paths: Vec<CameraPath>,
 }
 
 impl Default for CameraConfig {
@@ -30,6 +45,178 @@ impl Default for CameraConfig {
         Self {
             rotation_speed: 0.1,
             view_dist: 1000.0,
+            speed_easing_seconds: 0.0,
+            tilt: Default::default(),
+            paths: Vec::new(),
+        }
+    }
+}
+
+impl DescribeConfig for CameraConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "rotation_speed",
+            |c| &c.rotation_speed,
+            "Relative rotation speed.",
+        )
+        .key(
+            "view_dist",
+            |c| &c.view_dist,
+            "How far from the origin the camera is.",
+        )
+        .key(
+            "speed_easing_seconds",
+            |c| &c.speed_easing_seconds,
+            "Time in seconds the camera takes to get most of the way to a new rotation \
+                 speed. 0 changes speed immediately.",
+        )
+        .table(
+            "tilt",
+            |c| &c.tilt,
+            "Vertical oscillation of the camera while it orbits.",
+        )
+        .key(
+            "paths",
+            |c| &c.paths,
+            "Keyframed paths for the camera to follow in a loop instead of orbiting. Each has \
+                 `keyframes` with a `time` and `position`, an `interpolation` of `linear` or \
+                 `smooth`, and a point to `look_at`.",
+        );
+    }
+}
+
+/// Moves the camera up and down while it orbits the origin.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TiltConfig {
+    /// Maximum angle above and below the orbital plane, in radians. 0 disables tilting.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub amplitude: f32,
+
+    /// Time in seconds for a full up and down oscillation.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub period_seconds: f32,
+}
+
+impl Default for TiltConfig {
+    fn default() -> Self {
+        Self {
+            amplitude: 0.0,
+            period_seconds: 60.0,
+        }
+    }
+}
+
+impl DescribeConfig for TiltConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "amplitude",
+            |c| &c.amplitude,
+            "Maximum angle above and below the orbital plane, in radians. 0 disables tilting.",
+        )
+        .key(
+            "period_seconds",
+            |c| &c.period_seconds,
+            "Time in seconds for a full up and down oscillation.",
+        );
+    }
+}
+
+/// A path for the camera to follow, made of a series of keyframes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "camera_path_de::CameraPath")]
+pub struct CameraPath {
+    /// Points along the path. Never empty, and sorted by time.
+    pub keyframes: Vec<CameraKeyframe>,
+
+    /// How to move between keyframes.
+    pub interpolation: Interpolation,
+
+    /// Point the camera looks at while following this path.
+    pub look_at: Vector<f32>,
+}
+
+impl CameraPath {
+    /// How long the path takes to play, which is the time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+}
+
+/// A point on a camera path.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CameraKeyframe {
+    /// Time in seconds from the start of the path when the camera reaches this point.
+    pub time: f32,
+
+    /// Position of the camera at this point.
+    pub position: Vector<f32>,
+}
+
+/// How the camera moves between keyframes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Move in a straight line at constant speed between each pair of keyframes.
+    Linear,
+    /// Move along a curve through all of the keyframes, without sudden changes of direction.
+    Smooth,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Smooth
+    }
+}
+
+mod camera_path_de {
+    use std::convert::TryFrom;
+
+    use serde::Deserialize;
+
+    use super::super::util::Vector;
+    use super::{CameraKeyframe, Interpolation};
+
+    /// Shadow type that can implement Deserialize.
+    #[derive(Deserialize, Debug, Clone)]
+    pub(super) struct CameraPath {
+        keyframes: Vec<CameraKeyframe>,
+        #[serde(default)]
+        interpolation: Interpolation,
+        #[serde(default = "origin")]
+        look_at: Vector<f32>,
+    }
+
+    fn origin() -> Vector<f32> {
+        Vector {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    impl TryFrom<CameraPath> for super::CameraPath {
+        type Error = String;
+
+        fn try_from(path: CameraPath) -> Result<Self, String> {
+            if path.keyframes.is_empty() {
+                return Err("camera path must have at least one keyframe".to_string());
+            }
+            let mut prev = 0.0;
+            for keyframe in &path.keyframes {
+                if !keyframe.time.is_finite() || keyframe.time < prev {
+                    return Err(format!(
+                        "camera keyframe times must not be negative or decreasing, got {} after {}",
+                        keyframe.time, prev,
+                    ));
+                }
+                prev = keyframe.time;
+            }
+            Ok(Self {
+                keyframes: path.keyframes,
+                interpolation: path.interpolation,
+                look_at: path.look_at,
+            })
         }
     }
 }
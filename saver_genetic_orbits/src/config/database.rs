@@ -17,11 +17,15 @@
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
 
 /// Configuration parameters for the Sqlite Database.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct DatabaseConfig {
+    /// Which storage backend to keep scenarios in. Defaults to sqlite.
+    pub backend: StorageBackend,
+
     /// The path to the SqliteDatabase to use. If set, the parent directory must exist and the
     /// location must be writable. Saver will never fall back to an in-memory database if this is
     /// set.
@@ -37,14 +41,148 @@ pub struct DatabaseConfig {
     /// 20 minutes (1200 seconds). Regardless of what this is set to, it will always prune on
     /// shutdown unless max_scenarios_to_keep is unset.
     pub prune_interval_seconds: u64,
+
+    /// Which integrity check to run on the database at startup. If the database is corrupt, it is
+    /// moved aside and as many scenarios as possible are copied into a fresh database. Defaults to
+    /// the quick check.
+    pub integrity_check: IntegrityCheck,
+
+    /// Worlds in databases from the old 2D saver are moved into the 3D model at startup, flat in
+    /// the z = 0 plane. If this is more than zero, each of their planets is then moved off the
+    /// plane by a random distance of up to this much, so they don't stay flat. Defaults to 0.
+    pub migration_jitter: f32,
+
+    /// Backups of the database taken before migrations and large prunes.
+    pub backup: BackupConfig,
 }
 
 impl Default for DatabaseConfig {
     fn default() -> Self {
         DatabaseConfig {
+            backend: StorageBackend::Sqlite,
             database_path: None,
             max_scenarios_to_keep: Some(1000000),
             prune_interval_seconds: 1200,
+            integrity_check: IntegrityCheck::Quick,
+            migration_jitter: 0.0,
+            backup: Default::default(),
         }
     }
 }
+
+impl DescribeConfig for DatabaseConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "backend",
+            |c| &c.backend,
+            "Where to keep scenarios: `sqlite`, or `null` to keep none.",
+        )
+        .key(
+            "database_path",
+            |c| &c.database_path,
+            "The sqlite database to use. The saver never falls back to an in-memory database if \
+             this is set.",
+        )
+        .key(
+            "max_scenarios_to_keep",
+            |c| &c.max_scenarios_to_keep,
+            "Cap on the number of scenarios kept in the database, or unlimited if null.",
+        )
+        .key(
+            "prune_interval_seconds",
+            |c| &c.prune_interval_seconds,
+            "How often to prune excess scenarios while running. They are also pruned on shutdown.",
+        )
+        .key(
+            "integrity_check",
+            |c| &c.integrity_check,
+            "Check to run on the database at startup: `none`, `quick` or `full`.",
+        )
+        .key(
+            "migration_jitter",
+            |c| &c.migration_jitter,
+            "How far planets of worlds migrated from the old 2D saver are moved off the z = 0 \
+             plane, at most.",
+        )
+        .table(
+            "backup",
+            |c| &c.backup,
+            "Backups of the database taken before large prunes.",
+        );
+    }
+}
+
+/// Backends which scenarios can be stored in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// Store scenarios in sqlite, either at `database_path` or in memory if that isn't set.
+    Sqlite,
+    /// Don't store scenarios at all, so every world is newly generated. None of the other
+    /// database settings apply.
+    Null,
+}
+
+/// Integrity checks which can be run on the database at startup.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityCheck {
+    /// Don't check the database.
+    None,
+    /// Run sqlite's `quick_check`, which skips some of the slower index checks.
+    Quick,
+    /// Run sqlite's full `integrity_check`.
+    Full,
+}
+
+/// Configures backups of the database, which guard against a bad prune or migration throwing away a
+/// long history of evolved scenarios.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Whether to take backups at all. Defaults to true.
+    pub enabled: bool,
+
+    /// Directory to store backups in. Defaults to a `backups` directory next to the database. No
+    /// backups are taken of an in-memory database unless this is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+
+    /// Number of backups to keep. Once there are more than this, the oldest are deleted. Defaults
+    /// to 3.
+    pub keep: usize,
+
+    /// Only prunes which would remove at least this many scenarios are preceded by a backup, so
+    /// that routine prunes of a few scenarios don't copy the database each time. Migrations are
+    /// always backed up. Defaults to 10,000.
+    pub min_scenarios_pruned: u64,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            enabled: true,
+            directory: None,
+            keep: 3,
+            min_scenarios_pruned: 10000,
+        }
+    }
+}
+
+impl DescribeConfig for BackupConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key("enabled", |c| &c.enabled, "Whether to take backups at all.")
+            .key(
+                "directory",
+                |c| &c.directory,
+                "Directory to store backups in, instead of a `backups` directory next to the \
+                 database.",
+            )
+            .key("keep", |c| &c.keep, "Number of backups to keep.")
+            .key(
+                "min_scenarios_pruned",
+                |c| &c.min_scenarios_pruned,
+                "Only prunes removing at least this many scenarios are preceded by a backup.",
+            );
+    }
+}
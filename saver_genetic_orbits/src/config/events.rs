@@ -0,0 +1,81 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains configuration structs for the log of world events, such as planets merging or a new
+//! scenario starting. See [`crate::world_events`].
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
+
+use super::util::deserialize_non_negative;
+
+/// Configuration for the world event log.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// Where world events are logged.
+    pub world_events: WorldEventsConfig,
+}
+
+impl DescribeConfig for EventsConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.table(
+            "world_events",
+            |c| &c.world_events,
+            "Where world events, such as planets merging or a scenario ending, are logged.",
+        );
+    }
+}
+
+/// Logging of world events. The log is rate limited, since a busy scenario can have hundreds of
+/// collisions a second, but the JSON lines file gets every event.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WorldEventsConfig {
+    /// Most events of each kind logged per second, averaged over a second. Events over the limit
+    /// are counted, and the count logged with the next event of that kind which is logged. 0 turns
+    /// off logging events. Defaults to 2.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub max_logged_per_second: f32,
+
+    /// File to append every event to, as a line of JSON. Defaults to none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jsonl_path: Option<PathBuf>,
+}
+
+impl Default for WorldEventsConfig {
+    fn default() -> Self {
+        Self {
+            max_logged_per_second: 2.0,
+            jsonl_path: None,
+        }
+    }
+}
+
+impl DescribeConfig for WorldEventsConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "max_logged_per_second",
+            |c| &c.max_logged_per_second,
+            "Most events of each kind logged per second. 0 turns off logging events.",
+        )
+        .key(
+            "jsonl_path",
+            |c| &c.jsonl_path,
+            "File to append every event to, as a line of JSON, regardless of the log's limit.",
+        );
+    }
+}
@@ -16,11 +16,13 @@
 
 use serde::de::{Error, Unexpected};
 use serde::{Deserialize, Deserializer, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
 
 use crate::config::util::{
     Distribution, ExponentialDistribution, NormalDistribution, Range, UniformDistribution,
     Vector as SerVec,
 };
+use crate::model::{RadiusLaw, StabilityFilter};
 
 /// Tuning parameters for the world generator/mutator.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -33,6 +35,10 @@ pub struct GeneratorConfig {
     #[serde(deserialize_with = "deserialize_percent")]
     pub create_new_scenario_probability: f64,
 
+    /// Limits the scenarios chosen as parents by how their orbits were classified. The exponential
+    /// distribution is over only the scenarios which match.
+    pub parent_stability: StabilityFilter,
+
     /// The parameters affecting world mutation.
     pub mutation_parameters: MutationParameters,
 
@@ -60,12 +66,54 @@ impl Default for GeneratorConfig {
     fn default() -> Self {
         GeneratorConfig {
             create_new_scenario_probability: 0.05,
+            parent_stability: Default::default(),
             mutation_parameters: Default::default(),
             new_world_parameters: Default::default(),
         }
     }
 }
 
+impl DescribeConfig for GeneratorConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "create_new_scenario_probability",
+            |c| &c.create_new_scenario_probability,
+            "The probability of generating a new scenario instead of mutating a stored one.",
+        )
+        .table(
+            "parent_stability",
+            |c| &c.parent_stability,
+            "Limits the stored scenarios chosen as parents by their stability classification.",
+        )
+        .table(
+            "mutation_parameters",
+            |c| &c.mutation_parameters,
+            "The parameters affecting world mutation.",
+        )
+        .table(
+            "new_world_parameters",
+            |c| &c.new_world_parameters,
+            "The parameters affecting new world generation.",
+        );
+    }
+}
+
+impl DescribeConfig for StabilityFilter {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "only",
+            |c| &c.only,
+            "If not empty, only scenarios with one of these classifications are chosen: chaotic, \
+            hierarchical, single-dominant or dispersing.",
+        )
+        .key(
+            "exclude",
+            |c| &c.exclude,
+            "Scenarios with any of these classifications are never chosen.",
+        );
+    }
+}
+
 /// Parameters that control initial world generation.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
@@ -76,7 +124,7 @@ pub struct MutationParameters {
     pub add_planets_limits: Range<usize>,
 
     /// Distribution over the number of new planets to add. If using a uniform distribution, the
-    /// range is inclusive. Exponential distribution rounds down, normal distribution rounds to
+    /// range is inclusive. Exponential distribution rounds down, other distributions round to
     /// nearest.
     /// The default value is an exponential distribution with lambda chosen to have a 99.9% chance
     /// of having fewer than 10 new planets.
@@ -91,7 +139,7 @@ pub struct MutationParameters {
     pub remove_planets_limits: Range<usize>,
 
     /// Distribution over the number of new planets to remove. If using a uniform distribution, the
-    /// range is inclusive. Exponential distribution rounds down, normal distribution rounds to
+    /// range is inclusive. Exponential distribution rounds down, other distributions round to
     /// nearest.
     /// The default value is an exponential distribution with lambda chosen to have a 99.9% chance
     /// of removing fewer than 10 planets.
@@ -110,7 +158,9 @@ impl Default for MutationParameters {
         const DEFAULT_ADD_REMOVE_PLANETS_LIMITS: Range<usize> = Range { min: 0, max: 20 };
         // -ln(1 - .999) / 10 = 99.9% chance of adding or removing fewer than 10 planets.
         const DEFAULT_ADD_REMOVE_PLANETS_DIST: Distribution =
-            Distribution::Exponential(ExponentialDistribution(0.6907755278982136));
+            Distribution::Exponential(ExponentialDistribution {
+                lambda: 0.6907755278982136,
+            });
         MutationParameters {
             add_planets_limits: DEFAULT_ADD_REMOVE_PLANETS_LIMITS,
             add_planets_dist: DEFAULT_ADD_REMOVE_PLANETS_DIST,
@@ -123,6 +173,46 @@ impl Default for MutationParameters {
     }
 }
 
+impl DescribeConfig for MutationParameters {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "add_planets_limits",
+            |c| &c.add_planets_limits,
+            "Inclusive limits on the number of planets to add.",
+        )
+        .key(
+            "add_planets_dist",
+            |c| &c.add_planets_dist,
+            "Distribution over the number of planets to add.",
+        )
+        .table(
+            "new_planet_parameters",
+            |c| &c.new_planet_parameters,
+            "The parameters affecting planets added by a mutation.",
+        )
+        .key(
+            "remove_planets_limits",
+            |c| &c.remove_planets_limits,
+            "Inclusive limits on the number of planets to remove.",
+        )
+        .key(
+            "remove_planets_dist",
+            |c| &c.remove_planets_dist,
+            "Distribution over the number of planets to remove.",
+        )
+        .key(
+            "fraction_of_planets_to_change",
+            |c| &c.fraction_of_planets_to_change,
+            "Fraction of planets to change, on average.",
+        )
+        .table(
+            "planet_mutation_parameters",
+            |c| &c.planet_mutation_parameters,
+            "Parameters for how to mutate individual planets.",
+        );
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct NewWorldParameters {
@@ -131,13 +221,19 @@ pub struct NewWorldParameters {
     #[serde(deserialize_with = "Range::deserialize_reorder")]
     pub num_planets_range: Range<usize>,
     /// Distribution used for selecting the number of planets to distribute over. If using a
-    /// uniform distribution, the range is inclusive. Exponential distribution rounds down, normal
-    /// distribution rounds to nearest.
+    /// uniform distribution, the range is inclusive. Exponential distribution rounds down, other
+    /// distributions round to nearest.
     /// The default value is an exponential distribution with lambda chosen to have a 99.999%
     /// chance of picking fewer than 1000 planets.
     pub num_planets_dist: Distribution,
     /// Parameters for how new planets are generated.
     pub planet_parameters: NewPlanetParameters,
+    /// How big planets are for their mass, which sets both how they are drawn and when they
+    /// collide. Stored with each world, so changing it only affects new worlds and their
+    /// descendants. Defaults to solid spheres with density 0.1.
+    pub radius_law: RadiusLaw,
+    /// Parameters for moons seeded in orbit around the larger planets.
+    pub moons: MoonParameters,
 }
 
 impl Default for NewWorldParameters {
@@ -146,12 +242,63 @@ impl Default for NewWorldParameters {
             num_planets_range: Range { min: 1, max: 1000 },
             num_planets_dist:
                 // -ln(1 - .99999) / 1000 = 99.999% chance of choosing fewer than 1000 planets.
-                Distribution::Exponential(ExponentialDistribution(0.01151292546497023)),
+                Distribution::Exponential(ExponentialDistribution {
+                    lambda: 0.01151292546497023,
+                }),
             planet_parameters: Default::default(),
+            radius_law: Default::default(),
+            moons: Default::default(),
         }
     }
 }
 
+impl DescribeConfig for NewWorldParameters {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "num_planets_range",
+            |c| &c.num_planets_range,
+            "Inclusive limits on the number of planets to generate.",
+        )
+        .key(
+            "num_planets_dist",
+            |c| &c.num_planets_dist,
+            "Distribution over the number of planets to generate.",
+        )
+        .table(
+            "planet_parameters",
+            |c| &c.planet_parameters,
+            "Parameters for how new planets are generated.",
+        )
+        .table(
+            "radius_law",
+            |c| &c.radius_law,
+            "How big planets are for their mass. Only affects new worlds and their descendants.",
+        )
+        .table(
+            "moons",
+            |c| &c.moons,
+            "Parameters for moons seeded in orbit around the larger planets.",
+        );
+    }
+}
+
+impl DescribeConfig for RadiusLaw {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "density",
+            |c| &c.density,
+            "Density of planets. Must be positive.",
+        )
+        .key(
+            "exponent",
+            |c| &c.exponent,
+            "Exponent relating a planet's mass to its radius. 1/3 makes planets solid spheres of \
+             the given density; smaller values make heavy planets relatively smaller. Must be \
+             positive.",
+        );
+    }
+}
+
 /// Parameters to control how new planets are generated.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
@@ -170,6 +317,9 @@ pub struct NewPlanetParameters {
     /// Controls the distribution of starting masses for planets. Defaults to mean: 500.
     /// stddev: 400.
     pub start_mass: NormalDistribution,
+    /// Range of speeds planets spin at, in radians per second, about an axis pointing in a random
+    /// direction. Defaults to [0, 0.5].
+    pub spin: UniformDistribution,
 }
 
 impl Default for NewPlanetParameters {
@@ -208,10 +358,109 @@ impl Default for NewPlanetParameters {
                 mean: 500.,
                 standard_deviation: 400.,
             },
+            spin: UniformDistribution { min: 0., max: 0.5 },
         }
     }
 }
 
+impl DescribeConfig for NewPlanetParameters {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "start_position",
+            |c| &c.start_position,
+            "Distribution of the starting position in each axis.",
+        )
+        .key(
+            "start_velocity",
+            |c| &c.start_velocity,
+            "Distribution of the starting velocity in each axis.",
+        )
+        .key(
+            "min_start_mass",
+            |c| &c.min_start_mass,
+            "Minimum starting mass. Must be positive.",
+        )
+        .key(
+            "start_mass",
+            |c| &c.start_mass,
+            "Distribution of the starting mass.",
+        )
+        .key(
+            "spin",
+            |c| &c.spin,
+            "Range of speeds planets spin at, in radians per second, about a random axis.",
+        );
+    }
+}
+
+/// Parameters to control moons: small planets seeded in circular orbits around the larger planets
+/// of new worlds, so they start with some structure. Moons are otherwise ordinary planets, and
+/// merge with anything they overlap like any other.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MoonParameters {
+    /// Probability that a planet heavy enough to have moons gets them. Defaults to 0, which turns
+    /// moons off.
+    #[serde(deserialize_with = "deserialize_percent")]
+    pub probability: f64,
+    /// Only planets at least this heavy can have moons. Defaults to 800.
+    pub min_host_mass: f32,
+    /// Distribution over the number of moons a planet which gets them has. If using a uniform
+    /// distribution, the range is inclusive. Exponential distribution rounds down, other
+    /// distributions round to nearest. Defaults to uniform over [1, 3].
+    pub count_dist: Distribution,
+    /// Mass of each moon, as a fraction of its planet's mass. Moons are never lighter than the
+    /// new planets' `min_start_mass`. Defaults to [0.01, 0.05].
+    pub mass_fraction: UniformDistribution,
+    /// Radius of each moon's orbit, in radii of its planet. Defaults to [3, 8].
+    pub orbit_radius: UniformDistribution,
+}
+
+impl Default for MoonParameters {
+    fn default() -> Self {
+        MoonParameters {
+            probability: 0.,
+            min_host_mass: 800.,
+            count_dist: Distribution::Uniform(UniformDistribution { min: 1., max: 3. }),
+            mass_fraction: UniformDistribution {
+                min: 0.01,
+                max: 0.05,
+            },
+            orbit_radius: UniformDistribution { min: 3., max: 8. },
+        }
+    }
+}
+
+impl DescribeConfig for MoonParameters {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "probability",
+            |c| &c.probability,
+            "Probability that a planet heavy enough to have moons gets them. 0 turns moons off.",
+        )
+        .key(
+            "min_host_mass",
+            |c| &c.min_host_mass,
+            "Only planets at least this heavy can have moons.",
+        )
+        .key(
+            "count_dist",
+            |c| &c.count_dist,
+            "Distribution over the number of moons a planet which gets them has.",
+        )
+        .key(
+            "mass_fraction",
+            |c| &c.mass_fraction,
+            "Range of each moon's mass, as a fraction of its planet's mass.",
+        )
+        .key(
+            "orbit_radius",
+            |c| &c.orbit_radius,
+            "Range of each moon's orbit radius, in radii of its planet.",
+        );
+    }
+}
+
 /// Deserializes the min mass, erroring if not positive.
 fn deserialize_min_mass<'de, D>(deserializer: D) -> Result<f32, D::Error>
 where
@@ -241,8 +490,8 @@ pub struct PlanetMutationParameters {
     pub velocity_change: SerVec<NormalDistribution>,
 
     /// Distribution for how much to change mass when modifying the planet. Defaults to a normal
-    /// distribution with a mean of 0 and a standard deviation of 100. Cannot be an exponential
-    /// distribution because those only go up.
+    /// distribution with a mean of 0 and a standard deviation of 100. Must be able to produce
+    /// negative values, so exponential, poisson, and log-normal distributions aren't allowed.
     #[serde(deserialize_with = "deserialize_mass_change")]
     pub mass_change: Distribution,
 
@@ -280,16 +529,41 @@ impl Default for PlanetMutationParameters {
     }
 }
 
-/// Deserializes the min mass, erroring if not positive.
+impl DescribeConfig for PlanetMutationParameters {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "position_change",
+            |c| &c.position_change,
+            "Distribution of the change in position in each axis.",
+        )
+        .key(
+            "velocity_change",
+            |c| &c.velocity_change,
+            "Distribution of the change in velocity in each axis.",
+        )
+        .key(
+            "mass_change",
+            |c| &c.mass_change,
+            "Distribution of the change in mass. Must be able to produce negative values.",
+        )
+        .key(
+            "min_mass",
+            |c| &c.min_mass,
+            "Minimum mass after a change. Must be positive.",
+        );
+    }
+}
+
+/// Deserializes the mass change, erroring if it can only make planets heavier.
 fn deserialize_mass_change<'de, D>(deserializer: D) -> Result<Distribution, D::Error>
 where
     D: Deserializer<'de>,
 {
     let val = Distribution::deserialize(deserializer)?;
-    if let Distribution::Exponential(_) = val {
+    if val.is_non_negative() {
         Err(D::Error::invalid_value(
-            Unexpected::TupleVariant,
-            &"a non-exponential distribution",
+            Unexpected::StructVariant,
+            &"a distribution which can produce negative values",
         ))
     } else {
         Ok(val)
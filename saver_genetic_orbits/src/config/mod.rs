@@ -14,65 +14,270 @@
 
 //! Contains structs used for configuring the screensaver.
 
+use std::path::PathBuf;
+
 use bevy::prelude::*;
 use figment::providers::{Format, Serialized, Yaml};
 use figment::Figment;
+use serde::Serialize;
+use serde_json::Value;
+use xsecurelock_saver::cli;
+use xsecurelock_saver::config_help::{describe, ConfigHelp, DescribeConfig, Setting};
 
 use self::camera::CameraConfig;
 use self::database::DatabaseConfig;
+use self::events::EventsConfig;
 use self::generator::GeneratorConfig;
+use self::physics::PhysicsConfig;
 use self::scoring::ScoringConfig;
+use self::sound::SoundConfig;
+use self::visualization::VisualizationConfig;
 
 pub mod camera;
 pub mod database;
+pub mod events;
 pub mod generator;
+pub mod overrides;
+pub mod physics;
 pub mod scoring;
+pub mod sound;
+pub mod strict;
 pub mod util;
+pub mod visualization;
 
 /// The screensaver folder name, used both for saving the database in the user data directory and
 /// for looking for configs in the
 const SAVER_DIR: &'static str = "xsecurelock-saver-genetic-orbits";
 
+/// Path of the config file in the user's config directory, which is also where the `configure`
+/// subcommand saves the config.
+pub fn user_config_path() -> Option<PathBuf> {
+    let mut config_path = dirs::config_dir()?;
+    config_path.push(SAVER_DIR);
+    config_path.push("config.yaml");
+    Some(config_path)
+}
+
+/// Gathers the config from the default database location, the user's config files, the file given
+/// with `--config`, and the environment, in increasing order of precedence.
+pub fn load() -> Figment {
+    let mut figment = Figment::new();
+
+    if let Some(mut data_dir) = dirs::data_dir() {
+        data_dir.push(SAVER_DIR);
+        data_dir.push("scenario-db.sqlite3");
+        figment = figment.merge(Serialized::defaults(DatabaseConfig {
+            database_path: Some(data_dir),
+            ..Default::default()
+        }));
+    }
+
+    if let Some(config_path) = user_config_path() {
+        figment = figment.merge(Yaml::file(config_path));
+    }
+
+    if let Some(mut home_dir) = dirs::home_dir() {
+        home_dir.push(".xsecurelock-saver-genetic-orbits.yaml");
+        figment = figment.merge(Yaml::file(home_dir));
+    }
+
+    if let Some(config_path) = cli::config_path() {
+        figment = figment.merge(Yaml::file(config_path));
+    }
+
+    overrides::apply_from_env(figment)
+}
+
+/// Help for `--help-config`, listing every config key along with its default.
+pub fn help() -> ConfigHelp {
+    xsecurelock_saver::engine::config_help("saver_genetic_orbits")
+        .section("Camera", settings::<CameraConfig>())
+        .section("Database", settings::<DatabaseConfig>())
+        .section("Events", settings::<EventsConfig>())
+        .section("Generator", settings::<GeneratorConfig>())
+        .section("Physics", settings::<PhysicsConfig>())
+        .section("Scoring", settings::<ScoringConfig>())
+        .section("Sound", settings::<SoundConfig>())
+        .section("Visualization", settings::<VisualizationConfig>())
+        .section(
+            "Config loading",
+            overrides::settings()
+                .into_iter()
+                .chain(strict::settings())
+                .collect(),
+        )
+}
+
+/// Describes the keys of `T`, with the defaults taken from serializing `T::default()` so that
+/// they're written the way the config expects. Nested structs get no default of their own, since
+/// their keys are listed separately.
+fn settings<T: DescribeConfig + Default + Serialize>() -> Vec<Setting> {
+    let defaults = serde_json::to_value(T::default()).unwrap_or(Value::Null);
+    let mut settings = describe::<T>();
+    let tables: Vec<String> = settings
+        .iter()
+        .map(|setting| setting.key.clone())
+        .filter(|key| {
+            let nested = format!("{}.", key);
+            settings
+                .iter()
+                .any(|setting| setting.key.starts_with(&nested))
+        })
+        .collect();
+    for setting in &mut settings {
+        if tables.contains(&setting.key) {
+            continue;
+        }
+        let default = setting
+            .key
+            .split('.')
+            .try_fold(&defaults, |value, key| value.get(key));
+        match default {
+            None | Some(Value::Null) => {}
+            Some(default) => setting.default = Some(default.to_string()),
+        }
+    }
+    settings
+}
+
 /// Adds figment-based configs.
 pub struct ConfigPlugin;
 
 impl Plugin for ConfigPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let mut figment = Figment::new();
-
-        if let Some(mut data_dir) = dirs::data_dir() {
-            data_dir.push(SAVER_DIR);
-            data_dir.push("scenario-db.sqlite3");
-            figment = figment.merge(Serialized::defaults(DatabaseConfig {
-                database_path: Some(data_dir),
-                ..Default::default()
-            }));
-        }
-
-        if let Some(mut config_dir) = dirs::config_dir() {
-            config_dir.push(SAVER_DIR);
-            config_dir.push("config.yaml");
-            figment = figment.merge(Yaml::file(config_dir));
-        }
+        let figment = load();
 
-        if let Some(mut home_dir) = dirs::home_dir() {
-            home_dir.push(".xsecurelock-saver-genetic-orbits.yaml");
-            figment = figment.merge(Yaml::file(home_dir));
+        if strict::is_enabled(&figment) {
+            let unknown = strict::find_unknown_keys(&figment).unwrap();
+            if !unknown.is_empty() {
+                for key in &unknown {
+                    error!("{}", key);
+                }
+                panic!(
+                    "Strict config validation failed: {} unknown keys",
+                    unknown.len()
+                );
+            }
         }
 
         let camconf = figment.extract::<CameraConfig>().unwrap();
         let dbconf = figment.extract::<DatabaseConfig>().unwrap();
         let scoreconf = figment.extract::<ScoringConfig>().unwrap();
         let genconf = figment.extract::<GeneratorConfig>().unwrap();
+        let visconf = figment.extract::<VisualizationConfig>().unwrap();
+        let physconf = figment.extract::<PhysicsConfig>().unwrap();
+        let soundconf = figment.extract::<SoundConfig>().unwrap();
+        let eventsconf = figment.extract::<EventsConfig>().unwrap();
 
         info!("Loaded camera config: {:?}", camconf);
         info!("Loaded database config: {:?}", dbconf);
         info!("Loaded score config: {:?}", scoreconf);
         info!("Loaded generator config: {:?}", genconf);
+        info!("Loaded visualization config: {:?}", visconf);
+        info!("Loaded physics config: {:?}", physconf);
+        info!("Loaded sound config: {:?}", soundconf);
+        info!("Loaded events config: {:?}", eventsconf);
 
         app.insert_resource(camconf)
             .insert_resource(dbconf)
             .insert_resource(scoreconf)
-            .insert_resource(genconf);
+            .insert_resource(genconf)
+            .insert_resource(visconf)
+            .insert_resource(physconf)
+            .insert_resource(soundconf)
+            .insert_resource(eventsconf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::physics::CollisionLayers;
+    use super::*;
+
+    /// Checks that `--help-config` describes exactly the keys `T` has. Keys left out of the
+    /// serialized defaults, like unset options, only need to be described.
+    fn assert_described<T: DescribeConfig + Default + Serialize>() {
+        let described: BTreeSet<String> = describe::<T>()
+            .into_iter()
+            .map(|setting| setting.key)
+            .collect();
+        let mut serialized = BTreeSet::new();
+        let mut stack = vec![(String::new(), serde_json::to_value(T::default()).unwrap())];
+        while let Some((prefix, value)) = stack.pop() {
+            if let Value::Object(map) = value {
+                for (key, value) in map {
+                    let path = format!("{}{}", prefix, key);
+                    let nested = format!("{}.", path);
+                    if described.iter().any(|key| key.starts_with(&nested)) {
+                        stack.push((nested, value));
+                    }
+                    serialized.insert(path);
+                }
+            }
+        }
+        let undescribed: Vec<&String> = serialized.difference(&described).collect();
+        assert!(
+            undescribed.is_empty(),
+            "undescribed keys: {:?}",
+            undescribed
+        );
+        for setting in describe::<T>() {
+            assert!(
+                serialized.contains(&setting.key) || setting.kind.starts_with("Option<"),
+                "described key {} doesn't exist",
+                setting.key,
+            );
+        }
+    }
+
+    #[test]
+    fn all_keys_described() {
+        assert_described::<CameraConfig>();
+        assert_described::<DatabaseConfig>();
+        assert_described::<EventsConfig>();
+        assert_described::<GeneratorConfig>();
+        assert_described::<PhysicsConfig>();
+        assert_described::<ScoringConfig>();
+        assert_described::<SoundConfig>();
+        assert_described::<VisualizationConfig>();
+    }
+
+    #[test]
+    fn defaults_from_serde() {
+        let settings = settings::<CameraConfig>();
+        let default = |key: &str| {
+            settings
+                .iter()
+                .find(|setting| setting.key == key)
+                .unwrap()
+                .default
+                .clone()
+        };
+        assert_eq!(default("view_dist"), Some("1000.0".to_string()));
+        assert_eq!(default("tilt.period_seconds"), Some("60.0".to_string()));
+        assert_eq!(default("paths"), Some("[]".to_string()));
+        assert_eq!(default("tilt"), None);
+    }
+
+    #[test]
+    fn collision_layer_masks() {
+        let layers: CollisionLayers =
+            serde_json::from_str(r#"{"planet": ["planet"], "debris": [], "dust": ["planet"]}"#)
+                .unwrap();
+        // Layers get bits in order of their names.
+        assert_eq!(layers.masks("debris"), (0b001, 0b000));
+        assert_eq!(layers.masks("dust"), (0b010, 0b100));
+        assert_eq!(layers.masks("planet"), (0b100, 0b110));
+        assert_eq!(layers.masks("ghost"), (0, 0));
+        assert_eq!(
+            CollisionLayers::default().masks(CollisionLayers::PLANET),
+            (1, 1)
+        );
+
+        let too_many: BTreeMap<String, Vec<String>> =
+            (0..33).map(|i| (i.to_string(), vec![])).collect();
+        assert!(serde_json::from_value::<CollisionLayers>(serde_json::json!(too_many)).is_err());
     }
 }
@@ -0,0 +1,192 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resolves named config profiles and per-monitor overrides.
+//!
+//! Any config key may be overridden in two places:
+//!
+//! ```yaml
+//! profiles:
+//!   laptop:
+//!     num_planets_range: {min: 1, max: 200}
+//! outputs:
+//!   "1":
+//!     view_dist: 1500
+//! ```
+//!
+//! A profile is selected with `$XSECURELOCK_SAVER_PROFILE`, or by the machine's hostname if a
+//! profile with that name exists. Output overrides are keyed by the saver index xsecurelock
+//! assigns to each monitor, and are applied after the profile.
+
+use std::env;
+use std::fs;
+
+use bevy::prelude::*;
+use figment::providers::Serialized;
+use figment::value::Dict;
+use figment::Figment;
+use xsecurelock_saver::config_help::Setting;
+
+/// Environment variable which explicitly selects a profile.
+const PROFILE_ENV: &str = "XSECURELOCK_SAVER_PROFILE";
+
+/// Environment variable xsecurelock's saver multiplexer sets to the index of the monitor the
+/// saver is running on.
+const SAVER_INDEX_ENV: &str = "XSCREENSAVER_SAVER_INDEX";
+
+/// Key under which named profiles are stored.
+const PROFILES_KEY: &str = "profiles";
+
+/// Key under which per-monitor overrides are stored.
+const OUTPUTS_KEY: &str = "outputs";
+
+/// The settings selecting overrides, for `--help-config`.
+pub fn settings() -> Vec<Setting> {
+    vec![
+        Setting::new::<str>(
+            PROFILES_KEY,
+            "Named profiles, each holding config keys which override the base config.",
+        )
+        .with_kind("map"),
+        Setting::new::<str>(
+            OUTPUTS_KEY,
+            "Config keys overriding the base config and profile on one monitor, keyed by the \
+             monitor's saver index.",
+        )
+        .with_kind("map"),
+        Setting::new::<str>(
+            PROFILE_ENV,
+            "Profile to use. Defaults to the profile named after the hostname, if there is one.",
+        )
+        .with_kind("name"),
+    ]
+}
+
+/// Applies the profile and output overrides selected by the environment to the figment.
+pub fn apply_from_env(figment: Figment) -> Figment {
+    let profile = match env::var(PROFILE_ENV) {
+        Ok(profile) => Some(profile),
+        Err(_) => hostname().filter(|host| find_overrides(&figment, PROFILES_KEY, host).is_some()),
+    };
+    let output = env::var(SAVER_INDEX_ENV).ok();
+    apply(figment, profile.as_deref(), output.as_deref())
+}
+
+/// Merges the named profile and then the named output override on top of the figment. Names that
+/// don't exist in the config are logged and skipped.
+pub fn apply(mut figment: Figment, profile: Option<&str>, output: Option<&str>) -> Figment {
+    if let Some(profile) = profile {
+        match find_overrides(&figment, PROFILES_KEY, profile) {
+            Some(overrides) => {
+                info!("Using config profile {:?}", profile);
+                figment = figment.merge(Serialized::defaults(overrides));
+            }
+            None => warn!("Config profile {:?} not found, using base config", profile),
+        }
+    }
+    if let Some(output) = output {
+        if let Some(overrides) = find_overrides(&figment, OUTPUTS_KEY, output) {
+            info!("Using config overrides for output {}", output);
+            figment = figment.merge(Serialized::defaults(overrides));
+        }
+    }
+    figment
+}
+
+/// Finds the overrides with the given name in the `section` map. The name is looked up as is
+/// rather than as part of a key path, since names like hostnames may contain dots.
+fn find_overrides(figment: &Figment, section: &str, name: &str) -> Option<Dict> {
+    figment
+        .find_value(section)
+        .ok()?
+        .into_dict()?
+        .remove(name)?
+        .into_dict()
+}
+
+/// Reads the hostname of this machine, if available.
+fn hostname() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .ok()
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::providers::{Format, Yaml};
+
+    use super::*;
+    use crate::config::camera::CameraConfig;
+
+    const CONFIG: &str = "
+view_dist: 100
+rotation_speed: 1
+profiles:
+  laptop:
+    view_dist: 200
+  work.laptop:
+    view_dist: 300
+outputs:
+  '1':
+    rotation_speed: 2
+";
+
+    fn extract(profile: Option<&str>, output: Option<&str>) -> CameraConfig {
+        apply(Figment::from(Yaml::string(CONFIG)), profile, output)
+            .extract()
+            .unwrap()
+    }
+
+    #[test]
+    fn no_overrides() {
+        let conf = extract(None, None);
+        assert_eq!(conf.view_dist, 100.0);
+        assert_eq!(conf.rotation_speed, 1.0);
+    }
+
+    #[test]
+    fn profile_overrides_base() {
+        let conf = extract(Some("laptop"), None);
+        assert_eq!(conf.view_dist, 200.0);
+        assert_eq!(conf.rotation_speed, 1.0);
+    }
+
+    #[test]
+    fn dotted_profile_name() {
+        let conf = extract(Some("work.laptop"), None);
+        assert_eq!(conf.view_dist, 300.0);
+        assert_eq!(conf.rotation_speed, 1.0);
+
+        let conf = extract(Some("work"), None);
+        assert_eq!(conf.view_dist, 100.0);
+    }
+
+    #[test]
+    fn missing_profile_ignored() {
+        let conf = extract(Some("desktop"), None);
+        assert_eq!(conf.view_dist, 100.0);
+    }
+
+    #[test]
+    fn output_overrides_profile() {
+        let conf = extract(Some("laptop"), Some("1"));
+        assert_eq!(conf.view_dist, 200.0);
+        assert_eq!(conf.rotation_speed, 2.0);
+
+        let conf = extract(Some("laptop"), Some("0"));
+        assert_eq!(conf.rotation_speed, 1.0);
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains configuration structs for the physics simulation.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use xsecurelock_saver::cli;
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
+
+use super::util::{deserialize_non_negative, deserialize_positive, Vector};
+
+/// Configuration for the physics simulation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// How gravity between planets is computed.
+    pub integrator: IntegratorConfig,
+
+    /// Number of frames to spread spawning a scenario's planets over, so adding them to the
+    /// physics engine doesn't stall a single frame. The simulation and scoring start once all of
+    /// them are in. Defaults to 4.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub spawn_frames: u32,
+
+    /// Freezing of isolated clusters of planets which have settled down.
+    pub sleep: SleepConfig,
+
+    /// Gravity fields which pull on planets in addition to their attraction to each other.
+    pub fields: GravityFieldsConfig,
+
+    /// Which kinds of collider collide with each other. Defaults to planets colliding with each
+    /// other.
+    pub collision_layers: CollisionLayers,
+
+    /// Running scenarios the same way every time, for replaying them exactly.
+    pub deterministic: DeterministicConfig,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            integrator: Default::default(),
+            spawn_frames: 4,
+            sleep: Default::default(),
+            fields: Default::default(),
+            collision_layers: Default::default(),
+            deterministic: Default::default(),
+        }
+    }
+}
+
+impl DescribeConfig for PhysicsConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.table(
+            "integrator",
+            |c| &c.integrator,
+            "How gravity between planets is computed.",
+        )
+        .key(
+            "spawn_frames",
+            |c| &c.spawn_frames,
+            "Number of frames to spread spawning a scenario's planets over.",
+        )
+        .table(
+            "sleep",
+            |c| &c.sleep,
+            "Freezing of isolated clusters of planets which have settled down.",
+        )
+        .table(
+            "fields",
+            |c| &c.fields,
+            "Gravity fields which pull on planets in addition to their attraction to each other.",
+        )
+        .key(
+            "collision_layers",
+            |c| &c.collision_layers,
+            "Which kinds of collider collide with each other, as a map from each layer's name to \
+             the names of the layers it collides with. The saver's layers are `planet`.",
+        )
+        .table(
+            "deterministic",
+            |c| &c.deterministic,
+            "Running scenarios the same way every time, for replaying them exactly.",
+        );
+    }
+}
+
+/// Controls the accuracy of the gravity computation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IntegratorConfig {
+    /// Which gravity computation to use. Defaults to standard.
+    pub mode: IntegratorMode,
+
+    /// In high accuracy mode, pairs of planets closer than this are treated as a close encounter,
+    /// and the force between them is averaged over several points along their paths during the
+    /// physics step instead of only using their positions at the start of it. Defaults to 100.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub close_encounter_distance: f32,
+
+    /// Number of points the force of a close encounter is averaged over. Defaults to 8.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub close_encounter_substeps: u32,
+
+    /// In Barnes-Hut mode, how coarse the approximation is. A group of planets pulls as one when
+    /// its width is less than this times its distance, so 0 is exact and larger values are faster
+    /// but less accurate. Defaults to 0.5.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub barnes_hut_theta: f32,
+}
+
+impl Default for IntegratorConfig {
+    fn default() -> Self {
+        Self {
+            mode: IntegratorMode::Standard,
+            close_encounter_distance: 100.0,
+            close_encounter_substeps: 8,
+            barnes_hut_theta: 0.5,
+        }
+    }
+}
+
+impl DescribeConfig for IntegratorConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "mode",
+            |c| &c.mode,
+            "Which gravity computation to use: `standard`, `high_accuracy` or `barnes_hut`.",
+        )
+        .key(
+            "close_encounter_distance",
+            |c| &c.close_encounter_distance,
+            "In high accuracy mode, forces between planets closer than this are averaged along \
+             their paths.",
+        )
+        .key(
+            "close_encounter_substeps",
+            |c| &c.close_encounter_substeps,
+            "Number of points the force of a close encounter is averaged over.",
+        )
+        .key(
+            "barnes_hut_theta",
+            |c| &c.barnes_hut_theta,
+            "In Barnes-Hut mode, how coarse the approximation is, from 0 for exact forces up.",
+        );
+    }
+}
+
+/// Controls freezing of idle clusters. Frozen planets stop moving and drop out of the gravity
+/// computation until another planet comes near them, which saves time in long running scenarios.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SleepConfig {
+    /// Whether to freeze idle clusters at all. Freezing changes how scenarios play out, so
+    /// defaults to false.
+    pub enabled: bool,
+
+    /// Planets closer than this to each other are in the same cluster. Defaults to 100.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub cluster_distance: f32,
+
+    /// A cluster is isolated when no planet outside it is within this distance of any planet in
+    /// it. Defaults to 1000.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub isolation_distance: f32,
+
+    /// A cluster has settled down when none of its planets moves faster than this relative to the
+    /// cluster as a whole. Defaults to 2.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub max_relative_speed: f32,
+
+    /// How often, in seconds, to look for clusters to freeze or wake. Defaults to 1.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub check_interval_seconds: f32,
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cluster_distance: 100.0,
+            isolation_distance: 1000.0,
+            max_relative_speed: 2.0,
+            check_interval_seconds: 1.0,
+        }
+    }
+}
+
+impl DescribeConfig for SleepConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "enabled",
+            |c| &c.enabled,
+            "Whether to freeze idle clusters at all.",
+        )
+        .key(
+            "cluster_distance",
+            |c| &c.cluster_distance,
+            "Planets closer than this to each other are in the same cluster.",
+        )
+        .key(
+            "isolation_distance",
+            |c| &c.isolation_distance,
+            "A cluster is isolated when no other planet is within this distance of it.",
+        )
+        .key(
+            "max_relative_speed",
+            |c| &c.max_relative_speed,
+            "A cluster has settled down when none of its planets moves faster than this relative \
+             to the cluster.",
+        )
+        .key(
+            "check_interval_seconds",
+            |c| &c.check_interval_seconds,
+            "How often to look for clusters to freeze or wake.",
+        );
+    }
+}
+
+/// Runs scenarios deterministically, so that running a world again simulates it bit-identically,
+/// and a checksum of the simulation is logged when each scenario ends to check that it did.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DeterministicConfig {
+    /// Whether to run deterministically. Every frame then covers the same time and takes one
+    /// physics step, however long it really takes, so the saver runs slower when frames are slow.
+    /// Defaults to false.
+    pub enabled: bool,
+
+    /// Seed for the randomness used while a scenario runs, such as for launching probes. Each
+    /// scenario starts from the same seed. Defaults to the saver's `--seed`, or 0 without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+
+    /// Frames per second of simulated time. Defaults to 60.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub frame_rate: u32,
+}
+
+impl Default for DeterministicConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seed: None,
+            frame_rate: 60,
+        }
+    }
+}
+
+impl DeterministicConfig {
+    /// The seed to run with: the configured one, else the saver's `--seed`, else 0.
+    pub fn effective_seed(&self) -> u64 {
+        self.seed.or_else(cli::seed).unwrap_or(0)
+    }
+}
+
+impl DescribeConfig for DeterministicConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "enabled",
+            |c| &c.enabled,
+            "Whether to run deterministically, with every frame covering the same time.",
+        )
+        .key(
+            "seed",
+            |c| &c.seed,
+            "Seed for the randomness used while a scenario runs. Defaults to --seed.",
+        )
+        .key(
+            "frame_rate",
+            |c| &c.frame_rate,
+            "Frames per second of simulated time.",
+        );
+    }
+}
+
+/// Gravity fields which act on every planet the same way regardless of the other planets, for
+/// effects like planets falling like snow or circling a point.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GravityFieldsConfig {
+    /// Constant acceleration applied to every planet. Defaults to zero.
+    pub uniform: Vector<f32>,
+
+    /// Fields pulling planets towards a point. Defaults to none.
+    pub radial: Vec<RadialFieldConfig>,
+}
+
+impl Default for GravityFieldsConfig {
+    fn default() -> Self {
+        Self {
+            uniform: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            radial: vec![],
+        }
+    }
+}
+
+impl DescribeConfig for GravityFieldsConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "uniform",
+            |c| &c.uniform,
+            "Constant acceleration applied to every planet, as `{ x, y, z }`.",
+        )
+        .key(
+            "radial",
+            |c| &c.radial,
+            "Fields pulling planets towards a point, each with a `center`, a `strength` and a \
+             `falloff` of `none`, `linear` or `inverse_square`.",
+        );
+    }
+}
+
+/// A field pulling planets towards a point.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RadialFieldConfig {
+    /// Point the field pulls towards.
+    pub center: Vector<f32>,
+
+    /// Acceleration at a distance of 1 from the center. Negative values push planets away.
+    pub strength: f32,
+
+    /// How the acceleration falls off with distance. Defaults to inverse square, like gravity.
+    #[serde(default)]
+    pub falloff: Falloff,
+}
+
+/// How the acceleration from a radial field falls off with distance from its center.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Falloff {
+    /// The same acceleration at any distance.
+    None,
+    /// Acceleration inversely proportional to the distance.
+    Linear,
+    /// Acceleration inversely proportional to the square of the distance.
+    InverseSquare,
+}
+
+impl Default for Falloff {
+    fn default() -> Self {
+        Falloff::InverseSquare
+    }
+}
+
+/// Named collision layers, and which of them collide with each other. Two layers collide if
+/// either lists the other, so `{ planet: [planet], debris: [] }` has planets colliding with each
+/// other and debris colliding with nothing. There can be at most 32 layers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(
+    try_from = "collision_layers_de::CollisionLayers",
+    into = "collision_layers_de::CollisionLayers"
+)]
+pub struct CollisionLayers {
+    /// Every layer named in the matrix, in the order of their bits.
+    names: Vec<String>,
+    /// The layers each layer was configured to collide with.
+    matrix: BTreeMap<String, Vec<String>>,
+}
+
+impl CollisionLayers {
+    /// Name of the layer planets are in.
+    pub const PLANET: &'static str = "planet";
+
+    /// Bit masks of the layers the named layer is in and collides with, as `(memberships,
+    /// filter)`. A layer which isn't named in the matrix collides with nothing.
+    pub fn masks(&self, name: &str) -> (u32, u32) {
+        let bit = |name: &str| {
+            self.names
+                .iter()
+                .position(|layer| layer == name)
+                .map_or(0, |index| 1 << index)
+        };
+        let filter = self
+            .names
+            .iter()
+            .filter(|other| {
+                let lists = |a: &str, b: &str| {
+                    self.matrix
+                        .get(a)
+                        .map_or(false, |layers| layers.iter().any(|layer| layer == b))
+                };
+                lists(name, other) || lists(other, name)
+            })
+            .fold(0, |filter, other| filter | bit(other));
+        (bit(name), filter)
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        let mut matrix = BTreeMap::new();
+        matrix.insert(Self::PLANET.to_string(), vec![Self::PLANET.to_string()]);
+        Self {
+            names: vec![Self::PLANET.to_string()],
+            matrix,
+        }
+    }
+}
+
+mod collision_layers_de {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::convert::TryFrom;
+
+    use serde::{Deserialize, Serialize};
+
+    /// Shadow type that can implement Deserialize.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[serde(transparent)]
+    pub(super) struct CollisionLayers(BTreeMap<String, Vec<String>>);
+
+    impl TryFrom<CollisionLayers> for super::CollisionLayers {
+        type Error = String;
+
+        fn try_from(layers: CollisionLayers) -> Result<Self, String> {
+            let names: BTreeSet<&String> =
+                layers.0.keys().chain(layers.0.values().flatten()).collect();
+            if names.len() > 32 {
+                return Err(format!(
+                    "there can be at most 32 collision layers, got {}",
+                    names.len()
+                ));
+            }
+            Ok(Self {
+                names: names.into_iter().cloned().collect(),
+                matrix: layers.0,
+            })
+        }
+    }
+
+    impl From<super::CollisionLayers> for CollisionLayers {
+        fn from(layers: super::CollisionLayers) -> Self {
+            Self(layers.matrix)
+        }
+    }
+}
+
+/// Ways of computing gravity.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorMode {
+    /// Single precision forces, computed once per physics step. Fast and good enough for most
+    /// scenarios.
+    Standard,
+    /// Double precision forces accumulated with compensated summation, plus averaging of forces
+    /// during close encounters. Slower, but keeps long running scenarios closer to the true
+    /// orbits.
+    HighAccuracy,
+    /// Single precision forces approximated by grouping distant planets, as controlled by
+    /// `barnes_hut_theta`. Scales to thousands of planets, where computing every pair is too
+    /// slow.
+    BarnesHut,
+}
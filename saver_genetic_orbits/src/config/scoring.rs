@@ -18,7 +18,9 @@ use std::time::Duration;
 
 use serde::de::{Error, Unexpected};
 use serde::{Deserialize, Deserializer, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
 
+use super::util::deserialize_non_negative;
 use crate::statustracker::ScoringFunction;
 
 /// Tuning parameters for world scoring.
@@ -39,10 +41,47 @@ pub struct ScoringConfig {
     /// - `elapsed` is the percentage of scenario time that has completed, from 0 to 1.
     /// - `total_mass` is the total mass of all planets in the `scored_area`.
     /// - `mass_count` is the number of masses in the `scored_area`.
+    /// - `mass_in_core` is the total mass within `core_radius` of the origin.
+    /// - `largest_mass_fraction` is the largest single mass in the `scored_area` as a fraction of
+    ///   `total_mass`, from 0 to 1.
+    /// - `pairwise_close_encounters` is the number of pairs of masses in the `scored_area` within
+    ///   `close_encounter_distance` of each other. Only computed if the expression uses it.
+    /// - `bound_pairs` is the number of pairs of masses in the `scored_area` which have been
+    ///   gravitationally bound to each other for at least `bound_pair_time`. Only computed if the
+    ///   expression uses it, or bound pairs are shown.
     ///
     /// The score is "per second" because the output is multiplied by delta time before adding it to
     /// the total score.
     pub score_per_second: ScoringFunction,
+
+    /// Radius of the core of the scored area, centered on the origin, for `mass_in_core`.
+    /// Defaults to 500.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub core_radius: f32,
+
+    /// Masses closer than this to each other count towards `pairwise_close_encounters`. Defaults
+    /// to 100.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub close_encounter_distance: f32,
+
+    /// How long a pair of masses must stay bound to each other to count towards `bound_pairs`.
+    /// Defaults to 5 seconds.
+    #[serde(with = "humantime_serde")]
+    pub bound_pair_time: Duration,
+
+    /// Shortest time a scenario is shown for. Scenarios whose score is on track to fall short of
+    /// their parent's score end early, in proportion to how far short, but not before this. Their
+    /// final score is projected from the rate they were scoring at. Scenarios without a parent are
+    /// always shown for `scored_time`. Defaults to 30 seconds.
+    #[serde(with = "humantime_serde")]
+    pub min_display_time: Duration,
+
+    /// Longest time a scenario is shown for. Scenarios on track to beat their parent's score keep
+    /// playing past `scored_time` in proportion to how far ahead they are, up to this, though
+    /// their score stops counting at `scored_time`. Takes precedence over `min_display_time` if it
+    /// is shorter. Defaults to 2 minutes.
+    #[serde(with = "humantime_serde")]
+    pub max_display_time: Duration,
 }
 
 impl Default for ScoringConfig {
@@ -51,10 +90,63 @@ impl Default for ScoringConfig {
             scored_time: Duration::from_secs(60),
             scored_area: Default::default(),
             score_per_second: "total_mass * mass_count".parse().unwrap(),
+            core_radius: 500.0,
+            close_encounter_distance: 100.0,
+            bound_pair_time: Duration::from_secs(5),
+            min_display_time: Duration::from_secs(30),
+            max_display_time: Duration::from_secs(120),
         }
     }
 }
 
+impl DescribeConfig for ScoringConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "scored_time",
+            |c| &c.scored_time,
+            "How long the score is counted for.",
+        )
+        .table(
+            "scored_area",
+            |c| &c.scored_area,
+            "The region where planets count towards the score.",
+        )
+        .key(
+            "score_per_second",
+            |c| &c.score_per_second,
+            "Expression evaluated each frame for the score per second. Can use `elapsed`, \
+             `total_mass`, `mass_count`, `mass_in_core`, `largest_mass_fraction`, \
+             `pairwise_close_encounters` and `bound_pairs`.",
+        )
+        .key(
+            "core_radius",
+            |c| &c.core_radius,
+            "Radius of the core of the scored area, for `mass_in_core`.",
+        )
+        .key(
+            "close_encounter_distance",
+            |c| &c.close_encounter_distance,
+            "Masses closer than this to each other count towards `pairwise_close_encounters`.",
+        )
+        .key(
+            "bound_pair_time",
+            |c| &c.bound_pair_time,
+            "How long a pair of masses must stay bound to each other to count towards \
+             `bound_pairs`.",
+        )
+        .key(
+            "min_display_time",
+            |c| &c.min_display_time,
+            "Shortest time a scenario falling short of its parent's score is shown for.",
+        )
+        .key(
+            "max_display_time",
+            |c| &c.max_display_time,
+            "Longest time a scenario beating its parent's score is shown for.",
+        );
+    }
+}
+
 /// Defines the area where planets are actually scored. Area is centered on the origin, and planets
 /// outside of it don't get any score.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -82,6 +174,18 @@ impl Default for ScoredArea {
     }
 }
 
+impl DescribeConfig for ScoredArea {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key("width", |c| &c.width, "The width (x) of the scored region.")
+            .key(
+                "height",
+                |c| &c.height,
+                "The height (y) of the scored region.",
+            )
+            .key("depth", |c| &c.depth, "The depth (z) of the scored region.");
+    }
+}
+
 /// Deserializes the width or height of ScoredArea, flipping negatives and changing 0 to 4000.
 fn scored_area_whd_deserialize<'de, D>(deserializer: D) -> Result<f32, D::Error>
 where
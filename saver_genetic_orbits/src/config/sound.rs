@@ -0,0 +1,100 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains configuration structs for sound output. Sounds are only played if the saver is built
+//! with the `audio-out` feature.
+
+use serde::de::{Error, Unexpected};
+use serde::{Deserialize, Deserializer, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
+
+use super::util::deserialize_positive;
+
+/// Configuration for sound output.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SoundConfig {
+    /// Sounds played when planets collide.
+    pub collision_sounds: CollisionSoundConfig,
+}
+
+impl DescribeConfig for SoundConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.table(
+            "collision_sounds",
+            |c| &c.collision_sounds,
+            "Sounds played when planets collide.",
+        );
+    }
+}
+
+/// Soft chimes played when planets collide, louder and lower pitched for heavier planets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CollisionSoundConfig {
+    /// Silences all sounds. Defaults to false.
+    pub muted: bool,
+
+    /// Volume of the loudest possible collision, from 0 to 1. Defaults to 0.2, since the saver
+    /// runs unattended and shouldn't startle anyone.
+    #[serde(deserialize_with = "deserialize_volume")]
+    pub max_volume: f32,
+
+    /// Combined mass of a collision which plays at half of `max_volume`. Defaults to 1000, about
+    /// two average generated planets.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub reference_mass: f32,
+}
+
+impl Default for CollisionSoundConfig {
+    fn default() -> Self {
+        Self {
+            muted: false,
+            max_volume: 0.2,
+            reference_mass: 1000.0,
+        }
+    }
+}
+
+impl DescribeConfig for CollisionSoundConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key("muted", |c| &c.muted, "Silences all sounds.")
+            .key(
+                "max_volume",
+                |c| &c.max_volume,
+                "Volume of the loudest possible collision, from 0 to 1.",
+            )
+            .key(
+                "reference_mass",
+                |c| &c.reference_mass,
+                "Combined mass of a collision which plays at half of `max_volume`.",
+            );
+    }
+}
+
+/// Deserializes a volume, erroring if it is not between 0 and 1.
+fn deserialize_volume<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let val = f32::deserialize(deserializer)?;
+    if (0.0..=1.0).contains(&val) {
+        Ok(val)
+    } else {
+        Err(D::Error::invalid_value(
+            Unexpected::Float(val as f64),
+            &"a float from 0 to 1",
+        ))
+    }
+}
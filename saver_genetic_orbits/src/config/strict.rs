@@ -0,0 +1,257 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Optional strict validation of config keys. Normally unknown keys are silently ignored, which
+//! makes typos easy to miss. In strict mode (`strict_config: true` in the config, or
+//! `$XSECURELOCK_SAVER_STRICT_CONFIG` set to anything but `0` or `false`), every key must be
+//! consumed by at least one config struct, and misspelled keys are reported along with the
+//! closest known key.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use figment::Figment;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use xsecurelock_saver::cli;
+use xsecurelock_saver::config_help::Setting;
+
+use super::camera::CameraConfig;
+use super::database::DatabaseConfig;
+use super::events::EventsConfig;
+use super::generator::GeneratorConfig;
+use super::physics::PhysicsConfig;
+use super::scoring::ScoringConfig;
+use super::sound::SoundConfig;
+use super::visualization::VisualizationConfig;
+
+/// Config key which enables strict mode.
+const STRICT_KEY: &str = "strict_config";
+
+/// Environment variable which enables strict mode.
+const STRICT_ENV: &str = "XSECURELOCK_SAVER_STRICT_CONFIG";
+
+/// Top level keys consumed by the config loader itself rather than by a config struct.
+const LOADER_KEYS: &[&str] = &["profiles", "outputs", STRICT_KEY];
+
+/// A key present in the config which no config struct uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// Dotted path to the unknown key.
+    pub path: String,
+    /// The known key at the same level with the most similar name, if any is close enough.
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown config key `{}`", self.path)?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, "; did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// The settings turning on strict mode, for `--help-config`.
+pub fn settings() -> Vec<Setting> {
+    vec![
+        Setting::new::<bool>(
+            STRICT_KEY,
+            "Fail at startup if the config has keys no config struct uses.",
+        )
+        .with_default(false),
+        Setting::new::<bool>(
+            STRICT_ENV,
+            "Turns on strict mode when set to anything but 0 or false.",
+        ),
+    ]
+}
+
+/// Returns true if strict config validation was requested.
+pub fn is_enabled(figment: &Figment) -> bool {
+    cli::is_set(STRICT_ENV) || figment.extract_inner::<bool>(STRICT_KEY).unwrap_or(false)
+}
+
+/// Finds all keys in the figment which aren't used by any of the config structs.
+pub fn find_unknown_keys(figment: &Figment) -> Result<Vec<UnknownKey>, figment::Error> {
+    let value: Value = figment.extract()?;
+
+    let ignored_by_each = [
+        ignored_paths::<CameraConfig>(&value),
+        ignored_paths::<DatabaseConfig>(&value),
+        ignored_paths::<EventsConfig>(&value),
+        ignored_paths::<GeneratorConfig>(&value),
+        ignored_paths::<PhysicsConfig>(&value),
+        ignored_paths::<ScoringConfig>(&value),
+        ignored_paths::<SoundConfig>(&value),
+        ignored_paths::<VisualizationConfig>(&value),
+    ];
+    let mut defaults = serde_json::Map::new();
+    merge_defaults::<CameraConfig>(&mut defaults);
+    merge_defaults::<DatabaseConfig>(&mut defaults);
+    merge_defaults::<EventsConfig>(&mut defaults);
+    merge_defaults::<GeneratorConfig>(&mut defaults);
+    merge_defaults::<PhysicsConfig>(&mut defaults);
+    merge_defaults::<ScoringConfig>(&mut defaults);
+    merge_defaults::<SoundConfig>(&mut defaults);
+    merge_defaults::<VisualizationConfig>(&mut defaults);
+    let defaults = Value::Object(defaults);
+
+    // A key is unknown only if every config struct ignored it or one of its parents.
+    let unknown: BTreeSet<&Vec<String>> = ignored_by_each
+        .iter()
+        .flatten()
+        .filter(|path| match path.first() {
+            Some(root) => !LOADER_KEYS.contains(&root.as_str()),
+            None => false,
+        })
+        .filter(|path| {
+            ignored_by_each
+                .iter()
+                .all(|ignored| has_prefix_in(path, ignored))
+        })
+        .collect();
+
+    Ok(unknown
+        .iter()
+        // Only report the outermost unknown key.
+        .filter(|path| !(1..path.len()).any(|len| unknown.contains(&path[..len].to_vec())))
+        .map(|path| UnknownKey {
+            path: path.join("."),
+            suggestion: suggest(&defaults, path),
+        })
+        .collect())
+}
+
+/// Deserializes the value as `T`, returning the paths of all keys `T` ignored. Paths are split
+/// into their keys, leaving out the unnamed segments used for newtypes and options.
+fn ignored_paths<T: DeserializeOwned>(value: &Value) -> BTreeSet<Vec<String>> {
+    let mut ignored = BTreeSet::new();
+    // Deserialization errors are reported by the regular config extraction, so only the ignored
+    // keys are interesting here.
+    let _ = serde_ignored::deserialize::<_, _, T>(value.clone(), |path| {
+        ignored.insert(
+            path.to_string()
+                .split('.')
+                .filter(|seg| !seg.is_empty() && *seg != "?")
+                .map(str::to_string)
+                .collect(),
+        );
+    });
+    ignored
+}
+
+/// Returns true if the path or any of its parents is in the set.
+fn has_prefix_in(path: &[String], set: &BTreeSet<Vec<String>>) -> bool {
+    (1..=path.len()).any(|len| set.contains(&path[..len].to_vec()))
+}
+
+/// Adds the serialized default values of `T` to the map of defaults.
+fn merge_defaults<T: Default + Serialize>(defaults: &mut serde_json::Map<String, Value>) {
+    if let Ok(Value::Object(map)) = serde_json::to_value(T::default()) {
+        defaults.extend(map);
+    }
+}
+
+/// Suggests the known key most similar to the last key in the path, looking at the keys the
+/// defaults have at the same level.
+fn suggest(defaults: &Value, path: &[String]) -> Option<String> {
+    let (key, parents) = path.split_last()?;
+    let mut level = defaults;
+    for parent in parents {
+        level = match level {
+            Value::Object(map) => map.get(parent)?,
+            Value::Array(items) => items.get(parent.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    let max_distance = (key.len() / 3).max(2);
+    level
+        .as_object()?
+        .keys()
+        .map(|known| (strsim::levenshtein(key, known), known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, known)| {
+            parents
+                .iter()
+                .chain(Some(known))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::providers::{Format, Yaml};
+
+    use super::*;
+
+    fn unknown(yaml: &str) -> Vec<UnknownKey> {
+        find_unknown_keys(&Figment::from(Yaml::string(yaml))).unwrap()
+    }
+
+    #[test]
+    fn known_keys_accepted() {
+        assert_eq!(
+            unknown(
+                "
+view_dist: 10
+prune_interval_seconds: 5
+mutation_parameters:
+  fraction_of_planets_to_change: 0.5
+profiles:
+  anything: {}
+"
+            ),
+            vec![],
+        );
+    }
+
+    #[test]
+    fn misspelled_top_level_key() {
+        assert_eq!(
+            unknown("veiw_dist: 10"),
+            vec![UnknownKey {
+                path: "veiw_dist".to_string(),
+                suggestion: Some("view_dist".to_string()),
+            }],
+        );
+    }
+
+    #[test]
+    fn misspelled_nested_key() {
+        assert_eq!(
+            unknown("scored_area:\n  widht: 100"),
+            vec![UnknownKey {
+                path: "scored_area.widht".to_string(),
+                suggestion: Some("scored_area.width".to_string()),
+            }],
+        );
+    }
+
+    #[test]
+    fn unrelated_key_has_no_suggestion() {
+        assert_eq!(
+            unknown("bogus:\n  nested: 1"),
+            vec![UnknownKey {
+                path: "bogus".to_string(),
+                suggestion: None,
+            }],
+        );
+    }
+}
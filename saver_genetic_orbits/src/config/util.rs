@@ -14,6 +14,8 @@
 
 //! Contains serializable utility structs which are useful for other config structs.
 
+use rand::Rng;
+use rand_distr::{Distribution as _, Exp, LogNormal, Normal, Poisson, Uniform, WeightedIndex};
 use serde::de::{Error, Unexpected};
 use serde::{Deserialize, Deserializer, Serialize};
 
@@ -78,27 +80,157 @@ pub enum Distribution {
     Normal(NormalDistribution),
     /// Use a uniform distribution.
     Uniform(UniformDistribution),
+    /// Use a poisson distribution.
+    Poisson(PoissonDistribution),
+    /// Use a log-normal distribution.
+    LogNormal(LogNormalDistribution),
+    /// Pick from an explicit list of weighted values.
+    Weighted(WeightedDistribution),
+}
+
+impl Distribution {
+    /// Returns true if this distribution can never produce negative values.
+    pub fn is_non_negative(&self) -> bool {
+        match self {
+            Distribution::Exponential(_)
+            | Distribution::Poisson(_)
+            | Distribution::LogNormal(_) => true,
+            Distribution::Normal(_) => false,
+            Distribution::Uniform(UniformDistribution { min, .. }) => *min >= 0.0,
+            Distribution::Weighted(WeightedDistribution { choices }) => {
+                choices.iter().all(|choice| choice.value >= 0.0)
+            }
+        }
+    }
+
+    /// Samples a count from this distribution. A uniform distribution picks from its range
+    /// inclusively, an exponential distribution rounds down, and all others round to the nearest
+    /// whole number. Negative samples become 0.
+    pub fn sample_usize<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        match self {
+            Distribution::Exponential(_) => self.sample_f64(rng).floor() as usize,
+            Distribution::Uniform(UniformDistribution { min, max }) => {
+                Uniform::new_inclusive(*min as usize, *max as usize).sample(rng)
+            }
+            _ => self.sample_f64(rng).round() as usize,
+        }
+    }
+
+    /// Samples a value from this distribution.
+    pub fn sample_f64<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Distribution::Exponential(ExponentialDistribution { lambda }) => {
+                Exp::new(*lambda).unwrap().sample(rng)
+            }
+            Distribution::Normal(NormalDistribution {
+                mean,
+                standard_deviation,
+            }) => Normal::new(*mean, *standard_deviation).unwrap().sample(rng),
+            Distribution::Uniform(UniformDistribution { min, max }) => {
+                Uniform::new_inclusive(*min, *max).sample(rng)
+            }
+            Distribution::Poisson(PoissonDistribution { lambda }) => {
+                Poisson::new(*lambda).unwrap().sample(rng)
+            }
+            Distribution::LogNormal(LogNormalDistribution {
+                mean,
+                standard_deviation,
+            }) => LogNormal::new(*mean, *standard_deviation)
+                .unwrap()
+                .sample(rng),
+            Distribution::Weighted(WeightedDistribution { choices }) => {
+                let index = WeightedIndex::new(choices.iter().map(|choice| choice.weight))
+                    .unwrap()
+                    .sample(rng);
+                choices[index].value
+            }
+        }
+    }
 }
 
 /// A distribution that is required to be exponential. Serializable rand::distributions::Exp.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ExponentialDistribution(
-    #[serde(deserialize_with = "deserialize_exponential_lambda")] pub f64,
-);
+pub struct ExponentialDistribution {
+    /// The rate parameter of the distribution.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub lambda: f64,
+}
+
+/// A distribution that is required to be poisson. Serializable rand_distr::Poisson. Useful for
+/// counts, since it only produces whole numbers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PoissonDistribution {
+    /// The rate parameter of the distribution, which is also its mean.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub lambda: f64,
+}
+
+/// Numbers which config values can be required to be non-negative or positive, with
+/// [`deserialize_non_negative`] and [`deserialize_positive`]. Zero is the default value.
+pub trait ConfigNumber: Copy + PartialOrd + Default {
+    /// What kind of number this is in errors, like "a float".
+    const KIND: &'static str;
+
+    /// This value as an unexpected value in errors.
+    fn unexpected(self) -> Unexpected<'static>;
+}
+
+impl ConfigNumber for f32 {
+    const KIND: &'static str = "a float";
+
+    fn unexpected(self) -> Unexpected<'static> {
+        Unexpected::Float(self as f64)
+    }
+}
+
+impl ConfigNumber for f64 {
+    const KIND: &'static str = "a float";
 
-/// Deserializes an exponential distribution, flipping negative values and setting 0 to 1.
-fn deserialize_exponential_lambda<'de, D>(deserializer: D) -> Result<f64, D::Error>
+    fn unexpected(self) -> Unexpected<'static> {
+        Unexpected::Float(self)
+    }
+}
+
+impl ConfigNumber for u32 {
+    const KIND: &'static str = "an integer";
+
+    fn unexpected(self) -> Unexpected<'static> {
+        Unexpected::Unsigned(self as u64)
+    }
+}
+
+/// Deserializes a value, erroring if it is negative.
+pub fn deserialize_non_negative<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
+    T: ConfigNumber + Deserialize<'de>,
 {
-    let val = f64::deserialize(deserializer)?;
-    if val <= 0.0 {
+    let val = T::deserialize(deserializer)?;
+    if val >= T::default() {
+        Ok(val)
+    } else {
         Err(D::Error::invalid_value(
-            Unexpected::Float(val),
-            &"a float > 0",
+            val.unexpected(),
+            &format!("{} >= 0", T::KIND).as_str(),
         ))
-    } else {
+    }
+}
+
+/// Deserializes a value, erroring if it is not positive. Used for the rate of exponential and
+/// poisson distributions.
+pub fn deserialize_positive<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ConfigNumber + Deserialize<'de>,
+{
+    let val = T::deserialize(deserializer)?;
+    if val > T::default() {
         Ok(val)
+    } else {
+        Err(D::Error::invalid_value(
+            val.unexpected(),
+            &format!("{} > 0", T::KIND).as_str(),
+        ))
     }
 }
 
@@ -120,6 +252,17 @@ where
     Ok(f64::deserialize(deserializer)?.abs())
 }
 
+/// A distribution that is required to be log-normal. Serializable rand_distr::LogNormal. Useful for
+/// masses, since it is always positive and has a long tail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogNormalDistribution {
+    /// The mean of the logarithm of the distribution.
+    pub mean: f64,
+    /// The standard deviation of the logarithm of the distribution.
+    #[serde(deserialize_with = "deserialize_normal_mean")]
+    pub standard_deviation: f64,
+}
+
 /// A distribution that is required to be uniform. Serializable rand::distributions::Uniform.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(try_from = "uniform_distribution_de::UniformDistribution")]
@@ -156,3 +299,232 @@ mod uniform_distribution_de {
         }
     }
 }
+
+/// A discrete distribution which picks one of a fixed list of values, with probability
+/// proportional to each value's weight.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "weighted_distribution_de::WeightedDistribution")]
+pub struct WeightedDistribution {
+    /// The values to choose from. Never empty, and at least one weight is positive.
+    pub choices: Vec<WeightedChoice>,
+}
+
+/// A single value which may be chosen by a [`WeightedDistribution`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeightedChoice {
+    /// The value produced when this choice is picked.
+    pub value: f64,
+    /// Relative weight of this choice. Must not be negative.
+    pub weight: f64,
+}
+
+mod weighted_distribution_de {
+    use std::convert::TryFrom;
+
+    use serde::Deserialize;
+
+    use super::WeightedChoice;
+
+    /// Shadow type that can implement Deserialize.
+    #[derive(Deserialize, Debug, Clone)]
+    pub(super) struct WeightedDistribution {
+        /// The values to choose from.
+        choices: Vec<WeightedChoice>,
+    }
+
+    impl TryFrom<WeightedDistribution> for super::WeightedDistribution {
+        type Error = String;
+
+        fn try_from(wd: WeightedDistribution) -> Result<Self, String> {
+            if wd.choices.is_empty() {
+                return Err("weighted distribution must have at least one choice".to_string());
+            }
+            if let Some(bad) = wd
+                .choices
+                .iter()
+                .find(|choice| choice.weight < 0.0 || !choice.weight.is_finite())
+            {
+                return Err(format!(
+                    "weighted distribution weights must be finite and not negative, got {}",
+                    bad.weight,
+                ));
+            }
+            if wd.choices.iter().all(|choice| choice.weight == 0.0) {
+                return Err("weighted distribution must have a positive weight".to_string());
+            }
+            Ok(Self {
+                choices: wd.choices,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// Parses a distribution, then serializes and parses it again.
+    fn round_trip(yaml: &str) -> Distribution {
+        let distribution: Distribution = serde_yaml::from_str(yaml).unwrap();
+        serde_yaml::from_str(&serde_yaml::to_string(&distribution).unwrap()).unwrap()
+    }
+
+    fn parse_error(yaml: &str) -> String {
+        serde_yaml::from_str::<Distribution>(yaml)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn round_trips_distributions() {
+        match round_trip("type: poisson\nlambda: 2.5") {
+            Distribution::Poisson(PoissonDistribution { lambda }) => assert_eq!(lambda, 2.5),
+            other => panic!("expected a poisson distribution, got {:?}", other),
+        }
+        match round_trip("type: log_normal\nmean: 1.0\nstandard_deviation: 0.5") {
+            Distribution::LogNormal(LogNormalDistribution {
+                mean,
+                standard_deviation,
+            }) => assert_eq!((mean, standard_deviation), (1.0, 0.5)),
+            other => panic!("expected a log-normal distribution, got {:?}", other),
+        }
+        let yaml = "type: weighted
+choices:
+  - {value: 1.0, weight: 3.0}
+  - {value: 4.0, weight: 0.0}";
+        match round_trip(yaml) {
+            Distribution::Weighted(WeightedDistribution { choices }) => {
+                let choices: Vec<(f64, f64)> = choices
+                    .iter()
+                    .map(|choice| (choice.value, choice.weight))
+                    .collect();
+                assert_eq!(choices, [(1.0, 3.0), (4.0, 0.0)]);
+            }
+            other => panic!("expected a weighted distribution, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_weights() {
+        let error = parse_error("type: weighted\nchoices: []");
+        assert!(error.contains("at least one choice"), "{}", error);
+        let error = parse_error(
+            "type: weighted
+choices:
+  - {value: 1.0, weight: 2.0}
+  - {value: 2.0, weight: -1.0}",
+        );
+        assert!(error.contains("not negative, got -1"), "{}", error);
+        let error = parse_error(
+            "type: weighted
+choices:
+  - {value: 1.0, weight: 0.0}
+  - {value: 2.0, weight: 0.0}",
+        );
+        assert!(error.contains("must have a positive weight"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_non_positive_rates() {
+        for yaml in &[
+            "type: poisson\nlambda: 0.0",
+            "type: poisson\nlambda: -1.5",
+            "type: exponential\nlambda: 0.0",
+        ] {
+            let error = parse_error(yaml);
+            assert!(error.contains("expected a float > 0"), "{}", error);
+        }
+    }
+
+    fn normal(mean: f64) -> Distribution {
+        Distribution::Normal(NormalDistribution {
+            mean,
+            standard_deviation: 1.0,
+        })
+    }
+
+    fn uniform(min: f64, max: f64) -> Distribution {
+        Distribution::Uniform(UniformDistribution { min, max })
+    }
+
+    fn weighted(values: &[f64]) -> Distribution {
+        Distribution::Weighted(WeightedDistribution {
+            choices: values
+                .iter()
+                .map(|&value| WeightedChoice { value, weight: 1.0 })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn knows_which_distributions_are_non_negative() {
+        let exponential = Distribution::Exponential(ExponentialDistribution { lambda: 1.0 });
+        let poisson = Distribution::Poisson(PoissonDistribution { lambda: 1.0 });
+        let log_normal = Distribution::LogNormal(LogNormalDistribution {
+            mean: -1.0,
+            standard_deviation: 1.0,
+        });
+        assert!(exponential.is_non_negative());
+        assert!(poisson.is_non_negative());
+        assert!(log_normal.is_non_negative());
+        assert!(!normal(10.0).is_non_negative());
+        assert!(uniform(0.0, 1.0).is_non_negative());
+        assert!(!uniform(-1.0, 1.0).is_non_negative());
+        assert!(weighted(&[0.0, 2.0]).is_non_negative());
+        assert!(!weighted(&[-0.5, 2.0]).is_non_negative());
+    }
+
+    #[test]
+    fn rounds_counts_per_distribution() {
+        let distributions = [
+            Distribution::Exponential(ExponentialDistribution { lambda: 0.5 }),
+            normal(5.0),
+            Distribution::Poisson(PoissonDistribution { lambda: 3.0 }),
+            Distribution::LogNormal(LogNormalDistribution {
+                mean: 1.0,
+                standard_deviation: 0.5,
+            }),
+            weighted(&[1.4, 2.6]),
+        ];
+        for distribution in &distributions {
+            for seed in 0..100 {
+                // The same seed gives the same sample either way.
+                let value = distribution.sample_f64(&mut StdRng::seed_from_u64(seed));
+                let count = distribution.sample_usize(&mut StdRng::seed_from_u64(seed));
+                let expected = match distribution {
+                    Distribution::Exponential(_) => value.floor(),
+                    _ => value.round(),
+                };
+                assert_eq!(
+                    count, expected as usize,
+                    "{:?} sampled {}",
+                    distribution, value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_negative_counts_to_zero() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            assert_eq!(normal(-10.0).sample_usize(&mut rng), 0);
+            assert_eq!(weighted(&[-3.0, -1.0]).sample_usize(&mut rng), 0);
+        }
+    }
+
+    #[test]
+    fn samples_uniform_counts_inclusively() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let distribution = uniform(2.0, 4.0);
+        let counts: BTreeSet<usize> = (0..1000)
+            .map(|_| distribution.sample_usize(&mut rng))
+            .collect();
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), [2, 3, 4]);
+    }
+}
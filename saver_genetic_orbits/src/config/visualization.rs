@@ -0,0 +1,429 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains configuration structs for optional visual effects.
+
+use serde::de::{Error, Unexpected};
+use serde::{Deserialize, Deserializer, Serialize};
+use xsecurelock_saver::config_help::{ConfigDocs, DescribeConfig};
+
+use super::util::{deserialize_non_negative, deserialize_positive};
+
+/// Configuration for visual effects which aren't part of the simulation.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct VisualizationConfig {
+    /// Rendering of the gravitational potential of the planets.
+    pub potential_field: PotentialFieldConfig,
+
+    /// Massless probes whose paths show how the planets' gravity bends trajectories.
+    pub probes: ProbeConfig,
+
+    /// Halos around pairs of planets which have stayed gravitationally bound to each other.
+    pub bound_pairs: BoundPairConfig,
+
+    /// A decorative belt of small rocks orbiting the planets.
+    pub asteroid_belt: AsteroidBeltConfig,
+
+    /// Whether to show the last few generations of the current scenario's family, with their
+    /// scores, in the HUD. Defaults to off.
+    pub family_tree: bool,
+
+    /// Whether to show counts of entities, assets and physics bodies, and the memory used, in the
+    /// HUD. Defaults to off.
+    pub diagnostics: bool,
+}
+
+impl DescribeConfig for VisualizationConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.table(
+            "potential_field",
+            |c| &c.potential_field,
+            "Rendering of the gravitational potential of the planets.",
+        )
+        .table(
+            "probes",
+            |c| &c.probes,
+            "Massless probes whose paths show how the planets' gravity bends trajectories.",
+        )
+        .table(
+            "bound_pairs",
+            |c| &c.bound_pairs,
+            "Halos around pairs of planets which have stayed gravitationally bound to each \
+             other.",
+        )
+        .table(
+            "asteroid_belt",
+            |c| &c.asteroid_belt,
+            "A decorative belt of small rocks orbiting the planets.",
+        )
+        .key(
+            "family_tree",
+            |c| &c.family_tree,
+            "Whether to show the last few generations of the current scenario's family in the \
+             HUD.",
+        )
+        .key(
+            "diagnostics",
+            |c| &c.diagnostics,
+            "Whether to show counts of entities, assets and physics bodies, and the memory used, \
+             in the HUD.",
+        );
+    }
+}
+
+/// Renders the gravitational potential on a horizontal plane below the planets. Planets are
+/// projected straight down onto the plane, so their height doesn't affect the field.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PotentialFieldConfig {
+    /// How to show the potential. Defaults to off.
+    pub mode: FieldMode,
+
+    /// Width and depth of the plane, centered under the origin. Defaults to 4000, the same as the
+    /// default scored area.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub size: f32,
+
+    /// Number of points along each side of the plane where the potential is computed. The
+    /// potential is computed on the CPU every frame, so large values get slow quickly. Defaults to
+    /// 64.
+    #[serde(deserialize_with = "deserialize_resolution")]
+    pub resolution: u16,
+
+    /// Height of the plane where the potential is zero. Defaults to -1200, below the default
+    /// camera orbit so the plane isn't seen edge on.
+    pub height: f32,
+
+    /// How far the grid sinks where the potential is deepest. Defaults to 800.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub depth: f32,
+
+    /// Potential at which the grid sinks half of `depth`, or the heatmap is half way through its
+    /// colors. Deeper potentials approach the full depth without ever reaching it. Defaults to
+    /// 5000.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub potential_scale: f32,
+
+    /// Softening length which keeps the potential from going infinite directly under a planet.
+    /// Larger values give wider, shallower wells. Defaults to 100.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub softening: f32,
+}
+
+impl Default for PotentialFieldConfig {
+    fn default() -> Self {
+        Self {
+            mode: FieldMode::Off,
+            size: 4000.0,
+            resolution: 64,
+            height: -1200.0,
+            depth: 800.0,
+            potential_scale: 5000.0,
+            softening: 100.0,
+        }
+    }
+}
+
+impl DescribeConfig for PotentialFieldConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "mode",
+            |c| &c.mode,
+            "How to show the potential: `off`, `grid` or `heatmap`.",
+        )
+        .key(
+            "size",
+            |c| &c.size,
+            "Width and depth of the plane, centered under the origin.",
+        )
+        .key(
+            "resolution",
+            |c| &c.resolution,
+            "Number of points along each side of the plane where the potential is computed.",
+        )
+        .key(
+            "height",
+            |c| &c.height,
+            "Height of the plane where the potential is zero.",
+        )
+        .key(
+            "depth",
+            |c| &c.depth,
+            "How far the grid sinks where the potential is deepest.",
+        )
+        .key(
+            "potential_scale",
+            |c| &c.potential_scale,
+            "Potential at which the grid sinks half of `depth`, or the heatmap is half way \
+             through its colors.",
+        )
+        .key(
+            "softening",
+            |c| &c.softening,
+            "Softening length which keeps the potential from going infinite under a planet.",
+        );
+    }
+}
+
+/// Massless tracer particles launched on random trajectories through the planets. Probes feel the
+/// planets' gravity but have no effect on the planets or the score. Each probe's recent path is
+/// drawn as a line, and a probe is relaunched when its time is up, it strays too far, or it hits a
+/// planet.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProbeConfig {
+    /// Number of probes. Defaults to 0, which turns probes off.
+    pub count: u16,
+
+    /// Number of points kept in each probe's path. Defaults to 240.
+    #[serde(deserialize_with = "deserialize_trail_length")]
+    pub trail_length: u16,
+
+    /// Seconds between points of the path. Defaults to 0.05.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub sample_seconds: f32,
+
+    /// Seconds a probe flies for before it is relaunched. Defaults to 30.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub lifetime_seconds: f32,
+
+    /// Probes are launched from random points within this distance of the origin along each
+    /// axis, and relaunched once they are twice as far. Defaults to 2000, the edge of the default
+    /// scored area.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub launch_distance: f32,
+
+    /// Probes are launched in a random direction at this speed. Defaults to 40.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub launch_speed: f32,
+
+    /// Red, green and blue of the paths, from 0 to 1. Defaults to a pale cyan.
+    pub color: [f32; 3],
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            trail_length: 240,
+            sample_seconds: 0.05,
+            lifetime_seconds: 30.0,
+            launch_distance: 2000.0,
+            launch_speed: 40.0,
+            color: [0.55, 0.85, 0.95],
+        }
+    }
+}
+
+impl DescribeConfig for ProbeConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "count",
+            |c| &c.count,
+            "Number of probes. 0 turns probes off.",
+        )
+        .key(
+            "trail_length",
+            |c| &c.trail_length,
+            "Number of points kept in each probe's path.",
+        )
+        .key(
+            "sample_seconds",
+            |c| &c.sample_seconds,
+            "Seconds between points of the path.",
+        )
+        .key(
+            "lifetime_seconds",
+            |c| &c.lifetime_seconds,
+            "Seconds a probe flies for before it is relaunched.",
+        )
+        .key(
+            "launch_distance",
+            |c| &c.launch_distance,
+            "Probes are launched within this distance of the origin along each axis, and \
+             relaunched once they are twice as far.",
+        )
+        .key(
+            "launch_speed",
+            |c| &c.launch_speed,
+            "Speed probes are launched at, in a random direction.",
+        )
+        .key(
+            "color",
+            |c| &c.color,
+            "Red, green and blue of the paths, from 0 to 1.",
+        );
+    }
+}
+
+/// Draws a faint ring around each pair of planets which has been bound to each other for the
+/// scoring config's `bound_pair_time`, in the plane the pair orbits in. Only pairs in the scored
+/// area are shown, the same ones which count towards `bound_pairs` in the scoring function.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BoundPairConfig {
+    /// Whether to show the rings. Defaults to off.
+    pub show: bool,
+
+    /// Red, green and blue of the rings, from 0 to 1. Defaults to a dim gold.
+    pub color: [f32; 3],
+}
+
+impl Default for BoundPairConfig {
+    fn default() -> Self {
+        Self {
+            show: false,
+            color: [0.45, 0.4, 0.2],
+        }
+    }
+}
+
+impl DescribeConfig for BoundPairConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "show",
+            |c| &c.show,
+            "Whether to show rings around bound pairs of planets.",
+        )
+        .key(
+            "color",
+            |c| &c.color,
+            "Red, green and blue of the rings, from 0 to 1.",
+        );
+    }
+}
+
+/// A belt of small rocks on circular orbits in the horizontal plane around the planets' center of
+/// mass, orbiting as if all the planets' mass were at the center. The rocks are only decoration:
+/// they aren't simulated by the physics engine, feel no pull from individual planets, and have no
+/// effect on the planets or the score.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AsteroidBeltConfig {
+    /// Number of rocks in the belt. Defaults to 0, which turns the belt off.
+    pub count: u16,
+
+    /// Distance from the center of mass where the belt starts. Defaults to 2500.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub inner_radius: f32,
+
+    /// Distance from the center of mass where the belt ends. Defaults to 3200.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub outer_radius: f32,
+
+    /// Height of the belt. Rocks are spread evenly over it. Defaults to 80.
+    #[serde(deserialize_with = "deserialize_non_negative")]
+    pub thickness: f32,
+
+    /// Average size of a rock. Rocks range from half to one and a half times this. Defaults to 6.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub rock_size: f32,
+
+    /// Red, green and blue of the rocks, from 0 to 1. Defaults to a dusty brown.
+    pub color: [f32; 3],
+}
+
+impl Default for AsteroidBeltConfig {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            inner_radius: 2500.0,
+            outer_radius: 3200.0,
+            thickness: 80.0,
+            rock_size: 6.0,
+            color: [0.5, 0.42, 0.35],
+        }
+    }
+}
+
+impl DescribeConfig for AsteroidBeltConfig {
+    fn describe(docs: &mut ConfigDocs<Self>) {
+        docs.key(
+            "count",
+            |c| &c.count,
+            "Number of rocks in the belt. 0 turns the belt off.",
+        )
+        .key(
+            "inner_radius",
+            |c| &c.inner_radius,
+            "Distance from the planets' center of mass where the belt starts.",
+        )
+        .key(
+            "outer_radius",
+            |c| &c.outer_radius,
+            "Distance from the planets' center of mass where the belt ends.",
+        )
+        .key("thickness", |c| &c.thickness, "Height of the belt.")
+        .key("rock_size", |c| &c.rock_size, "Average size of a rock.")
+        .key(
+            "color",
+            |c| &c.color,
+            "Red, green and blue of the rocks, from 0 to 1.",
+        );
+    }
+}
+
+/// Ways to show the potential field.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldMode {
+    /// Don't show the potential.
+    Off,
+    /// Show a grid which sinks where the potential is deep, like a rubber sheet.
+    Grid,
+    /// Show a flat plane colored by the potential.
+    Heatmap,
+}
+
+/// Minimum resolution of the potential field.
+const MIN_RESOLUTION: u16 = 2;
+
+/// Maximum resolution of the potential field.
+const MAX_RESOLUTION: u16 = 512;
+
+/// Deserializes the field resolution, erroring if it is outside the supported range.
+fn deserialize_resolution<'de, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let val = u16::deserialize(deserializer)?;
+    if (MIN_RESOLUTION..=MAX_RESOLUTION).contains(&val) {
+        Ok(val)
+    } else {
+        Err(D::Error::invalid_value(
+            Unexpected::Unsigned(val as u64),
+            &"an integer from 2 to 512",
+        ))
+    }
+}
+
+/// Minimum number of points in a probe's path, enough to draw one line.
+const MIN_TRAIL_LENGTH: u16 = 2;
+
+/// Deserializes the trail length, erroring if it is too short to draw.
+fn deserialize_trail_length<'de, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let val = u16::deserialize(deserializer)?;
+    if val >= MIN_TRAIL_LENGTH {
+        Ok(val)
+    } else {
+        Err(D::Error::invalid_value(
+            Unexpected::Unsigned(val as u64),
+            &"an integer >= 2",
+        ))
+    }
+}
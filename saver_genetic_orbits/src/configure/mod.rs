@@ -0,0 +1,799 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Windowed config editor, run with the `configure` subcommand when built with the `configure`
+//! feature. Edits the config file in the user's config directory, validating the config the same
+//! way the saver does before saving it. Keys the editor doesn't know about, such as profiles and
+//! output overrides, are kept as-is, but comments in the file are lost when saving.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Color32, Grid, ScrollArea};
+use bevy_egui::{EguiContext, EguiPlugin};
+use figment::providers::{Format, Serialized, Yaml};
+use figment::Figment;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use serde_yaml::Mapping;
+use xsecurelock_saver::engine::XSecurelockSaverPlugins;
+
+use crate::config::camera::CameraConfig;
+use crate::config::database::{DatabaseConfig, StorageBackend};
+use crate::config::events::EventsConfig;
+use crate::config::generator::{GeneratorConfig, NewPlanetParameters};
+use crate::config::physics::{Falloff, IntegratorMode, PhysicsConfig};
+use crate::config::scoring::ScoringConfig;
+use crate::config::sound::SoundConfig;
+use crate::config::user_config_path;
+use crate::config::visualization::{FieldMode, VisualizationConfig};
+
+mod widgets;
+
+/// Opens the config editor window, and returns once it is closed.
+pub fn run() {
+    let path = user_config_path().expect("Unable to find the user config directory");
+    App::build()
+        .insert_resource(WindowDescriptor {
+            title: "Genetic Orbits Configuration".to_string(),
+            ..Default::default()
+        })
+        .insert_resource(Editor::load(path))
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_plugin(EguiPlugin)
+        .add_system(editor.system())
+        .run();
+}
+
+/// State of the config editor.
+struct Editor {
+    /// Where the config is loaded from and saved to.
+    path: PathBuf,
+    /// Contents of the config file as last loaded or saved.
+    file: Mapping,
+    camera: CameraConfig,
+    database: DatabaseConfig,
+    scoring: ScoringConfig,
+    generator: GeneratorConfig,
+    visualization: VisualizationConfig,
+    physics: PhysicsConfig,
+    sound: SoundConfig,
+    events: EventsConfig,
+    /// Text of the scoring function, which may not currently parse.
+    score_per_second: String,
+    /// Problems with the current config. The config can only be saved when there are none.
+    errors: Vec<String>,
+    /// Result of the last load or save.
+    status: String,
+}
+
+impl Editor {
+    /// Loads the config file at the given path. Missing or invalid configs are replaced with
+    /// defaults.
+    fn load(path: PathBuf) -> Self {
+        let (file, status) = match read_mapping(&path) {
+            Ok(Some(file)) => (file, format!("Loaded {}", path.display())),
+            Ok(None) => (
+                Mapping::new(),
+                format!("{} doesn't exist yet, using defaults", path.display()),
+            ),
+            Err(err) => (
+                Mapping::new(),
+                format!("Unable to read {}: {}", path.display(), err),
+            ),
+        };
+        let figment = Figment::from(Yaml::file(&path));
+        let mut errors = vec![];
+        let camera = extract_or_default(&figment, &mut errors);
+        let database = extract_or_default(&figment, &mut errors);
+        let scoring: ScoringConfig = extract_or_default(&figment, &mut errors);
+        let generator = extract_or_default(&figment, &mut errors);
+        let visualization = extract_or_default(&figment, &mut errors);
+        let physics = extract_or_default(&figment, &mut errors);
+        let sound = extract_or_default(&figment, &mut errors);
+        let events = extract_or_default(&figment, &mut errors);
+        Self {
+            path,
+            file,
+            camera,
+            database,
+            score_per_second: scoring.score_per_second.to_string(),
+            scoring,
+            generator,
+            visualization,
+            physics,
+            sound,
+            events,
+            errors,
+            status,
+        }
+    }
+
+    /// Serializes all of the configs into a single map, the same shape as the config file.
+    fn to_map(&self) -> Result<Map<String, Value>, serde_json::Error> {
+        let mut map = Map::new();
+        merge_into(&mut map, &self.camera)?;
+        merge_into(&mut map, &self.database)?;
+        merge_into(&mut map, &self.scoring)?;
+        merge_into(&mut map, &self.generator)?;
+        merge_into(&mut map, &self.visualization)?;
+        merge_into(&mut map, &self.physics)?;
+        merge_into(&mut map, &self.sound)?;
+        merge_into(&mut map, &self.events)?;
+        Ok(map)
+    }
+
+    /// Rechecks the config for errors by running it back through the same deserialization the
+    /// saver uses.
+    fn validate(&mut self) {
+        self.errors.clear();
+        match self.score_per_second.parse() {
+            Ok(score_per_second) => self.scoring.score_per_second = score_per_second,
+            Err(err) => self.errors.push(format!("score_per_second: {}", err)),
+        }
+        let map = match self.to_map() {
+            Ok(map) => map,
+            Err(err) => {
+                self.errors.push(err.to_string());
+                return;
+            }
+        };
+        let figment = Figment::from(Serialized::defaults(Value::Object(map)));
+        let errors = &mut self.errors;
+        extract_or_default::<CameraConfig>(&figment, errors);
+        extract_or_default::<DatabaseConfig>(&figment, errors);
+        extract_or_default::<ScoringConfig>(&figment, errors);
+        extract_or_default::<GeneratorConfig>(&figment, errors);
+        extract_or_default::<VisualizationConfig>(&figment, errors);
+        extract_or_default::<PhysicsConfig>(&figment, errors);
+        extract_or_default::<SoundConfig>(&figment, errors);
+        extract_or_default::<EventsConfig>(&figment, errors);
+    }
+
+    /// Writes the config back to the file. Only writes keys that were already in the file or that
+    /// differ from the defaults, so the file stays small and keeps picking up new defaults.
+    fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut defaults = Map::new();
+        merge_into(&mut defaults, &CameraConfig::default())?;
+        merge_into(&mut defaults, &DatabaseConfig::default())?;
+        merge_into(&mut defaults, &ScoringConfig::default())?;
+        merge_into(&mut defaults, &GeneratorConfig::default())?;
+        merge_into(&mut defaults, &VisualizationConfig::default())?;
+        merge_into(&mut defaults, &PhysicsConfig::default())?;
+        merge_into(&mut defaults, &SoundConfig::default())?;
+        merge_into(&mut defaults, &EventsConfig::default())?;
+
+        let mut file = self.file.clone();
+        for (key, value) in self.to_map()? {
+            let yaml_key = serde_yaml::Value::String(key.clone());
+            if file.contains_key(&yaml_key) || defaults.get(&key) != Some(&value) {
+                file.insert(yaml_key, serde_yaml::to_value(value)?);
+            }
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_yaml::to_string(&file)?)?;
+        self.file = file;
+        Ok(())
+    }
+}
+
+/// Reads the file as a yaml mapping, returning None if it doesn't exist.
+fn read_mapping(path: &Path) -> Result<Option<Mapping>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    if contents.trim().is_empty() {
+        return Ok(Some(Mapping::new()));
+    }
+    Ok(Some(serde_yaml::from_str(&contents)?))
+}
+
+/// Extracts a config from the figment, recording the error and using the default if it fails.
+fn extract_or_default<T: DeserializeOwned + Default>(
+    figment: &Figment,
+    errors: &mut Vec<String>,
+) -> T {
+    figment.extract().unwrap_or_else(|err| {
+        errors.extend(err.into_iter().map(|err| err.to_string()));
+        T::default()
+    })
+}
+
+/// Adds the serialized fields of the value to the map.
+fn merge_into<T: Serialize>(map: &mut Map<String, Value>, value: &T) -> serde_json::Result<()> {
+    if let Value::Object(fields) = serde_json::to_value(value)? {
+        map.extend(fields);
+    }
+    Ok(())
+}
+
+/// Draws the editor.
+fn editor(egui_context: Res<EguiContext>, mut editor: ResMut<Editor>) {
+    let editor = &mut *editor;
+    let ctx = egui_context.ctx();
+
+    egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+        for error in &editor.errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        ui.horizontal(|ui| {
+            let save = ui.add_enabled(editor.errors.is_empty(), egui::Button::new("Save"));
+            if save.clicked() {
+                editor.status = match editor.save() {
+                    Ok(()) => format!("Saved {}", editor.path.display()),
+                    Err(err) => format!("Unable to save {}: {}", editor.path.display(), err),
+                };
+            }
+            if ui.button("Revert").clicked() {
+                *editor = Editor::load(editor.path.clone());
+            }
+            ui.label(&editor.status);
+        });
+    });
+
+    let mut changed = false;
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ScrollArea::vertical().show(ui, |ui| {
+            changed |= camera_section(ui, &mut editor.camera);
+            changed |= scoring_section(ui, &mut editor.scoring, &mut editor.score_per_second);
+            changed |= generator_section(ui, &mut editor.generator);
+            changed |= database_section(ui, &mut editor.database);
+            changed |= visualization_section(ui, &mut editor.visualization);
+            changed |= physics_section(ui, &mut editor.physics);
+            changed |= sound_section(ui, &mut editor.sound);
+            changed |= events_section(ui, &mut editor.events);
+        });
+    });
+    if changed {
+        editor.validate();
+    }
+}
+
+fn camera_section(ui: &mut egui::Ui, camera: &mut CameraConfig) -> bool {
+    ui.collapsing("Camera", |ui| {
+        Grid::new("camera")
+            .show(ui, |ui| {
+                let mut changed = false;
+                changed |= widgets::number(ui, "rotation_speed", &mut camera.rotation_speed, 0.01);
+                changed |= widgets::number(ui, "view_dist", &mut camera.view_dist, 1.0);
+                changed |= widgets::number(
+                    ui,
+                    "speed_easing_seconds",
+                    &mut camera.speed_easing_seconds,
+                    0.1,
+                );
+                changed |= widgets::number(ui, "tilt amplitude", &mut camera.tilt.amplitude, 0.01);
+                changed |= widgets::number(
+                    ui,
+                    "tilt period_seconds",
+                    &mut camera.tilt.period_seconds,
+                    0.1,
+                );
+                changed
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn scoring_section(ui: &mut egui::Ui, scoring: &mut ScoringConfig, source: &mut String) -> bool {
+    ui.collapsing("Scoring", |ui| {
+        Grid::new("scoring")
+            .show(ui, |ui| {
+                let mut changed = false;
+                changed |=
+                    widgets::seconds(ui, "scored_time (seconds)", &mut scoring.scored_time, 1.0);
+                changed |= widgets::seconds(
+                    ui,
+                    "min_display_time (seconds)",
+                    &mut scoring.min_display_time,
+                    1.0,
+                );
+                changed |= widgets::seconds(
+                    ui,
+                    "max_display_time (seconds)",
+                    &mut scoring.max_display_time,
+                    1.0,
+                );
+                let area = &mut scoring.scored_area;
+                changed |= widgets::number(ui, "scored_area width", &mut area.width, 10.0);
+                changed |= widgets::number(ui, "scored_area height", &mut area.height, 10.0);
+                changed |= widgets::number(ui, "scored_area depth", &mut area.depth, 10.0);
+                changed |= widgets::number(ui, "core_radius", &mut scoring.core_radius, 10.0);
+                changed |= widgets::number(
+                    ui,
+                    "close_encounter_distance",
+                    &mut scoring.close_encounter_distance,
+                    1.0,
+                );
+                changed |= widgets::seconds(
+                    ui,
+                    "bound_pair_time (seconds)",
+                    &mut scoring.bound_pair_time,
+                    0.1,
+                );
+                ui.label("score_per_second");
+                changed |= ui.text_edit_singleline(source).changed();
+                ui.end_row();
+                changed
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn generator_section(ui: &mut egui::Ui, generator: &mut GeneratorConfig) -> bool {
+    ui.collapsing("Generator", |ui| {
+        let mut changed = false;
+        Grid::new("generator").show(ui, |ui| {
+            changed |= widgets::number(
+                ui,
+                "create_new_scenario_probability",
+                &mut generator.create_new_scenario_probability,
+                0.001,
+            );
+            let filter = &mut generator.parent_stability;
+            changed |= widgets::stabilities(ui, "parent_stability only", &mut filter.only);
+            changed |= widgets::stabilities(ui, "parent_stability exclude", &mut filter.exclude);
+        });
+        ui.collapsing("New worlds", |ui| {
+            let params = &mut generator.new_world_parameters;
+            Grid::new("new_world_parameters").show(ui, |ui| {
+                changed |=
+                    widgets::range(ui, "num_planets_range", &mut params.num_planets_range, 1.0);
+                changed |= widgets::distribution(
+                    ui,
+                    "num_planets_dist",
+                    &mut params.num_planets_dist,
+                    true,
+                );
+                let law = &mut params.radius_law;
+                changed |= widgets::number(ui, "radius_law density", &mut law.density, 0.001);
+                changed |= widgets::number(ui, "radius_law exponent", &mut law.exponent, 0.001);
+                let moons = &mut params.moons;
+                changed |= widgets::number(ui, "moons probability", &mut moons.probability, 0.001);
+                changed |=
+                    widgets::number(ui, "moons min_host_mass", &mut moons.min_host_mass, 1.0);
+                changed |=
+                    widgets::distribution(ui, "moons count_dist", &mut moons.count_dist, true);
+                changed |= widgets::uniform(ui, "moons mass_fraction", &mut moons.mass_fraction);
+                changed |= widgets::uniform(ui, "moons orbit_radius", &mut moons.orbit_radius);
+            });
+            changed |= planet_parameters(ui, "new world planets", &mut params.planet_parameters);
+        });
+        ui.collapsing("Mutations", |ui| {
+            let params = &mut generator.mutation_parameters;
+            Grid::new("mutation_parameters").show(ui, |ui| {
+                changed |= widgets::range(
+                    ui,
+                    "add_planets_limits",
+                    &mut params.add_planets_limits,
+                    1.0,
+                );
+                changed |= widgets::distribution(
+                    ui,
+                    "add_planets_dist",
+                    &mut params.add_planets_dist,
+                    true,
+                );
+                changed |= widgets::range(
+                    ui,
+                    "remove_planets_limits",
+                    &mut params.remove_planets_limits,
+                    1.0,
+                );
+                changed |= widgets::distribution(
+                    ui,
+                    "remove_planets_dist",
+                    &mut params.remove_planets_dist,
+                    true,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "fraction_of_planets_to_change",
+                    &mut params.fraction_of_planets_to_change,
+                    0.001,
+                );
+            });
+            changed |= planet_parameters(ui, "added planets", &mut params.new_planet_parameters);
+
+            let mutation = &mut params.planet_mutation_parameters;
+            Grid::new("planet_mutation_parameters").show(ui, |ui| {
+                changed |= widgets::vector(
+                    ui,
+                    "position_change",
+                    &mut mutation.position_change,
+                    widgets::normal,
+                );
+                changed |= widgets::vector(
+                    ui,
+                    "velocity_change",
+                    &mut mutation.velocity_change,
+                    widgets::normal,
+                );
+                changed |=
+                    widgets::distribution(ui, "mass_change", &mut mutation.mass_change, false);
+                changed |= widgets::number(ui, "min_mass", &mut mutation.min_mass, 0.1);
+            });
+        });
+        changed
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn planet_parameters(ui: &mut egui::Ui, id: &str, params: &mut NewPlanetParameters) -> bool {
+    Grid::new(id)
+        .show(ui, |ui| {
+            let mut changed = false;
+            changed |= widgets::vector(
+                ui,
+                "start_position",
+                &mut params.start_position,
+                widgets::uniform,
+            );
+            changed |= widgets::vector(
+                ui,
+                "start_velocity",
+                &mut params.start_velocity,
+                widgets::normal,
+            );
+            changed |= widgets::number(ui, "min_start_mass", &mut params.min_start_mass, 0.1);
+            changed |= widgets::normal(ui, "start_mass", &mut params.start_mass);
+            changed |= widgets::uniform(ui, "spin", &mut params.spin);
+            changed
+        })
+        .inner
+}
+
+fn database_section(ui: &mut egui::Ui, database: &mut DatabaseConfig) -> bool {
+    ui.collapsing("Database", |ui| {
+        Grid::new("database")
+            .show(ui, |ui| {
+                let mut changed = false;
+                ui.label("backend");
+                ui.horizontal(|ui| {
+                    for &(backend, name) in &[
+                        (StorageBackend::Sqlite, "sqlite"),
+                        (StorageBackend::Null, "null"),
+                    ] {
+                        changed |= ui
+                            .radio_value(&mut database.backend, backend, name)
+                            .changed();
+                    }
+                });
+                ui.end_row();
+                let mut keep_all = database.max_scenarios_to_keep.is_none();
+                ui.label("max_scenarios_to_keep");
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut keep_all, "unlimited").changed() {
+                        database.max_scenarios_to_keep = if keep_all {
+                            None
+                        } else {
+                            DatabaseConfig::default().max_scenarios_to_keep
+                        };
+                        changed = true;
+                    }
+                    if let Some(ref mut max) = database.max_scenarios_to_keep {
+                        changed |= ui.add(egui::DragValue::new(max).speed(100.0)).changed();
+                    }
+                });
+                ui.end_row();
+                changed |= widgets::number(
+                    ui,
+                    "prune_interval_seconds",
+                    &mut database.prune_interval_seconds,
+                    1.0,
+                );
+                changed |=
+                    widgets::number(ui, "migration_jitter", &mut database.migration_jitter, 1.0);
+                let backup = &mut database.backup;
+                ui.label("backup enabled");
+                changed |= ui.checkbox(&mut backup.enabled, "").changed();
+                ui.end_row();
+                changed |= widgets::number(ui, "backup keep", &mut backup.keep, 1.0);
+                changed |= widgets::number(
+                    ui,
+                    "backup min_scenarios_pruned",
+                    &mut backup.min_scenarios_pruned,
+                    100.0,
+                );
+                changed
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn visualization_section(ui: &mut egui::Ui, visualization: &mut VisualizationConfig) -> bool {
+    ui.collapsing("Visualization", |ui| {
+        Grid::new("visualization")
+            .show(ui, |ui| {
+                let mut changed = false;
+                let field = &mut visualization.potential_field;
+                ui.label("potential_field mode");
+                ui.horizontal(|ui| {
+                    for &(mode, name) in &[
+                        (FieldMode::Off, "off"),
+                        (FieldMode::Grid, "grid"),
+                        (FieldMode::Heatmap, "heatmap"),
+                    ] {
+                        changed |= ui.radio_value(&mut field.mode, mode, name).changed();
+                    }
+                });
+                ui.end_row();
+                changed |= widgets::number(ui, "potential_field size", &mut field.size, 10.0);
+                changed |=
+                    widgets::number(ui, "potential_field resolution", &mut field.resolution, 1.0);
+                changed |= widgets::number(ui, "potential_field height", &mut field.height, 10.0);
+                changed |= widgets::number(ui, "potential_field depth", &mut field.depth, 10.0);
+                changed |= widgets::number(
+                    ui,
+                    "potential_field potential_scale",
+                    &mut field.potential_scale,
+                    10.0,
+                );
+                changed |=
+                    widgets::number(ui, "potential_field softening", &mut field.softening, 1.0);
+                let probes = &mut visualization.probes;
+                changed |= widgets::number(ui, "probes count", &mut probes.count, 1.0);
+                changed |=
+                    widgets::number(ui, "probes trail_length", &mut probes.trail_length, 1.0);
+                changed |= widgets::number(
+                    ui,
+                    "probes sample_seconds",
+                    &mut probes.sample_seconds,
+                    0.01,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "probes lifetime_seconds",
+                    &mut probes.lifetime_seconds,
+                    0.1,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "probes launch_distance",
+                    &mut probes.launch_distance,
+                    10.0,
+                );
+                changed |=
+                    widgets::number(ui, "probes launch_speed", &mut probes.launch_speed, 1.0);
+                ui.label("probes color");
+                changed |= ui.color_edit_button_rgb(&mut probes.color).changed();
+                ui.end_row();
+                let bound_pairs = &mut visualization.bound_pairs;
+                ui.label("bound_pairs show");
+                changed |= ui.checkbox(&mut bound_pairs.show, "").changed();
+                ui.end_row();
+                ui.label("bound_pairs color");
+                changed |= ui.color_edit_button_rgb(&mut bound_pairs.color).changed();
+                ui.end_row();
+                let belt = &mut visualization.asteroid_belt;
+                changed |= widgets::number(ui, "asteroid_belt count", &mut belt.count, 10.0);
+                changed |= widgets::number(
+                    ui,
+                    "asteroid_belt inner_radius",
+                    &mut belt.inner_radius,
+                    10.0,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "asteroid_belt outer_radius",
+                    &mut belt.outer_radius,
+                    10.0,
+                );
+                changed |= widgets::number(ui, "asteroid_belt thickness", &mut belt.thickness, 1.0);
+                changed |= widgets::number(ui, "asteroid_belt rock_size", &mut belt.rock_size, 0.1);
+                ui.label("asteroid_belt color");
+                changed |= ui.color_edit_button_rgb(&mut belt.color).changed();
+                ui.end_row();
+                ui.label("family_tree");
+                changed |= ui.checkbox(&mut visualization.family_tree, "").changed();
+                ui.end_row();
+                ui.label("diagnostics");
+                changed |= ui.checkbox(&mut visualization.diagnostics, "").changed();
+                ui.end_row();
+                changed
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn physics_section(ui: &mut egui::Ui, physics: &mut PhysicsConfig) -> bool {
+    ui.collapsing("Physics", |ui| {
+        Grid::new("physics")
+            .show(ui, |ui| {
+                let mut changed = false;
+                let integrator = &mut physics.integrator;
+                ui.label("integrator mode");
+                ui.horizontal(|ui| {
+                    for &(mode, name) in &[
+                        (IntegratorMode::Standard, "standard"),
+                        (IntegratorMode::HighAccuracy, "high_accuracy"),
+                        (IntegratorMode::BarnesHut, "barnes_hut"),
+                    ] {
+                        changed |= ui.radio_value(&mut integrator.mode, mode, name).changed();
+                    }
+                });
+                ui.end_row();
+                changed |= widgets::number(
+                    ui,
+                    "integrator close_encounter_distance",
+                    &mut integrator.close_encounter_distance,
+                    1.0,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "integrator close_encounter_substeps",
+                    &mut integrator.close_encounter_substeps,
+                    1.0,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "integrator barnes_hut_theta",
+                    &mut integrator.barnes_hut_theta,
+                    0.01,
+                );
+                changed |= widgets::number(ui, "spawn_frames", &mut physics.spawn_frames, 1.0);
+                let sleep = &mut physics.sleep;
+                ui.label("sleep enabled");
+                changed |= ui.checkbox(&mut sleep.enabled, "").changed();
+                ui.end_row();
+                changed |= widgets::number(
+                    ui,
+                    "sleep cluster_distance",
+                    &mut sleep.cluster_distance,
+                    1.0,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "sleep isolation_distance",
+                    &mut sleep.isolation_distance,
+                    10.0,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "sleep max_relative_speed",
+                    &mut sleep.max_relative_speed,
+                    0.1,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "sleep check_interval_seconds",
+                    &mut sleep.check_interval_seconds,
+                    0.1,
+                );
+                let fields = &mut physics.fields;
+                changed |=
+                    widgets::vector(ui, "fields uniform", &mut fields.uniform, |ui, label, x| {
+                        widgets::number(ui, label, x, 0.1)
+                    });
+                for (i, radial) in fields.radial.iter_mut().enumerate() {
+                    let label = format!("fields radial {}", i);
+                    changed |= widgets::vector(
+                        ui,
+                        &format!("{} center", label),
+                        &mut radial.center,
+                        |ui, label, x| widgets::number(ui, label, x, 1.0),
+                    );
+                    changed |= widgets::number(
+                        ui,
+                        &format!("{} strength", label),
+                        &mut radial.strength,
+                        1.0,
+                    );
+                    ui.label(format!("{} falloff", label));
+                    ui.horizontal(|ui| {
+                        for &(falloff, name) in &[
+                            (Falloff::None, "none"),
+                            (Falloff::Linear, "linear"),
+                            (Falloff::InverseSquare, "inverse_square"),
+                        ] {
+                            changed |= ui.radio_value(&mut radial.falloff, falloff, name).changed();
+                        }
+                    });
+                    ui.end_row();
+                }
+                let deterministic = &mut physics.deterministic;
+                ui.label("deterministic enabled");
+                changed |= ui.checkbox(&mut deterministic.enabled, "").changed();
+                ui.end_row();
+                let mut default_seed = deterministic.seed.is_none();
+                ui.label("deterministic seed");
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut default_seed, "--seed").changed() {
+                        deterministic.seed = if default_seed {
+                            None
+                        } else {
+                            Some(deterministic.effective_seed())
+                        };
+                        changed = true;
+                    }
+                    if let Some(ref mut seed) = deterministic.seed {
+                        changed |= ui.add(egui::DragValue::new(seed).speed(1.0)).changed();
+                    }
+                });
+                ui.end_row();
+                changed |= widgets::number(
+                    ui,
+                    "deterministic frame_rate",
+                    &mut deterministic.frame_rate,
+                    1.0,
+                );
+                changed
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn sound_section(ui: &mut egui::Ui, sound: &mut SoundConfig) -> bool {
+    ui.collapsing("Sound", |ui| {
+        Grid::new("sound")
+            .show(ui, |ui| {
+                let mut changed = false;
+                let collisions = &mut sound.collision_sounds;
+                ui.label("collision_sounds muted");
+                changed |= ui.checkbox(&mut collisions.muted, "").changed();
+                ui.end_row();
+                changed |= widgets::number(
+                    ui,
+                    "collision_sounds max_volume",
+                    &mut collisions.max_volume,
+                    0.01,
+                );
+                changed |= widgets::number(
+                    ui,
+                    "collision_sounds reference_mass",
+                    &mut collisions.reference_mass,
+                    10.0,
+                );
+                changed
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+fn events_section(ui: &mut egui::Ui, events: &mut EventsConfig) -> bool {
+    ui.collapsing("Events", |ui| {
+        Grid::new("events")
+            .show(ui, |ui| {
+                widgets::number(
+                    ui,
+                    "world_events max_logged_per_second",
+                    &mut events.world_events.max_logged_per_second,
+                    0.1,
+                )
+            })
+            .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
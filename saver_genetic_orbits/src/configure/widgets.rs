@@ -0,0 +1,410 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Editors for the config utility types. Each editor adds a row to an enclosing [`Grid`] and
+//! returns true if the value was changed.
+
+use std::time::Duration;
+
+use bevy_egui::egui::emath::Numeric;
+use bevy_egui::egui::plot::{Line, Plot, Value, Values};
+use bevy_egui::egui::{ComboBox, DragValue, Grid, Ui};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::config::util::{
+    Distribution, ExponentialDistribution, LogNormalDistribution, NormalDistribution,
+    PoissonDistribution, Range, UniformDistribution, Vector, WeightedChoice, WeightedDistribution,
+};
+use crate::model::Stability;
+
+/// Number of samples drawn to preview a distribution.
+const PREVIEW_SAMPLES: usize = 2000;
+
+/// Number of bars in a distribution preview.
+const PREVIEW_BINS: usize = 40;
+
+/// Longest duration the editor accepts, in seconds: a day.
+const MAX_SECONDS: f64 = 24.0 * 60.0 * 60.0;
+
+/// Names of the distribution types, in the order they are offered.
+const DISTRIBUTION_TYPES: &[&str] = &[
+    "exponential",
+    "normal",
+    "uniform",
+    "poisson",
+    "log_normal",
+    "weighted",
+];
+
+/// Edits a single number.
+pub fn number<N: Numeric>(ui: &mut Ui, label: &str, value: &mut N, speed: f64) -> bool {
+    ui.label(label);
+    let changed = ui.add(DragValue::new(value).speed(speed)).changed();
+    ui.end_row();
+    changed
+}
+
+/// Edits a duration as a number of seconds, from 0 to [`MAX_SECONDS`].
+pub fn seconds(ui: &mut Ui, label: &str, value: &mut Duration, speed: f64) -> bool {
+    ui.label(label);
+    let mut secs = value.as_secs_f64();
+    let changed = ui
+        .add(
+            DragValue::new(&mut secs)
+                .speed(speed)
+                .clamp_range(0.0..=MAX_SECONDS),
+        )
+        .changed();
+    if changed {
+        // Bounded again in case the widget lets a typed value through unclamped.
+        *value = Duration::from_secs_f64(secs.max(0.0).min(MAX_SECONDS));
+    }
+    ui.end_row();
+    changed
+}
+
+/// Edits both ends of a range.
+pub fn range<N: Numeric>(ui: &mut Ui, label: &str, range: &mut Range<N>, speed: f64) -> bool {
+    ui.label(label);
+    let changed = ui
+        .horizontal(|ui| {
+            let min = ui
+                .add(DragValue::new(&mut range.min).speed(speed).prefix("min: "))
+                .changed();
+            let max = ui
+                .add(DragValue::new(&mut range.max).speed(speed).prefix("max: "))
+                .changed();
+            min || max
+        })
+        .inner;
+    ui.end_row();
+    changed
+}
+
+/// Edits a set of stability classifications, with a checkbox for each.
+pub fn stabilities(ui: &mut Ui, label: &str, stabilities: &mut Vec<Stability>) -> bool {
+    ui.label(label);
+    let changed = ui
+        .horizontal(|ui| {
+            let mut changed = false;
+            for stability in Stability::ALL.iter().copied() {
+                let mut selected = stabilities.contains(&stability);
+                if ui.checkbox(&mut selected, stability.as_str()).changed() {
+                    if selected {
+                        stabilities.push(stability);
+                    } else {
+                        stabilities.retain(|&s| s != stability);
+                    }
+                    changed = true;
+                }
+            }
+            changed
+        })
+        .inner;
+    ui.end_row();
+    changed
+}
+
+/// Edits each axis of a vector with the given editor.
+pub fn vector<T>(
+    ui: &mut Ui,
+    label: &str,
+    vector: &mut Vector<T>,
+    mut edit: impl FnMut(&mut Ui, &str, &mut T) -> bool,
+) -> bool {
+    let x = edit(ui, &format!("{} x", label), &mut vector.x);
+    let y = edit(ui, &format!("{} y", label), &mut vector.y);
+    let z = edit(ui, &format!("{} z", label), &mut vector.z);
+    x || y || z
+}
+
+/// Edits the parameters of a normal distribution.
+pub fn normal(ui: &mut Ui, label: &str, dist: &mut NormalDistribution) -> bool {
+    ui.label(label);
+    let changed = ui.horizontal(|ui| normal_params(ui, dist)).inner;
+    ui.end_row();
+    changed
+}
+
+/// Edits the parameters of a uniform distribution.
+pub fn uniform(ui: &mut Ui, label: &str, dist: &mut UniformDistribution) -> bool {
+    ui.label(label);
+    let changed = ui.horizontal(|ui| uniform_params(ui, dist)).inner;
+    ui.end_row();
+    changed
+}
+
+/// Edits a distribution which may be any of the supported types, and shows a histogram of values
+/// sampled from it. If `counts` is set, the preview samples whole numbers the same way the world
+/// generator does.
+pub fn distribution(ui: &mut Ui, label: &str, dist: &mut Distribution, counts: bool) -> bool {
+    ui.label(label);
+    let mut changed = false;
+    ui.vertical(|ui| {
+        let current = type_name(dist);
+        ComboBox::from_id_source(label)
+            .selected_text(current)
+            .show_ui(ui, |ui| {
+                for &name in DISTRIBUTION_TYPES {
+                    if ui.selectable_label(name == current, name).clicked() && name != current {
+                        *dist = default_of_type(name);
+                        changed = true;
+                    }
+                }
+            });
+        changed |= match dist {
+            Distribution::Exponential(ExponentialDistribution { lambda })
+            | Distribution::Poisson(PoissonDistribution { lambda }) => ui
+                .add(DragValue::new(lambda).speed(0.001).prefix("lambda: "))
+                .changed(),
+            Distribution::Normal(normal) => ui.horizontal(|ui| normal_params(ui, normal)).inner,
+            Distribution::Uniform(uniform) => ui.horizontal(|ui| uniform_params(ui, uniform)).inner,
+            Distribution::LogNormal(LogNormalDistribution {
+                mean,
+                standard_deviation,
+            }) => {
+                ui.horizontal(|ui| {
+                    let mean = ui
+                        .add(DragValue::new(mean).speed(0.01).prefix("log mean: "))
+                        .changed();
+                    let sd = ui
+                        .add(
+                            DragValue::new(standard_deviation)
+                                .speed(0.01)
+                                .prefix("log sd: "),
+                        )
+                        .changed();
+                    mean || sd
+                })
+                .inner
+            }
+            Distribution::Weighted(weighted) => weighted_params(ui, label, weighted),
+        };
+        preview(ui, label, dist, counts);
+    });
+    ui.end_row();
+    changed
+}
+
+fn normal_params(ui: &mut Ui, dist: &mut NormalDistribution) -> bool {
+    let mean = ui
+        .add(DragValue::new(&mut dist.mean).speed(0.1).prefix("mean: "))
+        .changed();
+    let sd = ui
+        .add(
+            DragValue::new(&mut dist.standard_deviation)
+                .speed(0.1)
+                .prefix("sd: "),
+        )
+        .changed();
+    mean || sd
+}
+
+fn uniform_params(ui: &mut Ui, dist: &mut UniformDistribution) -> bool {
+    let min = ui
+        .add(DragValue::new(&mut dist.min).speed(0.1).prefix("min: "))
+        .changed();
+    let max = ui
+        .add(DragValue::new(&mut dist.max).speed(0.1).prefix("max: "))
+        .changed();
+    min || max
+}
+
+fn weighted_params(ui: &mut Ui, label: &str, dist: &mut WeightedDistribution) -> bool {
+    let mut changed = false;
+    let mut remove = None;
+    Grid::new(format!("{} choices", label)).show(ui, |ui| {
+        for (i, choice) in dist.choices.iter_mut().enumerate() {
+            changed |= ui
+                .add(
+                    DragValue::new(&mut choice.value)
+                        .speed(0.1)
+                        .prefix("value: "),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut choice.weight)
+                        .speed(0.01)
+                        .prefix("weight: "),
+                )
+                .changed();
+            if ui.small_button("remove").clicked() {
+                remove = Some(i);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(i) = remove {
+        dist.choices.remove(i);
+        changed = true;
+    }
+    if ui.small_button("add choice").clicked() {
+        dist.choices.push(WeightedChoice {
+            value: 0.0,
+            weight: 1.0,
+        });
+        changed = true;
+    }
+    changed
+}
+
+/// Name of the distribution's type, as written in the config.
+fn type_name(dist: &Distribution) -> &'static str {
+    match dist {
+        Distribution::Exponential(_) => "exponential",
+        Distribution::Normal(_) => "normal",
+        Distribution::Uniform(_) => "uniform",
+        Distribution::Poisson(_) => "poisson",
+        Distribution::LogNormal(_) => "log_normal",
+        Distribution::Weighted(_) => "weighted",
+    }
+}
+
+/// Creates a distribution of the named type with simple default parameters.
+fn default_of_type(name: &str) -> Distribution {
+    match name {
+        "exponential" => Distribution::Exponential(ExponentialDistribution { lambda: 1.0 }),
+        "normal" => Distribution::Normal(NormalDistribution {
+            mean: 0.0,
+            standard_deviation: 1.0,
+        }),
+        "uniform" => Distribution::Uniform(UniformDistribution { min: 0.0, max: 1.0 }),
+        "poisson" => Distribution::Poisson(PoissonDistribution { lambda: 1.0 }),
+        "log_normal" => Distribution::LogNormal(LogNormalDistribution {
+            mean: 0.0,
+            standard_deviation: 1.0,
+        }),
+        "weighted" => Distribution::Weighted(WeightedDistribution {
+            choices: vec![WeightedChoice {
+                value: 1.0,
+                weight: 1.0,
+            }],
+        }),
+        _ => unreachable!("unknown distribution type {}", name),
+    }
+}
+
+/// Shows a histogram of samples from the distribution. Nothing is shown if the parameters are
+/// invalid, since sampling would panic; the validation errors explain what is wrong instead.
+fn preview(ui: &mut Ui, label: &str, dist: &Distribution, counts: bool) {
+    if !can_sample(dist) {
+        return;
+    }
+    // Always use the same seed so the preview doesn't flicker between frames.
+    let mut rng = StdRng::seed_from_u64(0);
+    let samples: Vec<f64> = (0..PREVIEW_SAMPLES)
+        .map(|_| {
+            if counts {
+                dist.sample_usize(&mut rng) as f64
+            } else {
+                dist.sample_f64(&mut rng)
+            }
+        })
+        .collect();
+    let outline = histogram(&samples, PREVIEW_BINS)
+        .into_iter()
+        .map(|(x, y)| Value::new(x, y))
+        .collect();
+    ui.add(
+        Plot::new(label)
+            .line(Line::new(Values::from_values(outline)).fill(0.0))
+            .include_y(0.0)
+            .height(100.0)
+            .allow_drag(false)
+            .allow_zoom(false),
+    );
+}
+
+/// Returns true if the distribution's parameters are valid enough to sample from.
+fn can_sample(dist: &Distribution) -> bool {
+    match dist {
+        Distribution::Exponential(ExponentialDistribution { lambda })
+        | Distribution::Poisson(PoissonDistribution { lambda }) => *lambda > 0.0,
+        Distribution::Normal(NormalDistribution {
+            standard_deviation, ..
+        })
+        | Distribution::LogNormal(LogNormalDistribution {
+            standard_deviation, ..
+        }) => standard_deviation.is_finite() && *standard_deviation >= 0.0,
+        Distribution::Uniform(UniformDistribution { min, max }) => min <= max,
+        Distribution::Weighted(WeightedDistribution { choices }) => {
+            choices
+                .iter()
+                .all(|choice| choice.weight.is_finite() && choice.weight >= 0.0)
+                && choices.iter().any(|choice| choice.weight > 0.0)
+        }
+    }
+}
+
+/// Computes the outline of a histogram of the samples, as points along the tops of the bars. Bar
+/// heights are the fraction of samples that fall in each bar.
+fn histogram(samples: &[f64], bins: usize) -> Vec<(f64, f64)> {
+    let finite = samples.iter().copied().filter(|sample| sample.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, f64::min);
+    let max = finite.clone().fold(f64::NEG_INFINITY, f64::max);
+    if min > max {
+        return vec![];
+    }
+    let width = if max > min {
+        (max - min) / bins as f64
+    } else {
+        1.0
+    };
+    let mut counts = vec![0usize; bins];
+    for sample in finite {
+        let bin = ((sample - min) / width) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+    let total = samples.len() as f64;
+    counts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &count)| {
+            let left = min + i as f64 * width;
+            let height = count as f64 / total;
+            vec![(left, height), (left + width, height)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_outlines_bars() {
+        let outline = histogram(&[0.0, 1.0, 1.0, 2.0], 2);
+        assert_eq!(
+            outline,
+            vec![(0.0, 0.25), (1.0, 0.25), (1.0, 0.75), (2.0, 0.75)],
+        );
+    }
+
+    #[test]
+    fn histogram_of_constant() {
+        let outline = histogram(&[3.0, 3.0], 2);
+        assert_eq!(
+            outline,
+            vec![(3.0, 1.0), (4.0, 1.0), (4.0, 0.0), (5.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn histogram_of_nothing() {
+        assert_eq!(histogram(&[], 2), vec![]);
+        assert_eq!(histogram(&[f64::NAN], 2), vec![]);
+    }
+}
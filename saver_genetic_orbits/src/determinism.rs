@@ -0,0 +1,240 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic runs, so a world can be simulated again bit-identically to its scored run. With
+//! `physics.deterministic.enabled` set:
+//!
+//! * every frame covers the same [`SaverTime`] and takes exactly one physics step of that length,
+//!   or the same number of substeps splitting it, so neither scoring nor the simulation depends on
+//!   how long frames take to draw,
+//! * the [`SimulationRng`]'s scenario stream is reseeded with the configured seed when each
+//!   scenario starts, and
+//! * a [`PhysicsChecksum`] of every planet's position and velocity is updated each tick and sent
+//!   with the scenario's end as a world event, so two runs can be compared.
+//!
+//! Systems which add to the same forces are ordered, so the sums come out the same every time.
+//! The scenarios are picked and mutated from the [`SimulationRng`]'s generation stream, which is
+//! seeded once at startup, so a seeded saver also runs the same sequence of scenarios from the same
+//! database.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::na::{Isometry3, Vector3};
+use bevy_rapier3d::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use xsecurelock_saver::cli;
+use xsecurelock_saver::engine::SaverTime;
+
+use crate::config::physics::PhysicsConfig;
+use crate::world::{PendingPlanets, Planet};
+use crate::SaverState;
+
+/// Sets up deterministic runs if they're configured, and keeps the [`SimulationRng`] and
+/// [`PhysicsChecksum`].
+pub struct DeterminismPlugin;
+
+impl Plugin for DeterminismPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(SimulationRng {
+            generation: StdRng::from_entropy(),
+            scenario: StdRng::from_entropy(),
+        })
+        .init_resource::<PhysicsChecksum>()
+        .add_startup_system(fix_timestep.system())
+        .add_startup_system(seed_generation.system())
+        .add_system_set(
+            SystemSet::on_enter(SaverState::Run)
+                .with_system(start_scenario.system().label("reseed-simulation")),
+        )
+        // Once the physics step has moved the planets.
+        .add_system_to_stage(CoreStage::Last, update_checksum.system());
+    }
+}
+
+/// Random numbers for the simulation, seeded from the configured seed or the saver's `--seed` so
+/// that seeded runs repeat.
+pub struct SimulationRng {
+    /// For picking and mutating the worlds to run. Seeded once at startup, and at random if there
+    /// is no seed.
+    pub generation: StdRng,
+    /// For anything which happens while a scenario runs. Reseeded when each scenario starts, with
+    /// the same seed in deterministic runs and from the generation stream otherwise. Systems using
+    /// it on entering [`SaverState::Run`] should run after `"reseed-simulation"`.
+    pub scenario: StdRng,
+}
+
+/// Running hash of the planets' positions and velocities on every physics tick of the current
+/// scenario. Only kept in deterministic runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PhysicsChecksum {
+    /// Number of ticks hashed.
+    pub ticks: u64,
+    pub hash: u64,
+}
+
+impl PhysicsChecksum {
+    /// Adds a tick with the given bodies, as pairs of position and velocity. The order of the
+    /// bodies doesn't matter, so planets spawned in a different order hash the same.
+    fn add_tick<'a>(
+        &mut self,
+        bodies: impl Iterator<Item = (&'a Isometry3<f32>, &'a Vector3<f32>)>,
+    ) {
+        let tick = bodies
+            .map(|(position, velocity)| {
+                let translation = position.translation.vector;
+                let rotation = position.rotation.coords;
+                hash_floats(translation.iter().chain(&rotation).chain(velocity))
+            })
+            .fold(0u64, u64::wrapping_add);
+        self.hash = mix(self.hash ^ tick);
+        self.ticks += 1;
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x100_0000_01b3;
+
+/// FNV-1a hash of the bits of `values`.
+fn hash_floats<'a>(values: impl Iterator<Item = &'a f32>) -> u64 {
+    values.fold(FNV_OFFSET, |hash, value| {
+        let bytes = value.to_bits().to_le_bytes();
+        bytes.iter().fold(hash, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+    })
+}
+
+/// Scrambles the bits of `x`, so that ticks with swapped hashes give different checksums.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// In deterministic runs, makes every frame cover the same time and take one physics step.
+fn fix_timestep(
+    physics: Res<PhysicsConfig>,
+    mut time: ResMut<SaverTime>,
+    mut rapier: ResMut<RapierConfiguration>,
+    mut integration: ResMut<IntegrationParameters>,
+) {
+    let config = &physics.deterministic;
+    if !config.enabled {
+        return;
+    }
+    let dt = 1.0 / config.frame_rate as f64;
+    time.set_fixed_delta(Some(Duration::from_secs_f64(dt)));
+    rapier.timestep_mode = TimestepMode::FixedTimestep;
+    integration.dt = dt as f32;
+    info!(
+        "Running deterministically at {} frames per second with seed {}",
+        config.frame_rate,
+        config.effective_seed()
+    );
+}
+
+/// Seeds the [`SimulationRng`]'s generation stream, from the deterministic seed in deterministic
+/// runs and otherwise from `--seed` if one was given.
+fn seed_generation(physics: Res<PhysicsConfig>, mut rng: ResMut<SimulationRng>) {
+    let config = &physics.deterministic;
+    rng.generation = if config.enabled {
+        StdRng::seed_from_u64(config.effective_seed())
+    } else {
+        cli::rng()
+    };
+}
+
+/// Reseeds the [`SimulationRng`] and restarts the [`PhysicsChecksum`] for a new scenario.
+fn start_scenario(
+    physics: Res<PhysicsConfig>,
+    mut rng: ResMut<SimulationRng>,
+    mut checksum: ResMut<PhysicsChecksum>,
+) {
+    let config = &physics.deterministic;
+    rng.scenario = if config.enabled {
+        StdRng::seed_from_u64(config.effective_seed())
+    } else {
+        StdRng::from_rng(&mut rng.generation).expect("StdRng can't fail to seed")
+    };
+    *checksum = PhysicsChecksum::default();
+}
+
+/// Hashes the planets into the [`PhysicsChecksum`] once the scenario's simulation has started.
+fn update_checksum(
+    physics: Res<PhysicsConfig>,
+    state: Res<State<SaverState>>,
+    pending: Res<PendingPlanets>,
+    mut checksum: ResMut<PhysicsChecksum>,
+    planets: Query<(&RigidBodyPosition, &RigidBodyVelocity), With<Planet>>,
+) {
+    if !physics.deterministic.enabled
+        || *state.current() != SaverState::Run
+        || pending.is_spawning()
+    {
+        return;
+    }
+    checksum.add_tick(
+        planets
+            .iter()
+            .map(|(position, velocity)| (&position.position, &velocity.linvel)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(ticks: &[&[(f32, f32)]]) -> PhysicsChecksum {
+        let mut checksum = PhysicsChecksum::default();
+        for bodies in ticks {
+            let bodies: Vec<_> = bodies
+                .iter()
+                .map(|&(x, vx)| {
+                    (
+                        Isometry3::translation(x, 0.0, 0.0),
+                        Vector3::new(vx, 0.0, 0.0),
+                    )
+                })
+                .collect();
+            checksum.add_tick(
+                bodies
+                    .iter()
+                    .map(|(position, velocity)| (position, velocity)),
+            );
+        }
+        checksum
+    }
+
+    #[test]
+    fn checksums_ignore_body_order() {
+        let a = checksum(&[&[(1.0, 2.0), (3.0, 4.0)], &[(1.5, 2.0), (3.5, 4.0)]]);
+        assert_eq!(a.ticks, 2);
+        assert_eq!(
+            a,
+            checksum(&[&[(3.0, 4.0), (1.0, 2.0)], &[(3.5, 4.0), (1.5, 2.0)]])
+        );
+        // Differs for the slightest change, and for ticks in a different order.
+        assert_ne!(
+            a,
+            checksum(&[&[(1.0, 2.0), (3.0, 4.0)], &[(1.5, 2.0), (3.5, 4.000001)]])
+        );
+        assert_ne!(
+            a,
+            checksum(&[&[(1.5, 2.0), (3.5, 4.0)], &[(1.0, 2.0), (3.0, 4.0)]])
+        );
+    }
+}
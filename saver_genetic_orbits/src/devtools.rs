@@ -0,0 +1,479 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Developer console for tuning the saver while it runs. Only available with the `devtools`
+//! feature, and only when running in a regular window rather than inside XSecurelock.
+//!
+//! Press the backtick key to open or close the console, then type `help` for a list of commands.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use xsecurelock_saver::engine::is_running_in_xsecurelock;
+
+use crate::config::camera::CameraConfig;
+use crate::config::scoring::ScoringConfig;
+use crate::config::visualization::{FieldMode, VisualizationConfig};
+use crate::statustracker::ActiveWorld;
+use crate::world::GravityConstant;
+use crate::SaverState;
+
+/// Number of lines of output to keep in the console.
+const MAX_LINES: usize = 12;
+
+/// Key which opens and closes the console.
+const TOGGLE_KEY: KeyCode = KeyCode::Grave;
+
+/// Character typed by the toggle key, which shouldn't end up in the input line.
+const TOGGLE_CHAR: char = '`';
+
+/// Longest time, in seconds, the console accepts for durations: a day.
+const MAX_DURATION_SECS: f32 = 24.0 * 60.0 * 60.0;
+
+const HELP: &str = "\
+commands:
+  gravity [G]                   show or set the gravitational constant
+  camera speed|dist|easing [V]  show or set camera rotation speed, distance or easing
+  camera tilt [RADIANS [SECS]]  show or set camera tilt amplitude and period
+  score fn [EXPR]               show or set the score per second expression
+  score area [W H D]            show or set the scored area
+  score time [SECS]             show or set how long scenarios are scored
+  score display [MIN MAX]       show or set how long scenarios can be shown
+  field [off|grid|heatmap]      show or set the potential field visualization
+  regen                         discard the current scenario and generate a new one
+  clear                         clear the console";
+
+/// Adds the developer console.
+pub struct DevtoolsPlugin;
+
+impl Plugin for DevtoolsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if is_running_in_xsecurelock() {
+            info!("Running in XSecurelock, devtools disabled");
+            return;
+        }
+        app.init_resource::<Console>()
+            .add_startup_system(setup.system())
+            .add_system(read_input.system().label("read-console-input"))
+            .add_system(show_console.system().after("read-console-input"));
+    }
+}
+
+/// State of the developer console.
+#[derive(Default)]
+struct Console {
+    /// Whether the console is currently shown and accepting input.
+    open: bool,
+    /// Command currently being typed.
+    input: String,
+    /// Recent commands and their output.
+    lines: VecDeque<String>,
+}
+
+impl Console {
+    /// Adds a line of output, dropping the oldest lines if there are too many.
+    fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.lines.push_back(line.to_string());
+        }
+        while self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+    }
+}
+
+/// Marker component for the console text entity.
+struct ConsoleText;
+
+/// Adds the console text, initially hidden.
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    const FONT_SIZE: f32 = 16.0;
+
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "".to_string(),
+                        style: style.clone(),
+                    },
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            color: Color::GOLD,
+                            ..style
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            visible: Visible {
+                is_transparent: true,
+                is_visible: false,
+            },
+            ..Default::default()
+        })
+        .insert(ConsoleText);
+}
+
+/// Handles typing into the console and runs commands when enter is pressed.
+#[allow(clippy::too_many_arguments)]
+fn read_input(
+    mut console: ResMut<Console>,
+    keys: Res<Input<KeyCode>>,
+    mut chars: EventReader<ReceivedCharacter>,
+    mut gravity: ResMut<GravityConstant>,
+    mut camera: ResMut<CameraConfig>,
+    mut scoring: ResMut<ScoringConfig>,
+    mut visualization: ResMut<VisualizationConfig>,
+    mut world: ResMut<ActiveWorld>,
+    mut state: ResMut<State<SaverState>>,
+) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        console.open = !console.open;
+    }
+    if !console.open {
+        chars.iter().for_each(drop);
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        console.open = false;
+        return;
+    }
+    for ch in chars.iter() {
+        if !ch.char.is_control() && ch.char != TOGGLE_CHAR {
+            console.input.push(ch.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        let command = std::mem::take(&mut console.input);
+        console.print(&format!("> {}", command));
+        if command.trim() == "clear" {
+            console.lines.clear();
+            return;
+        }
+        let output = execute(
+            &command,
+            &mut gravity,
+            &mut camera,
+            &mut scoring,
+            &mut visualization,
+            &mut world,
+            &mut state,
+        );
+        match output {
+            Ok(output) => console.print(&output),
+            Err(err) => console.print(&format!("error: {}", err)),
+        }
+    }
+}
+
+/// Runs a single console command, returning its output.
+fn execute(
+    command: &str,
+    gravity: &mut GravityConstant,
+    camera: &mut CameraConfig,
+    scoring: &mut ScoringConfig,
+    visualization: &mut VisualizationConfig,
+    world: &mut ActiveWorld,
+    state: &mut State<SaverState>,
+) -> Result<String, String> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    match args.as_slice() {
+        [] => Ok(String::new()),
+        ["help"] => Ok(HELP.to_string()),
+        ["gravity"] => Ok(format!("gravity: {}", gravity.0)),
+        ["gravity", g] => {
+            gravity.0 = parse(g)?;
+            world.discard = true;
+            Ok(format!(
+                "gravity: {} (current scenario won't be saved)",
+                gravity.0
+            ))
+        }
+        ["camera", "speed"] => Ok(format!("speed: {}", camera.rotation_speed)),
+        ["camera", "speed", speed] => {
+            camera.rotation_speed = parse(speed)?;
+            Ok(format!("speed: {}", camera.rotation_speed))
+        }
+        ["camera", "dist"] => Ok(format!("dist: {}", camera.view_dist)),
+        ["camera", "dist", dist] => {
+            camera.view_dist = parse(dist)?;
+            Ok(format!("dist: {}", camera.view_dist))
+        }
+        ["camera", "easing"] => Ok(format!("easing: {}s", camera.speed_easing_seconds)),
+        ["camera", "easing", secs] => {
+            camera.speed_easing_seconds = parse_non_negative(secs)?;
+            Ok(format!("easing: {}s", camera.speed_easing_seconds))
+        }
+        ["camera", "tilt", rest @ ..] if rest.len() <= 2 => {
+            if let Some(amplitude) = rest.get(0) {
+                camera.tilt.amplitude = parse_non_negative(amplitude)?;
+            }
+            if let Some(period) = rest.get(1) {
+                camera.tilt.period_seconds = parse_positive(period)?;
+            }
+            Ok(format!(
+                "tilt: {} radians every {}s",
+                camera.tilt.amplitude, camera.tilt.period_seconds,
+            ))
+        }
+        ["score", "fn"] => Ok(format!("score fn: {}", scoring.score_per_second)),
+        ["score", "fn", ..] => {
+            let source = command
+                .trim_start()
+                .strip_prefix("score")
+                .and_then(|rest| rest.trim_start().strip_prefix("fn"))
+                .unwrap_or_default();
+            scoring.score_per_second = source.parse()?;
+            world.discard = true;
+            Ok(format!(
+                "score fn: {} (current scenario won't be saved)",
+                scoring.score_per_second,
+            ))
+        }
+        ["score", "area"] => Ok(format!(
+            "score area: {} x {} x {}",
+            scoring.scored_area.width, scoring.scored_area.height, scoring.scored_area.depth,
+        )),
+        ["score", "area", width, height, depth] => {
+            scoring.scored_area.width = parse_positive(width)?;
+            scoring.scored_area.height = parse_positive(height)?;
+            scoring.scored_area.depth = parse_positive(depth)?;
+            world.discard = true;
+            Ok(format!(
+                "score area: {} x {} x {} (current scenario won't be saved)",
+                scoring.scored_area.width, scoring.scored_area.height, scoring.scored_area.depth,
+            ))
+        }
+        ["score", "time"] => Ok(format!("score time: {:?}", scoring.scored_time)),
+        ["score", "time", secs] => {
+            scoring.scored_time = parse_duration(secs)?;
+            world.timer.set_duration(scoring.scored_time);
+            world.discard = true;
+            Ok(format!(
+                "score time: {:?} (current scenario won't be saved)",
+                scoring.scored_time,
+            ))
+        }
+        ["score", "display"] => Ok(format!(
+            "score display: {:?} - {:?}",
+            scoring.min_display_time, scoring.max_display_time,
+        )),
+        ["score", "display", min, max] => {
+            let (min, max) = (parse_duration(min)?, parse_duration(max)?);
+            if min > max {
+                return Err(format!("expected min <= max, got {:?} - {:?}", min, max));
+            }
+            scoring.min_display_time = min;
+            scoring.max_display_time = max;
+            Ok(format!(
+                "score display: {:?} - {:?}",
+                scoring.min_display_time, scoring.max_display_time,
+            ))
+        }
+        ["field"] => Ok(format!("field: {:?}", visualization.potential_field.mode)),
+        ["field", mode] => {
+            visualization.potential_field.mode = match *mode {
+                "off" => FieldMode::Off,
+                "grid" => FieldMode::Grid,
+                "heatmap" => FieldMode::Heatmap,
+                _ => return Err(format!("expected off, grid or heatmap, got {:?}", mode)),
+            };
+            Ok(format!("field: {:?}", visualization.potential_field.mode))
+        }
+        ["regen"] => {
+            world.discard = true;
+            state
+                .set(SaverState::Generate)
+                .map_err(|err| format!("can't regenerate now: {:?}", err))?;
+            Ok("regenerating".to_string())
+        }
+        _ => Err(format!("unknown command {:?}, try `help`", command.trim())),
+    }
+}
+
+/// Parses a finite float argument.
+fn parse(arg: &str) -> Result<f32, String> {
+    match arg.parse::<f32>() {
+        Ok(val) if val.is_finite() => Ok(val),
+        _ => Err(format!("expected a number, got {:?}", arg)),
+    }
+}
+
+/// Parses a float argument, erroring if it is negative.
+fn parse_non_negative(arg: &str) -> Result<f32, String> {
+    match parse(arg)? {
+        val if val >= 0.0 => Ok(val),
+        val => Err(format!("expected a number >= 0, got {}", val)),
+    }
+}
+
+/// Parses a float argument, erroring if it is not positive.
+fn parse_positive(arg: &str) -> Result<f32, String> {
+    match parse(arg)? {
+        val if val > 0.0 => Ok(val),
+        val => Err(format!("expected a number > 0, got {}", val)),
+    }
+}
+
+/// Parses a positive number of seconds, up to [`MAX_DURATION_SECS`].
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    match parse_positive(arg)? {
+        secs if secs <= MAX_DURATION_SECS => Ok(Duration::from_secs_f32(secs)),
+        secs => Err(format!(
+            "expected at most {} seconds, got {}",
+            MAX_DURATION_SECS, secs
+        )),
+    }
+}
+
+/// Updates the console text and shows or hides it.
+fn show_console(
+    console: Res<Console>,
+    mut query: Query<(&mut Text, &mut Visible), With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for (mut text, mut visible) in query.iter_mut() {
+        visible.is_visible = console.open;
+        text.sections[0].value = console
+            .lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        text.sections[1].value = format!("> {}_", console.input);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::world::{FromWorld, World};
+
+    use super::*;
+
+    /// The resources a console command can change.
+    struct Saver {
+        gravity: GravityConstant,
+        camera: CameraConfig,
+        scoring: ScoringConfig,
+        visualization: VisualizationConfig,
+        world: ActiveWorld,
+        state: State<SaverState>,
+    }
+
+    impl Saver {
+        fn new() -> Self {
+            let mut world = World::default();
+            world.insert_resource(ScoringConfig::default());
+            Self {
+                gravity: GravityConstant(500.0),
+                camera: CameraConfig::default(),
+                scoring: ScoringConfig::default(),
+                visualization: VisualizationConfig::default(),
+                world: ActiveWorld::from_world(&mut world),
+                state: State::new(SaverState::Run),
+            }
+        }
+
+        fn run(&mut self, command: &str) -> Result<String, String> {
+            execute(
+                command,
+                &mut self.gravity,
+                &mut self.camera,
+                &mut self.scoring,
+                &mut self.visualization,
+                &mut self.world,
+                &mut self.state,
+            )
+        }
+    }
+
+    #[test]
+    fn parses_only_finite_numbers() {
+        assert_eq!(parse("1.5"), Ok(1.5));
+        assert_eq!(parse_non_negative("0"), Ok(0.0));
+        assert_eq!(parse_positive("2"), Ok(2.0));
+        for arg in &["NaN", "inf", "-inf", "1e39", "x"] {
+            assert!(parse(arg).is_err(), "{}", arg);
+            assert!(parse_positive(arg).is_err(), "{}", arg);
+        }
+        assert!(parse_non_negative("-1").is_err());
+        assert!(parse_positive("0").is_err());
+    }
+
+    #[test]
+    fn parses_durations_up_to_a_day() {
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(
+            parse_duration("86400"),
+            Ok(Duration::from_secs(24 * 60 * 60))
+        );
+        assert!(parse_duration("86401").is_err());
+        assert!(parse_duration("1e30").is_err());
+        assert!(parse_duration("inf").is_err());
+        assert!(parse_duration("0").is_err());
+    }
+
+    #[test]
+    fn rejects_values_which_would_break_the_saver() {
+        let mut saver = Saver::new();
+        let scoring = saver.scoring.clone();
+        assert!(saver.run("score time inf").is_err());
+        assert!(saver.run("score display 1 1e30").is_err());
+        assert!(saver.run("score display 5 1").is_err());
+        assert!(saver.run("gravity NaN").is_err());
+        assert!(saver.run("camera dist inf").is_err());
+        assert_eq!(saver.gravity.0, 500.0);
+        assert_eq!(saver.scoring.scored_time, scoring.scored_time);
+        assert_eq!(saver.scoring.min_display_time, scoring.min_display_time);
+        assert_eq!(saver.scoring.max_display_time, scoring.max_display_time);
+        assert!(!saver.world.discard);
+    }
+
+    #[test]
+    fn sets_values() {
+        let mut saver = Saver::new();
+        saver.run("score display 1 5").unwrap();
+        assert_eq!(saver.scoring.min_display_time, Duration::from_secs(1));
+        assert_eq!(saver.scoring.max_display_time, Duration::from_secs(5));
+        saver.run("score time 30").unwrap();
+        assert_eq!(saver.scoring.scored_time, Duration::from_secs(30));
+        assert_eq!(saver.world.timer.duration(), Duration::from_secs(30));
+        saver.run("gravity 250").unwrap();
+        assert_eq!(saver.gravity.0, 250.0);
+        assert!(saver.world.discard);
+        assert!(saver.run("bogus").is_err());
+    }
+}
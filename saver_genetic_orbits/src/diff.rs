@@ -0,0 +1,317 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares the worlds of two stored scenarios, to show what a mutation actually changed between a
+//! parent and its child. Run with the `diff <FROM> <TO>` subcommand, optionally with
+//! `--format json`.
+//!
+//! The database doesn't record which planet became which, so planets are matched up by position,
+//! closest pairs first. Planets with no counterpart within [`MATCH_DISTANCE`] count as removed or
+//! added. Planets which merged with another during mutation usually show up as moved, with the
+//! mass of the planet they absorbed added.
+
+use std::fmt::Write;
+use std::process;
+use std::str::FromStr;
+
+use bevy::math::Vec3;
+use serde_json::{json, Value};
+
+use crate::model::{Planet, Scenario};
+use crate::storage::{self, Storage};
+
+/// Furthest apart two planets can start and still be matched as the same planet.
+pub const MATCH_DISTANCE: f32 = 100.0;
+
+/// Output formats for a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for DiffFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, String> {
+        match format {
+            "text" => Ok(DiffFormat::Text),
+            "json" => Ok(DiffFormat::Json),
+            _ => Err(format!("expected text or json, got {:?}", format)),
+        }
+    }
+}
+
+/// How the planets of one world correspond to the planets of another, by index.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PlanetMatching {
+    /// Pairs of matched planets, in order of the planet in the first world.
+    pub matched: Vec<(usize, usize)>,
+    /// Planets in the first world with no match in the second.
+    pub removed: Vec<usize>,
+    /// Planets in the second world with no match in the first.
+    pub added: Vec<usize>,
+}
+
+/// Prints the differences between two stored scenarios to stdout. Exits the process if either
+/// can't be found.
+pub fn run(from: u64, to: u64, format: DiffFormat) {
+    let mut storage = match storage::open_configured() {
+        Some(storage) => storage,
+        None => {
+            eprintln!("No scenario database to read from");
+            process::exit(1);
+        }
+    };
+    let mut get = |id| match storage
+        .get_scenario(id)
+        .expect("Unable to read scenario from storage")
+    {
+        Some(scenario) => scenario,
+        None => {
+            eprintln!("No scenario {} stored", id);
+            process::exit(1);
+        }
+    };
+    let from = get(from);
+    let to = get(to);
+    let matching = match_planets(&from.world.planets, &to.world.planets);
+    match format {
+        DiffFormat::Text => print!("{}", to_text(&from, &to, &matching)),
+        DiffFormat::Json => println!("{:#}", to_json(&from, &to, &matching)),
+    }
+}
+
+/// Matches planets between the two worlds, closest pairs first.
+pub fn match_planets(from: &[Planet], to: &[Planet]) -> PlanetMatching {
+    let mut pairs = Vec::new();
+    for (i, a) in from.iter().enumerate() {
+        for (j, b) in to.iter().enumerate() {
+            let distance = a.position.distance(b.position);
+            if distance <= MATCH_DISTANCE {
+                pairs.push((distance, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let mut from_match = vec![None; from.len()];
+    let mut to_matched = vec![false; to.len()];
+    for (_, i, j) in pairs {
+        if from_match[i].is_none() && !to_matched[j] {
+            from_match[i] = Some(j);
+            to_matched[j] = true;
+        }
+    }
+
+    let mut matching = PlanetMatching::default();
+    for (i, j) in from_match.into_iter().enumerate() {
+        match j {
+            Some(j) => matching.matched.push((i, j)),
+            None => matching.removed.push(i),
+        }
+    }
+    matching.added = (0..to.len()).filter(|&j| !to_matched[j]).collect();
+    matching
+}
+
+/// Total mass of a list of planets.
+fn total_mass(planets: &[Planet]) -> f32 {
+    planets.iter().map(|planet| planet.mass).sum()
+}
+
+/// Formats a vector to one decimal place.
+fn vector_text(vector: Vec3) -> String {
+    format!("({:.1}, {:.1}, {:.1})", vector.x, vector.y, vector.z)
+}
+
+/// Describes the differences for people.
+pub fn to_text(from: &Scenario, to: &Scenario, matching: &PlanetMatching) -> String {
+    let from_planets = &from.world.planets;
+    let to_planets = &to.world.planets;
+    let mut text = String::new();
+    writeln!(
+        text,
+        "#{} -> #{}: {} -> {} planets, score {:.2} -> {:.2}",
+        from.id,
+        to.id,
+        from_planets.len(),
+        to_planets.len(),
+        from.score,
+        to.score,
+    )
+    .unwrap();
+    for &(i, j) in &matching.matched {
+        let (a, b) = (&from_planets[i], &to_planets[j]);
+        if a == b {
+            writeln!(text, "  unchanged {} -> {}", i, j).unwrap();
+        } else {
+            writeln!(
+                text,
+                "  moved     {} -> {}: position {:.1}, velocity {:.1}, mass {:+.1}",
+                i,
+                j,
+                a.position.distance(b.position),
+                a.velocity.distance(b.velocity),
+                b.mass - a.mass,
+            )
+            .unwrap();
+        }
+    }
+    for &i in &matching.removed {
+        let planet = &from_planets[i];
+        writeln!(
+            text,
+            "  removed   {}: mass {:.1} at {}",
+            i,
+            planet.mass,
+            vector_text(planet.position)
+        )
+        .unwrap();
+    }
+    for &j in &matching.added {
+        let planet = &to_planets[j];
+        writeln!(
+            text,
+            "  added     {}: mass {:.1} at {}",
+            j,
+            planet.mass,
+            vector_text(planet.position)
+        )
+        .unwrap();
+    }
+    let (from_mass, to_mass) = (total_mass(from_planets), total_mass(to_planets));
+    writeln!(
+        text,
+        "total mass {:.1} -> {:.1} ({:+.1})",
+        from_mass,
+        to_mass,
+        to_mass - from_mass
+    )
+    .unwrap();
+    text
+}
+
+/// Describes the differences as JSON, for further analysis.
+pub fn to_json(from: &Scenario, to: &Scenario, matching: &PlanetMatching) -> Value {
+    let from_planets = &from.world.planets;
+    let to_planets = &to.world.planets;
+    let planet = |index: usize, planet: &Planet| {
+        json!({
+            "index": index,
+            "position": <[f32; 3]>::from(planet.position),
+            "velocity": <[f32; 3]>::from(planet.velocity),
+            "mass": planet.mass,
+        })
+    };
+    json!({
+        "from": from.id,
+        "to": to.id,
+        "score_change": to.score - from.score,
+        "mass_change": total_mass(to_planets) - total_mass(from_planets),
+        "matched": matching
+            .matched
+            .iter()
+            .map(|&(i, j)| {
+                let (a, b) = (&from_planets[i], &to_planets[j]);
+                json!({
+                    "from": i,
+                    "to": j,
+                    "position_change": <[f32; 3]>::from(b.position - a.position),
+                    "velocity_change": <[f32; 3]>::from(b.velocity - a.velocity),
+                    "mass_change": b.mass - a.mass,
+                })
+            })
+            .collect::<Vec<_>>(),
+        "removed": matching
+            .removed
+            .iter()
+            .map(|&i| planet(i, &from_planets[i]))
+            .collect::<Vec<_>>(),
+        "added": matching
+            .added
+            .iter()
+            .map(|&j| planet(j, &to_planets[j]))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::World;
+
+    fn planet(x: f32, mass: f32) -> Planet {
+        Planet {
+            position: Vec3::new(x, 0., 0.),
+            velocity: Vec3::ZERO,
+            mass,
+            spin: Vec3::ZERO,
+        }
+    }
+
+    fn scenario(id: u64, planets: Vec<Planet>) -> Scenario {
+        Scenario {
+            id,
+            family: 1,
+            parent: None,
+            generation: 0,
+            world: World {
+                planets,
+                ..Default::default()
+            },
+            score: id as f64,
+            stability: None,
+        }
+    }
+
+    #[test]
+    fn matches_closest_first() {
+        let from = vec![planet(0., 1.), planet(50., 1.), planet(1000., 1.)];
+        let to = vec![planet(45., 1.), planet(10., 1.), planet(-1000., 1.)];
+        let matching = match_planets(&from, &to);
+        assert_eq!(matching.matched, vec![(0, 1), (1, 0)]);
+        assert_eq!(matching.removed, vec![2]);
+        assert_eq!(matching.added, vec![2]);
+    }
+
+    #[test]
+    fn text_lists_changes() {
+        let from = scenario(1, vec![planet(0., 10.), planet(500., 5.)]);
+        let to = scenario(
+            2,
+            vec![planet(0., 10.), planet(520., 8.), planet(-500., 2.)],
+        );
+        let matching = match_planets(&from.world.planets, &to.world.planets);
+        let text = to_text(&from, &to, &matching);
+        assert!(text.starts_with("#1 -> #2: 2 -> 3 planets, score 1.00 -> 2.00\n"));
+        assert!(text.contains("  unchanged 0 -> 0\n"));
+        assert!(text.contains("  moved     1 -> 1: position 20.0, velocity 0.0, mass +3.0\n"));
+        assert!(text.contains("  added     2: mass 2.0 at (-500.0, 0.0, 0.0)\n"));
+        assert!(text.ends_with("total mass 15.0 -> 20.0 (+5.0)\n"));
+    }
+
+    #[test]
+    fn json_describes_changes() {
+        let from = scenario(1, vec![planet(0., 10.), planet(500., 5.)]);
+        let to = scenario(2, vec![planet(20., 12.)]);
+        let matching = match_planets(&from.world.planets, &to.world.planets);
+        let json = to_json(&from, &to, &matching);
+        assert_eq!(json["matched"][0]["position_change"], json!([20., 0., 0.]));
+        assert_eq!(json["matched"][0]["mass_change"], 2.);
+        assert_eq!(json["removed"][0]["index"], 1);
+        assert_eq!(json["added"], json!([]));
+        assert_eq!(json["mass_change"], -3.);
+    }
+}
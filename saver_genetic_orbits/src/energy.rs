@@ -0,0 +1,145 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Total energy of the planets, for checking how well the gravity integrator conserves it. Energy
+//! is only conserved while the same planets are moving freely, so drift is measured from the
+//! energy when the set of planets gravity applies to last changed: when a scenario starts, when
+//! planets merge, and when clusters fall asleep or wake.
+
+use bevy::prelude::*;
+use bevy_rapier3d::na::Vector3;
+use bevy_rapier3d::prelude::*;
+
+use crate::world::{ApplyGravity, GravityConstant, PendingPlanets};
+
+/// Energy of the bodies gravity applies to, updated every frame before the physics step.
+#[derive(Debug, Clone, Default)]
+pub struct EnergyDiagnostics {
+    /// Kinetic energy of the bodies' linear motion.
+    pub kinetic: f64,
+    /// Gravitational potential energy between every pair of bodies.
+    pub potential: f64,
+    /// Total energy when the bodies last changed, which drift is measured from.
+    baseline: Option<f64>,
+    /// Number of bodies when the baseline was taken.
+    bodies: usize,
+}
+
+impl EnergyDiagnostics {
+    /// Kinetic plus potential energy.
+    pub fn total(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+
+    /// Change in total energy since the bodies last changed, as a fraction of the energy then.
+    /// None until there is a baseline, or if the baseline is zero.
+    pub fn drift(&self) -> Option<f64> {
+        let baseline = self.baseline?;
+        if baseline == 0.0 {
+            return None;
+        }
+        Some((self.total() - baseline) / baseline.abs())
+    }
+
+    /// Records the energy of `bodies` bodies, taking a new baseline if the number of bodies
+    /// changed.
+    fn record(&mut self, kinetic: f64, potential: f64, bodies: usize) {
+        self.kinetic = kinetic;
+        self.potential = potential;
+        if self.baseline.is_none() || bodies != self.bodies {
+            self.baseline = Some(self.total());
+            self.bodies = bodies;
+        }
+    }
+}
+
+/// Updates the [`EnergyDiagnostics`]. There's no baseline while a world is still being spawned.
+pub fn update_energy(
+    mut energy: ResMut<EnergyDiagnostics>,
+    mut bodies: Local<Vec<(Vector3<f64>, Vector3<f64>, f64)>>,
+    query: Query<(&RigidBodyMassProps, &RigidBodyVelocity), With<ApplyGravity>>,
+    g: Res<GravityConstant>,
+    pending: Option<Res<PendingPlanets>>,
+) {
+    bodies.clear();
+    bodies.extend(query.iter().map(|(mass, velocity)| {
+        (
+            mass.world_com.coords.cast(),
+            velocity.linvel.cast(),
+            mass.mass() as f64,
+        )
+    }));
+    let (kinetic, potential) = energy_of(g.0 as f64, &bodies);
+    if pending.map_or(false, |pending| pending.is_spawning()) {
+        energy.baseline = None;
+    }
+    energy.record(kinetic, potential, bodies.len());
+}
+
+/// Kinetic and potential energy of bodies given as `(position, velocity, mass)`. Bodies on top of
+/// each other don't add to the potential energy, as they don't pull on each other either.
+fn energy_of(g: f64, bodies: &[(Vector3<f64>, Vector3<f64>, f64)]) -> (f64, f64) {
+    let kinetic = bodies
+        .iter()
+        .map(|(_, velocity, mass)| 0.5 * mass * velocity.norm_squared())
+        .sum();
+    let mut potential = 0.0;
+    for (i, (position, _, mass)) in bodies.iter().enumerate() {
+        for (other, _, other_mass) in &bodies[i + 1..] {
+            let pair = -g * mass * other_mass / (other - position).norm();
+            if pair.is_finite() {
+                potential += pair;
+            }
+        }
+    }
+    (kinetic, potential)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_kinetic_and_potential_energy() {
+        let bodies = [
+            (
+                Vector3::new(-1.0, 0.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                2.0,
+            ),
+            (
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, -2.0, 0.0),
+                1.0,
+            ),
+            (Vector3::new(1.0, 0.0, 0.0), Vector3::zeros(), 1.0),
+        ];
+        let (kinetic, potential) = energy_of(10.0, &bodies);
+        assert_eq!(kinetic, 3.0);
+        assert_eq!(potential, -20.0);
+    }
+
+    #[test]
+    fn measures_drift_from_when_bodies_change() {
+        let mut energy = EnergyDiagnostics::default();
+        assert_eq!(energy.drift(), None);
+        energy.record(10.0, -20.0, 3);
+        assert_eq!(energy.drift(), Some(0.0));
+        energy.record(12.0, -21.0, 3);
+        assert_eq!(energy.total(), -9.0);
+        assert_eq!(energy.drift(), Some(0.1));
+        energy.record(5.0, -15.0, 2);
+        assert_eq!(energy.drift(), Some(0.0));
+    }
+}
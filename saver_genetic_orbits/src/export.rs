@@ -0,0 +1,341 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exports a recording made with `--record` as an animated GIF, with the `export-gif <RECORDING>
+//! <GIF>` subcommand. Frames are drawn on the CPU rather than read back from the renderer, so
+//! exporting doesn't need a window or a GPU, and can run much faster than the recording plays.
+//! Planets are drawn as flat shaded discs lit from the origin, like the saver's light, without
+//! their surface bands or the skybox, at a lower resolution and frame rate than the saver runs at.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
+
+use bevy::prelude::*;
+use bevy::render::camera::PerspectiveProjection;
+use gif::{Encoder, Repeat};
+
+use crate::recording::{self, Frame, PlanetState, Record};
+use crate::world::ColorKey;
+
+/// Brightness of the side of planets facing away from the light.
+const AMBIENT: f32 = 0.15;
+
+/// What to export from a recording.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub width: u16,
+    pub height: u16,
+    /// Frames per second of the GIF.
+    pub fps: u16,
+    /// Only export the scenario at this index in the recording, counting from 0, rather than all
+    /// of them.
+    pub scenario: Option<usize>,
+    /// Stop after this many seconds of the GIF.
+    pub seconds: Option<f32>,
+}
+
+/// Parses a size given as `<WIDTH>x<HEIGHT>`, both more than zero.
+pub fn parse_size(size: &str) -> Option<(u16, u16)> {
+    let mut parts = size.splitn(2, 'x');
+    let width = parts.next()?.parse().ok().filter(|&width| width > 0)?;
+    let height = parts.next()?.parse().ok().filter(|&height| height > 0)?;
+    Some((width, height))
+}
+
+/// Exports the recording at `recording` as a GIF at `output`. Exits the process if it fails.
+pub fn run(recording: &Path, output: &Path, options: ExportOptions) {
+    match export(recording, output, &options) {
+        Ok(0) => {
+            eprintln!("No frames to export from {}", recording.display());
+            process::exit(1);
+        }
+        Ok(frames) => eprintln!("Exported {} frames to {}", frames, output.display()),
+        Err(err) => {
+            eprintln!(
+                "Unable to export {} to {}: {}",
+                recording.display(),
+                output.display(),
+                err
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Exports the recording, returning the number of frames in the GIF.
+fn export(recording: &Path, output: &Path, options: &ExportOptions) -> io::Result<usize> {
+    let mut reader = recording::open(recording)?;
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = Encoder::new(file, options.width, options.height, &[]).map_err(gif_error)?;
+    encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+
+    let rasterizer = Rasterizer::new(options.width, options.height);
+    let mut sampler = FrameSampler::new(options);
+    let mut pixels = vec![0; options.width as usize * options.height as usize * 3];
+    let delay = (100.0 / options.fps as f32).round() as u16;
+    while let Some(record) = reader.next_record()? {
+        let frame = match sampler.sample(record) {
+            Sample::Skip => continue,
+            Sample::Draw(frame) => frame,
+            Sample::Done => break,
+        };
+        rasterizer.draw(&frame, &mut pixels);
+        let mut gif_frame = gif::Frame::from_rgb_speed(options.width, options.height, &pixels, 10);
+        gif_frame.delay = delay;
+        encoder.write_frame(&gif_frame).map_err(gif_error)?;
+    }
+    Ok(sampler.drawn)
+}
+
+fn gif_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+/// What to do with a record from the recording.
+#[derive(Debug)]
+enum Sample {
+    /// Nothing to draw for it.
+    Skip,
+    /// Draw the frame as the next frame of the GIF.
+    Draw(Frame),
+    /// The GIF is complete.
+    Done,
+}
+
+/// Picks the frames of a recording to draw at the GIF's frame rate. Each frame of the GIF shows
+/// the first recorded frame at or after its time in the scenario.
+struct FrameSampler {
+    fps: f32,
+    /// Only scenario to sample, if any.
+    only: Option<usize>,
+    /// Most frames to draw.
+    limit: Option<usize>,
+    /// Index of the current scenario, or None before the first.
+    scenario: Option<usize>,
+    /// Number of frames of the current scenario's time drawn or skipped so far.
+    steps: u32,
+    /// Number of frames drawn so far.
+    drawn: usize,
+}
+
+impl FrameSampler {
+    fn new(options: &ExportOptions) -> Self {
+        Self {
+            fps: options.fps as f32,
+            only: options.scenario,
+            limit: options
+                .seconds
+                .map(|seconds| (seconds * options.fps as f32).ceil() as usize),
+            scenario: None,
+            steps: 0,
+            drawn: 0,
+        }
+    }
+
+    fn sample(&mut self, record: Record) -> Sample {
+        if self.limit.map_or(false, |limit| self.drawn >= limit) {
+            return Sample::Done;
+        }
+        let frame = match record {
+            Record::Scenario { .. } => {
+                let scenario = self.scenario.map_or(0, |scenario| scenario + 1);
+                self.scenario = Some(scenario);
+                self.steps = 0;
+                return match self.only {
+                    Some(only) if scenario > only => Sample::Done,
+                    _ => Sample::Skip,
+                };
+            }
+            Record::Frame(frame) => frame,
+        };
+        let wanted = self.only.map_or(true, |only| self.scenario == Some(only));
+        if !wanted || frame.time < self.steps as f32 / self.fps {
+            return Sample::Skip;
+        }
+        // Long recorded frames, such as while the saver was stalled, skip frames of the GIF.
+        while self.steps as f32 / self.fps <= frame.time {
+            self.steps += 1;
+        }
+        self.drawn += 1;
+        Sample::Draw(frame)
+    }
+}
+
+/// Draws frames as RGB pixels, seen through the saver's camera.
+struct Rasterizer {
+    width: u16,
+    height: u16,
+    /// Distance to the image plane, in pixels, for the saver's field of view.
+    focal: f32,
+}
+
+impl Rasterizer {
+    fn new(width: u16, height: u16) -> Self {
+        let fov = PerspectiveProjection::default().fov;
+        Self {
+            width,
+            height,
+            focal: height as f32 / 2.0 / (fov / 2.0).tan(),
+        }
+    }
+
+    /// Draws the frame over a black background.
+    fn draw(&self, frame: &Frame, pixels: &mut [u8]) {
+        for pixel in pixels.iter_mut() {
+            *pixel = 0;
+        }
+        let to_view = frame.camera_rotation.conjugate();
+        // The light is at the origin.
+        let light = to_view * -frame.camera_position;
+        let mut planets: Vec<_> = frame
+            .planets
+            .iter()
+            .map(|planet| (to_view * (planet.position - frame.camera_position), planet))
+            .filter(|(center, _)| center.z < 0.0)
+            .collect();
+        // Farthest first, so nearer planets are drawn over them.
+        planets.sort_by(|(a, _), (b, _)| a.z.partial_cmp(&b.z).unwrap());
+        for (center, planet) in planets {
+            self.draw_planet(center, light, planet, pixels);
+        }
+    }
+
+    /// Draws a planet centered at `center` in view space, lit from `light` in view space.
+    fn draw_planet(&self, center: Vec3, light: Vec3, planet: &PlanetState, pixels: &mut [u8]) {
+        let depth = -center.z;
+        let x = self.width as f32 / 2.0 + self.focal * center.x / depth;
+        let y = self.height as f32 / 2.0 - self.focal * center.y / depth;
+        let radius = self.focal * planet.radius / depth;
+        let color = ColorKey::from_index(planet.color)
+            .unwrap_or_default()
+            .color()
+            .as_rgba_f32();
+        let to_light = (light - center).normalize();
+
+        let rows = (y - radius).floor().max(0.0) as usize..(y + radius).ceil().max(0.0) as usize;
+        let columns = (x - radius).floor().max(0.0) as usize..(x + radius).ceil().max(0.0) as usize;
+        for row in rows.take_while(|&row| row < self.height as usize) {
+            for column in columns
+                .clone()
+                .take_while(|&column| column < self.width as usize)
+            {
+                let dx = (column as f32 + 0.5 - x) / radius;
+                let dy = (y - row as f32 - 0.5) / radius;
+                let squared = dx * dx + dy * dy;
+                if squared > 1.0 {
+                    continue;
+                }
+                let normal = Vec3::new(dx, dy, (1.0 - squared).sqrt());
+                let brightness = AMBIENT + (1.0 - AMBIENT) * normal.dot(to_light).max(0.0);
+                let offset = (row * self.width as usize + column) * 3;
+                for (pixel, channel) in pixels[offset..offset + 3].iter_mut().zip(&color) {
+                    *pixel = (channel * brightness * 255.0).round().min(255.0) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(scenario: Option<usize>, seconds: Option<f32>) -> ExportOptions {
+        ExportOptions {
+            width: 32,
+            height: 32,
+            fps: 10,
+            scenario,
+            seconds,
+        }
+    }
+
+    fn frame(time: f32) -> Record {
+        Record::Frame(Frame {
+            time,
+            ..Default::default()
+        })
+    }
+
+    /// Times of the frames drawn from a recording of two scenarios of a second each, recorded at
+    /// 60 frames per second.
+    fn sampled(options: &ExportOptions) -> Vec<(usize, f32)> {
+        let mut sampler = FrameSampler::new(options);
+        let mut drawn = Vec::new();
+        for _ in 0..2 {
+            let records = std::iter::once(Record::Scenario { parent: None })
+                .chain((0..60).map(|i| frame(i as f32 / 60.0)));
+            for record in records {
+                match sampler.sample(record) {
+                    Sample::Skip => {}
+                    Sample::Draw(frame) => drawn.push((sampler.scenario.unwrap(), frame.time)),
+                    Sample::Done => return drawn,
+                }
+            }
+        }
+        drawn
+    }
+
+    #[test]
+    fn samples_frames_at_the_frame_rate() {
+        let all = sampled(&options(None, None));
+        assert_eq!(all.len(), 20);
+        assert_eq!(all[0], (0, 0.0));
+        assert_eq!(all[1], (0, 6.0 / 60.0));
+        assert_eq!(all[10], (1, 0.0));
+
+        let second = sampled(&options(Some(1), None));
+        assert_eq!(second, all[10..]);
+
+        let short = sampled(&options(None, Some(0.25)));
+        assert_eq!(short, all[..3]);
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("320x180"), Some((320, 180)));
+        assert_eq!(parse_size("320"), None);
+        assert_eq!(parse_size("0x180"), None);
+        assert_eq!(parse_size("320x-1"), None);
+    }
+
+    #[test]
+    fn draws_planets_in_front_of_the_camera() {
+        let rasterizer = Rasterizer::new(32, 32);
+        let planet = |id, z| PlanetState {
+            id,
+            color: 0,
+            radius: 1.0,
+            position: Vec3::new(0.0, 0.0, z),
+            rotation: Quat::IDENTITY,
+        };
+        let frame = Frame {
+            camera_position: Vec3::new(0.0, 0.0, 10.0),
+            planets: vec![planet(0, -5.0), planet(1, 20.0)],
+            ..Default::default()
+        };
+        let mut pixels = vec![0; 32 * 32 * 3];
+        rasterizer.draw(&frame, &mut pixels);
+        let pixel = |x: usize, y: usize| &pixels[(y * 32 + x) * 3..][..3];
+        // Lit from behind the camera, so the middle of the planet is brightest.
+        assert!(pixel(16, 16)[0] > 200);
+        assert!(pixel(16, 16)[0] > pixel(14, 16)[0]);
+        assert_eq!(pixel(0, 0), [0, 0, 0]);
+    }
+}
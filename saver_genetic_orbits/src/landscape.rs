@@ -0,0 +1,270 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Samples the scoring landscape around a stored scenario, for research into how rugged it is. Run
+//! with the `landscape <SCENARIO>` subcommand. Each parameter of each planet is perturbed on its
+//! own across a grid of offsets, the perturbed world is simulated without rendering, and the scores
+//! are written to stdout as CSV.
+//!
+//! Positions and velocities are offset along one axis by a fraction of the planet's distance from
+//! the origin or speed, and masses are scaled by one plus the offset. Headless simulations score
+//! once per physics step, so scores are what the saver would give at the physics rate, and can
+//! differ a little from the stored score of a scenario which was shown at a different frame rate.
+
+use std::process;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::bound_pairs::{Body, BoundPairs};
+use crate::config;
+use crate::config::physics::PhysicsConfig;
+use crate::config::scoring::ScoringConfig;
+use crate::model::{Planet as PlanetConfig, World};
+use crate::statustracker;
+use crate::storage::{self, Storage};
+use crate::world::{planet_groups, GravityConstant, GravityPlugin, Planet, PlanetBodyBundle};
+
+/// Parameters of a planet which can be perturbed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Parameter {
+    PositionX,
+    PositionY,
+    PositionZ,
+    VelocityX,
+    VelocityY,
+    VelocityZ,
+    Mass,
+}
+
+const PARAMETERS: [Parameter; 7] = [
+    Parameter::PositionX,
+    Parameter::PositionY,
+    Parameter::PositionZ,
+    Parameter::VelocityX,
+    Parameter::VelocityY,
+    Parameter::VelocityZ,
+    Parameter::Mass,
+];
+
+impl Parameter {
+    fn name(self) -> &'static str {
+        match self {
+            Parameter::PositionX => "position.x",
+            Parameter::PositionY => "position.y",
+            Parameter::PositionZ => "position.z",
+            Parameter::VelocityX => "velocity.x",
+            Parameter::VelocityY => "velocity.y",
+            Parameter::VelocityZ => "velocity.z",
+            Parameter::Mass => "mass",
+        }
+    }
+
+    /// Applies the offset to this parameter of the planet, and returns the parameter's new value.
+    fn perturb(self, planet: &mut PlanetConfig, offset: f32) -> f32 {
+        let distance = planet.position.length();
+        let speed = planet.velocity.length();
+        let (value, scale) = match self {
+            Parameter::PositionX => (&mut planet.position.x, distance),
+            Parameter::PositionY => (&mut planet.position.y, distance),
+            Parameter::PositionZ => (&mut planet.position.z, distance),
+            Parameter::VelocityX => (&mut planet.velocity.x, speed),
+            Parameter::VelocityY => (&mut planet.velocity.y, speed),
+            Parameter::VelocityZ => (&mut planet.velocity.z, speed),
+            Parameter::Mass => {
+                planet.mass *= 1.0 + offset;
+                return planet.mass;
+            }
+        };
+        *value += offset * scale;
+        *value
+    }
+}
+
+/// Evenly spaced offsets from `-spread` to `spread`. A single sample is just the unperturbed
+/// value.
+fn offsets(samples: u32, spread: f32) -> Vec<f32> {
+    if samples <= 1 {
+        return vec![0.0];
+    }
+    (0..samples)
+        .map(|i| spread * (2.0 * i as f32 / (samples - 1) as f32 - 1.0))
+        .collect()
+}
+
+/// Samples the landscape around the stored scenario with the given id, writing CSV to stdout.
+/// Only perturbs the planet at index `only_planet` if given. Exits the process if there is nothing
+/// to sample.
+pub fn run(scenario: u64, samples: u32, spread: f32, only_planet: Option<usize>) {
+    let mut storage = match storage::open_configured() {
+        Some(storage) => storage,
+        None => {
+            eprintln!("No scenario database to read from");
+            process::exit(1);
+        }
+    };
+    let scenario = match storage
+        .get_scenario(scenario)
+        .expect("Unable to read scenario from storage")
+    {
+        Some(scenario) => scenario,
+        None => {
+            eprintln!("No scenario {} stored", scenario);
+            process::exit(1);
+        }
+    };
+    let planets = scenario.world.planets.len();
+    let planet_range = match only_planet {
+        Some(planet) if planet < planets => planet..planet + 1,
+        Some(planet) => {
+            eprintln!("Scenario {} has no planet {}", scenario.id, planet);
+            process::exit(1);
+        }
+        None => 0..planets,
+    };
+    let figment = config::load();
+    let scoring = figment
+        .extract::<ScoringConfig>()
+        .expect("Unable to load scoring config");
+    let physics = figment
+        .extract::<PhysicsConfig>()
+        .expect("Unable to load physics config");
+
+    let offsets = offsets(samples, spread);
+    let total = planet_range.len() * PARAMETERS.len() * offsets.len();
+    let baseline = simulate(&scenario.world, &scoring, &physics);
+    eprintln!(
+        "Scenario {} scores {:.2} unperturbed (stored score {:.2})",
+        scenario.id, baseline, scenario.score
+    );
+
+    println!("planet,parameter,offset,value,score");
+    println!(",none,0,,{}", baseline);
+    let mut done = 0;
+    for planet in planet_range {
+        for &parameter in &PARAMETERS {
+            for &offset in &offsets {
+                let mut world = scenario.world.clone();
+                let value = parameter.perturb(&mut world.planets[planet], offset);
+                let score = if offset == 0.0 {
+                    baseline
+                } else {
+                    world.merge_overlapping_planets();
+                    simulate(&world, &scoring, &physics)
+                };
+                println!(
+                    "{},{},{},{},{}",
+                    planet,
+                    parameter.name(),
+                    offset,
+                    value,
+                    score
+                );
+                done += 1;
+                eprintln!("Simulated {}/{}", done, total);
+            }
+        }
+    }
+}
+
+/// Simulates the world without rendering for the configured scored time, and returns its score.
+pub fn simulate(world: &World, scoring: &ScoringConfig, physics: &PhysicsConfig) -> f64 {
+    let mut app = App::build();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .insert_resource(physics.clone())
+        .add_plugin(GravityPlugin);
+    let mut app = app.app;
+    let groups = planet_groups(physics);
+    for planet in &world.planets {
+        app.world
+            .spawn()
+            .insert_bundle(PlanetBodyBundle::new_from_planet(
+                planet,
+                &world.radius_law,
+                groups,
+            ));
+    }
+
+    let dt = app
+        .world
+        .get_resource::<IntegrationParameters>()
+        .expect("Rapier adds its integration parameters")
+        .dt as f64;
+    let steps = (scoring.scored_time.as_secs_f64() / dt).round() as u64;
+    let g = app
+        .world
+        .get_resource::<GravityConstant>()
+        .expect("The gravity plugin adds the gravity constant")
+        .0;
+    let mut bodies = app
+        .world
+        .query_filtered::<&RigidBodyMassProps, With<Planet>>();
+    let mut pair_bodies = app
+        .world
+        .query_filtered::<(Entity, &RigidBodyMassProps, &RigidBodyVelocity), With<Planet>>();
+    let mut bound_pairs = BoundPairs::default();
+    let mut score = 0.0;
+    for step in 0..steps {
+        // Rapier takes one step of `dt` per update.
+        app.update();
+        if scoring.score_per_second.uses_bound_pairs() {
+            let in_area = Body::in_scored_area(scoring, pair_bodies.iter(&app.world));
+            bound_pairs.update(g, &world.radius_law, &in_area, dt as f32);
+        }
+        let scenario_time = (step + 1) as f64 / steps as f64;
+        score += statustracker::score_per_second(
+            scoring,
+            scenario_time,
+            bodies.iter(&app.world),
+            &bound_pairs,
+        ) * dt;
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn offsets_span_spread() {
+        assert_eq!(offsets(1, 0.5), vec![0.0]);
+        assert_eq!(offsets(5, 0.5), vec![-0.5, -0.25, 0.0, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn perturbs_relative_to_magnitude() {
+        let planet = PlanetConfig {
+            position: Vec3::new(30.0, 40.0, 0.0),
+            velocity: Vec3::new(0.0, 0.0, 2.0),
+            mass: 10.0,
+            spin: Vec3::ZERO,
+        };
+
+        let mut perturbed = planet.clone();
+        assert_eq!(Parameter::PositionZ.perturb(&mut perturbed, 0.1), 5.0);
+        assert_eq!(perturbed.position, Vec3::new(30.0, 40.0, 5.0));
+
+        let mut perturbed = planet.clone();
+        assert_eq!(Parameter::VelocityX.perturb(&mut perturbed, -0.5), -1.0);
+        assert_eq!(perturbed.velocity, Vec3::new(-1.0, 0.0, 2.0));
+
+        let mut perturbed = planet.clone();
+        assert_eq!(Parameter::Mass.perturb(&mut perturbed, -0.5), 5.0);
+        assert_eq!(perturbed.position, planet.position);
+    }
+}
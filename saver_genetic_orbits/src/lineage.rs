@@ -0,0 +1,210 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exports the lineage of a family of scenarios from the database, for rendering evolution trees.
+//! Run with the `export-family <FAMILY>` subcommand, optionally with `--format json`; the default
+//! is a Graphviz DOT graph which can be rendered with e.g. `dot -Tsvg`.
+//!
+//! The database doesn't record how each child was mutated, so each scenario is described by how
+//! its planets and score compare to its parent's. Parents which have been pruned are shown as
+//! dashed placeholders.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::process;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::model::Scenario;
+use crate::storage::{self, Storage};
+
+/// Output formats for a lineage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineageFormat {
+    Dot,
+    Json,
+}
+
+impl FromStr for LineageFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, String> {
+        match format {
+            "dot" => Ok(LineageFormat::Dot),
+            "json" => Ok(LineageFormat::Json),
+            _ => Err(format!("expected dot or json, got {:?}", format)),
+        }
+    }
+}
+
+/// Prints the lineage of `family` from the configured database to stdout. Exits the process if
+/// there is nothing to export.
+pub fn run(family: u64, format: LineageFormat) {
+    let mut storage = match storage::open_configured() {
+        Some(storage) => storage,
+        None => {
+            eprintln!("No scenario database to export from");
+            process::exit(1);
+        }
+    };
+    let scenarios = storage
+        .get_family(family)
+        .expect("Unable to read family from storage");
+    if scenarios.is_empty() {
+        eprintln!("No scenarios stored in family {}", family);
+        process::exit(1);
+    }
+    match format {
+        LineageFormat::Dot => print!("{}", to_dot(family, &scenarios)),
+        LineageFormat::Json => println!("{:#}", to_json(&scenarios)),
+    }
+}
+
+/// Total mass of a scenario's planets.
+fn total_mass(scenario: &Scenario) -> f64 {
+    scenario
+        .world
+        .planets
+        .iter()
+        .map(|planet| planet.mass as f64)
+        .sum()
+}
+
+/// Renders the scenarios as a Graphviz digraph with an edge from each parent to its children.
+pub fn to_dot(family: u64, scenarios: &[Scenario]) -> String {
+    let stored: HashSet<u64> = scenarios.iter().map(|scenario| scenario.id).collect();
+    let mut dot = String::new();
+    writeln!(dot, "digraph family_{} {{", family).unwrap();
+    writeln!(dot, "    node [shape=box];").unwrap();
+    let mut pruned = HashSet::new();
+    for scenario in scenarios {
+        writeln!(
+            dot,
+            "    {} [label=\"#{}\\ngeneration {}\\nscore {:.2}\\n{} planets, mass {:.1}\"];",
+            scenario.id,
+            scenario.id,
+            scenario.generation,
+            scenario.score,
+            scenario.world.planets.len(),
+            total_mass(scenario),
+        )
+        .unwrap();
+        if let Some(parent) = scenario.parent {
+            if !stored.contains(&parent) && pruned.insert(parent) {
+                writeln!(
+                    dot,
+                    "    {} [label=\"#{}\\n(pruned)\", style=dashed];",
+                    parent, parent
+                )
+                .unwrap();
+            }
+            writeln!(dot, "    {} -> {};", parent, scenario.id).unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Renders the scenarios as a JSON array, one object per scenario.
+pub fn to_json(scenarios: &[Scenario]) -> Value {
+    let by_id: HashMap<u64, &Scenario> = scenarios
+        .iter()
+        .map(|scenario| (scenario.id, scenario))
+        .collect();
+    scenarios
+        .iter()
+        .map(|scenario| {
+            let parent = scenario.parent.and_then(|parent| by_id.get(&parent));
+            json!({
+                "id": scenario.id,
+                "family": scenario.family,
+                "parent": scenario.parent,
+                "generation": scenario.generation,
+                "score": scenario.score,
+                "planets": scenario.world.planets.len(),
+                "total_mass": total_mass(scenario),
+                "parent_pruned": scenario.parent.is_some() && parent.is_none(),
+                "score_change": parent.map(|parent| scenario.score - parent.score),
+                "planet_change": parent.map(|parent| {
+                    scenario.world.planets.len() as i64 - parent.world.planets.len() as i64
+                }),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+    use crate::model::{Planet, World};
+
+    fn scenario(id: u64, parent: Option<u64>, generation: u64, planets: usize) -> Scenario {
+        Scenario {
+            id,
+            family: 1,
+            parent,
+            generation,
+            world: World {
+                planets: vec![
+                    Planet {
+                        position: Vec3::ZERO,
+                        velocity: Vec3::ZERO,
+                        mass: 2.,
+                        spin: Vec3::ZERO,
+                    };
+                    planets
+                ],
+                ..Default::default()
+            },
+            score: id as f64 * 10.,
+            stability: None,
+        }
+    }
+
+    #[test]
+    fn dot_links_parents_and_marks_pruned() {
+        let scenarios = vec![
+            scenario(1, None, 0, 2),
+            scenario(2, Some(1), 1, 3),
+            scenario(4, Some(3), 2, 3),
+        ];
+        let dot = to_dot(1, &scenarios);
+        assert!(dot.starts_with("digraph family_1 {\n"));
+        assert!(dot.contains("    2 [label=\"#2\\ngeneration 1\\nscore 20.00\\n3 planets"));
+        assert!(dot.contains("    1 -> 2;\n"));
+        assert!(dot.contains("    3 [label=\"#3\\n(pruned)\", style=dashed];\n"));
+        assert!(dot.contains("    3 -> 4;\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn json_compares_to_parent() {
+        let scenarios = vec![
+            scenario(1, None, 0, 2),
+            scenario(2, Some(1), 1, 3),
+            scenario(4, Some(3), 2, 3),
+        ];
+        let json = to_json(&scenarios);
+        assert_eq!(json[0]["parent"], Value::Null);
+        assert_eq!(json[0]["parent_pruned"], false);
+        assert_eq!(json[1]["score_change"], 10.);
+        assert_eq!(json[1]["planet_change"], 1);
+        assert_eq!(json[1]["total_mass"], 6.);
+        assert_eq!(json[2]["parent_pruned"], true);
+        assert_eq!(json[2]["score_change"], Value::Null);
+    }
+}
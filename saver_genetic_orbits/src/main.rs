@@ -12,38 +12,401 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use bevy::app::PluginGroupBuilder;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use bevy_skybox_cubemap::SkyboxPlugin;
+use xsecurelock_saver::cli::{Arg, Cli, SubCommand};
 use xsecurelock_saver::engine::XSecurelockSaverPlugins;
 
+mod asteroids;
+#[cfg(feature = "audio-out")]
+mod audio;
+mod barnes_hut;
+mod bound_pairs;
+mod collisions;
+mod compensated;
 mod config;
+#[cfg(feature = "configure")]
+mod configure;
+mod determinism;
+#[cfg(feature = "devtools")]
+mod devtools;
+mod diff;
+mod energy;
+mod export;
+mod landscape;
+mod lineage;
 mod model;
+mod potential_field;
+mod probes;
+mod recording;
+mod scripted;
 mod skyboxes;
+mod sleep;
+mod soak;
 mod statustracker;
 mod storage;
+#[cfg(feature = "otlp")]
+mod telemetry;
+mod trails;
 mod world;
+mod world_events;
 mod worldgenerator;
 
 fn main() {
-    App::build()
-        .insert_resource(Msaa { samples: 4 })
+    let cli = Cli::new(config::help())
+        .about("Screensaver which evolves planetary systems to find interesting orbits.")
+        .own_config(
+            "YAML config file, merged over the config files in the user's config and home \
+             directories.",
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("PATH")
+                .help(
+                    "Records every scenario the saver shows to this file, replacing it, for \
+                     playing back with the play subcommand.",
+                ),
+        );
+    #[cfg(feature = "configure")]
+    let cli = cli.subcommand(
+        SubCommand::with_name("configure")
+            .about("Opens a window for editing the config instead of running the saver."),
+    );
+    let (_, args) = cli
+        .subcommand(
+            SubCommand::with_name("export-family")
+                .about("Prints the lineage of a family of scenarios from the database.")
+                .arg(
+                    Arg::with_name("FAMILY")
+                        .required(true)
+                        .validator(is_id)
+                        .help("Family to export."),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["dot", "json"])
+                        .default_value("dot")
+                        .help("Output format: a Graphviz graph or JSON."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about(
+                    "Prints how the planets of one stored scenario differ from another's, such as \
+                     a parent and its child.",
+                )
+                .arg(
+                    Arg::with_name("FROM")
+                        .required(true)
+                        .validator(is_id)
+                        .help("Scenario to compare from."),
+                )
+                .arg(
+                    Arg::with_name("TO")
+                        .required(true)
+                        .validator(is_id)
+                        .help("Scenario to compare to."),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Output format."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("landscape")
+                .about(
+                    "Simulates perturbations of each planet of a stored scenario without \
+                     rendering, and prints their scores as CSV.",
+                )
+                .arg(
+                    Arg::with_name("SCENARIO")
+                        .required(true)
+                        .validator(is_id)
+                        .help("Scenario to perturb."),
+                )
+                .arg(
+                    Arg::with_name("samples")
+                        .long("samples")
+                        .value_name("N")
+                        .default_value("11")
+                        .validator(|samples| match samples.parse::<u32>() {
+                            Ok(samples) if samples > 0 => Ok(()),
+                            Ok(_) => Err("must be at least 1".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        })
+                        .help("Number of offsets to try for each parameter."),
+                )
+                .arg(
+                    Arg::with_name("spread")
+                        .long("spread")
+                        .value_name("FRACTION")
+                        .default_value("0.1")
+                        .validator(|spread| match spread.parse::<f32>() {
+                            Ok(spread) if spread > 0.0 && spread < 1.0 => Ok(()),
+                            Ok(_) => Err("must be between 0 and 1".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        })
+                        .help(
+                            "Largest offset to try, as a fraction of each planet's distance from \
+                             the origin, speed, or mass.",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("planet")
+                        .long("planet")
+                        .value_name("INDEX")
+                        .validator(|planet| {
+                            planet
+                                .parse::<usize>()
+                                .map(drop)
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("Only perturb the planet at this index."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("soak")
+                .about(
+                    "Runs the saver headless with a scratch database, checking for leaks and slow \
+                     frames.",
+                )
+                .arg(
+                    Arg::with_name("CYCLES")
+                        .required(true)
+                        .validator(|cycles| match cycles.parse::<u64>() {
+                            Ok(cycles) if cycles > 0 => Ok(()),
+                            Ok(_) => Err("must be at least 1".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        })
+                        .help("Number of scenarios to run."),
+                )
+                .arg(
+                    Arg::with_name("frame-budget")
+                        .long("frame-budget")
+                        .value_name("MS")
+                        .default_value("16")
+                        .validator(|budget| match budget.parse::<f64>() {
+                            Ok(budget) if budget > 0.0 => Ok(()),
+                            Ok(_) => Err("must be more than 0".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        })
+                        .help("Longest average frame time allowed in any scenario."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("play")
+                .about("Plays back a recording made with --record, over and over.")
+                .arg(
+                    Arg::with_name("RECORDING")
+                        .required(true)
+                        .help("Recording to play."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export-gif")
+                .about("Exports a recording made with --record as an animated GIF.")
+                .arg(
+                    Arg::with_name("RECORDING")
+                        .required(true)
+                        .help("Recording to export."),
+                )
+                .arg(
+                    Arg::with_name("GIF")
+                        .required(true)
+                        .help("GIF file to write."),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .value_name("WIDTHxHEIGHT")
+                        .default_value("320x180")
+                        .validator(|size| match export::parse_size(&size) {
+                            Some(_) => Ok(()),
+                            None => Err("must be a width and height, like 320x180".to_string()),
+                        })
+                        .help("Size of the GIF, in pixels."),
+                )
+                .arg(
+                    Arg::with_name("fps")
+                        .long("fps")
+                        .value_name("FPS")
+                        .default_value("15")
+                        .validator(|fps| match fps.parse::<u16>() {
+                            Ok(fps) if fps > 0 && fps <= 100 => Ok(()),
+                            Ok(_) => Err("must be between 1 and 100".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        })
+                        .help("Frames per second of the GIF."),
+                )
+                .arg(
+                    Arg::with_name("scenario")
+                        .long("scenario")
+                        .value_name("INDEX")
+                        .validator(|scenario| {
+                            scenario
+                                .parse::<usize>()
+                                .map(drop)
+                                .map_err(|err| err.to_string())
+                        })
+                        .help(
+                            "Only exports the scenario at this index in the recording, counting \
+                             from 0.",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("seconds")
+                        .long("seconds")
+                        .value_name("SECONDS")
+                        .validator(|seconds| match seconds.parse::<f32>() {
+                            Ok(seconds) if seconds > 0.0 => Ok(()),
+                            Ok(_) => Err("must be more than 0".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        })
+                        .help("Longest GIF to make."),
+                ),
+        )
+        .get_matches();
+
+    // The validators and possible_values ensure all the values parse.
+    match args.subcommand() {
+        (_, None) => {}
+        _ if args.is_present("record") => {
+            eprintln!("--record only applies when running the saver, not with a subcommand");
+            process::exit(2);
+        }
+        #[cfg(feature = "configure")]
+        ("configure", Some(_)) => {
+            configure::run();
+            return;
+        }
+        ("export-family", Some(args)) => {
+            let family = args.value_of("FAMILY").unwrap().parse().unwrap();
+            let format = args.value_of("format").unwrap().parse().unwrap();
+            lineage::run(family, format);
+            return;
+        }
+        ("diff", Some(args)) => {
+            let from = args.value_of("FROM").unwrap().parse().unwrap();
+            let to = args.value_of("TO").unwrap().parse().unwrap();
+            let format = args.value_of("format").unwrap().parse().unwrap();
+            diff::run(from, to, format);
+            return;
+        }
+        ("landscape", Some(args)) => {
+            let scenario = args.value_of("SCENARIO").unwrap().parse().unwrap();
+            let samples = args.value_of("samples").unwrap().parse().unwrap();
+            let spread = args.value_of("spread").unwrap().parse().unwrap();
+            let planet = args
+                .value_of("planet")
+                .map(|planet| planet.parse().unwrap());
+            landscape::run(scenario, samples, spread, planet);
+            return;
+        }
+        ("soak", Some(args)) => {
+            let cycles = args.value_of("CYCLES").unwrap().parse().unwrap();
+            let budget: f64 = args.value_of("frame-budget").unwrap().parse().unwrap();
+            soak::run(cycles, Duration::from_secs_f64(budget / 1000.0));
+            return;
+        }
+        ("play", Some(args)) => {
+            recording::run(Path::new(args.value_of_os("RECORDING").unwrap()));
+            return;
+        }
+        ("export-gif", Some(args)) => {
+            let recording = Path::new(args.value_of_os("RECORDING").unwrap());
+            let output = Path::new(args.value_of_os("GIF").unwrap());
+            let (width, height) = export::parse_size(args.value_of("size").unwrap()).unwrap();
+            let options = export::ExportOptions {
+                width,
+                height,
+                fps: args.value_of("fps").unwrap().parse().unwrap(),
+                scenario: args
+                    .value_of("scenario")
+                    .map(|scenario| scenario.parse().unwrap()),
+                seconds: args
+                    .value_of("seconds")
+                    .map(|seconds| seconds.parse().unwrap()),
+            };
+            export::run(recording, output, options);
+            return;
+        }
+        (name, Some(_)) => unreachable!("unknown subcommand {}", name),
+    }
+
+    let mut app = App::build();
+    app.insert_resource(Msaa { samples: 4 })
         .add_plugins(XSecurelockSaverPlugins)
-        .add_plugin(SkyboxPlugin)
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_plugin(config::ConfigPlugin)
-        .add_state(SaverState::Generate)
-        .add_plugin(storage::StoragePlugin)
-        .add_plugin(worldgenerator::WorldGeneratorPlugin)
-        .add_plugin(statustracker::ScoringPlugin)
-        .add_plugin(world::WorldPlugin)
-        .add_plugin(skyboxes::SkyboxesPlugin)
-        .run();
+        .add_plugins(SaverPlugins);
+    if let Some(path) = args.value_of_os("record") {
+        app.add_plugin(recording::RecorderPlugin { path: path.into() });
+    }
+    app.run();
+}
+
+/// Validates the id of a stored scenario or family.
+fn is_id(id: String) -> Result<(), String> {
+    id.parse::<u64>().map(drop).map_err(|err| err.to_string())
+}
+
+/// The saver's own plugins, added after the engine's. Shared with the soak subcommand, so that it
+/// runs the same app as the saver.
+struct SaverPlugins;
+
+impl PluginGroup for SaverPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(SkyboxPlugin)
+            .add(RapierPhysicsPlugin::<NoUserData>::default())
+            .add(config::ConfigPlugin)
+            .add(SaverStatePlugin)
+            .add(storage::StoragePlugin)
+            .add(worldgenerator::WorldGeneratorPlugin)
+            .add(statustracker::ScoringPlugin)
+            .add(world::WorldPlugin)
+            .add(determinism::DeterminismPlugin)
+            .add(sleep::SleepPlugin)
+            .add(collisions::CollisionsPlugin)
+            .add(world_events::WorldEventsPlugin)
+            .add(potential_field::PotentialFieldPlugin)
+            .add(probes::ProbesPlugin)
+            .add(trails::TrailsPlugin)
+            .add(bound_pairs::BoundPairsPlugin)
+            .add(asteroids::AsteroidsPlugin)
+            .add(skyboxes::SkyboxesPlugin);
+        #[cfg(feature = "audio-out")]
+        group.add(audio::AudioPlugin);
+        #[cfg(feature = "devtools")]
+        group.add(devtools::DevtoolsPlugin);
+        #[cfg(feature = "otlp")]
+        group.add(telemetry::TelemetryPlugin);
+    }
+}
+
+/// Adds the [`SaverState`], starting by generating a scenario.
+struct SaverStatePlugin;
+
+impl Plugin for SaverStatePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_state(SaverState::Generate);
+    }
 }
 
 /// Game state of the generator.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-enum SaverState {
+pub enum SaverState {
     /// Loading state, world will be replaced.
     Generate,
     /// Run the game.
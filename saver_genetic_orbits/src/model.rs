@@ -14,11 +14,15 @@
 
 //! Model of the start-state of the world. Identifies a unique world.
 use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
 
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use crate::config::util::deserialize_positive;
+
+#[derive(Debug, Clone)]
 pub struct Scenario {
     /// The name of this scenario.
     pub id: u64,
@@ -33,11 +37,115 @@ pub struct Scenario {
     pub world: World,
     /// The score that this world earned when tested.
     pub score: f64,
+    /// How the world's orbits behaved when tested. None for scenarios stored before scenarios were
+    /// classified.
+    pub stability: Option<Stability>,
+}
+
+/// The world at the moment a scenario was scoring fastest, so a replay can start from its most
+/// interesting point rather than from its initial state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peak {
+    /// Seconds into the scored time when the world was captured.
+    pub seconds: f64,
+    /// The planets as they were at that moment.
+    pub world: World,
+}
+
+/// Rough classification of how a scenario's orbits behave.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stability {
+    /// Several bodies of comparable mass tugging each other around.
+    Chaotic,
+    /// A heavy primary with much lighter bodies orbiting it.
+    Hierarchical,
+    /// Nearly all of the mass has ended up in a single body.
+    SingleDominant,
+    /// Much of the mass has escaped the scored area.
+    Dispersing,
+}
+
+impl Stability {
+    /// Every classification, in order.
+    pub const ALL: [Stability; 4] = [
+        Stability::Chaotic,
+        Stability::Hierarchical,
+        Stability::SingleDominant,
+        Stability::Dispersing,
+    ];
+
+    /// The name of the classification, as used in configs and the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stability::Chaotic => "chaotic",
+            Stability::Hierarchical => "hierarchical",
+            Stability::SingleDominant => "single-dominant",
+            Stability::Dispersing => "dispersing",
+        }
+    }
+}
+
+impl fmt::Display for Stability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Stability {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        Stability::ALL
+            .iter()
+            .copied()
+            .find(|stability| stability.as_str() == name)
+            .ok_or_else(|| {
+                format!(
+                    "expected chaotic, hierarchical, single-dominant or dispersing, got {:?}",
+                    name
+                )
+            })
+    }
+}
+
+/// Selects scenarios by their stability classification.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct StabilityFilter {
+    /// If not empty, only scenarios with one of these classifications match. Scenarios which
+    /// haven't been classified don't match.
+    pub only: Vec<Stability>,
+    /// Scenarios with any of these classifications don't match.
+    pub exclude: Vec<Stability>,
+}
+
+impl StabilityFilter {
+    /// Whether every scenario matches.
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether a scenario with the given classification matches.
+    pub fn matches(&self, stability: Option<Stability>) -> bool {
+        match stability {
+            Some(stability) => {
+                (self.only.is_empty() || self.only.contains(&stability))
+                    && !self.exclude.contains(&stability)
+            }
+            None => self.only.is_empty(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
 pub struct World {
     pub planets: Vec<Planet>,
+    /// How big the planets are for their mass. Worlds stored before the law was configurable
+    /// have the default law, and it is only written if it isn't the default, so their stored
+    /// form doesn't change.
+    #[serde(default, skip_serializing_if = "RadiusLaw::is_default")]
+    pub radius_law: RadiusLaw,
 }
 
 impl World {
@@ -51,7 +159,8 @@ impl World {
             while left < self.planets.len() - 1 {
                 let mut right = left + 1;
                 while right < self.planets.len() {
-                    let total_radius = self.planets[left].radius() + self.planets[right].radius();
+                    let total_radius = self.radius_law.radius(self.planets[left].mass)
+                        + self.radius_law.radius(self.planets[right].mass);
                     let total_radius_sqr = total_radius * total_radius;
                     let dist_sqr = self.planets[left]
                         .position
@@ -79,7 +188,7 @@ impl World {
         assert!(right < self.planets.len());
         {
             let (left_sub, right_sub) = self.planets.split_at_mut(right);
-            left_sub[left].merge(&right_sub[0]);
+            left_sub[left].merge(&right_sub[0], &self.radius_law);
         }
         self.planets.remove(right);
     }
@@ -90,38 +199,16 @@ pub struct Planet {
     pub position: Vec3,
     pub velocity: Vec3,
     pub mass: f32,
+    /// Angular velocity, in radians per second about each axis. Worlds stored before planets
+    /// spun have planets which don't.
+    #[serde(default)]
+    pub spin: Vec3,
 }
 
 impl Planet {
-    /// Assumed density of planets.
-    pub const DENSITY: f32 = 0.1;
-
-    /// Calculates the radius for a planet of the given mass.
-    pub fn radius_from_mass(mass: f32) -> f32 {
-        // Calculate radius as if this planet were a sphere with the given mass and density:
-        // V = 4/3 * pi * r^3
-        // M = V * D
-        // M = 4/3 * pi * r^3 * D
-        // 3M / (4 * pi * D) = r^3
-        (3. * mass / (4.0 * PI * Self::DENSITY)).cbrt()
-    }
-
-    /// Calculates the radius of this planet.
-    pub fn radius(&self) -> f32 {
-        Self::radius_from_mass(self.mass)
-    }
-
-    /// Updates the mass so the planet has the given radius.
-    #[allow(dead_code)]
-    pub fn set_radius(&mut self, radius: f32) {
-        // V = 4/3 * pi * r^3
-        // M = V * D
-        // M = 4/3 * pi * r^3 * D
-        self.mass = 4. / 3. * PI * radius.powi(3) * Self::DENSITY;
-    }
-
-    /// Merges the given other planet into this one.
-    fn merge(&mut self, other: &Planet) {
+    /// Merges the given other planet into this one. Both planets are sized by `law`, which gives
+    /// their moments of inertia.
+    fn merge(&mut self, other: &Planet, law: &RadiusLaw) {
         let total_mass = self.mass + other.mass;
         // multiplying by mass may give less precision, maybe? So pre-calculate multiplication
         // factors.
@@ -131,9 +218,75 @@ impl Planet {
         let net_position = self.position * self_factor + other.position * other_factor;
         // Equivalent to calculating total momentum and dividing by mass.
         let net_velocity = self.velocity * self_factor + other.velocity * other_factor;
+        // The merged planet keeps the angular momentum around the center of mass, both from the
+        // planets' own spin and from their motion around each other.
+        let angular_momentum = [&*self, other].iter().fold(Vec3::ZERO, |total, planet| {
+            total
+                + planet.spin * law.moment_of_inertia(planet.mass)
+                + (planet.position - net_position).cross(planet.velocity - net_velocity)
+                    * planet.mass
+        });
         self.position = net_position;
         self.velocity = net_velocity;
         self.mass = total_mass;
+        self.spin = angular_momentum / law.moment_of_inertia(total_mass);
+    }
+}
+
+/// How the radius of a planet follows from its mass: `r = (3M / (4 * pi * D)) ^ exponent`. With
+/// the default exponent of 1/3, planets are solid spheres of density `D`. Smaller exponents make
+/// heavy planets relatively smaller, and larger ones make them relatively bigger.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RadiusLaw {
+    /// Density of planets. Defaults to 0.1.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub density: f32,
+    /// Exponent relating the mass to the radius. Defaults to 1/3.
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub exponent: f32,
+}
+
+impl Default for RadiusLaw {
+    fn default() -> Self {
+        Self {
+            density: 0.1,
+            exponent: 1. / 3.,
+        }
+    }
+}
+
+impl RadiusLaw {
+    /// Calculates the radius for a planet of the given mass.
+    pub fn radius(&self, mass: f32) -> f32 {
+        // For a sphere with the given mass and density:
+        // V = 4/3 * pi * r^3
+        // M = V * D
+        // M = 4/3 * pi * r^3 * D
+        // 3M / (4 * pi * D) = r^3
+        (3. * mass / (4.0 * PI * self.density)).powf(self.exponent)
+    }
+
+    /// Calculates the mass for a planet of the given radius.
+    #[allow(dead_code)]
+    pub fn mass(&self, radius: f32) -> f32 {
+        4. / 3. * PI * self.density * radius.powf(self.exponent.recip())
+    }
+
+    /// Moment of inertia of a planet of the given mass, which spins like a solid ball of its
+    /// radius, the same as its collider.
+    pub fn moment_of_inertia(&self, mass: f32) -> f32 {
+        0.4 * mass * self.radius(mass).powi(2)
+    }
+
+    /// Density a ball of the radius for the given mass needs to have that mass. The same as
+    /// `density` unless the exponent has been changed from 1/3.
+    pub fn ball_density(&self, mass: f32) -> f32 {
+        mass / (4. / 3. * PI * self.radius(mass).powi(3))
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Self::default()
     }
 }
 
@@ -150,18 +303,21 @@ mod tests {
                 position: Vec3::new(0., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             };
             let right = Planet {
                 position: Vec3::new(1., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             };
             let expected = Planet {
                 position: Vec3::new(0.5, 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 2.,
+                spin: Vec3::ZERO,
             };
-            left.merge(&right);
+            left.merge(&right, &RadiusLaw::default());
             assert_eq!(left, expected);
         }
 
@@ -171,18 +327,21 @@ mod tests {
                 position: Vec3::new(1., -5., 0.),
                 velocity: Vec3::new(3., 6., 0.),
                 mass: 8.,
+                spin: Vec3::ZERO,
             };
             let right = Planet {
                 position: Vec3::new(-9., 2., 0.),
                 velocity: Vec3::new(-7., -2., 0.),
                 mass: 24.,
+                spin: Vec3::ZERO,
             };
             let expected = Planet {
                 position: Vec3::new(-6.5, 0.25, 0.),
                 velocity: Vec3::new(-4.5, 0., 0.),
                 mass: 32.,
+                spin: Vec3::new(0., 0., 900. / RadiusLaw::default().moment_of_inertia(32.)),
             };
-            left.merge(&right);
+            left.merge(&right, &RadiusLaw::default());
             assert_eq!(left, expected);
         }
 
@@ -192,22 +351,59 @@ mod tests {
                 position: Vec3::new(-9., 2., 0.),
                 velocity: Vec3::new(-7., -2., 0.),
                 mass: 24.,
+                spin: Vec3::ZERO,
             };
             let right = Planet {
                 position: Vec3::new(1., -5., 0.),
                 velocity: Vec3::new(3., 6., 0.),
                 mass: 8.,
+                spin: Vec3::ZERO,
             };
             let expected = Planet {
                 position: Vec3::new(-6.5, 0.25, 0.),
                 velocity: Vec3::new(-4.5, 0., 0.),
                 mass: 32.,
+                spin: Vec3::new(0., 0., 900. / RadiusLaw::default().moment_of_inertia(32.)),
             };
-            left.merge(&right);
+            left.merge(&right, &RadiusLaw::default());
             assert_eq!(left, expected);
         }
     }
 
+    mod spin_tests {
+        use super::*;
+
+        #[test]
+        fn merge_keeps_spin_angular_momentum() {
+            let law = RadiusLaw::default();
+            let mut left = Planet {
+                position: Vec3::ZERO,
+                velocity: Vec3::ZERO,
+                mass: 10.,
+                spin: Vec3::new(0., 2., 0.),
+            };
+            let right = Planet {
+                position: Vec3::ZERO,
+                velocity: Vec3::ZERO,
+                mass: 30.,
+                spin: Vec3::new(0., -1., 1.),
+            };
+            let angular_momentum =
+                left.spin * law.moment_of_inertia(10.) + right.spin * law.moment_of_inertia(30.);
+            left.merge(&right, &law);
+            assert!((left.spin * law.moment_of_inertia(40.) - angular_momentum).length() < 1e-3);
+        }
+
+        #[test]
+        fn reads_worlds_without_spin() {
+            let planet: Planet = serde_json::from_str(
+                r#"{"position": [1, 2, 3], "velocity": [0, 0, 0], "mass": 5}"#,
+            )
+            .unwrap();
+            assert_eq!(planet.spin, Vec3::ZERO);
+        }
+    }
+
     mod world_tests {
         use super::*;
 
@@ -219,23 +415,28 @@ mod tests {
                         position: Vec3::new(0., 0., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(1., -5., 0.),
                         velocity: Vec3::new(3., 6., 0.),
                         mass: 8.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(1., 0., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(-9., 2., 0.),
                         velocity: Vec3::new(-7., -2., 0.),
                         mass: 24.,
+                        spin: Vec3::ZERO,
                     },
                 ],
+                ..Default::default()
             };
             let expected = World {
                 planets: vec![
@@ -243,18 +444,22 @@ mod tests {
                         position: Vec3::new(0., 0., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(-6.5, 0.25, 0.),
                         velocity: Vec3::new(-4.5, 0., 0.),
                         mass: 32.,
+                        spin: Vec3::new(0., 0., 900. / RadiusLaw::default().moment_of_inertia(32.)),
                     },
                     Planet {
                         position: Vec3::new(1., 0., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                 ],
+                ..Default::default()
             };
             world.merge_planets(1, 3);
             assert_eq!(world, expected);
@@ -268,23 +473,28 @@ mod tests {
                         position: Vec3::new(0., 0., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(2., -10., 0.),
                         velocity: Vec3::new(3., 6., 0.),
                         mass: 8.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(5., 5., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(-2., -12., 0.),
                         velocity: Vec3::new(-7., -2., 0.),
                         mass: 24.,
+                        spin: Vec3::ZERO,
                     },
                 ],
+                ..Default::default()
             };
             let expected = World {
                 planets: vec![
@@ -292,21 +502,108 @@ mod tests {
                         position: Vec3::new(0., 0., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                     Planet {
                         position: Vec3::new(-1., -11.5, 0.),
                         velocity: Vec3::new(-4.5, 0., 0.),
                         mass: 32.,
+                        spin: Vec3::new(0., 0., 72. / RadiusLaw::default().moment_of_inertia(32.)),
                     },
                     Planet {
                         position: Vec3::new(5., 5., 0.),
                         velocity: Vec3::new(0., 0., 0.),
                         mass: 1.,
+                        spin: Vec3::ZERO,
                     },
                 ],
+                ..Default::default()
             };
             world.merge_overlapping_planets();
             assert_eq!(world, expected);
         }
     }
+
+    mod radius_law_tests {
+        use super::*;
+
+        fn assert_close(actual: f32, expected: f32) {
+            assert!(
+                (actual - expected).abs() <= expected.abs() * 1e-5,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+
+        #[test]
+        fn default_is_solid_sphere() {
+            let law = RadiusLaw::default();
+            assert_close(law.radius(4. / 3. * PI * 0.1 * 8.), 2.);
+            assert_close(law.ball_density(100.), 0.1);
+        }
+
+        #[test]
+        fn mass_inverts_radius() {
+            let law = RadiusLaw {
+                density: 0.5,
+                exponent: 0.25,
+            };
+            for &mass in &[0.1, 1., 250., 10000.] {
+                assert_close(law.mass(law.radius(mass)), mass);
+                let radius = law.radius(mass);
+                assert_close(4. / 3. * PI * radius.powi(3) * law.ball_density(mass), mass);
+            }
+        }
+
+        #[test]
+        fn rejects_non_positive_values() {
+            let law: RadiusLaw = serde_json::from_str(r#"{"density": 2}"#).unwrap();
+            assert_eq!(law.density, 2.);
+            assert_eq!(law.exponent, RadiusLaw::default().exponent);
+            assert!(serde_json::from_str::<RadiusLaw>(r#"{"density": 0}"#).is_err());
+            assert!(serde_json::from_str::<RadiusLaw>(r#"{"exponent": -1}"#).is_err());
+        }
+    }
+
+    mod stability_tests {
+        use super::*;
+
+        #[test]
+        fn names_round_trip() {
+            for stability in Stability::ALL.iter().copied() {
+                assert_eq!(stability.as_str().parse(), Ok(stability));
+                assert_eq!(
+                    serde_json::to_string(&stability).unwrap(),
+                    format!("\"{}\"", stability)
+                );
+            }
+            assert!("stable".parse::<Stability>().is_err());
+        }
+
+        #[test]
+        fn filter_matches() {
+            let any = StabilityFilter::default();
+            assert!(any.is_empty());
+            assert!(any.matches(None));
+            assert!(any.matches(Some(Stability::Dispersing)));
+
+            let filter = StabilityFilter {
+                only: vec![Stability::Hierarchical, Stability::Dispersing],
+                exclude: vec![Stability::Dispersing],
+            };
+            assert!(filter.matches(Some(Stability::Hierarchical)));
+            assert!(!filter.matches(Some(Stability::Dispersing)));
+            assert!(!filter.matches(Some(Stability::Chaotic)));
+            assert!(!filter.matches(None));
+
+            let filter = StabilityFilter {
+                only: vec![],
+                exclude: vec![Stability::Dispersing],
+            };
+            assert!(filter.matches(Some(Stability::Chaotic)));
+            assert!(!filter.matches(Some(Stability::Dispersing)));
+            assert!(filter.matches(None));
+        }
+    }
 }
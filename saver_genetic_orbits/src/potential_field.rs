@@ -0,0 +1,249 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Renders the gravitational potential of the planets as a "rubber sheet" grid or a heatmap on a
+//! plane below them. The potential is computed coarsely on the CPU every frame.
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use bevy_rapier3d::prelude::*;
+
+use crate::config::visualization::{FieldMode, PotentialFieldConfig, VisualizationConfig};
+use crate::world::{GravityConstant, Planet};
+
+/// Color of the grid lines.
+const GRID_COLOR: Color = Color::rgb(0.25, 0.45, 0.8);
+
+/// Colors of the heatmap, from zero potential to the deepest potential.
+const HEATMAP_STOPS: &[[f32; 3]] = &[
+    [0.02, 0.02, 0.08],
+    [0.25, 0.05, 0.45],
+    [0.85, 0.25, 0.25],
+    [1.0, 0.75, 0.2],
+    [1.0, 1.0, 0.85],
+];
+
+/// Adds the potential field visualization.
+pub struct PotentialFieldPlugin;
+
+impl Plugin for PotentialFieldPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<FieldState>()
+            .add_system(rebuild_field.system().label("rebuild-field"))
+            .add_system(update_field.system().after("rebuild-field"));
+    }
+}
+
+/// The entity currently showing the field, if any.
+#[derive(Default)]
+struct FieldState {
+    entity: Option<Entity>,
+    mesh: Handle<Mesh>,
+    texture: Handle<Texture>,
+}
+
+/// Replaces the field entity whenever the config changes, so the mode can be switched while
+/// running.
+fn rebuild_field(
+    mut commands: Commands,
+    config: Res<VisualizationConfig>,
+    mut state: ResMut<FieldState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    if let Some(entity) = state.entity.take() {
+        commands.entity(entity).despawn();
+    }
+    let config = &config.potential_field;
+    let (topology, material) = match config.mode {
+        FieldMode::Off => return,
+        FieldMode::Grid => (
+            PrimitiveTopology::LineList,
+            StandardMaterial {
+                base_color: GRID_COLOR,
+                unlit: true,
+                ..Default::default()
+            },
+        ),
+        FieldMode::Heatmap => {
+            let size = config.resolution as u32;
+            state.texture = textures.add(Texture::new_fill(
+                Extent3d::new(size, size, 1),
+                TextureDimension::D2,
+                &[0, 0, 0, 255],
+                TextureFormat::Rgba8UnormSrgb,
+            ));
+            (
+                PrimitiveTopology::TriangleList,
+                StandardMaterial {
+                    base_color_texture: Some(state.texture.clone()),
+                    unlit: true,
+                    ..Default::default()
+                },
+            )
+        }
+    };
+    state.mesh = meshes.add(grid_mesh(config, topology));
+    let entity = commands
+        .spawn_bundle(PbrBundle {
+            mesh: state.mesh.clone(),
+            material: materials.add(material),
+            ..Default::default()
+        })
+        .id();
+    state.entity = Some(entity);
+}
+
+/// Recomputes the potential and updates the grid heights or heatmap colors.
+fn update_field(
+    config: Res<VisualizationConfig>,
+    state: Res<FieldState>,
+    g: Res<GravityConstant>,
+    planets: Query<&RigidBodyMassProps, With<Planet>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    if state.entity.is_none() {
+        return;
+    }
+    let config = &config.potential_field;
+    let masses: Vec<(f32, f32, f32)> = planets
+        .iter()
+        .map(|mass| (mass.world_com.x, mass.world_com.z, mass.mass()))
+        .collect();
+    let depths = relative_depths(config, g.0, &masses);
+    match config.mode {
+        FieldMode::Off => {}
+        FieldMode::Grid => {
+            if let Some(mesh) = meshes.get_mut(&state.mesh) {
+                let mut positions = grid_positions(config);
+                for (position, depth) in positions.iter_mut().zip(&depths) {
+                    position[1] -= depth * config.depth;
+                }
+                mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            }
+        }
+        FieldMode::Heatmap => {
+            if let Some(texture) = textures.get_mut(&state.texture) {
+                for (pixel, &depth) in texture.data.chunks_exact_mut(4).zip(&depths) {
+                    let [r, g, b] = heatmap_color(depth);
+                    pixel.copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+        }
+    }
+}
+
+/// Positions of the points of the flat grid, in rows of increasing z.
+fn grid_positions(config: &PotentialFieldConfig) -> Vec<[f32; 3]> {
+    let res = config.resolution as usize;
+    let coord = |i: usize| config.size * (i as f32 / (res - 1) as f32 - 0.5);
+    (0..res)
+        .flat_map(|row| (0..res).map(move |col| [coord(col), config.height, coord(row)]))
+        .collect()
+}
+
+/// Builds a flat grid mesh, either as lines between neighbouring points or as triangles covering
+/// the plane.
+fn grid_mesh(config: &PotentialFieldConfig, topology: PrimitiveTopology) -> Mesh {
+    let res = config.resolution as u32;
+    let positions = grid_positions(config);
+    let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+    let uvs: Vec<[f32; 2]> = (0..res)
+        .flat_map(|row| {
+            (0..res).map(move |col| {
+                [
+                    (col as f32 + 0.5) / res as f32,
+                    (row as f32 + 0.5) / res as f32,
+                ]
+            })
+        })
+        .collect();
+
+    let index = |row: u32, col: u32| row * res + col;
+    let mut indices = vec![];
+    for row in 0..res {
+        for col in 0..res {
+            let right = col + 1 < res;
+            let down = row + 1 < res;
+            if topology == PrimitiveTopology::LineList {
+                if right {
+                    indices.extend_from_slice(&[index(row, col), index(row, col + 1)]);
+                }
+                if down {
+                    indices.extend_from_slice(&[index(row, col), index(row + 1, col)]);
+                }
+            } else if right && down {
+                // Wound counter-clockwise seen from above.
+                indices.extend_from_slice(&[
+                    index(row, col),
+                    index(row + 1, col),
+                    index(row, col + 1),
+                    index(row, col + 1),
+                    index(row + 1, col),
+                    index(row + 1, col + 1),
+                ]);
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new(topology);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Computes how deep the potential is at each point of the grid, from 0 for no potential
+/// approaching 1 for very deep potentials. Planets are given as `(x, z, mass)`.
+fn relative_depths(config: &PotentialFieldConfig, g: f32, planets: &[(f32, f32, f32)]) -> Vec<f32> {
+    let softening_sq = config.softening * config.softening;
+    grid_positions(config)
+        .iter()
+        .map(|&[x, _, z]| {
+            let potential: f32 = planets
+                .iter()
+                .map(|&(px, pz, mass)| {
+                    let dist_sq = (x - px).powi(2) + (z - pz).powi(2) + softening_sq;
+                    g * mass / dist_sq.sqrt()
+                })
+                .sum();
+            if potential.is_finite() {
+                potential / (potential + config.potential_scale)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+/// Maps a relative depth from 0 to 1 onto the heatmap colors.
+fn heatmap_color(depth: f32) -> [u8; 3] {
+    let scaled = depth.max(0.0).min(1.0) * (HEATMAP_STOPS.len() - 1) as f32;
+    let low = (scaled as usize).min(HEATMAP_STOPS.len() - 2);
+    let t = scaled - low as f32;
+    let mut color = [0; 3];
+    for (channel, out) in color.iter_mut().enumerate() {
+        let value = HEATMAP_STOPS[low][channel] * (1.0 - t) + HEATMAP_STOPS[low + 1][channel] * t;
+        *out = (value * 255.0).round() as u8;
+    }
+    color
+}
@@ -0,0 +1,332 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Observer probes: massless tracers which fall through the planets' gravity and leave a trail,
+//! making the shape of the field visible. Probes are integrated here rather than by rapier, so
+//! they never touch the planets or the score.
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+
+use crate::config::visualization::{ProbeConfig, VisualizationConfig};
+use crate::determinism::SimulationRng;
+use crate::model::RadiusLaw;
+use crate::statustracker::ActiveWorld;
+use crate::trails::TrailHistory;
+use crate::world::{GravityConstant, Planet};
+use crate::SaverState;
+
+/// Number of integration steps each probe takes per physics step, so probes passing close to a
+/// planet don't get flung off by a single large step.
+const SUBSTEPS: u32 = 4;
+
+/// Adds the observer probes.
+pub struct ProbesPlugin;
+
+impl Plugin for ProbesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ProbeState>()
+            .add_system(rebuild_probes.system().label("rebuild-probes"))
+            .add_system(fly_probes.system().after("rebuild-probes"))
+            .add_system_set(
+                SystemSet::on_enter(SaverState::Run)
+                    .with_system(relaunch_probes.system().after("reseed-simulation")),
+            );
+    }
+}
+
+/// The probes, and the entity drawing their paths, if probes are on.
+#[derive(Default)]
+struct ProbeState {
+    entity: Option<Entity>,
+    mesh: Handle<Mesh>,
+    probes: Vec<Probe>,
+}
+
+/// A single massless probe.
+#[derive(Debug, Clone)]
+struct Probe {
+    position: Vec3,
+    velocity: Vec3,
+    /// Seconds of simulation the probe has flown for.
+    age: f32,
+    /// Earlier positions, starting from the launch point while the path is short. The current
+    /// position isn't included.
+    path: TrailHistory,
+}
+
+impl Probe {
+    /// A probe at a random point within the launch distance, heading in a random direction.
+    fn launch(config: &ProbeConfig, rng: &mut impl Rng) -> Self {
+        let distance = config.launch_distance;
+        let position = Vec3::new(
+            rng.gen_range(-distance..=distance),
+            rng.gen_range(-distance..=distance),
+            rng.gen_range(-distance..=distance),
+        );
+        // Uniform over the sphere: uniform height, uniform angle around the vertical.
+        let y: f32 = rng.gen_range(-1.0..=1.0);
+        let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+        let across = (1.0 - y * y).sqrt();
+        let direction = Vec3::new(across * angle.cos(), y, across * angle.sin());
+        let mut path = trail(config);
+        // Starting the path at the launch point means every probe draws at least one line.
+        path.record(position, 0.0);
+        Self {
+            position,
+            velocity: direction * config.launch_speed,
+            age: 0.0,
+            path,
+        }
+    }
+
+    /// Advances the probe by `dt` seconds through the gravity of planets given as
+    /// `(center, mass)`.
+    fn fly(&mut self, g: f32, planets: &[(Vec3, f32)], dt: f32) {
+        let step = dt / SUBSTEPS as f32;
+        for _ in 0..SUBSTEPS {
+            self.velocity += acceleration(g, self.position, planets) * step;
+            self.position += self.velocity * step;
+        }
+        self.age += dt;
+        self.path.record(self.position, dt);
+    }
+
+    /// Whether the probe should be relaunched: its time is up, it has strayed too far, or it has
+    /// hit one of the planets, sized by `law`.
+    fn is_done(&self, config: &ProbeConfig, law: &RadiusLaw, planets: &[(Vec3, f32)]) -> bool {
+        let limit = config.launch_distance * 2.0;
+        self.age >= config.lifetime_seconds
+            || self.position.abs().max_element() > limit
+            || !self.position.is_finite()
+            || planets.iter().any(|&(center, mass)| {
+                self.position.distance_squared(center) < law.radius(mass).powi(2)
+            })
+    }
+}
+
+/// An empty path for a probe, which leaves room for the current position in the trail length.
+fn trail(config: &ProbeConfig) -> TrailHistory {
+    TrailHistory::new(config.trail_length as usize - 1, config.sample_seconds)
+}
+
+/// Acceleration due to gravity at `position` from planets given as `(center, mass)`.
+fn acceleration(g: f32, position: Vec3, planets: &[(Vec3, f32)]) -> Vec3 {
+    planets.iter().fold(Vec3::ZERO, |total, &(center, mass)| {
+        let diff = center - position;
+        let dist_sq = diff.length_squared();
+        let magnitude = g * mass / dist_sq;
+        if magnitude.is_finite() {
+            total + diff * (magnitude / dist_sq.sqrt())
+        } else {
+            total
+        }
+    })
+}
+
+/// Replaces the probes whenever the config changes, so they can be turned on and restyled while
+/// running.
+fn rebuild_probes(
+    mut commands: Commands,
+    config: Res<VisualizationConfig>,
+    mut state: ResMut<ProbeState>,
+    mut rng: ResMut<SimulationRng>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    if let Some(entity) = state.entity.take() {
+        commands.entity(entity).despawn();
+    }
+    let config = &config.probes;
+    if config.count == 0 {
+        state.probes.clear();
+        return;
+    }
+    state.probes = launch_all(config, &mut rng.scenario);
+    state.mesh = meshes.add(path_mesh(&state.probes));
+    let [r, g, b] = config.color;
+    let entity = commands
+        .spawn_bundle(PbrBundle {
+            mesh: state.mesh.clone(),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(r, g, b),
+                unlit: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .id();
+    state.entity = Some(entity);
+}
+
+/// Launches fresh probes when a new scenario starts, so no paths are left from the last one.
+fn relaunch_probes(
+    config: Res<VisualizationConfig>,
+    mut state: ResMut<ProbeState>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    if state.entity.is_some() {
+        state.probes = launch_all(&config.probes, &mut rng.scenario);
+    }
+}
+
+/// Launches every probe. Each starts partway through its lifetime, so they don't all relaunch at
+/// once.
+fn launch_all(config: &ProbeConfig, rng: &mut impl Rng) -> Vec<Probe> {
+    (0..config.count)
+        .map(|_| {
+            let mut probe = Probe::launch(config, rng);
+            probe.age = config.lifetime_seconds * rng.gen::<f32>();
+            probe
+        })
+        .collect()
+}
+
+/// Moves the probes along by the physics timestep and redraws their paths.
+#[allow(clippy::too_many_arguments)]
+fn fly_probes(
+    config: Res<VisualizationConfig>,
+    mut state: ResMut<ProbeState>,
+    g: Res<GravityConstant>,
+    integration: Res<IntegrationParameters>,
+    world: Res<ActiveWorld>,
+    planets: Query<&RigidBodyMassProps, With<Planet>>,
+    mut rng: ResMut<SimulationRng>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if state.entity.is_none() {
+        return;
+    }
+    let config = &config.probes;
+    let planets: Vec<(Vec3, f32)> = planets
+        .iter()
+        .map(|mass| {
+            let com = mass.world_com;
+            (Vec3::new(com.x, com.y, com.z), mass.mass())
+        })
+        .collect();
+    let state = &mut *state;
+    for probe in &mut state.probes {
+        probe.fly(g.0, &planets, integration.dt);
+        if probe.is_done(config, &world.world.radius_law, &planets) {
+            *probe = Probe::launch(config, &mut rng.scenario);
+        }
+    }
+    if let Some(mesh) = meshes.get_mut(&state.mesh) {
+        *mesh = path_mesh(&state.probes);
+    }
+}
+
+/// Builds a mesh of lines along each probe's path, ending at its current position.
+fn path_mesh(probes: &[Probe]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut indices = vec![];
+    for probe in probes {
+        let start = positions.len() as u32;
+        positions.extend(
+            probe
+                .path
+                .points()
+                .chain(std::iter::once(&probe.position))
+                .map(|&point| point.into()),
+        );
+        for index in start + 1..positions.len() as u32 {
+            indices.extend_from_slice(&[index - 1, index]);
+        }
+    }
+    let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+    let uvs = vec![[0.0, 0.0]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(position: Vec3, velocity: Vec3) -> Probe {
+        probe_with(&ProbeConfig::default(), position, velocity)
+    }
+
+    fn probe_with(config: &ProbeConfig, position: Vec3, velocity: Vec3) -> Probe {
+        Probe {
+            position,
+            velocity,
+            age: 0.0,
+            path: trail(config),
+        }
+    }
+
+    #[test]
+    fn probes_orbit_planets() {
+        let config = ProbeConfig {
+            trail_length: 10,
+            sample_seconds: 0.1,
+            ..Default::default()
+        };
+        let g: f32 = 500.0;
+        let law = RadiusLaw::default();
+        let planets = [(Vec3::ZERO, 1000.0)];
+        // Circular orbit: v^2 / r = g * m / r^2.
+        let radius = 200.0;
+        let speed = (g * 1000.0 / radius).sqrt();
+        let start = Vec3::new(radius, 0.0, 0.0);
+        let mut probe = probe_with(&config, start, Vec3::new(0.0, 0.0, speed));
+        for _ in 0..600 {
+            probe.fly(g, &planets, 1.0 / 60.0);
+            assert!((probe.position.length() - radius).abs() < radius * 0.05);
+        }
+        assert_eq!(probe.path.points().count(), 9);
+        assert!(!probe.is_done(&config, &law, &planets));
+    }
+
+    #[test]
+    fn probes_are_done_when_they_hit_planets_or_stray() {
+        let config = ProbeConfig::default();
+        let law = RadiusLaw::default();
+        let planets = [(Vec3::ZERO, 1000.0)];
+        assert!(probe(Vec3::ONE, Vec3::ZERO).is_done(&config, &law, &planets));
+        let far = Vec3::new(0.0, config.launch_distance * 2.5, 0.0);
+        assert!(probe(far, Vec3::ZERO).is_done(&config, &law, &planets));
+        let mut old = probe(Vec3::splat(500.0), Vec3::ZERO);
+        assert!(!old.is_done(&config, &law, &planets));
+        old.age = config.lifetime_seconds;
+        assert!(old.is_done(&config, &law, &planets));
+    }
+
+    #[test]
+    fn paths_join_points_with_lines() {
+        let mut first = probe(Vec3::new(2.0, 0.0, 0.0), Vec3::ZERO);
+        first.path.record(Vec3::ZERO, 0.0);
+        first.path.record(Vec3::X, 1.0);
+        let second = probe(Vec3::ONE, Vec3::ZERO);
+        let mesh = path_mesh(&[first, second]);
+        assert_eq!(mesh.count_vertices(), 4);
+        match mesh.indices() {
+            Some(Indices::U32(indices)) => assert_eq!(indices, &[0, 1, 1, 2]),
+            other => panic!("unexpected indices {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,569 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recordings of what the saver shows, for replaying interesting worlds and for debugging their
+//! scores. Run the saver with `--record <PATH>` to record every scenario it shows, with the
+//! `play <PATH>` subcommand to play a recording back, over and over, and with `export-gif` to turn
+//! one into an animated GIF with [`crate::export`].
+//!
+//! A recording holds where the camera is and every planet's position, rotation, size and color on
+//! every frame, so playback draws exactly what was shown without simulating anything. Playback
+//! only draws the planets, over a plain background. Recordings are little-endian binary:
+//!
+//! * A header: the bytes `ORBITREC` and the format version as a `u16`.
+//! * Any number of records, each starting with a tag byte:
+//!   * `1` starts a scenario. A `u8` which is 1 if the scenario has a parent, followed by the
+//!     parent's id as a `u64`, which is 0 if there's no parent.
+//!   * `2` is a frame. The time since the scenario started in seconds as an `f32`, the score so
+//!     far as an `f64`, the camera's position and rotation, and the number of planets as a `u32`.
+//!     Then, for each planet, its id as a `u32`, its color as a `u16`, its radius as an `f32`, and
+//!     its position and rotation.
+//!
+//! Positions are three `f32`s, and rotations are quaternions of four `f32`s, `x`, `y`, `z` and
+//! then `w`.
+//!
+//! A recording which was cut short, such as when the saver was killed mid-write, is played up to
+//! the last complete record.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::render::camera::PerspectiveProjection;
+use bevy::utils::HashMap;
+use xsecurelock_saver::engine::{SaverTime, XSecurelockSaverPlugins};
+
+use crate::statustracker::ActiveWorld;
+use crate::world::{
+    planet_pbr, ColorKey, Planet, PlanetMaterials, PlanetMesh, PlanetRenderPlugin, PlanetSurface,
+};
+use crate::SaverState;
+
+/// Bytes every recording starts with.
+const MAGIC: &[u8; 8] = b"ORBITREC";
+
+/// Version of the format written.
+const VERSION: u16 = 2;
+
+const SCENARIO_TAG: u8 = 1;
+const FRAME_TAG: u8 = 2;
+
+/// A planet as drawn on one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanetState {
+    /// Identifies the planet from frame to frame within a scenario.
+    pub id: u32,
+    /// The planet's color, as a [`ColorKey::index`].
+    pub color: u16,
+    pub radius: f32,
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+/// Everything drawn on one frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frame {
+    /// Seconds since the scenario started.
+    pub time: f32,
+    /// The scenario's score so far.
+    pub score: f64,
+    pub camera_position: Vec3,
+    pub camera_rotation: Quat,
+    pub planets: Vec<PlanetState>,
+}
+
+/// An entry in a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    /// A new scenario starts, a child of the given scenario or a new world.
+    Scenario { parent: Option<u64> },
+    /// A frame of the current scenario.
+    Frame(Frame),
+}
+
+/// Writes a recording.
+pub struct RecordingWriter<W> {
+    out: W,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    /// Starts a recording by writing its header.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self { out })
+    }
+
+    /// Starts a scenario, a child of `parent` or a new world.
+    pub fn start_scenario(&mut self, parent: Option<u64>) -> io::Result<()> {
+        self.out
+            .write_all(&[SCENARIO_TAG, parent.is_some() as u8])?;
+        self.out.write_all(&parent.unwrap_or(0).to_le_bytes())
+    }
+
+    /// Appends a frame to the current scenario.
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.out.write_all(&[FRAME_TAG])?;
+        self.out.write_all(&frame.time.to_le_bytes())?;
+        self.out.write_all(&frame.score.to_le_bytes())?;
+        self.write_placement(frame.camera_position, frame.camera_rotation)?;
+        self.out
+            .write_all(&(frame.planets.len() as u32).to_le_bytes())?;
+        for planet in &frame.planets {
+            self.out.write_all(&planet.id.to_le_bytes())?;
+            self.out.write_all(&planet.color.to_le_bytes())?;
+            self.out.write_all(&planet.radius.to_le_bytes())?;
+            self.write_placement(planet.position, planet.rotation)?;
+        }
+        Ok(())
+    }
+
+    fn write_placement(&mut self, position: Vec3, rotation: Quat) -> io::Result<()> {
+        let position: [f32; 3] = position.into();
+        let rotation: [f32; 4] = rotation.into();
+        for value in position.iter().chain(&rotation) {
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes out anything buffered.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Reads a recording's records in order.
+pub struct RecordingReader<R> {
+    input: R,
+}
+
+impl<R: Read> RecordingReader<R> {
+    /// Reads and checks the recording's header.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a recording"));
+        }
+        let version = u16::from_le_bytes(read_array(&mut input)?);
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "recording format version {} is unsupported",
+                version
+            )));
+        }
+        Ok(Self { input })
+    }
+
+    /// Reads the next record, or None at the end of the recording. A record cut off partway is
+    /// treated as the end.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let tag = match read_array::<1>(&mut self.input) {
+            Ok([tag]) => tag,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        match self.read_record(tag) {
+            Ok(record) => Ok(Some(record)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_record(&mut self, tag: u8) -> io::Result<Record> {
+        match tag {
+            SCENARIO_TAG => {
+                let [has_parent] = read_array::<1>(&mut self.input)?;
+                let parent = u64::from_le_bytes(read_array(&mut self.input)?);
+                Ok(Record::Scenario {
+                    parent: if has_parent != 0 { Some(parent) } else { None },
+                })
+            }
+            FRAME_TAG => {
+                let time = f32::from_le_bytes(read_array(&mut self.input)?);
+                let score = f64::from_le_bytes(read_array(&mut self.input)?);
+                let (camera_position, camera_rotation) = self.read_placement()?;
+                let count = u32::from_le_bytes(read_array(&mut self.input)?);
+                let mut planets = Vec::new();
+                for _ in 0..count {
+                    let id = u32::from_le_bytes(read_array(&mut self.input)?);
+                    let color = u16::from_le_bytes(read_array(&mut self.input)?);
+                    let radius = f32::from_le_bytes(read_array(&mut self.input)?);
+                    let (position, rotation) = self.read_placement()?;
+                    planets.push(PlanetState {
+                        id,
+                        color,
+                        radius,
+                        position,
+                        rotation,
+                    });
+                }
+                Ok(Record::Frame(Frame {
+                    time,
+                    score,
+                    camera_position,
+                    camera_rotation,
+                    planets,
+                }))
+            }
+            tag => Err(invalid_data(format!("unknown record tag {}", tag))),
+        }
+    }
+
+    fn read_placement(&mut self) -> io::Result<(Vec3, Quat)> {
+        let mut floats = [0.0; 7];
+        for value in &mut floats {
+            *value = f32::from_le_bytes(read_array(&mut self.input)?);
+        }
+        Ok((
+            Vec3::new(floats[0], floats[1], floats[2]),
+            Quat::from_xyzw(floats[3], floats[4], floats[5], floats[6]),
+        ))
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Records every frame of every scenario the saver shows to a file.
+pub struct RecorderPlugin {
+    pub path: PathBuf,
+}
+
+impl Plugin for RecorderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let recorder = match File::create(&self.path)
+            .and_then(|file| RecordingWriter::new(BufWriter::new(file)))
+        {
+            Ok(writer) => {
+                info!("Recording to {}", self.path.display());
+                Recorder(Some(writer))
+            }
+            Err(err) => {
+                error!("Unable to record to {}: {}", self.path.display(), err);
+                Recorder(None)
+            }
+        };
+        // Recorded last, once the planets have moved for the frame.
+        app.insert_resource(recorder)
+            .add_system_to_stage(CoreStage::Last, record_frame.system());
+    }
+}
+
+/// The recording being written, or None if recording failed.
+struct Recorder(Option<RecordingWriter<BufWriter<File>>>);
+
+/// Records the planets while a scenario runs, starting a new scenario in the recording whenever
+/// the saver starts running one. Recording stops at the first error.
+fn record_frame(
+    mut recorder: ResMut<Recorder>,
+    mut running: Local<bool>,
+    mut frame: Local<Frame>,
+    state: Res<State<SaverState>>,
+    world: Res<ActiveWorld>,
+    camera: Query<&Transform, With<PerspectiveProjection>>,
+    planets: Query<(Entity, &Transform, &ColorKey), With<Planet>>,
+) {
+    let writer = match &mut recorder.0 {
+        Some(writer) => writer,
+        None => return,
+    };
+    let was_running = std::mem::replace(&mut *running, *state.current() == SaverState::Run);
+    if !*running {
+        return;
+    }
+    frame.time = world.displayed.as_secs_f32();
+    frame.score = world.cumulative_score;
+    if let Ok(camera) = camera.single() {
+        frame.camera_position = camera.translation;
+        frame.camera_rotation = camera.rotation;
+    }
+    frame.planets.clear();
+    frame.planets.extend(
+        planets
+            .iter()
+            .map(|(entity, transform, color)| PlanetState {
+                id: entity.id(),
+                color: color.index(),
+                radius: transform.scale.x,
+                position: transform.translation,
+                rotation: transform.rotation,
+            }),
+    );
+    let result = if was_running {
+        writer.write_frame(&frame)
+    } else {
+        // Flushing at the start of each scenario keeps most of the recording if the saver is
+        // killed.
+        let parent = world.parent.as_ref().map(|parent| parent.id);
+        writer
+            .flush()
+            .and_then(|()| writer.start_scenario(parent))
+            .and_then(|()| writer.write_frame(&frame))
+    };
+    if let Err(err) = result {
+        error!("Stopped recording: {}", err);
+        recorder.0 = None;
+    }
+}
+
+/// Plays the recording at `path` in a window, over and over. Exits the process if the recording
+/// can't be opened.
+pub fn run(path: &Path) {
+    if let Err(err) = open(path) {
+        eprintln!("Unable to play {}: {}", path.display(), err);
+        process::exit(1);
+    }
+    App::build()
+        .insert_resource(Msaa { samples: 4 })
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_plugin(PlanetRenderPlugin)
+        .insert_resource(Playback {
+            path: path.to_owned(),
+            reader: None,
+            next: None,
+            clock: 0.0,
+            frames: 0,
+            score: None,
+            shown: HashMap::default(),
+        })
+        .add_system(play_frame.system())
+        .run();
+}
+
+/// Opens the recording at `path` and checks its header.
+pub fn open(path: &Path) -> io::Result<RecordingReader<BufReader<File>>> {
+    RecordingReader::new(BufReader::new(File::open(path)?))
+}
+
+/// The state of playing a recording.
+struct Playback {
+    path: PathBuf,
+    /// The recording, or None if it needs to be opened, to start from the beginning.
+    reader: Option<RecordingReader<BufReader<File>>>,
+    /// The next frame, read ahead as it isn't due yet.
+    next: Option<Frame>,
+    /// Seconds since the current scenario started.
+    clock: f32,
+    /// Number of frames shown since the recording was last opened.
+    frames: u64,
+    /// Score of the last frame shown, which is logged when its scenario ends.
+    score: Option<f64>,
+    /// The entities drawing each planet of the current scenario, by planet id.
+    shown: HashMap<u32, Entity>,
+}
+
+impl Playback {
+    /// The next record, reopening the recording once it runs out. None if the recording fails
+    /// to open, or runs out without showing any frames.
+    fn next_record(&mut self) -> Option<Record> {
+        loop {
+            if self.reader.is_none() {
+                match open(&self.path) {
+                    Ok(reader) => self.reader = Some(reader),
+                    Err(err) => {
+                        error!("Unable to play {}: {}", self.path.display(), err);
+                        return None;
+                    }
+                }
+                self.frames = 0;
+            }
+            match self.reader.as_mut().unwrap().next_record() {
+                Ok(Some(record)) => return Some(record),
+                Ok(None) if self.frames == 0 => {
+                    error!("No frames to play in {}", self.path.display());
+                    return None;
+                }
+                Ok(None) => info!("Playing {} again", self.path.display()),
+                Err(err) => warn!("Stopped reading {}: {}", self.path.display(), err),
+            }
+            self.reader = None;
+        }
+    }
+}
+
+/// Advances the playback clock and draws the last frame due by then. Exits if the recording
+/// can't be played.
+#[allow(clippy::too_many_arguments)]
+fn play_frame(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    mut cameras: Query<&mut Transform, With<PerspectiveProjection>>,
+    mut transforms: Query<&mut Transform, Without<PerspectiveProjection>>,
+    time: Res<SaverTime>,
+    mesh: Res<PlanetMesh>,
+    surface: Res<PlanetSurface>,
+    mut planet_materials: ResMut<PlanetMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    playback.clock += time.delta_seconds();
+    let mut due = None;
+    loop {
+        let frame = match playback.next.take() {
+            Some(frame) => frame,
+            None => match playback.next_record() {
+                Some(Record::Frame(frame)) => frame,
+                Some(Record::Scenario { parent }) => {
+                    if let Some(score) = playback.score.take() {
+                        info!("The scenario scored {}", score);
+                    }
+                    match parent {
+                        Some(parent) => info!("Playing a child of scenario {}", parent),
+                        None => info!("Playing a new world"),
+                    }
+                    for (_, entity) in playback.shown.drain() {
+                        commands.entity(entity).despawn();
+                    }
+                    playback.clock = 0.0;
+                    due = None;
+                    continue;
+                }
+                None => {
+                    exit.send(AppExit);
+                    return;
+                }
+            },
+        };
+        if frame.time > playback.clock && due.is_some() {
+            playback.next = Some(frame);
+            break;
+        }
+        playback.frames += 1;
+        due = Some(frame);
+    }
+
+    let frame = match due {
+        Some(frame) => frame,
+        None => return,
+    };
+    playback.score = Some(frame.score);
+    if let Ok(mut camera) = cameras.single_mut() {
+        camera.translation = frame.camera_position;
+        camera.rotation = frame.camera_rotation;
+    }
+    let mut shown = std::mem::take(&mut playback.shown);
+    let mut seen = HashMap::default();
+    for planet in &frame.planets {
+        let transform = Transform {
+            translation: planet.position,
+            rotation: planet.rotation,
+            scale: Vec3::splat(planet.radius),
+        };
+        let entity = match shown.remove(&planet.id) {
+            Some(entity) => {
+                if let Ok(mut current) = transforms.get_mut(entity) {
+                    *current = transform;
+                }
+                entity
+            }
+            None => {
+                let color = ColorKey::from_index(planet.color).unwrap_or_default();
+                let pbr = planet_pbr(
+                    color,
+                    transform,
+                    &mesh,
+                    &surface,
+                    &mut planet_materials,
+                    &mut materials,
+                );
+                commands.spawn_bundle(pbr).id()
+            }
+        };
+        seen.insert(planet.id, entity);
+    }
+    // Planets missing from the frame have merged into others.
+    for (_, entity) in shown {
+        commands.entity(entity).despawn();
+    }
+    playback.shown = seen;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(time: f32, planets: u32) -> Frame {
+        Frame {
+            time,
+            score: time as f64 * 2.5,
+            camera_position: Vec3::new(0.0, 10.0, 100.0),
+            camera_rotation: Quat::from_rotation_x(-0.1),
+            planets: (0..planets)
+                .map(|id| PlanetState {
+                    id,
+                    color: id as u16 * 7,
+                    radius: 1.0 + id as f32,
+                    position: Vec3::new(id as f32, -2.0, 3.5),
+                    rotation: Quat::from_rotation_y(id as f32),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trips_records() {
+        let records = vec![
+            Record::Scenario { parent: None },
+            Record::Frame(frame(0.0, 3)),
+            Record::Frame(frame(0.016, 2)),
+            Record::Scenario { parent: Some(42) },
+            Record::Frame(frame(0.0, 0)),
+        ];
+        let mut writer = RecordingWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            match record {
+                Record::Scenario { parent } => writer.start_scenario(*parent).unwrap(),
+                Record::Frame(frame) => writer.write_frame(frame).unwrap(),
+            }
+        }
+        let bytes = writer.out;
+        // Header, two scenarios, three frames and five planets.
+        assert_eq!(bytes.len(), 10 + 2 * 10 + 3 * 45 + 5 * 38);
+
+        let mut reader = RecordingReader::new(&bytes[..]).unwrap();
+        let mut read = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            read.push(record);
+        }
+        assert_eq!(read, records);
+
+        // A cut off record ends the recording.
+        let mut reader = RecordingReader::new(&bytes[..bytes.len() - 50]).unwrap();
+        let mut read = 0;
+        while reader.next_record().unwrap().is_some() {
+            read += 1;
+        }
+        assert_eq!(read, 3);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(RecordingReader::new(&b"ORBITREC\x01\x00"[..]).is_err());
+        assert!(RecordingReader::new(&b"PNG"[..]).is_err());
+        let mut reader = RecordingReader::new(&b"ORBITREC\x02\x00\x07"[..]).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+}
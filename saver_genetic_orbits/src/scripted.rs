@@ -0,0 +1,201 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Drives the whole saver one step at a time, headless, so tests can follow the scenario state
+//! machine from generating a world through scoring and storing it. The soak test runs the saver
+//! the same way.
+//!
+//! Frames cover a 60th of a second of saver time however long they take to run, so when a
+//! scenario ends depends only on the config. What the scenarios contain still depends on the
+//! random worlds generated.
+
+use bevy::ecs::component::Component;
+use bevy::prelude::*;
+use xsecurelock_saver::engine::{SaverSignal, TestRunner};
+
+use crate::config::ConfigPlugin;
+use crate::{SaverPlugins, SaverState};
+
+/// Runs the saver's app, headless, by explicit steps.
+pub struct ScriptedRunner {
+    runner: TestRunner,
+}
+
+impl ScriptedRunner {
+    /// Builds the saver's app, leaving out the plugins which need a sound card or a window.
+    /// `configure` can change the configs once they are loaded, before any other plugin reads
+    /// them.
+    pub fn new(configure: impl Fn(&mut World) + Send + Sync + 'static) -> Self {
+        let runner = TestRunner::new(|app| {
+            app.add_plugins_with(SaverPlugins, |group| {
+                group.add_after::<ConfigPlugin, _>(ConfigurePlugin(Box::new(configure)));
+                // Plays through the sound card.
+                #[cfg(feature = "audio-out")]
+                group.disable::<crate::audio::AudioPlugin>();
+                // Needs a window to draw into.
+                #[cfg(feature = "devtools")]
+                group.disable::<crate::devtools::DevtoolsPlugin>();
+                group
+            });
+        });
+        Self { runner }
+    }
+
+    /// Runs the given number of frames, stopping early if the saver exits or was interrupted.
+    pub fn advance(&mut self, frames: u32) -> &mut Self {
+        self.runner.update(frames);
+        self
+    }
+
+    /// Runs frames until the saver is in `state`, for at most `max_frames`. Returns whether it got
+    /// there.
+    pub fn advance_until(&mut self, state: SaverState, max_frames: u32) -> bool {
+        for _ in 0..max_frames {
+            if self.state() == state {
+                return true;
+            }
+            if self.stopped() {
+                return false;
+            }
+            self.runner.update(1);
+        }
+        self.state() == state
+    }
+
+    /// Delivers a signal to the saver between frames.
+    pub fn send_signal(&mut self, signal: SaverSignal) -> &mut Self {
+        self.runner.send_signal(signal);
+        self
+    }
+
+    /// Ends the running scenario on the next frame, as if its display time were up, so it is
+    /// scored and stored as usual. Does nothing while a world is being generated.
+    pub fn force_scene_change(&mut self) -> &mut Self {
+        let mut state = self
+            .runner
+            .world_mut()
+            .get_resource_mut::<State<SaverState>>()
+            .expect("The saver adds its state");
+        if *state.current() == SaverState::Run {
+            // Only fails if the scenario was already ending.
+            let _ = state.set(SaverState::Generate);
+        }
+        self
+    }
+
+    /// The saver's current state.
+    pub fn state(&self) -> SaverState {
+        *self.runner.resource::<State<SaverState>>().current()
+    }
+
+    /// Number of frames run so far.
+    pub fn frames(&self) -> u64 {
+        self.runner.frames()
+    }
+
+    /// Whether the saver has stopped running frames, either by exiting or by being interrupted.
+    pub fn stopped(&self) -> bool {
+        self.runner.exited() || self.runner.interrupted()
+    }
+
+    /// Gets a resource, panicking if the app doesn't have it.
+    pub fn resource<T: Component>(&self) -> &T {
+        self.runner.resource::<T>()
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.runner.world_mut()
+    }
+}
+
+/// Applies the runner's changes to the configs.
+struct ConfigurePlugin(Box<dyn Fn(&mut World) + Send + Sync>);
+
+impl Plugin for ConfigurePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        (self.0)(app.world_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::database::{DatabaseConfig, StorageBackend};
+    use crate::config::scoring::ScoringConfig;
+    use crate::storage::{BoxedStorage, Storage};
+
+    /// Generous bound on the frames it takes to generate and spawn a world, or to score one.
+    const MAX_FRAMES: u32 = 600;
+
+    /// Keeps scenarios in memory, and shows each for a second.
+    fn short_scenarios(world: &mut World) {
+        let mut dbconf = world.get_resource_mut::<DatabaseConfig>().unwrap();
+        dbconf.backend = StorageBackend::Sqlite;
+        dbconf.database_path = None;
+        dbconf.max_scenarios_to_keep = None;
+        dbconf.backup.enabled = false;
+        let mut scoring = world.get_resource_mut::<ScoringConfig>().unwrap();
+        scoring.scored_time = Duration::from_secs(1);
+        scoring.min_display_time = Duration::from_secs(1);
+        scoring.max_display_time = Duration::from_secs(1);
+    }
+
+    fn stored_scenarios(runner: &mut ScriptedRunner) -> u64 {
+        runner
+            .world_mut()
+            .get_resource_mut::<BoxedStorage>()
+            .unwrap()
+            .num_scenarios()
+            .unwrap()
+    }
+
+    #[test]
+    fn scores_and_stores_scenarios() {
+        let mut runner = ScriptedRunner::new(short_scenarios);
+        assert_eq!(runner.state(), SaverState::Generate);
+        assert!(runner.advance_until(SaverState::Run, MAX_FRAMES));
+        assert!(runner.advance_until(SaverState::Generate, MAX_FRAMES));
+        assert_eq!(stored_scenarios(&mut runner), 1);
+        assert!(runner.advance_until(SaverState::Run, MAX_FRAMES));
+        assert!(runner.advance_until(SaverState::Generate, MAX_FRAMES));
+        assert_eq!(stored_scenarios(&mut runner), 2);
+    }
+
+    #[test]
+    fn forced_scene_changes_end_the_scenario() {
+        let mut runner = ScriptedRunner::new(short_scenarios);
+        // Does nothing before a scenario is running.
+        runner.force_scene_change();
+        assert!(runner.advance_until(SaverState::Run, MAX_FRAMES));
+        let started = runner.frames();
+        runner.advance(2).force_scene_change().advance(1);
+        assert_eq!(runner.state(), SaverState::Generate);
+        assert_eq!(runner.frames(), started + 3);
+        assert_eq!(stored_scenarios(&mut runner), 1);
+    }
+
+    #[test]
+    fn stops_when_interrupted() {
+        let mut runner = ScriptedRunner::new(short_scenarios);
+        runner
+            .advance(3)
+            .send_signal(SaverSignal::Interrupt)
+            .advance(10);
+        assert!(runner.stopped());
+        assert_eq!(runner.frames(), 3);
+        assert!(!runner.advance_until(SaverState::Run, MAX_FRAMES));
+    }
+}
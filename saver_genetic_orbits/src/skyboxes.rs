@@ -14,7 +14,9 @@
 
 use bevy::prelude::*;
 use bevy_skybox_cubemap::{SkyboxBundle, SkyboxMaterial, SkyboxTextureConversion};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use xsecurelock_saver::cli;
 
 use crate::SaverState;
 
@@ -30,8 +32,19 @@ impl Plugin for SkyboxesPlugin {
     }
 }
 
-#[derive(Default)]
-struct Skyboxes(Vec<Handle<SkyboxMaterial>>);
+struct Skyboxes {
+    materials: Vec<Handle<SkyboxMaterial>>,
+    rng: StdRng,
+}
+
+impl Default for Skyboxes {
+    fn default() -> Self {
+        Self {
+            materials: Vec::new(),
+            rng: cli::rng(),
+        }
+    }
+}
 
 /// Loads skybox textures.
 fn setup(
@@ -50,17 +63,21 @@ fn setup(
         let tex = asset_server.load(*tex);
         skybox_conversion.make_array(tex.clone());
         let mat = materials.add(SkyboxMaterial::from_texture(tex));
-        skyboxes.0.push(mat);
+        skyboxes.materials.push(mat);
     }
 
-    commands.spawn_bundle(SkyboxBundle::new(choose_skybox(&*skyboxes)));
+    commands.spawn_bundle(SkyboxBundle::new(choose_skybox(&mut *skyboxes)));
 }
 
 /// Randomly selects a new skybox texture.
-fn change_skybox(mut query: Query<&mut Handle<SkyboxMaterial>>, skyboxes: Res<Skyboxes>) {
-    *query.single_mut().unwrap() = choose_skybox(&*skyboxes);
+fn change_skybox(mut query: Query<&mut Handle<SkyboxMaterial>>, mut skyboxes: ResMut<Skyboxes>) {
+    *query.single_mut().unwrap() = choose_skybox(&mut *skyboxes);
 }
 
-fn choose_skybox(skyboxes: &Skyboxes) -> Handle<SkyboxMaterial> {
-    skyboxes.0.choose(&mut rand::thread_rng()).unwrap().clone()
+fn choose_skybox(skyboxes: &mut Skyboxes) -> Handle<SkyboxMaterial> {
+    skyboxes
+        .materials
+        .choose(&mut skyboxes.rng)
+        .unwrap()
+        .clone()
 }
@@ -0,0 +1,252 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Freezes idle clusters of planets. Long running scenarios often end up with a few clusters
+//! which have settled into stable orbits far from everything else, and still cost a full share of
+//! the n-body gravity computation every step. When enabled in the physics config, clusters which
+//! are isolated and whose planets barely move relative to each other are put to sleep: their
+//! planets stop, and drop out of the gravity computation, until another planet comes near. Their
+//! velocities are kept, and restored when they wake.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::na::Vector3;
+use bevy_rapier3d::prelude::*;
+use xsecurelock_saver::engine::SaverTime;
+
+use crate::config::physics::{PhysicsConfig, SleepConfig};
+use crate::world::{ApplyGravity, PendingPlanets, Planet};
+use crate::SaverState;
+
+/// Plugin which freezes idle clusters of planets.
+pub struct SleepPlugin;
+
+impl Plugin for SleepPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(SaverState::Run).with_system(update_sleeping.system()),
+        );
+    }
+}
+
+/// Marks a frozen planet, holding the velocity it had when it was frozen.
+pub struct Frozen {
+    /// The planet's linear velocity, which it gets back when it wakes.
+    pub linvel: Vector3<f32>,
+    angvel: Vector3<f32>,
+}
+
+/// A planet as seen by the cluster search.
+#[derive(Debug, Clone, Copy)]
+struct Body {
+    position: Vec3,
+    velocity: Vec3,
+    mass: f32,
+}
+
+fn to_vec3(vector: &Vector3<f32>) -> Vec3 {
+    Vec3::new(vector.x, vector.y, vector.z)
+}
+
+/// Periodically freezes planets in idle clusters, and wakes frozen planets whose clusters aren't
+/// idle any more.
+#[allow(clippy::type_complexity)]
+fn update_sleeping(
+    mut commands: Commands,
+    time: Res<SaverTime>,
+    physics: Res<PhysicsConfig>,
+    pending: Res<PendingPlanets>,
+    mut since_check: Local<Duration>,
+    mut planets: Query<
+        (
+            Entity,
+            &RigidBodyMassProps,
+            &mut RigidBodyVelocity,
+            &mut RigidBodyActivation,
+            Option<&Frozen>,
+        ),
+        With<Planet>,
+    >,
+) {
+    // Clusters of a partly spawned world would look more isolated than they are.
+    if !physics.sleep.enabled || pending.is_spawning() {
+        return;
+    }
+    *since_check += time.delta();
+    if since_check.as_secs_f32() < physics.sleep.check_interval_seconds {
+        return;
+    }
+    *since_check = Duration::from_secs(0);
+
+    let bodies: Vec<Body> = planets
+        .iter()
+        .map(|(_, mass, velocity, _, frozen)| Body {
+            position: to_vec3(&mass.world_com.coords),
+            velocity: to_vec3(frozen.map_or(&velocity.linvel, |frozen| &frozen.linvel)),
+            mass: mass.mass(),
+        })
+        .collect();
+    let asleep = idle_bodies(&bodies, &physics.sleep);
+
+    let (mut froze, mut woke) = (0, 0);
+    for ((entity, _, mut velocity, mut activation, frozen), asleep) in
+        planets.iter_mut().zip(asleep)
+    {
+        match (frozen, asleep) {
+            (None, true) => {
+                commands
+                    .entity(entity)
+                    .insert(Frozen {
+                        linvel: velocity.linvel,
+                        angvel: velocity.angvel,
+                    })
+                    .remove::<ApplyGravity>();
+                velocity.linvel = Vector3::zeros();
+                velocity.angvel = Vector3::zeros();
+                activation.sleep();
+                froze += 1;
+            }
+            (Some(frozen), false) => {
+                velocity.linvel = frozen.linvel;
+                velocity.angvel = frozen.angvel;
+                commands
+                    .entity(entity)
+                    .remove::<Frozen>()
+                    .insert(ApplyGravity);
+                activation.wake_up(true);
+                woke += 1;
+            }
+            _ => {}
+        }
+    }
+    if froze > 0 {
+        info!("Froze {} planets in idle clusters", froze);
+    }
+    if woke > 0 {
+        info!("Woke {} planets", woke);
+    }
+}
+
+/// Finds the root of the union-find set containing `i`.
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Returns whether each body is in an idle cluster: one of at least two bodies, with no other body
+/// within the isolation distance, whose bodies all move slower than the max relative speed
+/// relative to its center of mass.
+fn idle_bodies(bodies: &[Body], config: &SleepConfig) -> Vec<bool> {
+    let cluster_sq = config.cluster_distance * config.cluster_distance;
+    let near_sq = config
+        .isolation_distance
+        .max(config.cluster_distance)
+        .powi(2);
+
+    let mut parents: Vec<usize> = (0..bodies.len()).collect();
+    let mut near_pairs = Vec::new();
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let dist_sq = bodies[i].position.distance_squared(bodies[j].position);
+            if dist_sq < cluster_sq {
+                let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+                parents[root_i] = root_j;
+            } else if dist_sq < near_sq {
+                near_pairs.push((i, j));
+            }
+        }
+    }
+    let roots: Vec<usize> = (0..bodies.len()).map(|i| find(&mut parents, i)).collect();
+
+    let mut isolated = vec![true; bodies.len()];
+    for (i, j) in near_pairs {
+        if roots[i] != roots[j] {
+            isolated[roots[i]] = false;
+            isolated[roots[j]] = false;
+        }
+    }
+
+    let mut members = vec![0usize; bodies.len()];
+    let mut mass = vec![0.0f32; bodies.len()];
+    let mut momentum = vec![Vec3::ZERO; bodies.len()];
+    for (body, &root) in bodies.iter().zip(&roots) {
+        members[root] += 1;
+        mass[root] += body.mass;
+        momentum[root] += body.velocity * body.mass;
+    }
+    let mut settled = vec![true; bodies.len()];
+    for (body, &root) in bodies.iter().zip(&roots) {
+        let cluster_velocity = momentum[root] / mass[root];
+        if body.velocity.distance(cluster_velocity) > config.max_relative_speed {
+            settled[root] = false;
+        }
+    }
+
+    roots
+        .iter()
+        .map(|&root| members[root] >= 2 && isolated[root] && settled[root])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(x: f32, vx: f32) -> Body {
+        Body {
+            position: Vec3::new(x, 0., 0.),
+            velocity: Vec3::new(vx, 0., 0.),
+            mass: 10.,
+        }
+    }
+
+    #[test]
+    fn freezes_isolated_settled_clusters() {
+        let config = SleepConfig::default();
+        let bodies = vec![
+            // A settled pair drifting together, far from everything.
+            body(0., 5.),
+            body(50., 6.),
+            // A lone planet isn't a cluster.
+            body(5000., 0.),
+            // A pair whose planets are moving apart quickly.
+            body(-5000., -10.),
+            body(-5050., 10.),
+        ];
+        assert_eq!(
+            idle_bodies(&bodies, &config),
+            vec![true, true, false, false, false]
+        );
+    }
+
+    #[test]
+    fn wakes_clusters_with_neighbors() {
+        let config = SleepConfig::default();
+        let mut bodies = vec![body(0., 0.), body(50., 0.), body(3000., 0.)];
+        assert_eq!(idle_bodies(&bodies, &config), vec![true, true, false]);
+        bodies[2].position.x = 800.;
+        assert_eq!(idle_bodies(&bodies, &config), vec![false, false, false]);
+    }
+
+    #[test]
+    fn chains_clusters_through_neighbors() {
+        let config = SleepConfig::default();
+        let bodies = vec![body(0., 0.), body(90., 0.), body(180., 0.)];
+        assert_eq!(idle_bodies(&bodies, &config), vec![true, true, true]);
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Soak test for long lock sessions. Run with the `soak <CYCLES>` subcommand. The whole saver runs
+//! headless, with the renderer's headless render resource context in place of the GPU, for the
+//! given number of scenarios, and after each scenario it checks that:
+//!
+//! * the average frame time stayed within the budget set with `--frame-budget`,
+//! * memory use, the database file and the renderer's resources have stopped growing once warmed
+//!   up,
+//! * pruning keeps up with the scenarios being stored, and
+//! * material and mesh assets aren't piling up, beyond the materials planets share.
+//!
+//! The first failing check stops the run with a non-zero exit status, as does any panic, so the
+//! soak test can run in CI. Progress is written to stderr and a summary of each scenario to
+//! stdout as CSV.
+//!
+//! The saver's config is used as usual, except that scenarios are shortened to
+//! [`SCENARIO_TIME`], and scenarios are kept in a scratch database which is pruned often and
+//! removed afterwards.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy::render::renderer::RenderResourceContext;
+use bevy_wgpu_xsecurelock::renderer::HeadlessRenderResourceContext;
+
+use crate::config::database::{DatabaseConfig, StorageBackend};
+use crate::config::scoring::ScoringConfig;
+use crate::scripted::ScriptedRunner;
+use crate::statustracker::resident_memory_kib;
+use crate::storage::{BoxedStorage, Storage};
+use crate::world::PlanetMaterials;
+use crate::SaverState;
+
+/// How long each scenario is shown.
+const SCENARIO_TIME: Duration = Duration::from_secs(5);
+
+/// Number of scenarios the scratch database is pruned down to.
+const KEEP_SCENARIOS: u64 = 50;
+
+/// How often the scratch database is pruned, in seconds of saver time. A couple of scenarios
+/// finish between prunes.
+const PRUNE_INTERVAL_SECONDS: u64 = 10;
+
+/// Scenarios the database may hold past [`KEEP_SCENARIOS`], for those stored since the last prune
+/// and a prune still running.
+const PRUNE_SLACK: u64 = 5;
+
+/// Spare assets allowed, for those dropped in the last frame which haven't been freed yet.
+const LEAK_TOLERANCE: usize = 8;
+
+/// How much resident memory may grow after warming up.
+const MAX_MEMORY_GROWTH_KIB: u64 = 64 * 1024;
+
+/// Runs the saver headless for the given number of scenarios, checking each against the frame
+/// time budget and for leaks. Exits the process if a check fails.
+pub fn run(cycles: u64, frame_budget: Duration) {
+    let database = ScratchDatabase::new();
+    let path = database.0.clone();
+    let mut runner = ScriptedRunner::new(move |world| configure(world, &path));
+
+    // Memory and the database are only expected to level off once the database is full.
+    let warmup = (cycles / 10).max(1);
+    let mut first: Option<Sample> = None;
+    let mut warmed: Option<Sample> = None;
+    let mut state = SaverState::Generate;
+    let mut frame_times = Vec::new();
+    let mut cycle = 0;
+    println!(
+        "cycle,frames,mean_frame_ms,worst_frame_ms,rss_kib,database_bytes,scenarios,\
+         spare_materials,meshes,render_buffers,render_asset_resources"
+    );
+    while cycle < cycles {
+        let start = Instant::now();
+        runner.advance(1);
+        frame_times.push(start.elapsed());
+        if runner.stopped() {
+            eprintln!("The saver exited after {} scenarios", cycle);
+            fail(runner, database);
+        }
+        let previous = std::mem::replace(&mut state, runner.state());
+        if previous != SaverState::Run || state != SaverState::Generate {
+            continue;
+        }
+
+        cycle += 1;
+        let sample = Sample::take(&mut runner, &frame_times, &database);
+        frame_times.clear();
+        println!("{}", sample.csv(cycle));
+        eprintln!("Finished scenario {}/{}", cycle, cycles);
+        let failures = sample.check(
+            first.as_ref().unwrap_or(&sample),
+            warmed.as_ref(),
+            // The first scenario includes startup.
+            if cycle > 1 { Some(frame_budget) } else { None },
+        );
+        if !failures.is_empty() {
+            for failure in &failures {
+                eprintln!("Scenario {}: {}", cycle, failure);
+            }
+            fail(runner, database);
+        }
+        if first.is_none() {
+            first = Some(sample.clone());
+        }
+        if cycle == warmup {
+            warmed = Some(sample);
+        }
+    }
+    eprintln!("Soaked {} scenarios without problems", cycles);
+}
+
+/// Drops the app, which shuts down its pruner, before removing the database, then exits with a
+/// failure.
+fn fail(runner: ScriptedRunner, database: ScratchDatabase) -> ! {
+    drop(runner);
+    drop(database);
+    process::exit(1);
+}
+
+/// Points the database at the scratch file, prunes it often, and shortens scenarios.
+fn configure(world: &mut World, database: &Path) {
+    let mut dbconf = world
+        .get_resource_mut::<DatabaseConfig>()
+        .expect("The config plugin adds the database config");
+    dbconf.backend = StorageBackend::Sqlite;
+    dbconf.database_path = Some(database.to_owned());
+    dbconf.max_scenarios_to_keep = Some(KEEP_SCENARIOS);
+    dbconf.prune_interval_seconds = PRUNE_INTERVAL_SECONDS;
+    dbconf.backup.enabled = false;
+    let mut scoring = world
+        .get_resource_mut::<ScoringConfig>()
+        .expect("The config plugin adds the scoring config");
+    scoring.scored_time = SCENARIO_TIME;
+    scoring.min_display_time = SCENARIO_TIME;
+    scoring.max_display_time = SCENARIO_TIME;
+}
+
+/// Database file in the temp directory, removed when dropped, including when unwinding from a
+/// panic.
+struct ScratchDatabase(PathBuf);
+
+impl ScratchDatabase {
+    fn new() -> Self {
+        let path = env::temp_dir().join(format!("genetic-orbits-soak-{}.sqlite3", process::id()));
+        Self(path)
+    }
+
+    fn size(&self) -> u64 {
+        fs::metadata(&self.0).map_or(0, |metadata| metadata.len())
+    }
+}
+
+impl Drop for ScratchDatabase {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.0) {
+            eprintln!("Unable to remove {}: {}", self.0.display(), err);
+        }
+    }
+}
+
+/// What the soak test measures at the end of each scenario.
+#[derive(Debug, Clone)]
+struct Sample {
+    frames: usize,
+    mean_frame_time: Duration,
+    worst_frame_time: Duration,
+    /// Resident memory, if the kernel reports it.
+    rss_kib: Option<u64>,
+    database_bytes: u64,
+    scenarios: u64,
+    materials: usize,
+    /// Material assets other than those planets share.
+    spare_materials: usize,
+    meshes: usize,
+    render_buffers: usize,
+    render_asset_resources: usize,
+}
+
+impl Sample {
+    fn take(
+        runner: &mut ScriptedRunner,
+        frame_times: &[Duration],
+        database: &ScratchDatabase,
+    ) -> Self {
+        let world = runner.world_mut();
+        let pooled = world.get_resource::<PlanetMaterials>().unwrap().len();
+        let materials = world.get_resource::<Assets<StandardMaterial>>().unwrap();
+        let meshes = world.get_resource::<Assets<Mesh>>().unwrap();
+        let render = world
+            .get_resource::<Box<dyn RenderResourceContext>>()
+            .unwrap()
+            .downcast_ref::<HeadlessRenderResourceContext>()
+            .expect("The test runner renders headless");
+        let mut sample = Self {
+            frames: frame_times.len(),
+            mean_frame_time: frame_times.iter().sum::<Duration>() / frame_times.len() as u32,
+            worst_frame_time: frame_times.iter().copied().max().unwrap_or_default(),
+            rss_kib: resident_memory_kib(),
+            database_bytes: database.size(),
+            scenarios: 0,
+            materials: materials.len(),
+            spare_materials: materials.len().saturating_sub(pooled),
+            meshes: meshes.len(),
+            render_buffers: render.buffer_count(),
+            render_asset_resources: render.asset_resource_count(),
+        };
+        sample.scenarios = world
+            .get_resource_mut::<BoxedStorage>()
+            .unwrap()
+            .num_scenarios()
+            .expect("Unable to count scenarios");
+        sample
+    }
+
+    fn csv(&self, cycle: u64) -> String {
+        format!(
+            "{},{},{:.3},{:.3},{},{},{},{},{},{},{}",
+            cycle,
+            self.frames,
+            self.mean_frame_time.as_secs_f64() * 1000.0,
+            self.worst_frame_time.as_secs_f64() * 1000.0,
+            self.rss_kib.map_or(String::new(), |rss| rss.to_string()),
+            self.database_bytes,
+            self.scenarios,
+            self.spare_materials,
+            self.meshes,
+            self.render_buffers,
+            self.render_asset_resources,
+        )
+    }
+
+    /// Checks this sample against the first scenario's and, once warmed up, the warmed up
+    /// scenario's. Returns a description of each failed check.
+    fn check(
+        &self,
+        first: &Sample,
+        warmed: Option<&Sample>,
+        frame_budget: Option<Duration>,
+    ) -> Vec<String> {
+        let mut failures = Vec::new();
+        if let Some(budget) = frame_budget {
+            if self.mean_frame_time > budget {
+                failures.push(format!(
+                    "frames took {:?} on average, over the budget of {:?}",
+                    self.mean_frame_time, budget
+                ));
+            }
+        }
+        if self.scenarios > KEEP_SCENARIOS + PRUNE_SLACK {
+            failures.push(format!(
+                "the database holds {} scenarios, but is pruned to {}",
+                self.scenarios, KEEP_SCENARIOS
+            ));
+        }
+        if self.spare_materials > first.spare_materials + LEAK_TOLERANCE {
+            failures.push(format!(
+                "{} materials aren't used by a planet, up from {}",
+                self.spare_materials, first.spare_materials
+            ));
+        }
+        if self.meshes > first.meshes + LEAK_TOLERANCE {
+            failures.push(format!(
+                "there are {} meshes, up from {}",
+                self.meshes, first.meshes
+            ));
+        }
+        let warmed = match warmed {
+            Some(warmed) => warmed,
+            None => return failures,
+        };
+        if let (Some(now), Some(then)) = (self.rss_kib, warmed.rss_kib) {
+            if now > then + MAX_MEMORY_GROWTH_KIB {
+                failures.push(format!(
+                    "resident memory grew from {} KiB to {} KiB",
+                    then, now
+                ));
+            }
+        }
+        // Sqlite reuses the pages of pruned scenarios, so the file stops growing.
+        if self.database_bytes > 2 * warmed.database_bytes {
+            failures.push(format!(
+                "the database grew from {} to {} bytes",
+                warmed.database_bytes, self.database_bytes
+            ));
+        }
+        // The renderer's resources track the assets, so may only grow along with them, as planets
+        // use more of the shared materials.
+        if self.render_buffers > self.allowed(warmed.render_buffers, warmed) {
+            failures.push(format!(
+                "the renderer holds {} buffers, up from {}",
+                self.render_buffers, warmed.render_buffers
+            ));
+        }
+        if self.render_asset_resources > self.allowed(warmed.render_asset_resources, warmed) {
+            failures.push(format!(
+                "the renderer holds {} asset resources, up from {}",
+                self.render_asset_resources, warmed.render_asset_resources
+            ));
+        }
+        failures
+    }
+
+    /// Materials and meshes, which the renderer holds resources for.
+    fn assets(&self) -> usize {
+        self.materials + self.meshes
+    }
+
+    /// How many of something there may be, given `warmed_count` when warmed up, in proportion to
+    /// the assets and with some room to spare.
+    fn allowed(&self, warmed_count: usize, warmed: &Sample) -> usize {
+        2 * warmed_count * self.assets() / warmed.assets().max(1) + LEAK_TOLERANCE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Sample {
+        Sample {
+            frames: 330,
+            mean_frame_time: Duration::from_millis(4),
+            worst_frame_time: Duration::from_millis(9),
+            rss_kib: Some(200_000),
+            database_bytes: 1 << 20,
+            scenarios: KEEP_SCENARIOS,
+            materials: 50,
+            spare_materials: 3,
+            meshes: 2,
+            render_buffers: 100,
+            render_asset_resources: 200,
+        }
+    }
+
+    #[test]
+    fn steady_sample_passes() {
+        let first = sample();
+        let warmed = sample();
+        let now = Sample {
+            rss_kib: Some(210_000),
+            // Planets have used more colors.
+            materials: 200,
+            spare_materials: 5,
+            render_buffers: 300,
+            render_asset_resources: 600,
+            ..sample()
+        };
+        assert!(now
+            .check(&first, Some(&warmed), Some(Duration::from_millis(16)))
+            .is_empty());
+    }
+
+    #[test]
+    fn finds_leaks_and_slow_frames() {
+        let first = sample();
+        let warmed = sample();
+        let now = Sample {
+            mean_frame_time: Duration::from_millis(20),
+            rss_kib: Some(400_000),
+            scenarios: 2 * KEEP_SCENARIOS,
+            spare_materials: 500,
+            render_buffers: 300,
+            ..sample()
+        };
+        assert_eq!(
+            now.check(&first, Some(&warmed), Some(Duration::from_millis(16)))
+                .len(),
+            5
+        );
+        // Growth is only checked once warmed up.
+        assert_eq!(now.check(&first, None, None).len(), 2);
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HUD counts of the saver's entities, assets and physics bodies, and of the memory it uses, for
+//! checking that nothing piles up over a long lock session. Also shows how far the planets' energy
+//! has drifted, for checking the gravity integrator. Shown when `diagnostics` is set in the
+//! visualization config.
+
+use std::fmt::Write;
+use std::fs;
+
+use bevy::ecs::entity::Entities;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use xsecurelock_saver::engine::SaverTime;
+
+use crate::config::visualization::VisualizationConfig;
+use crate::energy::EnergyDiagnostics;
+
+/// Seconds between updates of the counts.
+const UPDATE_SECONDS: f32 = 1.0;
+
+/// Marker for the diagnostics text.
+pub struct DiagnosticsText;
+
+/// Times updates of the diagnostics text.
+pub struct DiagnosticsTimer(Timer);
+
+impl Default for DiagnosticsTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(UPDATE_SECONDS, true))
+    }
+}
+
+/// What the diagnostics show.
+#[derive(Debug, Clone)]
+struct Counts {
+    entities: u32,
+    bodies: usize,
+    materials: usize,
+    meshes: usize,
+    textures: usize,
+    /// Resident memory, if the kernel reports it.
+    rss_kib: Option<u64>,
+    /// Drift of the planets' energy, if there is a baseline to measure it from.
+    energy_drift: Option<f64>,
+}
+
+/// Updates the diagnostics text once a second.
+#[allow(clippy::too_many_arguments)]
+pub fn show_diagnostics(
+    config: Res<VisualizationConfig>,
+    time: Res<SaverTime>,
+    mut timer: ResMut<DiagnosticsTimer>,
+    entities: &Entities,
+    bodies: Query<(), With<RigidBodyMassProps>>,
+    materials: Res<Assets<StandardMaterial>>,
+    meshes: Res<Assets<Mesh>>,
+    textures: Res<Assets<Texture>>,
+    energy: Res<EnergyDiagnostics>,
+    mut query: Query<&mut Text, With<DiagnosticsText>>,
+) {
+    if !config.diagnostics || !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let counts = Counts {
+        entities: entities.len(),
+        bodies: bodies.iter().count(),
+        materials: materials.len(),
+        meshes: meshes.len(),
+        textures: textures.len(),
+        rss_kib: resident_memory_kib(),
+        energy_drift: energy.drift(),
+    };
+    let value = diagnostics_text(&counts);
+    for mut text in query.iter_mut() {
+        text.sections[0].value = "Diagnostics\n".to_string();
+        text.sections[1].value = value.clone();
+    }
+}
+
+fn diagnostics_text(counts: &Counts) -> String {
+    let mut text = String::new();
+    writeln!(text, "entities  {:>8}", counts.entities).unwrap();
+    writeln!(text, "bodies    {:>8}", counts.bodies).unwrap();
+    writeln!(text, "materials {:>8}", counts.materials).unwrap();
+    writeln!(text, "meshes    {:>8}", counts.meshes).unwrap();
+    writeln!(text, "textures  {:>8}", counts.textures).unwrap();
+    match counts.rss_kib {
+        Some(rss) => writeln!(text, "memory    {:>8.1} MiB", rss as f64 / 1024.0).unwrap(),
+        None => writeln!(text, "memory    {:>8}", "N/A").unwrap(),
+    }
+    match counts.energy_drift {
+        Some(drift) => write!(text, "drift     {:>+8.3} %", drift * 100.0).unwrap(),
+        None => write!(text, "drift     {:>8}", "N/A").unwrap(),
+    }
+    text
+}
+
+/// Reads the process's resident memory from `/proc/self/status`.
+pub fn resident_memory_kib() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_counts() {
+        let counts = Counts {
+            entities: 1234,
+            bodies: 56,
+            materials: 78,
+            meshes: 3,
+            textures: 9,
+            rss_kib: Some(204_800),
+            energy_drift: Some(-0.00125),
+        };
+        assert_eq!(
+            diagnostics_text(&counts),
+            "entities      1234\n\
+             bodies          56\n\
+             materials       78\n\
+             meshes           3\n\
+             textures         9\n\
+             memory       200.0 MiB\n\
+             drift       -0.125 %"
+        );
+        let counts = Counts {
+            rss_kib: None,
+            energy_drift: None,
+            ..counts
+        };
+        assert!(diagnostics_text(&counts).ends_with("memory         N/A\ndrift          N/A"));
+    }
+
+    #[test]
+    fn reads_resident_memory() {
+        if cfg!(target_os = "linux") {
+            assert!(resident_memory_kib().unwrap() > 0);
+        }
+    }
+}
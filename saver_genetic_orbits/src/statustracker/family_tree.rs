@@ -0,0 +1,151 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small HUD graph of the current scenario's recent ancestors. The parent is shown as soon as
+//! the scenario starts, and older ancestors are filled in once the storage thread has looked them
+//! up.
+
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::config::visualization::VisualizationConfig;
+use crate::storage::{Ancestor, Ancestry, AncestryLookup};
+
+use super::ActiveWorld;
+
+/// Number of generations shown, including the current scenario.
+const GENERATIONS: usize = 5;
+
+/// Marker for the family tree text.
+pub struct FamilyTreeText;
+
+/// Shows the current scenario and its parent, and starts looking up older ancestors.
+pub fn request_family_tree(
+    config: Res<VisualizationConfig>,
+    world: Res<ActiveWorld>,
+    lookup: Option<ResMut<AncestryLookup>>,
+    mut query: Query<&mut Text, With<FamilyTreeText>>,
+) {
+    let value = if config.family_tree {
+        let ancestry = world.parent.as_ref().map(|parent| Ancestry {
+            id: parent.id,
+            ancestors: vec![Ancestor::from(parent)],
+            pruned: false,
+        });
+        if let (Some(parent), Some(mut lookup)) = (world.parent.as_ref(), lookup) {
+            lookup.request(parent.id, GENERATIONS - 1);
+        }
+        tree_text(ancestry.as_ref())
+    } else {
+        String::new()
+    };
+    for mut text in query.iter_mut() {
+        text.sections[1].value = value.clone();
+        text.sections[0].value = if value.is_empty() {
+            String::new()
+        } else {
+            "Family Tree\n".to_string()
+        };
+    }
+}
+
+/// Fills in the ancestors once they have been looked up.
+pub fn show_family_tree(
+    config: Res<VisualizationConfig>,
+    world: Res<ActiveWorld>,
+    lookup: Option<ResMut<AncestryLookup>>,
+    mut query: Query<&mut Text, With<FamilyTreeText>>,
+) {
+    let ancestry = match lookup.and_then(|mut lookup| lookup.take_latest()) {
+        Some(ancestry) => ancestry,
+        None => return,
+    };
+    // Lookups which finish after their scenario has ended are for a different parent.
+    let current = world.parent.as_ref().map(|parent| parent.id);
+    if !config.family_tree || current != Some(ancestry.id) {
+        return;
+    }
+    let value = tree_text(Some(&ancestry));
+    for mut text in query.iter_mut() {
+        text.sections[1].value = value.clone();
+    }
+}
+
+/// Draws the ancestors oldest first, joined by lines, ending with the current scenario. Ancestry
+/// is that of the current scenario's parent, or None for a new family.
+fn tree_text(ancestry: Option<&Ancestry>) -> String {
+    let mut tree = String::new();
+    let ancestors = ancestry.map_or(&[][..], |ancestry| &ancestry.ancestors[..]);
+    let pruned = ancestry.map_or(false, |ancestry| ancestry.pruned);
+    match ancestors.last() {
+        Some(_) if pruned => tree.push_str("(pruned)\n  |\n"),
+        Some(oldest) if oldest.generation > 0 => tree.push_str("...\n  |\n"),
+        _ => {}
+    }
+    for ancestor in ancestors.iter().rev() {
+        writeln!(
+            tree,
+            "gen {:<4} #{:<6} {:.2}\n  |",
+            ancestor.generation, ancestor.id, ancestor.score
+        )
+        .unwrap();
+    }
+    let generation = ancestors.first().map_or(0, |parent| parent.generation + 1);
+    write!(tree, "gen {:<4} this", generation).unwrap();
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ancestor(id: u64, generation: u64, score: f64) -> Ancestor {
+        Ancestor {
+            id,
+            generation,
+            score,
+        }
+    }
+
+    #[test]
+    fn new_family() {
+        assert_eq!(tree_text(None), "gen 0    this");
+    }
+
+    #[test]
+    fn full_lineage_from_root() {
+        let ancestry = Ancestry {
+            id: 7,
+            ancestors: vec![ancestor(7, 1, 12.5), ancestor(3, 0, 10.)],
+            pruned: false,
+        };
+        assert_eq!(
+            tree_text(Some(&ancestry)),
+            "gen 0    #3      10.00\n  |\ngen 1    #7      12.50\n  |\ngen 2    this"
+        );
+    }
+
+    #[test]
+    fn marks_truncated_lineage() {
+        let mut ancestry = Ancestry {
+            id: 7,
+            ancestors: vec![ancestor(7, 5, 1.)],
+            pruned: false,
+        };
+        assert!(tree_text(Some(&ancestry)).starts_with("...\n  |\ngen 5 "));
+        ancestry.pruned = true;
+        assert!(tree_text(Some(&ancestry)).starts_with("(pruned)\n  |\ngen 5 "));
+    }
+}
@@ -12,48 +12,73 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp::Ordering;
+use std::fmt;
 use std::mem;
 use std::str::FromStr;
+use std::time::Duration;
 
 use bevy::ecs::component::Component;
 use bevy::prelude::*;
+use bevy_rapier3d::na::Point3;
 use bevy_rapier3d::prelude::*;
 use serde::{Deserialize, Serialize};
+use xsecurelock_saver::engine::{CrashContext, SaverTime};
 
+use crate::bound_pairs::BoundPairs;
 use crate::config::scoring::ScoringConfig;
-use crate::model::{Scenario, World};
-use crate::storage::sqlite::SqliteStorage;
-use crate::storage::Storage;
-use crate::world::Planet;
+use crate::model::{Peak, Planet as PlanetConfig, RadiusLaw, Scenario, World};
+use crate::sleep::Frozen;
+use crate::storage::{BoxedStorage, ScoreboardCache, Storage, StorageEvent};
+use crate::world::{PendingPlanets, Planet};
 use crate::SaverState;
 
-use self::scoring_function::Expression;
+use self::diagnostics::{DiagnosticsText, DiagnosticsTimer};
+use self::family_tree::FamilyTreeText;
+use self::scoring_function::{Expression, ScoringInputs};
+use self::stability::{MassStatistics, StabilityClassifier};
 
+mod diagnostics;
+mod family_tree;
 mod scoring_function;
+mod stability;
+
+pub use self::diagnostics::resident_memory_kib;
 
 pub struct ScoringPlugin;
 
 impl Plugin for ScoringPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<ActiveWorld>()
+            .init_resource::<DiagnosticsTimer>()
             .add_startup_system(setup.system())
+            .add_system(diagnostics::show_diagnostics.system())
             .add_system_set(
                 SystemSet::on_enter(SaverState::Run)
                     .with_system(parent_text.system())
                     .with_system(parent_score_text.system())
                     .with_system(generation_text.system())
                     .with_system(family_text.system())
-                    .with_system(high_score_text::<SqliteStorage>.system()),
+                    .with_system(high_score_text.system())
+                    .with_system(note_scenario.system())
+                    .with_system(family_tree::request_family_tree.system()),
             )
             .add_system_set(
                 SystemSet::on_update(SaverState::Run)
-                    .with_system(score.system().label("compute-score"))
+                    .with_system(
+                        score
+                            .system()
+                            .label("compute-score")
+                            .after("track-bound-pairs"),
+                    )
                     .with_system(score_text.system().after("compute-score"))
-                    .with_system(time_left_text.system().after("compute-score")),
+                    .with_system(time_left_text.system().after("compute-score"))
+                    .with_system(stability_text.system().after("compute-score"))
+                    .with_system(family_tree::show_family_tree.system()),
             )
             .add_system_set(
                 SystemSet::on_exit(SaverState::Run)
-                    .with_system(store_result::<SqliteStorage>.system()),
+                    .with_system(store_result::<BoxedStorage>.system().label("store-result")),
             );
     }
 }
@@ -64,8 +89,25 @@ pub struct ScoringFunction(Expression);
 
 impl ScoringFunction {
     /// Evaluate the expression given the scoring function inputs.
-    pub fn eval(&self, elapsed_fract: f64, total_mass: f64, mass_count: f64) -> f64 {
-        self.0.eval(elapsed_fract, total_mass, mass_count)
+    pub fn eval(&self, inputs: &ScoringInputs) -> f64 {
+        self.0.eval(inputs)
+    }
+
+    /// Whether the expression uses `pairwise_close_encounters`, which is only worth computing if
+    /// it does.
+    pub fn uses_close_encounters(&self) -> bool {
+        self.0.uses_close_encounters()
+    }
+
+    /// Whether the expression uses `bound_pairs`, which is only worth tracking if it does.
+    pub fn uses_bound_pairs(&self) -> bool {
+        self.0.uses_bound_pairs()
+    }
+}
+
+impl fmt::Display for ScoringFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
     }
 }
 
@@ -87,6 +129,21 @@ pub struct ActiveWorld {
     pub cumulative_score: f64,
     /// The number of physics ticks that the world has been scored on so far.
     pub timer: Timer,
+    /// How long the world has been shown for, which may run past the end of `timer`.
+    pub displayed: Duration,
+    /// How long the world will be shown for, given how it is scoring so far.
+    pub display_time: Duration,
+    /// Smoothed score per second, or None before the first scored frame.
+    pub score_rate: Option<f64>,
+    /// If set, the world's result is not stored when the scenario ends, because its score isn't
+    /// comparable to other worlds.
+    pub discard: bool,
+    /// Classifies the world's orbits over the scored time.
+    pub stability: StabilityClassifier,
+    /// The world on the frame it scored fastest so far, or None before the first scored frame.
+    pub peak: Option<Peak>,
+    /// The score per second on the frame the peak was captured.
+    pub peak_score_per_second: f64,
 }
 
 impl ActiveWorld {
@@ -96,6 +153,13 @@ impl ActiveWorld {
         self.parent = parent;
         self.cumulative_score = 0.0;
         self.timer.reset();
+        self.displayed = Duration::from_secs(0);
+        self.display_time = self.timer.duration();
+        self.score_rate = None;
+        self.discard = false;
+        self.stability.reset();
+        self.peak = None;
+        self.peak_score_per_second = f64::NEG_INFINITY;
     }
 }
 
@@ -103,10 +167,17 @@ impl FromWorld for ActiveWorld {
     fn from_world(world: &mut bevy::ecs::world::World) -> Self {
         let config = world.get_resource::<ScoringConfig>().unwrap();
         ActiveWorld {
-            world: World { planets: vec![] },
+            world: World::default(),
             parent: None,
             cumulative_score: 0.,
             timer: Timer::new(config.scored_time, false),
+            displayed: Duration::from_secs(0),
+            display_time: config.scored_time,
+            score_rate: None,
+            discard: false,
+            stability: Default::default(),
+            peak: None,
+            peak_score_per_second: f64::NEG_INFINITY,
         }
     }
 }
@@ -126,6 +197,8 @@ struct HighScoreText;
 
 struct TimeLeftText;
 
+struct StabilityText;
+
 /// Adds a ui camera and score keeper text.
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     const FONT_SIZE: f32 = 18.0;
@@ -243,6 +316,119 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ..Default::default()
                         })
                         .insert(TimeLeftText);
+
+                    left_col
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                align_self: AlignSelf::FlexStart,
+                                ..Default::default()
+                            },
+                            text: Text {
+                                sections: vec![
+                                    TextSection {
+                                        value: "Stability: ".to_string(),
+                                        style: TextStyle {
+                                            font: asset_server.load("fonts/FiraSans-Book.ttf"),
+                                            font_size: FONT_SIZE,
+                                            color: Color::WHITE,
+                                        },
+                                    },
+                                    TextSection {
+                                        value: "N/A".to_string(),
+                                        style: TextStyle {
+                                            font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                                            font_size: FONT_SIZE,
+                                            color: Color::GOLD,
+                                        },
+                                    },
+                                ],
+                                alignment: TextAlignment {
+                                    horizontal: HorizontalAlign::Left,
+                                    vertical: VerticalAlign::Top,
+                                },
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(StabilityText);
+
+                    left_col
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                align_self: AlignSelf::FlexStart,
+                                margin: Rect {
+                                    top: Val::Px(FONT_SIZE),
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            },
+                            text: Text {
+                                sections: vec![
+                                    TextSection {
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font: asset_server.load("fonts/FiraSans-Book.ttf"),
+                                            font_size: FONT_SIZE,
+                                            color: Color::WHITE,
+                                        },
+                                    },
+                                    TextSection {
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                                            font_size: FONT_SIZE * 0.75,
+                                            color: Color::GOLD,
+                                        },
+                                    },
+                                ],
+                                alignment: TextAlignment {
+                                    horizontal: HorizontalAlign::Left,
+                                    vertical: VerticalAlign::Top,
+                                },
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(FamilyTreeText);
+
+                    left_col
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                align_self: AlignSelf::FlexStart,
+                                margin: Rect {
+                                    top: Val::Px(FONT_SIZE),
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            },
+                            text: Text {
+                                sections: vec![
+                                    TextSection {
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font: asset_server.load("fonts/FiraSans-Book.ttf"),
+                                            font_size: FONT_SIZE,
+                                            color: Color::WHITE,
+                                        },
+                                    },
+                                    TextSection {
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                                            font_size: FONT_SIZE * 0.75,
+                                            color: Color::GOLD,
+                                        },
+                                    },
+                                ],
+                                alignment: TextAlignment {
+                                    horizontal: HorizontalAlign::Left,
+                                    vertical: VerticalAlign::Top,
+                                },
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(DiagnosticsText);
                 });
 
                 row.spawn_bundle(NodeBundle {
@@ -436,43 +622,218 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
-/// Compute the scenario score for each frame.
+/// Seconds over which the score rate used to project final scores is smoothed.
+const SCORE_RATE_SMOOTHING_SECS: f64 = 5.0;
+
+/// Compute the scenario score for each frame, and end the scenario once it has been shown for as
+/// long as its score warrants.
+#[allow(clippy::type_complexity)]
 fn score(
-    time: Res<Time>,
+    time: Res<SaverTime>,
     mut world: ResMut<ActiveWorld>,
     config: Res<ScoringConfig>,
-    query: Query<&RigidBodyMassProps, With<Planet>>,
+    query: Query<(&RigidBodyMassProps, &RigidBodyVelocity, Option<&Frozen>), With<Planet>>,
+    bound_pairs: Res<BoundPairs>,
+    pending: Res<PendingPlanets>,
     mut state: ResMut<State<SaverState>>,
 ) {
-    world.timer.tick(time.delta());
+    // The scenario starts once all of its planets are in.
+    if pending.is_spawning() {
+        return;
+    }
+    world.displayed += time.delta();
+    if !world.timer.finished() {
+        world.timer.tick(time.delta());
+        score_frame(&time, &mut world, &config, &query, &bound_pairs);
+    }
 
-    let scenario_time = world.timer.percent() as f64;
-    let mut mass_count = 0.0;
-    let mut total_mass = 0.0;
+    let remaining = world
+        .timer
+        .duration()
+        .saturating_sub(world.timer.elapsed())
+        .as_secs_f64();
+    let projected = world.cumulative_score + world.score_rate.unwrap_or(0.0) * remaining;
+    let reference = world.parent.as_ref().map(|parent| parent.score);
+    world.display_time = display_time(&config, world.timer.duration(), projected, reference);
 
-    let maxx = config.scored_area.width / 2.0;
-    let maxy = config.scored_area.height / 2.0;
-    let maxz = config.scored_area.depth / 2.0;
+    if world.displayed >= world.display_time {
+        if !world.timer.finished() {
+            info!(
+                "Ending scenario early with projected score {:.2}",
+                projected
+            );
+            world.cumulative_score = projected;
+        }
+        state
+            .set(SaverState::Generate)
+            .expect("Unable to switch to scenario generation");
+    }
+}
+
+/// Add this frame's score to the scenario, and capture the world if it is scoring faster than on
+/// any earlier frame.
+#[allow(clippy::type_complexity)]
+fn score_frame(
+    time: &SaverTime,
+    world: &mut ActiveWorld,
+    config: &ScoringConfig,
+    query: &Query<(&RigidBodyMassProps, &RigidBodyVelocity, Option<&Frozen>), With<Planet>>,
+    bound_pairs: &BoundPairs,
+) {
+    let per_second = score_per_second(
+        config,
+        world.timer.percent() as f64,
+        query.iter().map(|(rb, ..)| rb),
+        bound_pairs,
+    );
+    world.stability.observe(MassStatistics::from_masses(
+        query
+            .iter()
+            .map(|(rb, ..)| rb)
+            .filter(|rb| in_scored_area(config, rb))
+            .map(|rb| rb.mass() as f64),
+    ));
+    if per_second > world.peak_score_per_second {
+        world.peak_score_per_second = per_second;
+        world.peak = Some(Peak {
+            seconds: world.timer.elapsed_secs() as f64,
+            world: snapshot(world.world.radius_law, query.iter()),
+        });
+    }
+    let dt = time.delta_seconds_f64();
+    world.cumulative_score += per_second * dt;
+    let blend = 1.0 - (-dt / SCORE_RATE_SMOOTHING_SECS).exp();
+    world.score_rate = Some(match world.score_rate {
+        Some(rate) => rate + (per_second - rate) * blend,
+        None => per_second,
+    });
+}
+
+/// Captures the planets as a world whose planets are sized by `radius_law`. Frozen planets are
+/// given the velocity they will wake with, so the world carries on the same way when it is
+/// started again.
+fn snapshot<'a>(
+    radius_law: RadiusLaw,
+    planets: impl IntoIterator<
+        Item = (
+            &'a RigidBodyMassProps,
+            &'a RigidBodyVelocity,
+            Option<&'a Frozen>,
+        ),
+    >,
+) -> World {
+    World {
+        planets: planets
+            .into_iter()
+            .map(|(rb, velocity, frozen)| {
+                let com = rb.world_com;
+                let linvel = frozen.map_or(&velocity.linvel, |frozen| &frozen.linvel);
+                let angvel = frozen.map_or(&velocity.angvel, |frozen| &frozen.angvel);
+                PlanetConfig {
+                    position: Vec3::new(com.x, com.y, com.z),
+                    velocity: Vec3::new(linvel.x, linvel.y, linvel.z),
+                    mass: rb.mass(),
+                    spin: Vec3::new(angvel.x, angvel.y, angvel.z),
+                }
+            })
+            .collect(),
+        radius_law,
+    }
+}
+
+/// Evaluates the scoring function for planets with the given mass properties, `scenario_time` of
+/// the way through the scored time. `bound_pairs` only needs to be kept up to date if the scoring
+/// function uses it.
+pub fn score_per_second<'a>(
+    config: &ScoringConfig,
+    scenario_time: f64,
+    bodies: impl IntoIterator<Item = &'a RigidBodyMassProps>,
+    bound_pairs: &BoundPairs,
+) -> f64 {
+    let mut inputs = ScoringInputs {
+        elapsed: scenario_time,
+        ..Default::default()
+    };
+    let mut largest_mass = 0.0f64;
+    let count_encounters = config.score_per_second.uses_close_encounters();
+    let mut positions = Vec::new();
+
+    let core_sq = config.core_radius * config.core_radius;
 
-    for rb in query.iter() {
-        if rb.world_com.x.abs() > maxx || rb.world_com.y.abs() > maxy || rb.world_com.z.abs() > maxz
-        {
+    for rb in bodies {
+        if !in_scored_area(config, rb) {
             continue;
         }
-        mass_count += 1.0;
-        total_mass += rb.mass() as f64;
+        let mass = rb.mass() as f64;
+        inputs.mass_count += 1.0;
+        inputs.total_mass += mass;
+        largest_mass = largest_mass.max(mass);
+        if rb.world_com.coords.norm_squared() <= core_sq {
+            inputs.mass_in_core += mass;
+        }
+        if count_encounters {
+            positions.push(rb.world_com);
+        }
+    }
+    if inputs.total_mass > 0.0 {
+        inputs.largest_mass_fraction = largest_mass / inputs.total_mass;
+    }
+    if count_encounters {
+        inputs.pairwise_close_encounters =
+            close_encounters(&mut positions, config.close_encounter_distance) as f64;
+    }
+    if config.score_per_second.uses_bound_pairs() {
+        inputs.bound_pairs = bound_pairs.count(config.bound_pair_time) as f64;
     }
 
-    world.cumulative_score += config
-        .score_per_second
-        .eval(scenario_time, total_mass, mass_count)
-        * time.delta_seconds_f64();
+    config.score_per_second.eval(&inputs)
+}
 
-    if world.timer.just_finished() {
-        state
-            .set(SaverState::Generate)
-            .expect("Unable to switch to scenario generation");
+/// Whether the body's center of mass is inside the scored area.
+pub fn in_scored_area(config: &ScoringConfig, rb: &RigidBodyMassProps) -> bool {
+    rb.world_com.x.abs() <= config.scored_area.width / 2.0
+        && rb.world_com.y.abs() <= config.scored_area.height / 2.0
+        && rb.world_com.z.abs() <= config.scored_area.depth / 2.0
+}
+
+/// Counts the pairs of points closer than `distance` to each other. Sorts the points along x first,
+/// so each point is only compared with the points which follow it closely along x.
+fn close_encounters(points: &mut [Point3<f32>], distance: f32) -> usize {
+    points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+    let distance_sq = distance * distance;
+    let mut count = 0;
+    for (i, a) in points.iter().enumerate() {
+        for b in &points[i + 1..] {
+            if b.x - a.x >= distance {
+                break;
+            }
+            if (b - a).norm_squared() < distance_sq {
+                count += 1;
+            }
+        }
     }
+    count
+}
+
+/// How long to show a scenario scored over `scored_time` whose score is projected to finish at
+/// `projected`, given the `reference` score it is competing against, if any. Scales `scored_time`
+/// by how the projected score compares to the reference, within the configured bounds.
+fn display_time(
+    config: &ScoringConfig,
+    scored_time: Duration,
+    projected: f64,
+    reference: Option<f64>,
+) -> Duration {
+    let ratio = match reference {
+        Some(reference) if reference > 0.0 && projected.is_finite() => {
+            (projected / reference).max(0.0)
+        }
+        _ => 1.0,
+    };
+    let secs = (scored_time.as_secs_f64() * ratio)
+        .max(config.min_display_time.as_secs_f64())
+        .min(config.max_display_time.as_secs_f64());
+    Duration::from_secs_f64(secs)
 }
 
 /// Put the score in the score text.
@@ -492,6 +853,25 @@ fn parent_text(world: Res<ActiveWorld>, mut query: Query<&mut Text, With<ParentT
     }
 }
 
+/// Notes which scenario the world came from in crash reports. The world itself has no id until its
+/// result is stored.
+fn note_scenario(world: Res<ActiveWorld>, context: Option<Res<CrashContext>>) {
+    if let Some(context) = context {
+        context.set(
+            "scenario",
+            match world.parent {
+                None => "new root".to_string(),
+                Some(ref parent) => format!(
+                    "child of {} in family {}, generation {}",
+                    parent.id,
+                    parent.family,
+                    parent.generation + 1
+                ),
+            },
+        );
+    }
+}
+
 /// Add the parent score.
 fn parent_score_text(world: Res<ActiveWorld>, mut query: Query<&mut Text, With<ParentScoreText>>) {
     for mut text in query.iter_mut() {
@@ -523,23 +903,21 @@ fn family_text(world: Res<ActiveWorld>, mut query: Query<&mut Text, With<FamilyT
 }
 
 /// Add the high score
-fn high_score_text<S: Storage + Component>(
-    mut storage: ResMut<S>,
+fn high_score_text(
+    scoreboard: Res<ScoreboardCache>,
     mut query: Query<&mut Text, With<HighScoreText>>,
 ) {
-    let highest = storage.get_nth_scenario_by_score(0).unwrap();
     for mut text in query.iter_mut() {
-        match highest {
+        match scoreboard.high_score() {
             None => text.sections[1].value = "None".to_string(),
-            Some(ref highest) => text.sections[1].value = format!("{:.2}", highest.score),
+            Some(highest) => text.sections[1].value = format!("{:.2}", highest),
         }
     }
 }
 
 /// Show the time remaining
 fn time_left_text(world: Res<ActiveWorld>, mut query: Query<&mut Text, With<TimeLeftText>>) {
-    let duration = world.timer.duration();
-    let remaining = duration.mul_f32(world.timer.percent_left());
+    let remaining = world.display_time.saturating_sub(world.displayed);
     let secs = remaining.as_secs();
     let mins = secs / 60;
     let secs = secs % 60;
@@ -549,11 +927,35 @@ fn time_left_text(world: Res<ActiveWorld>, mut query: Query<&mut Text, With<Time
     }
 }
 
+/// Show the scenario's classification so far, and how much of its mass has escaped.
+fn stability_text(world: Res<ActiveWorld>, mut query: Query<&mut Text, With<StabilityText>>) {
+    let value = match world.stability.stability() {
+        None => "N/A".to_string(),
+        Some(stability) => format!(
+            "{} ({:.0}% escaped)",
+            stability,
+            world.stability.escaped_fraction() * 100.0
+        ),
+    };
+    for mut text in query.iter_mut() {
+        text.sections[1].value = value.clone();
+    }
+}
+
 /// Store scenario results.
-fn store_result<S: Storage + Component>(mut tracker: ResMut<ActiveWorld>, mut storage: ResMut<S>) {
-    info!("Storing scored world");
+fn store_result<S: Storage + Component>(
+    mut tracker: ResMut<ActiveWorld>,
+    mut storage: ResMut<S>,
+    mut events: EventWriter<StorageEvent>,
+) {
     let world = mem::replace(&mut tracker.world, World::default());
     let parent = mem::replace(&mut tracker.parent, None);
+    if tracker.discard {
+        info!("Discarding scored world");
+        return;
+    }
+    info!("Storing scored world");
+    let stability = tracker.stability.stability();
     let score = if tracker.cumulative_score.is_nan() {
         warn!("Score was NaN, replacing with -inf");
         f64::NEG_INFINITY
@@ -566,9 +968,100 @@ fn store_result<S: Storage + Component>(mut tracker: ResMut<ActiveWorld>, mut st
     };
     match store_result {
         Err(error) => error!("Error while storing finished scenario: {}", error),
-        Ok(scenario) => info!(
-            "Saved scenario {} (parent: {:?}, family: {}, generation: {}) with score {}",
-            scenario.id, scenario.parent, scenario.family, scenario.generation, scenario.score,
-        ),
+        Ok(scenario) => {
+            info!(
+                "Saved scenario {} (parent: {:?}, family: {}, generation: {}) with score {}",
+                scenario.id, scenario.parent, scenario.family, scenario.generation, scenario.score,
+            );
+            if let Some(stability) = stability {
+                info!("Scenario {} classified as {}", scenario.id, stability);
+                if let Err(error) = storage.set_stability(scenario.id, stability) {
+                    error!("Error while storing scenario classification: {}", error);
+                }
+            }
+            if let Some(peak) = tracker.peak.take() {
+                if let Err(error) = storage.set_peak(scenario.id, &peak) {
+                    error!("Error while storing scenario peak: {}", error);
+                }
+            }
+            events.send(StorageEvent::Stored {
+                score: scenario.score,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ScoringConfig {
+        ScoringConfig {
+            min_display_time: Duration::from_secs(30),
+            max_display_time: Duration::from_secs(120),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn counts_close_encounters() {
+        let mut points = vec![
+            Point3::new(0., 0., 0.),
+            Point3::new(300., 0., 0.),
+            Point3::new(5., 50., 0.),
+            Point3::new(8., 0., 120.),
+            Point3::new(350., 20., 20.),
+        ];
+        // (0, 2) and (1, 4) are close; (0, 3) is close in x but not in z.
+        assert_eq!(close_encounters(&mut points, 100.), 2);
+        assert_eq!(close_encounters(&mut points, 10.), 0);
+        assert_eq!(close_encounters(&mut [], 100.), 0);
+    }
+
+    #[test]
+    fn display_time_scales_with_projected_score() {
+        let scored_time = Duration::from_secs(60);
+        assert_eq!(
+            display_time(&config(), scored_time, 150.0, Some(100.0)),
+            Duration::from_secs(90),
+        );
+        assert_eq!(
+            display_time(&config(), scored_time, 75.0, Some(100.0)),
+            Duration::from_secs(45),
+        );
+    }
+
+    #[test]
+    fn display_time_is_bounded() {
+        let scored_time = Duration::from_secs(60);
+        assert_eq!(
+            display_time(&config(), scored_time, 1.0, Some(100.0)),
+            Duration::from_secs(30),
+        );
+        assert_eq!(
+            display_time(&config(), scored_time, 1e9, Some(100.0)),
+            Duration::from_secs(120),
+        );
+        assert_eq!(
+            display_time(&config(), scored_time, -50.0, Some(100.0)),
+            Duration::from_secs(30),
+        );
+    }
+
+    #[test]
+    fn display_time_without_reference_is_scored_time() {
+        let scored_time = Duration::from_secs(60);
+        assert_eq!(
+            display_time(&config(), scored_time, 10.0, None),
+            scored_time
+        );
+        assert_eq!(
+            display_time(&config(), scored_time, 10.0, Some(-5.0)),
+            scored_time
+        );
+        assert_eq!(
+            display_time(&config(), scored_time, f64::NAN, Some(100.0)),
+            scored_time
+        );
     }
 }
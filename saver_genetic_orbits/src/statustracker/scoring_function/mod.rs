@@ -27,8 +27,27 @@ lalrpop_mod!(
 mod expression_serde;
 mod transforms;
 
+/// Per-frame values which a scoring function can use.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScoringInputs {
+    /// The fraction of run time that is elapsed.
+    pub elapsed: f64,
+    /// The total mass for the frame.
+    pub total_mass: f64,
+    /// The number of masses for the frame.
+    pub mass_count: f64,
+    /// The total mass within the core radius.
+    pub mass_in_core: f64,
+    /// The largest single mass as a fraction of the total mass.
+    pub largest_mass_fraction: f64,
+    /// The number of pairs of masses within the close encounter distance of each other.
+    pub pairwise_close_encounters: f64,
+    /// The number of pairs of masses which have stayed bound to each other for the bound pair time.
+    pub bound_pairs: f64,
+}
+
 /// Expression for computing the per-frame score for a scene from that frame's total mass and total
-/// mass count and the fraction of runtime that is elapsed from 0 to 1.
+/// mass count and the fraction of runtime that is elapsed from 0 to 1, plus a few derived values.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     /// The fraction of run time that is elapsed.
@@ -37,6 +56,14 @@ pub enum Expression {
     TotalMass,
     /// The number of masses for the frame.
     MassCount,
+    /// The total mass within the core radius for the frame.
+    MassInCore,
+    /// The largest mass as a fraction of the total mass for the frame.
+    LargestMassFraction,
+    /// The number of pairs of masses in close encounters for the frame.
+    PairwiseCloseEncounters,
+    /// The number of long-lived bound pairs of masses for the frame.
+    BoundPairs,
     /// A floating point constant.
     Constant(f64),
     /// An operation applied to two expressions.
@@ -47,23 +74,46 @@ pub enum Expression {
 
 impl Expression {
     /// Evaluate the expression given the scoring function inputs.
-    pub fn eval(&self, elapsed: f64, total_mass: f64, mass_count: f64) -> f64 {
+    pub fn eval(&self, inputs: &ScoringInputs) -> f64 {
         match self {
-            Expression::Elapsed => elapsed,
-            Expression::TotalMass => total_mass,
-            Expression::MassCount => mass_count,
+            Expression::Elapsed => inputs.elapsed,
+            Expression::TotalMass => inputs.total_mass,
+            Expression::MassCount => inputs.mass_count,
+            Expression::MassInCore => inputs.mass_in_core,
+            Expression::LargestMassFraction => inputs.largest_mass_fraction,
+            Expression::PairwiseCloseEncounters => inputs.pairwise_close_encounters,
+            Expression::BoundPairs => inputs.bound_pairs,
             Expression::Constant(value) => *value,
             Expression::BinaryOp(left, op, right) => {
-                let left = left.eval(elapsed, total_mass, mass_count);
-                let right = right.eval(elapsed, total_mass, mass_count);
+                let left = left.eval(inputs);
+                let right = right.eval(inputs);
                 op.eval(left, right)
             }
             Expression::UnaryOp(op, value) => {
-                let value = value.eval(elapsed, total_mass, mass_count);
+                let value = value.eval(inputs);
                 op.eval(value)
             }
         }
     }
+
+    /// Whether the expression uses the number of close encounters, which is expensive to compute.
+    pub fn uses_close_encounters(&self) -> bool {
+        self.uses(&Expression::PairwiseCloseEncounters)
+    }
+
+    /// Whether the expression uses the number of bound pairs, which is expensive to compute.
+    pub fn uses_bound_pairs(&self) -> bool {
+        self.uses(&Expression::BoundPairs)
+    }
+
+    /// Whether the expression contains the given input anywhere.
+    fn uses(&self, input: &Expression) -> bool {
+        match self {
+            Expression::BinaryOp(left, _, right) => left.uses(input) || right.uses(input),
+            Expression::UnaryOp(_, value) => value.uses(input),
+            atom => atom == input,
+        }
+    }
 }
 
 impl Expression {
@@ -148,6 +198,10 @@ impl Expression {
             Expression::Elapsed => 5,
             Expression::TotalMass => 5,
             Expression::MassCount => 5,
+            Expression::MassInCore => 5,
+            Expression::LargestMassFraction => 5,
+            Expression::PairwiseCloseEncounters => 5,
+            Expression::BoundPairs => 5,
             Expression::Constant(_) => 5,
             Expression::BinaryOp(_, op, _) => op.precedence(),
             Expression::UnaryOp(..) => 4,
@@ -169,6 +223,10 @@ impl fmt::Display for Expression {
             Expression::Elapsed => f.pad("elapsed"),
             Expression::TotalMass => f.pad("total_mass"),
             Expression::MassCount => f.pad("mass_count"),
+            Expression::MassInCore => f.pad("mass_in_core"),
+            Expression::LargestMassFraction => f.pad("largest_mass_fraction"),
+            Expression::PairwiseCloseEncounters => f.pad("pairwise_close_encounters"),
+            Expression::BoundPairs => f.pad("bound_pairs"),
             Expression::Constant(v) => f.pad(&format!("{}", v)),
             Expression::BinaryOp(lhs, op, rhs) => {
                 let mut self_string = if lhs.precedence() < op.precedence() {
@@ -293,9 +351,22 @@ mod tests {
     const ELAPSED: f64 = 9.;
     const TOTAL_MASS: f64 = 486.8;
     const MASS_COUNT: f64 = 77.;
+    const MASS_IN_CORE: f64 = 120.5;
+    const LARGEST_MASS_FRACTION: f64 = 0.25;
+    const PAIRWISE_CLOSE_ENCOUNTERS: f64 = 3.;
+    const BOUND_PAIRS: f64 = 2.;
 
     fn assert_eval(expr: Expression, expected: f64) {
-        assert_eq!(expr.eval(ELAPSED, TOTAL_MASS, MASS_COUNT), expected);
+        let inputs = ScoringInputs {
+            elapsed: ELAPSED,
+            total_mass: TOTAL_MASS,
+            mass_count: MASS_COUNT,
+            mass_in_core: MASS_IN_CORE,
+            largest_mass_fraction: LARGEST_MASS_FRACTION,
+            pairwise_close_encounters: PAIRWISE_CLOSE_ENCOUNTERS,
+            bound_pairs: BOUND_PAIRS,
+        };
+        assert_eq!(expr.eval(&inputs), expected);
     }
 
     #[test]
@@ -313,6 +384,33 @@ mod tests {
         assert_eval(MassCount, MASS_COUNT);
     }
 
+    #[test]
+    fn eval_derived_inputs() {
+        assert_eval(MassInCore, MASS_IN_CORE);
+        assert_eval(LargestMassFraction, LARGEST_MASS_FRACTION);
+        assert_eval(PairwiseCloseEncounters, PAIRWISE_CLOSE_ENCOUNTERS);
+        assert_eval(BoundPairs, BOUND_PAIRS);
+    }
+
+    #[test]
+    fn uses_close_encounters() {
+        let uses: Expression = "total_mass * (1 + pairwise_close_encounters)"
+            .parse()
+            .unwrap();
+        assert!(uses.uses_close_encounters());
+        let unused: Expression = "total_mass * mass_in_core".parse().unwrap();
+        assert!(!unused.uses_close_encounters());
+    }
+
+    #[test]
+    fn uses_bound_pairs() {
+        let uses: Expression = "log(1 + bound_pairs) * mass_count".parse().unwrap();
+        assert!(uses.uses_bound_pairs());
+        assert!(!uses.uses_close_encounters());
+        let unused: Expression = "pairwise_close_encounters".parse().unwrap();
+        assert!(!unused.uses_bound_pairs());
+    }
+
     #[test]
     fn eval_constant() {
         assert_eval(Constant(88.97), 88.97);
@@ -450,6 +548,26 @@ mod tests {
         assert_eq!(Expression::parse_unsimplified("MaSs_CoUnT"), Ok(MassCount));
     }
 
+    #[test]
+    fn parse_derived_inputs() {
+        assert_eq!(
+            Expression::parse_unsimplified("mass_in_core"),
+            Ok(MassInCore)
+        );
+        assert_eq!(
+            Expression::parse_unsimplified("LARGEST_MASS_FRACTION"),
+            Ok(LargestMassFraction)
+        );
+        assert_eq!(
+            Expression::parse_unsimplified("pairwise_close_encounters"),
+            Ok(PairwiseCloseEncounters)
+        );
+        assert_eq!(
+            Expression::parse_unsimplified("Bound_Pairs"),
+            Ok(BoundPairs)
+        );
+    }
+
     #[test]
     fn parse_add() {
         let expected = add(1, 2);
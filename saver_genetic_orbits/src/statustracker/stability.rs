@@ -0,0 +1,158 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Classifies how the running scenario's orbits behave, from how its mass is distributed between
+//! the planets in the scored area and how much of it has escaped.
+
+use crate::model::Stability;
+
+/// Fraction of the starting mass which must have left the scored area for a scenario to be
+/// dispersing.
+const DISPERSING_ESCAPED_FRACTION: f64 = 0.5;
+
+/// Fraction of the remaining mass the largest planet must hold for a scenario to be
+/// single-dominant.
+const SINGLE_DOMINANT_MASS_FRACTION: f64 = 0.9;
+
+/// How many times heavier than the next largest planet the largest must be for a scenario to be
+/// hierarchical.
+const HIERARCHICAL_MASS_RATIO: f64 = 4.0;
+
+/// Masses of the planets in the scored area on one frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MassStatistics {
+    pub total: f64,
+    pub largest: f64,
+    pub second_largest: f64,
+}
+
+impl MassStatistics {
+    pub fn from_masses(masses: impl IntoIterator<Item = f64>) -> Self {
+        let mut stats = Self::default();
+        for mass in masses {
+            stats.total += mass;
+            if mass > stats.largest {
+                stats.second_largest = stats.largest;
+                stats.largest = mass;
+            } else if mass > stats.second_largest {
+                stats.second_largest = mass;
+            }
+        }
+        stats
+    }
+}
+
+/// Follows the mass statistics of the running scenario to classify it.
+#[derive(Debug, Clone, Default)]
+pub struct StabilityClassifier {
+    /// Mass in the scored area on the first frame observed.
+    initial_mass: Option<f64>,
+    /// The most recent frame observed.
+    latest: MassStatistics,
+}
+
+impl StabilityClassifier {
+    /// Forgets the last scenario, so the next frame observed is taken as the start of a new one.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Updates the classification with a scored frame.
+    pub fn observe(&mut self, stats: MassStatistics) {
+        self.initial_mass.get_or_insert(stats.total);
+        self.latest = stats;
+    }
+
+    /// Fraction of the starting mass which has left the scored area. Planets merging keeps their
+    /// mass, so this only counts mass which has escaped.
+    pub fn escaped_fraction(&self) -> f64 {
+        match self.initial_mass {
+            Some(initial) if initial > 0.0 => (1.0 - self.latest.total / initial).max(0.0),
+            _ => 0.0,
+        }
+    }
+
+    /// The classification of the scenario so far, or None if no frames with planets in the scored
+    /// area have been observed.
+    pub fn stability(&self) -> Option<Stability> {
+        if self.initial_mass.unwrap_or(0.0) <= 0.0 {
+            return None;
+        }
+        if self.escaped_fraction() >= DISPERSING_ESCAPED_FRACTION || self.latest.total <= 0.0 {
+            return Some(Stability::Dispersing);
+        }
+        let stats = &self.latest;
+        if stats.largest >= stats.total * SINGLE_DOMINANT_MASS_FRACTION {
+            Some(Stability::SingleDominant)
+        } else if stats.largest >= stats.second_largest * HIERARCHICAL_MASS_RATIO {
+            Some(Stability::Hierarchical)
+        } else {
+            Some(Stability::Chaotic)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(initial: &[f64], latest: &[f64]) -> Option<Stability> {
+        let mut classifier = StabilityClassifier::default();
+        classifier.observe(MassStatistics::from_masses(initial.iter().copied()));
+        classifier.observe(MassStatistics::from_masses(latest.iter().copied()));
+        classifier.stability()
+    }
+
+    #[test]
+    fn measures_largest_masses() {
+        assert_eq!(
+            MassStatistics::from_masses(vec![3., 10., 1., 7.]),
+            MassStatistics {
+                total: 21.,
+                largest: 10.,
+                second_largest: 7.,
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_mass_distributions() {
+        let start = [10., 10., 10., 10.];
+        assert_eq!(classify(&start, &start), Some(Stability::Chaotic));
+        assert_eq!(
+            classify(&start, &[30., 5., 3., 2.]),
+            Some(Stability::Hierarchical)
+        );
+        assert_eq!(
+            classify(&start, &[38., 2.]),
+            Some(Stability::SingleDominant)
+        );
+        assert_eq!(classify(&start, &[10., 5.]), Some(Stability::Dispersing));
+        assert_eq!(classify(&start, &[]), Some(Stability::Dispersing));
+        assert_eq!(classify(&[], &[]), None);
+    }
+
+    #[test]
+    fn reset_starts_a_new_scenario() {
+        let mut classifier = StabilityClassifier::default();
+        classifier.observe(MassStatistics::from_masses(vec![10., 10.]));
+        classifier.observe(MassStatistics::from_masses(vec![2.]));
+        assert_eq!(classifier.escaped_fraction(), 0.9);
+        classifier.reset();
+        assert_eq!(classifier.stability(), None);
+        classifier.observe(MassStatistics::from_masses(vec![2.]));
+        assert_eq!(classifier.escaped_fraction(), 0.0);
+        assert_eq!(classifier.stability(), Some(Stability::SingleDominant));
+    }
+}
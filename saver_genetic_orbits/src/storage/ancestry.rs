@@ -0,0 +1,207 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+
+use log::{error, info};
+use xsecurelock_saver::priority;
+
+use crate::model::Scenario;
+
+use super::Storage;
+
+/// A scenario in an ancestry, without its world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ancestor {
+    pub id: u64,
+    pub generation: u64,
+    pub score: f64,
+}
+
+impl From<&Scenario> for Ancestor {
+    fn from(scenario: &Scenario) -> Self {
+        Ancestor {
+            id: scenario.id,
+            generation: scenario.generation,
+            score: scenario.score,
+        }
+    }
+}
+
+/// The result of looking up a scenario's ancestors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ancestry {
+    /// Id of the scenario the lookup started from.
+    pub id: u64,
+    /// The scenario and its ancestors, nearest first.
+    pub ancestors: Vec<Ancestor>,
+    /// Whether the lookup stopped because the next ancestor has been pruned, rather than because
+    /// it reached the root of the family or the requested depth.
+    pub pruned: bool,
+}
+
+/// Looks up the ancestors of scenarios on a remote thread with its own connection, so reading
+/// them doesn't hold up a frame.
+pub struct AncestryLookup {
+    join_handle: Option<JoinHandle<()>>,
+    sender: Option<Sender<(u64, usize)>>,
+    /// Receives each finished lookup.
+    finished: Receiver<Ancestry>,
+}
+
+// This is safe because we require &mut Self for all methods that access sender and finished, so
+// sharing &self is safe though not useful.
+unsafe impl Sync for AncestryLookup {}
+
+impl AncestryLookup {
+    /// Creates a lookup running on a remote thread.
+    pub fn new<S>(storage: S) -> AncestryLookup
+    where
+        S: Storage + Send + 'static,
+    {
+        let (sender, recv) = mpsc::channel();
+        let (finished_sender, finished) = mpsc::channel();
+        let join_handle = priority::spawn_named("orbits-ancestry", move || {
+            let mut storage = storage;
+            for (id, depth) in recv {
+                // Nobody is listening during shutdown, which is fine.
+                let _ = finished_sender.send(lookup(&mut storage, id, depth));
+            }
+        });
+
+        AncestryLookup {
+            join_handle: Some(join_handle),
+            sender: Some(sender),
+            finished,
+        }
+    }
+
+    /// Start looking up the scenario with the given id and up to `depth - 1` of its ancestors.
+    // this has to be mut so that Sender isn't accidentally shared across threads.
+    pub fn request(&mut self, id: u64, depth: usize) {
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send((id, depth))
+            .expect("Ancestry lookup shut down unexpectedly");
+    }
+
+    /// Returns the most recently finished lookup, if any have finished since this was last called.
+    pub fn take_latest(&mut self) -> Option<Ancestry> {
+        self.finished.try_iter().last()
+    }
+}
+
+/// Follows parents from the scenario with the given id until `depth` scenarios are found, the root
+/// is reached, or a scenario is missing. Errors end the lookup early with whatever was found.
+fn lookup<S: Storage>(storage: &mut S, id: u64, depth: usize) -> Ancestry {
+    let mut ancestry = Ancestry {
+        id,
+        ancestors: Vec::with_capacity(depth),
+        pruned: false,
+    };
+    let mut next = Some(id);
+    while let Some(id) = next {
+        if ancestry.ancestors.len() >= depth {
+            break;
+        }
+        match storage.get_scenario(id) {
+            Ok(Some(scenario)) => {
+                ancestry.ancestors.push(Ancestor::from(&scenario));
+                next = scenario.parent;
+            }
+            Ok(None) => {
+                ancestry.pruned = true;
+                break;
+            }
+            Err(err) => {
+                error!("Failed to look up ancestor {}: {}", id, err);
+                break;
+            }
+        }
+    }
+    ancestry
+}
+
+impl Drop for AncestryLookup {
+    fn drop(&mut self) {
+        self.sender.take().unwrap();
+        self.join_handle
+            .take()
+            .unwrap()
+            .join()
+            .expect("Remote thread paniced");
+        info!("Ancestry lookup shutdown successfully.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::World;
+    use crate::storage::sqlite::SqliteStorage;
+
+    #[test]
+    fn follows_parents_up_to_depth() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let mut scenario = storage.add_root_scenario(World::default(), 0.).unwrap();
+        for score in 1..5 {
+            scenario = storage
+                .add_child_scenario(World::default(), score as f64, &scenario)
+                .unwrap();
+        }
+
+        let ancestry = lookup(&mut storage, scenario.id, 3);
+        let generations: Vec<u64> = ancestry.ancestors.iter().map(|a| a.generation).collect();
+        assert_eq!(generations, vec![4, 3, 2]);
+        assert!(!ancestry.pruned);
+
+        let ancestry = lookup(&mut storage, scenario.id, 10);
+        assert_eq!(ancestry.ancestors.len(), 5);
+        assert_eq!(ancestry.ancestors[4].generation, 0);
+        assert!(!ancestry.pruned);
+    }
+
+    #[test]
+    fn stops_at_pruned_ancestor() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let root = storage.add_root_scenario(World::default(), 0.).unwrap();
+        let child = storage
+            .add_child_scenario(World::default(), 2., &root)
+            .unwrap();
+        let grandchild = storage
+            .add_child_scenario(World::default(), 1., &child)
+            .unwrap();
+        storage.keep_top_scenarios_by_score(2).unwrap();
+
+        let ancestry = lookup(&mut storage, grandchild.id, 5);
+        assert_eq!(
+            ancestry.ancestors,
+            vec![
+                Ancestor {
+                    id: grandchild.id,
+                    generation: 2,
+                    score: 1.
+                },
+                Ancestor {
+                    id: child.id,
+                    generation: 1,
+                    score: 2.
+                },
+            ]
+        );
+        assert!(ancestry.pruned);
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rotating backups of the scenario database, taken before migrations and large prunes.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::database::DatabaseConfig;
+
+use super::{sqlite, Storage, StorageError};
+
+/// Prefix of backup file names.
+const BACKUP_PREFIX: &str = "scenario-db-";
+
+/// Extension of backup file names.
+const BACKUP_EXTENSION: &str = ".sqlite3";
+
+/// A directory of rotating database backups.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    /// Directory the backups are stored in.
+    directory: PathBuf,
+    /// Number of backups to keep.
+    keep: usize,
+    /// Minimum number of scenarios a prune must remove to be worth a backup.
+    min_scenarios_pruned: u64,
+}
+
+impl Backups {
+    /// Gets the backup settings from the config. Returns None if backups are disabled or there is
+    /// nowhere to put them.
+    pub fn from_conf(conf: &DatabaseConfig) -> Option<Backups> {
+        if !conf.backup.enabled || conf.backup.keep == 0 {
+            return None;
+        }
+        let directory = match (&conf.backup.directory, &conf.database_path) {
+            (Some(directory), _) => directory.clone(),
+            (None, Some(database_path)) => database_path.parent()?.join("backups"),
+            (None, None) => return None,
+        };
+        Some(Backups {
+            directory,
+            keep: conf.backup.keep,
+            min_scenarios_pruned: conf.backup.min_scenarios_pruned,
+        })
+    }
+
+    /// Returns true if a prune which removes the given number of scenarios should be preceded by
+    /// a backup.
+    pub fn should_backup_before_prune(&self, num_pruned: u64) -> bool {
+        num_pruned > 0 && num_pruned >= self.min_scenarios_pruned
+    }
+
+    /// Backs up the storage to a new file, then deletes the oldest backups beyond the number to
+    /// keep. Returns the path of the new backup.
+    pub fn backup<S: Storage>(&self, storage: &mut S) -> Result<PathBuf, StorageError> {
+        self.backup_with(|path| storage.backup_to(path))
+    }
+
+    /// Like [`Backups::backup`], for the sqlite database at `database`, which is copied as it is
+    /// rather than opened as storage so it can be backed up before it is migrated.
+    pub fn backup_file(&self, database: &Path) -> Result<PathBuf, StorageError> {
+        self.backup_with(|path| sqlite::backup_file(database, path))
+    }
+
+    /// Writes a new backup with `write`, then rotates the old ones.
+    fn backup_with(
+        &self,
+        write: impl FnOnce(&Path) -> Result<(), StorageError>,
+    ) -> Result<PathBuf, StorageError> {
+        fs::create_dir_all(&self.directory)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = self.directory.join(format!(
+            "{}{:016}{}",
+            BACKUP_PREFIX, timestamp, BACKUP_EXTENSION,
+        ));
+        write(&path)?;
+        self.rotate()?;
+        Ok(path)
+    }
+
+    /// Deletes the oldest backups so that at most `keep` remain.
+    fn rotate(&self) -> io::Result<()> {
+        let mut backups = vec![];
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let is_backup = name.to_str().map_or(false, |name| {
+                name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_EXTENSION)
+            });
+            if is_backup {
+                backups.push(entry.path());
+            }
+        }
+        // Timestamps are zero padded, so sorting by name sorts oldest first.
+        backups.sort();
+        let excess = backups.len().saturating_sub(self.keep);
+        for old in &backups[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteStorage;
+
+    #[test]
+    fn keeps_newest_backups() {
+        let mut directory = std::env::temp_dir();
+        directory.push(format!(
+            "genetic-orbits-backups-test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let unrelated = directory.join("notes.txt");
+        fs::write(&unrelated, "not a backup").unwrap();
+
+        let backups = Backups {
+            directory: directory.clone(),
+            keep: 2,
+            min_scenarios_pruned: 1,
+        };
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let mut paths = vec![];
+        for _ in 0..3 {
+            paths.push(backups.backup(&mut storage).unwrap());
+            // Make sure each backup gets a distinct timestamp.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        assert!(!paths[0].exists());
+        assert!(paths[1].exists());
+        assert!(paths[2].exists());
+        assert!(unrelated.exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn only_large_prunes_are_backed_up() {
+        let backups = Backups {
+            directory: PathBuf::new(),
+            keep: 1,
+            min_scenarios_pruned: 10,
+        };
+        assert!(!backups.should_backup_before_prune(0));
+        assert!(!backups.should_backup_before_prune(9));
+        assert!(backups.should_backup_before_prune(10));
+    }
+}
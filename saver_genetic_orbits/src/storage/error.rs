@@ -0,0 +1,84 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTimeError;
+
+use rusqlite::Error as SqlError;
+
+/// Errors from reading or writing scenario storage.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The sqlite database returned an error.
+    Sqlite(SqlError),
+    /// A write changed a different number of rows than it should have.
+    RowCount { expected: usize, actual: usize },
+    /// A path couldn't be passed to sqlite because it isn't valid unicode.
+    InvalidPath(PathBuf),
+    /// Reading or writing files outside the database failed.
+    Io(io::Error),
+    /// The system clock is set before the unix epoch.
+    Clock(SystemTimeError),
+    /// The storage backend doesn't support the operation.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(err) => write!(f, "sqlite error: {}", err),
+            StorageError::RowCount { expected, actual } => write!(
+                f,
+                "expected to change {} rows but had {} row changes",
+                expected, actual
+            ),
+            StorageError::InvalidPath(path) => write!(f, "path {:?} is not valid unicode", path),
+            StorageError::Io(err) => write!(f, "io error: {}", err),
+            StorageError::Clock(err) => write!(f, "system clock error: {}", err),
+            StorageError::Unsupported(operation) => write!(f, "unsupported: {}", operation),
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StorageError::Sqlite(err) => Some(err),
+            StorageError::Io(err) => Some(err),
+            StorageError::Clock(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<SqlError> for StorageError {
+    fn from(err: SqlError) -> Self {
+        StorageError::Sqlite(err)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+impl From<SystemTimeError> for StorageError {
+    fn from(err: SystemTimeError) -> Self {
+        StorageError::Clock(err)
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Versioned JSON format for the worlds stored in the database. Worlds are written as an envelope
+//! tagged with the format version, like `{"v":2,"planets":[...]}`. Worlds written before the tag
+//! was added have no `"v"` and are read as version 1. Version 1 also covers databases written by
+//! the old 2D saver, whose vectors have only x and y; those worlds are placed in the z = 0 plane.
+//!
+//! When the model changes, bump [`CURRENT_VERSION`] and add an upgrade step from the previous
+//! version to [`upgrade`], so databases written by older savers keep loading.
+
+use std::error::Error;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::model::World;
+
+/// Version of the format written by [`to_json`].
+pub const CURRENT_VERSION: u64 = 2;
+
+/// Key of the version tag in the envelope.
+const VERSION_KEY: &str = "v";
+
+/// Keys of the vectors in each planet.
+const VECTOR_KEYS: [&str; 2] = ["position", "velocity"];
+
+/// A world tagged with its format version, for writing.
+#[derive(Serialize)]
+struct EnvelopeRef<'a> {
+    v: u64,
+    #[serde(flatten)]
+    world: &'a World,
+}
+
+/// Reasons a stored world can't be read.
+#[derive(Debug)]
+pub enum FormatError {
+    /// The text isn't valid JSON, or doesn't match the world model of its version.
+    Json(serde_json::Error),
+    /// The JSON isn't an object.
+    NotAnObject,
+    /// The version tag isn't a non-negative integer.
+    InvalidVersion(Value),
+    /// The world was written by a newer saver than this one.
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Json(err) => write!(f, "invalid world JSON: {}", err),
+            FormatError::NotAnObject => write!(f, "stored world is not a JSON object"),
+            FormatError::InvalidVersion(version) => {
+                write!(f, "invalid world format version {}", version)
+            }
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "world format version {} is newer than the supported version {}",
+                version, CURRENT_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for FormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FormatError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for FormatError {
+    fn from(err: serde_json::Error) -> Self {
+        FormatError::Json(err)
+    }
+}
+
+/// Serializes the world in the current format.
+pub fn to_json(world: &World) -> serde_json::Result<String> {
+    serde_json::to_string(&EnvelopeRef {
+        v: CURRENT_VERSION,
+        world,
+    })
+}
+
+/// Prefix of every world written by [`to_json`], which always writes the version tag first. Lets
+/// the database find outdated worlds without parsing them.
+pub fn current_prefix() -> String {
+    format!("{{\"{}\":{},", VERSION_KEY, CURRENT_VERSION)
+}
+
+/// Reads a world written in the current format or any earlier one.
+pub fn from_json(serialized: &str) -> Result<World, FormatError> {
+    from_value(serde_json::from_str(serialized)?)
+}
+
+/// Reads a world from JSON already parsed, in the current format or any earlier one.
+pub fn from_value(value: Value) -> Result<World, FormatError> {
+    let mut value = upgrade(value)?;
+    value
+        .as_object_mut()
+        .expect("upgrade checks the world is an object")
+        .remove(VERSION_KEY);
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Returns the format version of a stored world.
+fn version(value: &Value) -> Result<u64, FormatError> {
+    let object = value.as_object().ok_or(FormatError::NotAnObject)?;
+    match object.get(VERSION_KEY) {
+        None => Ok(1),
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| FormatError::InvalidVersion(version.clone())),
+    }
+}
+
+/// Upgrades a stored world, one version at a time, to the current format.
+pub fn upgrade(mut value: Value) -> Result<Value, FormatError> {
+    loop {
+        value = match version(&value)? {
+            1 => upgrade_v1(value),
+            CURRENT_VERSION => return Ok(value),
+            newer => return Err(FormatError::UnsupportedVersion(newer)),
+        };
+    }
+}
+
+/// Version 1 is the untagged world, which is otherwise the same as version 2 unless it came from
+/// the old 2D saver.
+fn upgrade_v1(mut value: Value) -> Value {
+    for vector in vectors_mut(&mut value) {
+        if let Some((x, y)) = components_2d(vector) {
+            *vector = Value::Array(vec![x, y, Value::from(0.0)]);
+        }
+    }
+    value
+        .as_object_mut()
+        .expect("version checks the world is an object")
+        .insert(VERSION_KEY.to_string(), Value::from(2));
+    value
+}
+
+/// Whether the world was written by the old 2D saver.
+pub fn is_2d(value: &Value) -> bool {
+    if version(value).ok() != Some(1) {
+        return false;
+    }
+    value
+        .get("planets")
+        .and_then(Value::as_array)
+        .map_or(false, |planets| {
+            planets.iter().any(|planet| {
+                VECTOR_KEYS
+                    .iter()
+                    .any(|&key| planet.get(key).and_then(components_2d).is_some())
+            })
+        })
+}
+
+/// Every vector of every planet in the world.
+fn vectors_mut(value: &mut Value) -> impl Iterator<Item = &mut Value> {
+    value
+        .get_mut("planets")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+        .flat_map(|planet| {
+            planet
+                .iter_mut()
+                .filter(|(key, _)| VECTOR_KEYS.contains(&key.as_str()))
+                .map(|(_, vector)| vector)
+        })
+}
+
+/// Returns the x and y of a 2D vector, which the old saver wrote as either `[x, y]` or
+/// `{"x": x, "y": y}`.
+fn components_2d(vector: &Value) -> Option<(Value, Value)> {
+    match vector {
+        Value::Array(components) if components.len() == 2 => {
+            Some((components[0].clone(), components[1].clone()))
+        }
+        Value::Object(components) if !components.contains_key("z") => {
+            Some((components.get("x")?.clone(), components.get("y")?.clone()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+    use serde_json::json;
+
+    use super::*;
+    use crate::model::{Planet, RadiusLaw};
+
+    fn world() -> World {
+        World {
+            planets: vec![
+                Planet {
+                    position: Vec3::new(1., 2., 3.),
+                    velocity: Vec3::new(-1., 0., 0.5),
+                    mass: 20.,
+                    spin: Vec3::ZERO,
+                },
+                Planet {
+                    position: Vec3::new(-100., 0., 40.),
+                    velocity: Vec3::ZERO,
+                    mass: 5.,
+                    spin: Vec3::ZERO,
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_version_tag() {
+        let serialized = to_json(&world()).unwrap();
+        let value: Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(value["v"], json!(CURRENT_VERSION));
+        assert_eq!(value["planets"][0]["position"], json!([1., 2., 3.]));
+    }
+
+    #[test]
+    fn round_trips() {
+        let serialized = to_json(&world()).unwrap();
+        assert_eq!(from_json(&serialized).unwrap(), world());
+    }
+
+    #[test]
+    fn writes_radius_law_only_if_changed() {
+        let serialized = to_json(&world()).unwrap();
+        let value: Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(value.get("radius_law"), None);
+
+        let mut dense = world();
+        dense.radius_law = RadiusLaw {
+            density: 2.,
+            ..Default::default()
+        };
+        let serialized = to_json(&dense).unwrap();
+        assert_eq!(from_json(&serialized).unwrap(), dense);
+    }
+
+    #[test]
+    fn reads_untagged_v1() {
+        let v1 = json!({
+            "planets": [
+                {"position": [1., 2., 3.], "velocity": [-1., 0., 0.5], "mass": 20.},
+                {"position": [-100., 0., 40.], "velocity": [0., 0., 0.], "mass": 5.},
+            ]
+        });
+        assert_eq!(from_json(&v1.to_string()).unwrap(), world());
+        assert_eq!(upgrade(v1).unwrap()["v"], json!(2));
+    }
+
+    #[test]
+    fn places_2d_worlds_in_plane() {
+        let array_2d = json!({
+            "planets": [{"position": [1., 2.], "velocity": [-1., 0.], "mass": 20.}]
+        });
+        let object_2d = json!({
+            "planets": [
+                {"position": {"x": 1., "y": 2.}, "velocity": {"x": -1., "y": 0.}, "mass": 20.}
+            ]
+        });
+        let expected = World {
+            planets: vec![Planet {
+                position: Vec3::new(1., 2., 0.),
+                velocity: Vec3::new(-1., 0., 0.),
+                mass: 20.,
+                spin: Vec3::ZERO,
+            }],
+            ..Default::default()
+        };
+        for world_2d in vec![array_2d, object_2d] {
+            assert!(is_2d(&world_2d));
+            assert_eq!(from_value(world_2d).unwrap(), expected);
+        }
+        assert!(!is_2d(
+            &serde_json::from_str(&to_json(&expected).unwrap()).unwrap()
+        ));
+    }
+
+    #[test]
+    fn current_worlds_start_with_prefix() {
+        assert!(to_json(&world()).unwrap().starts_with(&current_prefix()));
+        assert_eq!(current_prefix(), r#"{"v":2,"#);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let newer = json!({"v": CURRENT_VERSION + 1, "planets": []}).to_string();
+        match from_json(&newer) {
+            Err(FormatError::UnsupportedVersion(version)) => {
+                assert_eq!(version, CURRENT_VERSION + 1)
+            }
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_worlds() {
+        assert!(matches!(from_json("[]"), Err(FormatError::NotAnObject)));
+        assert!(matches!(
+            from_json(r#"{"v":"two","planets":[]}"#),
+            Err(FormatError::InvalidVersion(_))
+        ));
+        assert!(matches!(
+            from_json(r#"{"v":2,"planets":[{"mass":1}]}"#),
+            Err(FormatError::Json(_))
+        ));
+    }
+}
@@ -12,38 +12,154 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bevy::prelude::*;
+use xsecurelock_saver::engine::SaverTime;
+use xsecurelock_saver::self_test::SelfTest;
 
-use crate::config::database::DatabaseConfig;
-use crate::model::{Scenario, World};
+use crate::config;
+use crate::config::database::{DatabaseConfig, StorageBackend};
+use crate::model::{Peak, Scenario, Stability, StabilityFilter, World};
 
+pub use self::ancestry::{Ancestor, Ancestry, AncestryLookup};
+use self::backup::Backups;
+pub use self::error::StorageError;
+use self::null::NullStorage;
 use self::pruner::Pruner;
-use self::sqlite::SqliteStorage;
+pub use self::scoreboard::{ScoreboardCache, StorageEvent};
+use self::sqlite::{PendingMigrations, SqliteStorage};
 
+mod ancestry;
+mod backup;
+mod error;
+pub mod format;
+mod null;
 mod pruner;
+mod repair;
+mod scoreboard;
 pub mod sqlite;
 
+/// Type of the storage resource used by the rest of the saver. The backend is chosen by the
+/// database config at startup.
+pub type BoxedStorage = Box<dyn Storage + Send + Sync>;
+
 pub struct StoragePlugin;
 
 impl Plugin for StoragePlugin {
     fn build(&self, app: &mut AppBuilder) {
         let dbconfig: DatabaseConfig = app.world().get_resource().cloned().unwrap_or_default();
 
-        if let Some(keep) = dbconfig.max_scenarios_to_keep {
-            let prune_conn = open_from_conf(dbconfig.database_path.as_ref());
-            app.insert_resource(Pruner::new(keep, prune_conn))
-                .insert_resource(PruneTimer(Timer::from_seconds(
-                    dbconfig.prune_interval_seconds as f32,
-                    true,
-                )))
-                .add_system(prune_sys.system());
+        let mut storage: BoxedStorage = match dbconfig.backend {
+            StorageBackend::Sqlite => Box::new(build_sqlite(app, &dbconfig)),
+            StorageBackend::Null => {
+                info!("Using null storage, scenarios will not be saved");
+                Box::new(NullStorage::new())
+            }
+        };
+        if let Some(mut test) = app.world_mut().get_resource_mut::<SelfTest>() {
+            test.record("database", storage.num_scenarios(), |count| {
+                match (dbconfig.backend, &dbconfig.database_path) {
+                    (StorageBackend::Sqlite, Some(path)) => {
+                        format!("{} scenarios in {}", count, path.display())
+                    }
+                    (StorageBackend::Sqlite, None) => "in memory".to_string(),
+                    (StorageBackend::Null, _) => "not storing scenarios".to_string(),
+                }
+            });
         }
+        app.insert_resource(storage)
+            .add_event::<StorageEvent>()
+            .init_resource::<ScoreboardCache>()
+            .add_system(scoreboard::update_scoreboard::<BoxedStorage>.system());
+    }
+}
+
+/// Sets up pruning and ancestry lookups on the sqlite database and returns the main connection to
+/// it.
+fn build_sqlite(app: &mut AppBuilder, dbconfig: &DatabaseConfig) -> SqliteStorage {
+    if let Some(ref path) = dbconfig.database_path {
+        repair::check_and_repair(path, dbconfig.integrity_check);
+        migrate(path, dbconfig);
+    }
+
+    if let Some(keep) = dbconfig.max_scenarios_to_keep {
+        let prune_conn = open_from_conf(dbconfig.database_path.as_ref());
+        let backups = Backups::from_conf(dbconfig);
+        app.insert_resource(Pruner::new(keep, prune_conn, backups))
+            .insert_resource(PruneTimer(Timer::from_seconds(
+                dbconfig.prune_interval_seconds as f32,
+                true,
+            )))
+            .add_system(prune_sys.system());
+    }
+
+    // A second connection to an in-memory database would see a different, empty database.
+    if dbconfig.database_path.is_some() {
+        let lookup_conn = open_from_conf(dbconfig.database_path.as_ref());
+        app.insert_resource(AncestryLookup::new(lookup_conn));
+    }
+
+    open_from_conf(dbconfig.database_path.as_ref())
+}
 
-        let main_conn = open_from_conf(dbconfig.database_path.as_ref());
-        app.insert_resource(main_conn);
+/// Brings a database made by an older saver up to date, backing it up first. New columns are added
+/// to the scenario table, and worlds stored by older savers, including the old 2D saver, are
+/// rewritten in the current format so they only have to be upgraded once. Does nothing if the
+/// database is already up to date.
+///
+/// If the backup fails, stored worlds are left as they are, since they can still be read. The
+/// columns are added regardless when the database is opened, which doesn't change existing rows.
+fn migrate(path: &Path, dbconfig: &DatabaseConfig) {
+    if !path.exists() {
+        return;
+    }
+    let pending = match PendingMigrations::check(path) {
+        Ok(pending) if pending.is_empty() => return,
+        Ok(pending) => pending,
+        Err(err) => {
+            error!(
+                "Unable to check whether the database needs migrating: {}",
+                err
+            );
+            return;
+        }
+    };
+    info!("Migrating the database: {:?}", pending);
+    if let Some(backups) = Backups::from_conf(dbconfig) {
+        match backups.backup_file(path) {
+            Ok(backup) => info!("Backed up scenarios to {}", backup.display()),
+            Err(err) => {
+                warn!("Not upgrading stored worlds because backup failed: {}", err);
+                return;
+            }
+        }
+    }
+    let storage = SqliteStorage::open(path).map_err(StorageError::from);
+    if pending.outdated_worlds == 0 {
+        if let Err(err) = storage {
+            error!("Unable to add new columns to the database: {}", err);
+        }
+        return;
+    }
+    match storage.and_then(|mut storage| storage.upgrade_worlds(dbconfig.migration_jitter)) {
+        Ok(upgraded) => info!("Upgraded {} stored worlds to the current format", upgraded),
+        Err(err) => error!("Unable to upgrade stored worlds: {}", err),
+    }
+}
+
+/// Opens the configured database for tools which read scenarios without running the saver.
+/// Returns None if there is no sqlite database to read.
+pub fn open_configured() -> Option<SqliteStorage> {
+    let dbconf = config::load()
+        .extract::<DatabaseConfig>()
+        .expect("Unable to load database config");
+    match dbconf.database_path {
+        Some(ref path) if dbconf.backend == StorageBackend::Sqlite && path.exists() => {
+            migrate(path, &dbconf);
+            Some(SqliteStorage::open(path).expect("Unable to open storage"))
+        }
+        _ => None,
     }
 }
 
@@ -61,12 +177,20 @@ fn open_from_conf(path: Option<&PathBuf>) -> SqliteStorage {
 
 struct PruneTimer(Timer);
 
-fn prune_sys(time: Res<Time>, mut timer: ResMut<PruneTimer>, mut pruner: ResMut<Pruner>) {
+fn prune_sys(
+    time: Res<SaverTime>,
+    mut timer: ResMut<PruneTimer>,
+    mut pruner: ResMut<Pruner>,
+    mut events: EventWriter<StorageEvent>,
+) {
     timer.0.tick(time.delta());
     if timer.0.finished() {
         info!("Triggering prune");
         pruner.prune();
     }
+    for _ in 0..pruner.take_finished() {
+        events.send(StorageEvent::Pruned);
+    }
 }
 
 /// Storage for models.
@@ -74,7 +198,7 @@ fn prune_sys(time: Res<Time>, mut timer: ResMut<PruneTimer>, mut pruner: ResMut<
 // use &self instead of &mut self.
 pub trait Storage {
     /// Add a new root scenario. This scenario is the new root of a family of scenarios.
-    fn add_root_scenario(&mut self, world: World, score: f64) -> Result<Scenario, Box<dyn Error>>;
+    fn add_root_scenario(&mut self, world: World, score: f64) -> Result<Scenario, StorageError>;
 
     /// Add a new scenario that is the child of the specified scenario
     fn add_child_scenario(
@@ -82,17 +206,204 @@ pub trait Storage {
         world: World,
         score: f64,
         parent: &Scenario,
-    ) -> Result<Scenario, Box<dyn Error>>;
+    ) -> Result<Scenario, StorageError>;
 
     /// Returns the number of scenarios available.
-    fn num_scenarios(&mut self) -> Result<u64, Box<dyn Error>>;
+    fn num_scenarios(&mut self) -> Result<u64, StorageError>;
 
     /// Gets the nth scenario, in order of score (descending, so lower indexes are higher scoring
     /// scenarios). May return None if the index is outside the number of scenarios.
-    fn get_nth_scenario_by_score(&mut self, index: u64)
-        -> Result<Option<Scenario>, Box<dyn Error>>;
+    fn get_nth_scenario_by_score(&mut self, index: u64) -> Result<Option<Scenario>, StorageError>;
+
+    /// Returns the number of scenarios whose stability classification matches the filter.
+    fn num_matching_scenarios(&mut self, filter: &StabilityFilter) -> Result<u64, StorageError>;
+
+    /// Like `get_nth_scenario_by_score`, counting only the scenarios whose stability
+    /// classification matches the filter.
+    fn get_nth_matching_scenario_by_score(
+        &mut self,
+        index: u64,
+        filter: &StabilityFilter,
+    ) -> Result<Option<Scenario>, StorageError>;
+
+    /// Gets up to count scenarios in order of score, starting from the scenario at index offset.
+    /// Returns fewer scenarios if the range runs past the end.
+    fn get_rank_range(&mut self, offset: u64, count: u64) -> Result<Vec<Scenario>, StorageError>;
+
+    /// Gets the k highest scoring scenarios, best first.
+    fn get_top_scenarios(&mut self, k: u64) -> Result<Vec<Scenario>, StorageError> {
+        self.get_rank_range(0, k)
+    }
+
+    /// Gets the scenario with the given id, or None if it isn't stored (or has been pruned).
+    fn get_scenario(&mut self, id: u64) -> Result<Option<Scenario>, StorageError>;
+
+    /// Gets every stored scenario in the given family, in order of generation and then id.
+    fn get_family(&mut self, family: u64) -> Result<Vec<Scenario>, StorageError>;
 
     /// Removes the bottom scoring scenarios, keeping up to number_to_keep top scoring scenarios.
     /// Returns the number of scenarios pruned.
-    fn keep_top_scenarios_by_score(&mut self, number_to_keep: u64) -> Result<u64, Box<dyn Error>>;
+    fn keep_top_scenarios_by_score(&mut self, number_to_keep: u64) -> Result<u64, StorageError>;
+
+    /// Records how the stored scenario with the given id behaved when it was tested.
+    fn set_stability(&mut self, id: u64, stability: Stability) -> Result<(), StorageError>;
+
+    /// Records the world at the peak of the stored scenario with the given id.
+    fn set_peak(&mut self, id: u64, peak: &Peak) -> Result<(), StorageError>;
+
+    /// Gets the world at the peak of the scenario with the given id, or None if the scenario isn't
+    /// stored or has no peak recorded.
+    fn get_peak(&mut self, id: u64) -> Result<Option<Peak>, StorageError>;
+
+    /// Writes a consistent copy of all stored scenarios to a new file at the given path.
+    fn backup_to(&mut self, path: &Path) -> Result<(), StorageError>;
+}
+
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn add_root_scenario(&mut self, world: World, score: f64) -> Result<Scenario, StorageError> {
+        (**self).add_root_scenario(world, score)
+    }
+
+    fn add_child_scenario(
+        &mut self,
+        world: World,
+        score: f64,
+        parent: &Scenario,
+    ) -> Result<Scenario, StorageError> {
+        (**self).add_child_scenario(world, score, parent)
+    }
+
+    fn num_scenarios(&mut self) -> Result<u64, StorageError> {
+        (**self).num_scenarios()
+    }
+
+    fn get_nth_scenario_by_score(&mut self, index: u64) -> Result<Option<Scenario>, StorageError> {
+        (**self).get_nth_scenario_by_score(index)
+    }
+
+    fn num_matching_scenarios(&mut self, filter: &StabilityFilter) -> Result<u64, StorageError> {
+        (**self).num_matching_scenarios(filter)
+    }
+
+    fn get_nth_matching_scenario_by_score(
+        &mut self,
+        index: u64,
+        filter: &StabilityFilter,
+    ) -> Result<Option<Scenario>, StorageError> {
+        (**self).get_nth_matching_scenario_by_score(index, filter)
+    }
+
+    fn get_rank_range(&mut self, offset: u64, count: u64) -> Result<Vec<Scenario>, StorageError> {
+        (**self).get_rank_range(offset, count)
+    }
+
+    fn get_top_scenarios(&mut self, k: u64) -> Result<Vec<Scenario>, StorageError> {
+        (**self).get_top_scenarios(k)
+    }
+
+    fn get_scenario(&mut self, id: u64) -> Result<Option<Scenario>, StorageError> {
+        (**self).get_scenario(id)
+    }
+
+    fn get_family(&mut self, family: u64) -> Result<Vec<Scenario>, StorageError> {
+        (**self).get_family(family)
+    }
+
+    fn keep_top_scenarios_by_score(&mut self, number_to_keep: u64) -> Result<u64, StorageError> {
+        (**self).keep_top_scenarios_by_score(number_to_keep)
+    }
+
+    fn set_stability(&mut self, id: u64, stability: Stability) -> Result<(), StorageError> {
+        (**self).set_stability(id, stability)
+    }
+
+    fn set_peak(&mut self, id: u64, peak: &Peak) -> Result<(), StorageError> {
+        (**self).set_peak(id, peak)
+    }
+
+    fn get_peak(&mut self, id: u64) -> Result<Option<Peak>, StorageError> {
+        (**self).get_peak(id)
+    }
+
+    fn backup_to(&mut self, path: &Path) -> Result<(), StorageError> {
+        (**self).backup_to(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rusqlite::{Connection, NO_PARAMS};
+
+    use super::*;
+
+    #[test]
+    fn migration_leaves_a_backup() {
+        let mut directory = std::env::temp_dir();
+        directory.push(format!(
+            "genetic-orbits-migration-test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("scenarios.sqlite3");
+        let old_world = r#"{"planets":[{"position":[3.0,4.0],"velocity":[0.5,0.0],"mass":2.0}]}"#;
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE scenario (
+                    id INTEGER PRIMARY KEY,
+                    family INTEGER NOT NULL,
+                    parent INTEGER,
+                    generation INTEGER NOT NULL,
+                    world TEXT NOT NULL,
+                    score REAL NOT NULL
+                )",
+                NO_PARAMS,
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO scenario (id, family, parent, generation, world, score)
+                    VALUES (1, 1, NULL, 0, ?1, 5.0)",
+                &[&old_world],
+            )
+            .unwrap();
+        }
+        let dbconfig = DatabaseConfig {
+            database_path: Some(path.clone()),
+            ..Default::default()
+        };
+
+        migrate(&path, &dbconfig);
+        assert!(PendingMigrations::check(&path).unwrap().is_empty());
+        let backups: Vec<_> = fs::read_dir(directory.join("backups"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(backups.len(), 1);
+        // The backup is of the database from before the migration.
+        assert_eq!(
+            PendingMigrations::check(&backups[0]).unwrap(),
+            PendingMigrations {
+                missing_columns: vec!["stability", "peak_world", "peak_seconds"],
+                outdated_worlds: 1,
+            }
+        );
+        let backed_up: String = Connection::open(&backups[0])
+            .unwrap()
+            .query_row(
+                "SELECT world FROM scenario WHERE id = 1",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(backed_up, old_world);
+
+        // A database which is already up to date isn't backed up again.
+        migrate(&path, &dbconfig);
+        assert_eq!(fs::read_dir(directory.join("backups")).unwrap().count(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }
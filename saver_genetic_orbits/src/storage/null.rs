@@ -0,0 +1,147 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Storage which keeps nothing, so every scenario is freshly generated.
+
+use std::path::Path;
+
+use crate::model::{Peak, Scenario, Stability, StabilityFilter, World};
+
+use super::{Storage, StorageError};
+
+/// Storage which discards every scenario added to it. Useful for watching randomly generated
+/// worlds without building up (or disturbing) a database of evolved ones.
+#[derive(Debug, Default)]
+pub struct NullStorage {
+    /// ID given to the last discarded scenario, so that logged IDs are still distinct.
+    last_id: u64,
+}
+
+impl NullStorage {
+    /// Creates a new storage which keeps nothing.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+impl Storage for NullStorage {
+    fn add_root_scenario(&mut self, world: World, score: f64) -> Result<Scenario, StorageError> {
+        let id = self.next_id();
+        Ok(Scenario {
+            id,
+            family: id,
+            parent: None,
+            generation: 0,
+            world,
+            score,
+            stability: None,
+        })
+    }
+
+    fn add_child_scenario(
+        &mut self,
+        world: World,
+        score: f64,
+        parent: &Scenario,
+    ) -> Result<Scenario, StorageError> {
+        Ok(Scenario {
+            id: self.next_id(),
+            family: parent.family,
+            parent: Some(parent.id),
+            generation: parent.generation + 1,
+            world,
+            score,
+            stability: None,
+        })
+    }
+
+    fn num_scenarios(&mut self) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    fn get_nth_scenario_by_score(&mut self, _index: u64) -> Result<Option<Scenario>, StorageError> {
+        Ok(None)
+    }
+
+    fn num_matching_scenarios(&mut self, _filter: &StabilityFilter) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    fn get_nth_matching_scenario_by_score(
+        &mut self,
+        _index: u64,
+        _filter: &StabilityFilter,
+    ) -> Result<Option<Scenario>, StorageError> {
+        Ok(None)
+    }
+
+    fn get_rank_range(&mut self, _offset: u64, _count: u64) -> Result<Vec<Scenario>, StorageError> {
+        Ok(vec![])
+    }
+
+    fn get_scenario(&mut self, _id: u64) -> Result<Option<Scenario>, StorageError> {
+        Ok(None)
+    }
+
+    fn get_family(&mut self, _family: u64) -> Result<Vec<Scenario>, StorageError> {
+        Ok(vec![])
+    }
+
+    fn keep_top_scenarios_by_score(&mut self, _number_to_keep: u64) -> Result<u64, StorageError> {
+        Ok(0)
+    }
+
+    fn set_stability(&mut self, _id: u64, _stability: Stability) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn set_peak(&mut self, _id: u64, _peak: &Peak) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn get_peak(&mut self, _id: u64) -> Result<Option<Peak>, StorageError> {
+        Ok(None)
+    }
+
+    fn backup_to(&mut self, _path: &Path) -> Result<(), StorageError> {
+        Err(StorageError::Unsupported(
+            "the null storage backend has nothing to back up",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_nothing() {
+        let mut storage = NullStorage::new();
+        let root = storage.add_root_scenario(World::default(), 1.).unwrap();
+        let child = storage
+            .add_child_scenario(World::default(), 2., &root)
+            .unwrap();
+        assert_ne!(root.id, child.id);
+        assert_eq!(child.family, root.id);
+        assert_eq!(child.parent, Some(root.id));
+        assert_eq!(child.generation, 1);
+        assert_eq!(storage.num_scenarios().unwrap(), 0);
+        assert!(storage.get_nth_scenario_by_score(0).unwrap().is_none());
+    }
+}
@@ -12,47 +12,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::mpsc::{self, Sender};
-use std::thread::{self, JoinHandle};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
 
-use log::{error, info};
+use log::{error, info, warn};
+use xsecurelock_saver::priority;
 
+use super::backup::Backups;
 use super::Storage;
 
 /// Struct used to shutdown pruning.
 pub struct Pruner {
     join_handle: Option<JoinHandle<()>>,
     sender: Option<Sender<()>>,
+    /// Receives a message each time the remote thread finishes a prune.
+    finished: Receiver<()>,
 }
 
-// This is safe because we require &mut Self for all methods that access sender, so sharing &self is
-// safe though not useful.
+// This is safe because we require &mut Self for all methods that access sender and finished, so
+// sharing &self is safe though not useful.
 unsafe impl Sync for Pruner {}
 
 impl Pruner {
     /// Creates a pruner running on a remote thread which can be triggered to asynchronously prune scenarios.
-    pub fn new<S>(number_to_keep: u64, storage: S) -> Pruner
+    /// If backups are given, large prunes are preceded by a backup.
+    pub fn new<S>(number_to_keep: u64, storage: S, backups: Option<Backups>) -> Pruner
     where
         S: Storage + Send + 'static,
     {
         let (sender, recv) = mpsc::channel();
-        let join_handle = thread::spawn(move || {
+        let (finished_sender, finished) = mpsc::channel();
+        let join_handle = priority::spawn_named("orbits-pruner", move || {
             let mut storage = storage;
             loop {
                 match recv.recv() {
                     Ok(()) => {
                         info!("Pruning scenarios");
-                        match storage.keep_top_scenarios_by_score(number_to_keep) {
-                            Ok(num_pruned) => info!("Pruned {} scenarios", num_pruned),
-                            Err(err) => error!("Falied to prune scenarios: {}", err),
-                        }
+                        prune(&mut storage, number_to_keep, backups.as_ref());
+                        // Nobody is listening during shutdown, which is fine.
+                        let _ = finished_sender.send(());
                     }
                     Err(_) => {
                         info!("Sending final prune and shutting down.");
-                        match storage.keep_top_scenarios_by_score(number_to_keep) {
-                            Ok(num_pruned) => info!("Pruned {} scenarios", num_pruned),
-                            Err(err) => error!("Falied to prune scenarios: {}", err),
-                        }
+                        prune(&mut storage, number_to_keep, backups.as_ref());
                         break;
                     }
                 }
@@ -62,6 +64,7 @@ impl Pruner {
         Pruner {
             join_handle: Some(join_handle),
             sender: Some(sender),
+            finished,
         }
     }
 
@@ -74,6 +77,39 @@ impl Pruner {
             .send(())
             .expect("Pruner shut down unexpectedly");
     }
+
+    /// Returns the number of prunes which have finished since this was last called.
+    pub fn take_finished(&mut self) -> usize {
+        self.finished.try_iter().count()
+    }
+}
+
+/// Prunes the storage down to the number of scenarios to keep, backing it up first if enough
+/// scenarios would be removed. If the backup fails, the prune is skipped.
+fn prune<S: Storage>(storage: &mut S, number_to_keep: u64, backups: Option<&Backups>) {
+    if let Some(backups) = backups {
+        let num_to_prune = match storage.num_scenarios() {
+            Ok(num_scenarios) => num_scenarios.saturating_sub(number_to_keep),
+            Err(err) => {
+                error!("Failed to count scenarios before pruning: {}", err);
+                return;
+            }
+        };
+        if backups.should_backup_before_prune(num_to_prune) {
+            info!("Backing up before pruning {} scenarios", num_to_prune);
+            match backups.backup(storage) {
+                Ok(path) => info!("Backed up scenarios to {}", path.display()),
+                Err(err) => {
+                    warn!("Skipping prune because backup failed: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+    match storage.keep_top_scenarios_by_score(number_to_keep) {
+        Ok(num_pruned) => info!("Pruned {} scenarios", num_pruned),
+        Err(err) => error!("Falied to prune scenarios: {}", err),
+    }
 }
 
 impl Drop for Pruner {
@@ -0,0 +1,167 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Startup integrity check of the scenario database.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+
+use crate::config::database::IntegrityCheck;
+
+use super::sqlite::SqliteStorage;
+
+/// Maximum number of problems to log when the database is corrupt.
+const MAX_PROBLEMS_LOGGED: usize = 10;
+
+/// Suffixes of the files sqlite keeps next to the database, which have to move with it.
+const SIDECAR_SUFFIXES: &[&str] = &["-journal", "-wal", "-shm"];
+
+/// Checks the integrity of the database at the given path. If it is corrupt, the database is moved
+/// aside so it isn't lost, and a fresh database is created in its place containing as many of the
+/// old scenarios as could be read.
+pub fn check_and_repair(path: &Path, check: IntegrityCheck) {
+    if check == IntegrityCheck::None || !path.exists() {
+        return;
+    }
+    info!("Checking integrity of {}", path.display());
+    let problems = SqliteStorage::open(path)
+        .and_then(|mut storage| storage.integrity_problems(check == IntegrityCheck::Quick))
+        .unwrap_or_else(|err| vec![err.to_string()]);
+    if problems.is_empty() {
+        info!("Database integrity check passed");
+        return;
+    }
+
+    error!(
+        "Database {} is corrupt ({} problems found)",
+        path.display(),
+        problems.len()
+    );
+    for problem in problems.iter().take(MAX_PROBLEMS_LOGGED) {
+        error!("  {}", problem);
+    }
+
+    let corrupt_path = match move_aside(path) {
+        Ok(corrupt_path) => corrupt_path,
+        Err(err) => {
+            error!("Unable to move corrupt database out of the way: {}", err);
+            return;
+        }
+    };
+    error!("Moved corrupt database to {}", corrupt_path.display());
+
+    match SqliteStorage::open(path)
+        .and_then(|mut storage| storage.salvage_scenarios_from(&corrupt_path))
+    {
+        Ok(salvaged) => warn!(
+            "Created a new database with {} scenarios salvaged from the corrupt one",
+            salvaged
+        ),
+        Err(err) => warn!(
+            "Unable to salvage scenarios, continuing with a new database: {}",
+            err
+        ),
+    }
+}
+
+/// Renames the database and its sidecar files to a timestamped name next to it, returning the new
+/// path of the database.
+fn move_aside(path: &Path) -> std::io::Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let corrupt_path = with_suffix(path, &format!(".corrupt-{}", timestamp));
+    fs::rename(path, &corrupt_path)?;
+    for suffix in SIDECAR_SUFFIXES {
+        let sidecar = with_suffix(path, suffix);
+        if sidecar.exists() {
+            fs::rename(&sidecar, with_suffix(&corrupt_path, suffix))?;
+        }
+    }
+    Ok(corrupt_path)
+}
+
+/// Appends the suffix to the file name of the path.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::World;
+    use crate::storage::Storage;
+
+    /// Creates an empty temporary directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!(
+            "genetic-orbits-repair-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn healthy_database_untouched() {
+        let dir = test_dir("healthy");
+        let path = dir.join("scenarios.sqlite3");
+        SqliteStorage::open(&path)
+            .unwrap()
+            .add_root_scenario(World::default(), 1.)
+            .unwrap();
+
+        check_and_repair(&path, IntegrityCheck::Full);
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.num_scenarios().unwrap(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_database_replaced() {
+        let dir = test_dir("unreadable");
+        let path = dir.join("scenarios.sqlite3");
+        fs::write(
+            &path,
+            "this is not a database, but it is long enough to look like one",
+        )
+        .unwrap();
+
+        check_and_repair(&path, IntegrityCheck::Quick);
+
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(names
+            .iter()
+            .any(|name| name.starts_with("scenarios.sqlite3.corrupt-")));
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.num_scenarios().unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
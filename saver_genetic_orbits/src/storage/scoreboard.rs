@@ -0,0 +1,108 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory copy of the scores shown in the HUD, kept up to date from storage events so that the
+//! UI never has to query the database.
+
+use bevy::ecs::component::Component;
+use bevy::prelude::*;
+
+use super::Storage;
+
+/// Sent when the contents of storage change.
+#[derive(Debug, Clone, Copy)]
+pub enum StorageEvent {
+    /// A scenario with the given score was stored.
+    Stored { score: f64 },
+    /// Low scoring scenarios were pruned.
+    Pruned,
+}
+
+/// Scores shown in the HUD.
+pub struct ScoreboardCache {
+    /// Highest score of any stored scenario, or None if there are none.
+    high_score: Option<f64>,
+    /// Whether the cache has to be reloaded from storage because it may have missed a change.
+    stale: bool,
+}
+
+impl Default for ScoreboardCache {
+    fn default() -> Self {
+        ScoreboardCache {
+            high_score: None,
+            stale: true,
+        }
+    }
+}
+
+impl ScoreboardCache {
+    /// Highest score of any stored scenario, or None if there are none.
+    pub fn high_score(&self) -> Option<f64> {
+        self.high_score
+    }
+
+    /// Updates the cache for a change to storage.
+    fn apply(&mut self, event: StorageEvent) {
+        match event {
+            StorageEvent::Stored { score } => {
+                self.high_score = Some(self.high_score.map_or(score, |high| high.max(score)));
+            }
+            // Pruning only removes low scores, but reload anyway so the cache can't drift from
+            // what is actually stored.
+            StorageEvent::Pruned => self.stale = true,
+        }
+    }
+}
+
+/// Applies storage events to the scoreboard, and reloads it from storage when it is stale.
+pub fn update_scoreboard<S: Storage + Component>(
+    mut events: EventReader<StorageEvent>,
+    mut cache: ResMut<ScoreboardCache>,
+    mut storage: ResMut<S>,
+) {
+    for &event in events.iter() {
+        cache.apply(event);
+    }
+    if !cache.stale {
+        return;
+    }
+    // Clear stale even on error so a broken database doesn't log every frame. The next prune will
+    // try again.
+    cache.stale = false;
+    match storage.get_top_scenarios(1) {
+        Ok(top) => cache.high_score = top.first().map(|scenario| scenario.score),
+        Err(err) => error!("Unable to load high score: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_high_score() {
+        let mut cache = ScoreboardCache {
+            high_score: None,
+            stale: false,
+        };
+        cache.apply(StorageEvent::Stored { score: 2. });
+        cache.apply(StorageEvent::Stored { score: 1. });
+        assert_eq!(cache.high_score(), Some(2.));
+        cache.apply(StorageEvent::Stored { score: 3. });
+        assert_eq!(cache.high_score(), Some(3.));
+        assert!(!cache.stale);
+        cache.apply(StorageEvent::Pruned);
+        assert!(cache.stale);
+    }
+}
@@ -12,20 +12,42 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::error::Error;
 use std::path::Path;
 
+use log::warn;
+use rand::Rng;
 use rusqlite::types::{
     FromSql, FromSqlError, ToSql, ToSqlOutput, Value as SqlValue, ValueRef as SqlValueRef,
 };
-use rusqlite::{Connection, Error as SqlError, NO_PARAMS};
-use serde_json;
+use rusqlite::{Connection, Error as SqlError, Row, NO_PARAMS};
+use xsecurelock_saver::cli;
 
-use crate::model::{Scenario, World};
-use crate::storage::Storage;
+use crate::model::{Peak, Scenario, Stability, StabilityFilter, World};
+use crate::storage::{format, Storage, StorageError};
+
+/// Number of top scoring scenarios kept in memory to answer rank queries without touching the
+/// database.
+const TOP_CACHE_SIZE: u64 = 64;
+
+/// Number of worlds upgraded per transaction, so upgrading a large database doesn't read it all
+/// into memory at once.
+const UPGRADE_BATCH_SIZE: i64 = 1000;
 
 pub struct SqliteStorage {
     conn: Connection,
+    /// The top scoring scenarios as of the last read. Cleared when this connection writes, and
+    /// ignored if another connection (such as the pruner) has written since it was filled.
+    top_cache: Option<TopCache>,
+}
+
+/// Cached top scoring scenarios.
+struct TopCache {
+    /// sqlite's `data_version` when the cache was filled. It changes whenever another connection
+    /// commits a write.
+    data_version: i64,
+    /// The top scoring scenarios, in rank order. Has fewer than TOP_CACHE_SIZE entries only if
+    /// that is all the scenarios there are.
+    scenarios: Vec<Scenario>,
 }
 
 // This is safe because all methods on SqliteStorage take &mut self, so sharing &self across
@@ -55,10 +77,14 @@ impl SqliteStorage {
                 parent INTEGER,
                 generation INTEGER NOT NULL,
                 world TEXT NOT NULL,
-                score REAL NOT NULL
+                score REAL NOT NULL,
+                stability TEXT,
+                peak_world TEXT,
+                peak_seconds REAL
             )",
             NO_PARAMS,
         )?;
+        add_missing_columns(&conn)?;
         conn.execute(
             "CREATE INDEX IF NOT EXISTS scenario_score_index
                 ON scenario (
@@ -68,7 +94,174 @@ impl SqliteStorage {
             ",
             NO_PARAMS,
         )?;
-        Ok(SqliteStorage { conn })
+        Ok(SqliteStorage {
+            conn,
+            top_cache: None,
+        })
+    }
+}
+
+impl SqliteStorage {
+    /// Gets the top scoring scenarios, refilling the cache if anything has been written since it
+    /// was filled.
+    fn cached_top(&mut self) -> Result<&[Scenario], SqlError> {
+        let data_version: i64 = self
+            .conn
+            .query_row("PRAGMA data_version", NO_PARAMS, |row| row.get(0))?;
+        let fresh = match self.top_cache {
+            Some(ref cache) => cache.data_version == data_version,
+            None => false,
+        };
+        if !fresh {
+            let scenarios = self.query_rank_range(0, TOP_CACHE_SIZE)?;
+            self.top_cache = Some(TopCache {
+                data_version,
+                scenarios,
+            });
+        }
+        Ok(&self.top_cache.as_ref().unwrap().scenarios)
+    }
+
+    /// Reads up to count scenarios in order of score, skipping the first offset.
+    fn query_rank_range(&mut self, offset: u64, count: u64) -> Result<Vec<Scenario>, SqlError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, family, parent, generation, world, score, stability
+                    FROM scenario
+                    ORDER BY score DESC,
+                             id ASC
+                    LIMIT ?1
+                    OFFSET ?2",
+        )?;
+        let scenarios = stmt
+            .query_and_then(
+                &[&SqlBoundedU64(count) as &dyn ToSql, &SqlBoundedU64(offset)],
+                scenario_from_row,
+            )?
+            .collect::<Result<Vec<_>, SqlError>>()?;
+        Ok(scenarios)
+    }
+
+    /// Runs sqlite's integrity check, returning the problems found. An empty list means the
+    /// database is fine. The quick check skips verifying that indexes match their tables.
+    pub fn integrity_problems(&mut self, quick: bool) -> Result<Vec<String>, SqlError> {
+        let pragma = if quick {
+            "PRAGMA quick_check"
+        } else {
+            "PRAGMA integrity_check"
+        };
+        let mut stmt = self.conn.prepare(pragma)?;
+        let problems = stmt
+            .query_and_then(NO_PARAMS, |row| row.get_checked::<_, String>(0))?
+            .collect::<Result<Vec<_>, SqlError>>()?;
+        Ok(problems
+            .into_iter()
+            .filter(|problem| problem != "ok")
+            .collect())
+    }
+
+    /// Copies as many scenarios as can be read from the database at the given path into this one,
+    /// stopping at the first unreadable row. Returns the number of scenarios copied.
+    pub fn salvage_scenarios_from(&mut self, path: &Path) -> Result<u64, SqlError> {
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| SqlError::InvalidPath(path.to_owned()))?;
+        self.conn
+            .execute("ATTACH DATABASE ?1 AS salvage", &[&path_str])?;
+        let result = self.copy_attached_scenarios();
+        self.conn.execute("DETACH DATABASE salvage", NO_PARAMS)?;
+        result
+    }
+
+    /// Copies rows from the attached `salvage` database until one can't be read.
+    fn copy_attached_scenarios(&mut self) -> Result<u64, SqlError> {
+        let txn = self.conn.transaction()?;
+        let mut copied = 0;
+        {
+            let mut select = txn.prepare(
+                "SELECT id, family, parent, generation, world, score FROM salvage.scenario",
+            )?;
+            let mut insert = txn.prepare(
+                "INSERT OR IGNORE INTO scenario (id, family, parent, generation, world, score)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            let mut rows = select.query(NO_PARAMS)?;
+            while let Some(row) = rows.next() {
+                let values = row.and_then(|row| {
+                    (0..6)
+                        .map(|i| row.get_checked::<_, SqlValue>(i))
+                        .collect::<Result<Vec<_>, _>>()
+                });
+                match values {
+                    Ok(values) => copied += insert.execute(&values)? as u64,
+                    Err(err) => {
+                        warn!("Stopped salvaging scenarios at unreadable row: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+        txn.commit()?;
+        Ok(copied)
+    }
+
+    /// Rewrites worlds stored in older formats in the current one. Worlds from the old 2D saver
+    /// are placed in the z = 0 plane, then each planet is moved off it by a random offset of up to
+    /// `jitter`. Worlds which can't be read are left alone. Returns the number of worlds rewritten.
+    pub fn upgrade_worlds(&mut self, jitter: f32) -> Result<u64, StorageError> {
+        let prefix = format::current_prefix();
+        let mut rng = cli::rng();
+        let mut last_id = i64::MIN;
+        let mut upgraded = 0;
+        loop {
+            let txn = self.conn.transaction()?;
+            let batch = {
+                let mut select = txn.prepare(
+                    "SELECT id, world FROM scenario
+                        WHERE id > ?1 AND substr(world, 1, length(?2)) != ?2
+                        ORDER BY id
+                        LIMIT ?3",
+                )?;
+                let rows = select.query_and_then(
+                    &[&last_id as &dyn ToSql, &prefix, &UPGRADE_BATCH_SIZE],
+                    |row| -> Result<(i64, String), SqlError> {
+                        Ok((row.get_checked(0)?, row.get_checked(1)?))
+                    },
+                )?;
+                rows.collect::<Result<Vec<_>, SqlError>>()?
+            };
+            if batch.is_empty() {
+                return Ok(upgraded);
+            }
+            {
+                let mut update = txn.prepare("UPDATE scenario SET world = ?2 WHERE id = ?1")?;
+                for (id, serialized) in &batch {
+                    let value = match serde_json::from_str(serialized) {
+                        Ok(value) => value,
+                        Err(err) => {
+                            warn!("Unable to upgrade world of scenario {}: {}", id, err);
+                            continue;
+                        }
+                    };
+                    let was_2d = format::is_2d(&value);
+                    let mut world = match format::from_value(value) {
+                        Ok(world) => world,
+                        Err(err) => {
+                            warn!("Unable to upgrade world of scenario {}: {}", id, err);
+                            continue;
+                        }
+                    };
+                    if was_2d && jitter > 0.0 {
+                        for planet in &mut world.planets {
+                            planet.position.z = rng.gen_range(-jitter..=jitter);
+                        }
+                    }
+                    update.execute(&[id as &dyn ToSql, &world])?;
+                    upgraded += 1;
+                }
+            }
+            txn.commit()?;
+            last_id = batch.last().unwrap().0;
+        }
     }
 }
 
@@ -80,7 +273,8 @@ impl Default for SqliteStorage {
 }
 
 impl Storage for SqliteStorage {
-    fn add_root_scenario(&mut self, world: World, score: f64) -> Result<Scenario, Box<dyn Error>> {
+    fn add_root_scenario(&mut self, world: World, score: f64) -> Result<Scenario, StorageError> {
+        self.top_cache = None;
         let txn = self.conn.transaction()?;
         let inserted = txn.execute(
             "INSERT INTO scenario (family, parent, generation, world, score)
@@ -88,14 +282,18 @@ impl Storage for SqliteStorage {
             &[&-1i64 as &dyn ToSql, &None::<i64>, &0i64, &world, &score],
         )?;
         if inserted != 1 {
-            return Err(
-                format!("Expected to insert 1 row but had {} row changes", inserted).into(),
-            );
+            return Err(StorageError::RowCount {
+                expected: 1,
+                actual: inserted,
+            });
         }
         let id = txn.last_insert_rowid();
         let updated = txn.execute("UPDATE scenario SET family = ?1 WHERE id = ?1", &[&id])?;
         if updated != 1 {
-            return Err(format!("Expected to update 1 row but had {} row changes", updated).into());
+            return Err(StorageError::RowCount {
+                expected: 1,
+                actual: updated,
+            });
         }
         txn.commit()?;
         Ok(Scenario {
@@ -105,6 +303,7 @@ impl Storage for SqliteStorage {
             generation: 0,
             world,
             score,
+            stability: None,
         })
     }
 
@@ -113,7 +312,8 @@ impl Storage for SqliteStorage {
         world: World,
         score: f64,
         parent: &Scenario,
-    ) -> Result<Scenario, Box<dyn Error>> {
+    ) -> Result<Scenario, StorageError> {
+        self.top_cache = None;
         let generation = parent.generation + 1;
         let inserted = self.conn.execute(
             "INSERT INTO scenario (family, parent, generation, world, score)
@@ -127,9 +327,10 @@ impl Storage for SqliteStorage {
             ],
         )?;
         if inserted != 1 {
-            return Err(
-                format!("Expected to insert 1 row but had {} row changes", inserted).into(),
-            );
+            return Err(StorageError::RowCount {
+                expected: 1,
+                actual: inserted,
+            });
         }
         let id = self.conn.last_insert_rowid() as u64;
         Ok(Scenario {
@@ -139,49 +340,101 @@ impl Storage for SqliteStorage {
             generation,
             world,
             score,
+            stability: None,
         })
     }
 
-    fn num_scenarios(&mut self) -> Result<u64, Box<dyn Error>> {
+    fn num_scenarios(&mut self) -> Result<u64, StorageError> {
         self.conn
             .query_row_and_then("SELECT COUNT(*) FROM scenario", NO_PARAMS, |row| {
                 Ok(row.get_checked::<_, SqlBoundedU64>(0)?.0)
             })
     }
 
-    fn get_nth_scenario_by_score(
+    fn get_nth_scenario_by_score(&mut self, index: u64) -> Result<Option<Scenario>, StorageError> {
+        Ok(self.get_rank_range(index, 1)?.pop())
+    }
+
+    fn num_matching_scenarios(&mut self, filter: &StabilityFilter) -> Result<u64, StorageError> {
+        if filter.is_empty() {
+            return self.num_scenarios();
+        }
+        self.conn.query_row_and_then(
+            &format!(
+                "SELECT COUNT(*) FROM scenario WHERE {}",
+                stability_condition(filter)
+            ),
+            NO_PARAMS,
+            |row| Ok(row.get_checked::<_, SqlBoundedU64>(0)?.0),
+        )
+    }
+
+    fn get_nth_matching_scenario_by_score(
         &mut self,
         index: u64,
-    ) -> Result<Option<Scenario>, Box<dyn Error>> {
-        let query_result = self.conn.query_row_and_then(
-            "SELECT id, family, parent, generation, world, score
+        filter: &StabilityFilter,
+    ) -> Result<Option<Scenario>, StorageError> {
+        if filter.is_empty() {
+            return self.get_nth_scenario_by_score(index);
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, family, parent, generation, world, score, stability
                     FROM scenario
+                    WHERE {}
                     ORDER BY score DESC,
                              id ASC
                     LIMIT 1
-                    OFFSET ?",
-            &[&SqlBoundedU64(index)],
-            |row| {
-                Ok(Scenario {
-                    id: row.get_checked::<_, SqlWrappingU64>(0)?.0,
-                    family: row.get_checked::<_, SqlWrappingU64>(1)?.0,
-                    parent: row
-                        .get_checked::<_, Option<SqlWrappingU64>>(2)?
-                        .map(|v| v.0),
-                    generation: row.get_checked::<_, SqlBoundedU64>(3)?.0,
-                    world: row.get_checked(4)?,
-                    score: row.get_checked(5)?,
-                })
-            },
-        );
-        match query_result {
-            Ok(scenario) => Ok(Some(scenario)),
-            Err(SqlError::QueryReturnedNoRows) => Ok(None),
-            Err(any_other_error) => Err(any_other_error.into()),
+                    OFFSET ?1",
+            stability_condition(filter)
+        ))?;
+        let scenario = stmt
+            .query_and_then(&[&SqlBoundedU64(index)], scenario_from_row)?
+            .next()
+            .transpose()?;
+        Ok(scenario)
+    }
+
+    fn get_rank_range(&mut self, offset: u64, count: u64) -> Result<Vec<Scenario>, StorageError> {
+        match offset.checked_add(count) {
+            Some(end) if end <= TOP_CACHE_SIZE => {
+                let top = self.cached_top()?;
+                let start = (offset as usize).min(top.len());
+                let end = (end as usize).min(top.len());
+                Ok(top[start..end].to_vec())
+            }
+            _ => Ok(self.query_rank_range(offset, count)?),
         }
     }
 
-    fn keep_top_scenarios_by_score(&mut self, number_to_keep: u64) -> Result<u64, Box<dyn Error>> {
+    fn get_scenario(&mut self, id: u64) -> Result<Option<Scenario>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, family, parent, generation, world, score, stability
+                    FROM scenario
+                    WHERE id = ?1",
+        )?;
+        let scenario = stmt
+            .query_and_then(&[&SqlWrappingU64(id)], scenario_from_row)?
+            .next()
+            .transpose()?;
+        Ok(scenario)
+    }
+
+    fn get_family(&mut self, family: u64) -> Result<Vec<Scenario>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, family, parent, generation, world, score, stability
+                    FROM scenario
+                    WHERE family = ?1
+                    ORDER BY generation ASC,
+                             id ASC",
+        )?;
+        let scenarios = stmt
+            .query_and_then(&[&SqlWrappingU64(family)], scenario_from_row)?
+            .collect::<Result<Vec<_>, SqlError>>()?;
+        Ok(scenarios)
+    }
+
+    fn keep_top_scenarios_by_score(&mut self, number_to_keep: u64) -> Result<u64, StorageError> {
+        self.top_cache = None;
         Ok(self.conn.execute(
             "DELETE
                     FROM scenario
@@ -195,6 +448,194 @@ impl Storage for SqliteStorage {
             &[&SqlBoundedU64(number_to_keep)],
         )? as u64)
     }
+
+    fn set_stability(&mut self, id: u64, stability: Stability) -> Result<(), StorageError> {
+        self.top_cache = None;
+        let updated = self.conn.execute(
+            "UPDATE scenario SET stability = ?2 WHERE id = ?1",
+            &[&SqlWrappingU64(id) as &dyn ToSql, &stability],
+        )?;
+        if updated != 1 {
+            return Err(StorageError::RowCount {
+                expected: 1,
+                actual: updated,
+            });
+        }
+        Ok(())
+    }
+
+    fn set_peak(&mut self, id: u64, peak: &Peak) -> Result<(), StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE scenario SET peak_world = ?2, peak_seconds = ?3 WHERE id = ?1",
+            &[
+                &SqlWrappingU64(id) as &dyn ToSql,
+                &peak.world,
+                &peak.seconds,
+            ],
+        )?;
+        if updated != 1 {
+            return Err(StorageError::RowCount {
+                expected: 1,
+                actual: updated,
+            });
+        }
+        Ok(())
+    }
+
+    fn get_peak(&mut self, id: u64) -> Result<Option<Peak>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT peak_world, peak_seconds
+                    FROM scenario
+                    WHERE id = ?1",
+        )?;
+        let peak = stmt
+            .query_and_then(&[&SqlWrappingU64(id)], peak_from_row)?
+            .next()
+            .transpose()?;
+        Ok(peak.flatten())
+    }
+
+    fn backup_to(&mut self, path: &Path) -> Result<(), StorageError> {
+        vacuum_into(&self.conn, path)
+    }
+}
+
+/// Columns added to the scenario table since it was first created, with their types.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("stability", "TEXT"),
+    ("peak_world", "TEXT"),
+    ("peak_seconds", "REAL"),
+];
+
+/// Changes a database made by an older saver needs before this one uses it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingMigrations {
+    /// Columns the scenario table is missing.
+    pub missing_columns: Vec<&'static str>,
+    /// Number of worlds stored in an older format.
+    pub outdated_worlds: u64,
+}
+
+impl PendingMigrations {
+    /// Checks what the database at `path` needs, without changing it.
+    pub fn check(path: &Path) -> Result<PendingMigrations, SqlError> {
+        let conn = Connection::open(path)?;
+        let columns = table_columns(&conn)?;
+        if columns.is_empty() {
+            // There is no scenario table yet, so there is nothing to migrate.
+            return Ok(PendingMigrations::default());
+        }
+        let missing_columns = ADDED_COLUMNS
+            .iter()
+            .map(|&(column, _)| column)
+            .filter(|column| !columns.iter().any(|existing| existing == column))
+            .collect();
+        let outdated_worlds = conn.query_row_and_then(
+            "SELECT COUNT(*) FROM scenario WHERE substr(world, 1, length(?1)) != ?1",
+            &[&format::current_prefix()],
+            |row| Ok::<_, SqlError>(row.get_checked::<_, SqlBoundedU64>(0)?.0),
+        )?;
+        Ok(PendingMigrations {
+            missing_columns,
+            outdated_worlds,
+        })
+    }
+
+    /// Returns true if the database is already up to date.
+    pub fn is_empty(&self) -> bool {
+        self.missing_columns.is_empty() && self.outdated_worlds == 0
+    }
+}
+
+/// Writes a consistent copy of the database at `path` to `backup`, without migrating it as opening
+/// it as a [`SqliteStorage`] would.
+pub fn backup_file(path: &Path, backup: &Path) -> Result<(), StorageError> {
+    vacuum_into(&Connection::open(path)?, backup)
+}
+
+/// Writes a consistent copy of the connection's database to a new file at `backup`.
+fn vacuum_into(conn: &Connection, backup: &Path) -> Result<(), StorageError> {
+    let backup = backup
+        .to_str()
+        .ok_or_else(|| StorageError::InvalidPath(backup.to_owned()))?;
+    conn.execute("VACUUM INTO ?", &[&backup])?;
+    Ok(())
+}
+
+/// Names of the columns of the scenario table, which are empty if there is no such table.
+fn table_columns(conn: &Connection) -> Result<Vec<String>, SqlError> {
+    let mut stmt = conn.prepare("PRAGMA table_info(scenario)")?;
+    let columns = stmt
+        .query_and_then(NO_PARAMS, |row| row.get_checked::<_, String>(1))?
+        .collect::<Result<Vec<_>, SqlError>>()?;
+    Ok(columns)
+}
+
+/// Adds the [`ADDED_COLUMNS`] to tables made by older savers. Added columns are nullable, so
+/// existing rows read as not having them set.
+fn add_missing_columns(conn: &Connection) -> Result<(), SqlError> {
+    let columns = table_columns(conn)?;
+    for &(column, column_type) in ADDED_COLUMNS {
+        if !columns.iter().any(|existing| existing == column) {
+            conn.execute(
+                &format!("ALTER TABLE scenario ADD COLUMN {} {}", column, column_type),
+                NO_PARAMS,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the `WHERE` condition selecting scenarios which match the filter. The classification
+/// names are fixed, so they are written into the query rather than bound.
+fn stability_condition(filter: &StabilityFilter) -> String {
+    let names = |stabilities: &[Stability]| {
+        stabilities
+            .iter()
+            .map(|stability| format!("'{}'", stability.as_str()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut conditions = Vec::new();
+    if !filter.only.is_empty() {
+        conditions.push(format!("stability IN ({})", names(&filter.only)));
+    }
+    if !filter.exclude.is_empty() {
+        conditions.push(format!(
+            "(stability IS NULL OR stability NOT IN ({}))",
+            names(&filter.exclude)
+        ));
+    }
+    if conditions.is_empty() {
+        "1".to_string()
+    } else {
+        conditions.join(" AND ")
+    }
+}
+
+/// Reads a peak from a row of `peak_world, peak_seconds`, which are both null if no peak was
+/// recorded.
+fn peak_from_row(row: &Row) -> Result<Option<Peak>, SqlError> {
+    let world: Option<World> = row.get_checked(0)?;
+    let seconds: Option<f64> = row.get_checked(1)?;
+    Ok(world
+        .zip(seconds)
+        .map(|(world, seconds)| Peak { seconds, world }))
+}
+
+/// Reads a scenario from a row of `id, family, parent, generation, world, score, stability`.
+fn scenario_from_row(row: &Row) -> Result<Scenario, SqlError> {
+    Ok(Scenario {
+        id: row.get_checked::<_, SqlWrappingU64>(0)?.0,
+        family: row.get_checked::<_, SqlWrappingU64>(1)?.0,
+        parent: row
+            .get_checked::<_, Option<SqlWrappingU64>>(2)?
+            .map(|v| v.0),
+        generation: row.get_checked::<_, SqlBoundedU64>(3)?.0,
+        world: row.get_checked(4)?,
+        score: row.get_checked(5)?,
+        stability: row.get_checked(6)?,
+    })
 }
 
 /// Struct for serializing u64 in Sql, wrapping out of range i64 values.
@@ -247,7 +688,7 @@ impl FromSql for SqlBoundedU64 {
 
 impl ToSql for World {
     fn to_sql(&self) -> Result<ToSqlOutput, SqlError> {
-        match serde_json::to_string(self) {
+        match format::to_json(self) {
             Ok(s) => Ok(ToSqlOutput::Owned(SqlValue::Text(s))),
             Err(err) => Err(SqlError::ToSqlConversionFailure(err.into())),
         }
@@ -260,7 +701,26 @@ impl FromSql for World {
             SqlValueRef::Text(serialized) => serialized,
             _ => return Err(FromSqlError::InvalidType),
         };
-        serde_json::from_str(serialized).map_err(|err| FromSqlError::Other(err.into()))
+        format::from_json(serialized).map_err(|err| FromSqlError::Other(err.into()))
+    }
+}
+
+impl ToSql for Stability {
+    fn to_sql(&self) -> Result<ToSqlOutput, SqlError> {
+        Ok(ToSqlOutput::Owned(SqlValue::Text(
+            self.as_str().to_string(),
+        )))
+    }
+}
+
+impl FromSql for Stability {
+    fn column_result(value: SqlValueRef) -> Result<Self, FromSqlError> {
+        match value {
+            SqlValueRef::Text(name) => name
+                .parse()
+                .map_err(|err: String| FromSqlError::Other(err.into())),
+            _ => Err(FromSqlError::InvalidType),
+        }
     }
 }
 
@@ -296,9 +756,7 @@ mod tests {
 
         assert_eq!(first.num_scenarios().unwrap(), 0);
         assert_eq!(second.num_scenarios().unwrap(), 0);
-        first
-            .add_root_scenario(World { planets: vec![] }, 0.)
-            .unwrap();
+        first.add_root_scenario(World::default(), 0.).unwrap();
         assert_eq!(first.num_scenarios().unwrap(), 1);
         assert_eq!(second.num_scenarios().unwrap(), 0);
     }
@@ -310,9 +768,7 @@ mod tests {
 
         assert_eq!(first.num_scenarios().unwrap(), 0);
         assert_eq!(second.num_scenarios().unwrap(), 0);
-        first
-            .add_root_scenario(World { planets: vec![] }, 0.)
-            .unwrap();
+        first.add_root_scenario(World::default(), 0.).unwrap();
         assert_eq!(first.num_scenarios().unwrap(), 1);
         assert_eq!(second.num_scenarios().unwrap(), 1);
     }
@@ -324,9 +780,7 @@ mod tests {
 
         assert_eq!(first.num_scenarios().unwrap(), 0);
         assert_eq!(second.num_scenarios().unwrap(), 0);
-        first
-            .add_root_scenario(World { planets: vec![] }, 0.)
-            .unwrap();
+        first.add_root_scenario(World::default(), 0.).unwrap();
         assert_eq!(first.num_scenarios().unwrap(), 1);
         assert_eq!(second.num_scenarios().unwrap(), 0);
     }
@@ -339,7 +793,9 @@ mod tests {
                 position: Vec3::new(0., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
         let scenario = storage.add_root_scenario(world.clone(), 54.).unwrap();
         assert_eq!(scenario.id, scenario.family);
@@ -388,15 +844,18 @@ mod tests {
             family: 87,
             parent: Some(60),
             generation: 10,
-            world: World { planets: vec![] },
+            world: World::default(),
             score: 3609.,
+            stability: None,
         };
         let world = World {
             planets: vec![Planet {
                 position: Vec3::new(0., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
         let scenario = storage
             .add_child_scenario(world.clone(), 987., &parent)
@@ -453,15 +912,19 @@ mod tests {
                 position: Vec3::new(0., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
-        let world2 = World { planets: vec![] };
+        let world2 = World::default();
         let world3 = World {
             planets: vec![Planet {
                 position: Vec3::new(80., 0., 0.),
                 velocity: Vec3::new(25., 30., 0.),
                 mass: 15.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
 
         {
@@ -497,15 +960,19 @@ mod tests {
                 position: Vec3::new(0., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
-        let world2 = World { planets: vec![] };
+        let world2 = World::default();
         let world3 = World {
             planets: vec![Planet {
                 position: Vec3::new(80., 0., 0.),
                 velocity: Vec3::new(25., 30., 0.),
                 mass: 15.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
 
         {
@@ -569,15 +1036,19 @@ mod tests {
                 position: Vec3::new(0., 0., 0.),
                 velocity: Vec3::new(0., 0., 0.),
                 mass: 1.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
-        let world2 = World { planets: vec![] };
+        let world2 = World::default();
         let world3 = World {
             planets: vec![Planet {
                 position: Vec3::new(80., 0., 0.),
                 velocity: Vec3::new(25., 30., 0.),
                 mass: 15.,
+                spin: Vec3::ZERO,
             }],
+            ..Default::default()
         };
 
         {
@@ -658,4 +1129,293 @@ mod tests {
         assert!(storage.get_nth_scenario_by_score(3).unwrap().is_none());
         assert!(storage.get_nth_scenario_by_score(4).unwrap().is_none());
     }
+
+    #[test]
+    fn backup_copies_scenarios() {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "genetic-orbits-backup-test-{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        storage.add_root_scenario(World::default(), 12.).unwrap();
+        storage.backup_to(&path).unwrap();
+        storage.add_root_scenario(World::default(), 34.).unwrap();
+
+        let mut backup = SqliteStorage::open(&path).unwrap();
+        assert_eq!(backup.num_scenarios().unwrap(), 1);
+        let scenario = backup.get_nth_scenario_by_score(0).unwrap().unwrap();
+        assert_eq!(scenario.score, 12.);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rank_range() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        for score in 0..100 {
+            storage
+                .add_root_scenario(World::default(), score as f64)
+                .unwrap();
+        }
+
+        let scores = |scenarios: Vec<Scenario>| -> Vec<f64> {
+            scenarios
+                .into_iter()
+                .map(|scenario| scenario.score)
+                .collect()
+        };
+        assert_eq!(
+            scores(storage.get_top_scenarios(3).unwrap()),
+            vec![99., 98., 97.]
+        );
+        assert_eq!(
+            scores(storage.get_rank_range(2, 2).unwrap()),
+            vec![97., 96.]
+        );
+        // Past the end of the cached scenarios.
+        assert_eq!(
+            scores(storage.get_rank_range(96, 10).unwrap()),
+            vec![3., 2., 1., 0.]
+        );
+        assert!(storage.get_rank_range(100, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn family() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let root = storage.add_root_scenario(World::default(), 1.).unwrap();
+        let other = storage.add_root_scenario(World::default(), 2.).unwrap();
+        let child = storage
+            .add_child_scenario(World::default(), 3., &root)
+            .unwrap();
+        storage
+            .add_child_scenario(World::default(), 4., &other)
+            .unwrap();
+        let grandchild = storage
+            .add_child_scenario(World::default(), 5., &child)
+            .unwrap();
+
+        let ids: Vec<u64> = storage
+            .get_family(root.family)
+            .unwrap()
+            .into_iter()
+            .map(|scenario| scenario.id)
+            .collect();
+        assert_eq!(ids, vec![root.id, child.id, grandchild.id]);
+        assert!(storage.get_family(12345).unwrap().is_empty());
+    }
+
+    #[test]
+    fn get_scenario() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let root = storage.add_root_scenario(World::default(), 1.).unwrap();
+        let child = storage
+            .add_child_scenario(World::default(), 2., &root)
+            .unwrap();
+
+        let found = storage.get_scenario(child.id).unwrap().unwrap();
+        assert_eq!(found.id, child.id);
+        assert_eq!(found.parent, Some(root.id));
+        assert_eq!(found.generation, 1);
+        assert_eq!(found.score, 2.);
+        assert!(storage.get_scenario(12345).unwrap().is_none());
+    }
+
+    #[test]
+    fn cache_sees_writes_from_other_connections() {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "genetic-orbits-cache-test-{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut reader = SqliteStorage::open(&path).unwrap();
+        let mut writer = SqliteStorage::open(&path).unwrap();
+        reader.add_root_scenario(World::default(), 1.).unwrap();
+        assert_eq!(reader.get_top_scenarios(5).unwrap().len(), 1);
+
+        writer.add_root_scenario(World::default(), 2.).unwrap();
+        let top = reader.get_top_scenarios(5).unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].score, 2.);
+
+        writer.keep_top_scenarios_by_score(1).unwrap();
+        assert_eq!(reader.get_top_scenarios(5).unwrap().len(), 1);
+
+        drop(reader);
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn upgrade_worlds_migrates_2d_worlds() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let current = storage.add_root_scenario(World::default(), 1.).unwrap();
+        storage
+            .conn
+            .execute(
+                "INSERT INTO scenario (family, parent, generation, world, score)
+                    VALUES (7, NULL, 3, ?1, 5.0)",
+                &[&r#"{"planets":[{"position":[3.0,4.0],"velocity":[0.5,0.0],"mass":2.0}]}"#],
+            )
+            .unwrap();
+        let old_id = storage.conn.last_insert_rowid() as u64;
+
+        assert_eq!(storage.upgrade_worlds(10.).unwrap(), 1);
+        assert_eq!(storage.upgrade_worlds(10.).unwrap(), 0);
+
+        let world = storage.get_scenario(old_id).unwrap().unwrap().world;
+        let planet = &world.planets[0];
+        assert_eq!((planet.position.x, planet.position.y), (3., 4.));
+        assert!(planet.position.z.abs() <= 10.);
+        assert_eq!(planet.velocity, Vec3::new(0.5, 0., 0.));
+        assert_eq!(
+            storage.get_scenario(current.id).unwrap().unwrap().world,
+            World::default()
+        );
+    }
+
+    #[test]
+    fn set_stability() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let scenario = storage.add_root_scenario(World::default(), 1.).unwrap();
+        assert_eq!(scenario.stability, None);
+        assert_eq!(storage.get_top_scenarios(1).unwrap()[0].stability, None);
+
+        storage
+            .set_stability(scenario.id, Stability::Hierarchical)
+            .unwrap();
+        assert_eq!(
+            storage
+                .get_scenario(scenario.id)
+                .unwrap()
+                .unwrap()
+                .stability,
+            Some(Stability::Hierarchical)
+        );
+        assert_eq!(
+            storage.get_top_scenarios(1).unwrap()[0].stability,
+            Some(Stability::Hierarchical)
+        );
+        assert!(storage.set_stability(12345, Stability::Chaotic).is_err());
+    }
+
+    #[test]
+    fn set_peak() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let scenario = storage.add_root_scenario(World::default(), 1.).unwrap();
+        assert_eq!(storage.get_peak(scenario.id).unwrap(), None);
+
+        let peak = Peak {
+            seconds: 12.5,
+            world: World {
+                planets: vec![Planet {
+                    position: Vec3::new(1., 2., 3.),
+                    velocity: Vec3::new(-4., 0., 0.5),
+                    mass: 60.,
+                    spin: Vec3::ZERO,
+                }],
+                ..Default::default()
+            },
+        };
+        storage.set_peak(scenario.id, &peak).unwrap();
+        assert_eq!(storage.get_peak(scenario.id).unwrap(), Some(peak.clone()));
+        // The scenario's starting world is unchanged.
+        assert_eq!(
+            storage.get_scenario(scenario.id).unwrap().unwrap().world,
+            World::default()
+        );
+        assert_eq!(storage.get_peak(12345).unwrap(), None);
+        assert!(storage.set_peak(12345, &peak).is_err());
+    }
+
+    #[test]
+    fn adds_new_columns_to_old_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE scenario (
+                id INTEGER PRIMARY KEY,
+                family INTEGER NOT NULL,
+                parent INTEGER,
+                generation INTEGER NOT NULL,
+                world TEXT NOT NULL,
+                score REAL NOT NULL
+            )",
+            NO_PARAMS,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO scenario (id, family, parent, generation, world, score)
+                VALUES (1, 1, NULL, 0, ?1, 5.0)",
+            &[&World::default()],
+        )
+        .unwrap();
+
+        let mut storage = SqliteStorage::from_conn(conn).unwrap();
+        assert_eq!(storage.get_scenario(1).unwrap().unwrap().stability, None);
+        storage.set_stability(1, Stability::Dispersing).unwrap();
+        assert_eq!(
+            storage.get_scenario(1).unwrap().unwrap().stability,
+            Some(Stability::Dispersing)
+        );
+        assert_eq!(storage.get_peak(1).unwrap(), None);
+        let peak = Peak {
+            seconds: 3.0,
+            world: World::default(),
+        };
+        storage.set_peak(1, &peak).unwrap();
+        assert_eq!(storage.get_peak(1).unwrap(), Some(peak));
+    }
+
+    #[test]
+    fn filters_by_stability() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let mut add = |score: f64, stability: Option<Stability>| {
+            let scenario = storage.add_root_scenario(World::default(), score).unwrap();
+            if let Some(stability) = stability {
+                storage.set_stability(scenario.id, stability).unwrap();
+            }
+            scenario.id
+        };
+        let chaotic = add(40., Some(Stability::Chaotic));
+        let dispersing = add(30., Some(Stability::Dispersing));
+        let unclassified = add(20., None);
+        let hierarchical = add(10., Some(Stability::Hierarchical));
+
+        let nth = |storage: &mut SqliteStorage, index: u64, filter: &StabilityFilter| {
+            storage
+                .get_nth_matching_scenario_by_score(index, filter)
+                .unwrap()
+                .map(|scenario| scenario.id)
+        };
+
+        let any = StabilityFilter::default();
+        assert_eq!(storage.num_matching_scenarios(&any).unwrap(), 4);
+        assert_eq!(nth(&mut storage, 1, &any), Some(dispersing));
+
+        let never_dispersing = StabilityFilter {
+            only: vec![],
+            exclude: vec![Stability::Dispersing],
+        };
+        assert_eq!(
+            storage.num_matching_scenarios(&never_dispersing).unwrap(),
+            3
+        );
+        assert_eq!(nth(&mut storage, 0, &never_dispersing), Some(chaotic));
+        assert_eq!(nth(&mut storage, 1, &never_dispersing), Some(unclassified));
+        assert_eq!(nth(&mut storage, 2, &never_dispersing), Some(hierarchical));
+        assert_eq!(nth(&mut storage, 3, &never_dispersing), None);
+
+        let only_ordered = StabilityFilter {
+            only: vec![Stability::Hierarchical, Stability::SingleDominant],
+            exclude: vec![],
+        };
+        assert_eq!(storage.num_matching_scenarios(&only_ordered).unwrap(), 1);
+        assert_eq!(nth(&mut storage, 0, &only_ordered), Some(hierarchical));
+        assert_eq!(nth(&mut storage, 1, &only_ordered), None);
+    }
 }
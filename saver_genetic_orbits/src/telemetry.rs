@@ -0,0 +1,328 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exports traces to an OpenTelemetry collector over OTLP/HTTP, for analyzing how the saver
+//! behaves over multi-hour locked sessions. Only available with the `otlp` feature, and only
+//! enabled when `OTEL_EXPORTER_OTLP_ENDPOINT` is set to the collector's endpoint, such as
+//! `http://localhost:4318`.
+//!
+//! Each scenario is a span of its own trace, with storing its result and changing to the next
+//! scenario as spans in the same trace. Frame and physics step times are too frequent for spans,
+//! so each scenario's span is given their count, mean and maximum instead.
+//!
+//! Spans are batched by the OpenTelemetry SDK, which reads the usual `OTEL_BSP_*` settings. The
+//! delay between batches defaults to a minute rather than the usual five seconds, so a locked
+//! machine isn't woken up for the network constantly.
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use opentelemetry::runtime::AsyncStd;
+use opentelemetry::sdk::trace::{
+    self as sdktrace, BatchSpanProcessor, Span, Tracer, TracerProvider,
+};
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::{
+    Span as _, TraceContextExt, TraceError, Tracer as _, TracerProvider as _,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+
+use crate::world_events::WorldEvent;
+use crate::SaverState;
+
+/// Name the saver reports itself as.
+const SERVICE_NAME: &str = "saver_genetic_orbits";
+
+/// Time between batches if `OTEL_BSP_SCHEDULE_DELAY` isn't set.
+const DEFAULT_SCHEDULE_DELAY: Duration = Duration::from_secs(60);
+
+/// Longest to wait for the collector when sending a batch.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exports traces if an OTLP endpoint is set.
+pub struct TelemetryPlugin;
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let endpoint = match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => endpoint,
+            _ => {
+                info!("OTEL_EXPORTER_OTLP_ENDPOINT not set, OTLP export disabled");
+                return;
+            }
+        };
+        let telemetry = match Telemetry::start(&endpoint) {
+            Ok(telemetry) => telemetry,
+            Err(err) => {
+                error!("Unable to set up OTLP export, export disabled: {}", err);
+                return;
+            }
+        };
+        info!("Exporting traces to {}", endpoint);
+        app.insert_resource(telemetry)
+            .add_stage_before(
+                PhysicsStages::StepWorld,
+                "otlp-physics-start",
+                SystemStage::single(start_physics.system()),
+            )
+            .add_stage_after(
+                PhysicsStages::StepWorld,
+                "otlp-physics-end",
+                SystemStage::single(end_physics.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(SaverState::Run)
+                    .with_system(start_storing.system().before("store-result"))
+                    .with_system(end_storing.system().after("store-result")),
+            )
+            .add_system_to_stage(CoreStage::Last, trace_scenarios.system())
+            .add_system_to_stage(CoreStage::Last, record_frame.system());
+    }
+}
+
+/// Count, total and longest of a set of durations, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Timings {
+    count: u64,
+    sum: f64,
+    max: f64,
+}
+
+impl Timings {
+    fn record(&mut self, duration: Duration) {
+        let millis = duration.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.sum += millis;
+        self.max = self.max.max(millis);
+    }
+
+    /// Attributes named `{prefix}_count`, `{prefix}_ms_mean` and `{prefix}_ms_max`. There's no
+    /// mean or maximum if nothing was recorded.
+    fn attributes(&self, prefix: &str) -> Vec<KeyValue> {
+        let mut attributes = vec![KeyValue::new(
+            format!("{}_count", prefix),
+            self.count as i64,
+        )];
+        if self.count > 0 {
+            attributes.push(KeyValue::new(
+                format!("{}_ms_mean", prefix),
+                self.sum / self.count as f64,
+            ));
+            attributes.push(KeyValue::new(format!("{}_ms_max", prefix), self.max));
+        }
+        attributes
+    }
+}
+
+/// Spans and timings of the running scenario. The SDK sends finished spans to the collector on
+/// its own threads, so the saver never waits on the network.
+struct Telemetry {
+    /// Kept so spans are flushed when the saver shuts down; the tracer only refers to it weakly.
+    _provider: TracerProvider,
+    tracer: Tracer,
+    /// Span of the running scenario.
+    scenario: Option<Span>,
+    /// Span of storing the last scenario's result, or of changing to the next scenario.
+    between: Option<Span>,
+    /// Frame times of the running scenario.
+    frames: Timings,
+    /// Physics step times of the running scenario.
+    physics: Timings,
+    /// Start of the current frame's physics step.
+    physics_started: Option<Instant>,
+    /// Start of the current frame.
+    frame_started: Option<Instant>,
+}
+
+impl Telemetry {
+    /// Sets up the exporter and the batching span processor.
+    fn start(endpoint: &str) -> Result<Self, TraceError> {
+        let exporter = opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(endpoint.trim_end_matches('/'))
+            .with_timeout(TIMEOUT)
+            .build_span_exporter()?;
+        // The builder reads OTEL_BSP_* itself, so only override the delay if it isn't set.
+        let mut processor = BatchSpanProcessor::builder(exporter, AsyncStd);
+        if env::var_os("OTEL_BSP_SCHEDULE_DELAY").is_none() {
+            processor = processor.with_scheduled_delay(DEFAULT_SCHEDULE_DELAY);
+        }
+        let provider = TracerProvider::builder()
+            .with_span_processor(processor.build())
+            .with_config(sdktrace::config().with_resource(Resource::new(vec![
+                KeyValue::new("service.name", SERVICE_NAME),
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            ])))
+            .build();
+        let tracer = provider.tracer(SERVICE_NAME);
+        Ok(Self {
+            _provider: provider,
+            tracer,
+            scenario: None,
+            between: None,
+            frames: Timings::default(),
+            physics: Timings::default(),
+            physics_started: None,
+            frame_started: None,
+        })
+    }
+
+    /// Starts a span as a child of the scenario's span.
+    fn start_child(&self, scenario: &Span, name: &'static str) -> Span {
+        let parent = Context::new().with_remote_span_context(scenario.span_context().clone());
+        self.tracer.start_with_context(name, &parent)
+    }
+
+    /// Finishes the span between scenarios, if any, so it's exported with the next batch.
+    fn end_between(&mut self) {
+        if let Some(mut span) = self.between.take() {
+            span.end();
+        }
+    }
+}
+
+/// Notes when the physics step starts.
+fn start_physics(mut telemetry: ResMut<Telemetry>) {
+    telemetry.physics_started = Some(Instant::now());
+}
+
+/// Records how long the physics step took.
+fn end_physics(mut telemetry: ResMut<Telemetry>) {
+    if let Some(started) = telemetry.physics_started.take() {
+        telemetry.physics.record(started.elapsed());
+    }
+}
+
+/// Starts the span for storing the scenario's result, in the scenario's trace.
+fn start_storing(mut telemetry: ResMut<Telemetry>) {
+    let between = match &telemetry.scenario {
+        Some(scenario) => telemetry.start_child(scenario, "store scenario"),
+        None => return,
+    };
+    telemetry.between = Some(between);
+}
+
+/// Ends the span for storing the scenario's result.
+fn end_storing(mut telemetry: ResMut<Telemetry>) {
+    telemetry.end_between();
+}
+
+/// Starts and ends the spans of scenarios, and of changing from one to the next.
+fn trace_scenarios(mut telemetry: ResMut<Telemetry>, mut events: EventReader<WorldEvent>) {
+    let telemetry = &mut *telemetry;
+    for event in events.iter() {
+        match *event {
+            WorldEvent::ScenarioStarted {
+                planets,
+                parent,
+                family,
+                generation,
+            } => {
+                telemetry.end_between();
+                // Each scenario is the root of a new trace.
+                let mut span = telemetry
+                    .tracer
+                    .start_with_context("scenario", &Context::new());
+                span.set_attribute(KeyValue::new("planets", planets as i64));
+                span.set_attribute(KeyValue::new("generation", generation as i64));
+                if let Some(parent) = parent {
+                    span.set_attribute(KeyValue::new("parent", parent as i64));
+                }
+                if let Some(family) = family {
+                    span.set_attribute(KeyValue::new("family", family as i64));
+                }
+                telemetry.scenario = Some(span);
+                telemetry.frames = Timings::default();
+                telemetry.physics = Timings::default();
+            }
+            WorldEvent::ScenarioEnded {
+                score,
+                discarded,
+                collisions,
+                merges,
+                near_misses,
+                checksum,
+            } => {
+                let mut span = match telemetry.scenario.take() {
+                    Some(span) => span,
+                    None => continue,
+                };
+                span.set_attribute(KeyValue::new("score", score));
+                span.set_attribute(KeyValue::new("discarded", discarded));
+                span.set_attribute(KeyValue::new("collisions", collisions as i64));
+                span.set_attribute(KeyValue::new("merges", merges as i64));
+                span.set_attribute(KeyValue::new("near_misses", near_misses as i64));
+                if let Some(checksum) = checksum {
+                    let hash = format!("{:016x}", checksum.hash);
+                    span.set_attribute(KeyValue::new("physics_checksum", hash));
+                }
+                let timings = telemetry.frames.attributes("frames");
+                for attribute in timings
+                    .into_iter()
+                    .chain(telemetry.physics.attributes("physics"))
+                {
+                    span.set_attribute(attribute);
+                }
+                // Changing to the next scenario lasts until it starts.
+                let between = telemetry.start_child(&span, "change scenario");
+                telemetry.end_between();
+                telemetry.between = Some(between);
+                span.end();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Records the frame time.
+fn record_frame(mut telemetry: ResMut<Telemetry>) {
+    let now = Instant::now();
+    if let Some(started) = telemetry.frame_started.replace(now) {
+        telemetry.frames.record(now.duration_since(started));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::Value;
+
+    use super::*;
+
+    #[test]
+    fn summarizes_timings() {
+        assert_eq!(
+            Timings::default().attributes("frames"),
+            vec![KeyValue::new("frames_count", 0i64)]
+        );
+        let mut timings = Timings::default();
+        timings.record(Duration::from_millis(10));
+        timings.record(Duration::from_millis(30));
+        let attributes = timings.attributes("physics");
+        let values: Vec<_> = attributes
+            .iter()
+            .map(|attribute| (attribute.key.as_str(), attribute.value.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("physics_count", Value::I64(2)),
+                ("physics_ms_mean", Value::F64(20.0)),
+                ("physics_ms_max", Value::F64(30.0)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recent positions of moving things, for drawing motion trails. A [`TrailHistory`] keeps the last
+//! few points of a path, sampled every so many seconds of simulation.
+//!
+//! Entities given a `TrailHistory` component have it updated every frame from where they're drawn,
+//! once the physics has moved them and their transforms have been propagated, so trails follow
+//! the interpolated positions rather than the physics steps. Things which aren't entities, such as
+//! the probes, keep a `TrailHistory` of their own and record into it as they move.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use xsecurelock_saver::engine::SaverTime;
+
+/// Keeps the [`TrailHistory`] of entities up to date.
+pub struct TrailsPlugin;
+
+impl Plugin for TrailsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            record_trails
+                .system()
+                .after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// The last points of a path, oldest first.
+#[derive(Debug, Clone)]
+pub struct TrailHistory {
+    /// Most points kept. The oldest are dropped to make room for new ones.
+    length: usize,
+    /// Seconds of simulation between points.
+    sample_seconds: f32,
+    /// Seconds of simulation since the last point.
+    since_sample: f32,
+    points: VecDeque<Vec3>,
+}
+
+impl TrailHistory {
+    /// An empty trail keeping at most `length` points, at least one, taken every `sample_seconds`.
+    pub fn new(length: usize, sample_seconds: f32) -> Self {
+        let length = length.max(1);
+        Self {
+            length,
+            sample_seconds,
+            since_sample: 0.0,
+            points: VecDeque::with_capacity(length),
+        }
+    }
+
+    /// Records that the path is at `position` after another `dt` seconds, adding it as a point if
+    /// it's time for the next one. The first position recorded is always added, so every trail
+    /// starts where the path did.
+    pub fn record(&mut self, position: Vec3, dt: f32) {
+        self.since_sample += dt;
+        if !self.points.is_empty() && self.since_sample < self.sample_seconds {
+            return;
+        }
+        self.since_sample = 0.0;
+        if self.points.len() >= self.length {
+            self.points.pop_front();
+        }
+        self.points.push_back(position);
+    }
+
+    /// The points of the trail, oldest first.
+    pub fn points(&self) -> impl Iterator<Item = &Vec3> + '_ {
+        self.points.iter()
+    }
+}
+
+/// Records where each entity with a [`TrailHistory`] is drawn this frame.
+fn record_trails(time: Res<SaverTime>, mut trails: Query<(&GlobalTransform, &mut TrailHistory)>) {
+    let dt = time.delta_seconds();
+    for (transform, mut trail) in trails.iter_mut() {
+        trail.record(transform.translation, dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_points_at_the_sample_rate() {
+        let mut trail = TrailHistory::new(3, 0.1);
+        for step in 0..=10 {
+            trail.record(Vec3::new(step as f32, 0.0, 0.0), 0.05);
+        }
+        let xs: Vec<f32> = trail.points().map(|point| point.x).collect();
+        assert_eq!(xs, [6.0, 8.0, 10.0]);
+
+        let mut trail = TrailHistory::new(0, 1.0);
+        trail.record(Vec3::ONE, 0.0);
+        trail.record(Vec3::ZERO, 0.5);
+        assert_eq!(trail.points().collect::<Vec<_>>(), [&Vec3::ONE]);
+    }
+}
@@ -15,12 +15,25 @@
 use bevy::prelude::shape;
 use bevy::prelude::*;
 use bevy::render::camera::PerspectiveProjection;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use bevy::utils::HashMap;
 use bevy_rapier3d::na::{Point3, Vector3};
+use bevy_rapier3d::physics::step_world_system;
 use bevy_rapier3d::prelude::*;
+use rand::Rng;
 use rand_distr::{Distribution, Uniform};
+use xsecurelock_saver::engine::{PhysicsSubsteps, SaverTime, SceneBuilder};
 
-use crate::config::camera::CameraConfig;
-use crate::model::Planet as PlanetConfig;
+use crate::barnes_hut;
+use crate::compensated::CompensatedSum;
+use crate::config::camera::{CameraConfig, CameraPath, Interpolation};
+use crate::config::physics::{
+    CollisionLayers, Falloff, IntegratorConfig, IntegratorMode, PhysicsConfig,
+};
+use crate::config::util::Vector;
+use crate::determinism::SimulationRng;
+use crate::energy::{self, EnergyDiagnostics};
+use crate::model::{Planet as PlanetConfig, RadiusLaw};
 use crate::statustracker::ActiveWorld;
 use crate::SaverState;
 
@@ -29,16 +42,138 @@ pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<PlanetMesh>()
-            .add_startup_system(setup_camera_light.system())
-            .add_startup_system(remove_rapier_gravity.system())
-            .add_system(rotate_camera.system())
+        app.add_plugin(GravityPlugin)
+            .add_plugin(PlanetRenderPlugin)
+            .init_resource::<PendingPlanets>()
+            .add_system(move_camera.system())
             .add_system_set(
                 SystemSet::on_enter(SaverState::Run)
                     .with_system(remove_planets.system().label("remove-old"))
-                    .with_system(spawn_planets.system().after("remove-old")),
+                    .with_system(queue_planets.system().after("remove-old")),
             )
-            .add_system(gravity.system());
+            .add_system_set(
+                SystemSet::on_update(SaverState::Run).with_system(spawn_pending_planets.system()),
+            );
+    }
+}
+
+/// Draws planets, without simulating them: adds the camera and light, and holds the assets planets
+/// are drawn with. Used on its own to play back recordings.
+pub struct PlanetRenderPlugin;
+
+impl Plugin for PlanetRenderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PlanetMesh>()
+            .init_resource::<PlanetSurface>()
+            .init_resource::<PlanetMaterials>()
+            .add_startup_system(setup_camera_light.system());
+    }
+}
+
+/// Applies gravity between planets in place of rapier's uniform gravity, plus the configured
+/// gravity fields, and keeps track of the planets' [`EnergyDiagnostics`]. Needs a `PhysicsConfig`
+/// resource. Used on its own to simulate without rendering. Splits rapier's step into the
+/// engine's [`PhysicsSubsteps`] if there's more than one.
+pub struct GravityPlugin;
+
+impl Plugin for GravityPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GravityConstant>()
+            .init_resource::<EnergyDiagnostics>()
+            .add_startup_system(remove_rapier_gravity.system())
+            .add_startup_system(spawn_gravity_fields.system())
+            // Both add to the planets' forces, so are ordered to always sum them the same way.
+            .add_system(gravity.system().label("gravity"))
+            .add_system(gravity_fields.system().after("gravity"))
+            .add_system(energy::update_energy.system());
+        let substeps = app
+            .world()
+            .get_resource::<PhysicsSubsteps>()
+            .map_or(1, PhysicsSubsteps::count);
+        if substeps > 1 {
+            app.init_resource::<FrameStep>()
+                .add_startup_system(fix_substep_timestep.system())
+                .add_stage_before(
+                    PhysicsStages::StepWorld,
+                    "physics-substeps",
+                    SubstepStage::new(substeps),
+                )
+                .add_stage_after(
+                    PhysicsStages::StepWorld,
+                    "physics-substeps-end",
+                    SystemStage::single(end_substeps.system()),
+                );
+        }
+    }
+}
+
+/// Runs all but the last of the frame's [`PhysicsSubsteps`] before rapier's own step, which runs
+/// the last. Gravity is applied again before each following substep, as rapier clears the forces
+/// once it has stepped.
+struct SubstepStage {
+    substeps: u32,
+    split: SystemStage,
+    substep: SystemStage,
+}
+
+impl SubstepStage {
+    fn new(substeps: u32) -> Self {
+        let mut substep = SystemStage::single_threaded();
+        substep
+            .add_system(step_world_system::<NoUserData>.system().label("step"))
+            .add_system(gravity.system().label("gravity").after("step"))
+            .add_system(gravity_fields.system().after("gravity"));
+        Self {
+            substeps,
+            split: SystemStage::single(split_step.system()),
+            substep,
+        }
+    }
+}
+
+impl Stage for SubstepStage {
+    fn run(&mut self, world: &mut World) {
+        self.split.run(world);
+        for _ in 1..self.substeps {
+            self.substep.run(world);
+        }
+    }
+}
+
+/// The whole frame's physics step while it's split into substeps, so it can be restored for the
+/// systems which use the timestep as the frame's step.
+#[derive(Default)]
+struct FrameStep(Option<f32>);
+
+/// Rapier's variable timestep would step by the whole frame, so the substeps set the timestep
+/// themselves.
+fn fix_substep_timestep(mut rapier: ResMut<RapierConfiguration>) {
+    rapier.timestep_mode = TimestepMode::FixedTimestep;
+}
+
+/// Splits the frame's physics step between the substeps. The step follows the [`SaverTime`],
+/// unless the run is deterministic or the planets are still being spawned, which set it
+/// themselves.
+fn split_step(
+    substeps: Res<PhysicsSubsteps>,
+    physics: Res<PhysicsConfig>,
+    time: Res<SaverTime>,
+    pending: Option<Res<PendingPlanets>>,
+    mut integration: ResMut<IntegrationParameters>,
+    mut frame: ResMut<FrameStep>,
+) {
+    let spawning = pending.map_or(false, |pending| pending.is_spawning());
+    if !physics.deterministic.enabled && !spawning {
+        integration.dt = time.delta_seconds();
+    }
+    frame.0 = Some(integration.dt);
+    integration.dt /= substeps.count() as f32;
+}
+
+/// Restores the frame's whole physics step once rapier has run the last substep.
+fn end_substeps(mut frame: ResMut<FrameStep>, mut integration: ResMut<IntegrationParameters>) {
+    if let Some(dt) = frame.0.take() {
+        integration.dt = dt;
     }
 }
 
@@ -71,21 +206,112 @@ fn setup_camera_light(mut commands: Commands) {
     });
 }
 
-/// rotate the camera around the origin.
-fn rotate_camera(
+/// Current orbital motion of the camera.
+#[derive(Default)]
+struct CameraMotion {
+    /// Angle around the orbit, in radians.
+    angle: f32,
+    /// Current rotation speed, which eases towards the configured speed.
+    speed: f32,
+}
+
+/// Moves the camera, either orbiting around the origin or following the configured paths.
+fn move_camera(
     mut query: Query<&mut Transform, With<PerspectiveProjection>>,
-    time: Res<Time>,
+    mut motion: Local<CameraMotion>,
+    time: Res<SaverTime>,
     config: Res<CameraConfig>,
 ) {
-    let t = time.seconds_since_startup() as f32 * config.rotation_speed;
+    let elapsed = time.seconds_since_startup() as f32;
+    let transform = if config.paths.is_empty() {
+        let delta = time.delta_seconds();
+        motion.speed = if config.speed_easing_seconds > 0.0 {
+            let blend = 1.0 - (-delta / config.speed_easing_seconds).exp();
+            motion.speed + (config.rotation_speed - motion.speed) * blend
+        } else {
+            config.rotation_speed
+        };
+        motion.angle = (motion.angle + motion.speed * delta) % std::f32::consts::TAU;
+
+        let tilt = config.tilt.amplitude
+            * (elapsed * std::f32::consts::TAU / config.tilt.period_seconds).sin();
+        Transform::from_xyz(
+            motion.angle.sin() * tilt.cos() * config.view_dist,
+            tilt.sin() * config.view_dist,
+            motion.angle.cos() * tilt.cos() * config.view_dist,
+        )
+        .looking_at(Vec3::ZERO, Vec3::Y)
+    } else {
+        let (path, t) = current_path(&config.paths, elapsed);
+        Transform::from_translation(path_position(path, t))
+            .looking_at(to_vec3(&path.look_at), Vec3::Y)
+    };
     for mut camera in query.iter_mut() {
-        *camera = Transform::from_xyz(t.sin() * config.view_dist, 0.0, t.cos() * config.view_dist)
-            .looking_at(Vec3::ZERO, Vec3::Y);
+        *camera = transform;
+    }
+}
+
+/// Finds which of the paths is playing at the given time, and the time within that path. Paths
+/// are played in order and loop once the last one ends.
+fn current_path(paths: &[CameraPath], elapsed: f32) -> (&CameraPath, f32) {
+    let total: f32 = paths.iter().map(CameraPath::duration).sum();
+    if total <= 0.0 {
+        return (&paths[0], 0.0);
     }
+    let mut t = elapsed % total;
+    for path in paths {
+        if t < path.duration() {
+            return (path, t);
+        }
+        t -= path.duration();
+    }
+    // Only reachable through rounding error at the very end of the last path.
+    let last = paths.last().unwrap();
+    (last, last.duration())
+}
+
+/// Computes the camera position on the path at the given time since the start of the path.
+fn path_position(path: &CameraPath, t: f32) -> Vec3 {
+    let keyframes = &path.keyframes;
+    let next = match keyframes.iter().position(|keyframe| keyframe.time > t) {
+        Some(0) => return to_vec3(&keyframes[0].position),
+        Some(next) => next,
+        None => return to_vec3(&keyframes[keyframes.len() - 1].position),
+    };
+    let prev = next - 1;
+    let u = (t - keyframes[prev].time) / (keyframes[next].time - keyframes[prev].time);
+
+    let p1 = to_vec3(&keyframes[prev].position);
+    let p2 = to_vec3(&keyframes[next].position);
+    match path.interpolation {
+        Interpolation::Linear => p1.lerp(p2, u),
+        Interpolation::Smooth => {
+            // Catmull-Rom spline, repeating the end points where there is no neighbouring
+            // keyframe.
+            let p0 = match prev.checked_sub(1) {
+                Some(before) => to_vec3(&keyframes[before].position),
+                None => p1,
+            };
+            let p3 = match keyframes.get(next + 1) {
+                Some(after) => to_vec3(&after.position),
+                None => p2,
+            };
+            let u2 = u * u;
+            let u3 = u2 * u;
+            0.5 * (2.0 * p1
+                + (p2 - p0) * u
+                + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+                + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+        }
+    }
+}
+
+fn to_vec3(vector: &Vector<f32>) -> Vec3 {
+    Vec3::new(vector.x, vector.y, vector.z)
 }
 
 /// Holds the sphere mesh used to render planets.
-struct PlanetMesh(Handle<Mesh>);
+pub struct PlanetMesh(Handle<Mesh>);
 
 impl FromWorld for PlanetMesh {
     fn from_world(world: &mut World) -> Self {
@@ -100,34 +326,78 @@ impl FromWorld for PlanetMesh {
     }
 }
 
+/// Width of the planets' surface texture, which wraps once around in longitude.
+const SURFACE_WIDTH: u32 = 128;
+
+/// Height of the planets' surface texture, from pole to pole.
+const SURFACE_HEIGHT: u32 = 64;
+
+/// Holds the texture which gives planets wavy bands, so their spin can be seen. The texture is
+/// grey, and tinted by each planet's color.
+pub struct PlanetSurface(Handle<Texture>);
+
+impl FromWorld for PlanetSurface {
+    fn from_world(world: &mut World) -> Self {
+        let data = (0..SURFACE_HEIGHT)
+            .flat_map(|y| (0..SURFACE_WIDTH).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let u = x as f32 / SURFACE_WIDTH as f32;
+                let v = (y as f32 + 0.5) / SURFACE_HEIGHT as f32;
+                let value = (surface_brightness(u, v) * 255.0).round() as u8;
+                vec![value, value, value, 255]
+            })
+            .collect();
+        let texture = Texture::new(
+            Extent3d::new(SURFACE_WIDTH, SURFACE_HEIGHT, 1),
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let texture = world
+            .get_resource_mut::<Assets<Texture>>()
+            .unwrap()
+            .add(texture);
+        Self(texture)
+    }
+}
+
+/// Brightness of the planets' surface at texture coordinates `u`, around the planet, and `v`,
+/// from pole to pole. Bands of latitude wave a couple of times around the planet, so spin about
+/// any axis moves them.
+fn surface_brightness(u: f32, v: f32) -> f32 {
+    let latitude = (v - 0.5) * std::f32::consts::PI;
+    let longitude = u * std::f32::consts::TAU;
+    0.8 + 0.2 * (7.0 * latitude + 1.5 * (2.0 * longitude).sin()).sin()
+}
+
 /// Marker component to identify planets for scoring and deletion.
 #[derive(Default)]
 pub struct Planet;
 
 /// Marker to apply gravity.
 #[derive(Default)]
-struct ApplyGravity;
+pub struct ApplyGravity;
 
 #[derive(Bundle, Default)]
 struct PlanetBundle {
     #[bundle]
     pbr: PbrBundle,
     #[bundle]
-    rigidbody: RigidBodyBundle,
-    #[bundle]
-    collider: ColliderBundle,
+    body: PlanetBodyBundle,
     sync: RigidBodyPositionSync,
-    gravity: ApplyGravity,
-    planet: Planet,
+    color: ColorKey,
 }
 
 impl PlanetBundle {
     fn new_from_planet(
         planet: &PlanetConfig,
+        law: &RadiusLaw,
+        groups: InteractionGroups,
         mesh: Handle<Mesh>,
         material: Handle<StandardMaterial>,
+        color: ColorKey,
     ) -> Self {
-        let radius = planet.radius();
+        let radius = law.radius(planet.mass);
         Self {
             pbr: PbrBundle {
                 mesh,
@@ -139,51 +409,243 @@ impl PlanetBundle {
                 },
                 ..Default::default()
             },
+            body: PlanetBodyBundle::new_from_planet(planet, law, groups),
+            sync: RigidBodyPositionSync::Interpolated { prev_pos: None },
+            color,
+        }
+    }
+}
+
+/// Draws a planet of the given color, with its size as the transform's scale.
+pub fn planet_pbr(
+    color: ColorKey,
+    transform: Transform,
+    mesh: &PlanetMesh,
+    surface: &PlanetSurface,
+    planet_materials: &mut PlanetMaterials,
+    materials: &mut Assets<StandardMaterial>,
+) -> PbrBundle {
+    PbrBundle {
+        mesh: mesh.0.clone(),
+        material: planet_materials.get(color, &surface.0, materials),
+        transform,
+        ..Default::default()
+    }
+}
+
+/// The physical parts of a planet, without anything needed to render it.
+#[derive(Bundle, Default)]
+pub struct PlanetBodyBundle {
+    #[bundle]
+    rigidbody: RigidBodyBundle,
+    #[bundle]
+    collider: ColliderBundle,
+    gravity: ApplyGravity,
+    planet: Planet,
+}
+
+impl PlanetBodyBundle {
+    /// Creates the body for a planet sized by the given law, colliding with the given groups. The
+    /// collider's density is chosen so the body has the planet's mass whatever the law's exponent.
+    pub fn new_from_planet(
+        planet: &PlanetConfig,
+        law: &RadiusLaw,
+        groups: InteractionGroups,
+    ) -> Self {
+        Self {
             rigidbody: RigidBodyBundle {
                 position: planet.position.into(),
                 velocity: RigidBodyVelocity {
                     linvel: planet.velocity.into(),
-                    ..Default::default()
+                    angvel: planet.spin.into(),
                 },
                 ..Default::default()
             },
             collider: ColliderBundle {
-                shape: ColliderShape::ball(radius),
-                mass_properties: ColliderMassProps::Density(PlanetConfig::DENSITY),
+                shape: ColliderShape::ball(law.radius(planet.mass)),
+                mass_properties: ColliderMassProps::Density(law.ball_density(planet.mass)),
+                // Contact events are used for collision sounds.
+                flags: ColliderFlags {
+                    active_events: ActiveEvents::CONTACT_EVENTS,
+                    collision_groups: groups,
+                    ..Default::default()
+                },
                 ..Default::default()
             },
-            sync: RigidBodyPositionSync::Interpolated { prev_pos: None },
             ..Default::default()
         }
     }
 }
 
-/// Generates a random color, usually fairly bright.
-fn generate_random_color() -> Color {
-    let hue_dist = Uniform::new(0.0, 360.0);
-    let sat_dist = Uniform::new_inclusive(0.75, 1.0);
-    let lightness_dist = Uniform::new_inclusive(0.75, 1.0);
+/// Interaction groups of planets' colliders, from the collision layers in the physics config.
+pub fn planet_groups(physics: &PhysicsConfig) -> InteractionGroups {
+    let (memberships, filter) = physics.collision_layers.masks(CollisionLayers::PLANET);
+    InteractionGroups::new(memberships, filter)
+}
 
-    let h = hue_dist.sample(&mut rand::thread_rng());
-    let s = sat_dist.sample(&mut rand::thread_rng());
-    let l = lightness_dist.sample(&mut rand::thread_rng());
-    Color::hsl(h, s, l)
+/// Number of hues planets are colored with.
+const HUES: u16 = 72;
+
+/// Number of saturations, and of lightnesses, planets are colored with.
+const SHADES: u16 = 6;
+
+/// A planet color, quantized so that planets can share a bounded set of materials. Kept on each
+/// planet so recordings can draw it in the same color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ColorKey {
+    hue: u16,
+    saturation: u16,
+    lightness: u16,
 }
 
-fn spawn_planets(
-    mut commands: Commands,
+impl ColorKey {
+    /// Number of distinct colors.
+    const COUNT: usize = HUES as usize * SHADES as usize * SHADES as usize;
+
+    /// Picks a random color, usually fairly bright.
+    fn random(rng: &mut impl Rng) -> Self {
+        Self {
+            hue: Uniform::new(0, HUES).sample(rng),
+            saturation: Uniform::new(0, SHADES).sample(rng),
+            lightness: Uniform::new(0, SHADES).sample(rng),
+        }
+    }
+
+    /// Numbers the colors below [`ColorKey::COUNT`], for storing them compactly.
+    pub fn index(self) -> u16 {
+        (self.hue * SHADES + self.saturation) * SHADES + self.lightness
+    }
+
+    /// The color numbered `index` by [`ColorKey::index`], or None if there's no such color.
+    pub fn from_index(index: u16) -> Option<Self> {
+        if index as usize >= Self::COUNT {
+            return None;
+        }
+        Some(Self {
+            hue: index / (SHADES * SHADES),
+            saturation: index / SHADES % SHADES,
+            lightness: index % SHADES,
+        })
+    }
+
+    pub fn color(self) -> Color {
+        let shade = |step: u16| 0.75 + 0.25 * step as f32 / (SHADES - 1) as f32;
+        Color::hsl(
+            360.0 * self.hue as f32 / HUES as f32,
+            shade(self.saturation),
+            shade(self.lightness),
+        )
+    }
+}
+
+/// Materials for planets, one per [`ColorKey`], kept for the whole session. Planets of later
+/// scenarios reuse them instead of adding a material each, so the number of materials, and of the
+/// renderer's buffers for them, stays bounded however long the saver runs.
+#[derive(Default)]
+pub struct PlanetMaterials(HashMap<ColorKey, Handle<StandardMaterial>>);
+
+impl PlanetMaterials {
+    /// Number of materials added so far.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Gets the material for the color, adding it with the given surface texture if no planet
+    /// has used the color yet.
+    fn get(
+        &mut self,
+        key: ColorKey,
+        surface: &Handle<Texture>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.0
+            .entry(key)
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: key.color(),
+                    base_color_texture: Some(surface.clone()),
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+}
+
+/// Planets of the active world still waiting to be spawned. Planets are spawned in batches over
+/// the first few frames of a scenario, so rapier's broadphase takes in one batch per step instead
+/// of the whole world at once. Physics steps with a timestep of zero until every planet is in, so
+/// the simulation starts with the complete world.
+#[derive(Default)]
+pub struct PendingPlanets {
+    planets: SceneBuilder<PlanetConfig>,
+    /// How big the active world's planets are for their mass.
+    radius_law: RadiusLaw,
+    /// Timestep to restore once every planet is spawned.
+    dt: Option<f32>,
+}
+
+impl PendingPlanets {
+    /// Whether the active world is still being spawned, so its simulation hasn't started.
+    pub fn is_spawning(&self) -> bool {
+        self.dt.is_some()
+    }
+}
+
+/// Queues the active world's planets to be spawned, and holds the simulation until they are.
+fn queue_planets(
     world: Res<ActiveWorld>,
+    physics: Res<PhysicsConfig>,
+    mut pending: ResMut<PendingPlanets>,
+    mut integration: ResMut<IntegrationParameters>,
+) {
+    pending
+        .planets
+        .queue_over_frames(world.world.planets.iter().cloned(), physics.spawn_frames);
+    pending.radius_law = world.world.radius_law;
+    // A scenario which ended while spawning has already stashed the real timestep.
+    if pending.dt.is_none() {
+        pending.dt = Some(integration.dt);
+    }
+    integration.dt = 0.0;
+}
+
+/// Spawns the next batch of pending planets. Once the last batch has had a physics step, restores
+/// the timestep so the simulation starts.
+#[allow(clippy::too_many_arguments)]
+fn spawn_pending_planets(
+    mut commands: Commands,
+    mut pending: ResMut<PendingPlanets>,
+    mut integration: ResMut<IntegrationParameters>,
+    physics: Res<PhysicsConfig>,
     mesh: Res<PlanetMesh>,
+    surface: Res<PlanetSurface>,
+    mut planet_materials: ResMut<PlanetMaterials>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rng: ResMut<SimulationRng>,
 ) {
-    for planet in &world.world.planets {
-        let material = materials.add(generate_random_color().into());
-        commands.spawn_bundle(PlanetBundle::new_from_planet(
-            planet,
-            mesh.0.clone(),
-            material,
-        ));
+    if !pending.planets.is_building() {
+        if let Some(dt) = pending.dt.take() {
+            integration.dt = dt;
+        }
+        return;
     }
+    let law = pending.radius_law;
+    let groups = planet_groups(&physics);
+    let rng = &mut rng.generation;
+    pending
+        .planets
+        .spawn_batch(&mut commands, |commands, planet| {
+            let color = ColorKey::random(rng);
+            let material = planet_materials.get(color, &surface.0, &mut materials);
+            commands.spawn_bundle(PlanetBundle::new_from_planet(
+                &planet,
+                &law,
+                groups,
+                mesh.0.clone(),
+                material,
+                color,
+            ));
+        });
 }
 
 /// Removes all planets.
@@ -203,15 +665,64 @@ struct Accumulator {
     force: Vector3<f32>,
 }
 
+/// Gravitational constant used to compute the attraction between planets.
+pub struct GravityConstant(pub f32);
+
+impl Default for GravityConstant {
+    fn default() -> Self {
+        Self(500.0)
+    }
+}
+
 /// Aplies gravity to rigidbodies.
+#[allow(clippy::type_complexity)]
 fn gravity(
     mut accumulator: Local<Vec<Accumulator>>,
-    mut query: Query<(&RigidBodyMassProps, &mut RigidBodyForces), With<ApplyGravity>>,
+    mut precise: Local<Vec<PreciseAccumulator>>,
+    mut query: Query<
+        (
+            &RigidBodyMassProps,
+            &RigidBodyVelocity,
+            &mut RigidBodyForces,
+        ),
+        With<ApplyGravity>,
+    >,
+    g: Res<GravityConstant>,
+    physics: Res<PhysicsConfig>,
+    integration: Res<IntegrationParameters>,
 ) {
-    const G: f32 = 500.0;
-
+    if physics.integrator.mode == IntegratorMode::HighAccuracy {
+        precise.clear();
+        for (mass, velocity, _) in query.iter_mut() {
+            precise.push(PreciseAccumulator {
+                com: mass.world_com.coords.cast(),
+                linvel: velocity.linvel.cast(),
+                mass: mass.mass() as f64,
+                force: CompensatedSum::default(),
+            });
+        }
+        precise_gravity(&mut precise, g.0, &physics.integrator, integration.dt);
+        for ((_, _, mut force), acc) in query.iter_mut().zip(&*precise) {
+            force.force += Vector3::from(acc.force.total()).cast::<f32>();
+        }
+        return;
+    }
+    if physics.integrator.mode == IntegratorMode::BarnesHut {
+        let bodies: Vec<(Vec3, f32)> = query
+            .iter_mut()
+            .map(|(mass, _, _)| {
+                let com = mass.world_com;
+                (Vec3::new(com.x, com.y, com.z), mass.mass())
+            })
+            .collect();
+        let forces = barnes_hut::forces(g.0, physics.integrator.barnes_hut_theta, &bodies);
+        for ((_, _, mut force), total) in query.iter_mut().zip(forces) {
+            force.force += Vector3::new(total.x, total.y, total.z);
+        }
+        return;
+    }
     accumulator.clear();
-    for (mass, _) in query.iter_mut() {
+    for (mass, _, _) in query.iter_mut() {
         accumulator.push(Accumulator {
             com: mass.world_com,
             mass: mass.mass(),
@@ -223,7 +734,7 @@ fn gravity(
         let current = &mut current[i - 1];
         for other in rest {
             let diff = other.com - current.com;
-            let force_magnitude = G * current.mass * other.mass / diff.norm_squared();
+            let force_magnitude = g.0 * current.mass * other.mass / diff.norm_squared();
             if !force_magnitude.is_finite() {
                 continue;
             }
@@ -233,7 +744,316 @@ fn gravity(
             other.force -= force;
         }
     }
-    for ((_, mut force), acc) in query.iter_mut().zip(&*accumulator) {
+    for ((_, _, mut force), acc) in query.iter_mut().zip(&*accumulator) {
         force.force += acc.force;
     }
 }
+
+/// Intermediate accumulator for high accuracy gravity calculations.
+struct PreciseAccumulator {
+    /// Center of mass of the rigidbody.
+    com: Vector3<f64>,
+    /// Linear velocity of the rigidbody.
+    linvel: Vector3<f64>,
+    /// Mass of the rigidbody.
+    mass: f64,
+    /// Accumulated forces.
+    force: CompensatedSum,
+}
+
+/// Computes gravity using double precision and compensated summation. Forces between planets in a
+/// close encounter are averaged over several points along their paths during the step, since the
+/// force changes too quickly there for the positions at the start of the step to be
+/// representative.
+fn precise_gravity(
+    accumulator: &mut [PreciseAccumulator],
+    g: f32,
+    config: &IntegratorConfig,
+    dt: f32,
+) {
+    let g = g as f64;
+    let dt = dt as f64;
+    let close_sq = (config.close_encounter_distance as f64).powi(2);
+    let substeps = config.close_encounter_substeps;
+
+    for i in 1..accumulator.len() {
+        let (current, rest) = accumulator.split_at_mut(i);
+        let current = &mut current[i - 1];
+        for other in rest {
+            let diff = other.com - current.com;
+            let force = if diff.norm_squared() < close_sq && substeps > 1 {
+                // Sample at the middle of each substep, following straight line paths.
+                let relvel = other.linvel - current.linvel;
+                let mut sum = CompensatedSum::default();
+                for step in 0..substeps {
+                    let t = dt * (step as f64 + 0.5) / substeps as f64;
+                    sum.add(pair_force(g, current.mass, other.mass, diff + relvel * t).into());
+                }
+                Vector3::from(sum.total()) / substeps as f64
+            } else {
+                pair_force(g, current.mass, other.mass, diff)
+            };
+            current.force.add(force.into());
+            other.force.add((-force).into());
+        }
+    }
+}
+
+/// Computes the gravitational force on a body from another body at offset diff from it. Returns
+/// zero if the bodies are in the same place.
+fn pair_force(g: f64, mass1: f64, mass2: f64, diff: Vector3<f64>) -> Vector3<f64> {
+    let dist_sq = diff.norm_squared();
+    let force_magnitude = g * mass1 * mass2 / dist_sq;
+    if !force_magnitude.is_finite() {
+        return Vector3::zeros();
+    }
+    diff * (force_magnitude / dist_sq.sqrt())
+}
+
+/// A field accelerating every body with [`ApplyGravity`] by the same amount, regardless of mass.
+pub struct UniformGravityField {
+    pub acceleration: Vec3,
+}
+
+/// A field pulling every body with [`ApplyGravity`] towards the entity's [`GlobalTransform`].
+pub struct RadialGravityField {
+    /// Acceleration at a distance of 1. Negative values push bodies away.
+    pub strength: f32,
+    /// How the acceleration falls off with distance.
+    pub falloff: Falloff,
+}
+
+impl RadialGravityField {
+    /// Acceleration of a body at `offset` from the center of the field, or zero at the center.
+    fn acceleration(&self, offset: Vec3) -> Vec3 {
+        let dist = offset.length();
+        let magnitude = match self.falloff {
+            Falloff::None => self.strength,
+            Falloff::Linear => self.strength / dist,
+            Falloff::InverseSquare => self.strength / (dist * dist),
+        };
+        let accel = offset * (-magnitude / dist);
+        if accel.is_finite() {
+            accel
+        } else {
+            Vec3::ZERO
+        }
+    }
+}
+
+/// Spawns the gravity fields from the physics config.
+fn spawn_gravity_fields(mut commands: Commands, physics: Res<PhysicsConfig>) {
+    let fields = &physics.fields;
+    let uniform = to_vec3(&fields.uniform);
+    if uniform != Vec3::ZERO {
+        commands.spawn().insert(UniformGravityField {
+            acceleration: uniform,
+        });
+    }
+    for radial in &fields.radial {
+        commands.spawn_bundle((
+            RadialGravityField {
+                strength: radial.strength,
+                falloff: radial.falloff,
+            },
+            Transform::from_translation(to_vec3(&radial.center)),
+            GlobalTransform::default(),
+        ));
+    }
+}
+
+/// Applies the forces from gravity fields to rigidbodies.
+fn gravity_fields(
+    uniform: Query<&UniformGravityField>,
+    radial: Query<(&RadialGravityField, &GlobalTransform)>,
+    mut bodies: Query<(&RigidBodyMassProps, &mut RigidBodyForces), With<ApplyGravity>>,
+) {
+    let uniform = uniform
+        .iter()
+        .fold(Vec3::ZERO, |total, field| total + field.acceleration);
+    if uniform == Vec3::ZERO && radial.iter().next().is_none() {
+        return;
+    }
+    for (mass, mut force) in bodies.iter_mut() {
+        let com = mass.world_com;
+        let position = Vec3::new(com.x, com.y, com.z);
+        let accel = radial.iter().fold(uniform, |accel, (field, transform)| {
+            accel + field.acceleration(position - transform.translation)
+        });
+        let total = accel * mass.mass();
+        force.force += Vector3::new(total.x, total.y, total.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::config::camera::CameraKeyframe;
+
+    fn keyframe(time: f32, x: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            position: Vector { x, y: 0.0, z: 0.0 },
+        }
+    }
+
+    fn camera_path(interpolation: Interpolation, keyframes: Vec<CameraKeyframe>) -> CameraPath {
+        CameraPath {
+            keyframes,
+            interpolation,
+            look_at: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn planet_materials_are_reused() {
+        let mut app = App::build();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<StandardMaterial>();
+        let mut materials = app
+            .world_mut()
+            .get_resource_mut::<Assets<StandardMaterial>>()
+            .unwrap();
+        let mut planet_materials = PlanetMaterials::default();
+        let surface = Handle::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let scenario: Vec<_> = (0..200)
+                .map(|_| planet_materials.get(ColorKey::random(&mut rng), &surface, &mut materials))
+                .collect();
+            assert!(materials.len() <= ColorKey::COUNT);
+            drop(scenario);
+        }
+        let key = ColorKey::random(&mut rng);
+        assert_eq!(
+            planet_materials.get(key, &surface, &mut materials),
+            planet_materials.get(key, &surface, &mut materials)
+        );
+    }
+
+    #[test]
+    fn color_keys_round_trip_through_indices() {
+        for index in 0..ColorKey::COUNT as u16 {
+            assert_eq!(ColorKey::from_index(index).unwrap().index(), index);
+        }
+        assert_eq!(ColorKey::from_index(ColorKey::COUNT as u16), None);
+    }
+
+    #[test]
+    fn surface_wraps_around() {
+        for y in 0..SURFACE_HEIGHT {
+            let v = y as f32 / SURFACE_HEIGHT as f32;
+            assert!((surface_brightness(0.0, v) - surface_brightness(1.0, v)).abs() < 1e-4);
+            for x in 0..SURFACE_WIDTH {
+                let brightness = surface_brightness(x as f32 / SURFACE_WIDTH as f32, v);
+                assert!((0.6..=1.0).contains(&brightness));
+            }
+        }
+    }
+
+    #[test]
+    fn radial_fields_fall_off() {
+        let field = |falloff| RadialGravityField {
+            strength: 8.0,
+            falloff,
+        };
+        let near = Vec3::new(2.0, 0.0, 0.0);
+        let far = Vec3::new(0.0, -4.0, 0.0);
+        let none = field(Falloff::None);
+        assert_eq!(none.acceleration(near), Vec3::new(-8.0, 0.0, 0.0));
+        assert_eq!(none.acceleration(far), Vec3::new(0.0, 8.0, 0.0));
+        let linear = field(Falloff::Linear);
+        assert_eq!(linear.acceleration(near), Vec3::new(-4.0, 0.0, 0.0));
+        assert_eq!(linear.acceleration(far), Vec3::new(0.0, 2.0, 0.0));
+        let inverse_square = field(Falloff::InverseSquare);
+        assert_eq!(inverse_square.acceleration(near), Vec3::new(-2.0, 0.0, 0.0));
+        assert_eq!(inverse_square.acceleration(far), Vec3::new(0.0, 0.5, 0.0));
+        assert_eq!(inverse_square.acceleration(Vec3::ZERO), Vec3::ZERO);
+        let repelling = RadialGravityField {
+            strength: -8.0,
+            falloff: Falloff::None,
+        };
+        assert_eq!(repelling.acceleration(near), Vec3::new(8.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn camera_paths_play_in_order_and_loop() {
+        let paths = vec![
+            camera_path(
+                Interpolation::Linear,
+                vec![keyframe(0.0, 0.0), keyframe(2.0, 1.0)],
+            ),
+            camera_path(
+                Interpolation::Linear,
+                vec![keyframe(0.0, 0.0), keyframe(3.0, 1.0)],
+            ),
+        ];
+        let playing = |elapsed| {
+            let (path, t) = current_path(&paths, elapsed);
+            let index = paths.iter().position(|p| std::ptr::eq(p, path)).unwrap();
+            (index, t)
+        };
+        assert_eq!(playing(0.0), (0, 0.0));
+        assert_eq!(playing(1.0), (0, 1.0));
+        assert_eq!(playing(2.0), (1, 0.0));
+        assert_eq!(playing(4.5), (1, 2.5));
+        // Wraps around to the first path once the last one ends.
+        assert_eq!(playing(5.0), (0, 0.0));
+        assert_eq!(playing(16.5), (0, 1.5));
+
+        // A path which takes no time just holds its only keyframe.
+        let still = vec![camera_path(Interpolation::Smooth, vec![keyframe(0.0, 4.0)])];
+        let (path, t) = current_path(&still, 10.0);
+        assert_eq!(t, 0.0);
+        assert_eq!(path_position(path, t), Vec3::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn camera_paths_pass_through_keyframes() {
+        for &interpolation in &[Interpolation::Linear, Interpolation::Smooth] {
+            let path = camera_path(
+                interpolation,
+                vec![
+                    keyframe(0.0, 0.0),
+                    keyframe(1.0, 2.0),
+                    keyframe(3.0, 4.0),
+                    keyframe(4.0, 10.0),
+                ],
+            );
+            for keyframe in &path.keyframes {
+                let position = path_position(&path, keyframe.time);
+                assert!(
+                    (position - to_vec3(&keyframe.position)).length() < 1e-5,
+                    "{:?} at {}: {}",
+                    interpolation,
+                    keyframe.time,
+                    position
+                );
+            }
+            assert_eq!(path_position(&path, 5.0), Vec3::new(10.0, 0.0, 0.0));
+        }
+
+        let single = camera_path(Interpolation::Smooth, vec![keyframe(2.0, 3.0)]);
+        for &t in &[0.0, 2.0, 5.0] {
+            assert_eq!(path_position(&single, t), Vec3::new(3.0, 0.0, 0.0));
+        }
+
+        let pair = camera_path(
+            Interpolation::Smooth,
+            vec![keyframe(1.0, 0.0), keyframe(3.0, 4.0)],
+        );
+        assert_eq!(path_position(&pair, 0.0), Vec3::ZERO);
+        assert_eq!(path_position(&pair, 1.0), Vec3::ZERO);
+        assert!((path_position(&pair, 2.0) - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-5);
+        assert_eq!(path_position(&pair, 3.0), Vec3::new(4.0, 0.0, 0.0));
+    }
+}
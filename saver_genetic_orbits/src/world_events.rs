@@ -0,0 +1,477 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A single channel for the notable things which happen in the world: scenarios starting and
+//! ending, planets merging, and planets leaving the scored area. Systems which want to show or
+//! react to these, such as overlays, read [`WorldEvent`]s rather than each tracking them again.
+//!
+//! Every event is logged, rate limited per kind as set in the
+//! [`WorldEventsConfig`](crate::config::events::WorldEventsConfig), and when a `jsonl_path` is set,
+//! also appended to that file as a line of JSON for external monitoring.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Serialize;
+use xsecurelock_saver::engine::SaverTime;
+
+use crate::collisions::{CollisionMerged, Collisions};
+use crate::config::events::EventsConfig;
+use crate::config::physics::PhysicsConfig;
+use crate::config::scoring::ScoringConfig;
+use crate::determinism::PhysicsChecksum;
+use crate::statustracker::{in_scored_area, ActiveWorld};
+use crate::world::Planet;
+use crate::SaverState;
+
+/// Plugin which sends and logs [`WorldEvent`]s.
+pub struct WorldEventsPlugin;
+
+impl Plugin for WorldEventsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<WorldEvent>()
+            .init_resource::<EventLog>()
+            .init_resource::<ScoredPlanets>()
+            .add_system_to_stage(CoreStage::Last, log_events.system())
+            .add_system_set(
+                SystemSet::on_enter(SaverState::Run).with_system(scenario_started.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(SaverState::Run)
+                    .with_system(merges.system())
+                    .with_system(ejections.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(SaverState::Run).with_system(scenario_ended.system()),
+            );
+    }
+}
+
+/// Something notable which happened in the world. Planets are identified by their entity ids,
+/// which are only unique within a scenario.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WorldEvent {
+    /// A scenario started running.
+    ScenarioStarted {
+        /// Number of planets in the world.
+        planets: usize,
+        /// The scenario the world was mutated from, or None for a new root.
+        parent: Option<u64>,
+        /// The family of the parent, if any.
+        family: Option<u64>,
+        /// The world's generation, 0 for a new root.
+        generation: u64,
+    },
+    /// The running scenario ended.
+    ScenarioEnded {
+        score: f64,
+        /// Whether the score was discarded rather than stored.
+        discarded: bool,
+        collisions: u32,
+        merges: u32,
+        near_misses: u32,
+        /// Checksum of the simulation, in deterministic runs.
+        checksum: Option<PhysicsChecksum>,
+    },
+    /// Two planets stayed in contact long enough to count as merged.
+    Merge {
+        first: u32,
+        second: u32,
+        /// Halfway between the planets' centers of mass.
+        position: [f32; 3],
+    },
+    /// A planet left the scored area.
+    Ejection {
+        planet: u32,
+        mass: f32,
+        position: [f32; 3],
+    },
+}
+
+impl WorldEvent {
+    /// Name of the kind of event, as written in the JSON lines file.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WorldEvent::ScenarioStarted { .. } => "scenario_started",
+            WorldEvent::ScenarioEnded { .. } => "scenario_ended",
+            WorldEvent::Merge { .. } => "merge",
+            WorldEvent::Ejection { .. } => "ejection",
+        }
+    }
+}
+
+impl fmt::Display for WorldEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WorldEvent::ScenarioStarted {
+                planets,
+                parent: Some(parent),
+                family,
+                generation,
+            } => write!(
+                f,
+                "Scenario started with {} planets, mutated from {} (family {}, generation {})",
+                planets,
+                parent,
+                family.unwrap_or(parent),
+                generation
+            ),
+            WorldEvent::ScenarioStarted { planets, .. } => {
+                write!(f, "Scenario started with {} planets as a new root", planets)
+            }
+            WorldEvent::ScenarioEnded {
+                score,
+                discarded,
+                collisions,
+                merges,
+                near_misses,
+                checksum,
+            } => {
+                write!(
+                    f,
+                    "Scenario ended with score {:.2}{}, after {} collisions, {} merges and {} \
+                     near misses",
+                    score,
+                    if discarded { " (discarded)" } else { "" },
+                    collisions,
+                    merges,
+                    near_misses
+                )?;
+                match checksum {
+                    Some(checksum) => write!(
+                        f,
+                        ", with physics checksum {:016x} over {} ticks",
+                        checksum.hash, checksum.ticks
+                    ),
+                    None => Ok(()),
+                }
+            }
+            WorldEvent::Merge {
+                first,
+                second,
+                position: [x, y, z],
+            } => write!(
+                f,
+                "Planets {} and {} merged at ({:.0}, {:.0}, {:.0})",
+                first, second, x, y, z
+            ),
+            WorldEvent::Ejection {
+                planet,
+                mass,
+                position: [x, y, z],
+            } => write!(
+                f,
+                "Planet {} with mass {:.0} left the scored area at ({:.0}, {:.0}, {:.0})",
+                planet, mass, x, y, z
+            ),
+        }
+    }
+}
+
+/// Limits how many events of each kind are logged, allowing short bursts. Each kind has a bucket
+/// which refills at the limit and holds up to a second's worth of events.
+#[derive(Debug, Default)]
+struct RateLimit {
+    per_second: f32,
+    buckets: HashMap<&'static str, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f32,
+    /// Events not logged since the last one which was.
+    suppressed: u32,
+}
+
+impl RateLimit {
+    fn new(per_second: f32) -> Self {
+        Self {
+            per_second,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn capacity(&self) -> f32 {
+        self.per_second.max(1.0)
+    }
+
+    /// Refills the buckets after `delta`.
+    fn refill(&mut self, delta: Duration) {
+        let capacity = self.capacity();
+        let added = self.per_second * delta.as_secs_f32();
+        for bucket in self.buckets.values_mut() {
+            bucket.tokens = (bucket.tokens + added).min(capacity);
+        }
+    }
+
+    /// Whether to log an event of `kind`. If so, returns the number of events of that kind which
+    /// weren't logged since the last which was.
+    fn admit(&mut self, kind: &'static str) -> Option<u32> {
+        if self.per_second <= 0.0 {
+            return None;
+        }
+        let capacity = self.capacity();
+        let bucket = self.buckets.entry(kind).or_insert(Bucket {
+            tokens: capacity,
+            suppressed: 0,
+        });
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Some(mem::take(&mut bucket.suppressed))
+        } else {
+            bucket.suppressed += 1;
+            None
+        }
+    }
+}
+
+/// Where events are logged.
+struct EventLog {
+    limit: RateLimit,
+    jsonl: Option<BufWriter<File>>,
+}
+
+impl FromWorld for EventLog {
+    fn from_world(world: &mut World) -> Self {
+        let config = &world.get_resource::<EventsConfig>().unwrap().world_events;
+        let jsonl = config.jsonl_path.as_ref().and_then(|path| {
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => {
+                    info!("Appending world events to {}", path.display());
+                    Some(BufWriter::new(file))
+                }
+                Err(err) => {
+                    warn!(
+                        "Unable to open {} for world events: {}",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            }
+        });
+        Self {
+            limit: RateLimit::new(config.max_logged_per_second),
+            jsonl,
+        }
+    }
+}
+
+/// A line of the JSON lines file.
+#[derive(Serialize)]
+struct Record<'a> {
+    /// Seconds since the Unix epoch.
+    time: f64,
+    #[serde(flatten)]
+    event: &'a WorldEvent,
+}
+
+/// Logs this frame's events, and writes them to the JSON lines file.
+fn log_events(
+    time: Res<SaverTime>,
+    mut log: ResMut<EventLog>,
+    mut events: EventReader<WorldEvent>,
+) {
+    let log = &mut *log;
+    log.limit.refill(time.delta());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |now| now.as_secs_f64());
+    let mut written = false;
+    for event in events.iter() {
+        match log.limit.admit(event.kind()) {
+            Some(0) => info!("{}", event),
+            Some(suppressed) => info!(
+                "{} ({} more {} events weren't logged)",
+                event,
+                suppressed,
+                event.kind()
+            ),
+            None => {}
+        }
+        if let Some(ref mut jsonl) = log.jsonl {
+            let record = Record { time: now, event };
+            let result = serde_json::to_writer(&mut *jsonl, &record)
+                .map_err(io::Error::from)
+                .and_then(|()| jsonl.write_all(b"\n"));
+            if let Err(err) = result {
+                warn!(
+                    "Unable to write world event, no longer writing them: {}",
+                    err
+                );
+                log.jsonl = None;
+            } else {
+                written = true;
+            }
+        }
+    }
+    if written {
+        if let Some(ref mut jsonl) = log.jsonl {
+            if let Err(err) = jsonl.flush() {
+                warn!(
+                    "Unable to write world events, no longer writing them: {}",
+                    err
+                );
+                log.jsonl = None;
+            }
+        }
+    }
+}
+
+/// Sends the event for the scenario starting.
+fn scenario_started(world: Res<ActiveWorld>, mut events: EventWriter<WorldEvent>) {
+    let parent = world.parent.as_ref();
+    events.send(WorldEvent::ScenarioStarted {
+        planets: world.world.planets.len(),
+        parent: parent.map(|parent| parent.id),
+        family: parent.map(|parent| parent.family),
+        generation: parent.map_or(0, |parent| parent.generation + 1),
+    });
+}
+
+/// Sends the event for the scenario ending.
+fn scenario_ended(
+    world: Res<ActiveWorld>,
+    collisions: Res<Collisions>,
+    physics: Res<PhysicsConfig>,
+    checksum: Res<PhysicsChecksum>,
+    mut events: EventWriter<WorldEvent>,
+) {
+    let counts = &collisions.scenario;
+    events.send(WorldEvent::ScenarioEnded {
+        score: world.cumulative_score,
+        discarded: world.discard,
+        collisions: counts.collisions,
+        merges: counts.merges,
+        near_misses: counts.near_misses,
+        checksum: if physics.deterministic.enabled {
+            Some(*checksum)
+        } else {
+            None
+        },
+    });
+}
+
+/// Sends events for the pairs of planets which merged this frame.
+fn merges(
+    mut merged: EventReader<CollisionMerged>,
+    planets: Query<&RigidBodyMassProps, With<Planet>>,
+    mut events: EventWriter<WorldEvent>,
+) {
+    for &CollisionMerged { first, second } in merged.iter() {
+        if let (Ok(a), Ok(b)) = (planets.get(first), planets.get(second)) {
+            let mid = (a.world_com.coords + b.world_com.coords) * 0.5;
+            events.send(WorldEvent::Merge {
+                first: first.id(),
+                second: second.id(),
+                position: [mid.x, mid.y, mid.z],
+            });
+        }
+    }
+}
+
+/// Planets which were in the scored area on the last frame.
+#[derive(Default)]
+struct ScoredPlanets(HashSet<Entity>);
+
+/// Sends events for planets which left the scored area this frame.
+fn ejections(
+    config: Res<ScoringConfig>,
+    mut scored: ResMut<ScoredPlanets>,
+    planets: Query<(Entity, &RigidBodyMassProps), With<Planet>>,
+    mut events: EventWriter<WorldEvent>,
+) {
+    let mut now_scored = HashSet::with_capacity(scored.0.len());
+    for (entity, rb) in planets.iter() {
+        if in_scored_area(&config, rb) {
+            now_scored.insert(entity);
+        } else if scored.0.contains(&entity) {
+            let com = rb.world_com;
+            events.send(WorldEvent::Ejection {
+                planet: entity.id(),
+                mass: rb.mass(),
+                position: [com.x, com.y, com.z],
+            });
+        }
+    }
+    scored.0 = now_scored;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_each_kind() {
+        let mut limit = RateLimit::new(2.0);
+        assert_eq!(limit.admit("merge"), Some(0));
+        assert_eq!(limit.admit("merge"), Some(0));
+        assert_eq!(limit.admit("merge"), None);
+        assert_eq!(limit.admit("merge"), None);
+        // Other kinds have their own limit.
+        assert_eq!(limit.admit("ejection"), Some(0));
+
+        limit.refill(Duration::from_millis(250));
+        assert_eq!(limit.admit("merge"), None);
+        limit.refill(Duration::from_millis(250));
+        assert_eq!(limit.admit("merge"), Some(3));
+        // The buckets only hold a second's worth.
+        limit.refill(Duration::from_secs(10));
+        assert_eq!(limit.admit("merge"), Some(0));
+        assert_eq!(limit.admit("merge"), Some(0));
+        assert_eq!(limit.admit("merge"), None);
+    }
+
+    #[test]
+    fn zero_limit_logs_nothing() {
+        let mut limit = RateLimit::new(0.0);
+        limit.refill(Duration::from_secs(1));
+        assert_eq!(limit.admit("merge"), None);
+    }
+
+    #[test]
+    fn slow_limits_allow_one_event() {
+        let mut limit = RateLimit::new(0.5);
+        assert_eq!(limit.admit("merge"), Some(0));
+        limit.refill(Duration::from_secs(1));
+        assert_eq!(limit.admit("merge"), None);
+        limit.refill(Duration::from_secs(1));
+        assert_eq!(limit.admit("merge"), Some(1));
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let event = WorldEvent::Merge {
+            first: 3,
+            second: 7,
+            position: [1.0, -2.0, 0.5],
+        };
+        let record = Record {
+            time: 12.5,
+            event: &event,
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"time":12.5,"event":"merge","first":3,"second":7,"position":[1.0,-2.0,0.5]}"#
+        );
+        assert_eq!(event.to_string(), "Planets 3 and 7 merged at (1, -2, 0)");
+    }
+}
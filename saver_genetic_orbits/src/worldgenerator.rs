@@ -12,23 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::error::Error;
+use std::fmt;
 use std::time::Duration;
 
 use bevy::ecs::component::Component;
 use bevy::prelude::*;
-use rand_distr::{Bernoulli, Distribution, Exp, Normal, Uniform};
+use rand::Rng;
+use rand_distr::{
+    Bernoulli, BernoulliError, Distribution, Exp, ExpError, Normal, NormalError, Uniform,
+    UnitSphere,
+};
+use xsecurelock_saver::engine::SaverTime;
 
 use crate::config::generator::{
     GeneratorConfig, MutationParameters, NewPlanetParameters, NewWorldParameters,
     PlanetMutationParameters,
 };
-use crate::config::util::{
-    Distribution as ConfDist, ExponentialDistribution, NormalDistribution, UniformDistribution,
-};
-use crate::model::{Planet, Scenario, World};
+use crate::config::util::UniformDistribution;
+use crate::determinism::SimulationRng;
+use crate::model::{Planet, Scenario, StabilityFilter, World};
 use crate::statustracker::ActiveWorld;
-use crate::storage::sqlite::SqliteStorage;
-use crate::storage::Storage;
+use crate::storage::{BoxedStorage, Storage, StorageError};
+use crate::world::GravityConstant;
 
 use super::SaverState;
 
@@ -40,7 +46,7 @@ impl Plugin for WorldGeneratorPlugin {
         app.insert_resource(DelayResume(Timer::new(Duration::from_secs(5), false)))
             .add_system_set(
                 SystemSet::on_enter(SaverState::Generate)
-                    .with_system(generate_world::<SqliteStorage>.system()),
+                    .with_system(generate_world::<BoxedStorage>.system()),
             )
             .add_system_set(
                 SystemSet::on_update(SaverState::Generate).with_system(resume.system()),
@@ -48,20 +54,90 @@ impl Plugin for WorldGeneratorPlugin {
     }
 }
 
+/// Errors from generating a world.
+#[derive(Debug)]
+pub enum GenerationError {
+    /// Reading the scenario to mutate from storage failed.
+    Storage(StorageError),
+    /// A distribution in the generator config has invalid parameters, such as after being edited
+    /// while running.
+    InvalidDistribution(String),
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerationError::Storage(err) => write!(f, "storage error: {}", err),
+            GenerationError::InvalidDistribution(err) => write!(f, "invalid distribution: {}", err),
+        }
+    }
+}
+
+impl Error for GenerationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GenerationError::Storage(err) => Some(err),
+            GenerationError::InvalidDistribution(_) => None,
+        }
+    }
+}
+
+impl From<StorageError> for GenerationError {
+    fn from(err: StorageError) -> Self {
+        GenerationError::Storage(err)
+    }
+}
+
+impl From<NormalError> for GenerationError {
+    fn from(err: NormalError) -> Self {
+        GenerationError::InvalidDistribution(err.to_string())
+    }
+}
+
+impl From<ExpError> for GenerationError {
+    fn from(err: ExpError) -> Self {
+        GenerationError::InvalidDistribution(err.to_string())
+    }
+}
+
+impl From<BernoulliError> for GenerationError {
+    fn from(err: BernoulliError) -> Self {
+        GenerationError::InvalidDistribution(err.to_string())
+    }
+}
+
 /// Generates a new world to run and inserts it into ActiveWorld, then sets the state to Run.
 fn generate_world<S: Storage + Component>(
     config: Res<GeneratorConfig>,
+    g: Res<GravityConstant>,
     mut storage: ResMut<S>,
     mut scenario: ResMut<ActiveWorld>,
     mut resume: ResMut<DelayResume>,
+    mut rng: ResMut<SimulationRng>,
 ) {
     info!("Generating world");
-    let parent = pick_parent(&mut *storage, config.create_new_scenario_probability);
+    let rng = &mut rng.generation;
+    let parent = match pick_parent(
+        &mut *storage,
+        config.create_new_scenario_probability,
+        &config.parent_stability,
+        rng,
+    ) {
+        Ok(parent) => parent,
+        Err(err) => {
+            error!(
+                "Generating new Scenario because picking a parent failed: {}",
+                err
+            );
+            None
+        }
+    };
 
     let world = match parent {
-        Some(ref parent) => generate_child_world(&parent.world, &config.mutation_parameters),
-        None => generate_new_world(&config.new_world_parameters),
-    };
+        Some(ref parent) => generate_child_world(&parent.world, &config.mutation_parameters, rng),
+        None => generate_new_world(&config.new_world_parameters, g.0, rng),
+    }
+    .unwrap_or_else(|err| panic!("Unable to generate a world: {}", err));
 
     scenario.start(world, parent);
 
@@ -71,7 +147,11 @@ fn generate_world<S: Storage + Component>(
 struct DelayResume(Timer);
 
 /// Delays returning to run by half a second.
-fn resume(mut state: ResMut<State<SaverState>>, mut timer: ResMut<DelayResume>, time: Res<Time>) {
+fn resume(
+    mut state: ResMut<State<SaverState>>,
+    mut timer: ResMut<DelayResume>,
+    time: Res<SaverTime>,
+) {
     timer.0.tick(time.delta());
     if timer.0.just_finished() {
         if let Err(err) = state.set(SaverState::Run) {
@@ -80,25 +160,21 @@ fn resume(mut state: ResMut<State<SaverState>>, mut timer: ResMut<DelayResume>,
     }
 }
 
-/// Picks a scenario to mutate or None if a new scenario should be generated.
+/// Picks a scenario matching the filter to mutate or None if a new scenario should be generated.
 fn pick_parent(
     storage: &mut impl Storage,
     create_new_scenario_probability: f64,
-) -> Option<Scenario> {
-    let num_scenarios = match storage.num_scenarios() {
-        Ok(0) => {
-            info!("No existing scenarios to mutate, generating new one by default");
-            return None;
-        }
-        Ok(ns) => ns,
-        Err(err) => {
-            error!("Error getting number of scenarios: {}", err);
-            return None;
-        }
-    };
-    let picked_scenario = select_index(num_scenarios, create_new_scenario_probability);
-    match storage.get_nth_scenario_by_score(picked_scenario) {
-        Ok(Some(scenario)) => {
+    filter: &StabilityFilter,
+    rng: &mut impl Rng,
+) -> Result<Option<Scenario>, GenerationError> {
+    let num_scenarios = storage.num_matching_scenarios(filter)?;
+    if num_scenarios == 0 {
+        info!("No existing scenarios to mutate, generating new one by default");
+        return Ok(None);
+    }
+    let picked_scenario = select_index(num_scenarios, create_new_scenario_probability, rng)?;
+    match storage.get_nth_matching_scenario_by_score(picked_scenario, filter)? {
+        Some(scenario) => {
             info!(
                 "Mutating Scenario {} (parent: {:?}, family: {}, generation: {}, score: {}, \
                 planets: {})",
@@ -109,18 +185,11 @@ fn pick_parent(
                 scenario.score,
                 scenario.world.planets.len(),
             );
-            Some(scenario)
+            Ok(Some(scenario))
         }
-        Ok(None) => {
+        None => {
             info!("Generating new Scenario");
-            None
-        }
-        Err(err) => {
-            error!(
-                "Generating new Scenario because of error fetching scenario {}: {}",
-                picked_scenario, err,
-            );
-            None
+            Ok(None)
         }
     }
 }
@@ -128,93 +197,75 @@ fn pick_parent(
 /// Selects a random index from the number of scenarios. The selected index may be out of
 /// range.  Uses an exponential distribution where the probability of choosing an out of range
 /// index (and thus starting a new scenario) is given by the config.
-fn select_index(num_items: u64, create_new_scenario_probability: f64) -> u64 {
+fn select_index(
+    num_items: u64,
+    create_new_scenario_probability: f64,
+    rng: &mut impl Rng,
+) -> Result<u64, GenerationError> {
     assert!(num_items > 0);
     // The CDF of the exponential distribution is f(x) = 1-e^(-lx). In order to have
     // P probability of getting a value in-range, we want to choose l such that
     // f(num-scenarios) = P. Therefore we solve for l:
     // l = -ln(1 - P) / num-scenarios
     let lambda = -(create_new_scenario_probability.ln()) / num_items as f64;
-    let dist = Exp::new(lambda).unwrap();
-    dist.sample(&mut rand::thread_rng()) as u64
+    let dist = Exp::new(lambda)?;
+    Ok(dist.sample(rng) as u64)
 }
 
-/// Randomly generate a new world.
-fn generate_new_world(params: &NewWorldParameters) -> World {
-    let num_planets = match params.num_planets_dist {
-        ConfDist::Exponential(ExponentialDistribution(lambda)) => {
-            Exp::new(lambda).unwrap().sample(&mut rand::thread_rng()) as usize
-        }
-        ConfDist::Normal(NormalDistribution {
-            mean,
-            standard_deviation,
-        }) => Normal::new(mean, standard_deviation)
-            .unwrap()
-            .sample(&mut rand::thread_rng())
-            .round() as usize,
-        ConfDist::Uniform(UniformDistribution { min, max }) => {
-            Uniform::new_inclusive(min as usize, max as usize).sample(&mut rand::thread_rng())
-        }
-    };
+/// Randomly generate a new world. `g` is the gravitational constant, which sets how fast moons
+/// orbit.
+fn generate_new_world(
+    params: &NewWorldParameters,
+    g: f32,
+    rng: &mut impl Rng,
+) -> Result<World, GenerationError> {
+    let num_planets = params.num_planets_dist.sample_usize(rng);
     let num_planets = params.num_planets_range.clamp_inclusive(num_planets);
     info!("Generating {} planets", num_planets);
 
     let mut planets = Vec::with_capacity(num_planets);
     for _ in 0..num_planets {
-        planets.push(generate_new_planet(&params.planet_parameters));
+        planets.push(generate_new_planet(&params.planet_parameters, rng)?);
+    }
+    let mut moons = Vec::new();
+    for planet in &mut planets {
+        moons.extend(generate_moons(planet, params, g, rng)?);
+    }
+    if !moons.is_empty() {
+        info!("Generated {} moons", moons.len());
     }
+    planets.extend(moons);
 
-    let mut world = World { planets };
+    let mut world = World {
+        planets,
+        radius_law: params.radius_law,
+    };
     world.merge_overlapping_planets();
     info!(
         "After overlap cleanup, world had {} planets",
         world.planets.len()
     );
-    world
+    Ok(world)
 }
 
 /// Mutate the given parent world to generate a new random world.
-fn generate_child_world(parent: &World, params: &MutationParameters) -> World {
-    let num_planets_to_add = match params.add_planets_dist {
-        ConfDist::Exponential(ExponentialDistribution(lambda)) => {
-            Exp::new(lambda).unwrap().sample(&mut rand::thread_rng()) as usize
-        }
-        ConfDist::Normal(NormalDistribution {
-            mean,
-            standard_deviation,
-        }) => Normal::new(mean, standard_deviation)
-            .unwrap()
-            .sample(&mut rand::thread_rng())
-            .round() as usize,
-        ConfDist::Uniform(UniformDistribution { min, max }) => {
-            Uniform::new_inclusive(min as usize, max as usize).sample(&mut rand::thread_rng())
-        }
-    };
+fn generate_child_world(
+    parent: &World,
+    params: &MutationParameters,
+    rng: &mut impl Rng,
+) -> Result<World, GenerationError> {
+    let num_planets_to_add = params.add_planets_dist.sample_usize(rng);
     let num_planets_to_add = params
         .add_planets_limits
         .clamp_inclusive(num_planets_to_add);
 
-    let num_planets_to_remove = match params.remove_planets_dist {
-        ConfDist::Exponential(ExponentialDistribution(lambda)) => {
-            Exp::new(lambda).unwrap().sample(&mut rand::thread_rng()) as usize
-        }
-        ConfDist::Normal(NormalDistribution {
-            mean,
-            standard_deviation,
-        }) => Normal::new(mean, standard_deviation)
-            .unwrap()
-            .sample(&mut rand::thread_rng())
-            .round() as usize,
-        ConfDist::Uniform(UniformDistribution { min, max }) => {
-            Uniform::new_inclusive(min as usize, max as usize).sample(&mut rand::thread_rng())
-        }
-    };
+    let num_planets_to_remove = params.remove_planets_dist.sample_usize(rng);
     let num_planets_to_remove = params
         .remove_planets_limits
         .clamp_inclusive(num_planets_to_remove);
     let num_planets_to_remove = parent.planets.len().min(num_planets_to_remove);
 
-    let change_planet_dist = Bernoulli::new(params.fraction_of_planets_to_change).unwrap();
+    let change_planet_dist = Bernoulli::new(params.fraction_of_planets_to_change)?;
 
     // Order of changes is remove, modify, add. This is so we don't remove or modify newly
     // added planets and don't modify planets that are about to be removed.
@@ -225,7 +276,7 @@ fn generate_child_world(parent: &World, params: &MutationParameters) -> World {
     for _ in 0..num_planets_to_remove {
         // panics if start >= end, but this loop doesn't run if planets.len() == 0, so this is
         // safe.
-        let selected = Uniform::new(0, world.planets.len()).sample(&mut rand::thread_rng());
+        let selected = Uniform::new(0, world.planets.len()).sample(rng);
         world.planets.remove(selected);
     }
     info!("Removed {} planets", num_planets_to_remove);
@@ -233,8 +284,8 @@ fn generate_child_world(parent: &World, params: &MutationParameters) -> World {
     // Modify
     let mut num_modified = 0;
     for planet in world.planets.iter_mut() {
-        if change_planet_dist.sample(&mut rand::thread_rng()) {
-            mutate_planet(planet, &params.planet_mutation_parameters);
+        if change_planet_dist.sample(rng) {
+            mutate_planet(planet, &params.planet_mutation_parameters, rng)?;
             num_modified += 1;
         }
     }
@@ -243,7 +294,7 @@ fn generate_child_world(parent: &World, params: &MutationParameters) -> World {
     for _ in 0..num_planets_to_add {
         world
             .planets
-            .push(generate_new_planet(&params.new_planet_parameters));
+            .push(generate_new_planet(&params.new_planet_parameters, rng)?);
     }
     info!("Added {} planets", num_planets_to_add);
 
@@ -252,110 +303,147 @@ fn generate_child_world(parent: &World, params: &MutationParameters) -> World {
         "After overlap cleanup, world had {} planets",
         world.planets.len()
     );
-    world
+    Ok(world)
 }
 
-/// Generates a new randomly sized planet at a random location with random velocity.
-fn generate_new_planet(params: &NewPlanetParameters) -> Planet {
+/// Generates a new randomly sized planet at a random location with random velocity and spin.
+fn generate_new_planet(
+    params: &NewPlanetParameters,
+    rng: &mut impl Rng,
+) -> Result<Planet, GenerationError> {
     let x_dist = Uniform::new_inclusive(params.start_position.x.min, params.start_position.x.max);
     let y_dist = Uniform::new_inclusive(params.start_position.y.min, params.start_position.y.max);
     let z_dist = Uniform::new_inclusive(params.start_position.z.min, params.start_position.z.max);
 
     let position = Vec3::new(
-        x_dist.sample(&mut rand::thread_rng()) as f32,
-        y_dist.sample(&mut rand::thread_rng()) as f32,
-        z_dist.sample(&mut rand::thread_rng()) as f32,
+        x_dist.sample(rng) as f32,
+        y_dist.sample(rng) as f32,
+        z_dist.sample(rng) as f32,
     );
 
     let x_velocity_dist = Normal::new(
         params.start_velocity.x.mean,
         params.start_velocity.x.standard_deviation,
-    )
-    .unwrap();
+    )?;
     let y_velocity_dist = Normal::new(
         params.start_velocity.y.mean,
         params.start_velocity.y.standard_deviation,
-    )
-    .unwrap();
+    )?;
     let z_velocity_dist = Normal::new(
         params.start_velocity.z.mean,
         params.start_velocity.z.standard_deviation,
-    )
-    .unwrap();
+    )?;
 
     let velocity = Vec3::new(
-        x_velocity_dist.sample(&mut rand::thread_rng()) as f32,
-        y_velocity_dist.sample(&mut rand::thread_rng()) as f32,
-        z_velocity_dist.sample(&mut rand::thread_rng()) as f32,
+        x_velocity_dist.sample(rng) as f32,
+        y_velocity_dist.sample(rng) as f32,
+        z_velocity_dist.sample(rng) as f32,
     );
 
-    let mass_dist =
-        Normal::new(params.start_mass.mean, params.start_mass.standard_deviation).unwrap();
-    let mass = params
-        .min_start_mass
-        .max(mass_dist.sample(&mut rand::thread_rng()) as f32);
+    let mass_dist = Normal::new(params.start_mass.mean, params.start_mass.standard_deviation)?;
+    let mass = params.min_start_mass.max(mass_dist.sample(rng) as f32);
 
-    Planet {
+    Ok(Planet {
         position,
         velocity,
         mass,
+        spin: random_spin(&params.spin, rng),
+    })
+}
+
+/// Seeds moons in circular orbits around the planet, if it is heavy enough and is picked to have
+/// them. The planet recoils from each moon, so their total momentum doesn't change.
+fn generate_moons(
+    planet: &mut Planet,
+    params: &NewWorldParameters,
+    g: f32,
+    rng: &mut impl Rng,
+) -> Result<Vec<Planet>, GenerationError> {
+    let moons = &params.moons;
+    if planet.mass < moons.min_host_mass || !Bernoulli::new(moons.probability)?.sample(rng) {
+        return Ok(vec![]);
     }
+    let count = moons.count_dist.sample_usize(rng);
+    let mass_dist = Uniform::new_inclusive(moons.mass_fraction.min, moons.mass_fraction.max);
+    let orbit_dist = Uniform::new_inclusive(moons.orbit_radius.min, moons.orbit_radius.max);
+    let radius = params.radius_law.radius(planet.mass);
+    let mut generated = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mass = params
+            .planet_parameters
+            .min_start_mass
+            .max(planet.mass * mass_dist.sample(rng) as f32);
+        let offset = random_direction(rng);
+        let distance = radius * orbit_dist.sample(rng) as f32;
+        // Any direction square to the offset makes a circular orbit, in some plane.
+        let (first, second) = offset.any_orthonormal_pair();
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let direction = first * angle.cos() + second * angle.sin();
+        // Circular orbit: v^2 / r = G * (M + m) / r^2, split between the two by their masses.
+        let total_mass = planet.mass + mass;
+        let speed = (g * total_mass / distance).sqrt();
+        generated.push(Planet {
+            position: planet.position + offset * distance,
+            velocity: planet.velocity + direction * (speed * planet.mass / total_mass),
+            mass,
+            spin: random_spin(&params.planet_parameters.spin, rng),
+        });
+        planet.velocity -= direction * (speed * mass / total_mass);
+    }
+    Ok(generated)
+}
+
+/// Spin about an axis pointing anywhere, at a speed from the range.
+fn random_spin(speed: &UniformDistribution, rng: &mut impl Rng) -> Vec3 {
+    let speed_dist = Uniform::new_inclusive(speed.min, speed.max);
+    random_direction(rng) * speed_dist.sample(rng) as f32
+}
+
+/// A unit vector pointing anywhere.
+fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    let [x, y, z]: [f32; 3] = UnitSphere.sample(rng);
+    Vec3::new(x, y, z)
 }
 
 /// Mutates a planet by making small changes to the mass, position, and velocity.
-fn mutate_planet(planet: &mut Planet, params: &PlanetMutationParameters) {
+fn mutate_planet(
+    planet: &mut Planet,
+    params: &PlanetMutationParameters,
+    rng: &mut impl Rng,
+) -> Result<(), GenerationError> {
     let x_pos_change = Normal::new(
         params.position_change.x.mean,
         params.position_change.x.standard_deviation,
-    )
-    .unwrap()
-    .sample(&mut rand::thread_rng()) as f32;
+    )?
+    .sample(rng) as f32;
     let y_pos_change = Normal::new(
         params.position_change.y.mean,
         params.position_change.y.standard_deviation,
-    )
-    .unwrap()
-    .sample(&mut rand::thread_rng()) as f32;
+    )?
+    .sample(rng) as f32;
     let z_pos_change = Normal::new(
         params.position_change.z.mean,
         params.position_change.z.standard_deviation,
-    )
-    .unwrap()
-    .sample(&mut rand::thread_rng()) as f32;
+    )?
+    .sample(rng) as f32;
 
     let x_vel_change = Normal::new(
         params.velocity_change.x.mean,
         params.velocity_change.x.standard_deviation,
-    )
-    .unwrap()
-    .sample(&mut rand::thread_rng()) as f32;
+    )?
+    .sample(rng) as f32;
     let y_vel_change = Normal::new(
         params.velocity_change.y.mean,
         params.velocity_change.y.standard_deviation,
-    )
-    .unwrap()
-    .sample(&mut rand::thread_rng()) as f32;
+    )?
+    .sample(rng) as f32;
     let z_vel_change = Normal::new(
         params.velocity_change.z.mean,
         params.velocity_change.z.standard_deviation,
-    )
-    .unwrap()
-    .sample(&mut rand::thread_rng()) as f32;
+    )?
+    .sample(rng) as f32;
 
-    let mass_change = match params.mass_change {
-        ConfDist::Exponential(ExponentialDistribution(lambda)) => {
-            Exp::new(lambda).unwrap().sample(&mut rand::thread_rng())
-        }
-        ConfDist::Normal(NormalDistribution {
-            mean,
-            standard_deviation,
-        }) => Normal::new(mean, standard_deviation)
-            .unwrap()
-            .sample(&mut rand::thread_rng()),
-        ConfDist::Uniform(UniformDistribution { min, max }) => {
-            Uniform::new_inclusive(min, max).sample(&mut rand::thread_rng())
-        }
-    } as f32;
+    let mass_change = params.mass_change.sample_f64(rng) as f32;
 
     planet.position.x += x_pos_change;
     planet.position.y += y_pos_change;
@@ -365,4 +453,57 @@ fn mutate_planet(planet: &mut Planet, params: &PlanetMutationParameters) {
     planet.velocity.z += z_vel_change;
     planet.mass += mass_change;
     planet.mass = params.min_mass.max(planet.mass);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::config::util::Distribution as CountDistribution;
+
+    #[test]
+    fn moons_orbit_their_planet() {
+        let g = 500.;
+        let mut params = NewWorldParameters::default();
+        params.moons.probability = 1.;
+        params.moons.count_dist =
+            CountDistribution::Uniform(UniformDistribution { min: 1., max: 1. });
+        let original = Planet {
+            position: Vec3::new(100., 0., 0.),
+            velocity: Vec3::new(0., 5., 0.),
+            mass: 1000.,
+            spin: Vec3::ZERO,
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let mut planet = original.clone();
+            let moons = generate_moons(&mut planet, &params, g, &mut rng).unwrap();
+            assert_eq!(moons.len(), 1);
+            let moon = &moons[0];
+            assert!(moon.mass >= 10. && moon.mass <= 50.);
+
+            let momentum = planet.velocity * planet.mass + moon.velocity * moon.mass;
+            assert!((momentum - original.velocity * (original.mass + moon.mass)).length() < 1e-2);
+
+            let offset = moon.position - planet.position;
+            let radius = params.radius_law.radius(planet.mass);
+            assert!(offset.length() >= radius * 3. - 1e-3 && offset.length() <= radius * 8. + 1e-3);
+            let relative = moon.velocity - planet.velocity;
+            assert!(relative.dot(offset).abs() < 1e-2);
+            let circular = (g * (planet.mass + moon.mass) / offset.length()).sqrt();
+            assert!((relative.length() - circular).abs() < circular * 1e-4);
+        }
+
+        // Light planets don't get moons.
+        let mut light = Planet {
+            mass: 100.,
+            ..original
+        };
+        assert!(generate_moons(&mut light, &params, g, &mut rng)
+            .unwrap()
+            .is_empty());
+    }
 }
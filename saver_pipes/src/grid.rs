@@ -0,0 +1,181 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Occupancy grid for the volume the pipes grow through.
+
+use bevy::math::IVec3;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// The six directions a pipe can grow in.
+fn directions() -> [IVec3; 6] {
+    [
+        IVec3::X,
+        IVec3::Y,
+        IVec3::Z,
+        -IVec3::X,
+        -IVec3::Y,
+        -IVec3::Z,
+    ]
+}
+
+/// Tracks which cells of a box-shaped volume are occupied by pipes.
+pub struct Grid {
+    size: IVec3,
+    cells: Vec<bool>,
+    filled: usize,
+}
+
+impl Grid {
+    pub fn new(size: IVec3) -> Self {
+        Self {
+            size,
+            cells: vec![false; (size.x * size.y * size.z) as usize],
+            filled: 0,
+        }
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.size
+    }
+
+    /// Empties every cell.
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = false);
+        self.filled = 0;
+    }
+
+    /// Fraction of cells which are occupied.
+    pub fn fill_ratio(&self) -> f32 {
+        self.filled as f32 / self.cells.len() as f32
+    }
+
+    fn index(&self, pos: IVec3) -> Option<usize> {
+        let in_bounds = pos.x >= 0
+            && pos.y >= 0
+            && pos.z >= 0
+            && pos.x < self.size.x
+            && pos.y < self.size.y
+            && pos.z < self.size.z;
+        if in_bounds {
+            Some(((pos.z * self.size.y + pos.y) * self.size.x + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the position is inside the grid and not yet occupied.
+    pub fn is_free(&self, pos: IVec3) -> bool {
+        self.index(pos).map_or(false, |i| !self.cells[i])
+    }
+
+    /// Marks the position as occupied. Positions outside the grid are ignored.
+    pub fn occupy(&mut self, pos: IVec3) {
+        if let Some(i) = self.index(pos) {
+            if !self.cells[i] {
+                self.cells[i] = true;
+                self.filled += 1;
+            }
+        }
+    }
+
+    /// Picks a random free cell, or `None` if the grid is full.
+    pub fn random_free(&self, rng: &mut impl Rng) -> Option<IVec3> {
+        // Random probing finds a cell quickly while the grid is mostly empty, which is the only
+        // time pipes get restarted in practice; fall back to a scan to be exact.
+        for _ in 0..32 {
+            let pos = IVec3::new(
+                rng.gen_range(0..self.size.x),
+                rng.gen_range(0..self.size.y),
+                rng.gen_range(0..self.size.z),
+            );
+            if self.is_free(pos) {
+                return Some(pos);
+            }
+        }
+        let free: Vec<usize> = (0..self.cells.len()).filter(|&i| !self.cells[i]).collect();
+        free.choose(rng).map(|&i| {
+            let i = i as i32;
+            IVec3::new(
+                i % self.size.x,
+                i / self.size.x % self.size.y,
+                i / (self.size.x * self.size.y),
+            )
+        })
+    }
+
+    /// Chooses the next direction for a pipe at `pos` which last grew in `current`. Keeps going
+    /// straight unless blocked or a turn is randomly chosen with probability `turn_chance`.
+    /// Returns `None` if every neighbouring cell is occupied.
+    pub fn next_direction(
+        &self,
+        pos: IVec3,
+        current: Option<IVec3>,
+        turn_chance: f64,
+        rng: &mut impl Rng,
+    ) -> Option<IVec3> {
+        if let Some(current) = current {
+            if self.is_free(pos + current) && !rng.gen_bool(turn_chance) {
+                return Some(current);
+            }
+        }
+        let turns: Vec<IVec3> = directions()
+            .iter()
+            .copied()
+            .filter(|&dir| Some(dir) != current && self.is_free(pos + dir))
+            .collect();
+        match turns.choose(rng) {
+            Some(&dir) => Some(dir),
+            None => current.filter(|&dir| self.is_free(pos + dir)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fills_and_blocks() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut grid = Grid::new(IVec3::new(2, 2, 2));
+        assert!(!grid.is_free(IVec3::new(2, 0, 0)));
+
+        let mut pos = grid.random_free(&mut rng).unwrap();
+        grid.occupy(pos);
+        let mut dir = None;
+        for _ in 0..7 {
+            match grid.next_direction(pos, dir, 0.5, &mut rng) {
+                Some(next) => {
+                    pos += next;
+                    dir = Some(next);
+                    grid.occupy(pos);
+                }
+                None => {
+                    pos = grid.random_free(&mut rng).unwrap();
+                    dir = None;
+                    grid.occupy(pos);
+                }
+            }
+        }
+        assert_eq!(grid.fill_ratio(), 1.0);
+        assert_eq!(grid.random_free(&mut rng), None);
+        assert_eq!(grid.next_direction(pos, dir, 0.5, &mut rng), None);
+
+        grid.clear();
+        assert_eq!(grid.fill_ratio(), 0.0);
+    }
+}
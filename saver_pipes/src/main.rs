@@ -0,0 +1,396 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The classic 3D pipes screensaver. Several pipes grow through a box-shaped grid one cell at a
+//! time, turning at random and leaving ball joints at each bend. Pipes that get boxed in end and a
+//! new one starts elsewhere, and once enough of the volume is filled everything is cleared.
+//!
+//! Configured with environment variables:
+//!
+//! * `XSECURELOCK_SAVER_PIPES_COUNT`: number of pipes growing at once. Defaults to 4.
+//! * `XSECURELOCK_SAVER_PIPES_COLORS`: color scheme, one of `classic` (default), `pastel`,
+//!   `metal`, or `neon`.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use bevy::math::IVec3;
+use bevy::prelude::*;
+use bevy::render::camera::{Camera, PerspectiveProjection};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use xsecurelock_saver::cli::{self, Cli};
+use xsecurelock_saver::config_help::Setting;
+use xsecurelock_saver::engine::{self, SaverTime, XSecurelockSaverPlugins};
+use xsecurelock_saver::env::parse_var_or;
+
+use crate::grid::Grid;
+
+mod grid;
+mod mesh;
+
+const COUNT_VAR: &str = "XSECURELOCK_SAVER_PIPES_COUNT";
+const COLORS_VAR: &str = "XSECURELOCK_SAVER_PIPES_COLORS";
+
+/// Number of cells in the volume along each axis.
+const GRID_SIZE: [i32; 3] = [24, 16, 24];
+/// Radius of the pipes, as a fraction of a cell.
+const PIPE_RADIUS: f32 = 0.18;
+/// Radius of the ball joints at bends and ends.
+const JOINT_RADIUS: f32 = 0.26;
+/// Time for a pipe to grow by one cell.
+const STEP: Duration = Duration::from_millis(90);
+/// Chance of turning at each step even when the way ahead is clear.
+const TURN_CHANCE: f64 = 0.2;
+/// Fraction of the volume filled before everything is cleared and started over.
+const RESET_FILL: f32 = 0.3;
+
+fn main() {
+    Cli::new(engine::config_help("saver_pipes").section("Pipes", PipesConfig::settings()))
+        .about("The classic 3D pipes.")
+        .get_matches();
+    let config = PipesConfig::from_env();
+    App::build()
+        .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(Msaa { samples: 4 })
+        .insert_resource(config)
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_startup_system(setup.system())
+        .add_system(step_pipes.system())
+        .add_system(grow_segments.system())
+        .add_system(orbit_camera.system())
+        .run();
+}
+
+/// Saver settings loaded from the environment.
+struct PipesConfig {
+    count: usize,
+    colors: ColorScheme,
+}
+
+impl PipesConfig {
+    fn from_env() -> Self {
+        Self {
+            count: parse_var_or(COUNT_VAR, 4, |&count| (1..=32).contains(&count)),
+            colors: parse_var_or(COLORS_VAR, ColorScheme::Classic, |_| true),
+        }
+    }
+
+    /// The settings read by [`PipesConfig::from_env`], for `--help-config`.
+    fn settings() -> Vec<Setting> {
+        vec![
+            Setting::new::<usize>(COUNT_VAR, "Number of pipes growing at once, from 1 to 32.")
+                .with_default(4),
+            Setting::new::<ColorScheme>(COLORS_VAR, "Color scheme.")
+                .with_kind("classic | pastel | metal | neon")
+                .with_default("classic"),
+        ]
+    }
+}
+
+/// Palettes pipes pick their colors from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorScheme {
+    Classic,
+    Pastel,
+    Metal,
+    Neon,
+}
+
+impl FromStr for ColorScheme {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "classic" => Ok(ColorScheme::Classic),
+            "pastel" => Ok(ColorScheme::Pastel),
+            "metal" => Ok(ColorScheme::Metal),
+            "neon" => Ok(ColorScheme::Neon),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ColorScheme {
+    /// Builds the material for a new pipe with a color picked at random from the scheme.
+    fn material(self, rng: &mut StdRng) -> StandardMaterial {
+        let (colors, metallic, roughness): (&[u32], f32, f32) = match self {
+            ColorScheme::Classic => (
+                &[0xd01010, 0x10a010, 0x1030d0, 0xd0d010, 0x10c0c0, 0xc010c0],
+                0.1,
+                0.4,
+            ),
+            ColorScheme::Pastel => (
+                &[0xf4a6a6, 0xa6d8f4, 0xb8f4a6, 0xf4e3a6, 0xd3a6f4],
+                0.0,
+                0.7,
+            ),
+            ColorScheme::Metal => (&[0xb8b8c0, 0xc89a5a, 0xd4af37, 0x8a9aa8], 0.9, 0.25),
+            ColorScheme::Neon => (&[0xff2079, 0x00f0ff, 0x39ff14, 0xfff01f], 0.0, 0.2),
+        };
+        let rgb = *colors.choose(rng).unwrap();
+        let color = Color::rgb_u8((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+        StandardMaterial {
+            base_color: color,
+            metallic,
+            roughness,
+            // Neon pipes glow rather than reflect.
+            emissive: if self == ColorScheme::Neon {
+                color * 0.6
+            } else {
+                Color::BLACK
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Meshes shared by every pipe.
+struct PipeMeshes {
+    segment: Handle<Mesh>,
+    joint: Handle<Mesh>,
+}
+
+/// A pipe which is currently growing.
+struct Pipe {
+    /// Cell the pipe's head is in.
+    pos: IVec3,
+    /// Direction the pipe last grew in, `None` if it has only just started.
+    dir: Option<IVec3>,
+    material: Handle<StandardMaterial>,
+}
+
+/// Simulation state for all the pipes.
+struct Pipes {
+    grid: Grid,
+    pipes: Vec<Pipe>,
+    timer: Timer,
+    rng: StdRng,
+}
+
+/// Marker for every entity making up the pipes, so they can be cleared on reset.
+struct PipePart;
+
+/// A segment still growing to its full length.
+struct Growing {
+    progress: f32,
+}
+
+/// Center of a grid cell in world space. The grid is centered on the origin.
+fn cell_center(grid: &Grid, pos: IVec3) -> Vec3 {
+    let size = grid.size();
+    Vec3::new(
+        pos.x as f32 - (size.x - 1) as f32 * 0.5,
+        pos.y as f32 - (size.y - 1) as f32 * 0.5,
+        pos.z as f32 - (size.z - 1) as f32 * 0.5,
+    )
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    config: Res<PipesConfig>,
+) {
+    let pipe_meshes = PipeMeshes {
+        segment: meshes.add(mesh::tube(PIPE_RADIUS, 16)),
+        joint: meshes.add(Mesh::from(shape::Icosphere {
+            radius: JOINT_RADIUS,
+            subdivisions: 2,
+        })),
+    };
+    let mut pipes = Pipes {
+        grid: Grid::new(IVec3::new(GRID_SIZE[0], GRID_SIZE[1], GRID_SIZE[2])),
+        pipes: Vec::with_capacity(config.count),
+        timer: Timer::new(STEP, true),
+        rng: cli::rng(),
+    };
+    start_pipes(
+        &mut commands,
+        &mut pipes,
+        &pipe_meshes,
+        &mut materials,
+        &config,
+    );
+    commands.insert_resource(pipes);
+    commands.insert_resource(pipe_meshes);
+
+    for &(x, y, z) in &[(20.0, 18.0, 20.0), (-20.0, 10.0, -10.0), (0.0, -15.0, 15.0)] {
+        commands.spawn_bundle(LightBundle {
+            light: Light {
+                intensity: 3000.0,
+                range: 100.0,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(x, y, z),
+            ..Default::default()
+        });
+    }
+    commands.spawn_bundle(PerspectiveCameraBundle::default());
+}
+
+/// Starts new pipes at random free cells until there are as many as configured. Returns false if
+/// the grid has no room left.
+fn start_pipes(
+    commands: &mut Commands,
+    pipes: &mut Pipes,
+    meshes: &PipeMeshes,
+    materials: &mut Assets<StandardMaterial>,
+    config: &PipesConfig,
+) -> bool {
+    while pipes.pipes.len() < config.count {
+        let pos = match pipes.grid.random_free(&mut pipes.rng) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        pipes.grid.occupy(pos);
+        let material = materials.add(config.colors.material(&mut pipes.rng));
+        spawn_joint(commands, &pipes.grid, meshes, &material, pos);
+        pipes.pipes.push(Pipe {
+            pos,
+            dir: None,
+            material,
+        });
+    }
+    true
+}
+
+fn spawn_joint(
+    commands: &mut Commands,
+    grid: &Grid,
+    meshes: &PipeMeshes,
+    material: &Handle<StandardMaterial>,
+    pos: IVec3,
+) {
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.joint.clone(),
+            material: material.clone(),
+            transform: Transform::from_translation(cell_center(grid, pos)),
+            ..Default::default()
+        })
+        .insert(PipePart);
+}
+
+/// Spawns a segment from the center of `pos` towards the neighbouring cell in `dir`. It starts
+/// with no length and is grown by [`grow_segments`].
+fn spawn_segment(
+    commands: &mut Commands,
+    grid: &Grid,
+    meshes: &PipeMeshes,
+    material: &Handle<StandardMaterial>,
+    pos: IVec3,
+    dir: IVec3,
+) {
+    let dir = Vec3::new(dir.x as f32, dir.y as f32, dir.z as f32);
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.segment.clone(),
+            material: material.clone(),
+            transform: Transform {
+                translation: cell_center(grid, pos),
+                rotation: Quat::from_rotation_arc(Vec3::Y, dir),
+                // Avoid a zero scale, which makes for degenerate normals.
+                scale: Vec3::new(1.0, 1e-3, 1.0),
+            },
+            ..Default::default()
+        })
+        .insert_bundle((PipePart, Growing { progress: 0.0 }));
+}
+
+/// Advances every pipe by one cell each step, restarting pipes which get stuck and clearing the
+/// volume once it is full enough.
+fn step_pipes(
+    mut commands: Commands,
+    time: Res<SaverTime>,
+    mut pipes: ResMut<Pipes>,
+    meshes: Res<PipeMeshes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    config: Res<PipesConfig>,
+    parts: Query<Entity, With<PipePart>>,
+) {
+    if !pipes.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let Pipes {
+        grid,
+        pipes: live,
+        rng,
+        ..
+    } = &mut *pipes;
+    *live = live
+        .drain(..)
+        .filter_map(|mut pipe| {
+            match grid.next_direction(pipe.pos, pipe.dir, TURN_CHANCE, rng) {
+                Some(dir) => {
+                    if pipe.dir.map_or(false, |prev| prev != dir) {
+                        spawn_joint(&mut commands, grid, &meshes, &pipe.material, pipe.pos);
+                    }
+                    spawn_segment(&mut commands, grid, &meshes, &pipe.material, pipe.pos, dir);
+                    pipe.pos += dir;
+                    pipe.dir = Some(dir);
+                    grid.occupy(pipe.pos);
+                    Some(pipe)
+                }
+                None => {
+                    // Boxed in; cap the end and let a new pipe start elsewhere.
+                    spawn_joint(&mut commands, grid, &meshes, &pipe.material, pipe.pos);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let room_left = start_pipes(&mut commands, &mut pipes, &meshes, &mut materials, &config);
+    if !room_left || pipes.grid.fill_ratio() >= RESET_FILL {
+        for entity in parts.iter() {
+            commands.entity(entity).despawn();
+        }
+        pipes.grid.clear();
+        pipes.pipes.clear();
+        start_pipes(&mut commands, &mut pipes, &meshes, &mut materials, &config);
+    }
+}
+
+/// Grows new segments to their full length over the course of one step.
+fn grow_segments(
+    mut commands: Commands,
+    time: Res<SaverTime>,
+    mut segments: Query<(Entity, &mut Transform, &mut Growing)>,
+) {
+    let rate = 1.0 / STEP.as_secs_f32();
+    for (entity, mut transform, mut growing) in segments.iter_mut() {
+        growing.progress = (growing.progress + time.delta_seconds() * rate).min(1.0);
+        transform.scale.y = growing.progress.max(1e-3);
+        if growing.progress >= 1.0 {
+            commands.entity(entity).remove::<Growing>();
+        }
+    }
+}
+
+/// Slowly circles the camera around the volume.
+fn orbit_camera(
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, (With<Camera>, With<PerspectiveProjection>)>,
+) {
+    const SPEED: f32 = 0.05;
+    const DIST: f32 = 38.0;
+    let t = time.seconds_since_startup() as f32 * SPEED;
+    for mut transform in cameras.iter_mut() {
+        let (sin, cos) = t.sin_cos();
+        *transform = Transform::from_xyz(sin * DIST, 12.0 + (t * 0.7).sin() * 6.0, cos * DIST)
+            .looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
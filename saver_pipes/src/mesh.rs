@@ -0,0 +1,52 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Procedural meshes for pipe segments.
+
+use std::f32::consts::TAU;
+
+use bevy::render::mesh::{Indices, Mesh};
+use bevy::render::pipeline::PrimitiveTopology;
+
+/// Builds an open tube of the given radius running along +Y from 0 to 1, with smooth normals.
+/// Pipe segments scale this along Y to grow it.
+pub fn tube(radius: f32, sides: u32) -> Mesh {
+    let ring = sides + 1;
+    let mut positions = Vec::with_capacity(ring as usize * 2);
+    let mut normals = Vec::with_capacity(ring as usize * 2);
+    let mut uvs = Vec::with_capacity(ring as usize * 2);
+    for &y in &[0.0, 1.0] {
+        for i in 0..ring {
+            let u = i as f32 / sides as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+            positions.push([cos * radius, y, sin * radius]);
+            normals.push([cos, 0.0, sin]);
+            uvs.push([u, y]);
+        }
+    }
+
+    let mut indices = Vec::with_capacity(sides as usize * 6);
+    for i in 0..sides {
+        let (bottom, next_bottom) = (i, i + 1);
+        let (top, next_top) = (i + ring, i + ring + 1);
+        indices.extend_from_slice(&[bottom, top, next_bottom, next_bottom, top, next_top]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
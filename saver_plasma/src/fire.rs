@@ -0,0 +1,100 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The doom fire effect: a bottom row of maximum heat which spreads upwards, cooling and drifting
+//! randomly sideways as it rises.
+
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use xsecurelock_saver::cli;
+use xsecurelock_saver::simple::PixelSaver;
+use xsecurelock_saver::time::{FixedTimestep, SaverTime};
+
+use crate::palette::Palette;
+
+/// Hottest heat value. Heat 0 is cold (transparent in the original, black here).
+const MAX_HEAT: u8 = 36;
+
+/// Time between simulation steps, so the fire rises at the same speed regardless of frame rate.
+const STEP: Duration = Duration::from_nanos(1_000_000_000 / 30);
+
+/// Most steps to run in a single frame, so a stall doesn't cause a burst of work.
+const MAX_STEPS_PER_FRAME: u32 = 4;
+
+pub struct Fire {
+    downscale: u32,
+    table: Vec<[u8; 4]>,
+    size: (u32, u32),
+    heat: Vec<u8>,
+    time: SaverTime,
+    timestep: FixedTimestep,
+    rng: StdRng,
+}
+
+impl Fire {
+    pub fn new(downscale: u32, palette: &Palette) -> Self {
+        Self {
+            downscale,
+            table: palette.table(MAX_HEAT as usize + 1),
+            size: (0, 0),
+            heat: Vec::new(),
+            time: SaverTime::from_env(),
+            timestep: FixedTimestep::new(STEP, MAX_STEPS_PER_FRAME),
+            rng: cli::rng(),
+        }
+    }
+
+    /// Clears the fire for a new frame size, leaving only the heat source along the bottom.
+    fn resize(&mut self, size: (u32, u32)) {
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        self.heat = vec![0; width * height];
+        self.heat[width * (height - 1)..].fill(MAX_HEAT);
+        self.size = size;
+    }
+
+    /// Advances the fire one step, spreading each cell's heat into the cell above it.
+    fn step(&mut self) {
+        let width = self.size.0 as usize;
+        for src in width..self.heat.len() {
+            let heat = self.heat[src];
+            // Drift up to two cells left or one cell right, and cool by one half of the time.
+            let drift: usize = self.rng.gen_range(0..4);
+            let dst = (src + 1).saturating_sub(drift + width);
+            self.heat[dst] = heat.saturating_sub(drift as u8 & 1);
+        }
+    }
+}
+
+impl PixelSaver for Fire {
+    fn fill(&mut self, buf: &mut [u8], size: (u32, u32)) {
+        if size != self.size {
+            self.resize(size);
+        }
+        self.time.update();
+        for _ in 0..self.timestep.advance(self.time.delta()) {
+            self.step();
+        }
+
+        for (pixel, &heat) in buf.chunks_exact_mut(4).zip(&self.heat) {
+            pixel.copy_from_slice(&self.table[heat as usize]);
+        }
+    }
+
+    fn downscale(&self) -> u32 {
+        self.downscale
+    }
+}
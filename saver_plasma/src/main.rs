@@ -0,0 +1,127 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Old-school demo effects rendered through the [`PixelSaver`] path: a palette-cycled plasma and
+//! the classic doom fire. Configured with environment variables:
+//!
+//! * `XSECURELOCK_SAVER_PLASMA_EFFECT`: `plasma` (default) or `fire`.
+//! * `XSECURELOCK_SAVER_PLASMA_DOWNSCALE`: how many screen pixels wide each effect pixel is.
+//!   Defaults to 4 for plasma and 6 for fire.
+//! * `XSECURELOCK_SAVER_PLASMA_PALETTE`: one of the named palettes (`rainbow`, `fire`, `ocean`,
+//!   `toxic`, `grayscale`) or a comma-separated list of hex colors such as `000000,ff8000,ffffff`
+//!   to build a gradient from. Defaults to `rainbow` for plasma and `fire` for fire.
+
+use std::str::FromStr;
+
+use xsecurelock_saver::cli::Cli;
+use xsecurelock_saver::config_help::{ConfigHelp, Setting};
+use xsecurelock_saver::env::parse_var_or;
+use xsecurelock_saver::simple::PixelSaver;
+
+use crate::fire::Fire;
+use crate::palette::Palette;
+use crate::plasma::Plasma;
+
+mod fire;
+mod palette;
+mod plasma;
+
+const EFFECT_VAR: &str = "XSECURELOCK_SAVER_PLASMA_EFFECT";
+const DOWNSCALE_VAR: &str = "XSECURELOCK_SAVER_PLASMA_DOWNSCALE";
+const PALETTE_VAR: &str = "XSECURELOCK_SAVER_PLASMA_PALETTE";
+
+/// Which effect to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    Plasma,
+    Fire,
+}
+
+impl FromStr for Effect {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "plasma" => Ok(Effect::Plasma),
+            "fire" => Ok(Effect::Fire),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Either of the effects, chosen at startup.
+enum DemoSaver {
+    Plasma(Plasma),
+    Fire(Fire),
+}
+
+impl PixelSaver for DemoSaver {
+    fn fill(&mut self, buf: &mut [u8], size: (u32, u32)) {
+        match self {
+            DemoSaver::Plasma(plasma) => plasma.fill(buf, size),
+            DemoSaver::Fire(fire) => fire.fill(buf, size),
+        }
+    }
+
+    fn downscale(&self) -> u32 {
+        match self {
+            DemoSaver::Plasma(plasma) => plasma.downscale(),
+            DemoSaver::Fire(fire) => fire.downscale(),
+        }
+    }
+}
+
+fn main() {
+    let help = ConfigHelp::new("saver_plasma").section(
+        "Plasma",
+        vec![
+            Setting::new::<Effect>(EFFECT_VAR, "Which effect to show.")
+                .with_kind("plasma | fire")
+                .with_default("plasma"),
+            Setting::new::<u32>(
+                DOWNSCALE_VAR,
+                "How many screen pixels wide each effect pixel is, from 1 to 64. Defaults to 4 \
+                 for plasma and 6 for fire.",
+            ),
+            Setting::new::<Palette>(
+                PALETTE_VAR,
+                "One of the named palettes, or a comma-separated list of hex colors such as \
+                 000000,ff8000,ffffff to build a gradient from. Defaults to rainbow for plasma \
+                 and fire for fire.",
+            )
+            .with_kind("rainbow | fire | ocean | toxic | grayscale | colors"),
+        ],
+    );
+    Cli::new(help)
+        .about("Palette-cycled plasma and doom fire.")
+        .get_matches();
+    let effect = parse_var_or(EFFECT_VAR, Effect::Plasma, |_| true);
+    let default_downscale = match effect {
+        Effect::Plasma => 4,
+        Effect::Fire => 6,
+    };
+    let downscale = parse_var_or(DOWNSCALE_VAR, default_downscale, |&scale| {
+        (1..=64).contains(&scale)
+    });
+    let default_palette = match effect {
+        Effect::Plasma => Palette::rainbow(),
+        Effect::Fire => Palette::fire(),
+    };
+    let palette = parse_var_or(PALETTE_VAR, default_palette, |_| true);
+
+    xsecurelock_saver::simple::run_pixel_saver(|_| match effect {
+        Effect::Plasma => DemoSaver::Plasma(Plasma::new(downscale, &palette)),
+        Effect::Fire => DemoSaver::Fire(Fire::new(downscale, &palette)),
+    });
+}
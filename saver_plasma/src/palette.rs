@@ -0,0 +1,129 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Color gradients which the effects sample into fixed-size lookup tables.
+
+use std::str::FromStr;
+
+/// A color gradient, defined by evenly spaced RGB stops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    stops: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Hue cycle which wraps around smoothly, suited to palette cycling.
+    pub fn rainbow() -> Self {
+        Self::from_hex(&[
+            0xff0000, 0xffff00, 0x00ff00, 0x00ffff, 0x0000ff, 0xff00ff, 0xff0000,
+        ])
+    }
+
+    /// Black through red and orange to white, like the original doom fire.
+    pub fn fire() -> Self {
+        Self::from_hex(&[
+            0x070707, 0x470f07, 0x8f2707, 0xc74707, 0xdf5707, 0xdf9f07, 0xcfcf27, 0xffffff,
+        ])
+    }
+
+    /// Deep blues and teals.
+    pub fn ocean() -> Self {
+        Self::from_hex(&[
+            0x000814, 0x003566, 0x1d7ea8, 0x7de2d1, 0x1d7ea8, 0x003566, 0x000814,
+        ])
+    }
+
+    /// Acid greens and purples.
+    pub fn toxic() -> Self {
+        Self::from_hex(&[
+            0x10002b, 0x5a189a, 0x9ef01a, 0xe0ff4f, 0x9ef01a, 0x5a189a, 0x10002b,
+        ])
+    }
+
+    /// Black to white.
+    pub fn grayscale() -> Self {
+        Self::from_hex(&[0x000000, 0xffffff])
+    }
+
+    fn from_hex(stops: &[u32]) -> Self {
+        Self {
+            stops: stops
+                .iter()
+                .map(|&rgb| [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+                .collect(),
+        }
+    }
+
+    /// Sample the gradient into `len` RGBA colors, running from the first stop to the last.
+    pub fn table(&self, len: usize) -> Vec<[u8; 4]> {
+        (0..len)
+            .map(|i| {
+                if self.stops.len() == 1 || len == 1 {
+                    let [r, g, b] = self.stops[0];
+                    return [r, g, b, 255];
+                }
+                let pos = i as f32 / (len - 1) as f32 * (self.stops.len() - 1) as f32;
+                let lower = (pos as usize).min(self.stops.len() - 2);
+                let t = pos - lower as f32;
+                let (from, to) = (self.stops[lower], self.stops[lower + 1]);
+                let lerp = |c: usize| (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t) as u8;
+                [lerp(0), lerp(1), lerp(2), 255]
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Palette {
+    type Err = ();
+
+    /// Parses either a palette name or a comma-separated list of hex colors.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "rainbow" => Ok(Self::rainbow()),
+            "fire" => Ok(Self::fire()),
+            "ocean" => Ok(Self::ocean()),
+            "toxic" => Ok(Self::toxic()),
+            "grayscale" => Ok(Self::grayscale()),
+            _ => {
+                let stops = s
+                    .split(',')
+                    .map(|stop| {
+                        let stop = stop.trim().trim_start_matches('#');
+                        if stop.len() != 6 {
+                            return Err(());
+                        }
+                        u32::from_str_radix(stop, 16).map_err(|_| ())
+                    })
+                    .collect::<Result<Vec<_>, ()>>()?;
+                Ok(Self::from_hex(&stops))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_custom_gradient() {
+        let palette: Palette = "000000, #ff8000,ffffff".parse().unwrap();
+        let table = palette.table(5);
+        assert_eq!(table[0], [0, 0, 0, 255]);
+        assert_eq!(table[2], [255, 128, 0, 255]);
+        assert_eq!(table[4], [255, 255, 255, 255]);
+        assert_eq!("12345".parse::<Palette>(), Err(()));
+        assert_eq!("ocean".parse::<Palette>(), Ok(Palette::ocean()));
+    }
+}
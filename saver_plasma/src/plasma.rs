@@ -0,0 +1,105 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Palette-cycled plasma. Two precomputed interference fields are summed, with the second one
+//! panned around slowly, and the result is looked up in a palette whose offset cycles over time.
+
+use xsecurelock_saver::simple::PixelSaver;
+use xsecurelock_saver::time::SaverTime;
+
+use crate::palette::Palette;
+
+/// Palette entries advanced per second.
+const CYCLE_SPEED: f32 = 40.0;
+
+pub struct Plasma {
+    downscale: u32,
+    /// Palette mirrored so that cycling through it never jumps between unrelated colors.
+    table: Vec<[u8; 4]>,
+    size: (u32, u32),
+    /// Field the size of the frame.
+    still: Vec<u8>,
+    /// Field twice the size of the frame in each direction, panned over time.
+    moving: Vec<u8>,
+    time: SaverTime,
+}
+
+impl Plasma {
+    pub fn new(downscale: u32, palette: &Palette) -> Self {
+        let mut table = palette.table(128);
+        table.extend(table.clone().into_iter().rev());
+        Self {
+            downscale,
+            table,
+            size: (0, 0),
+            still: Vec::new(),
+            moving: Vec::new(),
+            time: SaverTime::from_env(),
+        }
+    }
+
+    /// Recomputes the fields for a new frame size.
+    fn resize(&mut self, size: (u32, u32)) {
+        let (width, height) = size;
+        // Field coordinates are relative to the frame height so the pattern looks the same at any
+        // downscale.
+        let unit = 12.0 / height as f32;
+        self.still = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x as f32 * unit, y as f32 * unit)))
+            .map(|(x, y)| to_index((x * 0.9).sin() + (y * 0.6 + x * 0.3).sin()))
+            .collect();
+        let (cx, cy) = (width as f32 * unit, height as f32 * unit);
+        self.moving = (0..height * 2)
+            .flat_map(|y| (0..width * 2).map(move |x| (x as f32 * unit, y as f32 * unit)))
+            .map(|(x, y)| {
+                let dist = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+                to_index((dist * 1.1).sin() + ((x + y) * 0.45).sin())
+            })
+            .collect();
+        self.size = size;
+    }
+}
+
+/// Maps a sum of two sines to a palette index.
+fn to_index(value: f32) -> u8 {
+    ((value + 2.0) * 63.75) as u8
+}
+
+impl PixelSaver for Plasma {
+    fn fill(&mut self, buf: &mut [u8], size: (u32, u32)) {
+        if size != self.size {
+            self.resize(size);
+        }
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        self.time.update();
+        let t = self.time.seconds_since_startup() as f32;
+        let offset = (t * CYCLE_SPEED) as usize;
+        let pan_x = (((t * 0.13).sin() + 1.0) * 0.5 * width as f32) as usize;
+        let pan_y = (((t * 0.09).cos() + 1.0) * 0.5 * height as f32) as usize;
+
+        for (y, row) in buf.chunks_exact_mut(width * 4).enumerate() {
+            let still = &self.still[y * width..(y + 1) * width];
+            let moving_start = (y + pan_y) * width * 2 + pan_x;
+            let moving = &self.moving[moving_start..moving_start + width];
+            for ((pixel, &a), &b) in row.chunks_exact_mut(4).zip(still).zip(moving) {
+                let index = (a as usize + b as usize + offset) % self.table.len();
+                pixel.copy_from_slice(&self.table[index]);
+            }
+        }
+    }
+
+    fn downscale(&self) -> u32 {
+        self.downscale
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Slow drifting of the text, so that no part of the screen shows the same thing for long.
+
+use bevy::math::Vec2;
+
+/// Component moving the text around the screen, bouncing off the edges.
+#[derive(Debug, Clone, Copy)]
+pub struct Drift {
+    /// Offset of the text's bottom left corner from the bottom left of the screen, in pixels, which
+    /// is where Bevy's UI puts the origin.
+    pub position: Vec2,
+    /// Pixels per second.
+    pub velocity: Vec2,
+}
+
+impl Drift {
+    /// Moves the text for `delta` seconds. `room` is how far the text can move from the origin
+    /// before it would go off screen, that is the size of the screen minus the size of the text.
+    /// Text larger than the screen stays against the bottom or left edge.
+    pub fn step(&mut self, delta: f32, room: Vec2) {
+        let room = room.max(Vec2::ZERO);
+        self.position += self.velocity * delta;
+        for axis in 0..2 {
+            // Past an edge, the text bounces back by as much as it overshot, unless that would
+            // take it past the other edge, as when the room just shrank.
+            if self.position[axis] < 0.0 {
+                self.position[axis] = -self.position[axis];
+                if self.position[axis] > room[axis] {
+                    self.position[axis] = 0.0;
+                }
+                self.velocity[axis] = self.velocity[axis].abs();
+            } else if self.position[axis] > room[axis] {
+                self.position[axis] = 2.0 * room[axis] - self.position[axis];
+                if self.position[axis] < 0.0 {
+                    self.position[axis] = room[axis];
+                }
+                self.velocity[axis] = -self.velocity[axis].abs();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounces_off_edges() {
+        let mut drift = Drift {
+            position: Vec2::new(95.0, 5.0),
+            velocity: Vec2::new(10.0, -10.0),
+        };
+        drift.step(1.0, Vec2::new(100.0, 50.0));
+        assert_eq!(drift.position, Vec2::new(95.0, 5.0));
+        assert_eq!(drift.velocity, Vec2::new(-10.0, 10.0));
+        drift.step(1.0, Vec2::new(100.0, 50.0));
+        assert_eq!(drift.position, Vec2::new(85.0, 15.0));
+    }
+
+    #[test]
+    fn stays_on_screen_when_room_shrinks() {
+        let mut drift = Drift {
+            position: Vec2::new(90.0, 40.0),
+            velocity: Vec2::new(1.0, 1.0),
+        };
+        drift.step(0.0, Vec2::new(20.0, -10.0));
+        assert_eq!(drift.position, Vec2::new(20.0, 0.0));
+        assert_eq!(drift.velocity, Vec2::new(-1.0, -1.0));
+    }
+}
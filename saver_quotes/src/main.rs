@@ -0,0 +1,273 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Large text drifting slowly across a black screen: fortunes, quotes from a file, or the
+//! machine's stats. Each quote fades in, drifts for a while, and fades out, and the next one
+//! starts somewhere else, so the text never burns into one place.
+//!
+//! Configured with environment variables:
+//!
+//! * `XSECURELOCK_SAVER_QUOTES_SOURCE`: `fortune` (default) to show the output of `fortune -s`,
+//!   `file` to show quotes from `XSECURELOCK_SAVER_QUOTES_FILE`, or `stats` to show the host
+//!   name, uptime, load and memory use. Built-in quotes are shown if `fortune` isn't installed or
+//!   the file has no quotes.
+//! * `XSECURELOCK_SAVER_QUOTES_FILE`: quotes in the format used by `fortune`, separated by lines
+//!   containing only `%`.
+//! * `XSECURELOCK_SAVER_QUOTES_FONT`: path of the font to use. Relative paths are in the saver's
+//!   assets. Defaults to the bundled Fira Sans.
+//! * `XSECURELOCK_SAVER_QUOTES_FONT_SIZE`: font size in pixels, from 8 to 512. Defaults to 64.
+//! * `XSECURELOCK_SAVER_QUOTES_SECONDS`: how long each quote is shown, from 2 to 3600. Defaults
+//!   to 20.
+
+use std::env;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::Rng;
+use xsecurelock_saver::cli::{self, Cli};
+use xsecurelock_saver::config_help::Setting;
+use xsecurelock_saver::engine::{self, SaverTime, XSecurelockSaverPlugins};
+use xsecurelock_saver::env::parse_var_or;
+
+use crate::drift::Drift;
+use crate::quotes::{Quotes, Source};
+use crate::wrap::wrap;
+
+mod drift;
+mod quotes;
+mod wrap;
+
+const SOURCE_VAR: &str = "XSECURELOCK_SAVER_QUOTES_SOURCE";
+const FILE_VAR: &str = "XSECURELOCK_SAVER_QUOTES_FILE";
+const FONT_VAR: &str = "XSECURELOCK_SAVER_QUOTES_FONT";
+const FONT_SIZE_VAR: &str = "XSECURELOCK_SAVER_QUOTES_FONT_SIZE";
+const SECONDS_VAR: &str = "XSECURELOCK_SAVER_QUOTES_SECONDS";
+
+const DEFAULT_FONT: &str = "fonts/FiraSans-Book.ttf";
+
+/// How fast the text drifts, in pixels per second.
+const DRIFT_SPEED: f32 = 12.0;
+
+/// How long each quote takes to fade in and out.
+const FADE: Duration = Duration::from_secs(2);
+
+/// Fraction of the screen's width the text may take up.
+const MAX_WIDTH: f32 = 0.7;
+
+/// Rough width of a character relative to the font size, used to pick where to wrap.
+const CHAR_WIDTH: f32 = 0.5;
+
+fn main() {
+    Cli::new(engine::config_help("saver_quotes").section("Quotes", QuotesConfig::settings()))
+        .about("Fortunes, quotes or system stats drifting across the screen.")
+        .get_matches();
+    let config = QuotesConfig::from_env();
+    let quotes = Quotes::new(config.source.clone());
+    App::build()
+        .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(config)
+        .insert_resource(quotes)
+        .insert_resource(PlacementRng(cli::rng()))
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_startup_system(setup.system())
+        .add_system(show_quotes.system())
+        .add_system(drift_text.system())
+        .run();
+}
+
+/// Saver settings loaded from the environment.
+#[derive(Debug, Clone)]
+struct QuotesConfig {
+    source: Source,
+    /// Path of the font, relative to the assets.
+    font: String,
+    /// Font size in pixels.
+    font_size: f32,
+    /// How long each quote is shown.
+    period: Duration,
+}
+
+impl QuotesConfig {
+    fn from_env() -> Self {
+        Self {
+            source: source_from_env(),
+            font: env::var(FONT_VAR).unwrap_or_else(|_| DEFAULT_FONT.to_string()),
+            font_size: parse_var_or(FONT_SIZE_VAR, 64.0, |size| (8.0..=512.0).contains(size)),
+            period: Duration::from_secs_f32(parse_var_or(SECONDS_VAR, 20.0, |seconds| {
+                (2.0..=3600.0).contains(seconds)
+            })),
+        }
+    }
+
+    /// The settings read by [`QuotesConfig::from_env`], for `--help-config`.
+    fn settings() -> Vec<Setting> {
+        vec![
+            Setting::new::<Source>(
+                SOURCE_VAR,
+                "Where the text comes from: the output of `fortune -s`, the quotes in the file, \
+                 or the host name, uptime, load and memory use.",
+            )
+            .with_kind("fortune | file | stats")
+            .with_default("fortune"),
+            Setting::new::<str>(
+                FILE_VAR,
+                "Quotes in the format used by fortune, separated by lines containing only %.",
+            )
+            .with_kind("path"),
+            Setting::new::<str>(
+                FONT_VAR,
+                "Font to use. Relative paths are in the saver's assets.",
+            )
+            .with_kind("path")
+            .with_default(DEFAULT_FONT),
+            Setting::new::<f32>(FONT_SIZE_VAR, "Font size in pixels, from 8 to 512.")
+                .with_default(64),
+            Setting::new::<f32>(SECONDS_VAR, "How long each quote is shown, from 2 to 3600.")
+                .with_default(20),
+        ]
+    }
+}
+
+fn source_from_env() -> Source {
+    match env::var(SOURCE_VAR).as_deref() {
+        Ok("fortune") | Err(_) => Source::Fortune,
+        Ok("stats") => Source::Stats,
+        Ok("file") => match env::var_os(FILE_VAR) {
+            Some(path) => Source::File(path.into()),
+            None => {
+                warn!(
+                    "{} is file but {} is not set, using fortune",
+                    SOURCE_VAR, FILE_VAR
+                );
+                Source::Fortune
+            }
+        },
+        Ok(source) => {
+            warn!("Invalid {}: {:?}, using default", SOURCE_VAR, source);
+            Source::Fortune
+        }
+    }
+}
+
+/// Marker component for the text showing the quote.
+struct QuoteText;
+
+/// Picks where the text starts and which way it drifts.
+struct PlacementRng(StdRng);
+
+/// Adds a ui camera and the text, initially empty.
+fn setup(
+    mut commands: Commands,
+    config: Res<QuotesConfig>,
+    asset_server: Res<AssetServer>,
+    mut rng: ResMut<PlacementRng>,
+) {
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    let angle = rng.0.gen_range(0.0..std::f32::consts::TAU);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(config.font.as_str()),
+                    font_size: config.font_size,
+                    // Faded in once there is a quote.
+                    color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+                },
+                TextAlignment {
+                    horizontal: HorizontalAlign::Left,
+                    vertical: VerticalAlign::Top,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(QuoteText)
+        .insert(Drift {
+            position: Vec2::ZERO,
+            velocity: Vec2::new(angle.cos(), angle.sin()) * DRIFT_SPEED,
+        });
+}
+
+/// Swaps in the next quote when the current one has been shown long enough, and fades quotes in
+/// and out.
+fn show_quotes(
+    config: Res<QuotesConfig>,
+    time: Res<SaverTime>,
+    windows: Res<Windows>,
+    mut quotes: ResMut<Quotes>,
+    mut rng: ResMut<PlacementRng>,
+    mut shown: Local<Option<Duration>>,
+    mut text: Query<(&mut Text, &mut Drift, &Node), With<QuoteText>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (mut text, mut drift, node) = match text.single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let columns = (window.width() * MAX_WIDTH / (config.font_size * CHAR_WIDTH)) as usize;
+    let section = &mut text.sections[0];
+    let elapsed = match *shown {
+        Some(shown) if shown + time.delta() < config.period => {
+            let elapsed = shown + time.delta();
+            if quotes.is_live() && elapsed.as_secs() != shown.as_secs() {
+                section.value = wrap(&quotes.next(), columns);
+            }
+            elapsed
+        }
+        _ => {
+            section.value = wrap(&quotes.next(), columns);
+            // Start each quote somewhere new. The text isn't laid out yet, so this goes by the
+            // size of the last quote, and drifting pulls it back on screen if the new one is
+            // bigger.
+            let screen = Vec2::new(window.width(), window.height());
+            let rng = &mut rng.0;
+            drift.position = (screen - node.size).max(Vec2::ZERO) * Vec2::new(rng.gen(), rng.gen());
+            Duration::from_secs(0)
+        }
+    };
+    *shown = Some(elapsed);
+    let fade = FADE.min(config.period / 4).as_secs_f32();
+    let remaining = (config.period - elapsed).as_secs_f32();
+    let alpha = (elapsed.as_secs_f32() / fade)
+        .min(remaining / fade)
+        .min(1.0);
+    section.style.color.set_a(alpha);
+}
+
+/// Moves the text, keeping it on screen.
+fn drift_text(
+    time: Res<SaverTime>,
+    windows: Res<Windows>,
+    mut text: Query<(&mut Drift, &mut Style, &Node)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let screen = Vec2::new(window.width(), window.height());
+    for (mut drift, mut style, node) in text.iter_mut() {
+        drift.step(time.delta_seconds(), screen - node.size);
+        style.position.left = Val::Px(drift.position.x);
+        style.position.bottom = Val::Px(drift.position.y);
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Where the text comes from: the `fortune` program, a file of quotes, or the machine's stats
+//! read from `/proc`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use bevy::log::{info, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use xsecurelock_saver::cli;
+
+/// Shown when neither `fortune` nor the quote file gives anything to show.
+const BUILT_IN_QUOTES: &[&str] = &[
+    "The best way out is always through.\n\t-- Robert Frost",
+    "Simplicity is prerequisite for reliability.\n\t-- Edsger W. Dijkstra",
+    "What we know is a drop, what we don't know is an ocean.\n\t-- Isaac Newton",
+    "Nothing in life is to be feared, it is only to be understood.\n\t-- Marie Curie",
+    "We can only see a short distance ahead, but we can see plenty there that needs to be \
+     done.\n\t-- Alan Turing",
+    "The important thing is not to stop questioning.\n\t-- Albert Einstein",
+];
+
+/// Where to get the text from, as configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Run `fortune` for each quote.
+    Fortune,
+    /// Quotes from a file in the format used by `fortune`, separated by lines containing only `%`.
+    File(PathBuf),
+    /// Host name, uptime, load and memory use.
+    Stats,
+}
+
+/// Gives the text to show, one quote at a time.
+#[derive(Debug)]
+pub enum Quotes {
+    Fortune,
+    /// Quotes shown in a shuffled order, which is shuffled again each time through.
+    List {
+        quotes: Vec<String>,
+        next: usize,
+        rng: StdRng,
+    },
+    Stats,
+}
+
+impl Quotes {
+    pub fn new(source: Source) -> Self {
+        match source {
+            Source::Fortune if fortune().is_some() => Quotes::Fortune,
+            Source::Fortune => {
+                warn!("Unable to run fortune, showing built-in quotes instead");
+                Quotes::built_in()
+            }
+            Source::File(path) => match fs::read_to_string(&path) {
+                Ok(text) => {
+                    let quotes = parse_quotes(&text);
+                    if quotes.is_empty() {
+                        warn!("No quotes in {:?}, showing built-in quotes instead", path);
+                        Quotes::built_in()
+                    } else {
+                        info!("Loaded {} quotes from {:?}", quotes.len(), path);
+                        Quotes::list(quotes)
+                    }
+                }
+                Err(err) => {
+                    warn!(
+                        "Unable to read {:?}: {}, showing built-in quotes instead",
+                        path, err
+                    );
+                    Quotes::built_in()
+                }
+            },
+            Source::Stats => Quotes::Stats,
+        }
+    }
+
+    fn built_in() -> Self {
+        Quotes::list(BUILT_IN_QUOTES.iter().map(|&quote| quote.into()).collect())
+    }
+
+    fn list(quotes: Vec<String>) -> Self {
+        Quotes::List {
+            next: quotes.len(),
+            quotes,
+            rng: cli::rng(),
+        }
+    }
+
+    /// Whether the text changes while it's shown, so it should be refreshed every second.
+    pub fn is_live(&self) -> bool {
+        matches!(self, Quotes::Stats)
+    }
+
+    /// The next quote to show.
+    pub fn next(&mut self) -> String {
+        match self {
+            Quotes::Fortune => fortune().unwrap_or_default(),
+            Quotes::List { quotes, next, rng } => {
+                if *next >= quotes.len() {
+                    quotes.shuffle(rng);
+                    *next = 0;
+                }
+                *next += 1;
+                quotes[*next - 1].clone()
+            }
+            Quotes::Stats => stats(),
+        }
+    }
+}
+
+/// Runs `fortune` for a short fortune, which fits on the screen at a large font size.
+fn fortune() -> Option<String> {
+    let output = Command::new("fortune").arg("-s").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Splits the contents of a fortune file into quotes. Quotes are separated by lines containing
+/// only `%`; empty quotes are dropped.
+pub fn parse_quotes(text: &str) -> Vec<String> {
+    let mut quotes = Vec::new();
+    let mut current = Vec::new();
+    for line in text.lines().chain(std::iter::once("%")) {
+        if line.trim() == "%" {
+            let quote = current.join("\n");
+            let quote = quote.trim_matches('\n').trim_end();
+            if !quote.trim().is_empty() {
+                quotes.push(quote.to_string());
+            }
+            current.clear();
+        } else {
+            current.push(line);
+        }
+    }
+    quotes
+}
+
+/// The machine's host name, uptime, load and memory use, one per line. Stats which can't be read
+/// are left out.
+fn stats() -> String {
+    let read = |path| fs::read_to_string(path).ok();
+    let mut lines = Vec::new();
+    if let Some(hostname) = read("/proc/sys/kernel/hostname") {
+        lines.push(hostname.trim().to_string());
+    }
+    if let Some(uptime) = read("/proc/uptime").as_deref().and_then(parse_uptime) {
+        lines.push(format!("up {}", format_uptime(uptime)));
+    }
+    if let Some([one, five, fifteen]) = read("/proc/loadavg").as_deref().and_then(parse_loadavg) {
+        lines.push(format!("load {:.2} {:.2} {:.2}", one, five, fifteen));
+    }
+    if let Some((total, available)) = read("/proc/meminfo").as_deref().and_then(parse_meminfo) {
+        const KIB_PER_GIB: f64 = 1024.0 * 1024.0;
+        lines.push(format!(
+            "memory {:.1} / {:.1} GiB",
+            (total - available.min(total)) as f64 / KIB_PER_GIB,
+            total as f64 / KIB_PER_GIB,
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Parses the uptime out of `/proc/uptime`.
+fn parse_uptime(text: &str) -> Option<Duration> {
+    let seconds: f64 = text.split_whitespace().next()?.parse().ok()?;
+    if seconds.is_finite() && seconds >= 0.0 {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
+
+/// Formats an uptime like `uptime` does, as days, hours and minutes.
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match days {
+        0 => format!("{}:{:02}", hours, minutes),
+        1 => format!("1 day, {}:{:02}", hours, minutes),
+        _ => format!("{} days, {}:{:02}", days, hours, minutes),
+    }
+}
+
+/// Parses the 1, 5 and 15 minute load averages out of `/proc/loadavg`.
+fn parse_loadavg(text: &str) -> Option<[f32; 3]> {
+    let mut fields = text.split_whitespace().map(str::parse);
+    Some([
+        fields.next()?.ok()?,
+        fields.next()?.ok()?,
+        fields.next()?.ok()?,
+    ])
+}
+
+/// Parses the total and available memory, in KiB, out of `/proc/meminfo`.
+fn parse_meminfo(text: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        text.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            value.trim().trim_end_matches("kB").trim().parse().ok()
+        })
+    };
+    Some((field("MemTotal")?, field("MemAvailable")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fortune_files() {
+        let text =
+            "First quote,\non two lines.\n%\n\nSecond quote.\n\t-- Someone\n%\n%\n   \n%\nLast";
+        assert_eq!(
+            parse_quotes(text),
+            vec![
+                "First quote,\non two lines.",
+                "Second quote.\n\t-- Someone",
+                "Last",
+            ]
+        );
+        assert!(parse_quotes("").is_empty());
+    }
+
+    #[test]
+    fn shows_every_quote_before_repeating() {
+        let mut quotes = Quotes::list(vec!["a".into(), "b".into(), "c".into()]);
+        for _ in 0..3 {
+            let mut shown: Vec<String> = (0..3).map(|_| quotes.next()).collect();
+            shown.sort();
+            assert_eq!(shown, vec!["a", "b", "c"]);
+        }
+    }
+
+    #[test]
+    fn parses_proc_files() {
+        assert_eq!(
+            parse_uptime("350735.47 234388.90\n"),
+            Some(Duration::from_secs_f64(350735.47))
+        );
+        assert_eq!(parse_uptime(""), None);
+        assert_eq!(
+            parse_loadavg("0.52 0.48 0.40 1/467 12345\n"),
+            Some([0.52, 0.48, 0.40])
+        );
+        assert_eq!(parse_loadavg("0.52 0.48"), None);
+        let meminfo = "MemTotal:       16303548 kB\nMemFree:         1234567 kB\n\
+                       MemAvailable:    9876543 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some((16303548, 9876543)));
+        assert_eq!(parse_meminfo("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn formats_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(59)), "0:00");
+        assert_eq!(
+            format_uptime(Duration::from_secs(3 * 3600 + 5 * 60)),
+            "3:05"
+        );
+        assert_eq!(
+            format_uptime(Duration::from_secs(86400 + 60)),
+            "1 day, 0:01"
+        );
+        assert_eq!(
+            format_uptime(Duration::from_secs(4 * 86400 + 23 * 3600)),
+            "4 days, 23:00"
+        );
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Word wrapping. Bevy's UI text only wraps at the width of its node, which for an absolutely
+//! positioned node drifting around the screen is whatever room is left to the right of it, so
+//! quotes are wrapped to a fixed number of columns before they are shown instead.
+
+/// Width of a tab, in columns.
+const TAB_WIDTH: usize = 4;
+
+/// Wraps `text` to lines of at most `columns` characters, breaking between words. Existing line
+/// breaks are kept, since fortunes often lay out poems and attributions by hand, and so is the
+/// indentation at the start of each line. Words longer than a whole line are split.
+pub fn wrap(text: &str, columns: usize) -> String {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let line = line.replace('\t', &" ".repeat(TAB_WIDTH));
+        let mut current: String = line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .take(columns - 1)
+            .collect();
+        let mut has_word = false;
+        for word in line.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let len = current.chars().count();
+            if has_word && len + 1 + word.len() <= columns {
+                current.push(' ');
+            } else if has_word {
+                lines.push(current);
+                current = String::new();
+            }
+            let mut len = current.chars().count();
+            while len + word.len() > columns {
+                let rest = word.split_off(columns - len);
+                current.extend(word);
+                lines.push(current);
+                current = String::new();
+                len = 0;
+                word = rest;
+            }
+            current.extend(word);
+            has_word = true;
+        }
+        if !has_word {
+            current.clear();
+        }
+        lines.push(current);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_between_words() {
+        assert_eq!(
+            wrap("the quick brown fox jumps over the lazy dog", 10),
+            "the quick\nbrown fox\njumps over\nthe lazy\ndog"
+        );
+        assert_eq!(wrap("short", 10), "short");
+    }
+
+    #[test]
+    fn keeps_line_breaks_and_indentation() {
+        assert_eq!(
+            wrap("Roses are red,\n\n\t\t-- Anonymous", 40),
+            "Roses are red,\n\n        -- Anonymous"
+        );
+        assert_eq!(wrap("  one two three", 9), "  one two\nthree");
+    }
+
+    #[test]
+    fn splits_long_words() {
+        assert_eq!(
+            wrap("a supercalifragilistic b", 8),
+            "a\nsupercal\nifragili\nstic b"
+        );
+        assert_eq!(wrap("ünïcödé", 3), "ünï\ncöd\né");
+    }
+}
@@ -13,16 +13,17 @@
 // limitations under the License.
 
 use sfml::graphics::{Color, Image, RectangleShape, RenderTarget, Shape, Texture, Transformable};
-use sfml::system::{Clock, Time, Vector2f};
+use sfml::system::Vector2f;
 
+use xsecurelock_saver::cli::Cli;
+use xsecurelock_saver::config_help::ConfigHelp;
 use xsecurelock_saver::simple::Screensaver;
+use xsecurelock_saver::time::SaverTime;
 
 /// Simple screensaver that shows a rotating rectangle over a rotating textured square.
 struct RotatingRectScreensaver<'t> {
-    /// Clock used to track time.
-    clock: Clock,
-    /// Last update time, used to compute dt using clock.
-    tick: Time,
+    /// Tracks time between updates.
+    time: SaverTime,
     /// Rectangle shape to draw. Since it has no textures, uses `'static`.
     rect: RectangleShape<'static>,
     /// Second rectangle, demonstrating use of texture with lifetime. The texture is allocated
@@ -30,17 +31,10 @@ struct RotatingRectScreensaver<'t> {
     tex_rect: RectangleShape<'t>,
 }
 
-impl<'t> RotatingRectScreensaver<'t> {
-    /// Updates the previous tick time and computes delta time.
-    fn update_dt(&mut self) -> Time {
-        let prev = std::mem::replace(&mut self.tick, self.clock.elapsed_time());
-        self.tick - prev
-    }
-}
-
 impl<'t> Screensaver for RotatingRectScreensaver<'t> {
     fn update(&mut self) {
-        let dt = self.update_dt().as_seconds();
+        self.time.update();
+        let dt = self.time.delta_seconds();
         self.rect.rotate(50.0 * dt);
         self.tex_rect.rotate(-20.0 * dt);
     }
@@ -53,6 +47,9 @@ impl<'t> Screensaver for RotatingRectScreensaver<'t> {
 }
 
 fn main() {
+    Cli::new(ConfigHelp::new("saver_sfmlrect"))
+        .about("Minimal SFML saver with rotating rectangles.")
+        .get_matches();
     let mut img = Image::new(256, 256);
     for x in 0..256 {
         for y in 0..256 {
@@ -81,11 +78,8 @@ fn main() {
         tex_rect.set_outline_thickness(2.0);
         tex_rect.set_outline_color(Color::MAGENTA);
 
-        let clock = Clock::start();
-        let tick = clock.elapsed_time();
         RotatingRectScreensaver {
-            clock,
-            tick,
+            time: SaverTime::from_env(),
             rect,
             tex_rect,
         }
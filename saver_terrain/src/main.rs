@@ -0,0 +1,292 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A slow flight over endless procedurally generated terrain. The landscape is noise-based, with
+//! seas, hills, and ridged snowy mountains, and is streamed in chunks around the camera with
+//! coarser meshes further away. The sun crosses the sky over a configurable day, with the sky and
+//! fog shifting through dawn, daylight, dusk, and moonlit night.
+//!
+//! Configured with environment variables:
+//!
+//! * `XSECURELOCK_SAVER_TERRAIN_DAY_MINUTES`: length of a full day and night. Defaults to 10.
+//! * `XSECURELOCK_SAVER_TERRAIN_SPEED`: flying speed in world units per second. Defaults to 14.
+
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use bevy::math::IVec2;
+use bevy::prelude::*;
+use bevy::render::camera::{Camera, PerspectiveProjection};
+use rand::Rng;
+use xsecurelock_saver::cli::{self, Cli};
+use xsecurelock_saver::config_help::Setting;
+use xsecurelock_saver::engine::{self, SaverTime, XSecurelockSaverPlugins};
+use xsecurelock_saver::env::parse_var_or;
+
+use crate::material::{TerrainMaterial, TerrainMaterialPlugin};
+use crate::terrain::{smoothstep, Landscape, CHUNK_SIZE};
+
+mod material;
+mod terrain;
+
+const DAY_MINUTES_VAR: &str = "XSECURELOCK_SAVER_TERRAIN_DAY_MINUTES";
+const SPEED_VAR: &str = "XSECURELOCK_SAVER_TERRAIN_SPEED";
+
+/// Radius around the camera's chunk which is kept loaded, in chunks.
+const VIEW_RADIUS: i32 = 9;
+/// Most chunks built or rebuilt in one frame, to avoid hitches when crossing into a new chunk.
+const BUILDS_PER_FRAME: usize = 6;
+/// Fog density, per world unit.
+const FOG_DENSITY: f32 = 1.0 / 360.0;
+/// Height the camera keeps above the ground.
+const CLEARANCE: f32 = 35.0;
+/// Time of day the saver starts at, as a fraction of a day from midnight.
+const START_TIME_OF_DAY: f32 = 0.3;
+
+fn main() {
+    Cli::new(engine::config_help("saver_terrain").section("Terrain", TerrainConfig::settings()))
+        .about("A slow flight over endless procedurally generated terrain.")
+        .get_matches();
+    let config = TerrainConfig::from_env();
+    App::build()
+        .insert_resource(Msaa { samples: 4 })
+        .insert_resource(config)
+        .insert_resource(Landscape::new(cli::rng().gen()))
+        .insert_resource(Chunks::default())
+        .add_plugins(XSecurelockSaverPlugins)
+        .add_plugin(TerrainMaterialPlugin)
+        .add_startup_system(setup.system())
+        .add_system(fly_camera.system())
+        .add_system(stream_chunks.system())
+        .add_system(update_sky.system())
+        .run();
+}
+
+/// Saver settings loaded from the environment.
+struct TerrainConfig {
+    day_secs: f32,
+    speed: f32,
+}
+
+impl TerrainConfig {
+    fn from_env() -> Self {
+        let day_minutes = parse_var_or(DAY_MINUTES_VAR, 10.0, |&minutes| {
+            (0.5..=1440.0).contains(&minutes)
+        });
+        Self {
+            day_secs: day_minutes * 60.0,
+            speed: parse_var_or(SPEED_VAR, 14.0, |&speed| (1.0..=200.0).contains(&speed)),
+        }
+    }
+
+    /// The settings read by [`TerrainConfig::from_env`], for `--help-config`.
+    fn settings() -> Vec<Setting> {
+        vec![
+            Setting::new::<f32>(
+                DAY_MINUTES_VAR,
+                "Length of a full day and night in minutes, from 0.5 to 1440.",
+            )
+            .with_default(10),
+            Setting::new::<f32>(
+                SPEED_VAR,
+                "Flying speed in world units per second, from 1 to 200.",
+            )
+            .with_default(14),
+        ]
+    }
+}
+
+/// Loaded chunks by chunk coordinate, with their entity and level of detail.
+#[derive(Default)]
+struct Chunks(HashMap<(i32, i32), (Entity, u32)>);
+
+/// Progress of the camera along its flight path.
+struct Flight {
+    distance: f32,
+    altitude: f32,
+}
+
+fn setup(mut commands: Commands, landscape: Res<Landscape>) {
+    let altitude = landscape.height(flight_path(0.0).x, 0.0) + CLEARANCE;
+    commands.insert_resource(Flight {
+        distance: 0.0,
+        altitude,
+    });
+    commands.spawn_bundle(PerspectiveCameraBundle {
+        perspective_projection: PerspectiveProjection {
+            far: VIEW_RADIUS as f32 * CHUNK_SIZE * 1.5,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+}
+
+/// Ground position of the flight path after flying the given distance. The path heads along +Z,
+/// meandering from side to side.
+fn flight_path(distance: f32) -> Vec2 {
+    Vec2::new(
+        300.0 * (distance / 900.0).sin() + 120.0 * (distance / 310.0).sin(),
+        distance,
+    )
+}
+
+/// Flies the camera along its path, rising over high ground ahead of time.
+fn fly_camera(
+    time: Res<SaverTime>,
+    config: Res<TerrainConfig>,
+    landscape: Res<Landscape>,
+    mut flight: ResMut<Flight>,
+    mut cameras: Query<&mut Transform, (With<Camera>, With<PerspectiveProjection>)>,
+) {
+    let dt = time.delta_seconds();
+    flight.distance += config.speed * dt;
+    let here = flight_path(flight.distance);
+    let target = [0.0, 40.0, 80.0, 140.0]
+        .iter()
+        .map(|&ahead| {
+            let point = flight_path(flight.distance + ahead);
+            landscape.height(point.x, point.y)
+        })
+        .fold(f32::MIN, f32::max)
+        + CLEARANCE;
+    // Ease towards the target altitude, climbing faster than descending so the camera clears
+    // peaks.
+    let rate = if target > flight.altitude { 0.8 } else { 0.25 };
+    flight.altitude += (target - flight.altitude) * (1.0 - (-rate * dt).exp());
+
+    let ahead = flight_path(flight.distance + 120.0);
+    let position = Vec3::new(here.x, flight.altitude, here.y);
+    let look_at = Vec3::new(ahead.x, flight.altitude - 30.0, ahead.y);
+    for mut transform in cameras.iter_mut() {
+        *transform = Transform::from_translation(position).looking_at(look_at, Vec3::Y);
+    }
+}
+
+/// Loads chunks around the camera at the right level of detail and unloads distant ones.
+fn stream_chunks(
+    mut commands: Commands,
+    landscape: Res<Landscape>,
+    mut chunks: ResMut<Chunks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    cameras: Query<&Transform, (With<Camera>, With<PerspectiveProjection>)>,
+    mut chunk_meshes: Query<&mut Handle<Mesh>>,
+) {
+    let camera = match cameras.iter().next() {
+        Some(transform) => transform.translation,
+        None => return,
+    };
+    let center = IVec2::new(
+        (camera.x / CHUNK_SIZE).floor() as i32,
+        (camera.z / CHUNK_SIZE).floor() as i32,
+    );
+    let distance = |x: i32, z: i32| (x - center.x).abs().max((z - center.y).abs());
+
+    // Unload chunks a little beyond the view radius so chunks on the edge don't flicker in and out.
+    chunks.0.retain(|&(x, z), &mut (entity, _)| {
+        let keep = distance(x, z) <= VIEW_RADIUS + 1;
+        if !keep {
+            commands.entity(entity).despawn();
+        }
+        keep
+    });
+
+    let mut wanted = Vec::new();
+    for z in center.y - VIEW_RADIUS..=center.y + VIEW_RADIUS {
+        for x in center.x - VIEW_RADIUS..=center.x + VIEW_RADIUS {
+            let lod = terrain::lod_for_distance(distance(x, z));
+            if chunks
+                .0
+                .get(&(x, z))
+                .map_or(true, |&(_, loaded)| loaded != lod)
+            {
+                wanted.push((distance(x, z), x, z, lod));
+            }
+        }
+    }
+    wanted.sort_unstable();
+    for &(_, x, z, lod) in wanted.iter().take(BUILDS_PER_FRAME) {
+        let mesh = meshes.add(terrain::chunk_mesh(&landscape, IVec2::new(x, z), lod));
+        match chunks.0.get_mut(&(x, z)) {
+            Some((entity, loaded)) => {
+                if let Ok(mut handle) = chunk_meshes.get_mut(*entity) {
+                    *handle = mesh;
+                }
+                *loaded = lod;
+            }
+            None => {
+                let entity = commands
+                    .spawn_bundle(MeshBundle {
+                        mesh,
+                        render_pipelines: material::render_pipelines(),
+                        transform: Transform::from_xyz(
+                            x as f32 * CHUNK_SIZE,
+                            0.0,
+                            z as f32 * CHUNK_SIZE,
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(material::material())
+                    .id();
+                chunks.0.insert((x, z), (entity, lod));
+            }
+        }
+    }
+}
+
+/// Moves the sun and moon and updates the sky, light, and fog to match.
+fn update_sky(
+    time: Res<Time>,
+    config: Res<TerrainConfig>,
+    mut clear_color: ResMut<ClearColor>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    cameras: Query<&Transform, (With<Camera>, With<PerspectiveProjection>)>,
+) {
+    let time_of_day =
+        (START_TIME_OF_DAY + time.seconds_since_startup() as f32 / config.day_secs).fract();
+    // The sun rises in the east (+X), peaks at noon, and sets in the west.
+    let angle = (time_of_day - 0.25) * TAU;
+    let sun = Vec3::new(angle.cos(), angle.sin(), 0.35).normalize();
+    let elevation = sun.y;
+
+    let night = Vec3::new(0.004, 0.006, 0.018);
+    let dawn = Vec3::new(0.55, 0.24, 0.1);
+    let day = Vec3::new(0.3, 0.5, 0.85);
+    let sky = night
+        .lerp(dawn, smoothstep(-0.25, 0.0, elevation))
+        .lerp(day, smoothstep(0.0, 0.35, elevation));
+
+    let (light_direction, light_color) = if elevation > -0.05 {
+        let warm = Vec3::new(1.0, 0.45, 0.2)
+            .lerp(Vec3::new(1.0, 0.95, 0.85), smoothstep(0.0, 0.4, elevation));
+        (sun, warm * 1.6 * smoothstep(-0.05, 0.1, elevation))
+    } else {
+        // The moon is opposite the sun.
+        let moon = -sun;
+        (
+            moon,
+            Vec3::new(0.05, 0.07, 0.12) * smoothstep(-0.05, 0.1, moon.y),
+        )
+    };
+
+    clear_color.0 = Color::rgb_linear(sky.x, sky.y, sky.z);
+    if let Some(material) = materials.get_mut(&material::material()) {
+        material.light_direction = light_direction.extend(0.0);
+        material.light_color = light_color.extend(1.0);
+        material.ambient = (sky * 0.5 + Vec3::splat(0.01)).extend(1.0);
+        material.fog_color = sky.extend(1.0);
+        if let Some(camera) = cameras.iter().next() {
+            material.camera = camera.translation.extend(FOG_DENSITY);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The terrain shader. Colors the ground by height and slope, lights it with the sun or moon, and
+//! fades it into the sky with distance fog.
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::{
+    pipeline::{CullMode, PipelineDescriptor, RenderPipeline},
+    render_graph::{base, AssetRenderResourcesNode, RenderGraph},
+    renderer::RenderResources,
+    shader::{ShaderStage, ShaderStages},
+};
+use xsecurelock_saver::engine::ShaderHotReload;
+
+/// Render graph node binding the terrain material.
+const MATERIAL_NODE: &str = "terrain_material";
+
+const PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 9716204381572046337);
+const MATERIAL_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(TerrainMaterial::TYPE_UUID, 2290871462385915744);
+
+const VERTEX_SHADER: &str = include_str!("terrain.vert");
+const FRAGMENT_SHADER: &str = include_str!("terrain.frag");
+
+/// Lighting and fog for the terrain, shared by every chunk. All colors are linear.
+#[derive(Debug, Default, RenderResources, TypeUuid)]
+#[uuid = "5d7e0f13-2b6c-4e8a-9f41-c3a8b2d6e7f0"]
+pub struct TerrainMaterial {
+    /// Direction towards the sun or moon.
+    pub light_direction: Vec4,
+    pub light_color: Vec4,
+    pub ambient: Vec4,
+    /// Color of the sky the terrain fades into.
+    pub fog_color: Vec4,
+    /// Camera position in `xyz` and fog density in `w`.
+    pub camera: Vec4,
+}
+
+/// Sets up the terrain pipeline.
+pub struct TerrainMaterialPlugin;
+
+impl Plugin for TerrainMaterialPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<TerrainMaterial>();
+        let world = app.world_mut();
+        world
+            .get_resource_mut::<Assets<TerrainMaterial>>()
+            .unwrap()
+            .set_untracked(MATERIAL_HANDLE, TerrainMaterial::default());
+        let (vertex, fragment) = {
+            let mut shaders = world.get_resource_mut::<Assets<Shader>>().unwrap();
+            (
+                shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+                shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER)),
+            )
+        };
+        // Edits to the shader sources show up live when running in a window.
+        let mut hot_reload = world.get_resource_mut::<ShaderHotReload>().unwrap();
+        hot_reload.watch(
+            &vertex,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/terrain.vert"),
+        );
+        hot_reload.watch(
+            &fragment,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/terrain.frag"),
+        );
+        let pipeline = {
+            let mut pipeline = PipelineDescriptor::default_config(ShaderStages {
+                vertex,
+                fragment: Some(fragment),
+            });
+            // Chunk skirts are seen from both sides.
+            pipeline.primitive.cull_mode = CullMode::None;
+            pipeline
+        };
+        world
+            .get_resource_mut::<Assets<PipelineDescriptor>>()
+            .unwrap()
+            .set_untracked(PIPELINE_HANDLE, pipeline);
+        let mut render_graph = world.get_resource_mut::<RenderGraph>().unwrap();
+        render_graph.add_system_node(
+            MATERIAL_NODE,
+            AssetRenderResourcesNode::<TerrainMaterial>::new(true),
+        );
+        render_graph
+            .add_node_edge(MATERIAL_NODE, base::node::MAIN_PASS)
+            .unwrap();
+    }
+}
+
+/// Render pipelines for drawing a mesh with the terrain shader.
+pub fn render_pipelines() -> RenderPipelines {
+    RenderPipelines::from_pipelines(vec![RenderPipeline::new(PIPELINE_HANDLE.typed())])
+}
+
+/// The terrain material, which every chunk must have to be drawn.
+pub fn material() -> Handle<TerrainMaterial> {
+    MATERIAL_HANDLE.typed()
+}
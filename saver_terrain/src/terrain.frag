@@ -0,0 +1,58 @@
+#version 450
+
+layout(location = 0) in vec3 v_World;
+layout(location = 1) in vec3 v_Normal;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 2, binding = 0) uniform TerrainMaterial_light_direction {
+    vec4 light_direction;
+};
+layout(set = 2, binding = 1) uniform TerrainMaterial_light_color {
+    vec4 light_color;
+};
+layout(set = 2, binding = 2) uniform TerrainMaterial_ambient {
+    vec4 ambient;
+};
+layout(set = 2, binding = 3) uniform TerrainMaterial_fog_color {
+    vec4 fog_color;
+};
+layout(set = 2, binding = 4) uniform TerrainMaterial_camera {
+    vec4 camera;
+};
+
+const float SEA_LEVEL = 0.0;
+const vec3 WATER = vec3(0.01, 0.05, 0.09);
+const vec3 SAND = vec3(0.48, 0.42, 0.26);
+const vec3 GRASS = vec3(0.07, 0.18, 0.04);
+const vec3 ROCK = vec3(0.16, 0.13, 0.11);
+const vec3 SNOW = vec3(0.85, 0.88, 0.92);
+
+void main() {
+    vec3 normal = normalize(v_Normal);
+    vec3 to_camera = normalize(camera.xyz - v_World);
+    float height = v_World.y;
+    float steepness = 1.0 - normal.y;
+
+    vec3 color;
+    float shine = 0.0;
+    if (height <= SEA_LEVEL + 0.01) {
+        color = WATER;
+        shine = 1.0;
+    } else {
+        color = mix(SAND, GRASS, smoothstep(1.0, 5.0, height));
+        color = mix(color, ROCK, smoothstep(0.25, 0.45, steepness));
+        float snowline = 95.0 + 12.0 * sin(v_World.x * 0.013) * sin(v_World.z * 0.017);
+        float snow = smoothstep(snowline, snowline + 20.0, height) * smoothstep(0.55, 0.35, steepness);
+        color = mix(color, SNOW, snow);
+    }
+
+    float diffuse = max(dot(normal, light_direction.xyz), 0.0);
+    vec3 reflected = reflect(-light_direction.xyz, normal);
+    float specular = shine * pow(max(dot(reflected, to_camera), 0.0), 64.0);
+    vec3 lit = color * (light_color.rgb * diffuse + ambient.rgb) + light_color.rgb * specular;
+
+    float fog_depth = length(camera.xyz - v_World) * camera.w;
+    float fog = 1.0 - exp(-fog_depth * fog_depth);
+    o_Target = vec4(mix(lit, fog_color.rgb, fog), 1.0);
+}
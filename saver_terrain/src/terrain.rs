@@ -0,0 +1,133 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The landscape: a height function built from noise, and the chunked meshes which cover the area
+//! around the camera. Chunks further from the camera are meshed with fewer cells, and every chunk
+//! has a skirt hanging down from its edges to hide cracks where neighbouring levels of detail meet.
+
+use bevy::math::IVec2;
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+
+use xsecurelock_saver::noise::{perlin2, Fbm};
+
+/// Width of a chunk in world units.
+pub const CHUNK_SIZE: f32 = 64.0;
+/// Height the sea is flattened to.
+pub const SEA_LEVEL: f32 = 0.0;
+/// Cells across the most detailed chunks. Each further level of detail halves this.
+const MAX_CELLS: u32 = 64;
+/// Least detailed level of detail.
+const MAX_LOD: u32 = 3;
+/// Depth of the skirts below the chunk edges.
+const SKIRT_DEPTH: f32 = 6.0;
+
+/// Height of the landscape at any point.
+pub struct Landscape {
+    seed: u32,
+}
+
+impl Landscape {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// Height of the ground or sea at the given point.
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        self.ground(x, z).max(SEA_LEVEL)
+    }
+
+    /// Height of the ground, which may be below the sea.
+    fn ground(&self, x: f32, z: f32) -> f32 {
+        // Broad continents decide where the mountains go.
+        let seed = self.seed;
+        let continent = Fbm::new(4).sample2(perlin2, seed, x / 900.0, z / 900.0);
+        let hills = Fbm::new(6).sample2(perlin2, seed.wrapping_add(100), x / 180.0, z / 180.0);
+        let mountains = Fbm::new(6).ridged2(perlin2, seed.wrapping_add(200), x / 420.0, z / 420.0);
+        let mountain_mask = smoothstep(0.0, 0.45, continent);
+        30.0 * continent + 18.0 * hills + 160.0 * mountain_mask * mountains + 4.0
+    }
+
+    /// Surface normal at the given point, from central differences.
+    pub fn normal(&self, x: f32, z: f32, step: f32) -> Vec3 {
+        let dx = self.height(x + step, z) - self.height(x - step, z);
+        let dz = self.height(x, z + step) - self.height(x, z - step);
+        Vec3::new(-dx, 2.0 * step, -dz).normalize()
+    }
+}
+
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Level of detail for a chunk the given number of chunks away from the camera's chunk.
+pub fn lod_for_distance(distance: i32) -> u32 {
+    ((distance.max(1) as u32 - 1) / 2).min(MAX_LOD)
+}
+
+/// Builds the mesh for the chunk at the given chunk coordinates. The mesh is in world space
+/// relative to the chunk's corner.
+pub fn chunk_mesh(landscape: &Landscape, chunk: IVec2, lod: u32) -> Mesh {
+    let cells = MAX_CELLS >> lod;
+    let step = CHUNK_SIZE / cells as f32;
+    let origin = Vec2::new(chunk.x as f32, chunk.y as f32) * CHUNK_SIZE;
+    let side = cells + 1;
+
+    let mut positions = Vec::with_capacity((side * side + side * 4) as usize);
+    let mut normals = Vec::with_capacity(positions.capacity());
+    let mut indices = Vec::with_capacity((cells * cells * 6 + cells * 24) as usize);
+    for j in 0..side {
+        for i in 0..side {
+            let (x, z) = (i as f32 * step, j as f32 * step);
+            let (wx, wz) = (origin.x + x, origin.y + z);
+            positions.push([x, landscape.height(wx, wz), z]);
+            normals.push(landscape.normal(wx, wz, step).into());
+        }
+    }
+    for j in 0..cells {
+        for i in 0..cells {
+            let a = j * side + i;
+            let (b, c, d) = (a + 1, a + side, a + side + 1);
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+
+    // Walk the border and hang a strip below each edge.
+    let border: Vec<u32> = (0..cells)
+        .chain((0..cells).map(|j| j * side + cells))
+        .chain((0..cells).map(|i| cells * side + cells - i))
+        .chain((0..cells).map(|j| (cells - j) * side))
+        .collect();
+    let skirt_start = positions.len() as u32;
+    for &top in &border {
+        let [x, y, z] = positions[top as usize];
+        positions.push([x, y - SKIRT_DEPTH, z]);
+        normals.push(normals[top as usize]);
+    }
+    let count = border.len() as u32;
+    for k in 0..count {
+        let next = (k + 1) % count;
+        let (top, next_top) = (border[k as usize], border[next as usize]);
+        let (bottom, next_bottom) = (skirt_start + k, skirt_start + next);
+        indices.extend_from_slice(&[top, bottom, next_top, next_top, bottom, next_bottom]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in vec3 Vertex_Normal;
+
+layout(location = 0) out vec3 v_World;
+layout(location = 1) out vec3 v_Normal;
+
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+
+void main() {
+    vec4 world = Model * vec4(Vertex_Position, 1.0);
+    v_World = world.xyz;
+    v_Normal = mat3(Model) * Vertex_Normal;
+    gl_Position = ViewProj * world;
+}
@@ -0,0 +1,44 @@
+//! Compute support for the XSecurelock renderer. Bevy 0.5's render graph has no compute passes, so
+//! savers which need compute register [`ComputeJob`]s in the [`ComputeJobs`] resource, and the
+//! renderer runs them against wgpu directly each frame, before the render graph. Textures created
+//! through the [`WgpuRenderResourceContext`] can be written by compute jobs and then sampled by
+//! ordinary Bevy materials.
+
+use crate::renderer::WgpuRenderResourceContext;
+use bevy_ecs::world::World;
+
+/// Everything a [`ComputeJob`] needs to record GPU work.
+pub struct ComputeContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    /// Encoder for this frame's compute work. It is submitted before the render graph runs.
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// Render resources shared with Bevy, for looking up or creating textures Bevy can sample.
+    pub render_resource_context: &'a WgpuRenderResourceContext,
+}
+
+/// GPU work recorded directly with wgpu each frame.
+pub trait ComputeJob: Send + Sync + 'static {
+    /// Records this frame's work. Called once per rendered frame, in the order jobs were added.
+    fn run(&mut self, world: &World, context: &mut ComputeContext);
+}
+
+/// Compute jobs run by the renderer each frame.
+#[derive(Default)]
+pub struct ComputeJobs {
+    jobs: Vec<Box<dyn ComputeJob>>,
+}
+
+impl ComputeJobs {
+    pub fn add(&mut self, job: impl ComputeJob) {
+        self.jobs.push(Box::new(job));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn ComputeJob>> {
+        self.jobs.iter_mut()
+    }
+}
@@ -0,0 +1,62 @@
+//! Custom full-screen passes run after the render graph, for effects such as feedback blur which
+//! need to read the finished frame. While any [`CustomPass`] is registered in [`CustomPasses`],
+//! Bevy renders each window into an offscreen color target instead of the swap chain. Passes then
+//! run in the order they were added, each reading the previous pass's output as its `source` and
+//! drawing into its `target`, and the last pass draws into the swap chain.
+//!
+//! Effects which only draw into the scene, rather than reading it back, are better off as ordinary
+//! Bevy render graph nodes, which can take the main color target from
+//! `base::node::PRIMARY_SWAP_CHAIN` as usual.
+
+use crate::renderer::WgpuRenderResourceContext;
+use bevy_ecs::world::World;
+
+/// Everything a [`CustomPass`] needs to draw.
+pub struct CustomPassContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    /// Encoder for this frame's custom passes. It is submitted before the frame is presented.
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// Render resources shared with Bevy. Resources may be created here, but the swap chain
+    /// frame's view is borrowed for the duration of the pass.
+    pub render_resource_context: &'a WgpuRenderResourceContext,
+    /// The frame so far, which can be sampled.
+    pub source: &'a wgpu::TextureView,
+    /// Where this pass draws its output. Every pixel must be written.
+    pub target: &'a wgpu::TextureView,
+    /// Format of both `source` and `target`.
+    pub format: wgpu::TextureFormat,
+    /// Width and height of both `source` and `target`, in pixels.
+    pub size: (u32, u32),
+}
+
+/// A full-screen pass recorded directly with wgpu each frame.
+pub trait CustomPass: Send + Sync + 'static {
+    /// Records this frame's pass. Called once per rendered frame and window, in the order passes
+    /// were added.
+    fn run(&mut self, world: &World, context: &mut CustomPassContext);
+}
+
+/// Custom passes run by the renderer each frame.
+#[derive(Default)]
+pub struct CustomPasses {
+    passes: Vec<Box<dyn CustomPass>>,
+}
+
+impl CustomPasses {
+    pub fn add(&mut self, pass: impl CustomPass) {
+        self.passes.push(Box::new(pass));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn CustomPass>> {
+        self.passes.iter_mut()
+    }
+}
@@ -0,0 +1,302 @@
+//! Dynamic resolution. When enabled through [`WgpuOptions::dynamic_resolution`], windows are
+//! rendered offscreen, and Bevy's passes into the offscreen target are restricted to a viewport
+//! covering only part of it whenever frames take longer than the budget. An upscale pass then
+//! stretches that viewport over the whole window with bilinear filtering, before any custom passes
+//! run. The same pass, at a scale of one, does the fade in from [`crate::fade_in`] and the
+//! compositing from [`crate::transparency`].
+//!
+//! [`WgpuOptions::dynamic_resolution`]: crate::WgpuOptions::dynamic_resolution
+
+use crate::transparency::Composite;
+use bevy_render::shader::{Shader, ShaderStage};
+use std::time::{Duration, Instant};
+
+/// How much of each new frame time goes into the running average.
+const SMOOTHING: f32 = 0.1;
+/// Frame times longer than this are pauses, such as while the window was obscured, rather than
+/// slow frames, and are ignored.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+/// Factor the scale shrinks by each frame while over budget.
+const SCALE_DOWN: f32 = 0.95;
+/// Factor the scale grows by each frame while comfortably under budget.
+const SCALE_UP: f32 = 1.02;
+/// Fraction of the budget the average must drop below before the scale grows again.
+const HEADROOM: f32 = 0.8;
+
+/// Limits for dynamic resolution.
+#[derive(Debug, Clone, Copy)]
+pub struct DynamicResolution {
+    /// Smallest fraction of the window's width and height to render at.
+    pub min_scale: f32,
+    /// Largest fraction of the window's width and height to render at.
+    pub max_scale: f32,
+    /// Frame time to stay under. Defaults to a little over a 60 Hz frame so vsync jitter doesn't
+    /// count as running slow.
+    pub frame_budget: Duration,
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.5,
+            max_scale: 1.0,
+            frame_budget: Duration::from_secs_f32(1.0 / 50.0),
+        }
+    }
+}
+
+/// Picks the render scale from recent frame times.
+pub(crate) struct RenderScale {
+    limits: DynamicResolution,
+    scale: f32,
+    average: Option<f32>,
+    last_frame: Option<Instant>,
+}
+
+impl RenderScale {
+    pub fn new(limits: DynamicResolution) -> Self {
+        Self {
+            limits,
+            scale: limits.max_scale,
+            average: None,
+            last_frame: None,
+        }
+    }
+
+    /// Records that a frame is being rendered now, and returns the scale to render it at.
+    pub fn next_frame(&mut self) -> f32 {
+        let now = Instant::now();
+        if let Some(frame_time) = self.last_frame.replace(now).map(|last| now - last) {
+            if frame_time < MAX_FRAME_TIME {
+                let frame_time = frame_time.as_secs_f32();
+                let average = self.average.map_or(frame_time, |average| {
+                    average + (frame_time - average) * SMOOTHING
+                });
+                self.average = Some(average);
+                let budget = self.limits.frame_budget.as_secs_f32();
+                if average > budget {
+                    self.scale *= SCALE_DOWN;
+                } else if average < budget * HEADROOM {
+                    self.scale *= SCALE_UP;
+                }
+                self.scale = self
+                    .scale
+                    .clamp(self.limits.min_scale, self.limits.max_scale);
+            }
+        }
+        self.scale
+    }
+}
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) out vec2 v_Uv;
+
+layout(set = 0, binding = 2) uniform Upscale {
+    // Size of the viewport relative to the texture in xy, and the largest UV to sample in zw.
+    vec4 uv_scale;
+    // Linear brightness to output at in x, opacity in y, and 1 in z to premultiply by alpha.
+    vec4 composite;
+};
+
+void main() {
+    // One triangle covering the screen.
+    vec2 corner = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_Uv = vec2(corner.x, 1.0 - corner.y) * uv_scale.xy;
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec2 v_Uv;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform sampler source_sampler;
+layout(set = 0, binding = 2) uniform Upscale {
+    vec4 uv_scale;
+    vec4 composite;
+};
+
+void main() {
+    // Clamp to the viewport so filtering doesn't pick up texels outside it.
+    vec4 color = texture(sampler2D(source, source_sampler), min(v_Uv, uv_scale.zw));
+    float alpha = color.a * composite.y;
+    o_Target = vec4(color.rgb * composite.x * mix(1.0, alpha, composite.z), alpha);
+}
+"#;
+
+/// Pass stretching the rendered viewport of the offscreen target over the whole window, optionally
+/// dimming it or compositing it with alpha.
+pub(crate) struct Upscale {
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+}
+
+impl Upscale {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("upscale"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(32),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("upscale"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let vertex = shader_module(device, ShaderStage::Vertex, VERTEX_SHADER);
+        let fragment = shader_module(device, ShaderStage::Fragment, FRAGMENT_SHADER);
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("upscale"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment,
+                entry_point: "main",
+                targets: &[format.into()],
+            }),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("upscale"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("upscale"),
+            size: 32,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            layout,
+            pipeline,
+            sampler,
+            uniforms,
+        }
+    }
+
+    /// Records drawing the `viewport` at the top left of `source`, which is `size` pixels, over
+    /// all of `target`, written as described by `composite`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+        viewport: (u32, u32),
+        composite: Composite,
+    ) {
+        let (width, height) = (size.0 as f32, size.1 as f32);
+        let (viewport_width, viewport_height) = (viewport.0 as f32, viewport.1 as f32);
+        let uniforms = [
+            viewport_width / width,
+            viewport_height / height,
+            (viewport_width - 0.5) / width,
+            (viewport_height - 0.5) / height,
+            composite.brightness,
+            composite.opacity,
+            if composite.premultiply { 1.0 } else { 0.0 },
+            0.0,
+        ];
+        let bytes: Vec<u8> = uniforms
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        queue.write_buffer(&self.uniforms, 0, &bytes);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("upscale"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+            ],
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("upscale"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(if composite.premultiply {
+                        wgpu::Color::TRANSPARENT
+                    } else {
+                        wgpu::Color::BLACK
+                    }),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn shader_module(device: &wgpu::Device, stage: ShaderStage, source: &str) -> wgpu::ShaderModule {
+    let spirv = Shader::from_glsl(stage, source)
+        .get_spirv(None)
+        .expect("upscale shaders should compile");
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("upscale"),
+        source: wgpu::ShaderSource::SpirV(spirv.into()),
+        flags: Default::default(),
+    })
+}
@@ -0,0 +1,52 @@
+use std::{error::Error, fmt};
+
+/// Reasons the renderer or the external X window couldn't be set up.
+#[derive(Debug)]
+pub enum RenderInitError {
+    /// `$DISPLAY` isn't set.
+    NoDisplay,
+    /// `$DISPLAY` contains a nul byte.
+    InvalidDisplay,
+    /// The X display couldn't be opened.
+    OpenDisplay,
+    /// The attributes of the external X window couldn't be read.
+    WindowAttributes,
+    /// `$BEVY_WGPU_BACKEND` names a backend which doesn't exist.
+    UnknownBackend(String),
+    /// No GPU adapter was found for the requested backend.
+    NoAdapter,
+    /// The adapter refused to create a device.
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for RenderInitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderInitError::NoDisplay => write!(f, "no X11 $DISPLAY set"),
+            RenderInitError::InvalidDisplay => write!(f, "$DISPLAY was not a valid CString"),
+            RenderInitError::OpenDisplay => write!(f, "failed to open display"),
+            RenderInitError::WindowAttributes => write!(f, "failed to get window attributes"),
+            RenderInitError::UnknownBackend(backend) => write!(f, "unknown backend: {}", backend),
+            RenderInitError::NoAdapter => write!(
+                f,
+                "unable to find a GPU, make sure you have installed required drivers"
+            ),
+            RenderInitError::RequestDevice(err) => write!(f, "unable to create device: {}", err),
+        }
+    }
+}
+
+impl Error for RenderInitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderInitError::RequestDevice(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RenderInitError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        RenderInitError::RequestDevice(err)
+    }
+}
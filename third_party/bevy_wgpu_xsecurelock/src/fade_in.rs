@@ -0,0 +1,66 @@
+//! Fading in from black when the saver starts. XSecurelock's window can briefly show whatever was
+//! in video memory before the saver's first frame, so the first frame presented is always solid
+//! black. After that, frames fade in over [`WgpuOptions::fade_in`] with a final pass which scales
+//! the frame's brightness. The frame is stored as sRGB and decoded when sampled, so the scaling is
+//! done on linear light and the fade brightens evenly rather than crushing the shadows.
+//!
+//! Windows are rendered offscreen until the fade finishes, then go back to rendering straight into
+//! the swap chain unless something else needs them offscreen.
+//!
+//! [`WgpuOptions::fade_in`]: crate::WgpuOptions::fade_in
+
+use std::time::{Duration, Instant};
+
+/// Settings for fading in.
+#[derive(Debug, Clone, Copy)]
+pub struct FadeIn {
+    /// How long frames take to go from black to full brightness.
+    pub duration: Duration,
+}
+
+impl Default for FadeIn {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Tracks how far the fade has progressed.
+pub(crate) struct FadeState {
+    duration: Duration,
+    /// When the first frame was presented.
+    start: Option<Instant>,
+}
+
+impl FadeState {
+    /// Without a [`FadeIn`], the first frame is still black but the next is at full brightness.
+    pub fn new(fade_in: Option<FadeIn>) -> Self {
+        Self {
+            duration: fade_in.map_or(Duration::from_secs(0), |fade_in| fade_in.duration),
+            start: None,
+        }
+    }
+
+    /// Whether frames have reached full brightness, after which the fade pass is skipped.
+    pub fn is_done(&self) -> bool {
+        self.start
+            .map_or(false, |start| start.elapsed() >= self.duration)
+    }
+
+    /// Records that a frame is being presented now, and returns the linear brightness to present
+    /// it at. The first frame is always 0.
+    pub fn next_frame(&mut self) -> f32 {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some(Instant::now());
+                return 0.0;
+            }
+        };
+        if self.duration == Duration::from_secs(0) {
+            return 1.0;
+        }
+        (start.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+}
@@ -0,0 +1,109 @@
+//! Shader hot-reloading for saver development. Savers register the source files behind their
+//! shaders with [`ShaderHotReload`], and while the saver runs in its own window the files are
+//! polled for changes. A changed shader which compiles replaces the old one, and Bevy rebuilds the
+//! pipelines that use it. A shader which fails to compile is logged and skipped, leaving the last
+//! good pipelines in place. Under XSecurelock nothing is watched.
+//!
+//! Bevy 0.5 only compiles GLSL and SPIR-V, so only GLSL sources can be watched.
+
+use crate::ExternalXWindow;
+use bevy_asset::{Assets, Handle};
+use bevy_ecs::system::{Res, ResMut};
+use bevy_render::shader::Shader;
+use bevy_utils::tracing::{error, info};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct WatchedShader {
+    handle: Handle<Shader>,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Shader source files to reload when they change.
+#[derive(Default)]
+pub struct ShaderHotReload {
+    watched: Vec<WatchedShader>,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderHotReload {
+    /// Reloads the shader from `path` whenever the file changes. The shader should have been
+    /// created from the file's contents, typically with `include_str!`, so `path` is usually built
+    /// with `concat!(env!("CARGO_MANIFEST_DIR"), ...)`.
+    pub fn watch(&mut self, handle: &Handle<Shader>, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let modified = modified_time(&path);
+        self.watched.push(WatchedShader {
+            handle: handle.clone_weak(),
+            path,
+            modified,
+        });
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Replaces watched shaders whose source files have changed since the last poll.
+pub fn shader_hot_reload_system(
+    external_window: Option<Res<ExternalXWindow>>,
+    mut hot_reload: ResMut<ShaderHotReload>,
+    mut shaders: ResMut<Assets<Shader>>,
+) {
+    if external_window.is_some() || hot_reload.watched.is_empty() {
+        return;
+    }
+    let now = Instant::now();
+    if hot_reload
+        .last_poll
+        .map_or(false, |last| now - last < POLL_INTERVAL)
+    {
+        return;
+    }
+    hot_reload.last_poll = Some(now);
+
+    for watched in hot_reload.watched.iter_mut() {
+        let modified = modified_time(&watched.path);
+        // Missing files are skipped, and picked up again once an editor finishes saving them.
+        if modified.is_none() || modified == watched.modified {
+            continue;
+        }
+        watched.modified = modified;
+
+        let source = match fs::read_to_string(&watched.path) {
+            Ok(source) => source,
+            Err(err) => {
+                error!("Failed to read shader {}: {}", watched.path.display(), err);
+                continue;
+            }
+        };
+        let stage = match shaders.get(&watched.handle) {
+            Some(shader) => shader.stage,
+            None => continue,
+        };
+        let shader = Shader::from_glsl(stage, &source);
+        // Check the new source before swapping it in, so a typo doesn't take down the pipelines
+        // drawn with it.
+        if let Err(err) = shader.get_spirv(None) {
+            error!(
+                "Failed to compile shader {}, keeping the last good version: {}",
+                watched.path.display(),
+                err
+            );
+            continue;
+        }
+        info!("Reloaded shader {}", watched.path.display());
+        // Modifying the asset makes Bevy recompile the pipelines which use it.
+        if let Some(old) = shaders.get_mut(&watched.handle) {
+            *old = shader;
+        }
+    }
+}
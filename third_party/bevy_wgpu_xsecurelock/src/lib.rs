@@ -1,27 +1,42 @@
+pub mod compute;
+pub mod custom_pass;
 pub mod diagnostic;
+pub mod dynamic_resolution;
+mod error;
+pub mod fade_in;
+pub mod hot_reload;
+pub mod push_constants;
 pub mod renderer;
+pub mod screen_dissolve;
+pub mod temporal_anti_aliasing;
+pub mod transparency;
 mod wgpu_render_pass;
 mod wgpu_renderer;
 mod wgpu_resources;
 mod wgpu_type_converter;
 
-use bevy_window::{WindowDescriptor, WindowId};
+use bevy_window::{WindowDescriptor, WindowId, WindowResized, Windows};
+pub use error::RenderInitError;
 pub use wgpu_render_pass::*;
 pub use wgpu_renderer::*;
 pub use wgpu_resources::*;
 
-use bevy_app::prelude::*;
+/// Describes the GPU adapter the renderer runs on. Added as a resource once the renderer is set up.
+pub use wgpu::AdapterInfo;
+
+use bevy_app::{prelude::*, AppExit};
 use bevy_ecs::{
-    system::{IntoExclusiveSystem, IntoSystem},
+    system::{IntoExclusiveSystem, IntoSystem, ResMut},
     world::World,
 };
 use bevy_render::{
     renderer::{shared_buffers_update_system, RenderResourceContext, SharedBuffers},
     RenderStage,
 };
+use bevy_utils::tracing::{info, warn};
 use futures_lite::future;
 use raw_window_handle::{unix::XlibHandle, HasRawWindowHandle, RawWindowHandle};
-use renderer::WgpuRenderResourceContext;
+use renderer::{HeadlessRenderResourceContext, WgpuRenderResourceContext};
 use std::{borrow::Cow, env, os::unix::prelude::OsStringExt};
 
 #[derive(Clone, Copy)]
@@ -91,8 +106,31 @@ pub struct WgpuPlugin;
 
 impl Plugin for WgpuPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let render_system = get_wgpu_render_system(app.world_mut());
-        app.add_system_to_stage(RenderStage::Render, render_system.exclusive_system())
+        let options = app.world().get_resource::<WgpuOptions>();
+        let headless = options.map_or(false, |options| options.headless);
+        let jitter_cameras =
+            options.map_or(false, |options| options.temporal_anti_aliasing.is_some());
+        let render_system: Box<dyn FnMut(&mut World) + Send + Sync> = if headless {
+            Box::new(get_headless_render_system(app.world_mut()))
+        } else {
+            Box::new(get_wgpu_render_system(app.world_mut()))
+        };
+        if jitter_cameras {
+            app.add_system_to_stage(
+                RenderStage::RenderResource,
+                temporal_anti_aliasing::jitter_cameras_system.system(),
+            );
+        }
+        app.init_resource::<WindowVisibility>()
+            .init_resource::<compute::ComputeJobs>()
+            .init_resource::<custom_pass::CustomPasses>()
+            .init_resource::<hot_reload::ShaderHotReload>()
+            .add_system_to_stage(CoreStage::PreUpdate, external_window_event_system.system())
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                hot_reload::shader_hot_reload_system.system(),
+            )
+            .add_system_to_stage(RenderStage::Render, render_system.exclusive_system())
             .add_system_to_stage(
                 RenderStage::PostRender,
                 shared_buffers_update_system.system(),
@@ -105,16 +143,40 @@ pub fn get_wgpu_render_system(world: &mut World) -> impl FnMut(&mut World) {
         .get_resource::<WgpuOptions>()
         .cloned()
         .unwrap_or_else(WgpuOptions::default);
-    let mut wgpu_renderer = future::block_on(WgpuRenderer::new(options));
+    let mut wgpu_renderer = future::block_on(WgpuRenderer::new(options))
+        .unwrap_or_else(|err| panic!("Unable to initialize rendering: {}", err));
 
     let resource_context = WgpuRenderResourceContext::new(wgpu_renderer.device.clone());
     world.insert_resource::<Box<dyn RenderResourceContext>>(Box::new(resource_context));
     world.insert_resource(SharedBuffers::new(4096));
+    world.insert_resource(wgpu_renderer.adapter_info.clone());
     move |world| {
         wgpu_renderer.update(world);
     }
 }
 
+/// Sets up a GPU device with the given options, as the renderer would but without a window, and
+/// returns the adapter it was created on. Lets savers check that rendering will work.
+pub fn probe_adapter(options: &WgpuOptions) -> Result<wgpu::AdapterInfo, RenderInitError> {
+    future::block_on(WgpuRenderer::new(options.clone())).map(|renderer| renderer.adapter_info)
+}
+
+/// Like [`get_wgpu_render_system`], but with a [`HeadlessRenderResourceContext`] and no GPU. The
+/// render system only drops the frame's resources, as the wgpu renderer does after rendering.
+pub fn get_headless_render_system(world: &mut World) -> impl FnMut(&mut World) {
+    world.insert_resource::<Box<dyn RenderResourceContext>>(Box::new(
+        HeadlessRenderResourceContext::default(),
+    ));
+    world.insert_resource(SharedBuffers::new(4096));
+    move |world| {
+        let render_resource_context = world
+            .get_resource::<Box<dyn RenderResourceContext>>()
+            .unwrap();
+        render_resource_context.drop_all_swap_chain_textures();
+        render_resource_context.remove_stale_bind_groups();
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct WgpuOptions {
     pub device_label: Option<Cow<'static, str>>,
@@ -122,6 +184,19 @@ pub struct WgpuOptions {
     pub power_pref: WgpuPowerOptions,
     pub features: WgpuFeatures,
     pub limits: WgpuLimits,
+    /// Lowers the resolution the scene is rendered at when frames run slow. Off by default.
+    pub dynamic_resolution: Option<dynamic_resolution::DynamicResolution>,
+    /// Smooths edges by blending jittered frames together, as an alternative to MSAA. Off by
+    /// default.
+    pub temporal_anti_aliasing: Option<temporal_anti_aliasing::TemporalAntiAliasing>,
+    /// Fades in from black when rendering starts. Off by default, though the first frame is black
+    /// either way.
+    pub fade_in: Option<fade_in::FadeIn>,
+    /// Lets the desktop show through the saver, where the window supports it. Off by default.
+    pub transparency: Option<transparency::Transparency>,
+    /// Runs without a GPU, with a [`HeadlessRenderResourceContext`] in place of the wgpu one. The
+    /// app's systems and resources are the same, but nothing is drawn. Off by default.
+    pub headless: bool,
 }
 
 #[derive(Clone)]
@@ -136,26 +211,27 @@ pub enum WgpuBackend {
 }
 
 impl WgpuBackend {
-    fn from_env() -> Self {
+    /// Reads the backend from `$BEVY_WGPU_BACKEND`, defaulting to [`WgpuBackend::Auto`].
+    pub fn from_env() -> Result<Self, RenderInitError> {
         if let Ok(backend) = std::env::var("BEVY_WGPU_BACKEND") {
             match backend.to_lowercase().as_str() {
-                "vulkan" => WgpuBackend::Vulkan,
-                "metal" => WgpuBackend::Metal,
-                "dx12" => WgpuBackend::Dx12,
-                "dx11" => WgpuBackend::Dx11,
-                "gl" => WgpuBackend::Gl,
-                "webgpu" => WgpuBackend::BrowserWgpu,
-                other => panic!("Unknown backend: {}", other),
+                "vulkan" => Ok(WgpuBackend::Vulkan),
+                "metal" => Ok(WgpuBackend::Metal),
+                "dx12" => Ok(WgpuBackend::Dx12),
+                "dx11" => Ok(WgpuBackend::Dx11),
+                "gl" => Ok(WgpuBackend::Gl),
+                "webgpu" => Ok(WgpuBackend::BrowserWgpu),
+                _ => Err(RenderInitError::UnknownBackend(backend)),
             }
         } else {
-            WgpuBackend::Auto
+            Ok(WgpuBackend::Auto)
         }
     }
 }
 
 impl Default for WgpuBackend {
     fn default() -> Self {
-        Self::from_env()
+        Self::from_env().unwrap_or_else(|err| panic!("{}", err))
     }
 }
 
@@ -177,6 +253,35 @@ pub struct ExternalXWindow {
     display: *mut x11::xlib::Display,
     handle: x11::xlib::Window,
     pub window_id: WindowId,
+    destroyed: bool,
+    /// The window's drawable when the surface was last created, if its attributes could be read.
+    drawable: Option<Drawable>,
+    /// Whether the drawable changed since the surface was created, so the surface is stale.
+    surface_stale: bool,
+}
+
+/// What the surface for a window depends on: its size and its visual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Drawable {
+    width: i32,
+    height: i32,
+    depth: i32,
+    visual: x11::xlib::VisualID,
+}
+
+impl Drawable {
+    fn of(attributes: &x11::xlib::XWindowAttributes) -> Self {
+        Self {
+            width: attributes.width,
+            height: attributes.height,
+            depth: attributes.depth,
+            visual: if attributes.visual.is_null() {
+                0
+            } else {
+                unsafe { (*attributes.visual).visualid }
+            },
+        }
+    }
 }
 
 unsafe impl Send for ExternalXWindow {}
@@ -184,34 +289,141 @@ unsafe impl Sync for ExternalXWindow {}
 
 impl ExternalXWindow {
     /// Open a connection to the X Display attached to the given window.
-    pub fn new(handle: x11::xlib::Window) -> Self {
-        let display = env::var_os("DISPLAY").expect("No X11 $DISPLAY set");
-        let display =
-            std::ffi::CString::new(display.into_vec()).expect("$DISPLAY was not a valid CString");
+    pub fn new(handle: x11::xlib::Window) -> Result<Self, RenderInitError> {
+        let display = env::var_os("DISPLAY").ok_or(RenderInitError::NoDisplay)?;
+        let display = std::ffi::CString::new(display.into_vec())
+            .map_err(|_| RenderInitError::InvalidDisplay)?;
         let display = unsafe { x11::xlib::XOpenDisplay(display.as_ptr()) };
         if display.is_null() {
-            panic!("Failed to open display");
+            return Err(RenderInitError::OpenDisplay);
         }
-        Self {
+        // Each X client has its own event mask on a window, so this doesn't interfere with events
+        // XSecurelock receives for the same window.
+        unsafe {
+            x11::xlib::XSelectInput(
+                display,
+                handle,
+                x11::xlib::VisibilityChangeMask
+                    | x11::xlib::ExposureMask
+                    | x11::xlib::StructureNotifyMask,
+            )
+        };
+        let mut window = Self {
             display,
             handle,
             window_id: WindowId::primary(),
-        }
+            destroyed: false,
+            drawable: None,
+            surface_stale: false,
+        };
+        window.drawable = window
+            .attributes()
+            .ok()
+            .map(|attributes| Drawable::of(&attributes));
+        Ok(window)
     }
 
-    pub fn bevy_window_descriptor(&self) -> WindowDescriptor {
+    fn attributes(&self) -> Result<x11::xlib::XWindowAttributes, RenderInitError> {
         let mut attributes = unsafe { std::mem::zeroed::<x11::xlib::XWindowAttributes>() };
         if unsafe { x11::xlib::XGetWindowAttributes(self.display, self.handle, &mut attributes) }
             == 0
         {
-            panic!("Failed to get window attributes");
+            return Err(RenderInitError::WindowAttributes);
         }
+        Ok(attributes)
+    }
 
-        WindowDescriptor {
+    pub fn bevy_window_descriptor(&self) -> Result<WindowDescriptor, RenderInitError> {
+        let attributes = self.attributes()?;
+        Ok(WindowDescriptor {
             width: attributes.width as f32,
             height: attributes.height as f32,
             resizable: false,
             ..Default::default()
+        })
+    }
+
+    /// Position and size of the window on the screen, as `(x, y, width, height)` from the top
+    /// left of the root window. XSecurelock sizes the saver window to a single monitor, so this
+    /// tells which monitor the saver is on.
+    pub fn screen_rect(&self) -> Result<(i32, i32, u32, u32), RenderInitError> {
+        let attributes = self.attributes()?;
+        let (mut x, mut y) = (0, 0);
+        let mut child = 0;
+        if unsafe {
+            x11::xlib::XTranslateCoordinates(
+                self.display,
+                self.handle,
+                attributes.root,
+                0,
+                0,
+                &mut x,
+                &mut y,
+                &mut child,
+            )
+        } == 0
+        {
+            return Err(RenderInitError::WindowAttributes);
+        }
+        Ok((x, y, attributes.width as u32, attributes.height as u32))
+    }
+}
+
+impl ExternalXWindow {
+    /// Returns true once the X window has been destroyed, after which its surface is invalid and
+    /// must not be rendered to.
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed
+    }
+
+    /// Returns true if the window has a 32 bit ARGB visual, which a compositor can blend with
+    /// whatever is behind it. XSecurelock picks one for the saver window when it can.
+    pub fn has_alpha(&self) -> bool {
+        self.attributes().map_or(false, |attributes| {
+            attributes.depth == 32
+                && !attributes.visual.is_null()
+                && unsafe { (*attributes.visual).class } == x11::xlib::TrueColor
+        })
+    }
+
+    /// Returns true once after the window's drawable changed, such as when XSecurelock reparents
+    /// or remaps the window after startup, so the renderer knows to recreate the surface.
+    pub fn take_stale_surface(&mut self) -> bool {
+        std::mem::replace(&mut self.surface_stale, false)
+    }
+
+    /// Re-queries the window's attributes, marking the surface stale if the drawable changed.
+    /// Returns the new size if so.
+    fn check_drawable(&mut self) -> Option<(u32, u32)> {
+        let drawable = match self.attributes() {
+            Ok(attributes) => Drawable::of(&attributes),
+            Err(err) => {
+                warn!("Unable to check the saver window's drawable: {}", err);
+                return None;
+            }
+        };
+        if self.drawable == Some(drawable) {
+            return None;
+        }
+        info!(
+            "Saver window changed to {}x{} at depth {}, recreating its surface",
+            drawable.width, drawable.height, drawable.depth
+        );
+        self.drawable = Some(drawable);
+        self.surface_stale = true;
+        Some((drawable.width as u32, drawable.height as u32))
+    }
+
+    /// Returns the next pending X event for the window, if any, without blocking.
+    fn poll_event(&self) -> Option<x11::xlib::XEvent> {
+        unsafe {
+            if x11::xlib::XPending(self.display) > 0 {
+                let mut event = std::mem::zeroed::<x11::xlib::XEvent>();
+                x11::xlib::XNextEvent(self.display, &mut event);
+                Some(event)
+            } else {
+                None
+            }
         }
     }
 }
@@ -232,3 +444,103 @@ unsafe impl HasRawWindowHandle for ExternalXWindow {
         })
     }
 }
+
+/// How much of the window the renderer presents to is visible. Tracked from X11 visibility and
+/// expose events on the [`ExternalXWindow`]; always `Unobscured` when running under winit. Note
+/// that X servers with a compositor report every mapped window as unobscured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowVisibility {
+    Unobscured,
+    PartiallyObscured,
+    FullyObscured,
+}
+
+impl WindowVisibility {
+    /// Whether rendered frames can be seen at all. When false, the renderer skips presenting.
+    pub fn should_present(&self) -> bool {
+        *self != WindowVisibility::FullyObscured
+    }
+}
+
+impl Default for WindowVisibility {
+    fn default() -> Self {
+        WindowVisibility::Unobscured
+    }
+}
+
+/// Drains pending X events for the [`ExternalXWindow`] and updates the [`WindowVisibility`]. Exits
+/// the app if XSecurelock destroys the window. When XSecurelock reparents, maps or reconfigures the
+/// window, checks whether its drawable changed, and if so resizes the Bevy window and sends a
+/// [`WindowResized`] event, which recreates the swap chain once the renderer has recreated the
+/// stale surface.
+pub fn external_window_event_system(
+    external_window: Option<ResMut<ExternalXWindow>>,
+    mut visibility: ResMut<WindowVisibility>,
+    mut windows: ResMut<Windows>,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut resized_events: EventWriter<WindowResized>,
+) {
+    let mut external_window = match external_window {
+        Some(external_window) => external_window,
+        None => return,
+    };
+    if external_window.destroyed {
+        return;
+    }
+    let mut new_visibility = *visibility;
+    let mut check_drawable = false;
+    while let Some(event) = external_window.poll_event() {
+        match event.get_type() {
+            x11::xlib::DestroyNotify
+                if unsafe { event.destroy_window.window } == external_window.handle =>
+            {
+                external_window.destroyed = true;
+                app_exit_events.send(AppExit);
+                break;
+            }
+            x11::xlib::VisibilityNotify => {
+                new_visibility = match unsafe { event.visibility.state } {
+                    x11::xlib::VisibilityUnobscured => WindowVisibility::Unobscured,
+                    x11::xlib::VisibilityPartiallyObscured => WindowVisibility::PartiallyObscured,
+                    _ => WindowVisibility::FullyObscured,
+                };
+            }
+            // Some part of the window is visible again, even if no VisibilityNotify says so yet.
+            x11::xlib::Expose if new_visibility == WindowVisibility::FullyObscured => {
+                new_visibility = WindowVisibility::PartiallyObscured;
+            }
+            x11::xlib::ReparentNotify
+                if unsafe { event.reparent.window } == external_window.handle =>
+            {
+                check_drawable = true;
+            }
+            x11::xlib::MapNotify if unsafe { event.map.window } == external_window.handle => {
+                check_drawable = true;
+            }
+            x11::xlib::ConfigureNotify
+                if unsafe { event.configure.window } == external_window.handle =>
+            {
+                check_drawable = true;
+            }
+            _ => {}
+        }
+    }
+    // Checked once all the events are drained, as several often arrive together.
+    if check_drawable {
+        if let Some((width, height)) = external_window.check_drawable() {
+            let id = external_window.window_id;
+            if let Some(window) = windows.get_mut(id) {
+                window.update_actual_size_from_backend(width, height);
+                resized_events.send(WindowResized {
+                    id,
+                    width: window.width(),
+                    height: window.height(),
+                });
+            }
+        }
+    }
+    // Only write when changed so change detection on the resource is meaningful.
+    if new_visibility != *visibility {
+        *visibility = new_visibility;
+    }
+}
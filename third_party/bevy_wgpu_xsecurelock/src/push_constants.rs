@@ -0,0 +1,58 @@
+//! Push constants for small per-frame parameters, such as time or audio levels, which change every
+//! frame and are too small to be worth a uniform buffer. The renderer enables push constants
+//! whenever the adapter supports them; check
+//! [`WgpuRenderResourceContext::supports_push_constants`] and fall back to a uniform buffer
+//! otherwise.
+//!
+//! [`WgpuRenderResourceContext::supports_push_constants`]:
+//! crate::renderer::WgpuRenderResourceContext::supports_push_constants
+
+/// Push constant space the renderer asks for. This is the minimum Vulkan guarantees.
+pub const MAX_PUSH_CONSTANT_SIZE: u32 = 128;
+
+/// Push constant data, laid out to match a GLSL `layout(push_constant) uniform` block declaring
+/// the same values in the same order.
+#[derive(Debug, Default, Clone)]
+pub struct PushConstants {
+    bytes: Vec<u8>,
+}
+
+impl PushConstants {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Appends a `float`.
+    pub fn float(&mut self, value: f32) -> &mut Self {
+        self.push(4, &[value])
+    }
+
+    /// Appends a `vec2`.
+    pub fn vec2(&mut self, value: [f32; 2]) -> &mut Self {
+        self.push(8, &value)
+    }
+
+    /// Appends a `vec4`, which is also how a `vec3` should be passed.
+    pub fn vec4(&mut self, value: [f32; 4]) -> &mut Self {
+        self.push(16, &value)
+    }
+
+    fn push(&mut self, align: usize, values: &[f32]) -> &mut Self {
+        let padded = (self.bytes.len() + align - 1) / align * align;
+        self.bytes.resize(padded, 0);
+        for value in values {
+            self.bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        assert!(
+            self.bytes.len() <= MAX_PUSH_CONSTANT_SIZE as usize,
+            "push constants exceed {} bytes",
+            MAX_PUSH_CONSTANT_SIZE
+        );
+        self
+    }
+
+    /// The data to pass to `set_push_constants`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
@@ -0,0 +1,211 @@
+use super::{BIND_BUFFER_ALIGNMENT, COPY_BYTES_PER_ROW_ALIGNMENT};
+use bevy_asset::{Assets, Handle, HandleUntyped};
+use bevy_render::{
+    pipeline::{BindGroupDescriptorId, PipelineDescriptor},
+    renderer::{
+        BindGroup, BufferId, BufferInfo, BufferMapMode, RenderResourceContext, RenderResourceId,
+        SamplerId, TextureId,
+    },
+    shader::{glsl_to_spirv, Shader, ShaderError, ShaderSource},
+    texture::{SamplerDescriptor, TextureDescriptor},
+};
+use bevy_utils::{HashMap, HashSet};
+use bevy_window::Window;
+use parking_lot::RwLock;
+use std::{ops::Range, sync::Arc};
+
+/// A [`RenderResourceContext`] which needs no GPU, used by the [`WgpuPlugin`](crate::WgpuPlugin)
+/// when [`WgpuOptions::headless`](crate::WgpuOptions::headless) is set.
+///
+/// Unlike Bevy's own headless context, shaders are still compiled to SPIR-V, so pipelines can be
+/// specialized and reflected and the usual draw systems run unchanged. Resources are only
+/// bookkeeping, but they are tracked until removed, so tests can check that an app isn't leaking
+/// them.
+#[derive(Debug, Default, Clone)]
+pub struct HeadlessRenderResourceContext {
+    buffer_info: Arc<RwLock<HashMap<BufferId, BufferInfo>>>,
+    texture_descriptors: Arc<RwLock<HashMap<TextureId, TextureDescriptor>>>,
+    samplers: Arc<RwLock<HashSet<SamplerId>>>,
+    asset_resources: Arc<RwLock<HashMap<(HandleUntyped, u64), RenderResourceId>>>,
+}
+
+impl HeadlessRenderResourceContext {
+    /// Number of buffers created and not yet removed.
+    pub fn buffer_count(&self) -> usize {
+        self.buffer_info.read().len()
+    }
+
+    /// Number of textures created and not yet removed, not counting swap chain textures.
+    pub fn texture_count(&self) -> usize {
+        self.texture_descriptors.read().len()
+    }
+
+    /// Number of samplers created and not yet removed.
+    pub fn sampler_count(&self) -> usize {
+        self.samplers.read().len()
+    }
+
+    /// Number of render resources set for assets, such as the textures of images or the buffers of
+    /// meshes, and not yet removed.
+    pub fn asset_resource_count(&self) -> usize {
+        self.asset_resources.read().len()
+    }
+}
+
+impl RenderResourceContext for HeadlessRenderResourceContext {
+    fn create_swap_chain(&self, _window: &Window) {}
+
+    fn next_swap_chain_texture(&self, _window: &Window) -> TextureId {
+        TextureId::new()
+    }
+
+    fn drop_swap_chain_texture(&self, _render_resource: TextureId) {}
+
+    fn drop_all_swap_chain_textures(&self) {}
+
+    fn create_sampler(&self, _sampler_descriptor: &SamplerDescriptor) -> SamplerId {
+        let sampler = SamplerId::new();
+        self.samplers.write().insert(sampler);
+        sampler
+    }
+
+    fn create_texture(&self, texture_descriptor: TextureDescriptor) -> TextureId {
+        let texture = TextureId::new();
+        self.texture_descriptors
+            .write()
+            .insert(texture, texture_descriptor);
+        texture
+    }
+
+    fn create_buffer(&self, buffer_info: BufferInfo) -> BufferId {
+        let buffer = BufferId::new();
+        self.buffer_info.write().insert(buffer, buffer_info);
+        buffer
+    }
+
+    fn write_mapped_buffer(
+        &self,
+        _id: BufferId,
+        range: Range<u64>,
+        write: &mut dyn FnMut(&mut [u8], &dyn RenderResourceContext),
+    ) {
+        let mut buffer = vec![0; (range.end - range.start) as usize];
+        write(&mut buffer, self);
+    }
+
+    fn read_mapped_buffer(
+        &self,
+        _id: BufferId,
+        range: Range<u64>,
+        read: &dyn Fn(&[u8], &dyn RenderResourceContext),
+    ) {
+        let buffer = vec![0; (range.end - range.start) as usize];
+        read(&buffer, self);
+    }
+
+    fn map_buffer(&self, _id: BufferId, _mode: BufferMapMode) {}
+
+    fn unmap_buffer(&self, _id: BufferId) {}
+
+    fn create_buffer_with_data(&self, buffer_info: BufferInfo, _data: &[u8]) -> BufferId {
+        self.create_buffer(buffer_info)
+    }
+
+    fn create_shader_module(&self, _shader_handle: &Handle<Shader>, _shaders: &Assets<Shader>) {}
+
+    fn create_shader_module_from_source(&self, _shader_handle: &Handle<Shader>, _shader: &Shader) {}
+
+    fn get_specialized_shader(
+        &self,
+        shader: &Shader,
+        macros: Option<&[String]>,
+    ) -> Result<Shader, ShaderError> {
+        // Pipelines are reflected from SPIR-V, so this compiles GLSL just like the wgpu context.
+        let spirv_data = match shader.source {
+            ShaderSource::Spirv(ref bytes) => bytes.clone(),
+            ShaderSource::Glsl(ref source) => glsl_to_spirv(&source, shader.stage, macros)?,
+        };
+        Ok(Shader {
+            source: ShaderSource::Spirv(spirv_data),
+            ..*shader
+        })
+    }
+
+    fn remove_buffer(&self, buffer: BufferId) {
+        self.buffer_info.write().remove(&buffer);
+    }
+
+    fn remove_texture(&self, texture: TextureId) {
+        self.texture_descriptors.write().remove(&texture);
+    }
+
+    fn remove_sampler(&self, sampler: SamplerId) {
+        self.samplers.write().remove(&sampler);
+    }
+
+    fn get_buffer_info(&self, buffer: BufferId) -> Option<BufferInfo> {
+        self.buffer_info.read().get(&buffer).cloned()
+    }
+
+    fn get_aligned_uniform_size(&self, size: usize, dynamic: bool) -> usize {
+        // Matches the wgpu context, so dynamic uniform offsets are laid out the same way.
+        if dynamic {
+            (size + BIND_BUFFER_ALIGNMENT - 1) & !(BIND_BUFFER_ALIGNMENT - 1)
+        } else {
+            size
+        }
+    }
+
+    fn get_aligned_texture_size(&self, size: usize) -> usize {
+        (size + COPY_BYTES_PER_ROW_ALIGNMENT - 1) & !(COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+    }
+
+    fn set_asset_resource_untyped(
+        &self,
+        handle: HandleUntyped,
+        render_resource: RenderResourceId,
+        index: u64,
+    ) {
+        self.asset_resources
+            .write()
+            .insert((handle, index), render_resource);
+    }
+
+    fn get_asset_resource_untyped(
+        &self,
+        handle: HandleUntyped,
+        index: u64,
+    ) -> Option<RenderResourceId> {
+        self.asset_resources.read().get(&(handle, index)).cloned()
+    }
+
+    fn remove_asset_resource_untyped(&self, handle: HandleUntyped, index: u64) {
+        self.asset_resources.write().remove(&(handle, index));
+    }
+
+    fn create_render_pipeline(
+        &self,
+        _pipeline_handle: Handle<PipelineDescriptor>,
+        _pipeline_descriptor: &PipelineDescriptor,
+        _shaders: &Assets<Shader>,
+    ) {
+    }
+
+    fn bind_group_descriptor_exists(
+        &self,
+        _bind_group_descriptor_id: BindGroupDescriptorId,
+    ) -> bool {
+        false
+    }
+
+    fn create_bind_group(
+        &self,
+        _bind_group_descriptor_id: BindGroupDescriptorId,
+        _bind_group: &BindGroup,
+    ) {
+    }
+
+    fn clear_bind_groups(&self) {}
+
+    fn remove_stale_bind_groups(&self) {}
+}
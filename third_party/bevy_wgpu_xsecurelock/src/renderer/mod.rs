@@ -1,7 +1,9 @@
+mod headless_render_resource_context;
 mod wgpu_render_context;
 mod wgpu_render_graph_executor;
 mod wgpu_render_resource_context;
 
+pub use headless_render_resource_context::*;
 pub use wgpu_render_context::*;
 pub use wgpu_render_graph_executor::*;
 pub use wgpu_render_resource_context::*;
@@ -180,12 +180,25 @@ impl RenderContext for WgpuRenderContext {
         let refs = resource_lock.refs();
         let mut encoder = self.command_encoder.take().unwrap();
         {
-            let render_pass = create_render_pass(
+            let mut render_pass = create_render_pass(
                 pass_descriptor,
                 render_resource_bindings,
                 &refs,
                 &mut encoder,
             );
+            let attachments: Vec<TextureId> = pass_descriptor
+                .color_attachments
+                .iter()
+                .flat_map(|color| {
+                    std::iter::once(&color.attachment).chain(color.resolve_target.as_ref())
+                })
+                .filter_map(|attachment| attachment.get_texture_id())
+                .collect();
+            if let Some((width, height)) =
+                self.render_resource_context.scaled_viewport(&attachments)
+            {
+                render_pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+            }
             let mut wgpu_render_pass = WgpuRenderPass {
                 render_pass,
                 render_context: self,
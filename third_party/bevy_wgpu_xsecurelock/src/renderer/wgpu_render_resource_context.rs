@@ -1,4 +1,4 @@
-use crate::{wgpu_type_converter::WgpuInto, WgpuBindGroupInfo, WgpuResources};
+use crate::{wgpu_type_converter::WgpuInto, OffscreenTargets, WgpuBindGroupInfo, WgpuResources};
 
 use crate::wgpu_type_converter::OwnedWgpuVertexBufferLayout;
 use bevy_asset::{Assets, Handle, HandleUntyped};
@@ -11,12 +11,17 @@ use bevy_render::{
         RenderResourceContext, RenderResourceId, SamplerId, TextureId,
     },
     shader::{glsl_to_spirv, Shader, ShaderError, ShaderSource},
-    texture::{Extent3d, SamplerDescriptor, TextureDescriptor},
+    texture::{Extent3d, SamplerDescriptor, TextureDescriptor, TextureFormat, TextureUsage},
 };
 use bevy_utils::tracing::trace;
 use bevy_window::{Window, WindowId};
 use futures_lite::future;
-use std::{borrow::Cow, num::NonZeroU64, ops::Range, sync::Arc};
+use std::{
+    borrow::Cow,
+    num::NonZeroU64,
+    ops::Range,
+    sync::{atomic::Ordering, Arc},
+};
 use wgpu::util::DeviceExt;
 
 #[derive(Clone, Debug)]
@@ -38,6 +43,14 @@ impl WgpuRenderResourceContext {
         }
     }
 
+    /// Whether pipelines can use push constants, up to
+    /// [`MAX_PUSH_CONSTANT_SIZE`](crate::push_constants::MAX_PUSH_CONSTANT_SIZE) bytes.
+    pub fn supports_push_constants(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::PUSH_CONSTANTS)
+    }
+
     pub fn set_window_surface(&self, window_id: WindowId, surface: wgpu::Surface) {
         let mut window_surfaces = self.resources.window_surfaces.write();
         window_surfaces.insert(window_id, surface);
@@ -225,6 +238,61 @@ impl WgpuRenderResourceContext {
         bind_group_layouts.insert(descriptor.id, bind_group_layout);
     }
 
+    /// Gets the texture to render `window` into in place of the swap chain `frame`, creating the
+    /// window's offscreen targets if it has none of the right size.
+    fn offscreen_target(&self, window: &Window, frame: TextureId) -> TextureId {
+        let size = (window.physical_width(), window.physical_height());
+        let mut offscreen_targets = self.resources.offscreen_targets.write();
+        if offscreen_targets
+            .get(&window.id())
+            .map_or(true, |targets| targets.size != size)
+        {
+            if let Some(old) = offscreen_targets.remove(&window.id()) {
+                for &texture in old.textures.iter() {
+                    self.remove_texture(texture);
+                }
+            }
+            let create = || {
+                self.create_texture(TextureDescriptor {
+                    size: Extent3d::new(size.0, size.1, 1),
+                    format: TextureFormat::default(),
+                    usage: TextureUsage::SAMPLED | TextureUsage::OUTPUT_ATTACHMENT,
+                    ..Default::default()
+                })
+            };
+            offscreen_targets.insert(
+                window.id(),
+                OffscreenTargets {
+                    size,
+                    viewport: size,
+                    textures: [create(), create()],
+                    frame: None,
+                },
+            );
+        }
+        let targets = offscreen_targets.get_mut(&window.id()).unwrap();
+        targets.frame = Some(frame);
+        targets.viewport = match *self.resources.render_scale.read() {
+            Some(scale) => (
+                ((size.0 as f32 * scale).ceil() as u32).clamp(1, size.0),
+                ((size.1 as f32 * scale).ceil() as u32).clamp(1, size.1),
+            ),
+            None => size,
+        };
+        targets.textures[0]
+    }
+
+    /// Gets the viewport to restrict a pass to if it draws into a scaled down offscreen target.
+    pub(crate) fn scaled_viewport(&self, textures: &[TextureId]) -> Option<(u32, u32)> {
+        self.resources
+            .offscreen_targets
+            .read()
+            .values()
+            .find(|targets| textures.contains(&targets.textures[0]))
+            .filter(|targets| targets.viewport != targets.size)
+            .map(|targets| targets.viewport)
+    }
+
     fn try_next_swap_chain_texture(&self, window_id: bevy_window::WindowId) -> Option<TextureId> {
         let mut window_swap_chains = self.resources.window_swap_chains.write();
         let mut swap_chain_outputs = self.resources.swap_chain_frames.write();
@@ -369,7 +437,7 @@ impl RenderResourceContext for WgpuRenderResourceContext {
     }
 
     fn next_swap_chain_texture(&self, window: &bevy_window::Window) -> TextureId {
-        if let Some(texture_id) = self.try_next_swap_chain_texture(window.id()) {
+        let frame = if let Some(texture_id) = self.try_next_swap_chain_texture(window.id()) {
             texture_id
         } else {
             self.resources
@@ -379,6 +447,11 @@ impl RenderResourceContext for WgpuRenderResourceContext {
             self.create_swap_chain(window);
             self.try_next_swap_chain_texture(window.id())
                 .expect("Failed to acquire next swap chain texture!")
+        };
+        if self.resources.render_offscreen.load(Ordering::Relaxed) {
+            self.offscreen_target(window, frame)
+        } else {
+            frame
         }
     }
 
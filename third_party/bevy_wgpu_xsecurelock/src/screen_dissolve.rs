@@ -0,0 +1,412 @@
+//! Dissolving a screenshot of the desktop to reveal the saver, for classic screen melting savers.
+//! [`ScreenDissolvePass`] is a [`CustomPass`] which draws the screenshot over each frame, then
+//! progressively distorts it away with one of the [`DissolveEffect`]s until only the saver is
+//! left. Taking the screenshot is up to the app; it is uploaded on the pass's first frame.
+//!
+//! The fade in from [`crate::fade_in`] runs after custom passes, so savers dissolving the screen
+//! should set [`WgpuOptions::fade_in`] to a zero duration, or the screenshot fades in from black
+//! before it dissolves.
+//!
+//! [`WgpuOptions::fade_in`]: crate::WgpuOptions::fade_in
+
+use crate::custom_pass::{CustomPass, CustomPassContext};
+use bevy_ecs::world::World;
+use bevy_render::shader::{Shader, ShaderStage};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Ways the screenshot can dissolve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DissolveEffect {
+    /// Columns of the screen slide down at their own speeds.
+    Melt,
+    /// The screen breaks into ever larger blocks, then fades out.
+    Pixelate,
+    /// A black hole in the middle of the screen swirls it in.
+    Swallow,
+}
+
+impl DissolveEffect {
+    /// Index of the effect in the shader.
+    fn index(self) -> f32 {
+        match self {
+            DissolveEffect::Melt => 0.0,
+            DissolveEffect::Pixelate => 1.0,
+            DissolveEffect::Swallow => 2.0,
+        }
+    }
+}
+
+impl FromStr for DissolveEffect {
+    type Err = String;
+
+    fn from_str(effect: &str) -> Result<Self, String> {
+        match effect {
+            "melt" => Ok(DissolveEffect::Melt),
+            "pixelate" => Ok(DissolveEffect::Pixelate),
+            "swallow" => Ok(DissolveEffect::Swallow),
+            _ => Err(format!(
+                "expected melt, pixelate or swallow, got {:?}",
+                effect
+            )),
+        }
+    }
+}
+
+/// Settings for dissolving the screen.
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenDissolve {
+    pub effect: DissolveEffect,
+    /// How long the screenshot is shown still before it starts to dissolve.
+    pub delay: Duration,
+    /// How long the screenshot takes to dissolve completely.
+    pub duration: Duration,
+}
+
+impl Default for ScreenDissolve {
+    fn default() -> Self {
+        Self {
+            effect: DissolveEffect::Melt,
+            delay: Duration::from_millis(500),
+            duration: Duration::from_secs(3),
+        }
+    }
+}
+
+/// A screenshot, as 8 bit sRGB RGBA pixels in rows from top to bottom.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) out vec2 v_Uv;
+
+void main() {
+    // One triangle covering the screen.
+    vec2 corner = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_Uv = vec2(corner.x, 1.0 - corner.y);
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec2 v_Uv;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform texture2D screenshot;
+layout(set = 0, binding = 2) uniform sampler linear_sampler;
+layout(set = 0, binding = 3) uniform Dissolve {
+    // Progress from 0 to 1 in x, the effect in y, and the width over the height in z.
+    vec4 params;
+    // Size of the window in pixels in xy.
+    vec4 size;
+};
+
+float hash(float n) {
+    return fract(sin(n * 12.9898) * 43758.5453);
+}
+
+vec3 screen(vec2 uv) {
+    return texture(sampler2D(screenshot, linear_sampler), uv).rgb;
+}
+
+// Each effect returns the screenshot's color, and in alpha how much of it covers the frame.
+
+vec4 melt(vec2 uv, float t) {
+    // Columns slide down at their own speeds, revealing the saver above them.
+    float column = floor(uv.x * 160.0);
+    float y = uv.y - t * t * (1.0 + hash(column)) * 1.5;
+    return y < 0.0 ? vec4(0.0) : vec4(screen(vec2(uv.x, y)), 1.0);
+}
+
+vec4 pixelate(vec2 uv, float t) {
+    // Blocks double in size every eighth of the way, and fade out over the last part.
+    vec2 cells = max(size.xy / exp2(t * 8.0), vec2(1.0));
+    vec2 center = (floor(uv * cells) + 0.5) / cells;
+    return vec4(screen(center), 1.0 - smoothstep(0.6, 1.0, t));
+}
+
+vec4 swallow(vec2 uv, float t) {
+    vec2 aspect = vec2(params.z, 1.0);
+    vec2 offset = (uv - 0.5) * aspect;
+    float dist = length(offset);
+    // The event horizon grows, then shrinks away once it has eaten everything.
+    if (dist < 0.05 * sin(3.14159265 * t)) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    // Each pixel shows a point further out than it did, twisted more near the middle, so the
+    // screen shrinks into the hole as it spins.
+    float pull = 1.0 / max(1.0 - t, 0.001);
+    float angle = atan(offset.y, offset.x) + t * 3.0 / (dist + 0.1);
+    vec2 sampled = vec2(cos(angle), sin(angle)) * dist * pull / aspect + 0.5;
+    if (any(lessThan(sampled, vec2(0.0))) || any(greaterThan(sampled, vec2(1.0)))) {
+        return vec4(0.0);
+    }
+    return vec4(screen(sampled), 1.0);
+}
+
+void main() {
+    vec4 frame = texture(sampler2D(source, linear_sampler), v_Uv);
+    float t = clamp(params.x, 0.0, 1.0);
+    vec4 covering;
+    if (params.y < 0.5) {
+        covering = melt(v_Uv, t);
+    } else if (params.y < 1.5) {
+        covering = pixelate(v_Uv, t);
+    } else {
+        covering = swallow(v_Uv, t);
+    }
+    o_Target = vec4(mix(frame.rgb, covering.rgb, covering.a), frame.a);
+}
+"#;
+
+/// GPU resources of the pass, created on its first frame.
+struct Resources {
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+    screenshot: wgpu::TextureView,
+    _texture: wgpu::Texture,
+}
+
+impl Resources {
+    fn new(context: &CustomPassContext, screenshot: &Screenshot) -> Self {
+        let device = context.device;
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("screen_dissolve"),
+            entries: &[
+                texture(0),
+                texture(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(32),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("screen_dissolve"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let vertex = shader_module(device, ShaderStage::Vertex, VERTEX_SHADER);
+        let fragment = shader_module(device, ShaderStage::Fragment, FRAGMENT_SHADER);
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("screen_dissolve"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment,
+                entry_point: "main",
+                targets: &[context.format.into()],
+            }),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("screen_dissolve"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screen_dissolve"),
+            size: 32,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let size = wgpu::Extent3d {
+            width: screenshot.width,
+            height: screenshot.height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("screenshot"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        context.queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &screenshot.pixels,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * screenshot.width,
+                rows_per_image: screenshot.height,
+            },
+            size,
+        );
+        Self {
+            layout,
+            pipeline,
+            sampler,
+            uniforms,
+            screenshot: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            _texture: texture,
+        }
+    }
+}
+
+/// Pass drawing the screenshot over the frame as it dissolves.
+pub struct ScreenDissolvePass {
+    settings: ScreenDissolve,
+    /// The screenshot until it is uploaded.
+    screenshot: Option<Screenshot>,
+    resources: Option<Resources>,
+    /// When the first frame was drawn.
+    start: Option<Instant>,
+}
+
+impl ScreenDissolvePass {
+    pub fn new(settings: ScreenDissolve, screenshot: Screenshot) -> Self {
+        Self {
+            settings,
+            screenshot: Some(screenshot),
+            resources: None,
+            start: None,
+        }
+    }
+
+    /// How far the screenshot has dissolved, from 0 to 1.
+    fn progress(&mut self) -> f32 {
+        let elapsed = self.start.get_or_insert_with(Instant::now).elapsed();
+        let dissolving = elapsed.saturating_sub(self.settings.delay);
+        if self.settings.duration == Duration::from_secs(0) {
+            return if dissolving > Duration::from_secs(0) {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        (dissolving.as_secs_f32() / self.settings.duration.as_secs_f32()).min(1.0)
+    }
+}
+
+impl CustomPass for ScreenDissolvePass {
+    fn run(&mut self, _world: &World, context: &mut CustomPassContext) {
+        if let Some(screenshot) = self.screenshot.take() {
+            self.resources = Some(Resources::new(context, &screenshot));
+        }
+        let progress = self.progress();
+        let resources = self
+            .resources
+            .as_ref()
+            .expect("resources are created on the first frame");
+        let (width, height) = (context.size.0 as f32, context.size.1 as f32);
+        let uniforms = [
+            progress,
+            self.settings.effect.index(),
+            width / height.max(1.0),
+            0.0,
+            width,
+            height,
+            0.0,
+            0.0,
+        ];
+        let bytes: Vec<u8> = uniforms
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        context.queue.write_buffer(&resources.uniforms, 0, &bytes);
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("screen_dissolve"),
+                layout: &resources.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(context.source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&resources.screenshot),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&resources.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: resources.uniforms.as_entire_binding(),
+                    },
+                ],
+            });
+        let mut pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("screen_dissolve"),
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: context.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+        pass.set_pipeline(&resources.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn shader_module(device: &wgpu::Device, stage: ShaderStage, source: &str) -> wgpu::ShaderModule {
+    let spirv = Shader::from_glsl(stage, source)
+        .get_spirv(None)
+        .expect("screen dissolve shaders should compile");
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("screen_dissolve"),
+        source: wgpu::ShaderSource::SpirV(spirv.into()),
+        flags: Default::default(),
+    })
+}
@@ -0,0 +1,382 @@
+//! Temporal anti-aliasing, a cheaper alternative to MSAA for scenes which fill the screen. When
+//! enabled through [`WgpuOptions::temporal_anti_aliasing`], the 3D camera's projection is jittered
+//! by a different sub-pixel offset each frame, and a pass blends each frame into a history of the
+//! previous ones. Bevy doesn't render motion vectors, so history is instead clamped to the colors
+//! around each pixel in the new frame, and the further it had to be clamped the less of it is
+//! kept. History left behind by moving objects is rejected that way instead of ghosting.
+//!
+//! TAA replaces MSAA rather than adding to it, so savers using it should insert
+//! `Msaa { samples: 1 }`.
+//!
+//! [`WgpuOptions::temporal_anti_aliasing`]: crate::WgpuOptions::temporal_anti_aliasing
+
+use bevy_ecs::{
+    entity::Entity,
+    system::{Local, Query, Res},
+};
+use bevy_math::{Mat4, Vec3};
+use bevy_render::{
+    camera::Camera,
+    render_graph::base,
+    shader::{Shader, ShaderStage},
+};
+use bevy_utils::HashMap;
+use bevy_window::Windows;
+
+/// Format the history is kept in, with enough precision that slow blending doesn't band.
+const HISTORY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Number of jitter offsets cycled through.
+const JITTER_PHASES: u32 = 8;
+
+/// Settings for temporal anti-aliasing.
+#[derive(Debug, Clone, Copy)]
+pub struct TemporalAntiAliasing {
+    /// How much of the history is kept each frame, between 0 and 1. Higher values smooth edges
+    /// more but take longer to settle after things move.
+    pub feedback: f32,
+}
+
+impl Default for TemporalAntiAliasing {
+    fn default() -> Self {
+        Self { feedback: 0.9 }
+    }
+}
+
+/// Gets element `index` of the Halton sequence with the given base, which is in `[0, 1)`.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Jitters the 3D camera's projection by a sub-pixel offset which changes every frame. Keeps the
+/// unjittered projection for each camera so that changes Bevy or the saver make to it are picked
+/// up, rather than jittering an already jittered projection.
+pub fn jitter_cameras_system(
+    mut frame: Local<u32>,
+    mut projections: Local<HashMap<Entity, (Mat4, Mat4)>>,
+    windows: Res<Windows>,
+    mut cameras: Query<(Entity, &mut Camera)>,
+) {
+    *frame = (*frame + 1) % JITTER_PHASES;
+    // Halton indices start at 1, since index 0 is always zero.
+    let jitter = (halton(*frame + 1, 2) - 0.5, halton(*frame + 1, 3) - 0.5);
+    for (entity, mut camera) in cameras.iter_mut() {
+        if camera.name.as_deref() != Some(base::camera::CAMERA_3D) {
+            continue;
+        }
+        let window = match windows.get(camera.window) {
+            Some(window) => window,
+            None => continue,
+        };
+        let (width, height) = (window.physical_width(), window.physical_height());
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let unjittered = match projections.get(&entity) {
+            Some(&(unjittered, jittered)) if jittered == camera.projection_matrix => unjittered,
+            _ => camera.projection_matrix,
+        };
+        // A clip space translation offsets the projected image by the same number of pixels at
+        // every depth, for both perspective and orthographic projections.
+        let offset = Vec3::new(
+            2.0 * jitter.0 / width as f32,
+            2.0 * jitter.1 / height as f32,
+            0.0,
+        );
+        let jittered = Mat4::from_translation(offset) * unjittered;
+        camera.projection_matrix = jittered;
+        projections.insert(entity, (unjittered, jittered));
+    }
+}
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) out vec2 v_Uv;
+
+void main() {
+    // One triangle covering the screen.
+    vec2 corner = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_Uv = vec2(corner.x, 1.0 - corner.y);
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec2 v_Uv;
+
+layout(location = 0) out vec4 o_Target;
+layout(location = 1) out vec4 o_History;
+
+layout(set = 0, binding = 0) uniform texture2D current;
+layout(set = 0, binding = 1) uniform texture2D history;
+layout(set = 0, binding = 2) uniform sampler linear_sampler;
+layout(set = 0, binding = 3) uniform Taa {
+    // Texel size in xy and the fraction of history to keep in z.
+    vec4 params;
+};
+
+void main() {
+    vec3 color = texture(sampler2D(current, linear_sampler), v_Uv).rgb;
+    vec3 low = color;
+    vec3 high = color;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 uv = v_Uv + vec2(x, y) * params.xy;
+            vec3 neighbor = texture(sampler2D(current, linear_sampler), uv).rgb;
+            low = min(low, neighbor);
+            high = max(high, neighbor);
+        }
+    }
+
+    vec3 previous = texture(sampler2D(history, linear_sampler), v_Uv).rgb;
+    vec3 clamped = clamp(previous, low, high);
+    // History far outside the neighborhood belongs to something which has since moved.
+    float rejection = length(previous - clamped) / (length(high - low) + 0.0001);
+    float feedback = params.z * (1.0 - clamp(rejection, 0.0, 1.0));
+    vec3 result = mix(color, clamped, feedback);
+    o_Target = vec4(result, 1.0);
+    o_History = vec4(result, 1.0);
+}
+"#;
+
+/// The frames blended so far. Each frame reads one texture and writes the other.
+struct History {
+    size: (u32, u32),
+    views: [wgpu::TextureView; 2],
+    _textures: [wgpu::Texture; 2],
+    current: usize,
+    /// Whether the history has been written yet.
+    valid: bool,
+}
+
+impl History {
+    fn new(device: &wgpu::Device, (width, height): (u32, u32)) -> Self {
+        let create = || {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("taa_history"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HISTORY_FORMAT,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::RENDER_ATTACHMENT,
+            })
+        };
+        let textures = [create(), create()];
+        let view =
+            |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            size: (width, height),
+            views: [view(&textures[0]), view(&textures[1])],
+            _textures: textures,
+            current: 0,
+            valid: false,
+        }
+    }
+}
+
+/// Pass blending each frame into the history.
+pub(crate) struct TemporalPass {
+    settings: TemporalAntiAliasing,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+    history: Option<History>,
+}
+
+impl TemporalPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: TemporalAntiAliasing,
+    ) -> Self {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("taa"),
+            entries: &[
+                texture(0),
+                texture(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(16),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("taa"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let vertex = shader_module(device, ShaderStage::Vertex, VERTEX_SHADER);
+        let fragment = shader_module(device, ShaderStage::Fragment, FRAGMENT_SHADER);
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("taa"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment,
+                entry_point: "main",
+                targets: &[format.into(), HISTORY_FORMAT.into()],
+            }),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("taa"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("taa"),
+            size: 16,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            settings,
+            layout,
+            pipeline,
+            sampler,
+            uniforms,
+            history: None,
+        }
+    }
+
+    /// Records blending `source`, which is `size` pixels, into the history and drawing the result
+    /// into `target`.
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        if self
+            .history
+            .as_ref()
+            .map_or(true, |history| history.size != size)
+        {
+            self.history = Some(History::new(device, size));
+        }
+        let history = self.history.as_mut().unwrap();
+        // Until there is a history, the first frame is passed straight through.
+        let feedback = if history.valid {
+            self.settings.feedback.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let params = [1.0 / size.0 as f32, 1.0 / size.1 as f32, feedback, 0.0];
+        let bytes: Vec<u8> = params
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        queue.write_buffer(&self.uniforms, 0, &bytes);
+        let (read, write) = (history.current, 1 - history.current);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("taa"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&history.views[read]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+            ],
+        });
+        let ops = wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            store: true,
+        };
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("taa"),
+                color_attachments: &[
+                    wgpu::RenderPassColorAttachmentDescriptor {
+                        attachment: target,
+                        resolve_target: None,
+                        ops,
+                    },
+                    wgpu::RenderPassColorAttachmentDescriptor {
+                        attachment: &history.views[write],
+                        resolve_target: None,
+                        ops,
+                    },
+                ],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        history.current = write;
+        history.valid = true;
+    }
+}
+
+fn shader_module(device: &wgpu::Device, stage: ShaderStage, source: &str) -> wgpu::ShaderModule {
+    let spirv = Shader::from_glsl(stage, source)
+        .get_spirv(None)
+        .expect("taa shaders should compile");
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("taa"),
+        source: wgpu::ShaderSource::SpirV(spirv.into()),
+        flags: Default::default(),
+    })
+}
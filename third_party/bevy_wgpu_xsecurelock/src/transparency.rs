@@ -0,0 +1,52 @@
+//! See-through savers. XSecurelock can show the saver over a dimmed screenshot of the desktop when
+//! a compositor is running, if the saver window has an ARGB visual. When enabled through
+//! [`WgpuOptions::transparency`] and the external window has such a visual, windows are rendered
+//! offscreen and a final pass writes the frame with premultiplied alpha, scaled by the
+//! [`Transparency::opacity`], which is what compositors expect of ARGB windows. Savers which clear
+//! to a color with alpha below one let the desktop show through their background.
+//!
+//! Windows without an ARGB visual, including the window used outside XSecurelock, stay opaque.
+//!
+//! [`WgpuOptions::transparency`]: crate::WgpuOptions::transparency
+
+/// Settings for see-through savers.
+#[derive(Debug, Clone, Copy)]
+pub struct Transparency {
+    /// Opacity of the whole saver, from 0 for invisible to 1 for only as see-through as the
+    /// frame's own alpha.
+    pub opacity: f32,
+}
+
+impl Default for Transparency {
+    fn default() -> Self {
+        Self { opacity: 1.0 }
+    }
+}
+
+/// How the last pass of a frame writes it to the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Composite {
+    /// Linear brightness to scale the color by.
+    pub brightness: f32,
+    /// Factor to scale the frame's alpha by.
+    pub opacity: f32,
+    /// Whether to premultiply the color by its alpha.
+    pub premultiply: bool,
+}
+
+impl Composite {
+    /// Writes the frame unchanged, ignoring its alpha.
+    pub const OPAQUE: Self = Self {
+        brightness: 1.0,
+        opacity: 1.0,
+        premultiply: false,
+    };
+
+    /// Dims the frame without compositing it.
+    pub fn opaque(brightness: f32) -> Self {
+        Self {
+            brightness,
+            ..Self::OPAQUE
+        }
+    }
+}
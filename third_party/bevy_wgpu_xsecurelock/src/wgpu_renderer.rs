@@ -1,28 +1,66 @@
 use crate::{
+    compute::{ComputeContext, ComputeJobs},
+    custom_pass::{CustomPassContext, CustomPasses},
+    dynamic_resolution::{RenderScale, Upscale},
+    fade_in::FadeState,
+    push_constants::MAX_PUSH_CONSTANT_SIZE,
     renderer::{WgpuRenderGraphExecutor, WgpuRenderResourceContext},
+    temporal_anti_aliasing::{TemporalAntiAliasing, TemporalPass},
+    transparency::{Composite, Transparency},
     wgpu_type_converter::WgpuInto,
-    WgpuBackend, WgpuOptions, WgpuPowerOptions,
+    ExternalXWindow, RenderInitError, WgpuBackend, WgpuOptions, WgpuPowerOptions, WindowVisibility,
 };
 use bevy_app::{Events, ManualEventReader};
 use bevy_ecs::world::{Mut, World};
 use bevy_render::{
     render_graph::{DependentNodeStager, RenderGraph, RenderGraphStager},
     renderer::RenderResourceContext,
+    texture::TextureFormat,
 };
+use bevy_utils::tracing::{info, warn};
 use bevy_window::{WindowCreated, WindowResized, Windows};
-use std::{ops::Deref, sync::Arc};
+use std::{
+    ops::Deref,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+/// How often to run the render graph while the window is fully obscured. The graph still runs
+/// occasionally so that the uploads render graph systems queue every frame get flushed instead of
+/// piling up.
+const OBSCURED_RENDER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frame pacing used while not presenting. Presenting normally limits the frame rate to vsync;
+/// without it the app would spin and any per-frame simulation would run far ahead.
+const OBSCURED_FRAME_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 pub struct WgpuRenderer {
     pub instance: wgpu::Instance,
+    /// The adapter the device was created on.
+    pub adapter_info: wgpu::AdapterInfo,
     pub device: Arc<wgpu::Device>,
     pub queue: wgpu::Queue,
     pub window_resized_event_reader: ManualEventReader<WindowResized>,
     pub window_created_event_reader: ManualEventReader<WindowCreated>,
     pub initialized: bool,
+    pub last_render: Option<Instant>,
+    pub last_frame: Option<Instant>,
+    render_scale: Option<RenderScale>,
+    /// Created on first use, once dynamic resolution is enabled.
+    upscale: Option<Upscale>,
+    temporal_anti_aliasing: Option<TemporalAntiAliasing>,
+    /// Created on first use, once temporal anti-aliasing is enabled.
+    temporal_pass: Option<TemporalPass>,
+    fade: FadeState,
+    transparency: Option<Transparency>,
+    /// Whether the window can be composited with alpha, which see-through savers need.
+    alpha_window: bool,
+    /// Created on first use, and only needed until the fade in finishes or while compositing.
+    fade_pass: Option<Upscale>,
 }
 
 impl WgpuRenderer {
-    pub async fn new(options: WgpuOptions) -> Self {
+    pub async fn new(options: WgpuOptions) -> Result<Self, RenderInitError> {
         let backend = match options.backend {
             WgpuBackend::Auto => wgpu::BackendBit::PRIMARY,
             WgpuBackend::Vulkan => wgpu::BackendBit::VULKAN,
@@ -44,33 +82,54 @@ impl WgpuRenderer {
                 compatible_surface: None,
             })
             .await
-            .expect("Unable to find a GPU! Make sure you have installed required drivers!");
+            .ok_or(RenderInitError::NoAdapter)?;
 
         #[cfg(feature = "trace")]
         let trace_path = Some(std::path::Path::new("wgpu_trace"));
         #[cfg(not(feature = "trace"))]
         let trace_path = None;
 
+        let mut features: wgpu::Features = options.features.wgpu_into();
+        let mut limits: wgpu::Limits = options.limits.wgpu_into();
+        // Push constants are cheap to enable, so savers get them wherever the adapter has them.
+        if adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && adapter.limits().max_push_constant_size >= MAX_PUSH_CONSTANT_SIZE
+        {
+            features |= wgpu::Features::PUSH_CONSTANTS;
+            limits.max_push_constant_size =
+                limits.max_push_constant_size.max(MAX_PUSH_CONSTANT_SIZE);
+        }
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: options.device_label.as_ref().map(|a| a.as_ref()),
-                    features: options.features.wgpu_into(),
-                    limits: options.limits.wgpu_into(),
+                    features,
+                    limits,
                 },
                 trace_path,
             )
-            .await
-            .unwrap();
+            .await?;
         let device = Arc::new(device);
-        WgpuRenderer {
+        Ok(WgpuRenderer {
             instance,
+            adapter_info: adapter.get_info(),
             device,
             queue,
             window_resized_event_reader: Default::default(),
             window_created_event_reader: Default::default(),
             initialized: false,
-        }
+            last_render: None,
+            last_frame: None,
+            render_scale: options.dynamic_resolution.map(RenderScale::new),
+            upscale: None,
+            temporal_anti_aliasing: options.temporal_anti_aliasing,
+            temporal_pass: None,
+            fade: FadeState::new(options.fade_in),
+            transparency: options.transparency,
+            alpha_window: false,
+            fade_pass: None,
+        })
     }
 
     pub fn handle_window_created_events(&mut self, world: &mut World) {
@@ -102,10 +161,213 @@ impl WgpuRenderer {
                 assert!(window.id() == external_window.window_id);
                 let surface = unsafe { self.instance.create_surface(&*external_window) };
                 render_resource_context.set_window_surface(window.id(), surface);
+                self.alpha_window = external_window.has_alpha();
+                match self.transparency {
+                    Some(transparency) if self.alpha_window => {
+                        info!("Compositing saver with opacity {}", transparency.opacity)
+                    }
+                    Some(_) => warn!("Saver window has no alpha channel, so it stays opaque"),
+                    None => {}
+                }
             }
         }
     }
 
+    /// Records and submits the registered [`ComputeJobs`], ahead of the render graph so that the
+    /// graph can sample anything they write.
+    pub fn run_compute(&mut self, world: &mut World) {
+        if world
+            .get_resource::<ComputeJobs>()
+            .map_or(true, |jobs| jobs.is_empty())
+        {
+            return;
+        }
+        let render_resource_context = world
+            .get_resource::<Box<dyn RenderResourceContext>>()
+            .unwrap()
+            .downcast_ref::<WgpuRenderResourceContext>()
+            .unwrap()
+            .clone();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("compute_jobs"),
+            });
+        world.resource_scope(|world, mut jobs: Mut<ComputeJobs>| {
+            let mut context = ComputeContext {
+                device: &self.device,
+                queue: &self.queue,
+                encoder: &mut encoder,
+                render_resource_context: &render_resource_context,
+            };
+            for job in jobs.iter_mut() {
+                job.run(world, &mut context);
+            }
+        });
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Whether the last pass composites frames with alpha, and at what opacity.
+    fn composite_opacity(&self) -> Option<f32> {
+        match self.transparency {
+            Some(transparency) if self.alpha_window => Some(transparency.opacity),
+            _ => None,
+        }
+    }
+
+    /// Records and submits the registered [`CustomPasses`] for each window rendered offscreen this
+    /// frame, finishing with the window's swap chain frame.
+    pub fn run_custom_passes(&mut self, world: &mut World) {
+        let render_resource_context = world
+            .get_resource::<Box<dyn RenderResourceContext>>()
+            .unwrap()
+            .downcast_ref::<WgpuRenderResourceContext>()
+            .unwrap()
+            .clone();
+        let resources = &render_resource_context.resources;
+        let windows: Vec<_> = resources
+            .offscreen_targets
+            .write()
+            .values_mut()
+            .filter_map(|targets| {
+                let frame = targets.frame.take()?;
+                Some((frame, targets.size, targets.viewport, targets.textures))
+            })
+            .collect();
+        if windows.is_empty() {
+            return;
+        }
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("custom_passes"),
+            });
+        let format = TextureFormat::default().wgpu_into();
+        let upscale = if self.render_scale.is_some() {
+            let device = &self.device;
+            Some(
+                &*self
+                    .upscale
+                    .get_or_insert_with(|| Upscale::new(device, format)),
+            )
+        } else {
+            None
+        };
+        let mut temporal_pass = match self.temporal_anti_aliasing {
+            Some(settings) => {
+                let device = &self.device;
+                Some(
+                    self.temporal_pass
+                        .get_or_insert_with(|| TemporalPass::new(device, format, settings)),
+                )
+            }
+            None => None,
+        };
+        // The fade goes last, so that nothing but black reaches the screen on the first frame.
+        // Compositing goes in the same pass, after everything else has seen the straight alpha.
+        let opacity = self.composite_opacity();
+        let fade = if self.fade.is_done() && opacity.is_none() {
+            None
+        } else {
+            let brightness = self.fade.next_frame();
+            let composite = match opacity {
+                Some(opacity) => Composite {
+                    brightness,
+                    opacity,
+                    premultiply: true,
+                },
+                None => Composite::opaque(brightness),
+            };
+            let device = &self.device;
+            let fade_pass = &*self
+                .fade_pass
+                .get_or_insert_with(|| Upscale::new(device, format));
+            Some((fade_pass, composite))
+        };
+        let (device, queue) = (&self.device, &self.queue);
+        world.resource_scope(|world, mut passes: Mut<CustomPasses>| {
+            // Built in passes go first, so custom passes always see the whole, finished frame.
+            let builtin_count = upscale.is_some() as usize + temporal_pass.is_some() as usize;
+            let step_count = builtin_count + passes.len() + fade.is_some() as usize;
+            for (frame, size, viewport, textures) in windows {
+                // Views are made fresh so that passes can still create textures while running.
+                let views: Vec<_> = {
+                    let textures_by_id = resources.textures.read();
+                    textures
+                        .iter()
+                        .map(|id| {
+                            textures_by_id[id].create_view(&wgpu::TextureViewDescriptor::default())
+                        })
+                        .collect()
+                };
+                let swap_chain_frames = resources.swap_chain_frames.read();
+                let frame = match swap_chain_frames.get(&frame) {
+                    Some(frame) => &frame.output.view,
+                    None => continue,
+                };
+                let target = |step: usize| {
+                    if step + 1 == step_count {
+                        frame
+                    } else {
+                        &views[(step + 1) % 2]
+                    }
+                };
+                let mut step = 0;
+                if let Some(upscale) = upscale {
+                    upscale.run(
+                        device,
+                        queue,
+                        &mut encoder,
+                        &views[step % 2],
+                        target(step),
+                        size,
+                        viewport,
+                        Composite::OPAQUE,
+                    );
+                    step += 1;
+                }
+                if let Some(temporal_pass) = &mut temporal_pass {
+                    temporal_pass.run(
+                        device,
+                        queue,
+                        &mut encoder,
+                        &views[step % 2],
+                        target(step),
+                        size,
+                    );
+                    step += 1;
+                }
+                for pass in passes.iter_mut() {
+                    let mut context = CustomPassContext {
+                        device,
+                        queue,
+                        encoder: &mut encoder,
+                        render_resource_context: &render_resource_context,
+                        source: &views[step % 2],
+                        target: target(step),
+                        format,
+                        size,
+                    };
+                    pass.run(world, &mut context);
+                    step += 1;
+                }
+                if let Some((fade_pass, composite)) = fade {
+                    fade_pass.run(
+                        device,
+                        queue,
+                        &mut encoder,
+                        &views[step % 2],
+                        target(step),
+                        size,
+                        size,
+                        composite,
+                    );
+                }
+            }
+        });
+        self.queue.submit(Some(encoder.finish()));
+    }
+
     pub fn run_graph(&mut self, world: &mut World) {
         world.resource_scope(|world, mut render_graph: Mut<RenderGraph>| {
             render_graph.prepare(world);
@@ -122,9 +384,99 @@ impl WgpuRenderer {
         })
     }
 
+    /// Recreates the surface of the [`ExternalXWindow`] if its drawable changed, such as when
+    /// XSecurelock reparents the window after startup, so frames aren't rendered to a stale
+    /// surface. The window's swap chain node recreates the swap chain from the new surface on the
+    /// resize event sent with the change.
+    fn handle_stale_surface(&mut self, world: &mut World) {
+        let world = world.cell();
+        let mut external_window = match world.get_resource_mut::<ExternalXWindow>() {
+            Some(external_window) => external_window,
+            None => return,
+        };
+        if external_window.is_destroyed() || !external_window.take_stale_surface() {
+            return;
+        }
+        let mut render_resource_context = world
+            .get_resource_mut::<Box<dyn RenderResourceContext>>()
+            .unwrap();
+        let render_resource_context = render_resource_context
+            .downcast_mut::<WgpuRenderResourceContext>()
+            .unwrap();
+        let id = external_window.window_id;
+        // The old swap chain belongs to the old surface.
+        render_resource_context
+            .resources
+            .window_swap_chains
+            .write()
+            .remove(&id);
+        let surface = unsafe { self.instance.create_surface(&*external_window) };
+        render_resource_context.set_window_surface(id, surface);
+        self.alpha_window = external_window.has_alpha();
+    }
+
+    /// Decides whether to run the render graph this frame based on the [`WindowVisibility`],
+    /// pacing frames when skipping. Never renders once the external window is destroyed.
+    fn should_render(&mut self, world: &World) -> bool {
+        if world
+            .get_resource::<ExternalXWindow>()
+            .map_or(false, |external_window| external_window.is_destroyed())
+        {
+            return false;
+        }
+        let now = Instant::now();
+        let last_frame = self.last_frame.replace(now);
+        let presenting = world
+            .get_resource::<WindowVisibility>()
+            .map_or(true, |visibility| visibility.should_present());
+        if presenting
+            || self
+                .last_render
+                .map_or(true, |last| now - last >= OBSCURED_RENDER_INTERVAL)
+        {
+            self.last_render = Some(now);
+            return true;
+        }
+        if let Some(elapsed) = last_frame.map(|last| now - last) {
+            if elapsed < OBSCURED_FRAME_INTERVAL {
+                std::thread::sleep(OBSCURED_FRAME_INTERVAL - elapsed);
+                self.last_frame = Some(Instant::now());
+            }
+        }
+        false
+    }
+
     pub fn update(&mut self, world: &mut World) {
         self.handle_window_created_events(world);
-        self.run_graph(world);
+        self.handle_stale_surface(world);
+        if self.should_render(world) {
+            self.run_compute(world);
+            // Custom passes need to read the finished frame, as do the built in passes for
+            // dynamic resolution, temporal anti-aliasing, fading in and compositing, so it is
+            // rendered offscreen for them.
+            let render_offscreen = self.render_scale.is_some()
+                || self.temporal_anti_aliasing.is_some()
+                || !self.fade.is_done()
+                || self.composite_opacity().is_some()
+                || world
+                    .get_resource::<CustomPasses>()
+                    .map_or(false, |passes| !passes.is_empty());
+            let render_scale = self.render_scale.as_mut().map(RenderScale::next_frame);
+            let resources = &world
+                .get_resource::<Box<dyn RenderResourceContext>>()
+                .unwrap()
+                .downcast_ref::<WgpuRenderResourceContext>()
+                .unwrap()
+                .resources;
+            resources
+                .render_offscreen
+                .store(render_offscreen, Ordering::Relaxed);
+            *resources.render_scale.write() = render_scale;
+            self.run_graph(world);
+            if render_offscreen {
+                self.run_custom_passes(world);
+            }
+        }
 
         let render_resource_context = world
             .get_resource::<Box<dyn RenderResourceContext>>()
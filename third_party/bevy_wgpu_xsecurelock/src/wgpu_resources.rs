@@ -9,7 +9,21 @@ use bevy_utils::HashMap;
 use bevy_window::WindowId;
 use crossbeam_channel::{Receiver, Sender, TryRecvError};
 use parking_lot::{RwLock, RwLockReadGuard};
-use std::sync::Arc;
+use std::sync::{atomic::AtomicBool, Arc};
+
+/// Offscreen color targets a window is rendered into while custom passes are registered.
+#[derive(Debug)]
+pub struct OffscreenTargets {
+    pub size: (u32, u32),
+    /// The top left part of the main color target Bevy renders into. Smaller than `size` while
+    /// dynamic resolution is scaling the frame down.
+    pub viewport: (u32, u32),
+    /// The main color target Bevy renders into, then a second texture for custom passes to
+    /// ping-pong with.
+    pub textures: [TextureId; 2],
+    /// The swap chain frame acquired for this frame, which the last custom pass draws into.
+    pub frame: Option<TextureId>,
+}
 
 #[derive(Debug, Default)]
 pub struct WgpuBindGroupInfo {
@@ -86,6 +100,11 @@ pub struct WgpuResources {
     pub window_surfaces: Arc<RwLock<HashMap<WindowId, wgpu::Surface>>>,
     pub window_swap_chains: Arc<RwLock<HashMap<WindowId, wgpu::SwapChain>>>,
     pub swap_chain_frames: Arc<RwLock<HashMap<TextureId, wgpu::SwapChainFrame>>>,
+    /// Whether windows are rendered offscreen rather than straight into their swap chains.
+    pub render_offscreen: Arc<AtomicBool>,
+    /// Fraction of the window size to render offscreen targets at, if not the whole window.
+    pub render_scale: Arc<RwLock<Option<f32>>>,
+    pub offscreen_targets: Arc<RwLock<HashMap<WindowId, OffscreenTargets>>>,
     pub buffers: Arc<RwLock<HashMap<BufferId, Arc<wgpu::Buffer>>>>,
     pub texture_views: Arc<RwLock<HashMap<TextureId, wgpu::TextureView>>>,
     pub textures: Arc<RwLock<HashMap<TextureId, wgpu::Texture>>>,
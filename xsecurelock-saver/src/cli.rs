@@ -0,0 +1,473 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The command line shared by every saver. Each saver parses its arguments with a [`Cli`] at the
+//! start of `main`, which gives it the standard flags and lets it add its own:
+//!
+//! * `--config FILE`: the saver's config file. Unless the saver reads the file itself, it holds
+//!   `NAME=value` lines setting the environment variables listed by `--help-config`. Blank lines
+//!   and lines starting with `#` are ignored.
+//! * `--preview`: runs in a normal window which closes on any key, even when started with
+//!   `XSCREENSAVER_WINDOW`.
+//! * `--seed N`: seeds the saver's random choices, from [`rng`], so a run can be repeated.
+//! * `--log-level LEVEL`: one of `error`, `warn`, `info`, `debug` or `trace`.
+//! * `--headless`: runs without a window and without rendering.
+//! * `--self-test`: checks the renderer, config and whatever else the saver needs, runs a handful
+//!   of frames offscreen, then prints a report and exits with a non-zero status if anything
+//!   failed. See [`crate::self_test`].
+//! * `--help-config`: prints the [`ConfigHelp`] and exits.
+//!
+//! XSecurelock starts savers without any arguments, so each flag works by setting an environment
+//! variable, which can also be set directly: `XSECURELOCK_SAVER_CONFIG`,
+//! `XSECURELOCK_SAVER_PREVIEW`, `XSECURELOCK_SAVER_SEED`, `RUST_LOG`,
+//! `XSECURELOCK_SAVER_HEADLESS` and `XSECURELOCK_SAVER_SELF_TEST`. Variables set in the config
+//! file take precedence over the environment the saver was started with, and flags take
+//! precedence over both.
+//!
+//! Savers with tools which run instead of the saver, such as database utilities, add them as
+//! subcommands with [`Cli::subcommand`].
+//!
+//! ```no_run
+//! use xsecurelock_saver::cli::{Arg, Cli};
+//! use xsecurelock_saver::config_help::ConfigHelp;
+//!
+//! let (_args, matches) = Cli::new(ConfigHelp::new("saver_example"))
+//!     .about("Example saver.")
+//!     .arg(Arg::with_name("fast").long("fast").help("Goes faster."))
+//!     .get_matches();
+//! let fast = matches.is_present("fast");
+//! ```
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{App, ErrorKind};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::config_help::{ConfigHelp, Setting};
+use crate::env::parse_var;
+
+pub use clap::{Arg, ArgMatches, SubCommand};
+
+const CONFIG_VAR: &str = "XSECURELOCK_SAVER_CONFIG";
+const PREVIEW_VAR: &str = "XSECURELOCK_SAVER_PREVIEW";
+const SEED_VAR: &str = "XSECURELOCK_SAVER_SEED";
+const LOG_VAR: &str = "RUST_LOG";
+const HEADLESS_VAR: &str = "XSECURELOCK_SAVER_HEADLESS";
+const SELF_TEST_VAR: &str = "XSECURELOCK_SAVER_SELF_TEST";
+const XSCREENSAVER_WINDOW: &str = "XSCREENSAVER_WINDOW";
+
+const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+/// Description of `--config` for savers which read it as environment variables.
+const ENV_CONFIG_HELP: &str =
+    "File of NAME=value lines setting the environment variables listed by --help-config.";
+
+/// The standard flags, as given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaverArgs {
+    /// Path of the saver's config file.
+    pub config: Option<PathBuf>,
+    /// Whether to run in a normal window which closes on any key.
+    pub preview: bool,
+    /// Seed for the saver's random choices.
+    pub seed: Option<u64>,
+    /// Most detailed level of logs to show.
+    pub log_level: Option<String>,
+    /// Whether to run without a window and without rendering.
+    pub headless: bool,
+    /// Whether to check the saver works, print a report and exit.
+    pub self_test: bool,
+}
+
+impl SaverArgs {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            config: matches.value_of_os("config").map(PathBuf::from),
+            preview: matches.is_present("preview"),
+            // The validator ensures the seed parses.
+            seed: matches.value_of("seed").map(|seed| seed.parse().unwrap()),
+            log_level: matches.value_of("log-level").map(str::to_string),
+            headless: matches.is_present("headless"),
+            self_test: matches.is_present("self-test"),
+        }
+    }
+
+    /// Sets the environment variables for the flags which were given, which is where the rest of
+    /// the saver reads them from.
+    pub fn apply(&self) {
+        if let Some(ref config) = self.config {
+            env::set_var(CONFIG_VAR, config);
+        }
+        if self.preview {
+            env::set_var(PREVIEW_VAR, "1");
+            env::remove_var(XSCREENSAVER_WINDOW);
+        }
+        if let Some(seed) = self.seed {
+            env::set_var(SEED_VAR, seed.to_string());
+        }
+        if let Some(ref level) = self.log_level {
+            env::set_var(LOG_VAR, level);
+        }
+        if self.headless {
+            env::set_var(HEADLESS_VAR, "1");
+        }
+        if self.self_test {
+            env::set_var(SELF_TEST_VAR, "1");
+        }
+    }
+}
+
+/// Argument parser for a saver, with the standard flags.
+pub struct Cli<'a, 'b> {
+    app: App<'a, 'b>,
+    help: ConfigHelp,
+    /// Description of `--config`, which is set when the saver reads the file itself.
+    own_config: Option<&'b str>,
+}
+
+impl<'a, 'b> Cli<'a, 'b> {
+    /// Parser for the saver `help` describes, printing `help` for `--help-config`.
+    pub fn new(help: ConfigHelp) -> Self {
+        Self {
+            app: App::new(help.saver().to_string()),
+            help,
+            own_config: None,
+        }
+    }
+
+    /// Sets the description of the saver shown by `--help`.
+    pub fn about(mut self, about: &'b str) -> Self {
+        self.app = self.app.about(about);
+        self
+    }
+
+    /// Adds one of the saver's own arguments.
+    pub fn arg(mut self, arg: Arg<'a, 'b>) -> Self {
+        self.app = self.app.arg(arg);
+        self
+    }
+
+    /// Adds a subcommand, for savers with tools which run instead of the saver. The standard flags
+    /// go before the subcommand, and are applied for it the same as for the saver.
+    pub fn subcommand(mut self, subcommand: App<'a, 'b>) -> Self {
+        self.app = self.app.subcommand(subcommand);
+        self
+    }
+
+    /// Leaves reading the config file to the saver, which finds it with [`config_path`], for
+    /// savers whose config isn't environment variables. `help` describes the file for `--help`.
+    pub fn own_config(mut self, help: &'b str) -> Self {
+        self.own_config = Some(help);
+        self
+    }
+
+    /// Parses the saver's arguments, exiting with a message if they are invalid or were `--help`,
+    /// `--version` or `--help-config`, and applies the standard flags. Returns the standard flags
+    /// along with the matches for the saver's own arguments.
+    pub fn get_matches(self) -> (SaverArgs, ArgMatches<'a>) {
+        self.get_matches_from(env::args_os())
+    }
+
+    /// Like [`Cli::get_matches`], but parsing the given arguments, the first of which is the name
+    /// of the binary.
+    pub fn get_matches_from<I, T>(self, args: I) -> (SaverArgs, ArgMatches<'a>)
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let Cli {
+            app,
+            help,
+            own_config,
+        } = self;
+        let matches = with_standard_args(app, own_config).get_matches_from(args);
+        if matches.is_present("help-config") {
+            print!("{}", help);
+            process::exit(0);
+        }
+        let args = SaverArgs::from_matches(&matches);
+        if let Some(ref config) = args.config {
+            env::set_var(CONFIG_VAR, config);
+        }
+        if own_config.is_none() {
+            if let Some(path) = config_path() {
+                if let Err(err) = load_env_file(&path) {
+                    let message = format!("Unable to load {}: {}", path.display(), err);
+                    clap::Error::with_description(&message, ErrorKind::Io).exit();
+                }
+            }
+        }
+        args.apply();
+        (args, matches)
+    }
+}
+
+/// Adds the standard flags to `app`, after the saver's own. `own_config` is the description of
+/// `--config` for savers which read it themselves.
+fn with_standard_args<'a, 'b>(app: App<'a, 'b>, own_config: Option<&'b str>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("config")
+            .long("config")
+            .value_name("FILE")
+            .help(own_config.unwrap_or(ENV_CONFIG_HELP)),
+    )
+    .arg(
+        Arg::with_name("preview")
+            .long("preview")
+            .help("Runs in a normal window which closes on any key."),
+    )
+    .arg(
+        Arg::with_name("seed")
+            .long("seed")
+            .value_name("N")
+            .validator(|seed| seed.parse::<u64>().map(drop).map_err(|err| err.to_string()))
+            .help("Seeds the saver's random choices, to repeat a run."),
+    )
+    .arg(
+        Arg::with_name("log-level")
+            .long("log-level")
+            .value_name("LEVEL")
+            .possible_values(LOG_LEVELS)
+            .help("Most detailed level of logs to show."),
+    )
+    .arg(
+        Arg::with_name("headless")
+            .long("headless")
+            .conflicts_with("preview")
+            .help("Runs without a window and without rendering."),
+    )
+    .arg(
+        Arg::with_name("self-test")
+            .long("self-test")
+            .conflicts_with_all(&["preview", "headless"])
+            .help(
+                "Checks the renderer, config and anything else the saver needs, runs a few \
+                 frames offscreen, prints a report and exits.",
+            ),
+    )
+    .arg(
+        Arg::with_name("help-config")
+            .long("help-config")
+            .help("Prints every setting with its type, default and description, and exits."),
+    )
+}
+
+/// The settings behind the standard flags, for `--help-config`.
+pub(crate) fn settings() -> Vec<Setting> {
+    vec![
+        Setting::new::<Path>(
+            CONFIG_VAR,
+            "The saver's config file. For savers configured by environment variables, it holds \
+             NAME=value lines setting them, which take precedence over the environment.",
+        )
+        .with_kind("path"),
+        Setting::new::<u64>(
+            SEED_VAR,
+            "Seed for the saver's random choices. Defaults to a different seed each run.",
+        ),
+    ]
+}
+
+/// Path of the config file, from `--config` or `XSECURELOCK_SAVER_CONFIG`.
+pub fn config_path() -> Option<PathBuf> {
+    env::var_os(CONFIG_VAR)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Name of the saver's binary, which identifies the saver in file names.
+pub fn saver_name() -> String {
+    env::args_os()
+        .next()
+        .as_ref()
+        .and_then(|arg| Path::new(arg).file_name())
+        .map_or_else(
+            || "saver".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+}
+
+/// Whether the saver was started with `--preview`.
+pub fn is_preview() -> bool {
+    is_set(PREVIEW_VAR)
+}
+
+/// Whether the saver was started with `--headless`.
+pub fn is_headless() -> bool {
+    is_set(HEADLESS_VAR)
+}
+
+/// Whether the saver was started with `--self-test`.
+pub fn is_self_test() -> bool {
+    is_set(SELF_TEST_VAR)
+}
+
+/// The seed from `--seed`, if one was given.
+pub fn seed() -> Option<u64> {
+    parse_var(SEED_VAR, |_| true)
+}
+
+/// A random number generator for the saver, seeded from `--seed` when one was given. Every call
+/// gives a generator with the same seed, so savers should create one and keep it.
+pub fn rng() -> StdRng {
+    match seed() {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Whether a boolean environment variable is set to anything but `0`, `false` or nothing.
+pub fn is_set(name: &str) -> bool {
+    match env::var(name) {
+        Ok(value) => !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false"),
+        // Set, but not to a unicode string, so not to `0` or `false` either.
+        Err(env::VarError::NotUnicode(_)) => true,
+        Err(env::VarError::NotPresent) => false,
+    }
+}
+
+/// Sets the environment variables in a config file.
+fn load_env_file(path: &Path) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    let vars =
+        parse_env_file(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    for (name, value) in vars {
+        env::set_var(name, value);
+    }
+    Ok(())
+}
+
+/// Parses `NAME=value` lines, skipping blank lines and comments. Values may be quoted, as in a
+/// shell script, and lines may start with `export`.
+fn parse_env_file(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = match line.find('=') {
+            Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+            None => return Err(format!("line {}: expected NAME=value", number + 1)),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid name {:?}", number + 1, name));
+        }
+        let value = match value.as_bytes() {
+            [b'"', .., b'"'] | [b'\'', .., b'\''] => &value[1..value.len() - 1],
+            _ => value,
+        };
+        vars.push((name.to_string(), value.to_string()));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(args: &[&str]) -> clap::Result<SaverArgs> {
+        let app =
+            App::new("saver_test").arg(Arg::with_name("speed").long("speed").takes_value(true));
+        let app = with_standard_args(app, None);
+        let matches = app.get_matches_from_safe(args)?;
+        Ok(SaverArgs::from_matches(&matches))
+    }
+
+    #[test]
+    fn parses_standard_flags() {
+        assert_eq!(matches(&["saver_test"]).unwrap(), SaverArgs::default());
+        assert_eq!(
+            matches(&[
+                "saver_test",
+                "--config",
+                "saver.conf",
+                "--seed",
+                "42",
+                "--log-level",
+                "debug",
+                "--speed",
+                "3",
+                "--headless",
+            ])
+            .unwrap(),
+            SaverArgs {
+                config: Some("saver.conf".into()),
+                preview: false,
+                seed: Some(42),
+                log_level: Some("debug".to_string()),
+                headless: true,
+                self_test: false,
+            }
+        );
+        assert!(matches(&["saver_test", "--preview"]).unwrap().preview);
+        assert!(matches(&["saver_test", "--self-test"]).unwrap().self_test);
+    }
+
+    #[test]
+    fn rejects_invalid_flags() {
+        assert!(matches(&["saver_test", "--seed", "-1"]).is_err());
+        assert!(matches(&["saver_test", "--log-level", "loud"]).is_err());
+        assert!(matches(&["saver_test", "--preview", "--headless"]).is_err());
+        assert!(matches(&["saver_test", "--headless", "--self-test"]).is_err());
+        assert!(matches(&["saver_test", "--unknown"]).is_err());
+    }
+
+    #[test]
+    fn zero_and_false_leave_flags_unset() {
+        let name = "XSECURELOCK_SAVER_TEST_IS_SET";
+        assert!(!is_set(name));
+        for (value, set) in &[("1", true), ("yes", true), ("0", false), ("FALSE", false)] {
+            env::set_var(name, value);
+            assert_eq!(is_set(name), *set, "{}", value);
+        }
+        env::set_var(name, "");
+        assert!(!is_set(name));
+        env::remove_var(name);
+    }
+
+    #[test]
+    fn parses_env_files() {
+        let text = "# Plasma settings\n\
+                    XSECURELOCK_SAVER_PLASMA_MODE=fire\n\
+                    \n\
+                    export XSECURELOCK_SAVER_GAMMA = 2.2\n\
+                    XSECURELOCK_SAVER_NIGHT_LIGHT_HOURS=\"21-7\"\n\
+                    EMPTY=\n";
+        assert_eq!(
+            parse_env_file(text).unwrap(),
+            vec![
+                (
+                    "XSECURELOCK_SAVER_PLASMA_MODE".to_string(),
+                    "fire".to_string()
+                ),
+                ("XSECURELOCK_SAVER_GAMMA".to_string(), "2.2".to_string()),
+                (
+                    "XSECURELOCK_SAVER_NIGHT_LIGHT_HOURS".to_string(),
+                    "21-7".to_string()
+                ),
+                ("EMPTY".to_string(), String::new()),
+            ]
+        );
+        assert!(parse_env_file("NO_VALUE\n").is_err());
+        assert!(parse_env_file("BAD NAME=1\n").is_err());
+    }
+}
@@ -0,0 +1,715 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A final-output color transform shared by the simple and engine savers. The transform applies a
+//! brightness multiplier, a gamma adjustment, and optionally a redshift-style night light which
+//! warms the color temperature of the output during a configured span of hours. It can also reduce
+//! the output to a low-color [`Palette`] with ordered dithering, for a retro look, or to cut the
+//! bandwidth of remote X sessions, where frames with fewer colors compress better.
+//!
+//! The transform is configured globally for all savers using environment variables:
+//!
+//! * `XSECURELOCK_SAVER_GAMMA`: gamma adjustment, where values above 1 brighten midtones.
+//!   Defaults to 1.
+//! * `XSECURELOCK_SAVER_BRIGHTNESS`: multiplier applied to the output color. Defaults to 1.
+//! * `XSECURELOCK_SAVER_NIGHT_LIGHT`: color temperature in Kelvin to use at night, e.g. `3400`.
+//!   The night light is disabled unless this is set.
+//! * `XSECURELOCK_SAVER_NIGHT_LIGHT_HOURS`: local time span when the night light is active, as
+//!   `start-end` with times given as `H` or `H:MM`, e.g. `21-7` or `20:30-6:45`. Defaults to
+//!   `21-7`.
+//! * `XSECURELOCK_SAVER_NIGHT_LIGHT_TRANSITION_MINUTES`: how long the night light takes to fade
+//!   in and out at either end of the span. Defaults to 30.
+//! * `XSECURELOCK_SAVER_OUTPUT_CALIBRATION`: per-monitor adjustments for mixed panels, as a
+//!   `;` separated list of `GEOMETRY:ADJUSTMENTS`. The geometry is given as `WxH+X+Y` like
+//!   `xrandr` prints it, and a saver uses the entry for the monitor containing the center of its
+//!   window. Adjustments are `,` separated and are `gamma=G`, a gamma adjustment applied on top
+//!   of the global one, and `white=R/G/B`, per-channel multipliers setting the color full white is
+//!   shown as. For example `2560x1440+0+0:gamma=1.1,white=1/0.96/0.9;1920x1080+2560+0:gamma=0.9`.
+//! * `XSECURELOCK_SAVER_PALETTE`: number of colors to reduce the output to, one of `8`, `64`,
+//!   `256`, `512`, `4096`, `32768` or `65536`. For example `256` keeps 3 bits each of red and green
+//!   and 2 of blue. The output keeps its full colors unless this is set.
+//! * `XSECURELOCK_SAVER_DITHER`: `true` or `false`, whether to dither the reduced palette with an
+//!   ordered (Bayer) pattern rather than rounding each pixel to the nearest color. Defaults to
+//!   `true`.
+//! * `XSECURELOCK_SAVER_FADE_IN_SECONDS`: how long savers take to fade in from black when they
+//!   start, from 0 to 60. The first frame is always black. Defaults to 1.
+//!
+//! Invalid values are logged and replaced with their defaults.
+
+use std::env;
+use std::time::Duration;
+
+use log::warn;
+
+use crate::config_help::Setting;
+use crate::env::parse_var;
+
+const GAMMA_VAR: &str = "XSECURELOCK_SAVER_GAMMA";
+const BRIGHTNESS_VAR: &str = "XSECURELOCK_SAVER_BRIGHTNESS";
+const NIGHT_LIGHT_VAR: &str = "XSECURELOCK_SAVER_NIGHT_LIGHT";
+const NIGHT_LIGHT_HOURS_VAR: &str = "XSECURELOCK_SAVER_NIGHT_LIGHT_HOURS";
+const NIGHT_LIGHT_TRANSITION_VAR: &str = "XSECURELOCK_SAVER_NIGHT_LIGHT_TRANSITION_MINUTES";
+const OUTPUT_CALIBRATION_VAR: &str = "XSECURELOCK_SAVER_OUTPUT_CALIBRATION";
+const PALETTE_VAR: &str = "XSECURELOCK_SAVER_PALETTE";
+const DITHER_VAR: &str = "XSECURELOCK_SAVER_DITHER";
+const FADE_IN_VAR: &str = "XSECURELOCK_SAVER_FADE_IN_SECONDS";
+
+const DEFAULT_FADE_IN_SECONDS: f32 = 1.0;
+
+/// Load how long savers fade in from black for from `XSECURELOCK_SAVER_FADE_IN_SECONDS`.
+pub fn fade_in_from_env() -> Duration {
+    let seconds = parse_var(FADE_IN_VAR, |seconds: &f32| (0.0..=60.0).contains(seconds));
+    Duration::from_secs_f32(seconds.unwrap_or(DEFAULT_FADE_IN_SECONDS))
+}
+
+/// Linear brightness to show a frame at, `elapsed` into a fade in lasting `duration`. Scaling
+/// linear light rather than display color keeps the fade from crushing dark colors.
+pub fn fade_in_brightness(elapsed: Duration, duration: Duration) -> f32 {
+    if elapsed >= duration {
+        return 1.0;
+    }
+    elapsed.as_secs_f32() / duration.as_secs_f32()
+}
+
+/// The settings of the color transform and fade in, for `--help-config`.
+pub fn settings() -> Vec<Setting> {
+    let defaults = ColorTransform::default();
+    vec![
+        Setting::new::<f32>(
+            GAMMA_VAR,
+            "Gamma adjustment, where values above 1 brighten midtones.",
+        )
+        .with_default(defaults.gamma),
+        Setting::new::<f32>(BRIGHTNESS_VAR, "Multiplier applied to the output color.")
+            .with_default(defaults.brightness),
+        Setting::new::<f32>(
+            NIGHT_LIGHT_VAR,
+            "Color temperature in Kelvin to use at night, e.g. 3400. The night light is disabled \
+             unless this is set.",
+        ),
+        Setting::new::<str>(
+            NIGHT_LIGHT_HOURS_VAR,
+            "Local time span when the night light is active, as start-end with times given as H \
+             or H:MM.",
+        )
+        .with_kind("hours")
+        .with_default(format!(
+            "{}-{}",
+            NightLight::DEFAULT_HOURS.0,
+            NightLight::DEFAULT_HOURS.1
+        )),
+        Setting::new::<f32>(
+            NIGHT_LIGHT_TRANSITION_VAR,
+            "How long the night light takes to fade in and out at either end of the span.",
+        )
+        .with_default(NightLight::DEFAULT_TRANSITION_MINUTES),
+        Setting::new::<str>(
+            OUTPUT_CALIBRATION_VAR,
+            "Per-monitor adjustments, as a ; separated list of WxH+X+Y:ADJUSTMENTS where the \
+             adjustments are gamma=G and white=R/G/B, separated by commas.",
+        )
+        .with_kind("calibrations"),
+        Setting::new::<u32>(
+            PALETTE_VAR,
+            "Number of colors to reduce the output to, one of 8, 64, 256, 512, 4096, 32768 or \
+             65536. The output keeps its full colors unless this is set.",
+        ),
+        Setting::new::<bool>(
+            DITHER_VAR,
+            "Whether to dither the reduced palette with an ordered pattern rather than rounding \
+             to the nearest color.",
+        )
+        .with_default(Palette::DEFAULT_DITHER),
+        Setting::new::<f32>(
+            FADE_IN_VAR,
+            "How long savers take to fade in from black when they start, from 0 to 60.",
+        )
+        .with_default(DEFAULT_FADE_IN_SECONDS),
+    ]
+}
+
+/// Transform applied to the final output color of a screensaver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTransform {
+    /// Gamma adjustment. Output color is raised to the power of `1 / gamma`.
+    pub gamma: f32,
+    /// Multiplier applied to the output color before gamma adjustment.
+    pub brightness: f32,
+    /// Per-channel multiplier applied to the output color along with `brightness`, calibrating
+    /// the white point of the monitor the saver is on.
+    pub white_point: [f32; 3],
+    /// Optional color temperature schedule.
+    pub night_light: Option<NightLight>,
+    /// Optional reduced palette, applied after everything else.
+    pub palette: Option<Palette>,
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 1.0,
+            white_point: [1.0; 3],
+            night_light: None,
+            palette: None,
+        }
+    }
+}
+
+impl ColorTransform {
+    /// Load the color transform from the `XSECURELOCK_SAVER_*` environment variables.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let night_light = parse_var(NIGHT_LIGHT_VAR, |kelvin: &f32| {
+            (1000.0..=40000.0).contains(kelvin)
+        })
+        .map(|temperature| {
+            let (start_hour, end_hour) = match env::var(NIGHT_LIGHT_HOURS_VAR) {
+                Ok(hours) => parse_hours(&hours).unwrap_or_else(|| {
+                    warn!(
+                        "Invalid {}: {:?}, using default",
+                        NIGHT_LIGHT_HOURS_VAR, hours
+                    );
+                    NightLight::DEFAULT_HOURS
+                }),
+                Err(_) => NightLight::DEFAULT_HOURS,
+            };
+            NightLight {
+                temperature,
+                start_hour,
+                end_hour,
+                transition_minutes: parse_var(NIGHT_LIGHT_TRANSITION_VAR, |minutes: &f32| {
+                    (0.0..=720.0).contains(minutes)
+                })
+                .unwrap_or(NightLight::DEFAULT_TRANSITION_MINUTES),
+            }
+        });
+        let palette = parse_var(PALETTE_VAR, |colors: &u32| {
+            Palette::with_colors(*colors, true).is_some()
+        })
+        .and_then(|colors| {
+            let dither = parse_var(DITHER_VAR, |_: &bool| true).unwrap_or(Palette::DEFAULT_DITHER);
+            Palette::with_colors(colors, dither)
+        });
+        Self {
+            gamma: parse_var(GAMMA_VAR, |gamma: &f32| *gamma > 0.0).unwrap_or(defaults.gamma),
+            brightness: parse_var(BRIGHTNESS_VAR, |brightness: &f32| *brightness >= 0.0)
+                .unwrap_or(defaults.brightness),
+            white_point: defaults.white_point,
+            night_light,
+            palette,
+        }
+    }
+
+    /// Applies the calibration from `XSECURELOCK_SAVER_OUTPUT_CALIBRATION` for the monitor
+    /// showing `window`, if there is one.
+    pub fn calibrate_for(self, window: OutputRect) -> Self {
+        let calibrations = match env::var(OUTPUT_CALIBRATION_VAR) {
+            Ok(calibrations) => calibrations,
+            Err(_) => return self,
+        };
+        let calibrations = parse_calibrations(&calibrations).unwrap_or_else(|| {
+            warn!(
+                "Invalid {}: {:?}, ignoring it",
+                OUTPUT_CALIBRATION_VAR, calibrations
+            );
+            vec![]
+        });
+        match calibrations
+            .iter()
+            .find(|(output, _)| output.contains_center_of(&window))
+        {
+            Some((_, calibration)) => self.calibrated(calibration),
+            None => self,
+        }
+    }
+
+    /// This transform with the calibration for an output applied on top.
+    pub fn calibrated(self, calibration: &OutputCalibration) -> Self {
+        let [r, g, b] = self.white_point;
+        let [cr, cg, cb] = calibration.white_point;
+        Self {
+            gamma: self.gamma * calibration.gamma,
+            white_point: [r * cr, g * cg, b * cb],
+            ..self
+        }
+    }
+
+    /// Returns true if this transform leaves colors unchanged at every hour, in which case savers
+    /// skip the post-processing pass entirely.
+    pub fn is_identity(&self) -> bool {
+        self.gamma == 1.0
+            && self.brightness == 1.0
+            && self.white_point == [1.0; 3]
+            && self.night_light.is_none()
+            && self.palette.is_none()
+    }
+
+    /// Per-channel multiplier to apply to display (sRGB-encoded) color at the given local hour,
+    /// combining brightness, the white point and the night light tint.
+    pub fn color_scale_at(&self, hour: f32) -> [f32; 3] {
+        let tint = match self.night_light {
+            Some(night_light) => {
+                let strength = night_light.strength_at(hour);
+                let full = temperature_tint(night_light.temperature);
+                [
+                    1.0 + (full[0] - 1.0) * strength,
+                    1.0 + (full[1] - 1.0) * strength,
+                    1.0 + (full[2] - 1.0) * strength,
+                ]
+            }
+            None => [1.0; 3],
+        };
+        let white = self.white_point;
+        [
+            tint[0] * white[0] * self.brightness,
+            tint[1] * white[1] * self.brightness,
+            tint[2] * white[2] * self.brightness,
+        ]
+    }
+
+    /// Per-channel multiplier to apply to display color right now.
+    pub fn current_color_scale(&self) -> [f32; 3] {
+        match self.night_light {
+            Some(_) => self.color_scale_at(local_hour()),
+            None => self.color_scale_at(0.0),
+        }
+    }
+}
+
+/// A reduced output palette, with a fixed number of levels for each channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Bits kept of red, green and blue.
+    pub bits: [u32; 3],
+    /// Whether to dither with a 4x4 Bayer matrix rather than round to the nearest level.
+    pub dither: bool,
+}
+
+impl Palette {
+    const DEFAULT_DITHER: bool = true;
+
+    /// Bits of red, green and blue for each supported number of colors.
+    const SIZES: &'static [(u32, [u32; 3])] = &[
+        (8, [1, 1, 1]),
+        (64, [2, 2, 2]),
+        (256, [3, 3, 2]),
+        (512, [3, 3, 3]),
+        (4096, [4, 4, 4]),
+        (32768, [5, 5, 5]),
+        (65536, [5, 6, 5]),
+    ];
+
+    /// The palette with the given number of colors, if it is one of the supported sizes.
+    pub fn with_colors(colors: u32, dither: bool) -> Option<Self> {
+        Self::SIZES
+            .iter()
+            .find(|(size, _)| *size == colors)
+            .map(|&(_, bits)| Self { bits, dither })
+    }
+
+    /// Highest level of each channel, which the shaders scale colors by before rounding.
+    pub fn max_levels(&self) -> [f32; 3] {
+        let [r, g, b] = self.bits;
+        [
+            ((1 << r) - 1) as f32,
+            ((1 << g) - 1) as f32,
+            ((1 << b) - 1) as f32,
+        ]
+    }
+
+    /// Reduces a display color in `[0, 1]` to the palette, as the shaders do for the pixel at
+    /// `x`, `y`.
+    pub fn quantize(&self, color: [f32; 3], x: u32, y: u32) -> [f32; 3] {
+        let threshold = if self.dither {
+            bayer_threshold(x, y)
+        } else {
+            0.5
+        };
+        let quantize = |value: f32, levels: f32| {
+            ((value * levels + threshold).floor() / levels).clamp(0.0, 1.0)
+        };
+        let [r, g, b] = color;
+        let [r_levels, g_levels, b_levels] = self.max_levels();
+        [
+            quantize(r, r_levels),
+            quantize(g, g_levels),
+            quantize(b, b_levels),
+        ]
+    }
+}
+
+/// Threshold in `(0, 1)` for ordered dithering at a pixel, from a 4x4 Bayer matrix. Built up from
+/// the 2x2 matrix `[[0, 2], [3, 1]]` the same way as the shaders, which can't index constant arrays
+/// in every GLSL version.
+fn bayer_threshold(x: u32, y: u32) -> f32 {
+    let bayer2 = |x: u32, y: u32| 2 * (x ^ y) + y;
+    let index = 4 * bayer2(x & 1, y & 1) + bayer2((x >> 1) & 1, (y >> 1) & 1);
+    (index as f32 + 0.5) / 16.0
+}
+
+/// A rectangle of the screen, in pixels from the top left of the root window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OutputRect {
+    /// Returns true if the center of `other` is inside this rectangle, which picks the monitor a
+    /// window is on even if it doesn't exactly cover it, as with a test window.
+    pub fn contains_center_of(&self, other: &OutputRect) -> bool {
+        let x = other.x as i64 + other.width as i64 / 2;
+        let y = other.y as i64 + other.height as i64 / 2;
+        (self.x as i64..self.x as i64 + self.width as i64).contains(&x)
+            && (self.y as i64..self.y as i64 + self.height as i64).contains(&y)
+    }
+}
+
+/// Adjustments for one monitor, applied on top of the global transform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputCalibration {
+    /// Gamma adjustment, multiplied with the global gamma.
+    pub gamma: f32,
+    /// Per-channel multiplier giving the color full white is shown as.
+    pub white_point: [f32; 3],
+}
+
+impl Default for OutputCalibration {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            white_point: [1.0; 3],
+        }
+    }
+}
+
+/// Schedule for warming the output color temperature at night.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NightLight {
+    /// Color temperature in Kelvin when the night light is fully on.
+    pub temperature: f32,
+    /// Local hour when the night light starts fading in, in `[0, 24)`.
+    pub start_hour: f32,
+    /// Local hour when the night light has finished fading out, in `[0, 24)`. May be earlier than
+    /// `start_hour`, in which case the span wraps past midnight.
+    pub end_hour: f32,
+    /// Minutes spent fading in after `start_hour` and fading out before `end_hour`.
+    pub transition_minutes: f32,
+}
+
+impl NightLight {
+    const DEFAULT_HOURS: (f32, f32) = (21.0, 7.0);
+    const DEFAULT_TRANSITION_MINUTES: f32 = 30.0;
+
+    /// How strongly the night light applies at the given local hour, from 0 (off) to 1 (fully on).
+    pub fn strength_at(&self, hour: f32) -> f32 {
+        let span = (self.end_hour - self.start_hour).rem_euclid(24.0);
+        let since_start = (hour - self.start_hour).rem_euclid(24.0);
+        if since_start >= span {
+            return 0.0;
+        }
+        let transition = self.transition_minutes / 60.0;
+        if transition <= 0.0 {
+            return 1.0;
+        }
+        let until_end = span - since_start;
+        (since_start.min(until_end) / transition).min(1.0)
+    }
+}
+
+/// Approximate display color of a black body at the given temperature, normalized so that
+/// daylight (about 6600K) is white. Uses Tanner Helland's curve fit.
+fn temperature_tint(kelvin: f32) -> [f32; 3] {
+    let t = kelvin / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let green = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.075514846)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+    [
+        red.clamp(0.0, 255.0) / 255.0,
+        green.clamp(0.0, 255.0) / 255.0,
+        blue.clamp(0.0, 255.0) / 255.0,
+    ]
+}
+
+/// Parse a span of hours like `21-7` or `20:30-6:45`.
+fn parse_hours(hours: &str) -> Option<(f32, f32)> {
+    let mut parts = hours.splitn(2, '-');
+    let start = parse_time(parts.next()?)?;
+    let end = parse_time(parts.next()?)?;
+    Some((start, end))
+}
+
+/// Parse a time of day like `7` or `6:45` into fractional hours.
+fn parse_time(time: &str) -> Option<f32> {
+    let mut parts = time.trim().splitn(2, ':');
+    let hour: u8 = parts.next()?.parse().ok()?;
+    let minute: u8 = match parts.next() {
+        Some(minute) => minute.parse().ok()?,
+        None => 0,
+    };
+    if hour < 24 && minute < 60 {
+        Some(hour as f32 + minute as f32 / 60.0)
+    } else {
+        None
+    }
+}
+
+/// Parse a list of output calibrations like `1920x1080+0+0:gamma=1.1,white=1/0.96/0.9;...`.
+fn parse_calibrations(calibrations: &str) -> Option<Vec<(OutputRect, OutputCalibration)>> {
+    calibrations
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(2, ':');
+            let output = parse_geometry(parts.next()?)?;
+            let mut calibration = OutputCalibration::default();
+            for adjustment in parts.next()?.split(',') {
+                let mut parts = adjustment.splitn(2, '=');
+                let name = parts.next()?.trim();
+                let value = parts.next()?.trim();
+                match name {
+                    "gamma" => {
+                        calibration.gamma = value.parse().ok().filter(|gamma| *gamma > 0.0)?;
+                    }
+                    "white" => {
+                        let channels = value
+                            .split('/')
+                            .map(|channel| channel.trim().parse().ok().filter(|c| *c >= 0.0))
+                            .collect::<Option<Vec<f32>>>()?;
+                        match channels[..] {
+                            [r, g, b] => calibration.white_point = [r, g, b],
+                            _ => return None,
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            Some((output, calibration))
+        })
+        .collect()
+}
+
+/// Parse an X geometry like `1920x1080+2560+0`. Offsets may also be negative, as in
+/// `1920x1080-1920+0`.
+fn parse_geometry(geometry: &str) -> Option<OutputRect> {
+    let geometry = geometry.trim();
+    let offsets = geometry.find(&['+', '-'][..])?;
+    let (size, offsets) = geometry.split_at(offsets);
+    let mut size = size.splitn(2, 'x');
+    let width = size.next()?.parse().ok()?;
+    let height = size.next()?.parse().ok()?;
+    let y_start = offsets[1..].find(&['+', '-'][..])? + 1;
+    let (x, y) = offsets.split_at(y_start);
+    Some(OutputRect {
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+        width,
+        height,
+    })
+}
+
+/// Current local time of day in fractional hours.
+fn local_hour() -> f32 {
+    // Safety: localtime_r only writes to the provided tm struct, which is fully initialized by
+    // zeroing since it contains only integers and a pointer.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return 0.0;
+        }
+        tm.tm_hour as f32 + tm.tm_min as f32 / 60.0 + tm.tm_sec as f32 / 3600.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn night_light_wraps_midnight() {
+        let night_light = NightLight {
+            temperature: 3400.0,
+            start_hour: 21.0,
+            end_hour: 7.0,
+            transition_minutes: 60.0,
+        };
+        assert_eq!(night_light.strength_at(12.0), 0.0);
+        assert_eq!(night_light.strength_at(21.0), 0.0);
+        assert!((night_light.strength_at(21.5) - 0.5).abs() < 1e-4);
+        assert_eq!(night_light.strength_at(1.0), 1.0);
+        assert!((night_light.strength_at(6.75) - 0.25).abs() < 1e-4);
+        assert_eq!(night_light.strength_at(7.0), 0.0);
+    }
+
+    #[test]
+    fn parses_hours() {
+        assert_eq!(parse_hours("21-7"), Some((21.0, 7.0)));
+        assert_eq!(parse_hours("20:30-6:45"), Some((20.5, 6.75)));
+        assert_eq!(parse_hours("25-7"), None);
+        assert_eq!(parse_hours("21"), None);
+    }
+
+    #[test]
+    fn parses_calibrations() {
+        let calibrations = parse_calibrations(
+            "2560x1440+0+0:gamma=1.1,white=1/0.96/0.9; 1920x1080-1920+0:gamma=0.9",
+        )
+        .unwrap();
+        assert_eq!(
+            calibrations,
+            vec![
+                (
+                    OutputRect {
+                        x: 0,
+                        y: 0,
+                        width: 2560,
+                        height: 1440,
+                    },
+                    OutputCalibration {
+                        gamma: 1.1,
+                        white_point: [1.0, 0.96, 0.9],
+                    }
+                ),
+                (
+                    OutputRect {
+                        x: -1920,
+                        y: 0,
+                        width: 1920,
+                        height: 1080,
+                    },
+                    OutputCalibration {
+                        gamma: 0.9,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(parse_calibrations(""), Some(vec![]));
+        assert_eq!(parse_calibrations("1920x1080+0+0:white=1/1"), None);
+        assert_eq!(parse_calibrations("1920x1080+0+0:tint=1"), None);
+        assert_eq!(parse_calibrations("1920x1080:gamma=1"), None);
+    }
+
+    #[test]
+    fn calibration_applies_on_top() {
+        let monitor = OutputRect {
+            x: 1920,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let window = OutputRect {
+            x: 2000,
+            y: 100,
+            width: 1200,
+            height: 900,
+        };
+        assert!(monitor.contains_center_of(&window));
+        assert!(!monitor.contains_center_of(&OutputRect { x: 0, ..window }));
+
+        let transform = ColorTransform {
+            gamma: 2.0,
+            brightness: 0.5,
+            ..Default::default()
+        }
+        .calibrated(&OutputCalibration {
+            gamma: 1.5,
+            white_point: [1.0, 0.5, 0.25],
+        });
+        assert!(!transform.is_identity());
+        assert_eq!(transform.gamma, 3.0);
+        assert_eq!(transform.color_scale_at(12.0), [0.5, 0.25, 0.125]);
+        assert!(ColorTransform::default()
+            .calibrated(&OutputCalibration::default())
+            .is_identity());
+    }
+
+    #[test]
+    fn palette_dithers_to_the_average_color() {
+        assert_eq!(Palette::with_colors(100, true), None);
+        let palette = Palette::with_colors(256, true).unwrap();
+        assert_eq!(palette.bits, [3, 3, 2]);
+        assert_eq!(palette.max_levels(), [7.0, 7.0, 3.0]);
+
+        let mut thresholds: Vec<f32> = (0..16).map(|i| bayer_threshold(i % 4, i / 4)).collect();
+        thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(thresholds[0], 0.5 / 16.0);
+        assert!(thresholds
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] == 1.0 / 16.0));
+        // The pattern repeats every 4 pixels.
+        assert_eq!(bayer_threshold(5, 6), bayer_threshold(1, 2));
+
+        let palette = Palette::with_colors(8, true).unwrap();
+        let color = [0.25, 0.5, 1.0];
+        let mut sum = [0.0; 3];
+        for y in 0..4 {
+            for x in 0..4 {
+                let quantized = palette.quantize(color, x, y);
+                assert!(quantized.iter().all(|c| *c == 0.0 || *c == 1.0));
+                for (sum, channel) in sum.iter_mut().zip(&quantized) {
+                    *sum += channel / 16.0;
+                }
+            }
+        }
+        assert_eq!(sum, color);
+        let rounded = Palette {
+            dither: false,
+            ..palette
+        };
+        assert_eq!(rounded.quantize([0.25, 0.5, 1.2], 3, 3), [0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn fade_in_ramps_to_full() {
+        let duration = Duration::from_secs(2);
+        assert_eq!(fade_in_brightness(Duration::from_secs(0), duration), 0.0);
+        assert_eq!(
+            fade_in_brightness(Duration::from_millis(500), duration),
+            0.25
+        );
+        assert_eq!(fade_in_brightness(Duration::from_secs(3), duration), 1.0);
+        assert_eq!(
+            fade_in_brightness(Duration::from_secs(0), Duration::from_secs(0)),
+            1.0
+        );
+    }
+
+    #[test]
+    fn warm_tint_drops_blue() {
+        let daylight = temperature_tint(6600.0);
+        assert!(daylight.iter().all(|c| *c > 0.95));
+        let warm = temperature_tint(3400.0);
+        assert_eq!(warm[0], 1.0);
+        assert!(warm[2] < warm[1] && warm[1] < 1.0);
+    }
+}
@@ -0,0 +1,344 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Help for the settings a saver reads, printed when the saver is run with `--help-config`.
+//!
+//! Each saver builds a [`ConfigHelp`] at the start of `main` listing its own settings, and passes it
+//! to the [`crate::cli::Cli`] parsing its arguments. The settings shared by every saver, such as the
+//! output color transform, are listed after the saver's own.
+//!
+//! Config structs describe their keys by implementing [`DescribeConfig`]. Each key is described
+//! along with a function getting it from the struct, so the type shown is always the type of the
+//! field.
+//!
+//! ```
+//! use xsecurelock_saver::config_help::{describe, ConfigDocs, DescribeConfig};
+//!
+//! struct CameraConfig {
+//!     view_dist: f32,
+//! }
+//!
+//! impl DescribeConfig for CameraConfig {
+//!     fn describe(docs: &mut ConfigDocs<Self>) {
+//!         docs.key("view_dist", |c| &c.view_dist, "How far the camera is from the origin.");
+//!     }
+//! }
+//!
+//! let settings = describe::<CameraConfig>();
+//! assert_eq!(settings[0].kind, "f32");
+//! ```
+
+use std::any;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Width help text is wrapped to.
+const WIDTH: usize = 100;
+
+/// One setting a saver reads, either a config key or an environment variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    /// Name of the environment variable, or dotted path of the config key.
+    pub key: String,
+    /// What kind of value the setting takes.
+    pub kind: String,
+    /// The value used when the setting isn't given, if there is one.
+    pub default: Option<String>,
+    /// What the setting does.
+    pub doc: String,
+}
+
+impl Setting {
+    /// A setting holding a `T`.
+    pub fn new<T: ?Sized>(key: impl Into<String>, doc: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            kind: short_type_name(any::type_name::<T>()),
+            default: None,
+            doc: doc.into(),
+        }
+    }
+
+    /// Replaces the kind of value, for settings whose type doesn't say what to write, such as
+    /// enums parsed from a handful of names.
+    pub fn with_kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = kind.into();
+        self
+    }
+
+    /// Sets the value used when the setting isn't given.
+    pub fn with_default(mut self, default: impl ToString) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+}
+
+/// Implemented by config structs to describe their keys.
+pub trait DescribeConfig: 'static {
+    /// Adds each of the struct's keys to `docs`.
+    fn describe(docs: &mut ConfigDocs<Self>);
+}
+
+/// Collects the settings of a [`DescribeConfig`] struct `S`.
+pub struct ConfigDocs<S: ?Sized> {
+    /// Path of the struct within the config, with a trailing `.` unless the struct is the root.
+    prefix: String,
+    settings: Vec<Setting>,
+    config: PhantomData<fn(&S)>,
+}
+
+impl<S: ?Sized> ConfigDocs<S> {
+    fn new(prefix: String) -> Self {
+        Self {
+            prefix,
+            settings: Vec::new(),
+            config: PhantomData,
+        }
+    }
+
+    /// Describes the key holding the field `field` gets.
+    pub fn key<T: ?Sized>(&mut self, key: &str, _field: fn(&S) -> &T, doc: &str) -> &mut Self {
+        let setting = Setting::new::<T>(format!("{}{}", self.prefix, key), doc);
+        self.settings.push(setting);
+        self
+    }
+
+    /// Describes the key holding the nested config struct `field` gets, followed by the nested
+    /// struct's own keys.
+    pub fn table<T: DescribeConfig>(
+        &mut self,
+        key: &str,
+        _field: fn(&S) -> &T,
+        doc: &str,
+    ) -> &mut Self {
+        let path = format!("{}{}", self.prefix, key);
+        let mut nested = ConfigDocs::new(format!("{}.", path));
+        T::describe(&mut nested);
+        self.settings.push(Setting::new::<T>(path, doc));
+        self.settings.extend(nested.settings);
+        self
+    }
+}
+
+/// All of the keys of `S`, including the keys of nested structs.
+pub fn describe<S: DescribeConfig>() -> Vec<Setting> {
+    let mut docs = ConfigDocs::new(String::new());
+    S::describe(&mut docs);
+    docs.settings
+}
+
+/// Help listing the settings of a saver, grouped into titled sections.
+#[derive(Debug, Clone)]
+pub struct ConfigHelp {
+    saver: String,
+    sections: Vec<(String, Vec<Setting>)>,
+    /// Sections listed after the saver's own, for settings shared with other savers.
+    shared: Vec<(String, Vec<Setting>)>,
+}
+
+impl ConfigHelp {
+    /// Help for the named saver, listing only the settings shared by every saver until sections
+    /// are added.
+    pub fn new(saver: impl Into<String>) -> Self {
+        Self {
+            saver: saver.into(),
+            sections: Vec::new(),
+            shared: vec![
+                ("General".to_string(), crate::cli::settings()),
+                ("Output color".to_string(), crate::color::settings()),
+                ("Priority".to_string(), crate::priority::settings()),
+                ("Time".to_string(), crate::time::settings()),
+            ],
+        }
+    }
+
+    /// Name of the saver.
+    pub fn saver(&self) -> &str {
+        &self.saver
+    }
+
+    /// Adds a section of the saver's own settings.
+    pub fn section(mut self, title: impl Into<String>, settings: Vec<Setting>) -> Self {
+        self.sections.push((title.into(), settings));
+        self
+    }
+
+    /// Adds a section of settings shared with other savers, listed after the saver's own.
+    #[cfg_attr(not(feature = "engine"), allow(dead_code))]
+    pub(crate) fn shared_section(
+        mut self,
+        title: impl Into<String>,
+        settings: Vec<Setting>,
+    ) -> Self {
+        self.shared.insert(0, (title.into(), settings));
+        self
+    }
+}
+
+impl fmt::Display for ConfigHelp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Settings for {}.", self.saver)?;
+        for (title, settings) in self.sections.iter().chain(&self.shared) {
+            writeln!(f)?;
+            writeln!(f, "{}:", title)?;
+            for setting in settings {
+                write!(f, "  {} <{}>", setting.key, setting.kind)?;
+                if let Some(ref default) = setting.default {
+                    write!(f, " (default: {})", default)?;
+                }
+                writeln!(f)?;
+                for line in wrap(&setting.doc, WIDTH - 6) {
+                    writeln!(f, "      {}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Strips the module paths from a type name, so `alloc::vec::Vec<std::path::PathBuf>` becomes
+/// `Vec<PathBuf>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    // Start of the path segment being copied.
+    let mut segment = 0;
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(segment);
+        } else {
+            short.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                segment = short.len();
+            }
+        }
+    }
+    short
+}
+
+/// Splits text into lines of at most `width` characters, breaking between words.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Inner {
+        depth: Option<u16>,
+    }
+
+    impl DescribeConfig for Inner {
+        fn describe(docs: &mut ConfigDocs<Self>) {
+            docs.key("depth", |c| &c.depth, "How deep.");
+        }
+    }
+
+    struct Outer {
+        name: String,
+        inner: Inner,
+        sizes: Vec<f32>,
+    }
+
+    impl DescribeConfig for Outer {
+        fn describe(docs: &mut ConfigDocs<Self>) {
+            docs.key("name", |c| &c.name, "The name.")
+                .table("inner", |c| &c.inner, "Nested settings.")
+                .key("sizes", |c| &c.sizes, "Some sizes.");
+        }
+    }
+
+    #[test]
+    fn describes_nested_keys() {
+        let keys: Vec<(String, String)> = describe::<Outer>()
+            .into_iter()
+            .map(|setting| (setting.key, setting.kind))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("name".to_string(), "String".to_string()),
+                ("inner".to_string(), "Inner".to_string()),
+                ("inner.depth".to_string(), "Option<u16>".to_string()),
+                ("sizes".to_string(), "Vec<f32>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shortens_type_names() {
+        assert_eq!(short_type_name("f32"), "f32");
+        assert_eq!(
+            short_type_name("alloc::vec::Vec<std::path::PathBuf>"),
+            "Vec<PathBuf>"
+        );
+        assert_eq!(
+            short_type_name("core::option::Option<(u8, my_crate::Thing)>"),
+            "Option<(u8, Thing)>"
+        );
+    }
+
+    #[test]
+    fn formats_sections() {
+        let help = ConfigHelp {
+            saver: "saver_test".to_string(),
+            sections: vec![(
+                "Test".to_string(),
+                vec![
+                    Setting::new::<u32>("XSECURELOCK_SAVER_TEST_COUNT", "How many.")
+                        .with_default(4),
+                    Setting::new::<String>("XSECURELOCK_SAVER_TEST_MODE", "Which mode.")
+                        .with_kind("a | b"),
+                ],
+            )],
+            shared: Vec::new(),
+        };
+        assert_eq!(
+            help.to_string(),
+            "Settings for saver_test.\n\
+             \n\
+             Test:\n  \
+               XSECURELOCK_SAVER_TEST_COUNT <u32> (default: 4)\n      \
+                 How many.\n  \
+               XSECURELOCK_SAVER_TEST_MODE <a | b>\n      \
+                 Which mode.\n"
+        );
+    }
+
+    #[test]
+    fn wraps_docs() {
+        assert_eq!(
+            wrap("one two three four", 9),
+            vec!["one two", "three", "four"]
+        );
+        assert!(wrap("", 9).is_empty());
+    }
+}
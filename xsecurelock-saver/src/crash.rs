@@ -0,0 +1,205 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Crash reports, written when a saver panics so there is something to attach to an issue even
+//! though nobody sees a saver's output under XSecurelock.
+//!
+//! Both [`crate::simple`] and [`crate::engine`] install the panic hook with [`install`] when the
+//! saver starts. On a panic it writes `crash-<saver>-<time>-<pid>.txt` to `xsecurelock-saver` in
+//! the XDG state directory, `$XDG_STATE_HOME` or `~/.local/state`, holding the panic message and
+//! location, a backtrace, a digest of the saver's config, and whatever details the saver has added
+//! to the [`CrashContext`], such as the GPU adapter or the scenario running. The config itself
+//! isn't included, only a digest of the `XSECURELOCK_SAVER_*` environment variables and the config
+//! file, which is enough to tell whether two reports came from the same config.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, PanicInfo};
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli;
+
+/// Prefix of the environment variables which count towards the config digest.
+const CONFIG_VAR_PREFIX: &str = "XSECURELOCK_SAVER_";
+
+/// Details to include in crash reports, keyed by what they describe. Cloning gives another handle
+/// to the same details. Engine savers get it as a resource.
+#[derive(Debug, Clone, Default)]
+pub struct CrashContext(Arc<Mutex<BTreeMap<String, String>>>);
+
+impl CrashContext {
+    /// Sets a detail, replacing any earlier one with the same key.
+    pub fn set(&self, key: impl Into<String>, value: impl Into<String>) {
+        let mut details = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        details.insert(key.into(), value.into());
+    }
+
+    /// The details set so far. Doesn't wait for a panicking thread which held the lock.
+    fn details(&self) -> BTreeMap<String, String> {
+        match self.0.try_lock() {
+            Ok(details) => details.clone(),
+            Err(std::sync::TryLockError::Poisoned(err)) => err.into_inner().clone(),
+            Err(std::sync::TryLockError::WouldBlock) => BTreeMap::new(),
+        }
+    }
+}
+
+/// Installs a panic hook which writes a crash report before running the previous hook, and
+/// returns the context it reports.
+pub fn install() -> CrashContext {
+    let context = CrashContext::default();
+    context.set("config digest", config_digest());
+    let hook_context = context.clone();
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        write_report(info, &hook_context);
+        previous(info);
+    }));
+    context
+}
+
+/// Directory crash reports are written to, if there is a state directory.
+pub fn report_dir() -> Option<PathBuf> {
+    Some(dirs::state_dir()?.join("xsecurelock-saver"))
+}
+
+/// Writes the report for a panic, logging where it went. Never panics itself, since a panic in a
+/// panic hook aborts.
+fn write_report(info: &PanicInfo, context: &CrashContext) {
+    let dir = match report_dir() {
+        Some(dir) => dir,
+        None => return,
+    };
+    let saver = cli::saver_name();
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => info
+            .payload()
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    };
+    let location = info.location().map_or_else(
+        || "unknown".to_string(),
+        |location| format!("{}:{}", location.file(), location.line()),
+    );
+    let thread = thread::current().name().unwrap_or("unnamed").to_string();
+    let report = format_report(
+        &saver,
+        time,
+        &[
+            ("panic", message),
+            ("location", location),
+            ("thread", thread),
+        ],
+        &context.details(),
+        &format!("{:?}", backtrace::Backtrace::new()),
+    );
+    let path = dir.join(format!("crash-{}-{}-{}.txt", saver, time, process::id()));
+    match fs::create_dir_all(&dir).and_then(|()| fs::write(&path, report)) {
+        Ok(()) => eprintln!("Wrote crash report to {}", path.display()),
+        Err(err) => eprintln!("Unable to write crash report {}: {}", path.display(), err),
+    }
+}
+
+/// Formats a crash report from the panic, the saver's context and a backtrace.
+fn format_report(
+    saver: &str,
+    time: u64,
+    panic: &[(&str, String)],
+    context: &BTreeMap<String, String>,
+    backtrace: &str,
+) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "Crash report for {}", saver);
+    let _ = writeln!(report, "time: {}", time);
+    for (key, value) in panic
+        .iter()
+        .map(|(key, value)| (*key, value))
+        .chain(context.iter().map(|(key, value)| (key.as_str(), value)))
+    {
+        let _ = writeln!(report, "{}: {}", key, value);
+    }
+    let _ = writeln!(report, "\nbacktrace:\n{}", backtrace);
+    report
+}
+
+/// Digest of the `XSECURELOCK_SAVER_*` environment variables and the config file's contents.
+fn config_digest() -> String {
+    let vars: BTreeMap<String, String> = env::vars()
+        .filter(|(name, _)| name.starts_with(CONFIG_VAR_PREFIX))
+        .collect();
+    let file = cli::config_path().and_then(|path| fs::read(path).ok());
+    format!("{:016x}", digest(&vars, file.as_deref()))
+}
+
+fn digest(vars: &BTreeMap<String, String>, file: Option<&[u8]>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    vars.hash(&mut hasher);
+    file.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_reports() {
+        let context = CrashContext::default();
+        context.set("adapter", "llvmpipe (Vulkan)");
+        context.set("scenario", "12");
+        context.set("adapter", "Intel (Vulkan)");
+        let report = format_report(
+            "saver_test",
+            1600000000,
+            &[("panic", "boom".to_string())],
+            &context.details(),
+            "0: main",
+        );
+        assert_eq!(
+            report,
+            "Crash report for saver_test\n\
+             time: 1600000000\n\
+             panic: boom\n\
+             adapter: Intel (Vulkan)\n\
+             scenario: 12\n\
+             \n\
+             backtrace:\n\
+             0: main\n"
+        );
+    }
+
+    #[test]
+    fn digest_covers_vars_and_file() {
+        let mut vars = BTreeMap::new();
+        vars.insert("XSECURELOCK_SAVER_GAMMA".to_string(), "2.2".to_string());
+        let base = digest(&vars, None);
+        assert_eq!(digest(&vars.clone(), None), base);
+        assert_ne!(digest(&vars, Some(b"MODE=fire")), base);
+        vars.insert("XSECURELOCK_SAVER_SEED".to_string(), "1".to_string());
+        assert_ne!(digest(&vars, None), base);
+    }
+}
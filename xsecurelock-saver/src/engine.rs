@@ -17,66 +17,311 @@
 //! engine to use the window provided by XSecurelock instead of `winit` when running inside of
 //! XSecurelock. Outside of XSecurelock, functions like `DefaultPlugins`. You can plug this into an
 //! [`App`] like pretty much any other plugin.
+//!
+//! See [`XSecurelockSaverPlugins`] for what else the plugins set up, and [`config_help`] for the
+//! settings they read from the environment.
 use std::env;
+use std::thread;
+use std::time::Duration;
 
-use bevy::app::{Events, ManualEventReader, PluginGroupBuilder};
-use bevy::asset::{AssetPlugin, AssetServerSettings};
+use bevy::app::{AppExit, Events, ManualEventReader, PluginGroupBuilder};
+use bevy::asset::AssetPlugin;
+use bevy::core::CorePlugin;
+use bevy::ecs::system::System;
+use bevy::log::LogPlugin;
 use bevy::prelude::*;
 use bevy::wgpu::WgpuPlugin;
 use bevy::window::{CreateWindow, WindowCreated, WindowPlugin};
 use bevy::winit::WinitPlugin;
-use bevy_wgpu_xsecurelock::ExternalXWindow;
+use bevy_wgpu_xsecurelock::dynamic_resolution::DynamicResolution;
+use bevy_wgpu_xsecurelock::fade_in::FadeIn;
+use bevy_wgpu_xsecurelock::transparency::Transparency;
+use bevy_wgpu_xsecurelock::{AdapterInfo, ExternalXWindow, WgpuOptions};
+
+use crate::cli;
+use crate::color::fade_in_from_env;
+use crate::config_help::{ConfigHelp, Setting};
+use crate::crash;
+use crate::engine::screen_capture::ScreenDissolve;
+use crate::engine::test_runner::SimulatedClock;
+use crate::env::parse_var;
+use crate::priority;
+
+pub use self::color_management::ColorManagementPlugin;
+pub use self::pause::SaverPaused;
+pub use self::scene_builder::SceneBuilder;
+pub use self::test_runner::{SaverSignal, TestRunner};
+pub use crate::crash::CrashContext;
+pub use crate::time::{PhysicsSubsteps, SaverTime};
+/// Reloads shaders from their source files when they change, only while running in a window.
+pub use bevy_wgpu_xsecurelock::hot_reload::ShaderHotReload;
+/// Whether the saver window is visible. Rendering is mostly skipped while it is fully obscured,
+/// but the app keeps updating.
+pub use bevy_wgpu_xsecurelock::WindowVisibility;
+
+pub mod assets;
+mod color_management;
+pub mod pause;
+mod scene_builder;
+pub mod screen_capture;
+mod self_test;
+mod test_runner;
 
 /// A Bevy plugin for making the bevy app work as an X-Securelock screenaver using SFML rendering.
+///
+/// Besides the window and renderer, the plugins:
+///
+/// * add the [`SaverTime`], [`PhysicsSubsteps`], [`SaverPaused`] and [`CrashContext`] resources,
+/// * apply the output color transform from [`crate::color`], see [`ColorManagementPlugin`],
+/// * configure dynamic resolution, fading in, transparency and the screen capture from the
+///   environment, unless the app set them in its `WgpuOptions` or resources first, and
+/// * lower the saver's priority as described in [`crate::priority`] before Bevy starts its task
+///   pools, so their threads run at the lowered priority too.
+///
+/// With `--preview`, the saver runs under winit and exits on any key. With `--headless` or
+/// `--self-test`, it runs without a window or a GPU, so the app updates but nothing is drawn; see
+/// [`crate::cli`] and [`crate::self_test`]. Tests can run an app the same way for a set number of
+/// frames with a [`TestRunner`].
 #[derive(Debug)]
 pub struct XSecurelockSaverPlugins;
 
 impl PluginGroup for XSecurelockSaverPlugins {
     fn build(&mut self, plugins: &mut PluginGroupBuilder) {
-        DefaultPlugins.build(plugins);
-        plugins
-            .disable::<WinitPlugin>()
-            .disable::<WgpuPlugin>()
-            .add_before::<AssetPlugin, _>(ConfigAssetsPlugin)
-            .add_before::<WindowPlugin, _>(ConfigWindowPlugin)
-            .add(bevy_wgpu_xsecurelock::WgpuPlugin)
-            .add(CreateWindowPlugin)
-            .add(RunnerPlugin);
+        let self_test = cli::is_self_test();
+        build_plugins(plugins, cli::is_headless() || self_test);
+        if self_test {
+            plugins.add(self_test::SelfTestPlugin);
+        }
     }
 }
 
+/// Adds the engine's plugins in place of Bevy's defaults. When `headless`, there is no window and
+/// the renderer runs without a GPU, as set by [`WgpuOptions::headless`].
+fn build_plugins(plugins: &mut PluginGroupBuilder, headless: bool) {
+    DefaultPlugins.build(plugins);
+    plugins
+        .disable::<WinitPlugin>()
+        .disable::<WgpuPlugin>()
+        .add_before::<LogPlugin, _>(CrashReportPlugin)
+        .add_before::<CorePlugin, _>(PriorityPlugin)
+        .add_before::<AssetPlugin, _>(assets::AssetSearchPathPlugin)
+        .add_before::<WindowPlugin, _>(ConfigWindowPlugin { headless })
+        .add(bevy_wgpu_xsecurelock::WgpuPlugin)
+        .add(screen_capture::ScreenDissolvePlugin)
+        .add_before::<bevy_wgpu_xsecurelock::WgpuPlugin, _>(ConfigRendererPlugin { headless })
+        .add(CreateWindowPlugin)
+        .add(ColorManagementPlugin)
+        .add(SaverTimePlugin)
+        .add(PreviewPlugin)
+        .add(RunnerPlugin { headless });
+}
+
 const XSCREENSAVER_WINDOW: &str = "XSCREENSAVER_WINDOW";
 
-/// Adds an aset server config when running as a screensaver. Sets the asset location to the
-/// compile-time env variable `INSTALLED_SAVER_ASSET_PATH` when `XSCREENSAVER_WINDOW` is set.
+/// Returns true if the saver is running inside of XSecurelock rather than in its own window.
+pub fn is_running_in_xsecurelock() -> bool {
+    env::var_os(XSCREENSAVER_WINDOW).is_some()
+}
+
+const MIN_RENDER_SCALE_VAR: &str = "XSECURELOCK_SAVER_MIN_RENDER_SCALE";
+const MAX_RENDER_SCALE_VAR: &str = "XSECURELOCK_SAVER_MAX_RENDER_SCALE";
+const OPACITY_VAR: &str = "XSECURELOCK_SAVER_OPACITY";
+const PHYSICS_SUBSTEPS_VAR: &str = "XSECURELOCK_SAVER_PHYSICS_SUBSTEPS";
+
+/// Configures dynamic resolution, fading in and transparency in the renderer's `WgpuOptions`, and
+/// the [`ScreenDissolve`], from the environment, unless the app already configured them. Also turns
+/// on `WgpuOptions::headless` when `headless`.
 #[derive(Debug)]
-struct ConfigAssetsPlugin;
+struct ConfigRendererPlugin {
+    headless: bool,
+}
 
-impl Plugin for ConfigAssetsPlugin {
+impl Plugin for ConfigRendererPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        const INSTALLED_ASSET_PATH: Option<&str> = option_env!("INSTALLED_SAVER_ASSET_PATH");
-        if let Some(path) = INSTALLED_ASSET_PATH {
-            if env::var_os(XSCREENSAVER_WINDOW).is_some() {
-                app.insert_resource(AssetServerSettings {
-                    asset_folder: path.to_string(),
-                });
+        let mut options = app
+            .world()
+            .get_resource::<WgpuOptions>()
+            .cloned()
+            .unwrap_or_default();
+        options.headless |= self.headless;
+        if options.dynamic_resolution.is_none() {
+            options.dynamic_resolution = dynamic_resolution_from_env();
+        }
+        let dissolve = app.world().get_resource::<ScreenDissolve>().is_some() || {
+            let dissolve = screen_capture::screen_dissolve_from_env();
+            let configured = dissolve.is_some();
+            if let Some(dissolve) = dissolve {
+                app.insert_resource(dissolve);
             }
+            configured
+        };
+        if options.fade_in.is_none() {
+            // A dissolving screen should appear as it was, not fade in from black.
+            options.fade_in = Some(FadeIn {
+                duration: if dissolve {
+                    Duration::from_secs(0)
+                } else {
+                    fade_in_from_env()
+                },
+            });
+        }
+        if options.transparency.is_none() {
+            options.transparency =
+                parse_var(OPACITY_VAR, |opacity: &f32| (0.0..=1.0).contains(opacity))
+                    .map(|opacity| Transparency { opacity });
+        }
+        app.insert_resource(options);
+    }
+}
+
+/// Help for the settings of a saver built on the engine, listing the engine's own settings after
+/// the saver's. See [`crate::config_help`].
+pub fn config_help(saver: impl Into<String>) -> ConfigHelp {
+    let defaults = DynamicResolution::default();
+    let settings = vec![
+        Setting::new::<f32>(
+            MIN_RENDER_SCALE_VAR,
+            "Smallest fraction of the window's width and height to render at, between 0 and 1. \
+             Setting either render scale turns on dynamic resolution.",
+        )
+        .with_default(defaults.min_scale),
+        Setting::new::<f32>(
+            MAX_RENDER_SCALE_VAR,
+            "Largest fraction of the window's width and height to render at, between 0 and 1.",
+        )
+        .with_default(defaults.max_scale),
+        Setting::new::<f32>(
+            OPACITY_VAR,
+            "Opacity of the saver from 0 to 1, letting the desktop show through when XSecurelock \
+             gives the saver a window with an alpha channel.",
+        ),
+        Setting::new::<u32>(
+            PHYSICS_SUBSTEPS_VAR,
+            "Integration steps to split each simulation step into, from 1 to 64. Used by \
+             saver_fluid and saver_genetic_orbits; other savers ignore this. More substeps are \
+             more accurate and take longer.",
+        )
+        .with_default(PhysicsSubsteps::default().0),
+    ];
+    ConfigHelp::new(saver)
+        .shared_section("Screen capture", screen_capture::settings())
+        .shared_section("Assets", assets::settings())
+        .shared_section("Engine", settings)
+}
+
+/// Reads dynamic resolution limits from the environment. Off unless either limit is set.
+fn dynamic_resolution_from_env() -> Option<DynamicResolution> {
+    let valid = |scale: &f32| *scale > 0.0 && *scale <= 1.0;
+    let min_scale = parse_var(MIN_RENDER_SCALE_VAR, valid);
+    let max_scale = parse_var(MAX_RENDER_SCALE_VAR, valid);
+    if min_scale.is_none() && max_scale.is_none() {
+        return None;
+    }
+    let defaults = DynamicResolution::default();
+    let max_scale = max_scale.unwrap_or(defaults.max_scale);
+    let min_scale = min_scale.unwrap_or(defaults.min_scale).min(max_scale);
+    info!(
+        "Using dynamic resolution between {} and {}",
+        min_scale, max_scale
+    );
+    Some(DynamicResolution {
+        min_scale,
+        max_scale,
+        ..defaults
+    })
+}
+
+/// Installs the panic hook which writes crash reports, and adds its [`CrashContext`] as a resource.
+/// Comes first so that panics while setting up the renderer are reported too.
+#[derive(Debug)]
+pub(crate) struct CrashReportPlugin;
+
+impl Plugin for CrashReportPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(crash::install())
+            .add_startup_system(note_adapter.system());
+    }
+}
+
+/// Notes the GPU adapter in crash reports, once the renderer has set one up.
+fn note_adapter(context: Res<CrashContext>, adapter: Option<Res<AdapterInfo>>) {
+    if let Some(adapter) = adapter {
+        context.set(
+            "adapter",
+            format!(
+                "{} ({:?}, {:?})",
+                adapter.name, adapter.backend, adapter.device_type
+            ),
+        );
+    }
+}
+
+/// Lowers the saver's priority as configured in the environment. Comes after logging is set up,
+/// and before the [`CorePlugin`] spawns the task pool threads, which inherit the priority.
+#[derive(Debug)]
+struct PriorityPlugin;
+
+impl Plugin for PriorityPlugin {
+    fn build(&self, _app: &mut AppBuilder) {
+        priority::lower_from_env();
+    }
+}
+
+/// Adds the [`SaverTime`] and [`PhysicsSubsteps`] resources from the environment if the app didn't
+/// add them, and updates the `SaverTime` at the start of each frame.
+#[derive(Debug)]
+struct SaverTimePlugin;
+
+impl Plugin for SaverTimePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if app.world().get_resource::<PhysicsSubsteps>().is_none() {
+            let substeps = parse_var(PHYSICS_SUBSTEPS_VAR, |substeps: &u32| {
+                (1..=64).contains(substeps)
+            });
+            app.insert_resource(substeps.map(PhysicsSubsteps).unwrap_or_default());
         }
+        if app.world().get_resource::<SaverTime>().is_none() {
+            app.insert_resource(SaverTime::from_env());
+        }
+        app.init_resource::<SaverPaused>()
+            .add_system_to_stage(CoreStage::First, update_saver_time.system());
+    }
+}
+
+fn update_saver_time(
+    mut time: ResMut<SaverTime>,
+    paused: Res<SaverPaused>,
+    clock: Option<ResMut<SimulatedClock>>,
+) {
+    time.set_paused(paused.is_paused());
+    match clock {
+        Some(mut clock) => time.update_with_instant(clock.tick()),
+        None => time.update(),
     }
 }
 
 #[derive(Debug)]
-struct ConfigWindowPlugin;
+struct ConfigWindowPlugin {
+    headless: bool,
+}
 
 impl Plugin for ConfigWindowPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        if self.headless {
+            info!("Running without a window");
+            return;
+        }
         // Get the ID of the window from the $XSCREENSAVER_WINDOW environment variable, and attach a ExternalXWindow if so.
         if let Ok(window_id_str) = env::var(XSCREENSAVER_WINDOW) {
             info!("Opening existing window");
             let handle = window_id_str.parse().expect("window id was not an integer");
-            let external_window = ExternalXWindow::new(handle);
+            let external_window = ExternalXWindow::new(handle)
+                .unwrap_or_else(|err| panic!("Unable to open the saver window: {}", err));
+            let window_descriptor = external_window
+                .bevy_window_descriptor()
+                .unwrap_or_else(|err| panic!("Unable to open the saver window: {}", err));
 
-            app.insert_resource(external_window.bevy_window_descriptor());
+            app.insert_resource(window_descriptor);
             app.insert_resource(external_window);
         } else {
             info!("Using winit");
@@ -152,11 +397,32 @@ impl Plugin for CreateWindowPlugin {
     }
 }
 
-struct RunnerPlugin;
+/// Exits on any key with `--preview`.
+#[derive(Debug)]
+struct PreviewPlugin;
+
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if cli::is_preview() {
+            app.add_system(exit_on_key.system());
+        }
+    }
+}
+
+fn exit_on_key(keys: Res<Input<KeyCode>>, mut app_exit_events: EventWriter<AppExit>) {
+    if keys.get_just_pressed().next().is_some() {
+        info!("Key pressed, exiting preview");
+        app_exit_events.send(AppExit);
+    }
+}
+
+struct RunnerPlugin {
+    headless: bool,
+}
 
 impl Plugin for RunnerPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        if app.world().get_resource::<ExternalXWindow>().is_some() {
+        if app.world().get_resource::<ExternalXWindow>().is_some() || self.headless {
             info!("Configuring XSecurelockRunner");
 
             app.set_runner(runner);
@@ -166,15 +432,53 @@ impl Plugin for RunnerPlugin {
     }
 }
 
+/// How often the runner checks for signals while paused.
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 fn runner(mut app: App) {
     let span = info_span!("XSecurelock Engine Runner");
     let _ = span.enter();
 
     info!("starting runner");
     sigint::init();
+    pause::init();
+    let mut app_exit_event_reader = ManualEventReader::<AppExit>::default();
+    // Run on its own while paused, so XSecurelock destroying or remapping the window isn't missed.
+    let mut window_events = bevy_wgpu_xsecurelock::external_window_event_system.system();
+    window_events.initialize(&mut app.world);
+    // Whether the frame after pausing has run, so the app can stop updating.
+    let mut paused_frame_run = false;
     while !sigint::received_sigint() {
+        if let Some(paused) = pause::take_request() {
+            pause::set_paused(&mut app.world, paused);
+        }
+        let paused = pause::is_paused(&app.world);
+        if paused && paused_frame_run {
+            window_events.run((), &mut app.world);
+            window_events.apply_buffers(&mut app.world);
+            if app_exited(&app, &mut app_exit_event_reader) {
+                info!("Runner done (AppExit while paused)");
+                return;
+            }
+            thread::sleep(PAUSED_POLL_INTERVAL);
+            continue;
+        }
+        paused_frame_run = paused;
         trace!("Doing one loop");
         app.update();
+        if app_exited(&app, &mut app_exit_event_reader) {
+            info!("Runner done (AppExit)");
+            return;
+        }
     }
     info!("Runner done (SIGINT)");
 }
+
+/// Whether the app has sent an [`AppExit`] event since the reader last checked.
+fn app_exited(app: &App, app_exit_event_reader: &mut ManualEventReader<AppExit>) -> bool {
+    app.world
+        .get_resource::<Events<AppExit>>()
+        .map_or(false, |app_exit_events| {
+            app_exit_event_reader.iter(app_exit_events).next().is_some()
+        })
+}
@@ -0,0 +1,361 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Finds the saver's assets on a search path, so a saver still runs when it is installed somewhere
+//! unexpected or some of its assets are missing. Each asset is loaded from the first of these which
+//! has it, and the log says which one that was:
+//!
+//! 1. The directories in `XSECURELOCK_SAVER_ASSET_PATH`, separated by `:`.
+//! 2. Outside of XSecurelock, Bevy's usual asset folder, so savers run from a checkout use the
+//!    checkout's assets.
+//! 3. `xsecurelock-saver/<saver>` in the XDG data directories, `$XDG_DATA_HOME` (or
+//!    `~/.local/share`) and then each of `$XDG_DATA_DIRS` (or `/usr/local/share:/usr/share`), where
+//!    `<saver>` is the name of the saver's binary.
+//! 4. The directory set by `INSTALLED_SAVER_ASSET_PATH` when the saver was compiled.
+//! 5. Under XSecurelock, Bevy's usual asset folder.
+//! 6. The [`EmbeddedAssets`] compiled into the saver.
+//!
+//! Fonts which aren't found anywhere are replaced with a fallback font embedded in this crate, so
+//! text still shows up rather than disappearing. Other missing assets fail to load as usual.
+//!
+//! Assets aren't reloaded when they change on disk, even if the saver asks the `AssetServer` to
+//! watch for changes.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::asset::{AssetIo, AssetIoError, AssetServerSettings};
+use bevy::prelude::*;
+use bevy::tasks::IoTaskPool;
+use bevy::utils::BoxedFuture;
+
+use crate::cli;
+use crate::config_help::Setting;
+use crate::engine::is_running_in_xsecurelock;
+
+const ASSET_PATH_VAR: &str = "XSECURELOCK_SAVER_ASSET_PATH";
+
+/// Font used in place of any font which can't be found.
+const FALLBACK_FONT: &[u8] = include_bytes!("../../assets/fonts/FiraMono-Regular.ttf");
+
+/// Extensions of the assets which are replaced with [`FALLBACK_FONT`] when missing.
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf"];
+
+/// Assets compiled into the saver, used when they aren't found in any directory on the search
+/// path. Insert this as a resource before adding the plugins:
+///
+/// ```ignore
+/// App::build()
+///     .insert_resource(EmbeddedAssets::default().with(
+///         "fonts/FiraSans-Book.ttf",
+///         include_bytes!("../assets/fonts/FiraSans-Book.ttf"),
+///     ))
+///     .add_plugins(XSecurelockSaverPlugins)
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedAssets(HashMap<PathBuf, &'static [u8]>);
+
+impl EmbeddedAssets {
+    /// Embeds an asset, to be loaded from `path` relative to the asset folder.
+    pub fn with(mut self, path: impl Into<PathBuf>, bytes: &'static [u8]) -> Self {
+        self.0.insert(path.into(), bytes);
+        self
+    }
+}
+
+/// A directory on the search path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AssetDir {
+    /// Where the directory came from, for the log.
+    source: &'static str,
+    path: PathBuf,
+}
+
+/// Where an asset was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Resolved<'a> {
+    /// In a directory on the search path, at the given full path.
+    File(&'a AssetDir, PathBuf),
+    /// In the [`EmbeddedAssets`].
+    Embedded(&'static [u8]),
+    /// Nowhere, but it is a font, so the fallback font stands in.
+    FallbackFont,
+}
+
+/// Loads assets from the first directory on the search path which has them, then the
+/// [`EmbeddedAssets`], then for fonts the fallback font.
+#[derive(Debug)]
+pub struct SearchPathAssetIo {
+    dirs: Vec<AssetDir>,
+    embedded: EmbeddedAssets,
+}
+
+impl SearchPathAssetIo {
+    /// Finds the asset at `path` relative to the asset folder.
+    fn resolve(&self, path: &Path) -> Option<Resolved<'_>> {
+        self.dirs
+            .iter()
+            .map(|dir| (dir, dir.path.join(path)))
+            .find(|(_, full_path)| full_path.is_file())
+            .map(|(dir, full_path)| Resolved::File(dir, full_path))
+            .or_else(|| self.embedded.0.get(path).copied().map(Resolved::Embedded))
+            .or_else(|| {
+                path.extension()
+                    .and_then(OsStr::to_str)
+                    .filter(|ext| FONT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+                    .map(|_| Resolved::FallbackFont)
+            })
+    }
+}
+
+impl AssetIo for SearchPathAssetIo {
+    fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        Box::pin(async move {
+            match self.resolve(path) {
+                Some(Resolved::File(dir, full_path)) => {
+                    let bytes = fs::read(&full_path)?;
+                    info!(
+                        "Loaded {} from {} ({})",
+                        path.display(),
+                        dir.source,
+                        dir.path.display()
+                    );
+                    Ok(bytes)
+                }
+                Some(Resolved::Embedded(bytes)) => {
+                    info!("Loaded {} from the saver's embedded assets", path.display());
+                    Ok(bytes.to_vec())
+                }
+                Some(Resolved::FallbackFont) => {
+                    warn!(
+                        "Font {} not found on the asset search path, using the fallback font",
+                        path.display()
+                    );
+                    Ok(FALLBACK_FONT.to_vec())
+                }
+                None => {
+                    warn!(
+                        "Asset {} not found on the asset search path",
+                        path.display()
+                    );
+                    Err(AssetIoError::NotFound(path.to_owned()))
+                }
+            }
+        })
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        let dir = self
+            .dirs
+            .iter()
+            .find(|dir| dir.path.join(path).is_dir())
+            .ok_or_else(|| AssetIoError::NotFound(path.to_owned()))?;
+        let root = dir.path.clone();
+        let entries = fs::read_dir(root.join(path))?;
+        Ok(Box::new(entries.filter_map(move |entry| {
+            Some(entry.ok()?.path().strip_prefix(&root).ok()?.to_owned())
+        })))
+    }
+
+    fn is_directory(&self, path: &Path) -> bool {
+        self.dirs.iter().any(|dir| dir.path.join(path).is_dir())
+    }
+
+    fn watch_path_for_changes(&self, _path: &Path) -> Result<(), AssetIoError> {
+        Ok(())
+    }
+
+    fn watch_for_changes(&self) -> Result<(), AssetIoError> {
+        Ok(())
+    }
+}
+
+/// The asset search path setting, for `--help-config`.
+pub(crate) fn settings() -> Vec<Setting> {
+    vec![Setting::new::<str>(
+        ASSET_PATH_VAR,
+        "Directories to look for the saver's assets in, separated by ':', before the XDG data \
+         directories and the installed asset path.",
+    )
+    .with_kind("paths")]
+}
+
+/// Replaces Bevy's asset server with one loading from the search path. Honors the asset folder in
+/// an `AssetServerSettings` resource, and leaves alone an asset server the saver inserted itself.
+#[derive(Debug)]
+pub(crate) struct AssetSearchPathPlugin;
+
+impl Plugin for AssetSearchPathPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if app.world().get_resource::<AssetServer>().is_some() {
+            return;
+        }
+        let folder = app
+            .world()
+            .get_resource::<AssetServerSettings>()
+            .map_or_else(
+                || "assets".to_string(),
+                |settings| settings.asset_folder.clone(),
+            );
+        let dirs = search_path(
+            env::var_os(ASSET_PATH_VAR),
+            default_root().join(folder),
+            is_running_in_xsecurelock(),
+        );
+        info!(
+            "Asset search path: {}",
+            dirs.iter()
+                .filter(|dir| dir.path.is_dir())
+                .map(|dir| dir.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let embedded = app
+            .world()
+            .get_resource::<EmbeddedAssets>()
+            .cloned()
+            .unwrap_or_default();
+        let task_pool = app
+            .world()
+            .get_resource::<IoTaskPool>()
+            .expect("IoTaskPool is added by the CorePlugin")
+            .0
+            .clone();
+        app.insert_resource(AssetServer::new(
+            SearchPathAssetIo { dirs, embedded },
+            task_pool,
+        ));
+    }
+}
+
+/// Directory Bevy resolves a relative asset folder against: the package directory when run
+/// through cargo, otherwise the directory holding the binary.
+fn default_root() -> PathBuf {
+    env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|| Some(env::current_exe().ok()?.parent()?.to_owned()))
+        .unwrap_or_default()
+}
+
+/// The directories on the search path, in the order they are searched. `asset_path` is the value
+/// of `XSECURELOCK_SAVER_ASSET_PATH` and `default` Bevy's usual asset folder.
+fn search_path(
+    asset_path: Option<OsString>,
+    default: PathBuf,
+    in_xsecurelock: bool,
+) -> Vec<AssetDir> {
+    let mut dirs: Vec<_> = asset_path
+        .iter()
+        .flat_map(env::split_paths)
+        .filter(|path| !path.as_os_str().is_empty())
+        .map(|path| AssetDir {
+            source: ASSET_PATH_VAR,
+            path,
+        })
+        .collect();
+    let default = AssetDir {
+        source: "the default asset folder",
+        path: default,
+    };
+    if !in_xsecurelock {
+        dirs.push(default.clone());
+    }
+    let saver_dir = Path::new("xsecurelock-saver").join(cli::saver_name());
+    dirs.extend(dirs::data_dir().map(|dir| AssetDir {
+        source: "$XDG_DATA_HOME",
+        path: dir.join(&saver_dir),
+    }));
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    dirs.extend(env::split_paths(&data_dirs).map(|dir| AssetDir {
+        source: "$XDG_DATA_DIRS",
+        path: dir.join(&saver_dir),
+    }));
+    dirs.extend(
+        option_env!("INSTALLED_SAVER_ASSET_PATH").map(|path| AssetDir {
+            source: "the installed asset path",
+            path: path.into(),
+        }),
+    );
+    if in_xsecurelock {
+        dirs.push(default);
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn searches_in_order() {
+        let root = env::temp_dir().join(format!("xsecurelock-saver-assets-{}", process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(first.join("fonts")).unwrap();
+        fs::create_dir_all(second.join("fonts")).unwrap();
+        fs::write(first.join("fonts/a.ttf"), "first").unwrap();
+        fs::write(second.join("fonts/a.ttf"), "second").unwrap();
+        fs::write(second.join("fonts/b.ttf"), "second").unwrap();
+
+        let io = SearchPathAssetIo {
+            dirs: search_path(
+                Some(env::join_paths(&[&first, &second]).unwrap()),
+                root.join("assets"),
+                false,
+            ),
+            embedded: EmbeddedAssets::default().with("sky.png", b"embedded"),
+        };
+        assert_eq!(io.dirs[0].path, first);
+        assert_eq!(io.dirs[1].path, second);
+        assert_eq!(io.dirs[2].path, root.join("assets"));
+        let file = |path: &str| match io.resolve(Path::new(path)) {
+            Some(Resolved::File(_, full_path)) => full_path,
+            other => panic!("{} resolved to {:?}", path, other),
+        };
+        assert_eq!(file("fonts/a.ttf"), first.join("fonts/a.ttf"));
+        assert_eq!(file("fonts/b.ttf"), second.join("fonts/b.ttf"));
+        assert_eq!(
+            io.resolve(Path::new("sky.png")),
+            Some(Resolved::Embedded(&b"embedded"[..]))
+        );
+        assert_eq!(
+            io.resolve(Path::new("fonts/missing.OTF")),
+            Some(Resolved::FallbackFont)
+        );
+        assert_eq!(io.resolve(Path::new("missing.png")), None);
+
+        let mut listed: Vec<_> = io.read_directory(Path::new("fonts")).unwrap().collect();
+        listed.sort();
+        assert_eq!(listed, vec![PathBuf::from("fonts/a.ttf")]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn default_folder_comes_last_in_xsecurelock() {
+        let dirs = search_path(None, PathBuf::from("/saver/assets"), true);
+        assert_eq!(dirs.last().unwrap().path, Path::new("/saver/assets"));
+        assert!(dirs.iter().all(|dir| dir.source != ASSET_PATH_VAR));
+        let dirs = search_path(None, PathBuf::from("/saver/assets"), false);
+        assert_eq!(dirs[0].path, Path::new("/saver/assets"));
+    }
+}
@@ -0,0 +1,477 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Post pass applying the global [`ColorTransform`] to the final output of Bevy savers. When the
+//! transform is not the identity, every render graph node which would normally draw to the primary
+//! swap chain is rewired to draw to an intermediate window-sized texture instead, and a final pass
+//! copies that texture to the swap chain through the color transform shader. The same pass reduces
+//! the output to the transform's [`Palette`](crate::color::Palette), if it has one.
+
+use bevy::app::{Events, ManualEventReader};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::{
+    camera::ActiveCameras,
+    pass::{LoadOp, Operations, PassDescriptor, TextureAttachment},
+    pipeline::{CullMode, PipelineDescriptor, RenderPipeline},
+    render_graph::{
+        base, AssetRenderResourcesNode, CameraNode, Edge, Node, PassNode, RenderGraph,
+        ResourceSlotInfo, ResourceSlots, WindowSwapChainNode, WindowTextureNode,
+    },
+    renderer::{
+        RenderContext, RenderResourceContext, RenderResourceId, RenderResourceType,
+        RenderResources, SamplerId, TextureId,
+    },
+    shader::{ShaderStage, ShaderStages},
+    texture::{
+        Extent3d, SamplerDescriptor, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureUsage, SAMPLER_ASSET_INDEX, TEXTURE_ASSET_INDEX,
+    },
+};
+use bevy::window::{WindowCreated, WindowResized};
+use bevy_wgpu_xsecurelock::ExternalXWindow;
+use std::borrow::Cow;
+
+use crate::color::{ColorTransform, OutputRect};
+
+/// Render graph node producing the intermediate frame texture.
+const FRAME_TEXTURE_NODE: &str = "color_management_frame_texture";
+/// Render graph node binding the color transform material.
+const MATERIAL_NODE: &str = "color_management_material";
+/// Render graph node for the camera which draws the output quad.
+const CAMERA_NODE: &str = "color_management_camera";
+/// Render graph node for the final pass onto the swap chain.
+const PASS_NODE: &str = "color_management_pass";
+/// Name of the camera which draws the output quad.
+const CAMERA: &str = "ColorManagementCamera";
+
+const FRAME_TEXTURE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Texture::TYPE_UUID, 8829137706215416213);
+const MATERIAL_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(ColorManagementMaterial::TYPE_UUID, 3167207612940357561);
+const PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 12290358519405823377);
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in vec2 Vertex_Uv;
+
+layout(location = 0) out vec2 v_Uv;
+
+void main() {
+    v_Uv = Vertex_Uv;
+    gl_Position = vec4(Vertex_Position.xy, 0.0, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+
+layout(location = 0) in vec2 v_Uv;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 0, binding = 0) uniform ColorManagementMaterial_color_scale {
+    vec4 color_scale;
+};
+layout(set = 0, binding = 1) uniform ColorManagementMaterial_palette {
+    vec4 palette;
+};
+layout(set = 0, binding = 2) uniform texture2D ColorManagementMaterial_frame;
+layout(set = 0, binding = 3) uniform sampler ColorManagementMaterial_frame_sampler;
+
+// Ordered dithering threshold for a pixel from a 4x4 Bayer matrix, as in color::bayer_threshold.
+float bayer_threshold(vec2 pixel) {
+    vec2 p = mod(floor(pixel), 4.0);
+    vec2 low = mod(p, 2.0);
+    vec2 high = floor(p / 2.0);
+    float index = 4.0 * (2.0 * abs(low.x - low.y) + low.y) + 2.0 * abs(high.x - high.y) + high.y;
+    return (index + 0.5) / 16.0;
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055,
+        step(0.0031308, color));
+}
+
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
+
+void main() {
+    vec4 color = texture(
+        sampler2D(ColorManagementMaterial_frame, ColorManagementMaterial_frame_sampler), v_Uv);
+    vec3 transformed = pow(max(color.rgb * color_scale.rgb, vec3(0.0)), vec3(color_scale.w));
+    if (palette.x > 0.0) {
+        // Quantize the sRGB values the swap chain stores, so every pixel is exactly one of the
+        // palette's colors.
+        float threshold = palette.w > 0.0 ? bayer_threshold(gl_FragCoord.xy) : 0.5;
+        vec3 levels = floor(linear_to_srgb(transformed) * palette.rgb + threshold);
+        transformed = srgb_to_linear(clamp(levels / palette.rgb, 0.0, 1.0));
+    }
+    o_Target = vec4(transformed, 1.0);
+}
+"#;
+
+/// Plugin which applies the [`ColorTransform`] to the final output. Uses the `ColorTransform`
+/// resource if the app provides one, otherwise loads it from the environment, calibrated for the
+/// monitor the XSecurelock window is on. Does nothing if the transform is the identity.
+#[derive(Debug)]
+pub struct ColorManagementPlugin;
+
+impl Plugin for ColorManagementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let transform = match app.world().get_resource::<ColorTransform>() {
+            Some(transform) => *transform,
+            None => {
+                let mut transform = ColorTransform::from_env();
+                let window = app.world().get_resource::<ExternalXWindow>();
+                match window.map(|window| window.screen_rect()) {
+                    Some(Ok((x, y, width, height))) => {
+                        transform = transform.calibrate_for(OutputRect {
+                            x,
+                            y,
+                            width,
+                            height,
+                        });
+                    }
+                    Some(Err(err)) => warn!("Unable to find the saver's monitor: {}", err),
+                    None => {}
+                }
+                app.insert_resource(transform);
+                transform
+            }
+        };
+        if transform.is_identity() {
+            return;
+        }
+        info!("Applying output color transform {:?}", transform);
+
+        app.add_asset::<ColorManagementMaterial>();
+        let world = app.world_mut();
+        let color_scale = color_scale(&transform);
+        world
+            .get_resource_mut::<Assets<ColorManagementMaterial>>()
+            .unwrap()
+            .set_untracked(
+                MATERIAL_HANDLE,
+                ColorManagementMaterial {
+                    color_scale,
+                    palette: palette(&transform),
+                    frame: FRAME_TEXTURE_HANDLE.typed(),
+                },
+            );
+        let pipeline = {
+            let mut shaders = world.get_resource_mut::<Assets<Shader>>().unwrap();
+            let mut pipeline = PipelineDescriptor::default_config(ShaderStages {
+                vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+                fragment: Some(
+                    shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER)),
+                ),
+            });
+            pipeline.depth_stencil = None;
+            pipeline.primitive.cull_mode = CullMode::None;
+            pipeline
+        };
+        world
+            .get_resource_mut::<Assets<PipelineDescriptor>>()
+            .unwrap()
+            .set_untracked(PIPELINE_HANDLE, pipeline);
+
+        app.add_startup_system_to_stage(
+            StartupStage::PostStartup,
+            install_color_management.exclusive_system(),
+        )
+        .add_system(update_color_scale.system());
+    }
+}
+
+/// Marker for entities drawn by the color management pass.
+#[derive(Debug, Default)]
+struct ColorManagementPass;
+
+/// Material for the output quad, sampling the intermediate frame texture.
+#[derive(Debug, RenderResources, TypeUuid)]
+#[uuid = "6b2f8e55-0d0c-4b8f-9a4e-2f6f58c1d3a7"]
+struct ColorManagementMaterial {
+    /// Linear color multiplier in `rgb` and inverse gamma in `w`.
+    color_scale: Vec4,
+    /// Highest level of each channel of the palette in `rgb`, or zero for full color, and 1 in `w`
+    /// to dither.
+    palette: Vec4,
+    frame: Handle<Texture>,
+}
+
+/// Computes the shader color scale from the transform's current display color scale.
+fn color_scale(transform: &ColorTransform) -> Vec4 {
+    // The frame texture and swap chain are both sRGB, so the shader works in linear color. The
+    // transform's scale is specified for display color, so linearize it with the usual 2.2 power
+    // approximation. Gamma is a pure power, so it is the same in either space.
+    let [r, g, b] = transform.current_color_scale();
+    Vec4::new(r.powf(2.2), g.powf(2.2), b.powf(2.2), 1.0 / transform.gamma)
+}
+
+/// Computes the shader palette from the transform's palette.
+fn palette(transform: &ColorTransform) -> Vec4 {
+    match transform.palette {
+        Some(palette) => {
+            let [r, g, b] = palette.max_levels();
+            Vec4::new(r, g, b, if palette.dither { 1.0 } else { 0.0 })
+        }
+        None => Vec4::ZERO,
+    }
+}
+
+/// Keeps the color scale in sync with the night light schedule.
+fn update_color_scale(
+    transform: Res<ColorTransform>,
+    mut materials: ResMut<Assets<ColorManagementMaterial>>,
+) {
+    let color_scale = color_scale(&transform);
+    let handle = MATERIAL_HANDLE.typed();
+    // Only touch the material when the scale changes to avoid re-uploading it every frame.
+    if materials.get(&handle).map(|material| material.color_scale) != Some(color_scale) {
+        if let Some(material) = materials.get_mut(&handle) {
+            material.color_scale = color_scale;
+        }
+    }
+}
+
+/// Rewires the render graph to draw through the color management pass and spawns the camera and
+/// quad it draws. Runs after startup so it sees the nodes added by every other plugin.
+fn install_color_management(world: &mut World) {
+    let samples = world.get_resource::<Msaa>().unwrap().samples;
+    world
+        .get_resource_mut::<ActiveCameras>()
+        .unwrap()
+        .add(CAMERA);
+
+    let quad = world
+        .get_resource_mut::<Assets<Mesh>>()
+        .unwrap()
+        .add(Mesh::from(shape::Quad::new(Vec2::new(2.0, 2.0))));
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.camera.name = Some(CAMERA.to_string());
+    world.spawn().insert_bundle(camera);
+    world.spawn().insert_bundle((
+        quad,
+        MATERIAL_HANDLE.typed::<ColorManagementMaterial>(),
+        RenderPipelines::from_pipelines(vec![RenderPipeline::new(PIPELINE_HANDLE.typed())]),
+        Draw::default(),
+        Visible::default(),
+        Transform::default(),
+        GlobalTransform::default(),
+        ColorManagementPass,
+    ));
+
+    let mut graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    let swap_chain = graph.get_node_id(base::node::PRIMARY_SWAP_CHAIN).unwrap();
+    let swap_chain_edges: Vec<Edge> = graph
+        .get_node_state(swap_chain)
+        .unwrap()
+        .edges
+        .output_edges
+        .iter()
+        .filter(|edge| matches!(edge, Edge::SlotEdge { .. }))
+        .cloned()
+        .collect();
+
+    graph.add_node(FRAME_TEXTURE_NODE, FrameTextureNode::default());
+    graph.add_system_node(
+        MATERIAL_NODE,
+        AssetRenderResourcesNode::<ColorManagementMaterial>::new(true),
+    );
+    graph.add_system_node(CAMERA_NODE, CameraNode::new(CAMERA));
+    let mut pass = PassNode::<&ColorManagementPass>::new(PassDescriptor {
+        color_attachments: vec![Msaa { samples }.color_attachment_descriptor(
+            TextureAttachment::Input("color_attachment".to_string()),
+            TextureAttachment::Input("color_resolve_target".to_string()),
+            Operations {
+                load: LoadOp::Clear(Color::BLACK),
+                store: true,
+            },
+        )],
+        depth_stencil_attachment: None,
+        sample_count: samples,
+    });
+    pass.add_camera(CAMERA);
+    graph.add_node(PASS_NODE, pass);
+    graph.add_node_edge(MATERIAL_NODE, PASS_NODE).unwrap();
+    graph.add_node_edge(CAMERA_NODE, PASS_NODE).unwrap();
+
+    // Everything that drew to the swap chain now draws to the frame texture, and must finish
+    // before the color management pass reads it.
+    let mut rewired = Vec::new();
+    for edge in swap_chain_edges {
+        if let Edge::SlotEdge {
+            input_node,
+            input_index,
+            ..
+        } = edge
+        {
+            graph
+                .get_node_state_mut(swap_chain)
+                .unwrap()
+                .edges
+                .output_edges
+                .retain(|existing| *existing != edge);
+            graph
+                .get_node_state_mut(input_node)
+                .unwrap()
+                .edges
+                .input_edges
+                .retain(|existing| *existing != edge);
+            graph
+                .add_slot_edge(
+                    FRAME_TEXTURE_NODE,
+                    FrameTextureNode::OUT_TEXTURE,
+                    input_node,
+                    input_index,
+                )
+                .unwrap();
+            if !rewired.contains(&input_node) {
+                graph.add_node_edge(input_node, PASS_NODE).unwrap();
+                rewired.push(input_node);
+            }
+        }
+    }
+
+    if samples > 1 {
+        graph
+            .add_slot_edge(
+                base::node::MAIN_SAMPLED_COLOR_ATTACHMENT,
+                WindowTextureNode::OUT_TEXTURE,
+                PASS_NODE,
+                "color_attachment",
+            )
+            .unwrap();
+    }
+    graph
+        .add_slot_edge(
+            base::node::PRIMARY_SWAP_CHAIN,
+            WindowSwapChainNode::OUT_TEXTURE,
+            PASS_NODE,
+            if samples > 1 {
+                "color_resolve_target"
+            } else {
+                "color_attachment"
+            },
+        )
+        .unwrap();
+}
+
+/// Render graph node which owns the intermediate frame texture, sized to the primary window. The
+/// texture is also registered as the asset resource for [`FRAME_TEXTURE_HANDLE`] so the color
+/// management material can sample it.
+#[derive(Default)]
+struct FrameTextureNode {
+    window_created_event_reader: ManualEventReader<WindowCreated>,
+    window_resized_event_reader: ManualEventReader<WindowResized>,
+    texture: Option<TextureId>,
+    sampler: Option<SamplerId>,
+    /// Texture replaced on the previous frame. The material's bindings still refer to it until
+    /// the material is rebound during the next frame, so removal is deferred by one frame.
+    retired: Option<TextureId>,
+}
+
+impl FrameTextureNode {
+    const OUT_TEXTURE: &'static str = "texture";
+}
+
+impl Node for FrameTextureNode {
+    fn output(&self) -> &[ResourceSlotInfo] {
+        static OUTPUT: &[ResourceSlotInfo] = &[ResourceSlotInfo {
+            name: Cow::Borrowed(FrameTextureNode::OUT_TEXTURE),
+            resource_type: RenderResourceType::Texture,
+        }];
+        OUTPUT
+    }
+
+    fn prepare(&mut self, world: &mut World) {
+        {
+            let render_resource_context = world
+                .get_resource::<Box<dyn RenderResourceContext>>()
+                .unwrap();
+            if let Some(retired) = self.retired.take() {
+                render_resource_context.remove_texture(retired);
+            }
+
+            let window_created_events = world.get_resource::<Events<WindowCreated>>().unwrap();
+            let window_resized_events = world.get_resource::<Events<WindowResized>>().unwrap();
+            let windows = world.get_resource::<Windows>().unwrap();
+            let window = match windows.get_primary() {
+                Some(window) => window,
+                None => return,
+            };
+            let created = self
+                .window_created_event_reader
+                .iter(&window_created_events)
+                .any(|e| e.id == window.id());
+            let resized = self
+                .window_resized_event_reader
+                .iter(&window_resized_events)
+                .any(|e| e.id == window.id());
+            if self.texture.is_some() && !created && !resized {
+                return;
+            }
+
+            let texture = render_resource_context.create_texture(TextureDescriptor {
+                size: Extent3d {
+                    width: window.physical_width(),
+                    height: window.physical_height(),
+                    depth: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::default(),
+                usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::SAMPLED,
+            });
+            let sampler = *self.sampler.get_or_insert_with(|| {
+                render_resource_context.create_sampler(&SamplerDescriptor::default())
+            });
+            let handle = FRAME_TEXTURE_HANDLE.typed::<Texture>();
+            render_resource_context.set_asset_resource(
+                &handle,
+                RenderResourceId::Texture(texture),
+                TEXTURE_ASSET_INDEX,
+            );
+            render_resource_context.set_asset_resource(
+                &handle,
+                RenderResourceId::Sampler(sampler),
+                SAMPLER_ASSET_INDEX,
+            );
+            self.retired = self.texture.replace(texture);
+        }
+        // Mark the material modified so it gets rebound to the new texture.
+        world
+            .get_resource_mut::<Assets<ColorManagementMaterial>>()
+            .unwrap()
+            .get_mut(&MATERIAL_HANDLE.typed());
+    }
+
+    fn update(
+        &mut self,
+        _world: &World,
+        _render_context: &mut dyn RenderContext,
+        _input: &ResourceSlots,
+        output: &mut ResourceSlots,
+    ) {
+        if let Some(texture) = self.texture {
+            output.set(0, RenderResourceId::Texture(texture));
+        }
+    }
+}
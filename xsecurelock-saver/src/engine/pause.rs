@@ -0,0 +1,132 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pausing the saver between frames, for XSecurelock setups which stop the saver while the screen
+//! is blanked and start it again afterwards, rather than killing it. `SIGUSR1` pauses the saver
+//! and `SIGUSR2` resumes it.
+//!
+//! When paused, the runner runs one more frame, in which the [`SaverPaused`] resource says the
+//! saver is paused and the [`SaverTime`](crate::engine::SaverTime) covers no time, so savers can
+//! draw a static frame. It then stops updating the app, leaving that frame on screen, until the
+//! saver is resumed or interrupted, though it still exits if XSecurelock destroys the window.
+//! Neither the `SaverTime` nor Bevy's `Time` counts the time spent paused.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use bevy::prelude::*;
+
+/// Whether the saver is paused. Changes between frames, so savers can check `is_changed` to react
+/// when the saver pauses or resumes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaverPaused {
+    paused: bool,
+}
+
+impl SaverPaused {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+const NO_REQUEST: u8 = 0;
+const PAUSE_REQUEST: u8 = 1;
+const RESUME_REQUEST: u8 = 2;
+
+/// Latest request received from a signal, if any.
+static REQUEST: AtomicU8 = AtomicU8::new(NO_REQUEST);
+
+extern "C" fn request_pause(_signal: libc::c_int) {
+    REQUEST.store(PAUSE_REQUEST, Ordering::Relaxed);
+}
+
+extern "C" fn request_resume(_signal: libc::c_int) {
+    REQUEST.store(RESUME_REQUEST, Ordering::Relaxed);
+}
+
+/// Installs the handlers for the pause and resume signals.
+pub(crate) fn init() {
+    unsafe {
+        libc::signal(libc::SIGUSR1, request_pause as libc::sighandler_t);
+        libc::signal(libc::SIGUSR2, request_resume as libc::sighandler_t);
+    }
+}
+
+/// Takes the latest request received since the last call: true to pause, false to resume.
+pub(crate) fn take_request() -> Option<bool> {
+    match REQUEST.swap(NO_REQUEST, Ordering::Relaxed) {
+        PAUSE_REQUEST => Some(true),
+        RESUME_REQUEST => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether the app's [`SaverPaused`] says it's paused.
+pub(crate) fn is_paused(world: &World) -> bool {
+    world
+        .get_resource::<SaverPaused>()
+        .map_or(false, SaverPaused::is_paused)
+}
+
+/// Pauses or resumes the app between frames.
+pub(crate) fn set_paused(world: &mut World, paused: bool) {
+    if is_paused(world) == paused {
+        return;
+    }
+    info!("{}", if paused { "Pausing" } else { "Resuming" });
+    world.insert_resource(SaverPaused { paused });
+    if !paused {
+        // Otherwise the first frame would cover the whole pause, for anything stepped with Bevy's
+        // time rather than the SaverTime.
+        if let Some(mut time) = world.get_resource_mut::<Time>() {
+            time.update();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::engine::{SaverSignal, SaverTime, TestRunner};
+
+    #[test]
+    fn freezes_time_while_paused() {
+        let mut runner = TestRunner::with_frame_time(Duration::from_millis(10), |_| {});
+        runner.update(4);
+        assert!(!runner.resource::<SaverPaused>().is_paused());
+
+        runner.send_signal(SaverSignal::Pause).update(1);
+        assert!(runner.resource::<SaverPaused>().is_paused());
+        let paused_at = runner.resource::<SaverTime>().elapsed();
+        assert_eq!(paused_at, Duration::from_millis(30));
+        assert_eq!(
+            runner.resource::<SaverTime>().delta(),
+            Duration::from_secs(0)
+        );
+
+        runner.update(10);
+        assert!(runner.resource::<SaverPaused>().is_paused());
+        assert_eq!(runner.resource::<SaverTime>().elapsed(), paused_at);
+
+        runner.send_signal(SaverSignal::Resume).update(1);
+        assert!(!runner.resource::<SaverPaused>().is_paused());
+        assert_eq!(runner.resource::<SaverTime>().elapsed(), paused_at);
+        runner.update(1);
+        assert_eq!(
+            runner.resource::<SaverTime>().elapsed(),
+            paused_at + Duration::from_millis(10)
+        );
+    }
+}
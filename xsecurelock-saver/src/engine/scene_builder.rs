@@ -0,0 +1,172 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spawning big scenes over several frames.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+/// Spawns a queue of items with [`Commands`], a batch per frame, so that a big scene doesn't stall
+/// a single frame while everything is set up. Savers keep one as a resource, queue up the scene's
+/// items when it starts, and call [`SceneBuilder::spawn_batch`] from a system every frame.
+///
+/// ```no_run
+/// use bevy::prelude::*;
+/// use xsecurelock_saver::engine::SceneBuilder;
+///
+/// struct Star(Vec3);
+///
+/// fn queue_stars(mut builder: ResMut<SceneBuilder<Vec3>>) {
+///     let positions = (0..10_000).map(|i| Vec3::new(i as f32, 0.0, 0.0));
+///     builder.queue_over_frames(positions, 10);
+/// }
+///
+/// fn spawn_stars(mut commands: Commands, mut builder: ResMut<SceneBuilder<Vec3>>) {
+///     builder.spawn_batch(&mut commands, |commands, position| {
+///         commands.spawn().insert(Star(position));
+///     });
+///     if builder.is_building() {
+///         info!("Scene {:.0}% built", builder.progress() * 100.0);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SceneBuilder<T> {
+    pending: VecDeque<T>,
+    /// Number of items queued since the queue was last replaced.
+    total: usize,
+    /// Most items to spawn in a frame.
+    per_frame: usize,
+}
+
+impl<T> Default for SceneBuilder<T> {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            total: 0,
+            per_frame: 1,
+        }
+    }
+}
+
+impl<T> SceneBuilder<T> {
+    /// Replaces anything still queued with `items`, spawning up to `per_frame` of them each frame.
+    pub fn queue(&mut self, items: impl IntoIterator<Item = T>, per_frame: usize) {
+        self.pending.clear();
+        self.pending.extend(items);
+        self.total = self.pending.len();
+        self.per_frame = per_frame.max(1);
+    }
+
+    /// Replaces anything still queued with `items`, spread evenly over `frames` frames.
+    pub fn queue_over_frames(&mut self, items: impl IntoIterator<Item = T>, frames: u32) {
+        self.queue(items, usize::MAX);
+        let frames = frames.max(1) as usize;
+        self.per_frame = ((self.total + frames - 1) / frames).max(1);
+    }
+
+    /// Drops everything still queued.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.total = 0;
+    }
+
+    /// Spawns the next batch of items with `spawn`, returning how many were spawned.
+    pub fn spawn_batch(
+        &mut self,
+        commands: &mut Commands,
+        mut spawn: impl FnMut(&mut Commands, T),
+    ) -> usize {
+        let batch = self.per_frame.min(self.pending.len());
+        for item in self.pending.drain(..batch) {
+            spawn(commands, item);
+        }
+        batch
+    }
+
+    /// Whether any items are still waiting to be spawned.
+    pub fn is_building(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Number of items still waiting to be spawned.
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Fraction of the queued items spawned so far, from 0 to 1. An empty queue counts as done.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.total - self.pending.len()) as f32 / self.total as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+
+    struct Item(usize);
+
+    /// Spawns a batch from the builder into the world, returning how many were spawned.
+    fn spawn_batch(world: &mut World, builder: &mut SceneBuilder<usize>) -> usize {
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        let spawned = builder.spawn_batch(&mut commands, |commands, i| {
+            commands.spawn().insert(Item(i));
+        });
+        queue.apply(world);
+        spawned
+    }
+
+    #[test]
+    fn spawns_over_frames() {
+        let mut world = World::default();
+        let mut builder = SceneBuilder::default();
+        assert_eq!(builder.progress(), 1.0);
+        builder.queue_over_frames(0..10, 3);
+        assert!(builder.is_building());
+        assert_eq!(builder.progress(), 0.0);
+        assert_eq!(spawn_batch(&mut world, &mut builder), 4);
+        assert_eq!(spawn_batch(&mut world, &mut builder), 4);
+        assert_eq!(builder.remaining(), 2);
+        assert_eq!(builder.progress(), 0.8);
+        assert_eq!(spawn_batch(&mut world, &mut builder), 2);
+        assert!(!builder.is_building());
+        assert_eq!(spawn_batch(&mut world, &mut builder), 0);
+        let mut spawned: Vec<_> = world.query::<&Item>().iter(&world).map(|i| i.0).collect();
+        spawned.sort_unstable();
+        assert_eq!(spawned, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn queue_replaces_pending_items() {
+        let mut world = World::default();
+        let mut builder = SceneBuilder::default();
+        builder.queue(0..10, 3);
+        assert_eq!(spawn_batch(&mut world, &mut builder), 3);
+        builder.queue(10..12, 0);
+        assert_eq!(builder.progress(), 0.0);
+        assert_eq!(spawn_batch(&mut world, &mut builder), 1);
+        builder.clear();
+        assert_eq!(builder.progress(), 1.0);
+        assert_eq!(spawn_batch(&mut world, &mut builder), 0);
+        assert_eq!(world.query::<&Item>().iter(&world).count(), 4);
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Captures the screen once at startup, and dissolves it away to reveal the saver. Configured by
+//! these environment variables:
+//!
+//! * `XSECURELOCK_SAVER_SCREEN_DISSOLVE`: `melt`, `pixelate` or `swallow` to dissolve the screen
+//!   that way when the saver starts. Off by default.
+//! * `XSECURELOCK_SAVER_SCREEN_DISSOLVE_SECONDS`: how long the screen takes to dissolve, from 0 to
+//!   60. Defaults to 3.
+//!
+//! Savers can instead insert a [`ScreenDissolve`] resource before adding the plugins. Either way,
+//! fading in is turned off unless the saver configured it, since the screen should appear as it
+//! was rather than fade in from black.
+//!
+//! The capture is whatever the root window shows when the saver starts. XSecurelock may already
+//! have covered the desktop by then, in which case there isn't much left to dissolve.
+
+use std::time::Duration;
+use std::{mem, ptr};
+
+use bevy::prelude::*;
+use bevy_wgpu_xsecurelock::custom_pass::CustomPasses;
+use bevy_wgpu_xsecurelock::screen_dissolve::{ScreenDissolvePass, Screenshot};
+use x11::xlib;
+
+use crate::config_help::Setting;
+use crate::env::parse_var;
+
+pub use bevy_wgpu_xsecurelock::screen_dissolve::{DissolveEffect, ScreenDissolve};
+
+const SCREEN_DISSOLVE_VAR: &str = "XSECURELOCK_SAVER_SCREEN_DISSOLVE";
+const SCREEN_DISSOLVE_SECONDS_VAR: &str = "XSECURELOCK_SAVER_SCREEN_DISSOLVE_SECONDS";
+
+/// Reads screen dissolve settings from the environment. Off unless an effect is set.
+pub(crate) fn screen_dissolve_from_env() -> Option<ScreenDissolve> {
+    let effect: DissolveEffect = parse_var(SCREEN_DISSOLVE_VAR, |_| true)?;
+    let defaults = ScreenDissolve::default();
+    let duration = parse_var(SCREEN_DISSOLVE_SECONDS_VAR, |seconds: &f32| {
+        (0.0..=60.0).contains(seconds)
+    })
+    .map_or(defaults.duration, Duration::from_secs_f32);
+    info!("Dissolving the screen with {:?}", effect);
+    Some(ScreenDissolve {
+        effect,
+        duration,
+        ..defaults
+    })
+}
+
+/// The screen dissolve settings, for `--help-config`.
+pub(crate) fn settings() -> Vec<Setting> {
+    vec![
+        Setting::new::<DissolveEffect>(
+            SCREEN_DISSOLVE_VAR,
+            "How to dissolve the screen when the saver starts. Off unless set.",
+        )
+        .with_kind("melt | pixelate | swallow"),
+        Setting::new::<f32>(
+            SCREEN_DISSOLVE_SECONDS_VAR,
+            "How long the screen takes to dissolve, from 0 to 60.",
+        )
+        .with_default(ScreenDissolve::default().duration.as_secs_f32()),
+    ]
+}
+
+/// Captures the screen at startup and adds the pass dissolving it, if a [`ScreenDissolve`] is
+/// configured. Added after the renderer, so [`CustomPasses`] exists.
+#[derive(Debug)]
+pub(crate) struct ScreenDissolvePlugin;
+
+impl Plugin for ScreenDissolvePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if app.world().get_resource::<ScreenDissolve>().is_some() {
+            // After startup, so the dissolve draws over any passes the saver adds while starting.
+            app.add_startup_system_to_stage(
+                StartupStage::PostStartup,
+                start_screen_dissolve.system(),
+            );
+        }
+    }
+}
+
+fn start_screen_dissolve(settings: Res<ScreenDissolve>, mut passes: ResMut<CustomPasses>) {
+    match capture_root_window() {
+        Some(screenshot) => passes.add(ScreenDissolvePass::new(*settings, screenshot)),
+        None => warn!("Unable to capture the screen, not dissolving it"),
+    }
+}
+
+/// Captures the root window of `$DISPLAY` as RGBA pixels.
+pub fn capture_root_window() -> Option<Screenshot> {
+    unsafe {
+        let display = xlib::XOpenDisplay(ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let root = xlib::XDefaultRootWindow(display);
+        let mut attributes = mem::zeroed::<xlib::XWindowAttributes>();
+        let screenshot = if xlib::XGetWindowAttributes(display, root, &mut attributes) == 0 {
+            None
+        } else {
+            capture_window(display, root, attributes.width, attributes.height)
+        };
+        xlib::XCloseDisplay(display);
+        screenshot
+    }
+}
+
+/// Copies the pixels of a window of the given size.
+unsafe fn capture_window(
+    display: *mut xlib::Display,
+    window: xlib::Window,
+    width: i32,
+    height: i32,
+) -> Option<Screenshot> {
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    let image = xlib::XGetImage(
+        display,
+        window,
+        0,
+        0,
+        width as u32,
+        height as u32,
+        xlib::XAllPlanes(),
+        xlib::ZPixmap,
+    );
+    if image.is_null() {
+        return None;
+    }
+    let masks = [
+        (*image).red_mask as u64,
+        (*image).green_mask as u64,
+        (*image).blue_mask as u64,
+    ];
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = xlib::XGetPixel(image, x, y) as u64;
+            pixels.extend(masks.iter().map(|&mask| channel(pixel, mask)));
+            pixels.push(u8::MAX);
+        }
+    }
+    xlib::XDestroyImage(image);
+    Some(Screenshot {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
+
+/// Scales the bits of `pixel` under `mask` to 8 bits.
+fn channel(pixel: u64, mask: u64) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+    (((pixel & mask) >> shift) * 255 / max) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_channels_to_8_bits() {
+        // 24 bit color.
+        let pixel = 0x12_34_56;
+        assert_eq!(channel(pixel, 0xff_00_00), 0x12);
+        assert_eq!(channel(pixel, 0x00_ff_00), 0x34);
+        assert_eq!(channel(pixel, 0x00_00_ff), 0x56);
+        // 16 bit 565 color.
+        assert_eq!(channel(0b11111_000000_00000, 0xf800), 255);
+        assert_eq!(channel(0b00000_100000_00000, 0x07e0), 129);
+        assert_eq!(channel(0, 0x001f), 0);
+        assert_eq!(channel(0xffff, 0), 0);
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs apps built on the engine for `--self-test`; see [`crate::self_test`].
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
+
+use bevy::app::{AppExit, Events, ManualEventReader};
+use bevy::prelude::*;
+use bevy_wgpu_xsecurelock::WgpuOptions;
+
+use crate::self_test::{self, SelfTest};
+
+/// Starts the [`SelfTest`] resource, checking that a GPU device can be set up with the app's
+/// renderer options, and replaces the runner with one which runs the self test's frames. There is
+/// no window to draw into, so the app itself runs headless.
+#[derive(Debug)]
+pub(crate) struct SelfTestPlugin;
+
+impl Plugin for SelfTestPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let mut test = SelfTest::start();
+        let options = app
+            .world()
+            .get_resource::<WgpuOptions>()
+            .cloned()
+            .unwrap_or_default();
+        test.record(
+            "renderer",
+            bevy_wgpu_xsecurelock::probe_adapter(&options),
+            |adapter| format!("{} ({:?})", adapter.name, adapter.backend),
+        );
+        app.insert_resource(test).set_runner(runner);
+    }
+}
+
+/// Runs the app for the self test's frames, stopping early if it exits or panics, then prints the
+/// report and exits.
+fn runner(mut app: App) {
+    info!("Running self test");
+    let mut app_exit_event_reader = ManualEventReader::<AppExit>::default();
+    let mut times = Vec::with_capacity(self_test::FRAMES as usize);
+    let mut panicked = None;
+    for _ in 0..self_test::FRAMES {
+        let start = Instant::now();
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| app.update())) {
+            panicked = Some(panic_message(&*payload));
+            break;
+        }
+        times.push(start.elapsed());
+        if let Some(app_exit_events) = app.world.get_resource::<Events<AppExit>>() {
+            if app_exit_event_reader.iter(app_exit_events).next().is_some() {
+                break;
+            }
+        }
+    }
+    let mut test = app.world.remove_resource::<SelfTest>().unwrap_or_default();
+    match panicked {
+        Some(message) => test.fail(
+            "frames",
+            format!("panicked after {} frames: {}", times.len(), message),
+        ),
+        None => test.pass("frames", self_test::describe_frame_times(&times)),
+    }
+    test.finish()
+}
+
+/// The message a panic was started with.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running apps built on the engine in tests, without X or a GPU.
+
+use std::any;
+use std::time::{Duration, Instant};
+
+use bevy::app::{AppExit, Events, ManualEventReader, PluginGroupBuilder};
+use bevy::audio::AudioPlugin;
+use bevy::ecs::component::Component;
+use bevy::gilrs::GilrsPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+
+use crate::engine::pause;
+use crate::engine::screen_capture::ScreenDissolvePlugin;
+use crate::engine::CrashReportPlugin;
+
+/// Time each frame covers unless set with [`TestRunner::with_frame_time`].
+const DEFAULT_FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Runs an app with the engine's plugins for a set number of frames, so tests can check the app's
+/// ECS state after simulated frames. The plugins run headless, as with `--headless`: there is no
+/// window, and the renderer's headless render resource context stands in for the GPU.
+///
+/// Each frame advances the [`SaverTime`](crate::engine::SaverTime) by exactly the frame time,
+/// however long the frame took to run, so the results don't depend on how fast the machine is. As
+/// with the real clock, the first frame covers no time, and frame times are clamped to the
+/// `SaverTime`'s maximum delta.
+///
+/// ```no_run
+/// use bevy::prelude::*;
+/// use xsecurelock_saver::engine::{SaverTime, TestRunner};
+///
+/// struct Distance(f32);
+///
+/// fn travel(time: Res<SaverTime>, mut distance: ResMut<Distance>) {
+///     distance.0 += 2.0 * time.delta_seconds();
+/// }
+///
+/// let mut runner = TestRunner::new(|app| {
+///     app.insert_resource(Distance(0.0)).add_system(travel.system());
+/// });
+/// runner.update(61);
+/// assert!((runner.resource::<Distance>().0 - 2.0).abs() < 1e-3);
+/// ```
+///
+/// Tests can also stop or pause the app as XSecurelock would, with [`TestRunner::send_signal`].
+pub struct TestRunner {
+    app: App,
+    app_exit_event_reader: ManualEventReader<AppExit>,
+    frames: u64,
+    exited: bool,
+    interrupted: bool,
+    /// Whether the frame after pausing has run, as the real runner only runs that one.
+    paused_frame_run: bool,
+}
+
+/// Signals the saver's runner responds to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaverSignal {
+    /// SIGINT, which XSecurelock sends to stop the saver. The runner stops before the next frame,
+    /// without running any more systems.
+    Interrupt,
+    /// SIGUSR1, which pauses the saver. The runner runs one more frame, then no more until the
+    /// saver is resumed. See [`pause`](crate::engine::pause).
+    Pause,
+    /// SIGUSR2, which resumes a paused saver.
+    Resume,
+}
+
+impl TestRunner {
+    /// Builds an app with the engine's plugins, which `build` then adds the saver's own plugins
+    /// and systems to. Frames cover a 60th of a second.
+    pub fn new(build: impl FnOnce(&mut AppBuilder)) -> Self {
+        Self::with_frame_time(DEFAULT_FRAME_TIME, build)
+    }
+
+    /// Like [`TestRunner::new`], with each frame covering `frame_time`.
+    pub fn with_frame_time(frame_time: Duration, build: impl FnOnce(&mut AppBuilder)) -> Self {
+        let mut builder = App::build();
+        builder
+            .insert_resource(SimulatedClock::new(frame_time))
+            .add_plugins(TestPlugins);
+        build(&mut builder);
+        Self {
+            app: std::mem::take(&mut builder.app),
+            app_exit_event_reader: Default::default(),
+            frames: 0,
+            exited: false,
+            interrupted: false,
+            paused_frame_run: false,
+        }
+    }
+
+    /// Runs the given number of frames, stopping early once the app exits or is interrupted. While
+    /// paused, only the first frame after pausing runs, and the rest are skipped.
+    pub fn update(&mut self, frames: u32) -> &mut Self {
+        for _ in 0..frames {
+            if self.exited || self.interrupted {
+                break;
+            }
+            let paused = pause::is_paused(&self.app.world);
+            if paused && self.paused_frame_run {
+                continue;
+            }
+            self.paused_frame_run = paused;
+            self.app.update();
+            self.frames += 1;
+            if let Some(app_exit_events) = self.app.world.get_resource::<Events<AppExit>>() {
+                if self
+                    .app_exit_event_reader
+                    .iter(app_exit_events)
+                    .next()
+                    .is_some()
+                {
+                    self.exited = true;
+                }
+            }
+        }
+        self
+    }
+
+    /// Number of frames run so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Whether the app has sent an [`AppExit`] event.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Delivers a signal between frames, as the real runner would receive it.
+    pub fn send_signal(&mut self, signal: SaverSignal) -> &mut Self {
+        match signal {
+            SaverSignal::Interrupt => self.interrupted = true,
+            SaverSignal::Pause => pause::set_paused(&mut self.app.world, true),
+            SaverSignal::Resume => pause::set_paused(&mut self.app.world, false),
+        }
+        self
+    }
+
+    /// Whether the app was stopped with [`SaverSignal::Interrupt`].
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    /// Gets a resource, panicking if the app doesn't have it.
+    pub fn resource<T: Component>(&self) -> &T {
+        self.app
+            .world
+            .get_resource::<T>()
+            .unwrap_or_else(|| panic!("No {} resource", any::type_name::<T>()))
+    }
+
+    pub fn world(&self) -> &World {
+        &self.app.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+}
+
+/// The engine's plugins running headless, without the plugins which need devices or set up
+/// process-wide state.
+struct TestPlugins;
+
+impl PluginGroup for TestPlugins {
+    fn build(&mut self, plugins: &mut PluginGroupBuilder) {
+        super::build_plugins(plugins, true);
+        plugins
+            // The log subscriber can only be set once per process, and tests build many apps.
+            .disable::<LogPlugin>()
+            .disable::<AudioPlugin>()
+            .disable::<GilrsPlugin>()
+            // Would capture the real screen if a dissolve is set in the environment.
+            .disable::<ScreenDissolvePlugin>()
+            // The panic hook is process-wide, and a test's panics are reported by the test harness.
+            .disable::<CrashReportPlugin>();
+    }
+}
+
+/// Stands in for the monotonic clock when updating the `SaverTime` in a [`TestRunner`].
+pub(crate) struct SimulatedClock {
+    now: Instant,
+    frame_time: Duration,
+}
+
+impl SimulatedClock {
+    fn new(frame_time: Duration) -> Self {
+        Self {
+            now: Instant::now(),
+            frame_time,
+        }
+    }
+
+    /// Advances the clock by one frame, returning the new time.
+    pub(crate) fn tick(&mut self) -> Instant {
+        self.now += self.frame_time;
+        self.now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{SaverPaused, SaverTime};
+
+    struct Frames(u32);
+
+    fn count_frames(mut frames: ResMut<Frames>, mut app_exit_events: EventWriter<AppExit>) {
+        frames.0 += 1;
+        if frames.0 == 5 {
+            app_exit_events.send(AppExit);
+        }
+    }
+
+    #[test]
+    fn runs_frames_until_exit() {
+        let mut runner = TestRunner::with_frame_time(Duration::from_millis(20), |app| {
+            app.insert_resource(Frames(0))
+                .add_system(count_frames.system());
+        });
+        runner.update(3);
+        assert_eq!(runner.resource::<Frames>().0, 3);
+        // The first frame starts the clock.
+        assert_eq!(
+            runner.resource::<SaverTime>().elapsed(),
+            Duration::from_millis(40)
+        );
+        runner.update(10);
+        assert!(runner.exited());
+        assert_eq!(runner.frames(), 5);
+        assert_eq!(runner.resource::<Frames>().0, 5);
+    }
+
+    #[test]
+    fn stops_when_interrupted() {
+        let mut runner = TestRunner::new(|app| {
+            app.insert_resource(Frames(0))
+                .add_system(count_frames.system());
+        });
+        runner
+            .update(2)
+            .send_signal(SaverSignal::Interrupt)
+            .update(2);
+        assert!(runner.interrupted());
+        assert!(!runner.exited());
+        assert_eq!(runner.frames(), 2);
+        assert_eq!(runner.resource::<Frames>().0, 2);
+    }
+
+    fn count_paused_frames(paused: Res<SaverPaused>, mut frames: ResMut<Frames>) {
+        if paused.is_paused() {
+            frames.0 += 1;
+        }
+    }
+
+    #[test]
+    fn runs_one_frame_while_paused() {
+        let mut runner = TestRunner::with_frame_time(Duration::from_millis(20), |app| {
+            app.insert_resource(Frames(0))
+                .add_system(count_paused_frames.system());
+        });
+        runner.update(3).send_signal(SaverSignal::Pause).update(5);
+        assert_eq!(runner.frames(), 4);
+        assert_eq!(runner.resource::<Frames>().0, 1);
+        assert_eq!(
+            runner.resource::<SaverTime>().elapsed(),
+            Duration::from_millis(40)
+        );
+        // The frame after resuming covers no time either.
+        runner.send_signal(SaverSignal::Resume).update(2);
+        assert_eq!(runner.frames(), 6);
+        assert_eq!(runner.resource::<Frames>().0, 1);
+        assert_eq!(
+            runner.resource::<SaverTime>().elapsed(),
+            Duration::from_millis(60)
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading settings from environment variables, which is how XSecurelock passes settings to
+//! savers. Values which fail to parse or validate are logged and ignored, so a typo falls back to
+//! the default rather than stopping the saver.
+
+use std::env;
+use std::str::FromStr;
+
+use log::warn;
+
+/// Parse an environment variable, logging and ignoring values that fail to parse or validate.
+pub fn parse_var<T: FromStr>(name: &str, valid: impl Fn(&T) -> bool) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(parsed) if valid(&parsed) => Some(parsed),
+        _ => {
+            warn!("Invalid {}: {:?}, using default", name, value);
+            None
+        }
+    }
+}
+
+/// Parse an environment variable, falling back to the default if it is unset or invalid.
+pub fn parse_var_or<T: FromStr>(name: &str, default: T, valid: impl Fn(&T) -> bool) -> T {
+    parse_var(name, valid).unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_on_invalid_values() {
+        const VAR: &str = "XSECURELOCK_SAVER_TEST_ENV_PARSE_VAR";
+        let valid = |count: &u32| (1..=8).contains(count);
+        env::remove_var(VAR);
+        assert_eq!(parse_var(VAR, valid), None);
+        assert_eq!(parse_var_or(VAR, 4, valid), 4);
+        env::set_var(VAR, " 3 ");
+        assert_eq!(parse_var(VAR, valid), Some(3));
+        env::set_var(VAR, "9");
+        assert_eq!(parse_var_or(VAR, 4, valid), 4);
+        env::set_var(VAR, "three");
+        assert_eq!(parse_var_or(VAR, 4, valid), 4);
+        env::remove_var(VAR);
+    }
+}
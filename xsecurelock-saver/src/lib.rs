@@ -13,9 +13,19 @@
 // limitations under the License.
 
 //! Screensavers for XSecurelock using SFML or Bevy. Enable one of the features, either `simple` for
-//! SFML or `engine` for Bevy, and see the corresponding module for usage.
+//! SFML or `engine` for Bevy, and see the corresponding module for usage. Every saver parses its
+//! arguments with [`cli`]; the other modules hold what savers of either kind share.
 
+pub mod cli;
+pub mod color;
+pub mod config_help;
+pub mod crash;
 #[cfg(any(feature = "engine", doc))]
 pub mod engine;
+pub mod env;
+pub mod noise;
+pub mod priority;
+pub mod self_test;
 #[cfg(any(feature = "simple", doc))]
 pub mod simple;
+pub mod time;
@@ -0,0 +1,165 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Shader versions of xsecurelock_saver::noise. Keep in sync with noise.rs and noise.wgsl.
+
+uint noise_hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+uint noise_hash2(uint seed, ivec2 p) {
+    return noise_hash(noise_hash(noise_hash(seed) ^ uint(p.x)) ^ uint(p.y));
+}
+
+uint noise_hash3(uint seed, ivec3 p) {
+    return noise_hash(noise_hash2(seed, p.xy) ^ uint(p.z));
+}
+
+float noise_grad2(uint h, vec2 d) {
+    switch (h & 7u) {
+        case 0u: return d.x + d.y;
+        case 1u: return d.x - d.y;
+        case 2u: return -d.x + d.y;
+        case 3u: return -d.x - d.y;
+        case 4u: return d.x;
+        case 5u: return -d.x;
+        case 6u: return d.y;
+        default: return -d.y;
+    }
+}
+
+float noise_grad3(uint h, vec3 d) {
+    switch (h % 12u) {
+        case 0u: return d.x + d.y;
+        case 1u: return -d.x + d.y;
+        case 2u: return d.x - d.y;
+        case 3u: return -d.x - d.y;
+        case 4u: return d.x + d.z;
+        case 5u: return -d.x + d.z;
+        case 6u: return d.x - d.z;
+        case 7u: return -d.x - d.z;
+        case 8u: return d.y + d.z;
+        case 9u: return -d.y + d.z;
+        case 10u: return d.y - d.z;
+        default: return -d.y - d.z;
+    }
+}
+
+vec3 noise_fade(vec3 t) {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+float perlin2(uint seed, vec2 p) {
+    vec2 f = floor(p);
+    ivec2 i = ivec2(f);
+    vec2 d = p - f;
+    float c00 = noise_grad2(noise_hash2(seed, i), d);
+    float c10 = noise_grad2(noise_hash2(seed, i + ivec2(1, 0)), d - vec2(1.0, 0.0));
+    float c01 = noise_grad2(noise_hash2(seed, i + ivec2(0, 1)), d - vec2(0.0, 1.0));
+    float c11 = noise_grad2(noise_hash2(seed, i + ivec2(1, 1)), d - vec2(1.0, 1.0));
+    vec2 u = noise_fade(vec3(d, 0.0)).xy;
+    return mix(mix(c00, c10, u.x), mix(c01, c11, u.x), u.y);
+}
+
+float perlin3(uint seed, vec3 p) {
+    vec3 f = floor(p);
+    ivec3 i = ivec3(f);
+    vec3 d = p - f;
+    float c000 = noise_grad3(noise_hash3(seed, i), d);
+    float c100 = noise_grad3(noise_hash3(seed, i + ivec3(1, 0, 0)), d - vec3(1.0, 0.0, 0.0));
+    float c010 = noise_grad3(noise_hash3(seed, i + ivec3(0, 1, 0)), d - vec3(0.0, 1.0, 0.0));
+    float c110 = noise_grad3(noise_hash3(seed, i + ivec3(1, 1, 0)), d - vec3(1.0, 1.0, 0.0));
+    float c001 = noise_grad3(noise_hash3(seed, i + ivec3(0, 0, 1)), d - vec3(0.0, 0.0, 1.0));
+    float c101 = noise_grad3(noise_hash3(seed, i + ivec3(1, 0, 1)), d - vec3(1.0, 0.0, 1.0));
+    float c011 = noise_grad3(noise_hash3(seed, i + ivec3(0, 1, 1)), d - vec3(0.0, 1.0, 1.0));
+    float c111 = noise_grad3(noise_hash3(seed, i + ivec3(1, 1, 1)), d - vec3(1.0, 1.0, 1.0));
+    vec3 u = noise_fade(d);
+    float near = mix(mix(c000, c100, u.x), mix(c010, c110, u.x), u.y);
+    float far = mix(mix(c001, c101, u.x), mix(c011, c111, u.x), u.y);
+    return mix(near, far, u.z);
+}
+
+float noise_simplex_corner(uint seed, ivec2 i, vec2 d) {
+    float t = 0.5 - dot(d, d);
+    if (t < 0.0) {
+        return 0.0;
+    }
+    t *= t;
+    return t * t * noise_grad2(noise_hash2(seed, i), d);
+}
+
+float simplex2(uint seed, vec2 p) {
+    const float F2 = 0.3660254;
+    const float G2 = 0.21132487;
+    vec2 f = floor(p + (p.x + p.y) * F2);
+    ivec2 i = ivec2(f);
+    vec2 d0 = p - (f - (f.x + f.y) * G2);
+    ivec2 o = d0.x > d0.y ? ivec2(1, 0) : ivec2(0, 1);
+    vec2 d1 = d0 - vec2(o) + G2;
+    vec2 d2 = d0 - 1.0 + 2.0 * G2;
+    return 70.0 * (noise_simplex_corner(seed, i, d0) + noise_simplex_corner(seed, i + o, d1)
+        + noise_simplex_corner(seed, i + ivec2(1, 1), d2));
+}
+
+float fbm_perlin2(uint seed, vec2 p, int octaves, float lacunarity, float gain) {
+    float sum = 0.0, total = 0.0, amplitude = 1.0, frequency = 1.0;
+    for (int i = 0; i < octaves; i++) {
+        sum += amplitude * perlin2(seed + uint(i), p * frequency);
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    return total > 0.0 ? sum / total : 0.0;
+}
+
+float fbm_perlin3(uint seed, vec3 p, int octaves, float lacunarity, float gain) {
+    float sum = 0.0, total = 0.0, amplitude = 1.0, frequency = 1.0;
+    for (int i = 0; i < octaves; i++) {
+        sum += amplitude * perlin3(seed + uint(i), p * frequency);
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    return total > 0.0 ? sum / total : 0.0;
+}
+
+float fbm_simplex2(uint seed, vec2 p, int octaves, float lacunarity, float gain) {
+    float sum = 0.0, total = 0.0, amplitude = 1.0, frequency = 1.0;
+    for (int i = 0; i < octaves; i++) {
+        sum += amplitude * simplex2(seed + uint(i), p * frequency);
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    return total > 0.0 ? sum / total : 0.0;
+}
+
+float ridged_perlin2(uint seed, vec2 p, int octaves, float lacunarity, float gain) {
+    float sum = 0.0, total = 0.0, amplitude = 1.0, frequency = 1.0, weight = 1.0;
+    for (int i = 0; i < octaves; i++) {
+        float ridge = 1.0 - abs(perlin2(seed + uint(i), p * frequency));
+        ridge = ridge * ridge * weight;
+        weight = clamp(ridge, 0.0, 1.0);
+        sum += amplitude * ridge;
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    return total > 0.0 ? sum / total : 0.0;
+}
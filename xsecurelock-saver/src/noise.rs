@@ -0,0 +1,276 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Seeded procedural noise shared by savers: Perlin noise in two and three dimensions, 2D simplex
+//! noise, and fractal sums of them.
+//!
+//! Gradients are picked by hashing the lattice coordinates with the seed rather than through a
+//! permutation table, so the same functions can be written in a shader without uploading any
+//! tables. [`GLSL`] and [`WGSL`] contain shader versions of everything here which produce the same
+//! values up to floating point error, so a saver can, say, generate a landscape on the CPU and
+//! texture it on the GPU and have the two agree. The shader functions take the same arguments with
+//! vectors for points. Shaders can't pass functions around, so the fractal sums are spelled out per
+//! noise function instead: `fbm_perlin2`, `fbm_perlin3`, `fbm_simplex2`, and `ridged_perlin2`,
+//! each taking `(seed, p, octaves, lacunarity, gain)`.
+
+/// GLSL (4.50) versions of the noise functions, for pasting into Bevy shaders ahead of `main`.
+pub const GLSL: &str = include_str!("noise.glsl");
+
+/// WGSL versions of the noise functions, for shader modules created directly with wgpu.
+pub const WGSL: &str = include_str!("noise.wgsl");
+
+/// Mixes the bits of `x`. This is the "lowbias32" integer hash.
+pub fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+fn hash2(seed: u32, x: i32, y: i32) -> u32 {
+    hash(hash(hash(seed) ^ x as u32) ^ y as u32)
+}
+
+fn hash3(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    hash(hash2(seed, x, y) ^ z as u32)
+}
+
+/// Dot product of the offset with one of eight 2D gradients.
+fn grad2(hash: u32, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// Dot product of the offset with one of the twelve cube edge gradients of improved Perlin noise.
+fn grad3(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// 2D Perlin noise in `[-1, 1]`. Zero at every integer lattice point.
+pub fn perlin2(seed: u32, x: f32, y: f32) -> f32 {
+    let (xf, yf) = (x.floor(), y.floor());
+    let (xi, yi) = (xf as i32, yf as i32);
+    let (dx, dy) = (x - xf, y - yf);
+    let corner = |i: i32, j: i32| grad2(hash2(seed, xi + i, yi + j), dx - i as f32, dy - j as f32);
+    let (u, v) = (fade(dx), fade(dy));
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), u),
+        lerp(corner(0, 1), corner(1, 1), u),
+        v,
+    )
+}
+
+/// 3D Perlin noise in roughly `[-1, 1]`. Zero at every integer lattice point.
+pub fn perlin3(seed: u32, x: f32, y: f32, z: f32) -> f32 {
+    let (xf, yf, zf) = (x.floor(), y.floor(), z.floor());
+    let (xi, yi, zi) = (xf as i32, yf as i32, zf as i32);
+    let (dx, dy, dz) = (x - xf, y - yf, z - zf);
+    let corner = |i: i32, j: i32, k: i32| {
+        grad3(
+            hash3(seed, xi + i, yi + j, zi + k),
+            dx - i as f32,
+            dy - j as f32,
+            dz - k as f32,
+        )
+    };
+    let (u, v, w) = (fade(dx), fade(dy), fade(dz));
+    let near = lerp(
+        lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+        lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+        v,
+    );
+    let far = lerp(
+        lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+        lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+        v,
+    );
+    lerp(near, far, w)
+}
+
+/// 2D simplex noise in roughly `[-1, 1]`. Cheaper than Perlin noise and without its axis-aligned
+/// artifacts.
+pub fn simplex2(seed: u32, x: f32, y: f32) -> f32 {
+    const F2: f32 = 0.366_025_4; // (sqrt(3) - 1) / 2
+    const G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+    let skew = (x + y) * F2;
+    let (i, j) = ((x + skew).floor(), (y + skew).floor());
+    let unskew = (i + j) * G2;
+    let (x0, y0) = (x - (i - unskew), y - (j - unskew));
+    // Which of the two triangles in the skewed cell the point is in.
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let (xi, yi) = (i as i32, j as i32);
+    let corner = |ci: i32, cj: i32, x: f32, y: f32| {
+        let t = 0.5 - x * x - y * y;
+        if t < 0.0 {
+            0.0
+        } else {
+            let t2 = t * t;
+            t2 * t2 * grad2(hash2(seed, xi + ci, yi + cj), x, y)
+        }
+    };
+    let n0 = corner(0, 0, x0, y0);
+    let n1 = corner(i1, j1, x0 - i1 as f32 + G2, y0 - j1 as f32 + G2);
+    let n2 = corner(1, 1, x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2);
+    70.0 * (n0 + n1 + n2)
+}
+
+/// Fractal Brownian motion: octaves of a noise function at increasing frequency and decreasing
+/// amplitude. Each octave uses its own seed, counting up from the base seed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fbm {
+    pub octaves: u32,
+    /// Frequency multiplier between octaves.
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves.
+    pub gain: f32,
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self {
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fbm {
+    pub fn new(octaves: u32) -> Self {
+        Self {
+            octaves,
+            ..Default::default()
+        }
+    }
+
+    /// Sums octaves of a 2D noise function, normalized to the range of the noise.
+    pub fn sample2(&self, noise: fn(u32, f32, f32) -> f32, seed: u32, x: f32, y: f32) -> f32 {
+        self.sum(|octave, frequency| noise(seed.wrapping_add(octave), x * frequency, y * frequency))
+    }
+
+    /// Sums octaves of a 3D noise function, normalized to the range of the noise.
+    pub fn sample3(
+        &self,
+        noise: fn(u32, f32, f32, f32) -> f32,
+        seed: u32,
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> f32 {
+        self.sum(|octave, frequency| {
+            noise(
+                seed.wrapping_add(octave),
+                x * frequency,
+                y * frequency,
+                z * frequency,
+            )
+        })
+    }
+
+    /// Ridged multifractal noise in `[0, 1]`, with sharp crests where the noise crosses zero and
+    /// finer detail along the crests than in the valleys.
+    pub fn ridged2(&self, noise: fn(u32, f32, f32) -> f32, seed: u32, x: f32, y: f32) -> f32 {
+        let mut weight = 1.0;
+        self.sum(|octave, frequency| {
+            let ridge = 1.0 - noise(seed.wrapping_add(octave), x * frequency, y * frequency).abs();
+            let ridge = ridge * ridge * weight;
+            weight = ridge.clamp(0.0, 1.0);
+            ridge
+        })
+    }
+
+    fn sum(&self, mut octave: impl FnMut(u32, f32) -> f32) -> f32 {
+        let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+        for i in 0..self.octaves {
+            sum += amplitude * octave(i, frequency);
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = (f32, f32, f32)> {
+        (0..5000).map(|i| {
+            let i = i as f32;
+            (i * 0.173 - 300.0, i * 0.091 + 3.0, i * -0.057)
+        })
+    }
+
+    #[test]
+    fn seeded() {
+        let differ = points()
+            .filter(|&(x, y, _)| perlin2(1, x, y) != perlin2(2, x, y))
+            .count();
+        assert!(differ > 4000);
+        for (x, y, z) in points() {
+            assert_eq!(perlin3(9, x, y, z), perlin3(9, x, y, z));
+            assert_eq!(simplex2(9, x, y), simplex2(9, x, y));
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let fbm = Fbm::default();
+        for (x, y, z) in points() {
+            assert!(perlin2(3, x, y).abs() <= 1.0);
+            assert!(perlin3(3, x, y, z).abs() <= 1.05);
+            assert!(simplex2(3, x, y).abs() <= 1.0);
+            assert!(fbm.sample2(perlin2, 3, x, y).abs() <= 1.0);
+            assert!((0.0..=1.0).contains(&fbm.ridged2(simplex2, 3, x, y)));
+        }
+        assert_eq!(perlin2(3, 5.0, -7.0), 0.0);
+        assert_eq!(perlin3(3, 5.0, -7.0, 2.0), 0.0);
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Shader versions of xsecurelock_saver::noise. Keep in sync with noise.rs and noise.glsl.
+
+fn noise_hash(input: u32) -> u32 {
+    var x: u32 = input;
+    x = x ^ (x >> 16u);
+    x = x * 2146121005u;
+    x = x ^ (x >> 15u);
+    x = x * 2221713035u;
+    x = x ^ (x >> 16u);
+    return x;
+}
+
+fn noise_hash2(seed: u32, p: vec2<i32>) -> u32 {
+    return noise_hash(noise_hash(noise_hash(seed) ^ bitcast<u32>(p.x)) ^ bitcast<u32>(p.y));
+}
+
+fn noise_hash3(seed: u32, p: vec3<i32>) -> u32 {
+    return noise_hash(noise_hash2(seed, p.xy) ^ bitcast<u32>(p.z));
+}
+
+fn noise_grad2(h: u32, d: vec2<f32>) -> f32 {
+    let g: u32 = h & 7u;
+    if (g == 0u) { return d.x + d.y; }
+    if (g == 1u) { return d.x - d.y; }
+    if (g == 2u) { return -d.x + d.y; }
+    if (g == 3u) { return -d.x - d.y; }
+    if (g == 4u) { return d.x; }
+    if (g == 5u) { return -d.x; }
+    if (g == 6u) { return d.y; }
+    return -d.y;
+}
+
+fn noise_grad3(h: u32, d: vec3<f32>) -> f32 {
+    let g: u32 = h % 12u;
+    if (g == 0u) { return d.x + d.y; }
+    if (g == 1u) { return -d.x + d.y; }
+    if (g == 2u) { return d.x - d.y; }
+    if (g == 3u) { return -d.x - d.y; }
+    if (g == 4u) { return d.x + d.z; }
+    if (g == 5u) { return -d.x + d.z; }
+    if (g == 6u) { return d.x - d.z; }
+    if (g == 7u) { return -d.x - d.z; }
+    if (g == 8u) { return d.y + d.z; }
+    if (g == 9u) { return -d.y + d.z; }
+    if (g == 10u) { return d.y - d.z; }
+    return -d.y - d.z;
+}
+
+fn noise_fade(t: vec3<f32>) -> vec3<f32> {
+    return t * t * t * (t * (t * 6.0 - vec3<f32>(15.0)) + vec3<f32>(10.0));
+}
+
+fn perlin2(seed: u32, p: vec2<f32>) -> f32 {
+    let f: vec2<f32> = floor(p);
+    let i: vec2<i32> = vec2<i32>(f);
+    let d: vec2<f32> = p - f;
+    let c00: f32 = noise_grad2(noise_hash2(seed, i), d);
+    let c10: f32 = noise_grad2(noise_hash2(seed, i + vec2<i32>(1, 0)), d - vec2<f32>(1.0, 0.0));
+    let c01: f32 = noise_grad2(noise_hash2(seed, i + vec2<i32>(0, 1)), d - vec2<f32>(0.0, 1.0));
+    let c11: f32 = noise_grad2(noise_hash2(seed, i + vec2<i32>(1, 1)), d - vec2<f32>(1.0, 1.0));
+    let u: vec3<f32> = noise_fade(vec3<f32>(d, 0.0));
+    return mix(mix(c00, c10, u.x), mix(c01, c11, u.x), u.y);
+}
+
+fn perlin3(seed: u32, p: vec3<f32>) -> f32 {
+    let f: vec3<f32> = floor(p);
+    let i: vec3<i32> = vec3<i32>(f);
+    let d: vec3<f32> = p - f;
+    let c000: f32 = noise_grad3(noise_hash3(seed, i), d);
+    let c100: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(1, 0, 0)), d - vec3<f32>(1.0, 0.0, 0.0));
+    let c010: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(0, 1, 0)), d - vec3<f32>(0.0, 1.0, 0.0));
+    let c110: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(1, 1, 0)), d - vec3<f32>(1.0, 1.0, 0.0));
+    let c001: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(0, 0, 1)), d - vec3<f32>(0.0, 0.0, 1.0));
+    let c101: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(1, 0, 1)), d - vec3<f32>(1.0, 0.0, 1.0));
+    let c011: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(0, 1, 1)), d - vec3<f32>(0.0, 1.0, 1.0));
+    let c111: f32 = noise_grad3(noise_hash3(seed, i + vec3<i32>(1, 1, 1)), d - vec3<f32>(1.0, 1.0, 1.0));
+    let u: vec3<f32> = noise_fade(d);
+    let near: f32 = mix(mix(c000, c100, u.x), mix(c010, c110, u.x), u.y);
+    let far: f32 = mix(mix(c001, c101, u.x), mix(c011, c111, u.x), u.y);
+    return mix(near, far, u.z);
+}
+
+fn noise_simplex_corner(seed: u32, i: vec2<i32>, d: vec2<f32>) -> f32 {
+    var t: f32 = 0.5 - dot(d, d);
+    if (t < 0.0) {
+        return 0.0;
+    }
+    t = t * t;
+    return t * t * noise_grad2(noise_hash2(seed, i), d);
+}
+
+fn simplex2(seed: u32, p: vec2<f32>) -> f32 {
+    let F2: f32 = 0.3660254;
+    let G2: f32 = 0.21132487;
+    let f: vec2<f32> = floor(p + vec2<f32>((p.x + p.y) * F2));
+    let i: vec2<i32> = vec2<i32>(f);
+    let d0: vec2<f32> = p - (f - vec2<f32>((f.x + f.y) * G2));
+    var o: vec2<i32> = vec2<i32>(0, 1);
+    if (d0.x > d0.y) {
+        o = vec2<i32>(1, 0);
+    }
+    let d1: vec2<f32> = d0 - vec2<f32>(o) + vec2<f32>(G2);
+    let d2: vec2<f32> = d0 - vec2<f32>(1.0 - 2.0 * G2);
+    return 70.0 * (noise_simplex_corner(seed, i, d0) + noise_simplex_corner(seed, i + o, d1)
+        + noise_simplex_corner(seed, i + vec2<i32>(1, 1), d2));
+}
+
+fn fbm_perlin2(seed: u32, p: vec2<f32>, octaves: i32, lacunarity: f32, gain: f32) -> f32 {
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var frequency: f32 = 1.0;
+    for (var i: i32 = 0; i < octaves; i = i + 1) {
+        sum = sum + amplitude * perlin2(seed + u32(i), p * frequency);
+        total = total + amplitude;
+        amplitude = amplitude * gain;
+        frequency = frequency * lacunarity;
+    }
+    if (total > 0.0) {
+        return sum / total;
+    }
+    return 0.0;
+}
+
+fn fbm_perlin3(seed: u32, p: vec3<f32>, octaves: i32, lacunarity: f32, gain: f32) -> f32 {
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var frequency: f32 = 1.0;
+    for (var i: i32 = 0; i < octaves; i = i + 1) {
+        sum = sum + amplitude * perlin3(seed + u32(i), p * frequency);
+        total = total + amplitude;
+        amplitude = amplitude * gain;
+        frequency = frequency * lacunarity;
+    }
+    if (total > 0.0) {
+        return sum / total;
+    }
+    return 0.0;
+}
+
+fn fbm_simplex2(seed: u32, p: vec2<f32>, octaves: i32, lacunarity: f32, gain: f32) -> f32 {
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var frequency: f32 = 1.0;
+    for (var i: i32 = 0; i < octaves; i = i + 1) {
+        sum = sum + amplitude * simplex2(seed + u32(i), p * frequency);
+        total = total + amplitude;
+        amplitude = amplitude * gain;
+        frequency = frequency * lacunarity;
+    }
+    if (total > 0.0) {
+        return sum / total;
+    }
+    return 0.0;
+}
+
+fn ridged_perlin2(seed: u32, p: vec2<f32>, octaves: i32, lacunarity: f32, gain: f32) -> f32 {
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var frequency: f32 = 1.0;
+    var weight: f32 = 1.0;
+    for (var i: i32 = 0; i < octaves; i = i + 1) {
+        var ridge: f32 = 1.0 - abs(perlin2(seed + u32(i), p * frequency));
+        ridge = ridge * ridge * weight;
+        weight = clamp(ridge, 0.0, 1.0);
+        sum = sum + amplitude * ridge;
+        total = total + amplitude;
+        amplitude = amplitude * gain;
+        frequency = frequency * lacunarity;
+    }
+    if (total > 0.0) {
+        return sum / total;
+    }
+    return 0.0;
+}
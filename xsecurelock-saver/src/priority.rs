@@ -0,0 +1,147 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scheduling priority, so a saver's background work never competes with the user's processes
+//! while the machine locks and unlocks.
+//!
+//! * `XSECURELOCK_SAVER_NICE`: nice level to run at, from 0 to 19. The priority is only ever
+//!   lowered, so a level below the saver's current one is ignored. Unchanged unless set.
+//! * `XSECURELOCK_SAVER_SCHED_IDLE`: set to 1 to run under the `SCHED_IDLE` policy, which only
+//!   gives the saver the CPU when nothing else wants it.
+//!
+//! Both [`crate::simple`] and [`crate::engine`] call [`lower_from_env`] when the saver starts,
+//! before spawning any threads, since Linux sets priority per thread and new threads inherit it
+//! from the thread which spawns them. Threads a saver spawns itself should be named with
+//! [`spawn_named`], so they can be told apart in `top -H` and in crash reports.
+
+use std::io;
+use std::thread::{self, JoinHandle};
+
+use log::{info, warn};
+
+use crate::cli;
+use crate::config_help::Setting;
+use crate::env::parse_var;
+
+const NICE_VAR: &str = "XSECURELOCK_SAVER_NICE";
+const SCHED_IDLE_VAR: &str = "XSECURELOCK_SAVER_SCHED_IDLE";
+
+/// Settings for the saver's scheduling priority, for [`crate::config_help`].
+pub(crate) fn settings() -> Vec<Setting> {
+    vec![
+        Setting::new::<i32>(
+            NICE_VAR,
+            "Nice level to run at, from 0 to 19. Only ever lowers the saver's priority. Unchanged \
+             unless set.",
+        ),
+        Setting::new::<bool>(
+            SCHED_IDLE_VAR,
+            "Set to 1 to run under the SCHED_IDLE policy, only using the CPU when nothing else \
+             wants it.",
+        ),
+    ]
+}
+
+/// Lowers the priority of the calling thread, and so of any threads it spawns afterwards, as
+/// configured in the environment. Failures are logged and otherwise ignored.
+pub fn lower_from_env() {
+    if let Some(nice) = parse_var(NICE_VAR, |nice: &i32| (0..=19).contains(nice)) {
+        match set_nice(nice) {
+            Ok(true) => info!("Running at nice level {}", nice),
+            Ok(false) => info!("Already running at nice level {} or lower priority", nice),
+            Err(err) => warn!("Unable to set nice level {}: {}", nice, err),
+        }
+    }
+    if cli::is_set(SCHED_IDLE_VAR) {
+        match set_sched_idle() {
+            Ok(()) => info!("Running under SCHED_IDLE"),
+            Err(err) => warn!("Unable to switch to SCHED_IDLE: {}", err),
+        }
+    }
+}
+
+/// Spawns a thread with the given name, panicking if the thread can't be created like
+/// [`thread::spawn`]. Linux shows at most 15 bytes of the name.
+pub fn spawn_named<F, T>(name: impl Into<String>, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new()
+        .name(name.into())
+        .spawn(f)
+        .expect("failed to spawn thread")
+}
+
+/// Sets the calling thread's nice level, unless it is already at that level or higher. Returns
+/// whether the level changed.
+fn set_nice(nice: i32) -> io::Result<bool> {
+    // -1 is a valid nice level, so errors from getpriority are told apart by errno.
+    let current = unsafe {
+        *libc::__errno_location() = 0;
+        libc::getpriority(libc::PRIO_PROCESS, 0)
+    };
+    if current == -1 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(0) {
+            return Err(err);
+        }
+    }
+    if current >= nice {
+        return Ok(false);
+    }
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(true)
+}
+
+/// Switches the calling thread to the `SCHED_IDLE` policy.
+fn set_sched_idle() -> io::Result<()> {
+    let param = libc::sched_param { sched_priority: 0 };
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_threads() {
+        let name = spawn_named("saver-worker", || {
+            thread::current().name().map(String::from)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(name.as_deref(), Some("saver-worker"));
+    }
+
+    #[test]
+    fn only_lowers_priority() {
+        // Run in a thread of its own, since the level sticks to the thread.
+        spawn_named("nice-test", || {
+            let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+            assert!(!set_nice(current).unwrap());
+            if current < 19 {
+                assert!(set_nice(current + 1).unwrap());
+                assert!(!set_nice(current).unwrap());
+            }
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The report printed by `--self-test`, which is meant to be run before trusting a saver with the
+//! lock screen.
+//!
+//! Both [`crate::simple`] and [`crate::engine`] run the self test in place of the saver's usual
+//! loop. They check the config and color transform, initialize the renderer without a window, run
+//! [`FRAMES`] frames of the saver, then print the [`SelfTest`] and exit, with a non-zero status if
+//! any check failed. Engine savers can add checks of their own, such as opening a database, from
+//! their plugins through the `SelfTest` resource, which is only present during a self test. A panic
+//! fails the test too, with the panic's message.
+
+use std::fmt;
+use std::process;
+use std::time::Duration;
+
+use crate::cli;
+use crate::color::ColorTransform;
+
+/// Number of frames the self test runs.
+pub const FRAMES: u32 = 60;
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked.
+    pub name: String,
+    /// What was found if the check passed, or what went wrong if it failed.
+    pub outcome: Result<String, String>,
+}
+
+/// The checks made so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTest {
+    checks: Vec<Check>,
+}
+
+impl SelfTest {
+    /// A self test starting with the checks every saver shares: the config file and the output
+    /// color transform.
+    pub fn start() -> Self {
+        let mut test = Self::default();
+        // The command line has already loaded the config file, or exited if it couldn't.
+        test.pass(
+            "config",
+            match cli::config_path() {
+                Some(path) => format!("using {}", path.display()),
+                None => "no config file given".to_string(),
+            },
+        );
+        let transform = ColorTransform::from_env();
+        test.pass(
+            "color transform",
+            if transform.is_identity() {
+                "none".to_string()
+            } else {
+                format!("{:?}", transform)
+            },
+        );
+        test
+    }
+
+    /// Records a check which passed, with what was found.
+    pub fn pass(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            outcome: Ok(detail.into()),
+        });
+    }
+
+    /// Records a check which failed, with what went wrong.
+    pub fn fail(&mut self, name: impl Into<String>, error: impl fmt::Display) {
+        self.checks.push(Check {
+            name: name.into(),
+            outcome: Err(error.to_string()),
+        });
+    }
+
+    /// Records a check of `result`, described by `detail` if it is `Ok`. Returns the value, if
+    /// there was one, so later checks can use it.
+    pub fn record<T, E: fmt::Display>(
+        &mut self,
+        name: impl Into<String>,
+        result: Result<T, E>,
+        detail: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.pass(name, detail(&value));
+                Some(value)
+            }
+            Err(err) => {
+                self.fail(name, err);
+                None
+            }
+        }
+    }
+
+    /// The checks made so far, in order.
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.outcome.is_ok())
+    }
+
+    /// Prints the report and exits, with a non-zero status if any check failed.
+    pub fn finish(&self) -> ! {
+        print!("{}", self);
+        process::exit(if self.passed() { 0 } else { 1 });
+    }
+}
+
+impl fmt::Display for SelfTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match check.outcome {
+                Ok(ref detail) => writeln!(f, "ok    {}: {}", check.name, detail)?,
+                Err(ref error) => writeln!(f, "FAIL  {}: {}", check.name, error)?,
+            }
+        }
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| check.outcome.is_err())
+            .count();
+        if failed == 0 {
+            writeln!(f, "All {} checks passed", self.checks.len())
+        } else {
+            writeln!(f, "{} of {} checks failed", failed, self.checks.len())
+        }
+    }
+}
+
+/// Describes how long the self test's frames took, as the detail of the frames check.
+pub fn describe_frame_times(times: &[Duration]) -> String {
+    if times.is_empty() {
+        return "no frames".to_string();
+    }
+    let total: Duration = times.iter().sum();
+    let worst = times.iter().max().copied().unwrap_or_default();
+    format!(
+        "{} frames, mean {:.1} ms, worst {:.1} ms",
+        times.len(),
+        total.as_secs_f64() * 1000.0 / times.len() as f64,
+        worst.as_secs_f64() * 1000.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_failures() {
+        let mut test = SelfTest::default();
+        test.pass("renderer", "offscreen");
+        assert_eq!(
+            test.record("frames", Ok::<_, String>(3), |n| n.to_string()),
+            Some(3)
+        );
+        assert!(test.passed());
+        assert_eq!(
+            test.to_string(),
+            "ok    renderer: offscreen\nok    frames: 3\nAll 2 checks passed\n"
+        );
+
+        assert_eq!(
+            test.record("database", Err::<(), _>("locked"), |_| unreachable!()),
+            None
+        );
+        assert!(!test.passed());
+        assert_eq!(
+            test.to_string().lines().skip(2).collect::<Vec<_>>(),
+            vec!["FAIL  database: locked", "1 of 3 checks failed"]
+        );
+    }
+
+    #[test]
+    fn describes_frame_times() {
+        assert_eq!(describe_frame_times(&[]), "no frames");
+        assert_eq!(
+            describe_frame_times(&[Duration::from_millis(2), Duration::from_millis(6)]),
+            "2 frames, mean 4.0 ms, worst 6.0 ms"
+        );
+    }
+}
@@ -22,14 +22,29 @@
 //! XSecurelock, this will create a small window for testing purposes.
 //!
 //! See `saver_sfmlrect` for basic example usage.
+//!
+//! Raster savers which compute every pixel themselves can instead implement [`PixelSaver`] and run
+//! with [`run_pixel_saver`], which handles uploading the pixels without the saver needing any SFML
+//! types. See `saver_colorstatic` for example usage.
 
 use std::env;
+use std::time::{Duration, Instant};
+
+use crate::cli;
+use crate::color::{fade_in_brightness, fade_in_from_env, ColorTransform, OutputRect};
+use crate::crash;
+use crate::priority;
+use crate::self_test::{self, SelfTest};
 
 use log::info;
 
-use sfml::graphics::{Color, RenderTarget, RenderWindow};
-use sfml::system::Vector2u;
-use sfml::window::{ContextSettings, Style};
+use sfml::graphics::{
+    Color, RectangleShape, RenderStates, RenderTarget, RenderTexture, RenderWindow, Shader, Shape,
+    Sprite, Texture, Transformable,
+};
+use sfml::system::{Vector2f, Vector2u, Vector3f};
+use sfml::window::{ContextSettings, Event, Style};
+use sfml::SfBox;
 
 /// A screensaver which can be run on an SFML RenderTarget.
 pub trait Screensaver {
@@ -43,31 +58,364 @@ pub trait Screensaver {
         T: RenderTarget;
 }
 
+/// A screensaver which draws by filling a buffer of RGBA pixels.
+pub trait PixelSaver {
+    /// Fill `buf` with the next frame. `size` is `(width, height)` in pixels, and `buf` holds
+    /// `width * height` pixels in row-major order starting from the top left, with 4 bytes per
+    /// pixel in RGBA order. The buffer keeps its contents between frames, so savers only need to
+    /// write the pixels which change. Will be run as fast as possible by [`run_pixel_saver`].
+    fn fill(&mut self, buf: &mut [u8], size: (u32, u32));
+
+    /// Factor by which the pixel buffer is smaller than the window. The buffer is stretched to
+    /// cover the window, so each buffer pixel covers a `downscale` by `downscale` block of the
+    /// screen. Read once when the saver starts. Defaults to 1, for a buffer the size of the window.
+    fn downscale(&self) -> u32 {
+        1
+    }
+}
+
+/// Adapts a [`PixelSaver`] to a [`Screensaver`], streaming its buffer into a persistent texture.
+struct PixelScreensaver<S> {
+    saver: S,
+    buf: Vec<u8>,
+    size: (u32, u32),
+    scale: f32,
+    texture: SfBox<Texture>,
+}
+
+impl<S: PixelSaver> Screensaver for PixelScreensaver<S> {
+    fn update(&mut self) {
+        let (width, height) = self.size;
+        self.saver.fill(&mut self.buf, self.size);
+        self.texture
+            .update_from_pixels(&self.buf, width, height, 0, 0);
+    }
+
+    fn draw<T>(&self, target: &mut T)
+    where
+        T: RenderTarget,
+    {
+        let mut sprite = Sprite::with_texture(&self.texture);
+        sprite.set_scale((self.scale, self.scale));
+        target.draw(&sprite);
+    }
+}
+
+/// Run a pixel screensaver created by the given function. The argument to create will be the size
+/// of the window as `(width, height)`; frames are filled at that size divided by the saver's
+/// [`PixelSaver::downscale`], rounded up.
+pub fn run_pixel_saver<F, S>(create_saver: F)
+where
+    F: FnOnce((u32, u32)) -> S,
+    S: PixelSaver,
+{
+    run_saver(|window_size| {
+        let saver = create_saver((window_size.x, window_size.y));
+        let downscale = saver.downscale().max(1);
+        let size = (
+            (window_size.x + downscale - 1) / downscale,
+            (window_size.y + downscale - 1) / downscale,
+        );
+        info!(
+            "Filling {}x{} pixels at 1/{} scale",
+            size.0, size.1, downscale
+        );
+        let texture = Texture::new(size.0, size.1).expect("could not create pixel texture");
+        PixelScreensaver {
+            saver,
+            // Start opaque black so savers which only write changed pixels have a sane first frame.
+            buf: [0, 0, 0, 255].repeat(size.0 as usize * size.1 as usize),
+            size,
+            scale: downscale as f32,
+            texture,
+        }
+    });
+}
+
 /// Run a screensaver created by the given function. The argument to create will be the size of the
 /// render target.
+///
+/// The saver fades in from black over `XSECURELOCK_SAVER_FADE_IN_SECONDS`; see [`crate::color`].
+/// With `--preview`, the window has a title bar and closes on any key. With `--headless`, the saver
+/// draws into an offscreen texture instead of a window, and with `--self-test` it draws a few
+/// frames offscreen and reports whether it drew anything; see [`crate::self_test`]. The saver's
+/// priority is lowered and crash reports are set up, as described in [`crate::priority`] and
+/// [`crate::crash`], before anything else starts.
 pub fn run_saver<F, S>(create_saver: F)
 where
     F: FnOnce(Vector2u) -> S,
     S: Screensaver,
 {
+    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"))
+        .try_init();
+    priority::lower_from_env();
+    crash::install();
     sigint::init();
+    if cli::is_self_test() {
+        run_self_test(create_saver);
+    }
+    if cli::is_headless() {
+        run_headless(create_saver);
+        return;
+    }
 
-    let mut window = open_window();
+    let preview = cli::is_preview();
+    let mut window = open_window(preview);
+    // Cover whatever was left in the window before the saver is ready to draw, which can take a
+    // while.
+    window.clear(Color::BLACK);
+    window.display();
     let mut saver = create_saver(window.size());
 
+    let fade_in = fade_in_from_env();
+    let (position, size) = (window.position(), window.size());
+    let transform = ColorTransform::from_env().calibrate_for(OutputRect {
+        x: position.x,
+        y: position.y,
+        width: size.x,
+        height: size.y,
+    });
+    if transform.is_identity() {
+        let start = Instant::now();
+        while !sigint::received_sigint() {
+            if handle_events(&mut window, preview) {
+                break;
+            }
+
+            saver.update();
+
+            window.clear(Color::GREEN);
+            saver.draw(&mut window);
+            draw_fade(&mut window, start.elapsed(), fade_in);
+            window.display();
+        }
+    } else {
+        info!("Applying output color transform {:?}", transform);
+        run_color_transformed(&mut window, &mut saver, &transform, fade_in, preview);
+    }
+    info!("Shutting Down");
+}
+
+/// Size of the texture headless savers draw into.
+const HEADLESS_SIZE: (u32, u32) = (1200, 900);
+
+/// Run the saver loop without a window, drawing each frame into an offscreen texture.
+fn run_headless<F, S>(create_saver: F)
+where
+    F: FnOnce(Vector2u) -> S,
+    S: Screensaver,
+{
+    info!("Running headless");
+    let (width, height) = HEADLESS_SIZE;
+    let mut frame =
+        RenderTexture::new(width, height, false).expect("could not create frame texture");
+    let mut saver = create_saver(frame.size());
+    while !sigint::received_sigint() {
+        saver.update();
+
+        frame.clear(Color::GREEN);
+        saver.draw(&mut frame);
+        frame.display();
+    }
+    info!("Shutting Down");
+}
+
+/// Run the saver for `--self-test`, drawing [`self_test::FRAMES`] frames into an offscreen texture,
+/// through the color transform if there is one, then print the report and exit.
+fn run_self_test<F, S>(create_saver: F) -> !
+where
+    F: FnOnce(Vector2u) -> S,
+    S: Screensaver,
+{
+    let mut test = SelfTest::start();
+    let (width, height) = HEADLESS_SIZE;
+    let frame = test.record(
+        "renderer",
+        RenderTexture::new(width, height, false).ok_or("could not create an offscreen texture"),
+        |_| format!("drawing into a {}x{} offscreen texture", width, height),
+    );
+    let mut frame = match frame {
+        Some(frame) => frame,
+        None => test.finish(),
+    };
+    let transform = ColorTransform::from_env();
+    let mut transformed = if transform.is_identity() {
+        None
+    } else {
+        let shader = Shader::from_memory(None, None, Some(COLOR_TRANSFORM_SHADER))
+            .ok_or("could not compile the shader");
+        let output = RenderTexture::new(width, height, false)
+            .ok_or("could not create an offscreen texture for the output");
+        match test.record(
+            "color transform shader",
+            shader.and_then(|shader| output.map(|output| (shader, output))),
+            |_| "compiled".to_string(),
+        ) {
+            Some((mut shader, output)) => {
+                set_constant_uniforms(&mut shader, &transform);
+                Some((shader, output))
+            }
+            None => test.finish(),
+        }
+    };
+
+    let mut saver = create_saver(frame.size());
+    let mut times = Vec::with_capacity(self_test::FRAMES as usize);
+    for _ in 0..self_test::FRAMES {
+        let start = Instant::now();
+        saver.update();
+
+        frame.clear(Color::GREEN);
+        saver.draw(&mut frame);
+        frame.display();
+
+        if let Some((ref mut shader, ref mut output)) = transformed {
+            let [r, g, b] = transform.current_color_scale();
+            shader.set_uniform_vec3("color_scale", Vector3f::new(r, g, b));
+            let states = RenderStates {
+                shader: Some(&*shader),
+                ..Default::default()
+            };
+            output.draw_with_renderstates(&Sprite::with_texture(frame.texture()), &states);
+            output.display();
+        }
+        times.push(start.elapsed());
+    }
+    test.pass("frames", self_test::describe_frame_times(&times));
+
+    // Frames are cleared to green first, so a saver which draws nothing leaves them all green.
+    let green = [
+        Color::GREEN.r,
+        Color::GREEN.g,
+        Color::GREEN.b,
+        Color::GREEN.a,
+    ];
+    match frame.texture().copy_to_image() {
+        Some(image) if image.pixel_data().chunks(4).any(|pixel| pixel != green) => {
+            test.pass("output", "the saver drew over the frame")
+        }
+        Some(_) => test.fail("output", "the saver drew nothing"),
+        None => test.fail("output", "could not read the frame back"),
+    }
+    test.finish()
+}
+
+/// Handle the window's events, returning whether the saver should stop. Only a preview stops,
+/// when its window is closed or any key is pressed.
+fn handle_events(window: &mut RenderWindow, preview: bool) -> bool {
+    let mut close = false;
+    while let Some(event) = window.poll_event() {
+        close |= preview && matches!(event, Event::Closed | Event::KeyPressed { .. });
+    }
+    close
+}
+
+/// Fragment shader applying the [`ColorTransform`] to the saver's rendered frame.
+const COLOR_TRANSFORM_SHADER: &str = r#"
+uniform sampler2D texture;
+uniform vec3 color_scale;
+uniform float inverse_gamma;
+uniform vec3 palette_levels;
+uniform float dither;
+
+// Ordered dithering threshold for a pixel from a 4x4 Bayer matrix, as in color::bayer_threshold.
+float bayer_threshold(vec2 pixel) {
+    vec2 p = mod(floor(pixel), 4.0);
+    vec2 low = mod(p, 2.0);
+    vec2 high = floor(p / 2.0);
+    float index = 4.0 * (2.0 * abs(low.x - low.y) + low.y) + 2.0 * abs(high.x - high.y) + high.y;
+    return (index + 0.5) / 16.0;
+}
+
+void main() {
+    vec4 color = texture2D(texture, gl_TexCoord[0].xy);
+    vec3 transformed = pow(max(color.rgb * color_scale, 0.0), vec3(inverse_gamma));
+    if (palette_levels.r > 0.0) {
+        float threshold = dither > 0.0 ? bayer_threshold(gl_FragCoord.xy) : 0.5;
+        vec3 levels = floor(transformed * palette_levels + threshold);
+        transformed = clamp(levels / palette_levels, 0.0, 1.0);
+    }
+    gl_FragColor = vec4(transformed, color.a);
+}
+"#;
+
+/// Sets the uniforms of the color transform shader which don't change between frames.
+fn set_constant_uniforms(shader: &mut Shader, transform: &ColorTransform) {
+    shader.set_uniform_current_texture("texture");
+    shader.set_uniform_float("inverse_gamma", 1.0 / transform.gamma);
+    // SFML's output isn't sRGB, so the palette applies directly to the shader's output color.
+    let (levels, dither) = match transform.palette {
+        Some(palette) => (palette.max_levels(), palette.dither),
+        None => ([0.0; 3], false),
+    };
+    let [r, g, b] = levels;
+    shader.set_uniform_vec3("palette_levels", Vector3f::new(r, g, b));
+    shader.set_uniform_float("dither", if dither { 1.0 } else { 0.0 });
+}
+
+/// Approximate gamma of the display, for fading in linear light on SFML's gamma-encoded output.
+const DISPLAY_GAMMA: f32 = 2.2;
+
+/// Darken everything drawn on the target so far for the fade in, `elapsed` after the first frame.
+fn draw_fade<T: RenderTarget>(target: &mut T, elapsed: Duration, fade_in: Duration) {
+    let brightness = fade_in_brightness(elapsed, fade_in);
+    if brightness >= 1.0 {
+        return;
+    }
+    // Scaling linear light by `brightness` scales the gamma-encoded color by its encoded value.
+    let alpha = 1.0 - brightness.powf(1.0 / DISPLAY_GAMMA);
+    let size = target.size();
+    let mut overlay = RectangleShape::with_size(Vector2f::new(size.x as f32, size.y as f32));
+    overlay.set_fill_color(Color::rgba(0, 0, 0, (alpha * 255.0).round() as u8));
+    // Cover the whole target even if the saver moved the view.
+    let view = target.view().to_owned();
+    let default_view = target.default_view().to_owned();
+    target.set_view(&default_view);
+    target.draw(&overlay);
+    target.set_view(&view);
+}
+
+/// Run the saver loop, drawing each frame to an offscreen texture which is then copied to the
+/// window through the color transform shader.
+fn run_color_transformed<S: Screensaver>(
+    window: &mut RenderWindow,
+    saver: &mut S,
+    transform: &ColorTransform,
+    fade_in: Duration,
+    preview: bool,
+) {
+    let size = window.size();
+    let mut frame =
+        RenderTexture::new(size.x, size.y, false).expect("could not create frame texture");
+    let mut shader = Shader::from_memory(None, None, Some(COLOR_TRANSFORM_SHADER))
+        .expect("could not compile color transform shader");
+    set_constant_uniforms(&mut shader, transform);
+
+    let start = Instant::now();
     while !sigint::received_sigint() {
-        while let Some(_) = window.poll_event() {}
+        if handle_events(window, preview) {
+            break;
+        }
 
         saver.update();
 
-        window.clear(Color::GREEN);
-        saver.draw(&mut window);
+        frame.clear(Color::GREEN);
+        saver.draw(&mut frame);
+        frame.display();
+
+        let [r, g, b] = transform.current_color_scale();
+        shader.set_uniform_vec3("color_scale", Vector3f::new(r, g, b));
+        let states = RenderStates {
+            shader: Some(&shader),
+            ..Default::default()
+        };
+        window.draw_with_renderstates(&Sprite::with_texture(frame.texture()), &states);
+        draw_fade(window, start.elapsed(), fade_in);
         window.display();
     }
-    info!("Shutting Down");
 }
 
-pub(crate) fn open_window() -> RenderWindow {
+pub(crate) fn open_window(preview: bool) -> RenderWindow {
     let mut settings = ContextSettings::default();
     settings.set_antialiasing_level(4);
     let window = match env::var("XSCREENSAVER_WINDOW") {
@@ -78,6 +426,15 @@ pub(crate) fn open_window() -> RenderWindow {
             let window_handle = window_id_str.parse().expect("window id was not an integer");
             unsafe { RenderWindow::from_handle(window_handle, &settings) }
         }
+        Err(_) if preview => {
+            info!("Creating preview window");
+            RenderWindow::new(
+                (1200, 900),
+                "Screensaver Preview",
+                Style::DEFAULT,
+                &settings,
+            )
+        }
         Err(_) => {
             info!("Creating new window");
             RenderWindow::new(
@@ -0,0 +1,397 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Frame timing shared by the simple and engine savers. [`SaverTime`] measures frames with the
+//! monotonic clock, so wall clock changes such as NTP steps never show up as a frame, and caps how
+//! much time a single frame can cover. After a suspend, or a long stall while the screen is locked,
+//! the saver carries on from where it was instead of simulating the whole gap in one step.
+//!
+//! Engine savers get a `SaverTime` resource which is updated at the start of every frame, and
+//! should use it instead of Bevy's `Time` for simulation, scoring, and timers. Simple savers keep
+//! their own and call [`SaverTime::update`] once per frame. Either way, the cap and a slow motion
+//! factor can be set in the environment; see [`SaverTime::from_env`].
+//!
+//! Savers which simulate in fixed steps should feed each frame's delta to a [`FixedTimestep`],
+//! which also caps how many steps a single frame can run. Savers which need to run the same way
+//! every time, such as to replay a simulation exactly, can instead give every frame the same
+//! delta with [`SaverTime::set_fixed_delta`].
+//!
+//! Stiff simulations which need shorter steps to stay accurate than they need to be drawn at can
+//! split each step into [`PhysicsSubsteps`].
+//!
+//! A paused `SaverTime`, see [`SaverTime::set_paused`], gives every frame no time at all, so the
+//! simulation stays frozen, and picks up from where it stopped when resumed.
+
+use std::time::{Duration, Instant};
+
+use crate::config_help::Setting;
+use crate::env::parse_var;
+
+const MAX_DELTA_VAR: &str = "XSECURELOCK_SAVER_MAX_DELTA_MS";
+const TIME_SCALE_VAR: &str = "XSECURELOCK_SAVER_TIME_SCALE";
+
+/// Default limit on how much time a single frame can cover.
+pub const DEFAULT_MAX_DELTA: Duration = Duration::from_millis(100);
+
+/// Frame timer measured with a monotonic clock, with the time between frames clamped.
+#[derive(Debug, Clone)]
+pub struct SaverTime {
+    max_delta: Duration,
+    time_scale: f32,
+    fixed_delta: Option<Duration>,
+    paused: bool,
+    last_update: Option<Instant>,
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl Default for SaverTime {
+    fn default() -> Self {
+        Self::with_max_delta(DEFAULT_MAX_DELTA)
+    }
+}
+
+impl SaverTime {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a timer where frames cover at most `max_delta`.
+    pub fn with_max_delta(max_delta: Duration) -> Self {
+        Self {
+            max_delta,
+            time_scale: 1.0,
+            fixed_delta: None,
+            paused: false,
+            last_update: None,
+            delta: Duration::from_secs(0),
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    /// Create a timer with the maximum delta and time scale set in the environment, or the
+    /// defaults for any which aren't set.
+    pub fn from_env() -> Self {
+        let max_delta = parse_var(MAX_DELTA_VAR, |millis: &u64| *millis > 0)
+            .map_or(DEFAULT_MAX_DELTA, Duration::from_millis);
+        let mut time = Self::with_max_delta(max_delta);
+        if let Some(time_scale) = parse_var(TIME_SCALE_VAR, |scale: &f32| {
+            scale.is_finite() && *scale > 0.0
+        }) {
+            time.set_time_scale(time_scale);
+        }
+        time
+    }
+
+    /// Scale time by `time_scale`, for slow motion below 1 or fast forward above it. Applied after
+    /// clamping, so frames cover at most the maximum delta times this.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Make every frame after the first cover exactly `delta`, however long it really took, or
+    /// go back to measuring frames with None. Time scaling still applies.
+    pub fn set_fixed_delta(&mut self, delta: Option<Duration>) {
+        self.fixed_delta = delta;
+    }
+
+    pub fn fixed_delta(&self) -> Option<Duration> {
+        self.fixed_delta
+    }
+
+    /// Pause or resume time. While paused, frames cover no time. The first frame after resuming
+    /// covers no time either, so the time spent paused is never simulated.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Start a new frame now.
+    pub fn update(&mut self) {
+        self.update_with_instant(Instant::now());
+    }
+
+    /// Start a new frame at the given instant. The first frame has a delta of zero.
+    pub fn update_with_instant(&mut self, now: Instant) {
+        let last_update = if self.paused {
+            self.last_update = None;
+            None
+        } else {
+            self.last_update.replace(now)
+        };
+        self.delta = match (last_update, self.fixed_delta) {
+            (Some(_), Some(fixed)) => fixed,
+            // Saturates if `now` is somehow earlier than the last frame.
+            (Some(last), None) => now.saturating_duration_since(last).min(self.max_delta),
+            (None, _) => Duration::from_secs(0),
+        };
+        if self.time_scale != 1.0 {
+            self.delta = self.delta.mul_f32(self.time_scale);
+        }
+        self.elapsed += self.delta;
+    }
+
+    /// Time covered by the current frame, after clamping.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn delta_seconds_f64(&self) -> f64 {
+        self.delta.as_secs_f64()
+    }
+
+    /// Total time covered by all frames so far. Falls behind the wall clock by however much was
+    /// clamped off.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn seconds_since_startup(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+}
+
+/// Splits frame deltas into fixed simulation steps, carrying the remainder over to later frames.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    pending: Duration,
+}
+
+impl FixedTimestep {
+    /// Create a timestep running steps of `step`, at most `max_steps` per frame. Time beyond what
+    /// `max_steps` can cover is dropped rather than caught up on later.
+    pub fn new(step: Duration, max_steps: u32) -> Self {
+        Self {
+            step,
+            max_steps,
+            pending: Duration::from_secs(0),
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Add a frame's delta, and return how many steps to run for it.
+    pub fn advance(&mut self, delta: Duration) -> u32 {
+        self.pending += delta;
+        let mut steps = 0;
+        while self.pending >= self.step && steps < self.max_steps {
+            self.pending -= self.step;
+            steps += 1;
+        }
+        if self.pending >= self.step {
+            self.pending = Duration::from_secs(0);
+        }
+        steps
+    }
+
+    /// How far the simulation is into the next step, from 0 to 1, for interpolating between
+    /// steps.
+    pub fn overstep(&self) -> f32 {
+        (self.pending.as_secs_f32() / self.step.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Settings for frame timing, for [`crate::config_help`].
+pub(crate) fn settings() -> Vec<Setting> {
+    vec![
+        Setting::new::<u64>(
+            MAX_DELTA_VAR,
+            "Most time in milliseconds a single frame can cover, so the saver doesn't jump ahead \
+             after a stall.",
+        )
+        .with_default(DEFAULT_MAX_DELTA.as_millis()),
+        Setting::new::<f32>(
+            TIME_SCALE_VAR,
+            "Speed of the saver's simulation, below 1 for slow motion or above 1 for fast \
+             forward.",
+        )
+        .with_default(1.0),
+    ]
+}
+
+/// Number of smaller integration steps to split each simulation step into. Engine savers get it as
+/// a resource, which their simulation systems should use for each step they run. It's read from
+/// `XSECURELOCK_SAVER_PHYSICS_SUBSTEPS`, from 1 to 64, unless the saver inserted its own. Used by
+/// `saver_fluid` and `saver_genetic_orbits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicsSubsteps(pub u32);
+
+impl Default for PhysicsSubsteps {
+    fn default() -> Self {
+        PhysicsSubsteps(1)
+    }
+}
+
+impl PhysicsSubsteps {
+    /// Number of substeps per step, at least 1.
+    pub fn count(&self) -> u32 {
+        self.0.max(1)
+    }
+
+    /// Time each substep of a step covering `delta` covers.
+    pub fn substep(&self, delta: Duration) -> Duration {
+        delta / self.count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_frame_is_empty() {
+        let mut time = SaverTime::new();
+        time.update_with_instant(Instant::now());
+        assert_eq!(time.delta(), Duration::from_secs(0));
+        assert_eq!(time.elapsed(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn accumulates_frames() {
+        let start = Instant::now();
+        let mut time = SaverTime::new();
+        time.update_with_instant(start);
+        time.update_with_instant(start + Duration::from_millis(16));
+        assert_eq!(time.delta(), Duration::from_millis(16));
+        time.update_with_instant(start + Duration::from_millis(40));
+        assert_eq!(time.delta(), Duration::from_millis(24));
+        assert_eq!(time.elapsed(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn clamps_long_frames() {
+        let start = Instant::now();
+        let mut time = SaverTime::with_max_delta(Duration::from_millis(50));
+        time.update_with_instant(start);
+        // A resume from suspend an hour later only advances by one maximum frame.
+        time.update_with_instant(start + Duration::from_secs(3600));
+        assert_eq!(time.delta(), Duration::from_millis(50));
+        time.update_with_instant(start + Duration::from_millis(3_600_010));
+        assert_eq!(time.delta(), Duration::from_millis(10));
+        assert_eq!(time.elapsed(), Duration::from_millis(60));
+    }
+
+    #[test]
+    fn scales_time() {
+        let start = Instant::now();
+        let mut time = SaverTime::with_max_delta(Duration::from_millis(100));
+        time.set_time_scale(0.5);
+        time.update_with_instant(start);
+        time.update_with_instant(start + Duration::from_millis(40));
+        assert!((time.delta_seconds() - 0.02).abs() < 1e-6);
+        time.update_with_instant(start + Duration::from_secs(10));
+        assert!((time.delta_seconds() - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reads_settings_from_env() {
+        std::env::set_var(MAX_DELTA_VAR, "250");
+        std::env::set_var(TIME_SCALE_VAR, "0.25");
+        let time = SaverTime::from_env();
+        assert_eq!(time.max_delta, Duration::from_millis(250));
+        assert_eq!(time.time_scale(), 0.25);
+
+        std::env::set_var(MAX_DELTA_VAR, "0");
+        std::env::set_var(TIME_SCALE_VAR, "inf");
+        let time = SaverTime::from_env();
+        assert_eq!(time.max_delta, DEFAULT_MAX_DELTA);
+        assert_eq!(time.time_scale(), 1.0);
+        std::env::remove_var(MAX_DELTA_VAR);
+        std::env::remove_var(TIME_SCALE_VAR);
+    }
+
+    #[test]
+    fn fixes_frame_delta() {
+        let start = Instant::now();
+        let mut time = SaverTime::new();
+        time.set_fixed_delta(Some(Duration::from_millis(20)));
+        time.update_with_instant(start);
+        assert_eq!(time.delta(), Duration::from_secs(0));
+        time.update_with_instant(start + Duration::from_secs(5));
+        assert_eq!(time.delta(), Duration::from_millis(20));
+        time.update_with_instant(start + Duration::from_secs(5));
+        assert_eq!(time.delta(), Duration::from_millis(20));
+        time.set_fixed_delta(None);
+        time.update_with_instant(start + Duration::from_millis(5010));
+        assert_eq!(time.delta(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn pauses_time() {
+        let start = Instant::now();
+        let mut time = SaverTime::new();
+        time.set_fixed_delta(Some(Duration::from_millis(20)));
+        time.update_with_instant(start);
+        time.update_with_instant(start + Duration::from_millis(20));
+        time.set_paused(true);
+        time.update_with_instant(start + Duration::from_millis(40));
+        assert_eq!(time.delta(), Duration::from_secs(0));
+        time.set_paused(false);
+        time.update_with_instant(start + Duration::from_secs(60));
+        assert_eq!(time.delta(), Duration::from_secs(0));
+        time.update_with_instant(start + Duration::from_millis(60_020));
+        assert_eq!(time.delta(), Duration::from_millis(20));
+        assert_eq!(time.elapsed(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn fixed_timestep_carries_remainder() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10), 8);
+        assert_eq!(timestep.advance(Duration::from_millis(25)), 2);
+        assert!((timestep.overstep() - 0.5).abs() < 1e-4);
+        assert_eq!(timestep.advance(Duration::from_millis(5)), 1);
+        assert_eq!(timestep.advance(Duration::from_millis(9)), 0);
+    }
+
+    #[test]
+    fn fixed_timestep_caps_steps() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10), 3);
+        assert_eq!(timestep.advance(Duration::from_millis(95)), 3);
+        // The backlog is dropped rather than run over the next frames.
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 1);
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 1);
+    }
+
+    #[test]
+    fn splits_steps_into_substeps() {
+        let step = Duration::from_millis(30);
+        assert_eq!(PhysicsSubsteps::default().substep(step), step);
+        assert_eq!(PhysicsSubsteps(3).substep(step), Duration::from_millis(10));
+        assert_eq!(PhysicsSubsteps(0).count(), 1);
+    }
+
+    #[test]
+    fn ignores_instants_going_backwards() {
+        let start = Instant::now() + Duration::from_secs(1);
+        let mut time = SaverTime::new();
+        time.update_with_instant(start);
+        time.update_with_instant(start - Duration::from_millis(500));
+        assert_eq!(time.delta(), Duration::from_secs(0));
+    }
+}